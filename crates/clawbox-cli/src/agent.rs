@@ -0,0 +1,1028 @@
+//! Session agent
+//!
+//! `clawbox unlock` hands the derived vault key to a small per-user background
+//! process listening on a unix socket. Later invocations fetch the key from the
+//! agent instead of prompting for the master password and re-running Argon2.
+//! The agent forgets a key when its timeout expires or on `clawbox lock`, and
+//! exits once it holds no sessions.
+//!
+//! With `clawbox unlock --keychain` on macOS the agent also remembers the key
+//! in the Keychain, along with its expiry and the boot time, so a restarted
+//! agent can pick the session up again; a reboot, the timeout or
+//! `clawbox lock` ends it.
+//!
+//! Each session records when it started, when its key was last fetched and
+//! which process stored it. `clawbox sessions` lists them and revokes them.
+//!
+//! The socket lives in a directory only its user can enter, and the agent
+//! also asks the kernel who is connecting: it only answers processes of its
+//! own user and, if `[agent] executables` is configured, only processes
+//! running one of those binaries. Refused requests about a vault are recorded
+//! in that vault's audit log.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zeroize::{Zeroize, Zeroizing};
+
+/// How long an idle agent without sessions stays around
+const IDLE_EXIT: Duration = Duration::from_secs(5);
+
+/// How long a client waits for a freshly spawned agent
+const SPAWN_WAIT: Duration = Duration::from_secs(3);
+
+/// Keychain service of remembered sessions
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+const KEYCHAIN_SERVICE: &str = "dev.clawbox.agent";
+
+/// Agent protocol request (one JSON object per line)
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Store {
+        vault: PathBuf,
+        key: Vec<u8>,
+        timeout_secs: u64,
+        /// Also remember the session outside the agent
+        #[serde(default)]
+        remember: bool,
+    },
+    Fetch {
+        vault: PathBuf,
+    },
+    Query {
+        vault: PathBuf,
+    },
+    Lock {
+        vault: Option<PathBuf>,
+    },
+    List,
+    Revoke {
+        /// Session to revoke, or all of them
+        id: Option<String>,
+    },
+}
+
+/// Agent protocol response
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Ok,
+    Key { key: Vec<u8> },
+    Session { expires_in_secs: u64 },
+    NoSession,
+    Locked { vaults: Vec<PathBuf> },
+    Sessions { sessions: Vec<SessionInfo> },
+    Error { message: String },
+}
+
+/// The process on the other end of an agent connection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peer {
+    pub uid: u32,
+    pub pid: Option<i32>,
+    /// The binary it runs, if the platform says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe: Option<PathBuf>,
+}
+
+impl Peer {
+    /// Credentials of the process connected to `stream`, as the kernel reports them
+    #[cfg(target_os = "linux")]
+    fn of(stream: &UnixStream) -> std::io::Result<Self> {
+        let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: SO_PEERCRED fills a ucred and `len` says how much room there is
+        let status = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                (&mut cred as *mut libc::ucred).cast(),
+                &mut len,
+            )
+        };
+        if status != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let pid = (cred.pid > 0).then_some(cred.pid);
+        Ok(Peer { uid: cred.uid, pid, exe: pid.and_then(exe_of) })
+    }
+
+    /// Credentials of the process connected to `stream`, as the kernel reports them
+    #[cfg(not(target_os = "linux"))]
+    fn of(stream: &UnixStream) -> std::io::Result<Self> {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: getpeereid only writes the two ids
+        if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        #[cfg(target_os = "macos")]
+        let pid = {
+            let mut pid: libc::pid_t = 0;
+            let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+            // SAFETY: LOCAL_PEERPID fills a pid_t and `len` says how much room there is
+            let status = unsafe {
+                libc::getsockopt(
+                    stream.as_raw_fd(),
+                    libc::SOL_LOCAL,
+                    libc::LOCAL_PEERPID,
+                    (&mut pid as *mut libc::pid_t).cast(),
+                    &mut len,
+                )
+            };
+            (status == 0 && pid > 0).then_some(pid)
+        };
+        #[cfg(not(target_os = "macos"))]
+        let pid = None;
+        Ok(Peer { uid, pid, exe: pid.and_then(exe_of) })
+    }
+}
+
+/// Path of the binary process `pid` runs
+#[cfg(target_os = "linux")]
+fn exe_of(pid: i32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/exe", pid)).ok()
+}
+
+/// Path of the binary process `pid` runs
+#[cfg(target_os = "macos")]
+fn exe_of(pid: i32) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let mut buf = vec![0u8; libc::PROC_PIDPATHINFO_MAXSIZE as usize];
+    // SAFETY: proc_pidpath writes at most `buf.len()` bytes and returns how many
+    let len = unsafe { libc::proc_pidpath(pid, buf.as_mut_ptr().cast(), buf.len() as u32) };
+    (len > 0).then(|| PathBuf::from(std::ffi::OsStr::from_bytes(&buf[..len as usize])))
+}
+
+/// Path of the binary process `pid` runs
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn exe_of(_pid: i32) -> Option<PathBuf> {
+    None
+}
+
+/// Which connecting processes the agent serves
+pub struct PeerPolicy {
+    /// The user the agent runs as
+    owner: u32,
+    /// SHA-256 digests (hex) of the binaries allowed to connect; empty allows any
+    executables: Vec<String>,
+}
+
+impl PeerPolicy {
+    /// Serve processes of the current user running one of `executables`
+    /// (any binary if empty)
+    pub fn new(executables: Vec<String>) -> Self {
+        // SAFETY: getuid has no preconditions and cannot fail
+        let owner = unsafe { libc::getuid() };
+        Self { owner, executables }
+    }
+
+    /// Why `peer` may not use the agent, if it may not
+    fn check(&self, peer: Option<&Peer>) -> std::result::Result<(), String> {
+        let Some(peer) = peer else {
+            return Err("the peer's credentials are unknown".to_string());
+        };
+        if peer.uid != self.owner {
+            return Err(format!("uid {} is not the agent's owner", peer.uid));
+        }
+        if self.executables.is_empty() {
+            return Ok(());
+        }
+        let exe = peer.exe.as_deref().ok_or("the peer's executable is unknown")?;
+        let binary = std::fs::read(exe).map_err(|e| format!("could not read {}: {}", exe.display(), e))?;
+        let digest = format!("{:x}", Sha256::digest(&binary));
+        if !self.executables.iter().any(|allowed| allowed.eq_ignore_ascii_case(&digest)) {
+            return Err(format!("{} is not an allowed executable", exe.display()));
+        }
+        Ok(())
+    }
+}
+
+/// Name of the user with `uid`, if it has one
+fn user_name(uid: u32) -> Option<String> {
+    // SAFETY: passwd is plain data, valid when zeroed
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 4096];
+    let mut result = std::ptr::null_mut();
+    // SAFETY: getpwuid_r writes into `pwd` and `buf`, whose size it is told;
+    // on success pw_name points into `buf`
+    let status = unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    // SAFETY: pw_name is a NUL-terminated string inside `buf`
+    let name = unsafe { std::ffi::CStr::from_ptr(pwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// What `clawbox sessions` shows about a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub vault: PathBuf,
+    /// Unix time the session started
+    pub created_at: i64,
+    /// Unix time its key was last handed out
+    pub last_used: Option<i64>,
+    pub expires_in_secs: u64,
+    /// The process that stored the key; unknown for remembered sessions
+    pub origin: Option<Peer>,
+    pub user: Option<String>,
+}
+
+/// A cached vault key
+struct Session {
+    id: String,
+    key: Zeroizing<Vec<u8>>,
+    expires_at: Instant,
+    remembered: bool,
+    created_at: i64,
+    last_used: Option<i64>,
+    origin: Option<Peer>,
+}
+
+/// A short random id, unique among `sessions`
+fn session_id(sessions: &HashMap<PathBuf, Session>) -> String {
+    loop {
+        let id = format!("{:08x}", rand::random::<u32>());
+        if !sessions.values().any(|session| session.id == id) {
+            return id;
+        }
+    }
+}
+
+impl Session {
+    fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+
+    fn info(&self, vault: &Path, now: Instant) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            vault: vault.to_path_buf(),
+            created_at: self.created_at,
+            last_used: self.last_used,
+            expires_in_secs: self.expires_at.saturating_duration_since(now).as_secs(),
+            user: self.origin.as_ref().and_then(|origin| user_name(origin.uid)),
+            origin: self.origin.clone(),
+        }
+    }
+}
+
+type Sessions = Arc<Mutex<HashMap<PathBuf, Session>>>;
+
+/// Keeps copies of sessions outside the agent, so they survive it restarting
+pub trait SessionBacking: Send + Sync {
+    fn save(&self, vault: &Path, blob: &[u8]) -> Result<()>;
+    fn load(&self, vault: &Path) -> Result<Option<Zeroizing<Vec<u8>>>>;
+    /// Forget one vault's session, or all of them; whether any was there
+    fn forget(&self, vault: Option<&Path>) -> Result<bool>;
+}
+
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+impl SessionBacking for clawbox_core::keychain::Keychain {
+    fn save(&self, vault: &Path, blob: &[u8]) -> Result<()> {
+        Ok(self.store(&vault.to_string_lossy(), blob)?)
+    }
+
+    fn load(&self, vault: &Path) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        Ok(self.fetch(&vault.to_string_lossy())?)
+    }
+
+    fn forget(&self, vault: Option<&Path>) -> Result<bool> {
+        Ok(self.delete(vault.map(|vault| vault.to_string_lossy()).as_deref())?)
+    }
+}
+
+/// Where this platform remembers sessions, if anywhere
+pub fn backing() -> Option<Box<dyn SessionBacking>> {
+    #[cfg(all(feature = "keychain", target_os = "macos"))]
+    return Some(Box::new(clawbox_core::keychain::Keychain::new(KEYCHAIN_SERVICE)));
+    #[cfg(not(all(feature = "keychain", target_os = "macos")))]
+    None
+}
+
+/// A remembered session: the key wrapped with what decides whether it is
+/// still valid, since the copy outlives the agent's clock
+#[derive(Serialize, Deserialize)]
+struct Remembered {
+    key: Vec<u8>,
+    /// Unix time the session ends
+    expires_at: i64,
+    /// Unix time this machine booted; a different one means it rebooted
+    booted_at: Option<i64>,
+    /// Unix time the session started
+    #[serde(default)]
+    created_at: Option<i64>,
+}
+
+fn unix_now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// When this machine booted
+#[cfg(target_os = "macos")]
+pub fn boot_time() -> Option<i64> {
+    let mut boot = libc::timeval { tv_sec: 0, tv_usec: 0 };
+    let mut size = std::mem::size_of::<libc::timeval>();
+    // SAFETY: kern.boottime is a timeval and `size` says how much room there is
+    let status = unsafe {
+        libc::sysctlbyname(
+            b"kern.boottime\0".as_ptr().cast(),
+            (&mut boot as *mut libc::timeval).cast(),
+            &mut size,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (status == 0).then_some(boot.tv_sec)
+}
+
+/// When this machine booted
+#[cfg(not(target_os = "macos"))]
+pub fn boot_time() -> Option<i64> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    stat.lines().find_map(|line| line.strip_prefix("btime ")?.trim().parse().ok())
+}
+
+/// Whether `vault` has a remembered session, without asking the agent
+fn is_remembered(vault: &Path) -> bool {
+    backing().is_some_and(|backing| backing.load(vault).is_ok_and(|blob| blob.is_some()))
+}
+
+/// Bring a remembered session back into the table, dropping it if it has
+/// ended in the meantime
+fn restore(vault: &Path, sessions: &mut HashMap<PathBuf, Session>, backing: &dyn SessionBacking, now: Instant) {
+    let Ok(Some(blob)) = backing.load(vault) else {
+        return;
+    };
+    let Ok(mut remembered) = serde_json::from_slice::<Remembered>(&blob) else {
+        let _ = backing.forget(Some(vault));
+        return;
+    };
+    let left = remembered.expires_at - unix_now();
+    if left > 0 && remembered.booted_at.is_some() && remembered.booted_at == boot_time() {
+        let id = session_id(sessions);
+        sessions.insert(
+            vault.to_path_buf(),
+            Session {
+                id,
+                key: Zeroizing::new(std::mem::take(&mut remembered.key)),
+                expires_at: now + Duration::from_secs(left as u64),
+                remembered: true,
+                created_at: remembered.created_at.unwrap_or_else(unix_now),
+                last_used: None,
+                origin: None,
+            },
+        );
+    } else {
+        remembered.key.zeroize();
+        let _ = backing.forget(Some(vault));
+    }
+}
+
+/// Drop expired sessions, and their remembered copies
+fn expire(sessions: &mut HashMap<PathBuf, Session>, backing: Option<&dyn SessionBacking>, now: Instant) {
+    sessions.retain(|vault, session| {
+        if !session.is_expired(now) {
+            return true;
+        }
+        if let (true, Some(backing)) = (session.remembered, backing) {
+            let _ = backing.forget(Some(vault));
+        }
+        false
+    });
+}
+
+/// Per-user directory for the agent socket and other runtime state, created with mode 0700
+pub fn runtime_dir() -> Result<PathBuf> {
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("clawbox"),
+        _ => std::env::temp_dir().join(format!("clawbox-{}", uid)),
+    };
+
+    if !dir.exists() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Could not create agent directory {:?}", dir))?;
+    }
+
+    check_private_dir(&dir)?;
+    Ok(dir)
+}
+
+/// Refuse a directory for the agent socket that another user could enter
+fn check_private_dir(dir: &Path) -> Result<()> {
+    // SAFETY: getuid has no preconditions and cannot fail
+    let uid = unsafe { libc::getuid() };
+    let meta = std::fs::metadata(dir)?;
+    if meta.uid() != uid || meta.permissions().mode() & 0o077 != 0 {
+        anyhow::bail!(
+            "Refusing to use agent directory {:?}: it must be owned by you with mode 0700",
+            dir
+        );
+    }
+    Ok(())
+}
+
+/// Path of the agent socket
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(runtime_dir()?.join("agent.sock"))
+}
+
+/// Sessions are keyed by the canonical vault path
+pub fn vault_id(vault: &Path) -> PathBuf {
+    std::fs::canonicalize(vault).unwrap_or_else(|_| vault.to_path_buf())
+}
+
+/// Send a request to the running agent; `Ok(None)` if no agent is listening
+fn request(req: &Request) -> Result<Option<Response>> {
+    let path = socket_path()?;
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(_) => return Ok(None),
+    };
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut line = Zeroizing::new(serde_json::to_string(req)?);
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut reply = Zeroizing::new(String::new());
+    BufReader::new(&stream).read_line(&mut reply)?;
+    Ok(Some(serde_json::from_str(&reply)?))
+}
+
+/// Start a detached agent and wait for its socket to come up
+fn spawn_agent() -> Result<()> {
+    let path = socket_path()?;
+    Command::new(std::env::current_exe()?)
+        .arg("agent")
+        .arg("--socket")
+        .arg(&path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Keep terminal signals (Ctrl-C) aimed at the CLI away from the agent
+        .process_group(0)
+        .spawn()
+        .context("Could not start session agent")?;
+
+    let started = Instant::now();
+    while started.elapsed() < SPAWN_WAIT {
+        if UnixStream::connect(&path).is_ok() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    anyhow::bail!("Session agent did not start")
+}
+
+/// Send a request about `vault`, starting an agent first if none is running
+/// but the vault has a remembered session for it to pick up
+fn request_for(vault: &Path, req: &Request) -> Result<Option<Response>> {
+    match request(req)? {
+        None if is_remembered(vault) => {
+            spawn_agent()?;
+            request(req)
+        }
+        response => Ok(response),
+    }
+}
+
+/// Hand a vault key to the agent, starting it if necessary; with `remember`
+/// the agent also keeps it in the Keychain (see `backing`)
+pub fn store(vault: &Path, key: &[u8], timeout: Duration, remember: bool) -> Result<()> {
+    let req = Request::Store {
+        vault: vault_id(vault),
+        key: key.to_vec(),
+        timeout_secs: timeout.as_secs(),
+        remember,
+    };
+
+    let response = match request(&req) {
+        Ok(Some(response)) => response,
+        _ => {
+            spawn_agent()?;
+            request(&req)?.context("Session agent is not responding")?
+        }
+    };
+
+    if let Request::Store { mut key, .. } = req {
+        key.zeroize();
+    }
+
+    match response {
+        Response::Ok => Ok(()),
+        Response::Error { message } => anyhow::bail!("Session agent error: {}", message),
+        _ => anyhow::bail!("Unexpected response from session agent"),
+    }
+}
+
+/// Fetch the cached key for a vault, if a session is active
+pub fn fetch(vault: &Path) -> Option<Zeroizing<Vec<u8>>> {
+    let vault = vault_id(vault);
+    match request_for(&vault, &Request::Fetch { vault: vault.clone() }) {
+        Ok(Some(Response::Key { key })) => Some(Zeroizing::new(key)),
+        _ => None,
+    }
+}
+
+/// Time remaining on a vault's session, if one is active
+pub fn session_expires_in(vault: &Path) -> Option<Duration> {
+    let vault = vault_id(vault);
+    match request_for(&vault, &Request::Query { vault: vault.clone() }) {
+        Ok(Some(Response::Session { expires_in_secs })) => {
+            Some(Duration::from_secs(expires_in_secs))
+        }
+        _ => None,
+    }
+}
+
+/// Drop cached keys (one vault, or all if `None`); returns the vaults that had a session
+///
+/// Remembered copies go too, by the agent or, with none running, right here.
+pub fn lock(vault: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let vault = vault.map(vault_id);
+    let req = Request::Lock { vault: vault.clone() };
+    match request(&req)? {
+        None => {
+            let forgot = match backing() {
+                Some(backing) => backing.forget(vault.as_deref())?,
+                None => false,
+            };
+            Ok(vault.filter(|_| forgot).into_iter().collect())
+        }
+        Some(Response::Locked { vaults }) => Ok(vaults),
+        Some(Response::Error { message }) => anyhow::bail!("Session agent error: {}", message),
+        Some(_) => anyhow::bail!("Unexpected response from session agent"),
+    }
+}
+
+/// Active sessions, oldest first; empty if no agent is running
+pub fn sessions() -> Result<Vec<SessionInfo>> {
+    match request(&Request::List)? {
+        None => Ok(Vec::new()),
+        Some(Response::Sessions { sessions }) => Ok(sessions),
+        Some(Response::Error { message }) => anyhow::bail!("Session agent error: {}", message),
+        Some(_) => anyhow::bail!("Unexpected response from session agent"),
+    }
+}
+
+/// Revoke one session by id, or all of them; returns the sessions revoked
+///
+/// The agent zeroizes their keys and forgets any remembered copies.
+pub fn revoke(id: Option<&str>) -> Result<Vec<SessionInfo>> {
+    match request(&Request::Revoke { id: id.map(str::to_string) })? {
+        None => Ok(Vec::new()),
+        Some(Response::Sessions { sessions }) => Ok(sessions),
+        Some(Response::Error { message }) => anyhow::bail!("Session agent error: {}", message),
+        Some(_) => anyhow::bail!("Unexpected response from session agent"),
+    }
+}
+
+/// Apply a request from `peer` to the session table
+fn handle(
+    req: Request,
+    sessions: &mut HashMap<PathBuf, Session>,
+    backing: Option<&dyn SessionBacking>,
+    peer: Option<&Peer>,
+    now: Instant,
+) -> Response {
+    expire(sessions, backing, now);
+    if let (Request::Fetch { vault } | Request::Query { vault }, Some(backing)) = (&req, backing) {
+        if !sessions.contains_key(vault) {
+            restore(vault, sessions, backing, now);
+        }
+    }
+
+    match req {
+        Request::Store { vault, key, timeout_secs, remember } => {
+            let saved = match (remember, backing) {
+                (false, backing) => {
+                    // An earlier `--keychain` unlock is superseded
+                    if let Some(backing) = backing {
+                        let _ = backing.forget(Some(&vault));
+                    }
+                    Ok(())
+                }
+                (true, None) => Err(anyhow::anyhow!("Sessions can only be remembered in the macOS Keychain")),
+                (true, Some(backing)) => {
+                    let mut remembered = Remembered {
+                        key: key.clone(),
+                        expires_at: unix_now().saturating_add(timeout_secs as i64),
+                        booted_at: boot_time(),
+                        created_at: Some(unix_now()),
+                    };
+                    let blob = serde_json::to_vec(&remembered).map(Zeroizing::new);
+                    remembered.key.zeroize();
+                    blob.map_err(anyhow::Error::from).and_then(|blob| backing.save(&vault, &blob))
+                }
+            };
+            let id = session_id(sessions);
+            sessions.insert(
+                vault,
+                Session {
+                    id,
+                    key: Zeroizing::new(key),
+                    expires_at: now + Duration::from_secs(timeout_secs),
+                    remembered: remember,
+                    created_at: unix_now(),
+                    last_used: None,
+                    origin: peer.cloned(),
+                },
+            );
+            match saved {
+                Ok(()) => Response::Ok,
+                Err(e) => Response::Error { message: format!("Could not remember the session: {:#}", e) },
+            }
+        }
+        Request::Fetch { vault } => match sessions.get_mut(&vault) {
+            Some(session) => {
+                session.last_used = Some(unix_now());
+                Response::Key { key: session.key.to_vec() }
+            }
+            None => Response::NoSession,
+        },
+        Request::Query { vault } => match sessions.get(&vault) {
+            Some(session) => Response::Session {
+                expires_in_secs: session.expires_at.saturating_duration_since(now).as_secs(),
+            },
+            None => Response::NoSession,
+        },
+        // Dropping a session zeroizes its key
+        Request::Lock { vault } => {
+            let mut vaults: Vec<PathBuf> = match &vault {
+                Some(vault) => sessions.remove_entry(vault).map(|(v, _)| v).into_iter().collect(),
+                None => sessions.drain().map(|(v, _)| v).collect(),
+            };
+            if let Some(backing) = backing {
+                match backing.forget(vault.as_deref()) {
+                    Ok(true) => {
+                        if let Some(vault) = vault.filter(|vault| !vaults.contains(vault)) {
+                            vaults.push(vault);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => return Response::Error { message: format!("Could not forget the session: {:#}", e) },
+                }
+            }
+            Response::Locked { vaults }
+        }
+        Request::List => {
+            let mut list: Vec<SessionInfo> =
+                sessions.iter().map(|(vault, session)| session.info(vault, now)).collect();
+            list.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+            Response::Sessions { sessions: list }
+        }
+        // Dropping a session zeroizes its key
+        Request::Revoke { id } => {
+            let vaults: Vec<PathBuf> = sessions
+                .iter()
+                .filter(|(_, session)| id.as_ref().is_none_or(|id| *id == session.id))
+                .map(|(vault, _)| vault.clone())
+                .collect();
+            let mut revoked = Vec::new();
+            for vault in vaults {
+                let session = sessions.remove(&vault).expect("listed above");
+                if let (true, Some(backing)) = (session.remembered, backing) {
+                    if let Err(e) = backing.forget(Some(&vault)) {
+                        return Response::Error { message: format!("Could not forget the session: {:#}", e) };
+                    }
+                }
+                revoked.push(session.info(&vault, now));
+            }
+            Response::Sessions { sessions: revoked }
+        }
+    }
+}
+
+impl Request {
+    /// The vault the request is about, if just one
+    fn vault(&self) -> Option<&Path> {
+        match self {
+            Request::Store { vault, .. } | Request::Fetch { vault } | Request::Query { vault } => Some(vault),
+            Request::Lock { vault } => vault.as_deref(),
+            Request::List | Request::Revoke { .. } => None,
+        }
+    }
+}
+
+/// Record a refused request in the audit log of the vault it was about
+fn record_refusal(req: &Request, peer: Option<&Peer>, reason: &str) {
+    // Only for existing vaults: the path came from the refused process
+    let Some(vault) = req.vault().filter(|vault| vault.join("vault.db").is_file()) else {
+        return;
+    };
+    if let Ok(vault) = clawbox_core::ClawBox::open(vault) {
+        vault.record_agent_refusal(reason, serde_json::json!({ "peer": peer }));
+    }
+}
+
+fn serve_connection(
+    stream: UnixStream,
+    sessions: &Sessions,
+    backing: Option<&dyn SessionBacking>,
+    policy: &PeerPolicy,
+) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut line = Zeroizing::new(String::new());
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let peer = Peer::of(&stream).ok();
+    let response = match serde_json::from_str::<Request>(&line) {
+        Ok(req) => match policy.check(peer.as_ref()) {
+            Ok(()) => handle(req, &mut sessions.lock().unwrap(), backing, peer.as_ref(), Instant::now()),
+            Err(reason) => {
+                record_refusal(&req, peer.as_ref(), &reason);
+                if let Request::Store { mut key, .. } = req {
+                    key.zeroize();
+                }
+                Response::Error { message: format!("Permission denied: {}", reason) }
+            }
+        },
+        Err(e) => Response::Error { message: e.to_string() },
+    };
+
+    let mut reply = Zeroizing::new(serde_json::to_string(&response)?);
+    if let Response::Key { mut key } = response {
+        key.zeroize();
+    }
+    reply.push('\n');
+    (&stream).write_all(reply.as_bytes())?;
+    Ok(())
+}
+
+/// Bind the agent socket, replacing a stale socket file left by a dead agent
+fn bind(path: &Path) -> Result<UnixListener> {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    check_private_dir(dir)?;
+    let listener = match UnixListener::bind(path) {
+        Ok(listener) => listener,
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                anyhow::bail!("Another session agent is already running");
+            }
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Run the agent in the foreground until it has no sessions left
+pub fn run(socket: &Path, policy: PeerPolicy) -> Result<()> {
+    let listener = bind(socket)?;
+    let sessions: Sessions = Arc::default();
+    let backing: Option<Arc<dyn SessionBacking>> = backing().map(Arc::from);
+
+    let reaper_sessions = Arc::clone(&sessions);
+    let reaper_backing = backing.clone();
+    let reaper_socket = socket.to_path_buf();
+    std::thread::spawn(move || {
+        let mut idle_since = Instant::now();
+        loop {
+            std::thread::sleep(Duration::from_millis(500));
+            let now = Instant::now();
+            let mut sessions = reaper_sessions.lock().unwrap();
+            expire(&mut sessions, reaper_backing.as_deref(), now);
+            if !sessions.is_empty() {
+                idle_since = now;
+            } else if now.duration_since(idle_since) >= IDLE_EXIT {
+                let _ = std::fs::remove_file(&reaper_socket);
+                std::process::exit(0);
+            }
+        }
+    });
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let _ = serve_connection(stream, &sessions, backing.as_deref(), &policy);
+            }
+            Err(_) => continue,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_expiry_and_lock() {
+        let mut sessions = HashMap::new();
+        let now = Instant::now();
+        let vault = PathBuf::from("/vaults/work");
+
+        handle(
+            Request::Store { vault: vault.clone(), key: vec![7; 32], timeout_secs: 60, remember: false },
+            &mut sessions,
+            None,
+            None,
+            now,
+        );
+        assert!(matches!(
+            handle(Request::Fetch { vault: vault.clone() }, &mut sessions, None, None, now),
+            Response::Key { key } if key == vec![7; 32]
+        ));
+
+        // Expired sessions are gone on the next request
+        let later = now + Duration::from_secs(61);
+        assert!(matches!(
+            handle(Request::Fetch { vault: vault.clone() }, &mut sessions, None, None, later),
+            Response::NoSession
+        ));
+
+        handle(
+            Request::Store { vault: vault.clone(), key: vec![7; 32], timeout_secs: 60, remember: false },
+            &mut sessions,
+            None,
+            None,
+            now,
+        );
+        assert!(matches!(
+            handle(Request::Lock { vault: None }, &mut sessions, None, None, now),
+            Response::Locked { vaults } if vaults == vec![vault.clone()]
+        ));
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn test_list_and_revoke_sessions() {
+        let mut sessions = HashMap::new();
+        let now = Instant::now();
+        // SAFETY: getuid has no preconditions and cannot fail
+        let me = Peer { uid: unsafe { libc::getuid() }, pid: Some(4242), exe: None };
+        let store = |vault: &str| Request::Store {
+            vault: PathBuf::from(vault),
+            key: vec![7; 32],
+            timeout_secs: 60,
+            remember: false,
+        };
+
+        handle(store("/vaults/work"), &mut sessions, None, Some(&me), now);
+        handle(store("/vaults/home"), &mut sessions, None, Some(&me), now);
+        handle(Request::Fetch { vault: PathBuf::from("/vaults/work") }, &mut sessions, None, Some(&me), now);
+
+        let Response::Sessions { sessions: list } = handle(Request::List, &mut sessions, None, Some(&me), now) else {
+            panic!("expected sessions");
+        };
+        assert_eq!(list.len(), 2);
+        let work = list.iter().find(|s| s.vault == Path::new("/vaults/work")).unwrap();
+        assert_eq!(work.origin, Some(me.clone()));
+        assert!(work.last_used.is_some());
+        assert!(list.iter().any(|s| s.vault == Path::new("/vaults/home") && s.last_used.is_none()));
+
+        // Revoking by id drops just that key
+        let revoke = |id: Option<&str>| Request::Revoke { id: id.map(str::to_string) };
+        assert!(matches!(
+            handle(revoke(Some("nope")), &mut sessions, None, Some(&me), now),
+            Response::Sessions { sessions } if sessions.is_empty()
+        ));
+        assert!(matches!(
+            handle(revoke(Some(&work.id)), &mut sessions, None, Some(&me), now),
+            Response::Sessions { sessions } if sessions.len() == 1 && sessions[0].id == work.id
+        ));
+        assert!(matches!(
+            handle(Request::Fetch { vault: work.vault.clone() }, &mut sessions, None, Some(&me), now),
+            Response::NoSession
+        ));
+        assert!(matches!(
+            handle(revoke(None), &mut sessions, None, Some(&me), now),
+            Response::Sessions { sessions } if sessions.len() == 1
+        ));
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn test_peer_policy() {
+        let policy = PeerPolicy::new(Vec::new());
+        let exe = std::env::current_exe().unwrap();
+        let me = Peer { uid: policy.owner, pid: Some(std::process::id() as i32), exe: Some(exe.clone()) };
+        assert!(policy.check(Some(&me)).is_ok());
+
+        // Another user's process, or one the kernel can't vouch for, is refused
+        let stranger = Peer { uid: policy.owner.wrapping_add(1), ..me.clone() };
+        assert!(policy.check(Some(&stranger)).unwrap_err().contains("not the agent's owner"));
+        assert!(policy.check(None).is_err());
+
+        // With executables configured, only those binaries get in
+        let digest = format!("{:x}", Sha256::digest(std::fs::read(&exe).unwrap()));
+        let pinned = PeerPolicy::new(vec![digest.to_uppercase()]);
+        assert!(pinned.check(Some(&me)).is_ok());
+        let other = PeerPolicy::new(vec!["0".repeat(64)]);
+        assert!(other.check(Some(&me)).unwrap_err().contains("not an allowed executable"));
+        assert!(pinned.check(Some(&Peer { exe: None, ..me.clone() })).is_err());
+        assert!(pinned.check(Some(&stranger)).is_err());
+    }
+
+    #[test]
+    fn test_refused_peer_is_audited() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let vault = dir.path().join("vault");
+        clawbox_core::ClawBox::open(&vault).unwrap().init("correct horse battery staple").unwrap();
+        let policy = PeerPolicy::new(Vec::new());
+        let stranger = Peer { uid: policy.owner.wrapping_add(1), pid: Some(4343), exe: None };
+
+        let reason = policy.check(Some(&stranger)).unwrap_err();
+        record_refusal(&Request::Fetch { vault: vault.clone() }, Some(&stranger), &reason);
+
+        let mut vault = clawbox_core::ClawBox::open(&vault).unwrap();
+        vault.unlock("correct horse battery staple").unwrap();
+        let entries = vault.audit(&Default::default()).unwrap();
+        let refusal = entries.iter().find(|entry| entry.key_path == "session agent").unwrap();
+        assert!(!refusal.success);
+        assert_eq!(refusal.metadata.as_ref().unwrap()["peer"]["uid"], stranger.uid);
+        assert_eq!(refusal.metadata.as_ref().unwrap()["peer"]["pid"], 4343);
+    }
+
+    /// Stands in for the Keychain
+    #[derive(Default)]
+    struct MemoryBacking(Mutex<HashMap<PathBuf, Vec<u8>>>);
+
+    impl SessionBacking for MemoryBacking {
+        fn save(&self, vault: &Path, blob: &[u8]) -> Result<()> {
+            self.0.lock().unwrap().insert(vault.to_path_buf(), blob.to_vec());
+            Ok(())
+        }
+
+        fn load(&self, vault: &Path) -> Result<Option<Zeroizing<Vec<u8>>>> {
+            Ok(self.0.lock().unwrap().get(vault).cloned().map(Zeroizing::new))
+        }
+
+        fn forget(&self, vault: Option<&Path>) -> Result<bool> {
+            let mut items = self.0.lock().unwrap();
+            Ok(match vault {
+                Some(vault) => items.remove(vault).is_some(),
+                None => items.drain().count() > 0,
+            })
+        }
+    }
+
+    #[test]
+    fn test_remembered_session_survives_restart_until_lock() {
+        let backing = MemoryBacking::default();
+        let now = Instant::now();
+        let vault = PathBuf::from("/vaults/work");
+        let store = |remember, timeout_secs| Request::Store {
+            vault: vault.clone(),
+            key: vec![7; 32],
+            timeout_secs,
+            remember,
+        };
+
+        let mut sessions = HashMap::new();
+        assert!(matches!(handle(store(true, 60), &mut sessions, Some(&backing), None, now), Response::Ok));
+        assert!(backing.load(&vault).unwrap().is_some());
+
+        // A restarted agent finds the session again
+        let mut restarted = HashMap::new();
+        assert!(matches!(
+            handle(Request::Fetch { vault: vault.clone() }, &mut restarted, Some(&backing), None, now),
+            Response::Key { key } if key == vec![7; 32]
+        ));
+
+        // `clawbox lock` removes the Keychain item, even from an agent that
+        // never loaded it
+        assert!(matches!(
+            handle(Request::Lock { vault: Some(vault.clone()) }, &mut HashMap::new(), Some(&backing), None, now),
+            Response::Locked { vaults } if vaults == vec![vault.clone()]
+        ));
+        assert!(backing.load(&vault).unwrap().is_none());
+        handle(store(true, 60), &mut sessions, Some(&backing), None, now);
+        handle(Request::Lock { vault: None }, &mut sessions, Some(&backing), None, now);
+        assert!(backing.0.lock().unwrap().is_empty());
+
+        // Expired or superseded sessions don't linger either
+        handle(store(true, 0), &mut sessions, Some(&backing), None, now);
+        assert!(matches!(
+            handle(Request::Query { vault: vault.clone() }, &mut HashMap::new(), Some(&backing), None, now),
+            Response::NoSession
+        ));
+        assert!(backing.load(&vault).unwrap().is_none());
+        handle(store(true, 60), &mut sessions, Some(&backing), None, now);
+        handle(store(false, 60), &mut sessions, Some(&backing), None, now);
+        assert!(backing.load(&vault).unwrap().is_none());
+    }
+}
@@ -0,0 +1,171 @@
+//! Clipboard with delayed auto-clear
+//!
+//! Secrets copied to the clipboard are wiped after a timeout by a small
+//! detached process (`clawbox clipboard-clear`), so the wipe happens even after
+//! the command that copied them has exited. The clearer only gets a SHA-256
+//! fingerprint of the value and leaves the clipboard alone if something else
+//! has been copied since. The fingerprint of the last value we copied is also
+//! kept in the runtime directory so `clawbox lock --all` can wipe it.
+
+use crate::agent;
+use crate::config::ClipboardConfig;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Commands used to talk to the system clipboard
+pub struct Clipboard {
+    copy: Vec<String>,
+    paste: Vec<String>,
+}
+
+fn command(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+fn fingerprint(value: &[u8]) -> String {
+    Sha256::digest(value).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// File remembering the fingerprint of the last value we copied
+fn state_path() -> Result<PathBuf> {
+    Ok(agent::runtime_dir()?.join("clipboard"))
+}
+
+fn remember(hash: &str) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(state_path()?)?;
+    file.write_all(hash.as_bytes())?;
+    Ok(())
+}
+
+fn forget(hash: &str) -> Result<()> {
+    let path = state_path()?;
+    if std::fs::read_to_string(&path).ok().as_deref() == Some(hash) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+impl Clipboard {
+    /// Use the configured commands, or detect the platform's clipboard tools
+    pub fn new(config: &ClipboardConfig) -> Result<Self> {
+        if let (Some(copy), Some(paste)) = (&config.copy_command, &config.paste_command) {
+            if copy.is_empty() || paste.is_empty() {
+                anyhow::bail!("Clipboard commands in the config file must not be empty");
+            }
+            return Ok(Self { copy: copy.clone(), paste: paste.clone() });
+        }
+
+        let (copy, paste) = if cfg!(target_os = "macos") {
+            (command(&["pbcopy"]), command(&["pbpaste"]))
+        } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            (command(&["wl-copy"]), command(&["wl-paste", "--no-newline"]))
+        } else if std::env::var_os("DISPLAY").is_some() {
+            (
+                command(&["xclip", "-selection", "clipboard", "-in"]),
+                command(&["xclip", "-selection", "clipboard", "-out"]),
+            )
+        } else {
+            anyhow::bail!(
+                "No clipboard available (set copy_command and paste_command under [clipboard] in the config file)"
+            );
+        };
+        Ok(Self { copy, paste })
+    }
+
+    fn write(&self, value: &[u8]) -> Result<()> {
+        let mut child = Command::new(&self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Could not run clipboard command '{}'", self.copy[0]))?;
+        child.stdin.take().context("Clipboard command has no stdin")?.write_all(value)?;
+        if !child.wait()?.success() {
+            anyhow::bail!("Clipboard command '{}' failed", self.copy[0]);
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let output = Command::new(&self.paste[0])
+            .args(&self.paste[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .with_context(|| format!("Could not run clipboard command '{}'", self.paste[0]))?;
+        Ok(Zeroizing::new(output.stdout))
+    }
+
+    /// Copy a secret; with a timeout, schedule a wipe that outlives this process
+    pub fn copy(&self, value: &str, clear_after: Option<Duration>, vault: &Path, path: &str) -> Result<()> {
+        self.write(value.as_bytes())?;
+        let hash = fingerprint(value.as_bytes());
+        remember(&hash)?;
+
+        if let Some(after) = clear_after {
+            let mut child = Command::new(std::env::current_exe()?)
+                .arg("--vault")
+                .arg(vault)
+                .arg("clipboard-clear")
+                .arg("--after")
+                .arg(after.as_secs().to_string())
+                .arg("--secret")
+                .arg(path)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                // Survive Ctrl-C in the terminal that started us
+                .process_group(0)
+                .spawn()
+                .context("Could not start clipboard clearer")?;
+            child.stdin.take().context("Clipboard clearer has no stdin")?.write_all(hash.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Wipe the clipboard if it still holds the value with this fingerprint
+    fn clear_if(&self, hash: &str) -> Result<bool> {
+        let current = self.read()?;
+        // Paste tools may add a trailing newline
+        let matches = fingerprint(&current) == hash
+            || current.strip_suffix(b"\n").is_some_and(|v| fingerprint(v) == hash);
+        if matches {
+            self.write(b"")?;
+        }
+        forget(hash)?;
+        Ok(matches)
+    }
+
+    /// Wipe the clipboard if it still holds the last secret we copied
+    pub fn clear_ours(&self) -> Result<bool> {
+        match std::fs::read_to_string(state_path()?) {
+            Ok(hash) => self.clear_if(hash.trim()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Body of `clawbox clipboard-clear`: wait, then wipe if unchanged
+    ///
+    /// Reads the fingerprint from stdin; returns whether the clipboard was wiped.
+    pub fn run_clearer(&self, after: Duration) -> Result<bool> {
+        let mut hash = String::new();
+        std::io::stdin().read_to_string(&mut hash)?;
+        std::thread::sleep(after);
+        self.clear_if(hash.trim())
+    }
+}
+
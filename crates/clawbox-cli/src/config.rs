@@ -0,0 +1,368 @@
+//! CLI configuration file
+//!
+//! Read from `$CLAWBOX_CONFIG` or `~/.config/clawbox/config.toml`:
+//!
+//! ```toml
+//! default_profile = "personal"
+//! # Cache the vault key between commands; weaker than `clawbox unlock`
+//! session_cache = true
+//! session_ttl = "15m"
+//!
+//! [profiles.personal]
+//! vault = "~/.clawbox"
+//!
+//! [profiles.work]
+//! vault = "~/vaults/work"
+//!
+//! [clipboard]
+//! clear_after = 45
+//!
+//! [history]
+//! keep_versions = 20
+//!
+//! [output]
+//! mask_from = "sensitive"
+//!
+//! [sync]
+//! backend = "dir"
+//! path = "~/Sync/clawbox"
+//! # or: backend = "webdav", url = "https://cloud.example.com/remote.php/dav/files/me/clawbox", username = "me"
+//! # or: backend = "git", url = "git@github.com:me/vault.git", branch = "main"
+//! resolution = "keep-both"
+//!
+//! [hooks]
+//! on_critical_read = "https://hooks.slack.com/services/T000/B000/XXXX"
+//!
+//! [[hooks.rules]]
+//! run = "logger -t clawbox"
+//! actions = ["read"]
+//! levels = ["sensitive", "critical"]
+//! actors = ["ai"]
+//!
+//! [agent]
+//! executables = ["<sha256 of /usr/local/bin/clawbox>"]
+//! ```
+
+use anyhow::{Context, Result};
+use clawbox_core::audit::Action;
+use clawbox_core::hooks::{Hook, HookTarget};
+use clawbox_core::AccessLevel;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Parsed configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Profile used when neither `--vault` nor `--profile` is given
+    pub default_profile: Option<String>,
+    /// Keep the vault key in a file between commands (see `keycache`)
+    pub session_cache: bool,
+    /// How long a cached key lasts, e.g. `15m`
+    pub session_ttl: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    pub clipboard: ClipboardConfig,
+    pub history: HistoryConfig,
+    pub output: OutputConfig,
+    pub sync: SyncConfig,
+    pub hooks: HooksConfig,
+    pub agent: AgentConfig,
+}
+
+/// A named vault
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub vault: PathBuf,
+}
+
+/// Clipboard settings
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipboardConfig {
+    /// Seconds before a copied secret is wiped from the clipboard (0 = never)
+    pub clear_after: u64,
+    /// Command that reads the new clipboard contents from stdin
+    pub copy_command: Option<Vec<String>>,
+    /// Command that prints the clipboard contents
+    pub paste_command: Option<Vec<String>>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            clear_after: 45,
+            copy_command: None,
+            paste_command: None,
+        }
+    }
+}
+
+/// Version history retention
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Versions kept per secret by `clawbox prune` (0 = keep all)
+    pub keep_versions: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { keep_versions: 20 }
+    }
+}
+
+/// Terminal output settings
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Lowest access level whose values `get` masks on a terminal unless
+    /// given `--show`
+    pub mask_from: MaskFrom,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MaskFrom {
+    Public,
+    Normal,
+    #[default]
+    Sensitive,
+    Critical,
+    /// Always print values in full
+    Never,
+}
+
+impl MaskFrom {
+    /// Whether values at `access` are masked
+    pub fn masks(self, access: AccessLevel) -> bool {
+        let from = match self {
+            Self::Public => AccessLevel::Public,
+            Self::Normal => AccessLevel::Normal,
+            Self::Sensitive => AccessLevel::Sensitive,
+            Self::Critical => AccessLevel::Critical,
+            Self::Never => return false,
+        };
+        access as u8 >= from as u8
+    }
+}
+
+/// Where `clawbox sync` keeps the encrypted vault
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub backend: SyncBackendKind,
+    /// Shared folder used by the `dir` backend
+    pub path: Option<PathBuf>,
+    /// Collection URL used by the `webdav` backend, or repository used by
+    /// the `git` backend
+    pub url: Option<String>,
+    /// Branch used by the `git` backend (default `main`)
+    pub branch: Option<String>,
+    /// How secrets changed on both devices are merged; unset keeps the
+    /// vault's current choice (last writer wins by default)
+    pub resolution: Option<ConflictStrategy>,
+    /// Seconds between checks by `sync --watch` for changes it can't watch
+    /// (remote backends other than `dir`)
+    pub poll_interval: u64,
+    /// Backups of the local vault kept by `sync --pull` (see
+    /// `restore-backup`)
+    pub backups: usize,
+    /// Whether iCloud pushes carry the audit log (see `--no-audit`); the
+    /// other backends only ever sync secrets
+    pub audit: bool,
+    /// Seconds to wait for iCloud Drive to download a vault file that is
+    /// only a placeholder on this Mac
+    pub download_timeout: u64,
+    /// WebDAV basic auth user; the password comes from `password` or
+    /// `$CLAWBOX_WEBDAV_PASSWORD`
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// WebDAV bearer token, or `$CLAWBOX_WEBDAV_TOKEN`
+    pub token: Option<String>,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            backend: SyncBackendKind::default(),
+            path: None,
+            url: None,
+            branch: None,
+            username: None,
+            password: None,
+            token: None,
+            resolution: None,
+            poll_interval: 60,
+            backups: clawbox_core::backup::DEFAULT_KEEP,
+            audit: true,
+            download_timeout: clawbox_core::icloud::DEFAULT_DOWNLOAD_TIMEOUT.as_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    LastWriterWins,
+    KeepLocal,
+    KeepRemote,
+    /// Keep the local value and the remote one in a conflict copy
+    KeepBoth,
+}
+
+impl From<ConflictStrategy> for clawbox_core::sync::ConflictResolution {
+    fn from(strategy: ConflictStrategy) -> Self {
+        match strategy {
+            ConflictStrategy::LastWriterWins => Self::LastWriterWins,
+            ConflictStrategy::KeepLocal => Self::KeepLocal,
+            ConflictStrategy::KeepRemote => Self::KeepRemote,
+            ConflictStrategy::KeepBoth => Self::KeepBoth,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncBackendKind {
+    /// iCloud Drive (macOS only)
+    #[default]
+    ICloud,
+    /// Any folder kept in sync by another tool (Syncthing, Dropbox, ...)
+    Dir,
+    /// A WebDAV collection, e.g. on Nextcloud (needs the `webdav` feature)
+    WebDav,
+    /// A branch of a git repository (needs the `git-sync` feature)
+    Git,
+}
+
+/// Commands or webhooks called after audited operations succeed
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Called whenever a Critical secret is read or exported
+    pub on_critical_read: Option<String>,
+    pub rules: Vec<HookRule>,
+}
+
+/// A hook with its own conditions; an empty list matches anything
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookRule {
+    /// Recorded with the outcome (default `rule<N>`)
+    pub name: Option<String>,
+    /// Shell command, or `https://` URL to POST to
+    pub run: String,
+    /// Audit actions such as `read`, `export` or `write`
+    #[serde(default)]
+    pub actions: Vec<String>,
+    /// Access levels of the secret involved
+    #[serde(default)]
+    pub levels: Vec<String>,
+    /// Actor types: `human`, `ai`, `app`, `token` or `system`
+    #[serde(default)]
+    pub actors: Vec<String>,
+}
+
+impl HooksConfig {
+    /// The configured hooks, ready for `ClawBox::set_hooks`
+    pub fn hooks(&self) -> Result<Vec<Hook>> {
+        let mut hooks = vec![];
+        if let Some(target) = &self.on_critical_read {
+            hooks.push(Hook::on_critical_read(HookTarget::parse(target)?));
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            let name = rule.name.clone().unwrap_or_else(|| format!("rule{}", i + 1));
+            let actions = rule
+                .actions
+                .iter()
+                .map(|action| {
+                    Action::from_str(action).with_context(|| format!("Unknown action {:?} in hook {}", action, name))
+                })
+                .collect::<Result<_>>()?;
+            let levels = rule
+                .levels
+                .iter()
+                .map(|level| match level.to_lowercase().as_str() {
+                    "public" => Ok(AccessLevel::Public),
+                    "normal" => Ok(AccessLevel::Normal),
+                    "sensitive" => Ok(AccessLevel::Sensitive),
+                    "critical" => Ok(AccessLevel::Critical),
+                    _ => anyhow::bail!("Unknown access level {:?} in hook {}", level, name),
+                })
+                .collect::<Result<_>>()?;
+            let known = |actor: &&String| matches!(actor.as_str(), "human" | "ai" | "app" | "token" | "system");
+            if let Some(actor) = rule.actors.iter().find(|actor| !known(actor)) {
+                anyhow::bail!("Unknown actor type {:?} in hook {}", actor, name);
+            }
+            hooks.push(Hook {
+                target: HookTarget::parse(&rule.run)?,
+                name,
+                actions,
+                levels,
+                actor_types: rule.actors.clone(),
+            });
+        }
+        Ok(hooks)
+    }
+}
+
+/// Session agent settings
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentConfig {
+    /// SHA-256 digests (hex) of the only binaries the agent serves; empty
+    /// allows any process of the same user
+    pub executables: Vec<String>,
+}
+
+impl AgentConfig {
+    /// The allowed digests, checked to be SHA-256 hex
+    pub fn executables(&self) -> Result<Vec<String>> {
+        self.executables
+            .iter()
+            .map(|digest| {
+                let digest = digest.trim().to_lowercase();
+                if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    anyhow::bail!("[agent] executables: {:?} is not a SHA-256 digest", digest);
+                }
+                Ok(digest)
+            })
+            .collect()
+    }
+}
+
+impl Config {
+    /// Location of the configuration file
+    pub fn path() -> Option<PathBuf> {
+        match std::env::var_os("CLAWBOX_CONFIG") {
+            Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => dirs::config_dir().map(|dir| dir.join("clawbox").join("config.toml")),
+        }
+    }
+
+    /// Load the configuration file; a missing file yields the defaults
+    pub fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Could not read config file {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    /// Look up a profile by name
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).with_context(|| {
+            let known: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                format!("Unknown profile '{}' (no profiles configured)", name)
+            } else {
+                format!("Unknown profile '{}' (known: {})", name, known.join(", "))
+            }
+        })
+    }
+}
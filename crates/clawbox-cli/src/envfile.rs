@@ -0,0 +1,161 @@
+//! `.env` import and export
+//!
+//! Imported secrets are tagged `env:<NAME>` with their original variable name,
+//! so `export --format env` writes them back under exactly that name.
+
+use anyhow::Result;
+
+/// Tag prefix recording the original variable name
+const VAR_TAG: &str = "env:";
+
+/// How variable names become secret paths on import
+#[derive(Debug, Default)]
+pub struct PathMapping {
+    /// Prepended verbatim; the name itself is not split when set
+    pub prefix: Option<String>,
+    /// Keep the name as one path segment instead of turning `_` into `/`
+    pub no_split: bool,
+    /// Don't lowercase the name
+    pub keep_case: bool,
+}
+
+impl PathMapping {
+    /// Secret path for a variable name
+    pub fn path_for(&self, name: &str) -> String {
+        let name = if self.keep_case { name.to_string() } else { name.to_lowercase() };
+        match &self.prefix {
+            Some(prefix) => format!("{}{}", prefix, name),
+            None if self.no_split => name,
+            None => name.replace('_', "/"),
+        }
+    }
+}
+
+/// Tag recording a variable name
+pub fn var_tag(name: &str) -> String {
+    format!("{}{}", VAR_TAG, name)
+}
+
+/// Variable name to export a secret under
+pub fn var_name(path: &str, tags: &[String]) -> String {
+    tags.iter()
+        .find_map(|tag| tag.strip_prefix(VAR_TAG))
+        .map(str::to_string)
+        .unwrap_or_else(|| path.replace('/', "_").to_uppercase())
+}
+
+/// Variable name `run --prefix` gives a secret at `relative` below the
+/// prefix: `db/url` becomes `DB_URL`
+pub fn relative_var_name(relative: &str) -> String {
+    relative
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Undo the escaping done by `format_line` inside double quotes
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Parse `.env` content into `(name, value)` pairs
+///
+/// Supports comments, blank lines, an optional `export ` prefix, and
+/// unquoted, single-quoted (literal) and double-quoted (escaped) values.
+pub fn parse(content: &str) -> Result<Vec<(String, String)>> {
+    let mut vars = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Line {}: expected NAME=value", number + 1))?;
+
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("Line {}: invalid variable name '{}'", number + 1, name);
+        }
+
+        let value = value.trim();
+        let value = if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            unescape(inner)
+        } else if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+            inner.to_string()
+        } else {
+            value.to_string()
+        };
+        vars.push((name.to_string(), value));
+    }
+    Ok(vars)
+}
+
+/// Format one `NAME="value"` line
+pub fn format_line(name: &str, value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    format!("{}=\"{}\"\n", name, escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_mapping() {
+        let split = PathMapping::default();
+        assert_eq!(split.path_for("AWS_SECRET_KEY"), "aws/secret/key");
+
+        let no_split = PathMapping { no_split: true, ..Default::default() };
+        assert_eq!(no_split.path_for("DATABASE_URL"), "database_url");
+
+        let prefixed = PathMapping { prefix: Some("app/".into()), keep_case: true, ..Default::default() };
+        assert_eq!(prefixed.path_for("DATABASE_URL"), "app/DATABASE_URL");
+
+        assert_eq!(var_name("app/database_url", &[var_tag("DATABASE_URL")]), "DATABASE_URL");
+        assert_eq!(var_name("db/url", &[]), "DB_URL");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = "a \"quoted\" \\ value\nwith newline";
+        let line = format_line("TRICKY", value);
+        assert_eq!(parse(&line).unwrap(), vec![("TRICKY".to_string(), value.to_string())]);
+
+        let parsed = parse("# comment\nexport A=1\nB='x\\ny'\n\nC=\"\"").unwrap();
+        assert_eq!(
+            parsed,
+            vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "x\\ny".to_string()),
+                ("C".to_string(), String::new()),
+            ]
+        );
+        assert!(parse("NOT A VAR").is_err());
+    }
+}
@@ -0,0 +1,60 @@
+//! A pretty-printed JSON array written one element at a time
+//!
+//! The output is byte for byte what `serde_json::to_string_pretty` gives
+//! for the whole `Vec`, so commands can stream large listings without
+//! changing their format. Exports write secret values through it, so the
+//! text of each element is zeroized once written.
+
+use serde::Serialize;
+use std::io::{self, Write};
+use zeroize::Zeroizing;
+
+pub struct JsonArray<W: Write> {
+    out: W,
+    empty: bool,
+}
+
+impl<W: Write> JsonArray<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, empty: true }
+    }
+
+    /// Write the next element
+    pub fn push<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        let json = Zeroizing::new(serde_json::to_string_pretty(value)?);
+        // Strings escape their newlines, so every newline is layout
+        let indented = Zeroizing::new(json.replace('\n', "\n  "));
+        self.out.write_all(if self.empty { b"[\n  " } else { b",\n  " })?;
+        self.out.write_all(indented.as_bytes())?;
+        self.empty = false;
+        Ok(())
+    }
+
+    /// Close the array, without a trailing newline
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.write_all(if self.empty { b"[]" } else { b"\n]" })?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_to_string_pretty() {
+        let cases = vec![
+            vec![],
+            vec![serde_json::json!({"path": "a", "tags": []})],
+            vec![serde_json::json!({"path": "a\nb", "tags": ["x", "y"]}), serde_json::json!(1), serde_json::json!([])],
+        ];
+        for values in cases {
+            let mut array = JsonArray::new(vec![]);
+            for value in &values {
+                array.push(value).unwrap();
+            }
+            let written = String::from_utf8(array.finish().unwrap()).unwrap();
+            assert_eq!(written, serde_json::to_string_pretty(&values).unwrap());
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Session key cache
+//!
+//! A lighter alternative to the session agent, off unless the config file
+//! sets `session_cache = true`: after a password unlock the derived vault
+//! key is written to `<runtime dir>/session-cache/`, sealed with a random
+//! key made once per boot, together with its expiry (`session_ttl`,
+//! default 15 minutes). Later invocations unlock from it instead of asking
+//! for the password and re-running Argon2; `clawbox lock` deletes it.
+//!
+//! This is weaker than the agent. The boot key sits in the same directory,
+//! so anything running as the user can read the vault key, and nothing
+//! checks which program asks. The directory and files are private to the
+//! user (0700 / 0600); the sealing only keeps a copied cache file, e.g. in
+//! a backup, from opening the vault after a reboot or past its expiry.
+
+use crate::agent;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use clawbox_core::crypto::{self, DerivedKey, EncryptedData};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Expiry when the config sets none
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// File holding this boot's sealing key
+const BOOT_KEY_FILE: &str = "boot.key";
+
+/// How long cached keys last, or `None` if caching is off
+static TTL: OnceLock<Option<Duration>> = OnceLock::new();
+
+/// The sealing key and the boot it belongs to
+#[derive(Serialize, Deserialize)]
+struct BootKey {
+    boot: i64,
+    key: String,
+}
+
+/// A cached vault key, as stored
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// The vault it opens, for `clear(None)` to report
+    vault: PathBuf,
+    boot: i64,
+    nonce: String,
+    /// The expiry (Unix time, 8 bytes big-endian) followed by the vault key
+    ciphertext: String,
+}
+
+/// Turn caching on with the given expiry; call once at startup
+pub fn init(ttl: Option<Duration>) {
+    let _ = TTL.set(ttl);
+}
+
+fn ttl() -> Option<Duration> {
+    TTL.get().copied().flatten()
+}
+
+/// Directory of the cache files, created private to the user
+fn cache_dir() -> Result<PathBuf> {
+    let dir = agent::runtime_dir()?.join("session-cache");
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("Could not create session cache directory {:?}", dir))?;
+    Ok(dir)
+}
+
+fn entry_path(dir: &Path, vault: &Path) -> PathBuf {
+    let id = Sha256::digest(agent::vault_id(vault).as_os_str().as_encoded_bytes());
+    dir.join(format!("{}.json", hex(&id[..16])))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Write `contents` to `path` readable only by the user, replacing it whole
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let _ = std::fs::remove_file(&tmp);
+    let mut file = std::fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&tmp)?;
+    file.write_all(contents)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// This boot's sealing key, made on first use; `None` where the boot time
+/// can't be told, since the key then wouldn't end with the boot
+fn boot_key(dir: &Path, create: bool) -> Result<Option<DerivedKey>> {
+    let Some(boot) = agent::boot_time() else {
+        return Ok(None);
+    };
+    let path = dir.join(BOOT_KEY_FILE);
+    if let Ok(content) = std::fs::read(&path) {
+        if let Ok(stored) = serde_json::from_slice::<BootKey>(&content) {
+            if stored.boot == boot {
+                let key = Zeroizing::new(BASE64.decode(&stored.key)?);
+                return Ok(Some(DerivedKey::try_from_slice(&key)?));
+            }
+        }
+    }
+    if !create {
+        return Ok(None);
+    }
+
+    // A new boot: keys sealed in an earlier one are useless now
+    clear_in(dir, None)?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    rand::thread_rng().fill_bytes(&mut *key);
+    let stored = Zeroizing::new(serde_json::to_vec(&BootKey { boot, key: BASE64.encode(*key) })?);
+    write_private(&path, &stored)?;
+    Ok(Some(DerivedKey::try_from_slice(&*key)?))
+}
+
+/// Cache the key of `vault` if caching is on
+pub fn store(vault: &Path, key: &[u8]) -> Result<()> {
+    match ttl() {
+        Some(ttl) => store_in(&cache_dir()?, vault, key, chrono::Utc::now().timestamp() + ttl.as_secs() as i64),
+        None => Ok(()),
+    }
+}
+
+fn store_in(dir: &Path, vault: &Path, key: &[u8], expires_at: i64) -> Result<()> {
+    let Some(boot_key) = boot_key(dir, true)? else {
+        return Ok(());
+    };
+    let mut plaintext = Zeroizing::new(expires_at.to_be_bytes().to_vec());
+    plaintext.extend_from_slice(key);
+    let sealed = crypto::encrypt(&plaintext, &boot_key)?;
+    let entry = Entry {
+        vault: agent::vault_id(vault),
+        boot: agent::boot_time().unwrap_or_default(),
+        nonce: BASE64.encode(sealed.nonce()),
+        ciphertext: BASE64.encode(sealed.ciphertext()),
+    };
+    write_private(&entry_path(dir, vault), &serde_json::to_vec(&entry)?)
+}
+
+/// The cached key of `vault`, if caching is on and it hasn't expired
+///
+/// Expired or unreadable entries are deleted.
+pub fn fetch(vault: &Path) -> Option<Zeroizing<Vec<u8>>> {
+    ttl()?;
+    fetch_in(&cache_dir().ok()?, vault, chrono::Utc::now().timestamp())
+}
+
+fn fetch_in(dir: &Path, vault: &Path, now: i64) -> Option<Zeroizing<Vec<u8>>> {
+    let path = entry_path(dir, vault);
+    let content = std::fs::read(&path).ok()?;
+    let key = open_entry(dir, &content, now);
+    if key.is_none() {
+        let _ = std::fs::remove_file(&path);
+    }
+    key
+}
+
+fn open_entry(dir: &Path, content: &[u8], now: i64) -> Option<Zeroizing<Vec<u8>>> {
+    let entry: Entry = serde_json::from_slice(content).ok()?;
+    let boot_key = boot_key(dir, false).ok()??;
+    let sealed = EncryptedData::from_parts(&BASE64.decode(&entry.nonce).ok()?, &BASE64.decode(&entry.ciphertext).ok()?);
+    let plaintext = Zeroizing::new(crypto::decrypt(&sealed, &boot_key).ok()?);
+    let (expires_at, key) = plaintext.split_at_checked(8)?;
+    (i64::from_be_bytes(expires_at.try_into().ok()?) > now).then(|| Zeroizing::new(key.to_vec()))
+}
+
+/// Delete the cached key of one vault, or of all if `None`; returns the
+/// vaults that had one
+///
+/// Works whether or not caching is on, so `clawbox lock` always clears it.
+pub fn clear(vault: Option<&Path>) -> Result<Vec<PathBuf>> {
+    clear_in(&cache_dir()?, vault)
+}
+
+fn clear_in(dir: &Path, vault: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let paths = match vault {
+        Some(vault) => vec![entry_path(dir, vault)],
+        None => std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+    };
+    let mut cleared = vec![];
+    for path in paths {
+        let Ok(content) = std::fs::read(&path) else {
+            continue;
+        };
+        std::fs::remove_file(&path)?;
+        if let Ok(entry) = serde_json::from_slice::<Entry>(&content) {
+            cleared.push(entry.vault);
+        }
+    }
+    Ok(cleared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cached_key_expires() {
+        if agent::boot_time().is_none() {
+            return;
+        }
+        let dir = TempDir::new().unwrap();
+        let vault = Path::new("/vaults/personal");
+        store_in(dir.path(), vault, b"0123456789abcdef0123456789abcdef", 1_000).unwrap();
+
+        let path = entry_path(dir.path(), vault);
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::metadata(dir.path().join(BOOT_KEY_FILE)).unwrap().permissions().mode() & 0o777, 0o600);
+        assert!(!String::from_utf8_lossy(&std::fs::read(&path).unwrap()).contains("0123456789abcdef"));
+
+        assert_eq!(fetch_in(dir.path(), vault, 999).unwrap().as_slice(), b"0123456789abcdef0123456789abcdef");
+        assert!(fetch_in(dir.path(), Path::new("/vaults/other"), 999).is_none());
+        // Expired entries are gone for good
+        assert!(fetch_in(dir.path(), vault, 1_000).is_none());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_cache_needs_this_boots_key() {
+        if agent::boot_time().is_none() {
+            return;
+        }
+        let dir = TempDir::new().unwrap();
+        let vault = Path::new("/vaults/personal");
+        store_in(dir.path(), vault, b"key", i64::MAX).unwrap();
+
+        // As after a reboot
+        let stale = BootKey { boot: 0, key: BASE64.encode([0u8; 32]) };
+        write_private(&dir.path().join(BOOT_KEY_FILE), &serde_json::to_vec(&stale).unwrap()).unwrap();
+        assert!(fetch_in(dir.path(), vault, 0).is_none());
+
+        store_in(dir.path(), vault, b"key", i64::MAX).unwrap();
+        assert_eq!(clear_in(dir.path(), None).unwrap(), vec![agent::vault_id(vault)]);
+        assert!(fetch_in(dir.path(), vault, 0).is_none());
+    }
+}
@@ -2,12 +2,29 @@
 //!
 //! AI-Native Secret Manager
 
+mod agent;
+mod clipboard;
+mod config;
+mod envfile;
+mod jsonarray;
+mod keycache;
+mod mask;
+mod password;
+mod table;
+mod template;
+mod watch;
+
 use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use clap::{Parser, Subcommand};
-use clawbox_core::{AccessLevel, ClawBox, SetOptions};
+use clawbox_core::{
+    audit::ActorInfo, hooks::Hook, AccessLevel, ClawBox, DeleteMode, ListFilter, OpenOptions, SecretValue, SetOptions,
+    TokenScope,
+};
+use config::Config;
 use console::style;
-use std::io::{self, BufRead};
-use std::path::PathBuf;
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
 
 /// ClawBox - AI-Native Secret Manager
 #[derive(Parser)]
@@ -18,32 +35,136 @@ struct Cli {
     #[arg(long, global = true)]
     vault: Option<PathBuf>,
 
+    /// Use a vault profile from the config file
+    #[arg(long, conflicts_with = "vault")]
+    profile: Option<String>,
+
     /// Output in JSON format
     #[arg(long, global = true)]
     json: bool,
 
+    /// Suppress decorative output (only print results)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Act as this identity: human, human:<name>, ai:<agent> or app:<name>.
+    /// AI agents and apps cannot read Sensitive or Critical secrets
+    #[arg(long, global = true, value_name = "ACTOR")]
+    actor: Option<ActorInfo>,
+
+    /// Open the vault read-only: commands that would modify it fail, and
+    /// audit entries go to audit-read-only.jsonl next to it
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Refuse to open a vault whose files other users can access, instead
+    /// of warning about them
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Read the master password from the first line of this file (or
+    /// CLAWBOX_PASSWORD_FILE); it must not be readable by other users.
+    /// Use /dev/fd/N to pass it through a file descriptor
+    #[arg(long, global = true, value_name = "PATH")]
+    password_file: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
-/// Get password from various sources
-fn get_password(prompt: &str) -> Result<String> {
-    // 1. Check environment variable
-    if let Ok(pass) = std::env::var("CLAWBOX_PASSWORD") {
-        return Ok(pass);
+/// Process exit codes
+///
+/// Scripts rely on these values; keep them in sync with docs/CLI-REFERENCE.md.
+mod exit_code {
+    pub const OK: i32 = 0;
+    pub const FAILURE: i32 = 1;
+    pub const VAULT_LOCKED: i32 = 2;
+    pub const NOT_FOUND: i32 = 3;
+    pub const INVALID_PASSWORD: i32 = 4;
+    pub const IO: i32 = 5;
+    pub const ACCESS_DENIED: i32 = 6;
+    pub const APPROVAL_TIMEOUT: i32 = 7;
+    pub const TOO_MANY_ATTEMPTS: i32 = 8;
+    pub const RATE_LIMITED: i32 = 9;
+    pub const READ_ONLY: i32 = 10;
+    pub const USAGE: i32 = 64;
+}
+
+/// Map an error to its documented exit code
+fn exit_code_for(err: &anyhow::Error) -> i32 {
+    for cause in err.chain() {
+        if let Some(e) = cause.downcast_ref::<clawbox_core::Error>() {
+            return match e {
+                clawbox_core::Error::VaultLocked | clawbox_core::Error::VaultNotFound { .. } => {
+                    exit_code::VAULT_LOCKED
+                }
+                clawbox_core::Error::SecretNotFound { .. }
+                | clawbox_core::Error::VersionNotFound { .. } => exit_code::NOT_FOUND,
+                clawbox_core::Error::InvalidPassword => exit_code::INVALID_PASSWORD,
+                clawbox_core::Error::AccessDenied { .. }
+                | clawbox_core::Error::ReauthRequired { .. }
+                | clawbox_core::Error::InsecurePermissions(_) => exit_code::ACCESS_DENIED,
+                clawbox_core::Error::ApprovalTimeout => exit_code::APPROVAL_TIMEOUT,
+                clawbox_core::Error::TooManyAttempts { .. } => exit_code::TOO_MANY_ATTEMPTS,
+                clawbox_core::Error::RateLimited { .. } => exit_code::RATE_LIMITED,
+                clawbox_core::Error::ReadOnly => exit_code::READ_ONLY,
+                clawbox_core::Error::Io(_) => exit_code::IO,
+                _ => exit_code::FAILURE,
+            };
+        }
+        if cause.downcast_ref::<io::Error>().is_some() {
+            return exit_code::IO;
+        }
     }
-    
-    // 2. Check if stdin is a TTY
-    if atty::is(atty::Stream::Stdin) {
-        // Interactive mode - use rpassword
-        Ok(rpassword::prompt_password(prompt)?)
-    } else {
-        // Non-interactive mode - read from stdin
-        let stdin = io::stdin();
-        let mut line = String::new();
-        stdin.lock().read_line(&mut line)?;
-        Ok(line.trim().to_string())
+    exit_code::FAILURE
+}
+
+/// Output settings shared by all commands
+#[derive(Clone, Copy)]
+struct Output {
+    json: bool,
+    quiet: bool,
+}
+
+impl Output {
+    /// Print a decorative status line (suppressed by `--quiet` and `--json`)
+    fn status(&self, msg: impl std::fmt::Display) {
+        if !self.quiet && !self.json {
+            println!("{}", msg);
+        }
+    }
+
+    /// Print the structured result of a command in `--json` mode
+    fn result(&self, value: serde_json::Value) {
+        if self.json {
+            println!("{}", value);
+        }
+    }
+}
+
+/// Get the master password from `--password-file`, `CLAWBOX_PASSWORD` or a prompt
+fn get_password(prompt: &str) -> Result<String> {
+    password::read(password::PASSWORD_VAR, prompt)
+}
+
+/// Read a duress password (`CLAWBOX_DURESS_PASSWORD` or a prompt) and set it
+fn set_duress_password(vault: &mut ClawBox, strength: &StrengthArgs) -> Result<()> {
+    let password = password::read(password::DURESS_PASSWORD_VAR, "Enter duress password: ")?;
+    check_password_strength(&password, strength)?;
+    let confirm = password::read(password::DURESS_PASSWORD_VAR, "Confirm duress password: ")?;
+    if password != confirm {
+        anyhow::bail!("Passwords do not match");
     }
+    vault.set_duress_password(&password)?;
+    Ok(())
+}
+
+/// Prompt on stderr and read one line from stdin
+fn read_line(prompt: &str) -> Result<String> {
+    eprint!("{}", prompt);
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
 }
 
 #[derive(Subcommand)]
@@ -53,23 +174,51 @@ enum Commands {
         /// Path to create vault
         #[arg(long)]
         path: Option<PathBuf>,
+        /// Re-initialize an existing vault, deleting its secrets (a copy of
+        /// the database is kept next to it)
+        #[arg(long)]
+        force: bool,
+        /// With --force, skip typing the vault path to confirm
+        #[arg(long, requires = "force")]
+        yes: bool,
+        /// Also set a duress password, which unlocks a separate decoy vault
+        /// (on an initialized vault, sets only that)
+        #[arg(long)]
+        duress: bool,
+        #[command(flatten)]
+        strength: StrengthArgs,
+    },
+
+    /// Change the master password
+    Passwd {
+        #[command(flatten)]
+        strength: StrengthArgs,
     },
 
     /// Set a secret
     Set {
         /// Secret path (e.g., github/token)
-        path: String,
+        #[arg(required_unless_present = "stdin_json")]
+        path: Option<String>,
         /// Secret value
-        value: String,
+        #[arg(required_unless_present = "stdin_json")]
+        value: Option<String>,
+        /// Read many secrets from stdin as a JSON object ({"path": "value"})
+        /// or an array as written by `export --format json`
+        #[arg(long, conflicts_with_all = ["path", "value"])]
+        stdin_json: bool,
         /// Access level: public, normal, sensitive, critical
-        #[arg(long, default_value = "normal")]
-        access: String,
+        #[arg(long, default_value = "normal", value_parser = parse_access_level)]
+        access: AccessLevel,
         /// Tags (comma-separated)
         #[arg(long)]
         tags: Option<String>,
         /// Note
         #[arg(long)]
         note: Option<String>,
+        /// Expire after this long (e.g. 12h, 7d, 1y); removed by `prune`
+        #[arg(long, value_parser = parse_duration)]
+        ttl: Option<std::time::Duration>,
     },
 
     /// Get a secret
@@ -79,35 +228,198 @@ enum Commands {
         /// Copy to clipboard
         #[arg(long)]
         clipboard: bool,
+        /// Don't wipe the clipboard after the configured timeout
+        #[arg(long, requires = "clipboard")]
+        no_clear: bool,
+        /// Print the value without a trailing newline
+        #[arg(long, short = 'n', visible_alias = "no-newline", conflicts_with = "clipboard")]
+        raw: bool,
+        /// Print the value base64-encoded
+        #[arg(long, conflicts_with = "clipboard")]
+        base64: bool,
+        /// Print Sensitive and Critical values in full on a terminal too
+        /// (see `output.mask_from`)
+        #[arg(long, conflicts_with = "clipboard")]
+        show: bool,
+    },
+
+    /// Run a command with every secret below a prefix in its environment
+    Run {
+        /// Subtree to load, e.g. app/ (app/db/url becomes DB_URL)
+        #[arg(long)]
+        prefix: String,
+        /// Command to run, after `--`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
     },
 
     /// List secrets
     List {
         /// Filter pattern (e.g., github/*)
         pattern: Option<String>,
+        /// Only secrets with this tag (repeatable or comma-separated; all
+        /// must match)
+        #[arg(long, alias = "tags", value_delimiter = ',')]
+        tag: Vec<String>,
+        /// Only secrets at this access level (repeatable or comma-separated;
+        /// any may match)
+        #[arg(long, value_delimiter = ',', value_parser = parse_access_level)]
+        access: Vec<AccessLevel>,
         /// Display as tree
         #[arg(long)]
         tree: bool,
+        /// Sort order
+        #[arg(long, value_enum, default_value_t = ListSort::Path)]
+        sort: ListSort,
+        /// Reverse the sort order
+        #[arg(long)]
+        reverse: bool,
+        /// One path per line (the default when stdout is not a terminal)
+        #[arg(long, conflicts_with = "tree")]
+        plain: bool,
     },
 
-    /// Delete a secret
-    Delete {
+    /// Copy secrets into another vault, preserving their metadata
+    CopyTo {
+        /// Secret paths to copy
+        #[arg(required = true)]
+        paths: Vec<String>,
+        /// Destination profile
+        #[arg(long, required_unless_present = "to_vault", conflicts_with = "to_vault")]
+        profile: Option<String>,
+        /// Destination vault directory
+        #[arg(long)]
+        to_vault: Option<PathBuf>,
+        /// Replace secrets that already exist in the destination
+        #[arg(long)]
+        overwrite: bool,
+    },
+
+    /// Show the version history of a secret
+    History {
+        /// Secret path
+        path: String,
+    },
+
+    /// Restore an earlier version of a secret
+    Rollback {
         /// Secret path
         path: String,
+        /// Version to restore (chosen interactively if omitted)
+        #[arg(long, conflicts_with = "previous")]
+        version: Option<u32>,
+        /// Restore the version before the latest one
+        #[arg(long)]
+        previous: bool,
+        /// Skip confirmation
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Remove expired secrets and old versions, and optionally old audit entries
+    Prune {
+        /// Report what would be removed; exits 1 if anything would be
+        #[arg(long)]
+        dry_run: bool,
+        /// Versions to keep per secret (default from config; 0 keeps all)
+        #[arg(long)]
+        keep_versions: Option<usize>,
+        /// Also remove audit entries older than this (e.g. 90d)
+        #[arg(long, value_parser = parse_duration)]
+        audit_older_than: Option<std::time::Duration>,
+        /// Vacuum the database afterwards to reclaim space
+        #[arg(long, conflicts_with = "dry_run")]
+        compact: bool,
+    },
+
+    /// Render files containing secret references
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+
+    /// Delete secrets
+    Delete {
+        /// Secret paths
+        #[arg(required = true)]
+        paths: Vec<String>,
         /// Skip confirmation
         #[arg(long)]
         force: bool,
+        /// Exit successfully even if some paths don't exist
+        #[arg(long)]
+        ignore_missing: bool,
+        /// Also erase the version history, so the secrets can't be rolled back
+        #[arg(long)]
+        hard: bool,
     },
 
-    /// Unlock the vault
+    /// Unlock the vault and keep it unlocked for a while
     Unlock {
         /// Auto-lock timeout in minutes
-        #[arg(long, default_value = "30")]
+        #[arg(long, default_value = "30", value_parser = clap::value_parser!(u64).range(1..))]
         timeout: u64,
+
+        /// Also keep the session in the macOS Keychain, so it survives the
+        /// session agent restarting (not a reboot)
+        #[arg(long)]
+        keychain: bool,
+    },
+
+    /// Lock the vault, revoking its session
+    Lock {
+        /// Lock every vault with an active session
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// List the session agent's active sessions, or revoke them
+    Sessions {
+        #[command(subcommand)]
+        action: Option<SessionsAction>,
+    },
+
+    /// Manage access tokens that let an agent read part of the vault
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
     },
 
-    /// Lock the vault
-    Lock,
+    /// Show or change vault policies
+    Policy {
+        #[command(subcommand)]
+        action: PolicyAction,
+    },
+
+    /// Show vault status (does not require the password)
+    Status,
+
+    /// Check the database, the audit chain and the vault's file permissions
+    /// (does not require the password); exits 1 if anything is wrong
+    Verify {
+        /// Make the vault's files 0600 and its directories 0700 first
+        #[arg(long)]
+        fix_permissions: bool,
+    },
+
+    /// Run the session agent (started automatically by `unlock`)
+    #[command(hide = true)]
+    Agent {
+        /// Socket to listen on
+        #[arg(long)]
+        socket: PathBuf,
+    },
+
+    /// Wipe a copied secret from the clipboard (started automatically by `get --clipboard`)
+    #[command(hide = true)]
+    ClipboardClear {
+        /// Seconds to wait before wiping
+        #[arg(long)]
+        after: u64,
+        /// Secret that was copied, for the audit log
+        #[arg(long)]
+        secret: String,
+    },
 
     /// View audit log
     Audit {
@@ -129,6 +441,15 @@ enum Commands {
         /// Encrypt output
         #[arg(long)]
         encrypted: bool,
+        /// Write the secrets unencrypted without asking first
+        #[arg(long)]
+        plaintext_ok: bool,
+        /// Include Critical secrets (asks for the master password again)
+        #[arg(long)]
+        include_critical: bool,
+        /// Decrypt on N threads (0: one per CPU)
+        #[arg(long, value_name = "N", default_value_t = 1)]
+        jobs: usize,
     },
 
     /// Import secrets from file
@@ -141,13 +462,21 @@ enum Commands {
         /// Skip existing keys
         #[arg(long)]
         skip_existing: bool,
+        /// env: nest every variable under this path prefix, without splitting on `_`
+        #[arg(long)]
+        env_prefix: Option<String>,
+        /// env: keep each variable name as a single path segment
+        #[arg(long)]
+        no_split: bool,
+        /// env: don't lowercase variable names
+        #[arg(long)]
+        keep_case: bool,
     },
 
-    /// Sync vault with iCloud (macOS only)
-    #[cfg(target_os = "macos")]
+    /// Sync vault with iCloud or the folder set in `[sync]`
     Sync {
         /// Force push to iCloud
-        #[arg(long)]
+        #[arg(long, conflicts_with = "pull")]
         push: bool,
         /// Force pull from iCloud
         #[arg(long)]
@@ -155,217 +484,1774 @@ enum Commands {
         /// Show sync status
         #[arg(long)]
         status: bool,
+        /// Settle conflict copies left by earlier syncs, one path at a time
+        #[arg(long, conflicts_with_all = ["push", "pull", "status"])]
+        resolve: bool,
+        /// Keep syncing after every local or remote change until stopped
+        /// (needs a `[sync]` backend)
+        #[arg(long, conflicts_with_all = ["push", "pull", "status", "resolve"])]
+        watch: bool,
+        /// Show what differs from the remote without changing anything;
+        /// exits 1 if anything does
+        #[arg(long, conflicts_with_all = ["push", "pull", "status", "resolve", "watch"])]
+        diff: bool,
+        /// List the backups taken before pulls, newest first
+        #[arg(long, conflicts_with_all = ["push", "pull", "status", "resolve", "watch", "diff"])]
+        list_backups: bool,
+        /// Leave the audit log out of what is pushed to iCloud (same as
+        /// `sync.audit = false`); pulls keep the local audit log
+        #[arg(long, conflicts_with_all = ["pull", "status", "resolve", "diff", "list_backups"])]
+        no_audit: bool,
+        /// List the devices that synced this vault and when they last did
+        #[arg(long, conflicts_with_all = ["push", "pull", "status", "resolve", "watch", "diff", "list_backups", "no_audit"])]
+        devices: bool,
+        /// Remove a device from the list, e.g. one that was retired
+        #[arg(long, value_name = "ID", conflicts_with_all = ["push", "pull", "status", "resolve", "watch", "diff", "list_backups", "no_audit", "devices"])]
+        forget_device: Option<String>,
+    },
+
+    /// Swap a backup from `sync --list-backups` in for the vault database,
+    /// keeping the replaced database as a new backup
+    RestoreBackup {
+        /// Backup name, e.g. vault-20240601T120304.db
+        name: String,
+    },
+
+    /// List, restore or prune the snapshots taken before destructive
+    /// operations
+    Snapshots {
+        #[command(subcommand)]
+        action: Option<SnapshotsAction>,
     },
 }
 
-fn get_vault_path(custom: Option<PathBuf>) -> PathBuf {
-    custom.unwrap_or_else(|| {
-        dirs::home_dir()
-            .expect("Could not find home directory")
-            .join(".clawbox")
-    })
+/// Sort key for `list`
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ListSort {
+    Path,
+    /// Oldest first
+    Updated,
+    /// Public first
+    Access,
 }
 
-fn parse_access_level(s: &str) -> AccessLevel {
-    match s.to_lowercase().as_str() {
-        "public" => AccessLevel::Public,
-        "normal" => AccessLevel::Normal,
-        "sensitive" => AccessLevel::Sensitive,
-        "critical" => AccessLevel::Critical,
-        _ => AccessLevel::Normal,
-    }
+#[derive(Subcommand)]
+enum TemplateAction {
+    /// Replace {{ clawbox "path" }} placeholders with secret values
+    Render {
+        /// Template file
+        template: PathBuf,
+        /// Output file, written with mode 0600 (default: stdout)
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+        /// Only check that the template parses and every secret exists
+        #[arg(long, conflicts_with = "out")]
+        check: bool,
+    },
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let vault_path = get_vault_path(cli.vault);
+#[derive(Subcommand)]
+enum SessionsAction {
+    /// Revoke a session, making the agent forget its key at once
+    Revoke {
+        /// Session id, as shown by `clawbox sessions`
+        #[arg(required_unless_present = "all")]
+        id: Option<String>,
+        /// Revoke every session
+        #[arg(long, conflicts_with = "id")]
+        all: bool,
+    },
+}
 
-    match cli.command {
-        Commands::Init { path } => {
-            let path = path.unwrap_or(vault_path);
-            println!("{} Initializing vault at {:?}", style("◆").cyan(), path);
+#[derive(Subcommand)]
+enum SnapshotsAction {
+    /// List snapshots, newest first (the default)
+    List,
+    /// Swap a snapshot in for the vault database, first taking a snapshot
+    /// of the database it replaces
+    Restore {
+        /// Snapshot name, e.g. 20240601T120304-change-password.db
+        name: String,
+    },
+    /// Delete all but the newest snapshots
+    Prune {
+        /// Snapshots to keep
+        #[arg(long, default_value_t = 0)]
+        keep: usize,
+    },
+}
 
-            let password = get_password("Enter master password: ")?;
-            let confirm = get_password("Confirm password: ")?;
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Create a token and print it; use it as CLAWBOX_TOKEN
+    Create {
+        /// Token name, shown in the audit log as token:<name>
+        name: String,
+        /// Readable subtree, e.g. llm/* (repeatable)
+        #[arg(long = "path", required = true)]
+        paths: Vec<String>,
+        /// Highest access level readable: public or normal
+        #[arg(long, default_value = "normal", value_parser = parse_token_access)]
+        max_access: AccessLevel,
+        /// Stop working after this long (e.g. 30d)
+        #[arg(long, value_parser = parse_duration)]
+        expires: Option<std::time::Duration>,
+    },
+    /// List tokens (the tokens themselves are not stored)
+    List,
+    /// Revoke a token
+    Revoke {
+        /// Token name
+        name: String,
+    },
+}
 
-            if password != confirm {
-                anyhow::bail!("Passwords do not match");
-            }
+#[derive(Subcommand)]
+enum PolicyAction {
+    /// Show the policies in effect
+    Show,
+    /// Change a policy
+    Set {
+        #[command(subcommand)]
+        policy: PolicySetting,
+    },
+}
 
-            let mut vault = ClawBox::open(&path)?;
-            vault.init(&password)?;
+#[derive(Subcommand)]
+enum PolicySetting {
+    /// Limit how many secrets each actor of a type may read per minute
+    RateLimit {
+        // Not `--actor`, which is the global option
+        /// Actor type: human, ai, app or token
+        #[arg(long)]
+        actor_type: String,
+        /// Reads allowed per actor per minute
+        #[arg(long, required_unless_present = "off", conflicts_with = "off")]
+        per_minute: Option<u32>,
+        /// Only for secrets at this access level (repeatable; default: all)
+        #[arg(long, value_parser = parse_access_level)]
+        access: Vec<AccessLevel>,
+        /// Remove the limit
+        #[arg(long)]
+        off: bool,
+    },
+    /// Change how many snapshots are kept before destructive operations
+    Snapshots {
+        /// Snapshots to keep; 0 turns them off
+        #[arg(long)]
+        keep: usize,
+    },
+}
 
-            println!("{} Vault created at {:?}", style("✓").green(), path);
-        }
+/// Parse a duration like `30s`, `15m`, `12h`, `7d`, `2w` or `1y`
+fn parse_duration(s: &str) -> Result<std::time::Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid duration '{}' (expected e.g. 12h, 7d)", s))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        "y" => 365 * 24 * 60 * 60,
+        _ => anyhow::bail!("Invalid duration unit in '{}' (use s, m, h, d, w or y)", s),
+    };
+    Ok(std::time::Duration::from_secs(number * unit_secs))
+}
 
-        Commands::Set {
-            path,
-            value,
-            access,
-            tags,
-            note,
-        } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
+/// A secret as `list --json` shows it
+fn list_entry_json(s: &clawbox_core::SecretInfo) -> serde_json::Value {
+    serde_json::json!({
+        "path": s.path,
+        "access": format!("{:?}", s.access),
+        "tags": s.tags,
+        "updated_at": s.updated_at.to_rfc3339(),
+        "size": s.size,
+    })
+}
 
-            let opts = SetOptions {
-                access: parse_access_level(&access),
-                tags: tags
-                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
-                    .unwrap_or_default(),
-                note,
-                ..Default::default()
-            };
+/// Write a file readable only by its owner
+fn write_private(path: &std::path::Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
 
-            vault.set(&path, &value, opts)?;
-            println!("{} Secret set: {}", style("✓").green(), path);
-        }
+    let mut file = create_private(path).with_context(|| format!("Could not write {:?}", path))?;
+    file.write_all(contents)?;
+    Ok(())
+}
 
-        Commands::Get { path, clipboard } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
+/// Create or truncate a file readable only by its owner
+fn create_private(path: &std::path::Path) -> io::Result<std::fs::File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 
-            match vault.get(&path)? {
-                Some(value) => {
-                    if clipboard {
-                        // TODO: Implement clipboard
-                        println!("{} Copied to clipboard", style("✓").green());
-                    } else if cli.json {
-                        println!(
-                            "{}",
-                            serde_json::json!({
-                                "path": path,
-                                "value": value
-                            })
-                        );
-                    } else {
-                        println!("{}", value);
-                    }
-                }
-                None => {
-                    anyhow::bail!("Secret not found: {}", path);
-                }
-            }
+    let file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // `mode` only applies to newly created files
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+/// A file readable only by its owner, created on the first write so that
+/// nothing is touched until there is something to write
+struct PrivateFile<'a> {
+    path: &'a std::path::Path,
+    file: Option<std::fs::File>,
+}
+
+impl<'a> PrivateFile<'a> {
+    fn new(path: &'a std::path::Path) -> Self {
+        Self { path, file: None }
+    }
+
+    fn create(&mut self) -> io::Result<&mut std::fs::File> {
+        match &mut self.file {
+            Some(file) => Ok(file),
+            file => Ok(file.insert(create_private(self.path)?)),
         }
+    }
 
-        Commands::List { pattern, tree } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
+    /// Remove what was written so far, e.g. after a failed export
+    fn discard(&mut self) {
+        if self.file.take().is_some() {
+            let _ = std::fs::remove_file(self.path);
+        }
+    }
+}
 
-            let secrets = vault.list(pattern.as_deref())?;
+impl io::Write for PrivateFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.create()?.write(buf)
+    }
 
-            if cli.json {
-                let json: Vec<_> = secrets
-                    .iter()
-                    .map(|s| {
-                        serde_json::json!({
-                            "path": s.path,
-                            "access": format!("{:?}", s.access),
-                            "tags": s.tags,
-                        })
-                    })
-                    .collect();
-                println!("{}", serde_json::to_string_pretty(&json)?);
-            } else if tree {
-                // TODO: Implement tree view
-                for secret in secrets {
-                    println!("📁 {}", secret.path);
-                }
-            } else {
-                for secret in secrets {
-                    let access_icon = match secret.access {
-                        AccessLevel::Public => "🔓",
-                        AccessLevel::Normal => "🔑",
-                        AccessLevel::Sensitive => "🔐",
-                        AccessLevel::Critical => "🔒",
-                    };
-                    println!("{} {}", access_icon, secret.path);
-                }
-            }
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
         }
+    }
+}
 
-        Commands::Delete { path, force } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
+/// Expand a leading `~` and `$VAR` / `${VAR}` references in a path
+fn expand_path(path: &std::path::Path) -> Result<PathBuf> {
+    let raw = path
+        .to_str()
+        .context("Vault path is not valid UTF-8")?;
 
-            if !force {
-                print!("Delete '{}'? [y/N] ", path);
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input)?;
-                if !input.trim().eq_ignore_ascii_case("y") {
-                    println!("Cancelled");
-                    return Ok(());
-                }
-            }
+    let mut expanded = String::with_capacity(raw.len());
+    let mut chars = raw.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
 
-            if vault.delete(&path)? {
-                println!("{} Deleted: {}", style("✓").green(), path);
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                name.push(c);
+                chars.next();
             } else {
-                println!("Secret not found: {}", path);
+                break;
             }
         }
-
-        Commands::Unlock { timeout: _ } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
-            println!("{} Vault unlocked", style("✓").green());
+        if braced && chars.next() != Some('}') {
+            anyhow::bail!("Unterminated '${{' in path {:?}", raw);
         }
-
-        Commands::Lock => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            vault.lock();
-            println!("{} Vault locked", style("✓").green());
+        if name.is_empty() {
+            // A lone '$' is kept literally
+            expanded.push('$');
+            continue;
         }
+        let value = std::env::var(&name)
+            .with_context(|| format!("Environment variable ${} in path {:?} is not set", name, raw))?;
+        expanded.push_str(&value);
+    }
 
-        Commands::Audit { key, since } => {
-            let mut vault = ClawBox::open(&vault_path)?;
-            unlock_vault(&mut vault)?;
-            
-            use clawbox_core::audit::AuditFilter;
-            use chrono::{Duration, Utc};
-            
-            let mut filter = AuditFilter::default();
-            filter.key_path = key;
-            filter.limit = Some(50);
-            
-            // Parse since parameter (e.g., "1h", "24h", "7d")
-            if let Some(since_str) = since {
-                let since_time = if since_str.ends_with('h') {
-                    let hours: i64 = since_str.trim_end_matches('h').parse().unwrap_or(24);
-                    Utc::now() - Duration::hours(hours)
-                } else if since_str.ends_with('d') {
-                    let days: i64 = since_str.trim_end_matches('d').parse().unwrap_or(7);
-                    Utc::now() - Duration::days(days)
-                } else {
-                    Utc::now() - Duration::hours(24)
-                };
-                filter.since = Some(since_time);
-            }
-            
-            let entries = vault.audit(&filter)?;
-            
-            if cli.json {
-                println!("{}", serde_json::to_string_pretty(&entries)?);
-            } else if entries.is_empty() {
-                println!("No audit entries found.");
-            } else {
-                let count = entries.len();
-                println!("{:<20} {:<8} {:<8} {:<30} {}", 
-                    "TIMESTAMP", "ACTOR", "ACTION", "KEY", "STATUS");
-                println!("{}", "-".repeat(80));
-                
-                for entry in &entries {
-                    let status = if entry.success { 
-                        style("✓").green().to_string() 
-                    } else { 
-                        style("✗").red().to_string() 
-                    };
-                    
-                    println!("{:<20} {:<8} {:<8} {:<30} {}", 
-                        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
-                        entry.actor.actor_type,
-                        entry.action.as_str(),
-                        if entry.key_path.len() > 28 { 
+    if expanded == "~" || expanded.starts_with("~/") {
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        return Ok(home.join(expanded[1..].trim_start_matches('/')));
+    }
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// Master password strength options
+#[derive(clap::Args)]
+struct StrengthArgs {
+    /// Accept a master password that fails the strength check
+    #[arg(long)]
+    allow_weak_password: bool,
+    /// Minimum password strength score (0-4)
+    #[arg(long, default_value_t = clawbox_core::crypto::DEFAULT_MIN_PASSWORD_SCORE,
+          value_parser = clap::value_parser!(u8).range(0..=4))]
+    min_password_score: u8,
+}
+
+/// Render a strength meter for a 0-4 score
+fn strength_bar(score: u8) -> String {
+    let label = match score {
+        0 => style("very weak").red(),
+        1 => style("weak").red(),
+        2 => style("fair").yellow(),
+        3 => style("good").green(),
+        _ => style("strong").green(),
+    };
+    let filled = "█".repeat(score as usize + 1);
+    let empty = "░".repeat(4 - score.min(4) as usize);
+    format!("{}{} {}", filled, empty, label)
+}
+
+/// Reject a weak new master password unless explicitly allowed
+fn check_password_strength(password: &str, args: &StrengthArgs) -> Result<()> {
+    let strength = clawbox_core::crypto::assess_password(password);
+
+    if atty::is(atty::Stream::Stdin) {
+        eprintln!("  Strength: {}", strength_bar(strength.score));
+    }
+
+    if strength.is_acceptable(args.min_password_score) || args.allow_weak_password {
+        return Ok(());
+    }
+
+    let mut msg = format!(
+        "Master password is too weak (score {}/4, need at least {})",
+        strength.score, args.min_password_score
+    );
+    if let Some(warning) = &strength.warning {
+        msg.push_str(&format!("\n  {}", warning));
+    }
+    for suggestion in &strength.suggestions {
+        msg.push_str(&format!("\n  - {}", suggestion));
+    }
+    msg.push_str("\n  Use --allow-weak-password to accept it anyway (not recommended)");
+    anyhow::bail!(msg)
+}
+
+/// Resolve the vault directory: `--vault`, `--profile`, `CLAWBOX_VAULT`,
+/// the configured default profile, then `~/.clawbox`
+fn resolve_vault_path(
+    flag: Option<PathBuf>,
+    profile: Option<&str>,
+    config: &Config,
+) -> Result<PathBuf> {
+    let chosen = match (flag, profile) {
+        (Some(path), _) => Some(path),
+        (None, Some(name)) => Some(config.profile(name)?.vault.clone()),
+        (None, None) => match std::env::var_os("CLAWBOX_VAULT") {
+            Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+            _ => match &config.default_profile {
+                Some(name) => Some(config.profile(name)?.vault.clone()),
+                None => None,
+            },
+        },
+    };
+    get_vault_path(chosen)
+}
+
+/// Expand a vault path, defaulting to `~/.clawbox`
+fn get_vault_path(custom: Option<PathBuf>) -> Result<PathBuf> {
+    let path = match custom {
+        Some(path) if !path.as_os_str().is_empty() => expand_path(&path)?,
+        _ => dirs::home_dir()
+            .context("Could not find home directory")?
+            .join(".clawbox"),
+    };
+
+    if path.exists() && !path.is_dir() {
+        anyhow::bail!("Vault path {:?} exists but is not a directory", path);
+    }
+
+    Ok(path)
+}
+
+/// Parse an access level; anything unknown is an error rather than a
+/// guess, since the level decides who may read a secret
+fn parse_access_level(s: &str) -> Result<AccessLevel> {
+    match s.to_lowercase().as_str() {
+        "public" => Ok(AccessLevel::Public),
+        "normal" => Ok(AccessLevel::Normal),
+        "sensitive" => Ok(AccessLevel::Sensitive),
+        "critical" => Ok(AccessLevel::Critical),
+        _ => anyhow::bail!("unknown access level '{}'; use public, normal, sensitive or critical", s),
+    }
+}
+
+/// Parse `token create --max-access`: tokens never read Sensitive or
+/// Critical secrets, so only `public` and `normal` are accepted
+fn parse_token_access(s: &str) -> Result<AccessLevel> {
+    match s.to_lowercase().as_str() {
+        "public" => Ok(AccessLevel::Public),
+        "normal" => Ok(AccessLevel::Normal),
+        _ => anyhow::bail!("a token's access level must be public or normal, not '{}'", s),
+    }
+}
+
+/// A secret to write with `set --stdin-json`
+type BulkItem = (String, String, SetOptions);
+
+/// Turn `set --stdin-json` input into secrets to write
+///
+/// Accepts `{"path": "value", ...}` or the array written by
+/// `export --format json`; fields given per item override `defaults`. Items
+/// are returned alongside the reason each one is invalid, if any.
+fn parse_bulk_set(input: serde_json::Value, defaults: &SetOptions) -> Result<(Vec<BulkItem>, Vec<Option<String>>)> {
+    #[derive(serde::Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct BulkSecret {
+        path: String,
+        value: String,
+        #[serde(default)]
+        access: Option<String>,
+        #[serde(default)]
+        tags: Option<Vec<String>>,
+        #[serde(default)]
+        note: Option<String>,
+    }
+
+    let secrets: Vec<(String, Result<BulkSecret, String>)> = match input {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .map(|(path, value)| {
+                let secret = match value {
+                    serde_json::Value::String(value) => Ok(BulkSecret {
+                        path: path.clone(),
+                        value,
+                        access: None,
+                        tags: None,
+                        note: None,
+                    }),
+                    _ => Err("value must be a string".to_string()),
+                };
+                (path, secret)
+            })
+            .collect(),
+        serde_json::Value::Array(array) => array
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let path = item
+                    .get("path")
+                    .and_then(|p| p.as_str())
+                    .map_or_else(|| format!("[{}]", i), str::to_string);
+                (path, serde_json::from_value(item).map_err(|e| e.to_string()))
+            })
+            .collect(),
+        _ => anyhow::bail!("Expected a JSON object or array on stdin"),
+    };
+
+    let mut items = Vec::with_capacity(secrets.len());
+    let mut errors = Vec::with_capacity(secrets.len());
+    let mut seen = std::collections::HashSet::new();
+    for (path, secret) in secrets {
+        let mut opts = defaults.clone();
+        let checked = secret.and_then(|secret| {
+            let path = clawbox_core::storage::normalize_and_validate_path(&secret.path).map_err(|e| e.to_string())?;
+            if !seen.insert(path.clone()) {
+                return Err("duplicate path".to_string());
+            }
+            if let Some(access) = secret.access {
+                opts.access = parse_access_level(&access).map_err(|e| e.to_string())?;
+            }
+            if let Some(tags) = secret.tags {
+                opts.tags = tags;
+            }
+            if secret.note.is_some() {
+                opts.note = secret.note;
+            }
+            Ok((path, secret.value))
+        });
+        match checked {
+            Ok((path, value)) => {
+                items.push((path, value, opts));
+                errors.push(None);
+            }
+            Err(error) => {
+                items.push((path, String::new(), opts));
+                errors.push(Some(error));
+            }
+        }
+    }
+    Ok((items, errors))
+}
+
+fn main() {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(if e.use_stderr() { exit_code::USAGE } else { exit_code::OK });
+        }
+    };
+    password::init(cli.password_file.clone(), cli.json);
+
+    let json = cli.json;
+    let code = match run(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            let code = exit_code_for(&e);
+            if json {
+                eprintln!(
+                    "{}",
+                    serde_json::json!({
+                        "ok": false,
+                        "error": format!("{:#}", e),
+                        "code": code,
+                    })
+                );
+            } else {
+                eprintln!("{} {:#}", style("Error:").red(), e);
+            }
+            code
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Run a command, returning the exit code for non-error outcomes
+fn run(cli: Cli) -> Result<i32> {
+    let config = Config::load()?;
+    let session_ttl = match &config.session_ttl {
+        Some(ttl) => parse_duration(ttl).context("Invalid session_ttl in the config file")?,
+        None => keycache::DEFAULT_TTL,
+    };
+    keycache::init(config.session_cache.then_some(session_ttl));
+    let vault_path = resolve_vault_path(cli.vault, cli.profile.as_deref(), &config)?;
+    let out = Output {
+        json: cli.json,
+        quiet: cli.quiet,
+    };
+    let actor = cli.actor.unwrap_or_else(ActorInfo::human);
+    let read_only = cli.read_only;
+    let open_opts = OpenOptions { read_only, enforce_permissions: cli.strict, ..Default::default() };
+    let hooks = config.hooks.hooks()?;
+
+    match cli.command {
+        Commands::Init { path, force, yes, duress, strength } => {
+            if read_only {
+                return Err(clawbox_core::Error::ReadOnly.into());
+            }
+            let path = match path {
+                Some(path) => get_vault_path(Some(path))?,
+                None => vault_path,
+            };
+
+            // Only open an existing vault here; a failed init shouldn't leave files behind
+            let existing = if path.join("vault.db").exists() {
+                Some(ClawBox::open(&path)?)
+            } else {
+                None
+            };
+            let reinit = match &existing {
+                Some(vault) => vault.is_initialized()?,
+                None => false,
+            };
+            // On an initialized vault, `--duress` alone only adds the duress password
+            if reinit && duress && !force {
+                let mut vault = existing.expect("initialized vault is open");
+                vault.check_unlock_allowed()?;
+                vault.unlock(&get_password("Enter master password: ")?).context("Failed to unlock vault")?;
+                set_duress_password(&mut vault, &strength)?;
+                out.status(format!("{} Duress password set for {:?}", style("✓").green(), path));
+                out.result(serde_json::json!({ "ok": true, "action": "init", "vault": path, "duress": true }));
+                return Ok(exit_code::OK);
+            }
+            if reinit {
+                if !force {
+                    return Err(anyhow::Error::new(clawbox_core::Error::AlreadyInitialized {
+                        path: path.to_string_lossy().to_string(),
+                    })
+                    .context(
+                        "Refusing to re-initialize: a new master password would make every \
+                         existing secret permanently unreadable. If you have lost the password \
+                         and accept losing all data, run 'clawbox init --force'",
+                    ));
+                }
+
+                if !yes {
+                    eprintln!(
+                        "{} Re-initializing {} deletes every secret, its history, the audit log and \
+                         all access tokens. A copy of the database is kept next to it.",
+                        style("WARNING:").red().bold(),
+                        path.display()
+                    );
+                    let expected = path.to_string_lossy();
+                    if read_line("Type the vault path to confirm: ")?.trim() != expected {
+                        anyhow::bail!("Confirmation did not match; vault left unchanged");
+                    }
+                }
+            }
+
+            out.status(format!("{} Initializing vault at {:?}", style("◆").cyan(), path));
+
+            let password = get_password("Enter master password: ")?;
+            check_password_strength(&password, &strength)?;
+            let confirm = get_password("Confirm password: ")?;
+
+            if password != confirm {
+                anyhow::bail!("Passwords do not match");
+            }
+
+            let mut vault = match existing {
+                Some(vault) => vault,
+                None => ClawBox::open(&path)?,
+            };
+            let backup = if reinit {
+                let backup = vault.reinit(&password)?;
+                agent::lock(Some(&path))?;
+                out.status(format!(
+                    "{} Previous database saved to {:?}; it opens with the old password if you remember it",
+                    style("!").yellow(),
+                    backup
+                ));
+                Some(backup)
+            } else {
+                vault.init(&password)?;
+                None
+            };
+            if duress {
+                set_duress_password(&mut vault, &strength)?;
+            }
+
+            out.status(format!("{} Vault created at {:?}", style("✓").green(), path));
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "init",
+                "vault": path,
+                "backup": backup,
+                "duress": duress,
+            }));
+        }
+
+        Commands::Passwd { strength } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            if !vault.is_initialized()? {
+                return Err(clawbox_core::Error::VaultNotFound {
+                    path: vault_path.to_string_lossy().to_string(),
+                }
+                .into());
+            }
+
+            let old_password = get_password("Enter current password: ")?;
+            let new_password = password::read(password::NEW_PASSWORD_VAR, "Enter new password: ")?;
+            check_password_strength(&new_password, &strength)?;
+            let confirm = password::read(password::NEW_PASSWORD_VAR, "Confirm new password: ")?;
+
+            if new_password != confirm {
+                anyhow::bail!("Passwords do not match");
+            }
+
+            vault.change_password(&old_password, &new_password)?;
+            // Any cached session key is now stale
+            agent::lock(Some(&vault_path))?;
+
+            out.status(format!("{} Password changed", style("✓").green()));
+            out.result(serde_json::json!({ "ok": true, "action": "passwd" }));
+        }
+
+        Commands::Set {
+            path,
+            value,
+            stdin_json,
+            access,
+            tags,
+            note,
+            ttl,
+        } => {
+            let opts = SetOptions {
+                access,
+                ttl,
+                tags: tags
+                    .map(|t| t.split(',').map(|s| s.trim().to_string()).collect())
+                    .unwrap_or_default(),
+                note,
+            };
+
+            if stdin_json {
+                let mut input = String::new();
+                std::io::stdin().read_to_string(&mut input)?;
+                let input: serde_json::Value =
+                    serde_json::from_str(&input).context("Invalid JSON on stdin")?;
+
+                let (items, errors) = parse_bulk_set(input, &opts)?;
+                if errors.iter().any(Option::is_some) {
+                    let mut results = Vec::with_capacity(items.len());
+                    for ((path, _, _), error) in items.iter().zip(&errors) {
+                        match error {
+                            Some(error) => out.status(format!("{} {}: {}", style("✗").red(), path, error)),
+                            None => out.status(format!("  {}: ok", path)),
+                        }
+                        results.push(serde_json::json!({
+                            "path": path,
+                            "status": if error.is_some() { "invalid" } else { "not_written" },
+                            "error": error,
+                        }));
+                    }
+                    out.result(serde_json::json!(results));
+                    eprintln!("Error: Invalid input, nothing was written");
+                    return Ok(exit_code::FAILURE);
+                }
+
+                let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+                unlock_vault(&mut vault)?;
+
+                let refs: Vec<(&str, &str, SetOptions)> = items
+                    .iter()
+                    .map(|(path, value, opts)| (path.as_str(), value.as_str(), opts.clone()))
+                    .collect();
+                vault.set_many(&refs)?;
+
+                for (path, _, _) in &items {
+                    out.status(format!("{} Secret set: {}", style("✓").green(), path));
+                }
+                out.result(serde_json::json!(items
+                    .iter()
+                    .map(|(path, _, _)| serde_json::json!({ "path": path, "status": "set" }))
+                    .collect::<Vec<_>>()));
+                return Ok(exit_code::OK);
+            }
+
+            // clap guarantees both are present without --stdin-json
+            let (Some(path), Some(value)) = (path, value) else {
+                anyhow::bail!("A path and a value are required");
+            };
+
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            vault.set(&path, &value, opts)?;
+            out.status(format!("{} Secret set: {}", style("✓").green(), path));
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "set",
+                "path": path,
+            }));
+        }
+
+        Commands::Get { path, clipboard, no_clear, raw, base64, show } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            match read_secret(&vault, &path, &mut None)? {
+                Some(value) => {
+                    // Only a terminal keeps values in scrollback; pipes get them in full
+                    let masked = !clipboard
+                        && !show
+                        && console::Term::stdout().is_term()
+                        && vault.info(&path)?.is_some_and(|info| config.output.mask_from.masks(info.access));
+                    if masked {
+                        let preview = mask::preview(&value);
+                        if cli.json {
+                            println!("{}", serde_json::json!({ "path": path, "value": preview, "masked": true }));
+                        } else {
+                            println!("{}", preview);
+                        }
+                        if !out.quiet {
+                            eprintln!("Value masked; use --show to print it or --clipboard to copy it");
+                        }
+                    } else if clipboard {
+                        let clear_after = match config.clipboard.clear_after {
+                            0 => None,
+                            _ if no_clear => None,
+                            secs => Some(std::time::Duration::from_secs(secs)),
+                        };
+                        clipboard::Clipboard::new(&config.clipboard)?.copy(
+                            &value,
+                            clear_after,
+                            &vault_path,
+                            &path,
+                        )?;
+                        match clear_after {
+                            Some(after) => out.status(format!(
+                                "{} Copied to clipboard (clears in {}s)",
+                                style("✓").green(),
+                                after.as_secs()
+                            )),
+                            None => out.status(format!("{} Copied to clipboard", style("✓").green())),
+                        }
+                        out.result(serde_json::json!({
+                            "ok": true,
+                            "action": "copy_to_clipboard",
+                            "path": path,
+                            "clear_after_secs": clear_after.map(|d| d.as_secs()),
+                        }));
+                    } else if cli.json {
+                        let json = if base64 {
+                            serde_json::json!({
+                                "path": path,
+                                "value": BASE64.encode(value.as_bytes()),
+                                "encoding": "base64",
+                            })
+                        } else {
+                            serde_json::json!({
+                                "path": path,
+                                "value": value.expose()
+                            })
+                        };
+                        println!("{}", json);
+                    } else {
+                        // Exactly the value, plus a newline unless --raw
+                        use std::io::Write;
+                        let value = match base64 {
+                            true => SecretValue::new(BASE64.encode(value.as_bytes())),
+                            false => value,
+                        };
+                        let mut stdout = io::stdout().lock();
+                        stdout.write_all(value.as_bytes())?;
+                        if !raw {
+                            stdout.write_all(b"\n")?;
+                        }
+                        stdout.flush()?;
+                    }
+                }
+                None => {
+                    return Err(clawbox_core::Error::SecretNotFound { path }.into());
+                }
+            }
+        }
+
+        Commands::List { pattern, tag, access, tree, sort, reverse, plain } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+            let filter = ListFilter {
+                pattern,
+                tags: tag,
+                access,
+            };
+
+            let table = !(cli.json || tree || plain || !console::Term::stdout().is_term());
+            if sort == ListSort::Path && !reverse && !table {
+                // Secrets come by path: print each as it is read
+                use std::io::Write;
+                let mut stdout = io::stdout().lock();
+                if cli.json {
+                    let mut array = jsonarray::JsonArray::new(&mut stdout);
+                    vault.for_each_secret(&filter, |s| Ok(array.push(&list_entry_json(&s))?))?;
+                    writeln!(array.finish()?)?;
+                } else {
+                    let icon = if tree { "📁 " } else { "" };
+                    vault.for_each_secret(&filter, |s| Ok(writeln!(stdout, "{}{}", icon, s.path)?))?;
+                }
+                return Ok(exit_code::OK);
+            }
+
+            let mut secrets = vec![];
+            vault.for_each_secret(&filter, |s| {
+                secrets.push(s);
+                Ok(())
+            })?;
+            match sort {
+                ListSort::Path => secrets.sort_by(|a, b| a.path.cmp(&b.path)),
+                ListSort::Updated => secrets.sort_by(|a, b| a.updated_at.cmp(&b.updated_at).then(a.path.cmp(&b.path))),
+                ListSort::Access => {
+                    secrets.sort_by(|a, b| (a.access as u8).cmp(&(b.access as u8)).then(a.path.cmp(&b.path)))
+                }
+            }
+            if reverse {
+                secrets.reverse();
+            }
+
+            if cli.json {
+                let json: Vec<_> = secrets.iter().map(list_entry_json).collect();
+                println!("{}", serde_json::to_string_pretty(&json)?);
+            } else if tree {
+                // TODO: Implement tree view
+                for secret in secrets {
+                    println!("📁 {}", secret.path);
+                }
+            } else if plain || !console::Term::stdout().is_term() {
+                for secret in secrets {
+                    println!("{}", secret.path);
+                }
+            } else if secrets.is_empty() {
+                out.status("No secrets found.");
+            } else {
+                use table::Column;
+
+                let columns = [
+                    Column::new("PATH").shrink_to(16),
+                    Column::new("ACCESS"),
+                    Column::new("TAGS").shrink_to(8),
+                    Column::new("UPDATED"),
+                    Column::new("SIZE").right(),
+                ];
+                let rows: Vec<Vec<String>> = secrets
+                    .iter()
+                    .map(|s| {
+                        let access_icon = match s.access {
+                            AccessLevel::Public => "🔓",
+                            AccessLevel::Normal => "🔑",
+                            AccessLevel::Sensitive => "🔐",
+                            AccessLevel::Critical => "🔒",
+                        };
+                        vec![
+                            s.path.clone(),
+                            format!("{} {:?}", access_icon, s.access),
+                            s.tags.join(","),
+                            s.updated_at.format("%Y-%m-%d %H:%M").to_string(),
+                            format_size(s.size as u64),
+                        ]
+                    })
+                    .collect();
+                let width = console::Term::stdout().size_checked().map(|(_, cols)| cols as usize);
+                print!("{}", table::render(&columns, &rows, width));
+            }
+        }
+
+        Commands::History { path } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            let history = vault.history(&path)?;
+            if history.is_empty() {
+                return Err(clawbox_core::Error::SecretNotFound { path }.into());
+            }
+            // The newest version is the live value unless the secret was deleted
+            let live = vault.info(&path)?.is_some();
+
+            if cli.json {
+                let json: Vec<_> = history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        serde_json::json!({
+                            "version": v.version,
+                            "created_at": v.created_at.to_rfc3339(),
+                            "created_by": v.created_by,
+                            "access": format!("{:?}", v.access),
+                            "fingerprint": v.fingerprint,
+                            "current": live && i == 0,
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&json)?);
+            } else {
+                println!("{:<9} {:<20} {:<16} {:<10} FINGERPRINT", "VERSION", "WRITTEN", "BY", "ACCESS");
+                for (i, v) in history.iter().enumerate() {
+                    let marker = if live && i == 0 { "*" } else { " " };
+                    println!(
+                        "{:<9} {:<20} {:<16} {:<10} {}",
+                        format!("{} {}", v.version, marker),
+                        v.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        v.created_by,
+                        format!("{:?}", v.access),
+                        v.fingerprint
+                    );
+                }
+                if !live {
+                    out.status(format!("{} has been deleted; use `clawbox rollback` to restore it", path));
+                }
+            }
+        }
+
+        Commands::Rollback { path, version, previous, force } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            let history = vault.history(&path)?;
+            if history.is_empty() {
+                return Err(clawbox_core::Error::SecretNotFound { path }.into());
+            }
+
+            let version = match (version, previous) {
+                (Some(version), _) => version,
+                (None, true) => history
+                    .get(1)
+                    .map(|v| v.version)
+                    .with_context(|| format!("{} has no previous version", path))?,
+                (None, false) => {
+                    if !atty::is(atty::Stream::Stdin) || cli.json {
+                        anyhow::bail!("Specify --version or --previous when not running interactively");
+                    }
+                    let items: Vec<String> = history
+                        .iter()
+                        .map(|v| {
+                            format!(
+                                "v{}  {}  {}  {}",
+                                v.version,
+                                v.created_at.format("%Y-%m-%d %H:%M:%S"),
+                                v.created_by,
+                                v.fingerprint
+                            )
+                        })
+                        .collect();
+                    let selected = dialoguer::Select::new()
+                        .with_prompt(format!("Version of {} to restore", path))
+                        .items(&items)
+                        .default(history.len().min(2) - 1)
+                        .interact()?;
+                    history[selected].version
+                }
+            };
+
+            if !force {
+                let answer = read_line(&format!("Restore {} to version {}? [y/N] ", path, version))?;
+                if !answer.trim().eq_ignore_ascii_case("y") {
+                    out.status("Cancelled");
+                    out.result(serde_json::json!({
+                        "ok": false,
+                        "action": "rollback",
+                        "path": path,
+                        "version": version,
+                        "cancelled": true,
+                    }));
+                    return Ok(exit_code::OK);
+                }
+            }
+
+            vault.rollback(&path, version)?;
+            out.status(format!("{} Restored {} to version {}", style("✓").green(), path, version));
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "rollback",
+                "path": path,
+                "version": version,
+            }));
+        }
+
+        Commands::CopyTo { paths, profile, to_vault, overwrite } => {
+            let dest_path = resolve_vault_path(to_vault, profile.as_deref(), &config)?;
+            if std::fs::canonicalize(&dest_path).ok() == std::fs::canonicalize(&vault_path).ok() {
+                anyhow::bail!("Source and destination are the same vault");
+            }
+            let dest_label = match &profile {
+                Some(name) => format!("profile '{}'", name),
+                None => format!("{:?}", dest_path),
+            };
+
+            let mut source = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut source)?;
+
+            if !dest_path.join("vault.db").exists() {
+                return Err(clawbox_core::Error::VaultNotFound {
+                    path: dest_path.to_string_lossy().to_string(),
+                }
+                .into());
+            }
+            let mut dest = open_vault(&dest_path, &actor, &OpenOptions { read_only: false, ..open_opts.clone() }, &hooks)?;
+            unlock_vault_with(
+                &mut dest,
+                password::DEST_PASSWORD_VAR,
+                &format!("Enter master password for {}: ", dest_label),
+            )?;
+
+            // Check everything up front so a failure doesn't leave a partial copy
+            let mut secrets = vec![];
+            let mut reauth = None;
+            for path in &paths {
+                let not_found = || clawbox_core::Error::SecretNotFound { path: path.clone() };
+                let value = read_secret(&source, path, &mut reauth)?.ok_or_else(not_found)?;
+                let info = source.info(path)?.ok_or_else(not_found)?;
+                if !overwrite && dest.info(path)?.is_some() {
+                    anyhow::bail!(
+                        "Secret '{}' already exists in {} (use --overwrite to replace it)",
+                        path,
+                        dest_label
+                    );
+                }
+                secrets.push((value, info));
+            }
+
+            for (value, info) in secrets {
+                let opts = SetOptions {
+                    access: info.access,
+                    tags: info.tags,
+                    note: info.note,
+                    ..Default::default()
+                };
+                dest.set(&info.path, &value, opts)?;
+                out.status(format!("{} Copied {} to {}", style("✓").green(), info.path, dest_label));
+            }
+
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "copy",
+                "destination": dest_path,
+                "paths": paths,
+            }));
+        }
+
+        Commands::Prune { dry_run, keep_versions, audit_older_than, compact } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            let keep_versions = keep_versions.unwrap_or(config.history.keep_versions);
+            let audit_before = audit_older_than
+                .map(|age| chrono::Duration::from_std(age).context("--audit-older-than is too large"))
+                .transpose()?
+                .map(|age| chrono::Utc::now() - age);
+            let opts = clawbox_core::PruneOptions {
+                keep_versions: (keep_versions > 0).then_some(keep_versions),
+                audit_before,
+                dry_run,
+            };
+
+            let report = vault.prune(&opts)?;
+            if compact {
+                vault.compact()?;
+            }
+
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            if report.is_empty() {
+                out.status("Nothing to prune");
+            } else {
+                out.status(format!("{}:", verb));
+                out.status(format!(
+                    "  Expired secrets:  {}{}",
+                    report.expired_secrets.len(),
+                    if report.expired_secrets.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" ({})", report.expired_secrets.join(", "))
+                    }
+                ));
+                out.status(format!("  Old versions:     {}", report.old_versions));
+                if audit_before.is_some() {
+                    out.status(format!("  Audit entries:    {}", report.audit_entries));
+                }
+            }
+            if compact {
+                out.status(format!("{} Database compacted", style("✓").green()));
+            }
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "prune",
+                "dry_run": dry_run,
+                "expired_secrets": report.expired_secrets,
+                "old_versions": report.old_versions,
+                "audit_entries": report.audit_entries,
+                "compacted": compact,
+            }));
+
+            // Lets cron jobs alert when there is something to clean up
+            if dry_run && !report.is_empty() {
+                return Ok(exit_code::FAILURE);
+            }
+        }
+
+        Commands::Run { prefix, command } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+            let secrets = match vault.get_prefix(&prefix) {
+                Err(clawbox_core::Error::ReauthRequired { path }) => {
+                    let password = zeroize::Zeroizing::new(password::read(
+                        password::PASSWORD_VAR,
+                        &format!("Re-enter master password to read {}: ", path),
+                    )?);
+                    vault.get_prefix_with_reauth(&prefix, &password)?
+                }
+                secrets => secrets?,
+            };
+            // Hooks finish and the database is released before the command starts
+            drop(vault);
+
+            let base = prefix.trim_end_matches('*').trim_end_matches('/');
+            let mut child = std::process::Command::new(&command[0]);
+            child.args(&command[1..]);
+            for (path, value) in &secrets {
+                let relative = path.strip_prefix(base).unwrap_or(path).trim_start_matches('/');
+                child.env(envfile::relative_var_name(relative), value.expose());
+            }
+            let status = child.status().with_context(|| format!("Could not run {:?}", command[0]))?;
+            return Ok(status.code().unwrap_or(exit_code::FAILURE));
+        }
+
+        Commands::Template { action: TemplateAction::Render { template, out: output, check } } => {
+            let source = std::fs::read_to_string(&template)
+                .with_context(|| format!("Could not read template {:?}", template))?;
+            let parsed = template::Template::parse(&source)
+                .with_context(|| format!("Invalid template {:?}", template))?;
+            let references = parsed.references();
+
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            if check {
+                let mut missing = vec![];
+                for path in &references {
+                    if vault.info(path)?.is_none() {
+                        missing.push(*path);
+                    }
+                }
+                if !missing.is_empty() {
+                    return Err(clawbox_core::Error::SecretNotFound { path: missing.join(", ") })
+                        .with_context(|| format!("Template {:?} references missing secrets", template));
+                }
+
+                out.status(format!(
+                    "{} Template OK ({} secret references)",
+                    style("✓").green(),
+                    references.len()
+                ));
+                out.result(serde_json::json!({
+                    "ok": true,
+                    "action": "template_check",
+                    "template": template,
+                    "references": references,
+                }));
+                return Ok(exit_code::OK);
+            }
+
+            let values = match vault.get_many(&references) {
+                // Read one by one, asking for the password once
+                Err(clawbox_core::Error::ReauthRequired { .. }) => {
+                    let mut reauth = None;
+                    references
+                        .iter()
+                        .map(|path| read_secret(&vault, path, &mut reauth))
+                        .collect::<Result<Vec<_>>>()?
+                }
+                values => values?,
+            };
+            let missing: Vec<&str> = references
+                .iter()
+                .zip(&values)
+                .filter(|(_, value)| value.is_none())
+                .map(|(path, _)| *path)
+                .collect();
+            if !missing.is_empty() {
+                return Err(clawbox_core::Error::SecretNotFound { path: missing.join(", ") })
+                    .with_context(|| format!("Template {:?} references missing secrets", template));
+            }
+
+            let rendered = zeroize::Zeroizing::new(parsed.render(|path| {
+                references
+                    .iter()
+                    .position(|r| *r == path)
+                    .and_then(|i| values[i].as_deref())
+            })?);
+
+            match &output {
+                Some(dest) => {
+                    write_private(dest, rendered.as_bytes())?;
+                    out.status(format!(
+                        "{} Rendered {} secrets into {:?}",
+                        style("✓").green(),
+                        references.len(),
+                        dest
+                    ));
+                    out.result(serde_json::json!({
+                        "ok": true,
+                        "action": "template_render",
+                        "template": template,
+                        "output": dest,
+                        "references": references,
+                    }));
+                }
+                None => {
+                    use std::io::Write;
+                    io::stdout().write_all(rendered.as_bytes())?;
+                }
+            }
+        }
+
+        Commands::Delete { paths, force, ignore_missing, hard } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            if !force {
+                let how = if hard { " and its history (this cannot be undone)" } else { "" };
+                let prompt = match paths.as_slice() {
+                    [path] => format!("Delete '{}'{}? [y/N] ", path, how),
+                    _ => {
+                        eprintln!("About to delete:");
+                        for path in &paths {
+                            eprintln!("  {}", path);
+                        }
+                        let how = if hard { " and their history (this cannot be undone)" } else { "" };
+                        format!("Delete these {} secrets{}? [y/N] ", paths.len(), how)
+                    }
+                };
+                if !read_line(&prompt)?.trim().eq_ignore_ascii_case("y") {
+                    out.status("Cancelled");
+                    let results: Vec<_> = paths
+                        .iter()
+                        .map(|path| serde_json::json!({ "path": path, "status": "cancelled" }))
+                        .collect();
+                    out.result(serde_json::json!(results));
+                    return Ok(exit_code::OK);
+                }
+            }
+
+            let path_refs: Vec<&str> = paths.iter().map(String::as_str).collect();
+            let mode = if hard { DeleteMode::Hard } else { DeleteMode::Soft };
+            let existed = vault.delete_many(&path_refs, mode)?;
+
+            let mut results = Vec::with_capacity(paths.len());
+            for (path, deleted) in paths.iter().zip(&existed) {
+                if *deleted {
+                    out.status(format!("{} Deleted: {}", style("✓").green(), path));
+                } else {
+                    out.status(format!("{} Not found: {}", style("✗").red(), path));
+                }
+                results.push(serde_json::json!({
+                    "path": path,
+                    "status": if *deleted { "deleted" } else { "not_found" },
+                }));
+            }
+            out.result(serde_json::json!(results));
+
+            if !ignore_missing && existed.contains(&false) {
+                return Ok(exit_code::NOT_FOUND);
+            }
+        }
+
+        Commands::Unlock { timeout, keychain } => {
+            if keychain && agent::backing().is_none() {
+                anyhow::bail!("--keychain needs the macOS Keychain, which this build cannot use");
+            }
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            let key = vault.export_key()?;
+            agent::store(&vault_path, &key, std::time::Duration::from_secs(timeout * 60), keychain)?;
+
+            out.status(format!(
+                "{} Vault unlocked (will lock in {} minutes)",
+                style("✓").green(),
+                timeout
+            ));
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "unlock",
+                "timeout_minutes": timeout,
+                "keychain": keychain,
+            }));
+        }
+
+        Commands::Lock { all } => {
+            let mut locked = agent::lock(if all { None } else { Some(&vault_path) })?;
+            for path in keycache::clear(if all { None } else { Some(&vault_path) })? {
+                if !locked.contains(&path) {
+                    locked.push(path);
+                }
+            }
+
+            for path in &locked {
+                // The vault may have been moved since it was unlocked
+                if let Ok(vault) = ClawBox::builder(path).read_only(read_only).create(false).open() {
+                    vault.record_lock();
+                }
+            }
+
+            // Best effort: a missing clipboard shouldn't stop the lock
+            let clipboard_cleared = all
+                && clipboard::Clipboard::new(&config.clipboard)
+                    .and_then(|clipboard| clipboard.clear_ours())
+                    .unwrap_or(false);
+
+            if locked.is_empty() {
+                out.status(if all { "No vaults were unlocked" } else { "Vault was not unlocked" });
+            } else {
+                for path in &locked {
+                    out.status(format!("{} Vault locked: {}", style("✓").green(), path.display()));
+                }
+            }
+            if clipboard_cleared {
+                out.status(format!("{} Clipboard cleared", style("✓").green()));
+            }
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "lock",
+                "locked": locked,
+                "clipboard_cleared": clipboard_cleared,
+            }));
+
+            if locked.is_empty() {
+                return Ok(exit_code::VAULT_LOCKED);
+            }
+        }
+
+        Commands::Sessions { action } => {
+            if actor.is_restricted() {
+                return Err(clawbox_core::Error::AccessDenied {
+                    reason: "only a human can list or revoke sessions".to_string(),
+                }
+                .into());
+            }
+
+            match action {
+                None => {
+                    let sessions = agent::sessions()?;
+                    if out.json {
+                        out.result(serde_json::json!(sessions));
+                    } else if sessions.is_empty() {
+                        out.status("No active sessions");
+                    } else {
+                        let time = |t: i64| {
+                            chrono::DateTime::from_timestamp(t, 0)
+                                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                                .unwrap_or_default()
+                        };
+                        for session in &sessions {
+                            let origin = match &session.origin {
+                                Some(origin) => format!(
+                                    "{} (pid {})",
+                                    session.user.clone().unwrap_or_else(|| format!("uid {}", origin.uid)),
+                                    origin.pid.map_or("unknown".to_string(), |pid| pid.to_string())
+                                ),
+                                None => "restored from Keychain".to_string(),
+                            };
+                            println!("{}  {}", style(&session.id).bold(), session.vault.display());
+                            println!(
+                                "    created {}, last used {}, locks in {} min, by {}",
+                                time(session.created_at),
+                                session.last_used.map_or("never".to_string(), time),
+                                session.expires_in_secs.div_ceil(60),
+                                origin
+                            );
+                        }
+                    }
+                }
+                Some(SessionsAction::Revoke { id, .. }) => {
+                    let revoked = agent::revoke(id.as_deref())?;
+                    for session in &revoked {
+                        // The vault may have been moved since it was unlocked
+                        if let Ok(vault) = open_vault(&session.vault, &actor, &open_opts, &hooks) {
+                            vault.record_session_revoke(&session.id);
+                        }
+                        out.status(format!(
+                            "{} Session {} revoked: {}",
+                            style("✓").green(),
+                            session.id,
+                            session.vault.display()
+                        ));
+                    }
+                    let found = id.is_none() || !revoked.is_empty();
+                    match &id {
+                        Some(id) if !found => out.status(format!("No session with id {}", id)),
+                        None if revoked.is_empty() => out.status("No active sessions"),
+                        _ => {}
+                    }
+                    out.result(serde_json::json!({
+                        "ok": found,
+                        "action": "revoke",
+                        "revoked": revoked,
+                    }));
+
+                    if !found {
+                        return Ok(exit_code::NOT_FOUND);
+                    }
+                }
+            }
+        }
+
+        Commands::Token { action } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            match action {
+                TokenAction::Create { name, paths, max_access, expires } => {
+                    let expires_at = expires
+                        .map(|expires| -> Result<_> { Ok(chrono::Utc::now() + chrono::Duration::from_std(expires)?) })
+                        .transpose()?;
+                    let scope = TokenScope { path_prefixes: paths, max_access, expires_at };
+                    let token = vault.create_token(&name, scope)?;
+
+                    if out.json {
+                        out.result(serde_json::json!({
+                            "ok": true,
+                            "action": "token_create",
+                            "name": name,
+                            "token": token.expose(),
+                            "expires_at": expires_at,
+                        }));
+                    } else {
+                        out.status(format!(
+                            "{} Token {} created; it is shown only once:",
+                            style("✓").green(),
+                            name
+                        ));
+                        println!("{}", token.expose());
+                    }
+                }
+                TokenAction::List => {
+                    let tokens = vault.tokens()?;
+                    if out.json {
+                        let tokens: Vec<_> = tokens
+                            .iter()
+                            .map(|token| {
+                                serde_json::json!({
+                                    "name": token.name,
+                                    "paths": token.scope.path_prefixes,
+                                    "max_access": format!("{:?}", token.scope.max_access).to_lowercase(),
+                                    "expires_at": token.scope.expires_at,
+                                    "created_at": token.created_at,
+                                })
+                            })
+                            .collect();
+                        out.result(serde_json::json!(tokens));
+                    } else if tokens.is_empty() {
+                        out.status("No access tokens");
+                    } else {
+                        for token in &tokens {
+                            let expires = token
+                                .scope
+                                .expires_at
+                                .map(|t| format!("expires {}", t.format("%Y-%m-%d %H:%M")))
+                                .unwrap_or_else(|| "no expiry".to_string());
+                            println!(
+                                "{}  {}  up to {:?}, {}",
+                                style(&token.name).bold(),
+                                token.scope.path_prefixes.join(", "),
+                                token.scope.max_access,
+                                expires
+                            );
+                        }
+                    }
+                }
+                TokenAction::Revoke { name } => {
+                    let revoked = vault.revoke_token(&name)?;
+                    match revoked {
+                        true => out.status(format!("{} Token {} revoked", style("✓").green(), name)),
+                        false => out.status(format!("Token {} not found", name)),
+                    }
+                    out.result(serde_json::json!({
+                        "ok": revoked,
+                        "action": "token_revoke",
+                        "name": name,
+                    }));
+                    if !revoked {
+                        return Ok(exit_code::NOT_FOUND);
+                    }
+                }
+            }
+        }
+
+        Commands::Policy { action } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+
+            match action {
+                PolicyAction::Show => {
+                    let levels = [AccessLevel::Public, AccessLevel::Normal, AccessLevel::Sensitive, AccessLevel::Critical];
+                    let mut rate_limits = vec![];
+                    for actor_type in ["human", "ai", "app", "token"] {
+                        for access in levels {
+                            if let Some(per_minute) = vault.read_rate_limit(actor_type, access)? {
+                                rate_limits.push((actor_type, access, per_minute));
+                            }
+                        }
+                    }
+                    let mut reauth = vec![];
+                    for access in levels {
+                        if vault.requires_reauth(access)? {
+                            reauth.push(access);
+                        }
+                    }
+                    let snapshot_keep = vault.snapshot_keep()?;
+
+                    if out.json {
+                        let level = |access: AccessLevel| format!("{:?}", access).to_lowercase();
+                        out.result(serde_json::json!({
+                            "rate_limits": rate_limits
+                                .iter()
+                                .map(|(actor_type, access, per_minute)| serde_json::json!({
+                                    "actor": actor_type,
+                                    "access": level(*access),
+                                    "per_minute": per_minute,
+                                }))
+                                .collect::<Vec<_>>(),
+                            "reauth": reauth.iter().map(|access| level(*access)).collect::<Vec<_>>(),
+                            "snapshot_keep": snapshot_keep,
+                        }));
+                    } else {
+                        println!("{}", style("Read rate limits (per actor, per minute):").bold());
+                        if rate_limits.is_empty() {
+                            println!("  none");
+                        }
+                        for (actor_type, access, per_minute) in &rate_limits {
+                            println!("  {:<6} {:<10} {}", actor_type, format!("{:?}", access), per_minute);
+                        }
+                        println!("{}", style("Password asked again to read:").bold());
+                        let reauth: Vec<String> = reauth.iter().map(|access| format!("{:?}", access)).collect();
+                        println!("  {}", if reauth.is_empty() { "none".to_string() } else { reauth.join(", ") });
+                        println!("{}", style("Snapshots kept:").bold());
+                        println!("  {}", if snapshot_keep == 0 { "none (off)".to_string() } else { snapshot_keep.to_string() });
+                    }
+                }
+                PolicyAction::Set { policy: PolicySetting::RateLimit { actor_type, per_minute, access, off } } => {
+                    let levels = match access.is_empty() {
+                        true => vec![AccessLevel::Public, AccessLevel::Normal, AccessLevel::Sensitive, AccessLevel::Critical],
+                        false => access,
+                    };
+                    let per_minute = if off { None } else { per_minute };
+                    for access in &levels {
+                        vault.set_read_rate_limit(&actor_type, *access, per_minute)?;
+                    }
+
+                    match per_minute {
+                        Some(n) => out.status(format!(
+                            "{} {} actors may read {} secrets per minute each",
+                            style("✓").green(),
+                            actor_type,
+                            n
+                        )),
+                        None => out.status(format!("{} Rate limit for {} actors removed", style("✓").green(), actor_type)),
+                    }
+                    out.result(serde_json::json!({
+                        "ok": true,
+                        "action": "policy_set",
+                        "policy": "rate_limit",
+                        "actor": actor_type,
+                        "access": levels.iter().map(|access| format!("{:?}", access).to_lowercase()).collect::<Vec<_>>(),
+                        "per_minute": per_minute,
+                    }));
+                }
+                PolicyAction::Set { policy: PolicySetting::Snapshots { keep } } => {
+                    vault.set_snapshot_keep(keep)?;
+                    match keep {
+                        0 => out.status(format!("{} Snapshots turned off", style("✓").green())),
+                        n => out.status(format!("{} Keeping the newest {} snapshots", style("✓").green(), n)),
+                    }
+                    out.result(serde_json::json!({
+                        "ok": true,
+                        "action": "policy_set",
+                        "policy": "snapshots",
+                        "keep": keep,
+                    }));
+                }
+            }
+        }
+
+        Commands::Status => {
+            print_status(&vault_path, out, &open_opts)?;
+        }
+
+        Commands::Verify { fix_permissions } => {
+            // Loose permissions are reported below, not refused
+            let mut vault = ClawBox::builder(&vault_path)
+                .options(open_opts)
+                .create(false)
+                .enforce_permissions(false)
+                .actor(actor.clone())
+                .open()?;
+            let fixed = match fix_permissions {
+                true => vault.fix_permissions()?.problems,
+                false => vec![],
+            };
+            for problem in &fixed {
+                out.status(format!(
+                    "{} {} {:03o} → {:03o}",
+                    style("✓").green(),
+                    problem.path.display(),
+                    problem.mode,
+                    problem.expected
+                ));
+            }
+            let permissions = &vault.permission_report().problems;
+            let report = vault.verify()?;
+            let ok = report.is_ok() && permissions.is_empty();
+
+            for problem in permissions {
+                out.status(format!(
+                    "{} {} has mode {:03o} (should be {:03o})",
+                    style("✗").red(),
+                    problem.path.display(),
+                    problem.mode,
+                    problem.expected
+                ));
+            }
+            if !permissions.is_empty() {
+                out.status("  Run 'clawbox verify --fix-permissions' to fix them");
+            }
+            for error in &report.database_errors {
+                out.status(format!("{} Database: {}", style("✗").red(), error));
+            }
+            if !report.audit_chain_valid {
+                out.status(format!("{} Audit log hash chain is broken", style("✗").red()));
+            }
+            if report.schema_version > clawbox_core::storage::SCHEMA_VERSION {
+                out.status(format!(
+                    "{} Schema version {} is newer than this clawbox supports ({})",
+                    style("✗").red(),
+                    report.schema_version,
+                    clawbox_core::storage::SCHEMA_VERSION
+                ));
+            }
+            if ok {
+                out.status(format!("{} Vault verified", style("✓").green()));
+            }
+            out.result(serde_json::json!({
+                "ok": ok,
+                "action": "verify",
+                "permissions": permissions,
+                "fixed_permissions": fixed,
+                "database_errors": report.database_errors,
+                "audit_chain_valid": report.audit_chain_valid,
+                "schema_version": report.schema_version,
+            }));
+            if !ok {
+                return Ok(exit_code::FAILURE);
+            }
+        }
+
+        Commands::ClipboardClear { after, secret } => {
+            let clipboard = clipboard::Clipboard::new(&config.clipboard)?;
+            if clipboard.run_clearer(std::time::Duration::from_secs(after))? {
+                open_vault(&vault_path, &actor, &open_opts, &hooks)?.record_clipboard_clear(&secret);
+            }
+        }
+
+        Commands::Agent { socket } => {
+            agent::run(&socket, agent::PeerPolicy::new(config.agent.executables()?))?;
+        }
+
+        Commands::Audit { key, since } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+            
+            use clawbox_core::audit::AuditFilter;
+            use chrono::{Duration, Utc};
+            
+            let mut filter = AuditFilter {
+                key_path: key,
+                limit: Some(50),
+                ..Default::default()
+            };
+            
+            // Parse since parameter (e.g., "1h", "24h", "7d")
+            if let Some(since_str) = since {
+                let since_time = if since_str.ends_with('h') {
+                    let hours: i64 = since_str.trim_end_matches('h').parse().unwrap_or(24);
+                    Utc::now() - Duration::hours(hours)
+                } else if since_str.ends_with('d') {
+                    let days: i64 = since_str.trim_end_matches('d').parse().unwrap_or(7);
+                    Utc::now() - Duration::days(days)
+                } else {
+                    Utc::now() - Duration::hours(24)
+                };
+                filter.since = Some(since_time);
+            }
+            
+            let entries = vault.audit(&filter)?;
+            
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&entries)?);
+            } else if entries.is_empty() {
+                out.status("No audit entries found.");
+            } else {
+                let count = entries.len();
+                println!("{:<20} {:<8} {:<8} {:<30} STATUS",
+                    "TIMESTAMP", "ACTOR", "ACTION", "KEY");
+                println!("{}", "-".repeat(80));
+                
+                for entry in &entries {
+                    let status = if entry.success { 
+                        style("✓").green().to_string() 
+                    } else { 
+                        style("✗").red().to_string() 
+                    };
+                    
+                    println!("{:<20} {:<8} {:<8} {:<30} {}", 
+                        entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                        entry.actor.actor_type,
+                        entry.action.as_str(),
+                        if entry.key_path.len() > 28 { 
                             format!("{}...", &entry.key_path[..25]) 
                         } else { 
                             entry.key_path.clone() 
@@ -374,71 +2260,144 @@ fn main() -> Result<()> {
                     );
                 }
                 
-                println!("\nTotal: {} entries", count);
+                out.status(format!("\nTotal: {} entries", count));
             }
         }
 
-        Commands::Export { output, format, encrypted } => {
-            let mut vault = ClawBox::open(&vault_path)?;
+        Commands::Export { output, format, encrypted, plaintext_ok, include_critical, jobs } => {
+            if encrypted {
+                // TODO: Implement encrypted export
+                anyhow::bail!("Encrypted export not yet implemented");
+            }
+            if !matches!(format.as_str(), "json" | "env" | "yaml") {
+                anyhow::bail!("Unsupported format: {}", format);
+            }
+
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
             unlock_vault(&mut vault)?;
-            
-            let secrets = vault.list(None)?;
-            
+            vault.set_decrypt_jobs(jobs);
+
+            let summary = vault.export_summary(include_critical)?;
+            if !plaintext_ok {
+                if summary.skipped_critical > 0 {
+                    eprintln!(
+                        "{} Critical secret(s) left out; add --include-critical to export them",
+                        summary.skipped_critical
+                    );
+                }
+                let highest = summary.highest.map(|access| format!("{:?}", access)).unwrap_or_else(|| "-".to_string());
+                let prompt = format!(
+                    "Write {} secrets (highest access level: {}) UNENCRYPTED to {:?}? [y/N] ",
+                    summary.count, highest, output
+                );
+                if !read_line(&prompt)?.trim().eq_ignore_ascii_case("y") {
+                    out.status("Cancelled");
+                    out.result(serde_json::json!({
+                        "ok": false,
+                        "action": "export",
+                        "output": output,
+                        "cancelled": true,
+                    }));
+                    return Ok(exit_code::OK);
+                }
+            }
+
+            let opts = clawbox_core::PlaintextExportOptions {
+                plaintext_ok: true,
+                include_critical,
+                destination: output.display().to_string(),
+                format: format.clone(),
+            };
+            let password = match summary.needs_reauth {
+                true => Some(zeroize::Zeroizing::new(password::read(
+                    password::PASSWORD_VAR,
+                    "Re-enter master password to export: ",
+                )?)),
+                false => None,
+            };
             #[derive(serde::Serialize)]
             struct ExportSecret {
                 path: String,
-                value: String,
+                #[serde(serialize_with = "serialize_exposed")]
+                value: SecretValue,
                 access: String,
                 tags: Vec<String>,
                 note: Option<String>,
             }
-            
-            let mut export_data: Vec<ExportSecret> = vec![];
-            
-            for secret in secrets {
-                if let Some(value) = vault.get(&secret.path)? {
-                    export_data.push(ExportSecret {
-                        path: secret.path.clone(),
-                        value,
-                        access: format!("{:?}", secret.access),
-                        tags: secret.tags.clone(),
-                        note: secret.note.clone(),
-                    });
-                }
-            }
-            
-            let content = match format.as_str() {
-                "json" => serde_json::to_string_pretty(&export_data)?,
-                "env" => {
-                    let mut env = String::new();
-                    for s in &export_data {
-                        let key = s.path.replace("/", "_").to_uppercase();
-                        env.push_str(&format!("{}=\"{}\"\n", key, s.value.replace("\"", "\\\"")));
-                    }
-                    env
+
+            // Each secret is written out as soon as it is decrypted
+            use std::io::Write;
+            let password = password.as_deref().map(String::as_str);
+            let mut file = PrivateFile::new(&output);
+            let exported = match format.as_str() {
+                "json" => {
+                    let mut array = jsonarray::JsonArray::new(&mut file);
+                    vault
+                        .export_plaintext_each(&opts, password, |info, value| {
+                            Ok(array.push(&ExportSecret {
+                                path: info.path,
+                                value,
+                                access: format!("{:?}", info.access),
+                                tags: info.tags,
+                                note: info.note,
+                            })?)
+                        })
+                        .and_then(|count| {
+                            array.finish()?;
+                            Ok(count)
+                        })
                 }
-                "yaml" => {
-                    let mut yaml = String::from("# ClawBox Export\n");
-                    for s in &export_data {
-                        yaml.push_str(&format!("{}:\n  value: \"{}\"\n", s.path, s.value));
-                    }
-                    yaml
+                "env" => vault.export_plaintext_each(&opts, password, |info, value| {
+                    let line = zeroize::Zeroizing::new(envfile::format_line(&envfile::var_name(&info.path, &info.tags), &value));
+                    Ok(file.write_all(line.as_bytes())?)
+                }),
+                _ => {
+                    const HEADER: &[u8] = b"# ClawBox Export\n";
+                    vault
+                        .export_plaintext_each(&opts, password, |info, value| {
+                            if file.file.is_none() {
+                                file.write_all(HEADER)?;
+                            }
+                            let entry = zeroize::Zeroizing::new(format!("{}:\n  value: \"{}\"\n", info.path, value.expose()));
+                            Ok(file.write_all(entry.as_bytes())?)
+                        })
+                        .and_then(|count| {
+                            if count == 0 {
+                                file.write_all(HEADER)?;
+                            }
+                            Ok(count)
+                        })
                 }
-                _ => anyhow::bail!("Unsupported format: {}", format),
             };
-            
-            if encrypted {
-                // TODO: Implement encrypted export
-                anyhow::bail!("Encrypted export not yet implemented");
+            // An empty export still writes its (empty) file
+            let exported = exported.and_then(|count| {
+                file.create()?;
+                file.flush()?;
+                Ok(count)
+            });
+            if exported.is_err() {
+                file.discard();
             }
-            
-            std::fs::write(&output, content)?;
-            println!("{} Exported {} secrets to {:?}", 
-                style("✓").green(), export_data.len(), output);
+            let count = exported?;
+
+            out.status(format!("{} Exported {} secrets to {:?}",
+                style("✓").green(), count, output));
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "export",
+                "output": output,
+                "format": format,
+                "count": count,
+            }));
         }
 
-        Commands::Import { input, format, skip_existing } => {
-            let mut vault = ClawBox::open(&vault_path)?;
+        Commands::Import { input, format, skip_existing, env_prefix, no_split, keep_case } => {
+            let mapping = envfile::PathMapping { prefix: env_prefix, no_split, keep_case };
+            if format != "env" && (mapping.prefix.is_some() || no_split || keep_case) {
+                anyhow::bail!("--env-prefix, --no-split and --keep-case only apply to --format env");
+            }
+
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
             unlock_vault(&mut vault)?;
             
             let content = std::fs::read_to_string(&input)?;
@@ -457,125 +2416,887 @@ fn main() -> Result<()> {
             
             let secrets: Vec<ImportSecret> = match format.as_str() {
                 "json" => serde_json::from_str(&content)?,
-                "env" => {
-                    let mut secrets = vec![];
-                    for line in content.lines() {
-                        let line = line.trim();
-                        if line.is_empty() || line.starts_with('#') {
-                            continue;
-                        }
-                        if let Some((key, value)) = line.split_once('=') {
-                            let path = key.trim().to_lowercase().replace("_", "/");
-                            let value = value.trim().trim_matches('"').to_string();
-                            secrets.push(ImportSecret {
-                                path,
-                                value,
-                                access: None,
-                                tags: None,
-                                note: None,
-                            });
-                        }
-                    }
-                    secrets
-                }
+                "env" => envfile::parse(&content)
+                    .with_context(|| format!("Invalid env file {:?}", input))?
+                    .into_iter()
+                    .map(|(name, value)| ImportSecret {
+                        path: mapping.path_for(&name),
+                        value,
+                        access: None,
+                        tags: Some(vec![envfile::var_tag(&name)]),
+                        note: None,
+                    })
+                    .collect(),
                 _ => anyhow::bail!("Unsupported format: {}", format),
             };
             
-            let mut imported = 0;
+            // Check every path before writing any
+            let secrets = secrets
+                .into_iter()
+                .map(|mut secret| {
+                    secret.path = clawbox_core::storage::normalize_and_validate_path(&secret.path)?;
+                    Ok(secret)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // Overwriting can be undone with `clawbox snapshots restore`
+            let mut snapshot = None;
+            if !skip_existing {
+                let mut overwrites = false;
+                for secret in &secrets {
+                    overwrites |= vault.info(&secret.path)?.is_some();
+                }
+                if overwrites {
+                    snapshot = vault.snapshot(clawbox_core::snapshot::Operation::Import)?.map(|snapshot| snapshot.name);
+                }
+            }
+
             let mut skipped = 0;
-            
-            for secret in secrets {
-                if skip_existing {
-                    if vault.get(&secret.path)?.is_some() {
-                        skipped += 1;
-                        continue;
-                    }
+            let mut items = vec![];
+            let mut writes = vec![];
+            for secret in &secrets {
+                if skip_existing && vault.info(&secret.path)?.is_some() {
+                    skipped += 1;
+                    items.push(serde_json::json!({ "path": secret.path, "status": "skipped" }));
+                    continue;
                 }
                 
                 let opts = SetOptions {
-                    access: secret.access.as_ref()
-                        .map(|a| parse_access_level(a))
+                    access: secret
+                        .access
+                        .as_deref()
+                        .map(parse_access_level)
+                        .transpose()
+                        .with_context(|| secret.path.clone())?
                         .unwrap_or_default(),
-                    tags: secret.tags.unwrap_or_default(),
-                    note: secret.note,
+                    tags: secret.tags.clone().unwrap_or_default(),
+                    note: secret.note.clone(),
                     ..Default::default()
                 };
-                
-                vault.set(&secret.path, &secret.value, opts)?;
-                imported += 1;
+                writes.push((secret.path.as_str(), secret.value.as_bytes(), opts));
+                items.push(serde_json::json!({ "path": secret.path, "status": "imported" }));
             }
+
+            // One transaction for the lot, secrets and audit entries alike,
+            // with a progress line on stderr every so many
+            const PROGRESS_EVERY: usize = 1000;
+            let imported = writes.len();
+            vault.set_many_with_progress(&writes, |done, total| {
+                let due = done % PROGRESS_EVERY == 0 || (done == total && total > PROGRESS_EVERY);
+                if due && !out.quiet && !out.json {
+                    eprintln!("  Imported {}/{}...", done, total);
+                }
+            })?;
             
-            println!("{} Imported {} secrets ({} skipped)", 
-                style("✓").green(), imported, skipped);
+            out.status(format!("{} Imported {} secrets ({} skipped)",
+                style("✓").green(), imported, skipped));
+            if let Some(snapshot) = &snapshot {
+                out.status(format!("  Snapshot of the previous vault: {}", snapshot));
+            }
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "import",
+                "imported": imported,
+                "skipped": skipped,
+                "items": items,
+                "snapshot": snapshot,
+            }));
         }
 
-        #[cfg(target_os = "macos")]
-        Commands::Sync { push, pull, status } => {
-            use clawbox_core::icloud::ICloudSync;
+        Commands::Sync { push, pull, status, resolve, watch, diff, list_backups, no_audit, devices, forget_device } => {
+            use clawbox_core::icloud::{ICloudSync, SyncResult};
             
-            let mut vault = ClawBox::open(&vault_path)?;
-            let sync = ICloudSync::new(vault_path.clone());
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            if list_backups {
+                let backups = vault.backups()?;
+                for backup in &backups {
+                    out.status(format!(
+                        "  {}  {}  {} bytes",
+                        backup.name,
+                        backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        backup.size
+                    ));
+                }
+                if backups.is_empty() {
+                    out.status("No backups yet");
+                }
+                out.result(serde_json::json!(backups));
+                return Ok(exit_code::OK);
+            }
+            if resolve {
+                unlock_vault(&mut vault)?;
+                return resolve_conflicts(&mut vault, out);
+            }
+            if devices || forget_device.is_some() {
+                unlock_vault(&mut vault)?;
+                let icloud = (config.sync.backend == config::SyncBackendKind::ICloud).then(|| {
+                    let mut sync = ICloudSync::new(vault_path.clone(), &vault.vault_id()?);
+                    sync.set_key(vault.sync_key()?);
+                    report_icloud_downloads(&mut sync, &config.sync, out);
+                    anyhow::Ok(sync)
+                }).transpose()?;
+                if let Some(device) = forget_device {
+                    let forgotten = match &icloud {
+                        Some(sync) => sync.forget_device(&device)?,
+                        None => vault.forget_sync_device(sync_backend(&config.sync, &vault_path)?.0.as_ref(), &device)?,
+                    };
+                    if !forgotten {
+                        anyhow::bail!("No device {} has synced this vault; see 'clawbox sync --devices'", device);
+                    }
+                    out.status(format!("{} Forgot device {}", style("✓").green(), device));
+                    out.result(serde_json::json!({ "ok": true, "action": "forget_device", "device": device }));
+                    return Ok(exit_code::OK);
+                }
+                let devices = match &icloud {
+                    Some(sync) => sync.devices()?,
+                    None => vault.sync_devices(sync_backend(&config.sync, &vault_path)?.0.as_ref())?,
+                };
+                print_sync_devices(&devices, out);
+                return Ok(exit_code::OK);
+            }
+            if diff {
+                unlock_vault(&mut vault)?;
+                let (diffs, location) = if config.sync.backend == config::SyncBackendKind::ICloud {
+                    let mut sync = ICloudSync::new(vault_path.clone(), &vault.vault_id()?);
+                    let location = sync.icloud_path().ok_or(clawbox_core::Error::ICloudUnavailable)?.display().to_string();
+                    sync.set_key(vault.sync_key()?);
+                    report_icloud_downloads(&mut sync, &config.sync, out);
+                    (vault.sync_diff_records(&sync.remote_records()?)?, location)
+                } else {
+                    let (backend, location) = sync_backend(&config.sync, &vault_path)?;
+                    (vault.sync_diff(backend.as_ref())?, location)
+                };
+                return Ok(print_sync_diff(&diffs, &location, out));
+            }
+            if config.sync.backend != config::SyncBackendKind::ICloud {
+                if push || pull {
+                    anyhow::bail!("--push and --pull only apply to the iCloud backend; run 'clawbox sync' to merge");
+                }
+                let (backend, location) = sync_backend(&config.sync, &vault_path)?;
+                if !status {
+                    prepare_sync(&mut vault, &config.sync)?;
+                }
+                if watch {
+                    let remote_dir = match config.sync.backend {
+                        config::SyncBackendKind::Dir => Some(PathBuf::from(&location)),
+                        _ => None,
+                    };
+                    let poll = std::time::Duration::from_secs(config.sync.poll_interval.max(1));
+                    return sync_watch(&mut vault, backend.as_ref(), &location, remote_dir, poll, out);
+                }
+                return sync_merge(&mut vault, backend.as_ref(), &location, status, out);
+            }
+            if watch {
+                anyhow::bail!("sync --watch needs a [sync] backend (dir, webdav or git); see 'clawbox config'");
+            }
+            // Each vault has its own folder in the container
+            let vault_id = vault.vault_id()?;
+            let sync = ICloudSync::new(vault_path.clone(), &vault_id);
             
             if !sync.is_available() {
+                if push || pull {
+                    return Err(clawbox_core::Error::ICloudUnavailable.into());
+                }
                 println!("{} iCloud Drive not available", style("✗").red());
                 println!("  Make sure iCloud Drive is enabled in System Preferences");
-                return Ok(());
+                return Ok(exit_code::OK);
             }
             
             if status {
-                let local = sync.local_version().unwrap_or(0);
+                let state = vault.sync_status()?;
+                let local = state.local_version;
                 let remote = sync.remote_version().unwrap_or(0);
+                let pending = vault.pending_changes()?;
                 
-                println!("📊 Sync Status");
-                println!("  Local version:  {}", local);
-                println!("  Remote version: {}", remote);
-                println!("  iCloud path: {:?}", sync.icloud_path());
+                out.status("📊 Sync Status");
+                out.status(format!("  Local version:  {}", local));
+                out.status(format!("  Remote version: {}", remote));
+                out.status(format!("  Last sync:      {}", format_last_sync(&state)));
+                out.status(format!("  Vault ID:       {}", vault_id));
+                out.status(format!("  iCloud path: {:?}", sync.icloud_path()));
                 
                 if remote > local {
-                    println!("  {} Remote has newer version", style("↓").cyan());
+                    out.status(format!("  {} Remote has newer version", style("↓").cyan()));
                 } else if local > remote {
-                    println!("  {} Local has newer version", style("↑").cyan());
-                } else {
-                    println!("  {} Up to date", style("✓").green());
+                    out.status(format!("  {} Local has newer version", style("↑").cyan()));
+                } else if pending == 0 {
+                    out.status(format!("  {} Up to date", style("✓").green()));
                 }
-                return Ok(());
+                print_pending_changes(pending, out);
+                let mut json = sync_status_json(&state, &sync.icloud_path().map_or(String::new(), |p| p.display().to_string()), remote, pending);
+                json["vault_id"] = vault_id.into();
+                out.result(json);
+                return Ok(exit_code::OK);
             }
             
             unlock_vault(&mut vault)?;
-            
-            // For sync, we need the derived key
-            // This is a simplified approach - in production we'd store key hash
-            println!("🔄 Syncing with iCloud...");
-            
-            if push {
-                // Force push
-                println!("  Pushing to iCloud...");
-                // sync.push()?;
-                println!("  {} Pushed to iCloud", style("✓").green());
+            // The uploaded copy is encrypted with a key derived for sync
+            let mut sync = sync;
+            sync.set_key(vault.sync_key()?);
+            sync.set_keep_backups(config.sync.backups);
+            sync.set_include_audit(config.sync.audit && !no_audit);
+            report_icloud_downloads(&mut sync, &config.sync, out);
+
+            let result = if push {
+                sync.push()?;
+                SyncResult::Pushed
             } else if pull {
-                // Force pull
-                println!("  Pulling from iCloud...");
-                // sync.pull()?;
-                println!("  {} Pulled from iCloud", style("✓").green());
+                sync.pull()?;
+                SyncResult::Pulled
+            } else {
+                sync.sync()?
+            };
+
+            if result == SyncResult::Pulled {
+                vault.reload()?;
+                if !vault.is_unlocked() {
+                    out.status(format!(
+                        "{} The pulled vault has a different master password",
+                        style("!").yellow()
+                    ));
+                }
+            }
+            vault.record_sync(sync.local_version()?)?;
+            out.status(format!("{} {}", style("✓").green(), result));
+            let pushed = sync.last_push();
+            if let Some(stats) = pushed {
+                out.status(format!(
+                    "  Uploaded {} ({} database, compressed)",
+                    format_size(stats.uploaded_size),
+                    format_size(stats.database_size)
+                ));
+            }
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "sync",
+                "result": sync_result_name(result),
+                "database_size": pushed.map(|stats| stats.database_size),
+                "uploaded_size": pushed.map(|stats| stats.uploaded_size),
+            }));
+        }
+
+        Commands::RestoreBackup { name } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            unlock_vault(&mut vault)?;
+            let replaced = vault.restore_backup(&name, config.sync.backups)?;
+            out.status(format!("{} Restored {}", style("✓").green(), name));
+            if let Some(replaced) = &replaced {
+                out.status(format!("  Previous database kept as {}", replaced.name));
+            }
+            out.result(serde_json::json!({
+                "ok": true,
+                "action": "restore_backup",
+                "restored": name,
+                "replaced": replaced.map(|backup| backup.name),
+            }));
+        }
+
+        Commands::Snapshots { action } => {
+            let mut vault = open_vault(&vault_path, &actor, &open_opts, &hooks)?;
+            match action.unwrap_or(SnapshotsAction::List) {
+                SnapshotsAction::List => {
+                    let snapshots = vault.snapshots()?;
+                    for snapshot in &snapshots {
+                        out.status(format!(
+                            "  {}  {}  {} bytes",
+                            snapshot.name,
+                            snapshot.created_at.format("%Y-%m-%d %H:%M:%S"),
+                            snapshot.size
+                        ));
+                    }
+                    if snapshots.is_empty() {
+                        out.status("No snapshots yet");
+                    }
+                    out.result(serde_json::json!(snapshots));
+                }
+                SnapshotsAction::Restore { name } => {
+                    unlock_vault(&mut vault)?;
+                    let replaced = vault.restore_snapshot(&name)?;
+                    out.status(format!("{} Restored {}", style("✓").green(), name));
+                    if let Some(replaced) = &replaced {
+                        out.status(format!("  Previous database kept as {}", replaced.name));
+                    }
+                    out.result(serde_json::json!({
+                        "ok": true,
+                        "action": "restore_snapshot",
+                        "restored": name,
+                        "replaced": replaced.map(|snapshot| snapshot.name),
+                    }));
+                }
+                SnapshotsAction::Prune { keep } => {
+                    let pruned = vault.prune_snapshots(keep)?;
+                    out.status(format!("{} Deleted {} snapshots", style("✓").green(), pruned.len()));
+                    out.result(serde_json::json!({
+                        "ok": true,
+                        "action": "prune_snapshots",
+                        "deleted": pruned.iter().map(|snapshot| &snapshot.name).collect::<Vec<_>>(),
+                    }));
+                }
+            }
+        }
+    }
+
+    Ok(exit_code::OK)
+}
+
+/// Name of a sync result in `--json` output
+fn sync_result_name(result: clawbox_core::sync::SyncResult) -> &'static str {
+    use clawbox_core::sync::SyncResult;
+
+    match result {
+        SyncResult::Pulled => "pulled",
+        SyncResult::Pushed => "pushed",
+        SyncResult::Merged => "merged",
+        SyncResult::UpToDate => "up_to_date",
+        SyncResult::Unavailable => "unavailable",
+    }
+}
+
+/// The backend configured in `[sync]` (other than iCloud) and where it syncs to
+#[cfg_attr(not(feature = "git-sync"), allow(unused_variables))]
+fn sync_backend(
+    config: &config::SyncConfig,
+    vault_path: &Path,
+) -> Result<(Box<dyn clawbox_core::sync::SyncBackend>, String)> {
+    use clawbox_core::sync::{DirBackend, SyncBackend};
+
+    match config.backend {
+        config::SyncBackendKind::Dir => {
+            let dir = expand_path(config.path.as_deref().context("[sync] backend = \"dir\" needs a path")?)?;
+            let backend = DirBackend::new(&dir);
+            if !backend.is_connected() {
+                anyhow::bail!("Sync folder {} does not exist", dir.display());
+            }
+            Ok((Box::new(backend), dir.display().to_string()))
+        }
+        #[cfg(feature = "webdav")]
+        config::SyncBackendKind::WebDav => {
+            use clawbox_core::webdav::{WebDavAuth, WebDavBackend};
+            use zeroize::Zeroizing;
+
+            let url = config.url.as_deref().context("[sync] backend = \"webdav\" needs a url")?;
+            let secret = |env_var: &str, configured: &Option<String>| {
+                std::env::var(env_var).ok().or_else(|| configured.clone()).map(Zeroizing::new)
+            };
+            let auth = match (&config.username, secret("CLAWBOX_WEBDAV_TOKEN", &config.token)) {
+                (Some(username), _) => WebDavAuth::Basic {
+                    username: username.clone(),
+                    password: secret("CLAWBOX_WEBDAV_PASSWORD", &config.password)
+                        .context("WebDAV username set without a password (CLAWBOX_WEBDAV_PASSWORD)")?,
+                },
+                (None, Some(token)) => WebDavAuth::Bearer(token),
+                (None, None) => WebDavAuth::None,
+            };
+            let backend = WebDavBackend::new(url, auth);
+            if !backend.is_connected() {
+                anyhow::bail!("WebDAV collection {} is not reachable", url);
+            }
+            Ok((Box::new(backend), url.to_string()))
+        }
+        #[cfg(not(feature = "webdav"))]
+        config::SyncBackendKind::WebDav => {
+            anyhow::bail!("This build has no WebDAV support; rebuild with --features webdav")
+        }
+        #[cfg(feature = "git-sync")]
+        config::SyncBackendKind::Git => {
+            use clawbox_core::git_sync::GitBackend;
+
+            let url = config.url.as_deref().context("[sync] backend = \"git\" needs a url")?;
+            let branch = config.branch.as_deref().unwrap_or("main");
+            // A private clone next to the vault; credentials come from git's own helpers
+            let backend = GitBackend::new(url, branch, vault_path.join("sync-git"));
+            if !backend.is_connected() {
+                anyhow::bail!("Git repository {} is not reachable", url);
+            }
+            Ok((Box::new(backend), format!("{} ({})", url, branch)))
+        }
+        #[cfg(not(feature = "git-sync"))]
+        config::SyncBackendKind::Git => {
+            anyhow::bail!("This build has no git sync support; rebuild with --features git-sync")
+        }
+        config::SyncBackendKind::ICloud => unreachable!("iCloud syncs the database file"),
+    }
+}
+
+/// When a vault last synced, for `sync --status`
+fn format_last_sync(state: &clawbox_core::sync::SyncState) -> String {
+    state.last_sync.map_or("never".to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+/// Wait `sync.download_timeout` for files iCloud Drive hasn't downloaded to
+/// this Mac yet, saying so every 10 seconds
+fn report_icloud_downloads(sync: &mut clawbox_core::icloud::ICloudSync, config: &config::SyncConfig, out: Output) {
+    sync.set_download_timeout(std::time::Duration::from_secs(config.download_timeout));
+    sync.set_download_progress(move |file, waited| {
+        if waited.as_secs() % 10 == 0 && !out.quiet && !out.json {
+            let name = file.file_name().unwrap_or(file.as_os_str()).to_string_lossy();
+            eprintln!("  Waiting for iCloud Drive to download {} ({}s)...", name, waited.as_secs());
+        }
+    });
+}
+
+/// Edits the next sync will push, if there are any
+fn print_pending_changes(pending: u64, out: Output) {
+    if pending > 0 {
+        let noun = if pending == 1 { "change" } else { "changes" };
+        out.status(format!("  {} {} local {} pending upload", style("↑").cyan(), pending, noun));
+    }
+}
+
+/// `sync --status --json` output
+fn sync_status_json(state: &clawbox_core::sync::SyncState, location: &str, remote: u64, pending: u64) -> serde_json::Value {
+    serde_json::json!({
+        "location": location,
+        "enabled": state.sync_enabled,
+        "local_version": state.local_version,
+        "synced_version": state.remote_version,
+        "remote_version": remote,
+        "last_sync": state.last_sync.map(|t| t.to_rfc3339()),
+        "conflicts": state.conflict_count,
+        "pending_changes": pending,
+    })
+}
+
+/// `sync` with a configured backend: merge with the vault stored there
+fn sync_merge(
+    vault: &mut ClawBox,
+    backend: &dyn clawbox_core::sync::SyncBackend,
+    location: &str,
+    status: bool,
+    out: Output,
+) -> Result<i32> {
+    if status {
+        let state = vault.sync_status()?;
+        let remote = backend.get_remote_version()?;
+        let pending = vault.pending_changes()?;
+        out.status("📊 Sync Status");
+        out.status(format!("  Location:       {}", location));
+        out.status(format!("  Synced version: {}", state.remote_version));
+        out.status(format!("  Remote version: {}", remote));
+        out.status(format!("  Last sync:      {}", format_last_sync(&state)));
+        print_pending_changes(pending, out);
+        out.result(sync_status_json(&state, location, remote, pending));
+        return Ok(exit_code::OK);
+    }
+
+    unlock_vault(vault)?;
+    let report = vault.sync_with(&mut vault.sync_manager()?, backend)?;
+    print_sync_report(&report, location, out);
+    Ok(exit_code::OK)
+}
+
+/// List the devices from the sync device registry
+fn print_sync_devices(devices: &[clawbox_core::devices::Device], out: Output) {
+    let when = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map_or("never".to_string(), |time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+    };
+    for device in devices {
+        out.status(format!("{} {}", style("◆").cyan(), device.device_id));
+        if device.hostname != device.device_id {
+            out.status(format!("  Hostname:  {}", device.hostname));
+        }
+        out.status(format!("  Last seen: {}", when(Some(device.last_seen))));
+        out.status(format!("  Last push: {}", when(device.last_push)));
+        out.status(format!("  Last pull: {}", when(device.last_pull)));
+        out.status(format!("  ClawBox:   {}", device.version));
+    }
+    if devices.is_empty() {
+        out.status("No device has synced this vault yet");
+    }
+    out.result(serde_json::json!(devices));
+}
+
+/// Print what differs from the remote; 1 if anything does, so cron jobs
+/// can alert
+fn print_sync_diff(diffs: &[clawbox_core::sync::SyncDiff], location: &str, out: Output) -> i32 {
+    use clawbox_core::sync::{DiffChange, SyncSide};
+
+    for diff in diffs {
+        let (mark, what) = match diff.change {
+            DiffChange::Added => (style("+").green(), "only on the remote"),
+            DiffChange::Removed => (style("-").red(), "only here"),
+            DiffChange::Changed if diff.value_changed => (style("~").yellow(), "value differs"),
+            DiffChange::Changed => (style("~").yellow(), "metadata differs"),
+        };
+        let newer = match diff.newer {
+            Some(SyncSide::Local) => "local is newer",
+            Some(SyncSide::Remote) => "remote is newer",
+            None => "changed on both at the same time",
+        };
+        out.status(format!("  {} {}  {} ({})", mark, diff.path, what, newer));
+    }
+    if diffs.is_empty() {
+        out.status(format!("{} No differences with {}", style("✓").green(), location));
+    } else {
+        out.status(format!("{} path(s) differ from {}", diffs.len(), location));
+    }
+    out.result(serde_json::json!({
+        "ok": true,
+        "action": "sync_diff",
+        "location": location,
+        "differences": diffs,
+    }));
+    if diffs.is_empty() { exit_code::OK } else { exit_code::FAILURE }
+}
+
+/// Enable sync and apply the conflict resolution set in `[sync]`
+fn prepare_sync(vault: &mut ClawBox, config: &config::SyncConfig) -> Result<()> {
+    let mut manager = vault.sync_manager()?;
+    manager.enable();
+    if let Some(resolution) = config.resolution {
+        manager.set_resolution(resolution.into());
+    }
+    Ok(vault.save_sync_state(&manager)?)
+}
+
+/// Print the outcome of a merging sync
+fn print_sync_report(report: &clawbox_core::sync::SyncReport, location: &str, out: Output) {
+    use clawbox_core::sync::SyncResult;
+
+    for conflict in &report.conflicts {
+        out.status(format!(
+            "{} {} changed on both devices{}",
+            style("!").yellow(),
+            conflict.path,
+            conflict.conflict_copy.as_ref().map_or(String::new(), |copy| format!("; remote value kept at {}", copy))
+        ));
+    }
+    let summary = match report.result {
+        SyncResult::Pulled => format!("Pulled from {}", location),
+        SyncResult::Pushed => format!("Pushed to {}", location),
+        SyncResult::Merged => format!("Merged with {}", location),
+        SyncResult::UpToDate | SyncResult::Unavailable => report.result.to_string(),
+    };
+    out.status(format!("{} {}", style("✓").green(), summary));
+    out.result(serde_json::json!({
+        "ok": true,
+        "action": "sync",
+        "result": sync_result_name(report.result),
+        "conflicts": report
+            .conflicts
+            .iter()
+            .map(|conflict| serde_json::json!({ "path": conflict.path, "copy": conflict.conflict_copy }))
+            .collect::<Vec<_>>(),
+    }));
+}
+
+/// `sync --watch`: sync after every change until SIGTERM or Ctrl-C
+///
+/// Changes to the vault database and to a `dir` backend's folder are picked
+/// up as they happen; anything else is checked every `poll`.
+fn sync_watch(
+    vault: &mut ClawBox,
+    backend: &dyn clawbox_core::sync::SyncBackend,
+    location: &str,
+    remote_dir: Option<PathBuf>,
+    poll: std::time::Duration,
+    out: Output,
+) -> Result<i32> {
+    use clawbox_core::watch::SyncWatcher;
+
+    unlock_vault(vault)?;
+    let _pidfile = watch::PidFile::create(&vault.path().join("sync-watch.pid"))?;
+    let (events, received) = std::sync::mpsc::channel();
+    // Before any other thread starts, so they leave the signals to it
+    watch::stop_on_signals(events.clone())?;
+    #[cfg(feature = "watch")]
+    let _watcher = clawbox_core::watch::watch_files(vault.path(), remote_dir.as_deref(), events)?;
+    // Poll for whatever file events don't cover
+    let poll = (cfg!(not(feature = "watch")) || remote_dir.is_none()).then_some(poll);
+
+    out.status(format!("👀 Watching for changes to sync with {} (Ctrl-C to stop)", location));
+    SyncWatcher::new(vault, backend)?.with_poll_interval(poll).run(&received, |result| match result {
+        Ok(report) => print_sync_report(report, location, out),
+        Err(err) => {
+            if !out.json {
+                eprintln!("{} Sync failed: {}", style("✗").red(), err);
+            }
+            out.result(serde_json::json!({ "ok": false, "action": "sync", "error": err.to_string() }));
+        }
+    })?;
+    out.status("Stopped watching");
+    Ok(exit_code::OK)
+}
+
+/// `sync --resolve`: ask whether to keep the local or remote value of each
+/// conflict copy (`--json` only lists them)
+fn resolve_conflicts(vault: &mut ClawBox, out: Output) -> Result<i32> {
+    use clawbox_core::sync::conflict_origin;
+
+    let copies = vault.conflict_copies()?;
+    if out.json {
+        let items: Vec<_> = copies
+            .iter()
+            .map(|info| serde_json::json!({
+                "path": conflict_origin(&info.path),
+                "copy": info.path,
+                "updated_at": info.updated_at.to_rfc3339(),
+            }))
+            .collect();
+        out.result(serde_json::json!(items));
+        return Ok(exit_code::OK);
+    }
+    if copies.is_empty() {
+        out.status(format!("{} No sync conflicts", style("✓").green()));
+        return Ok(exit_code::OK);
+    }
+
+    for info in copies {
+        let path = conflict_origin(&info.path).unwrap_or_default().to_string();
+        eprintln!("{}: remote value kept at {} ({})", path, info.path, info.updated_at.format("%Y-%m-%d %H:%M:%S"));
+        let answer = read_line("Keep [l]ocal, keep [r]emote or [s]kip? ")?;
+        let keep_remote = match answer.trim().to_ascii_lowercase().as_str() {
+            "l" | "local" => false,
+            "r" | "remote" => true,
+            _ => {
+                out.status(format!("  Skipped {}", path));
+                continue;
+            }
+        };
+        vault.resolve_conflict(&info.path, keep_remote)?;
+        out.status(format!(
+            "{} Kept the {} value of {}",
+            style("✓").green(),
+            if keep_remote { "remote" } else { "local" },
+            path
+        ));
+    }
+    Ok(exit_code::OK)
+}
+
+/// Format a byte count for humans
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn print_status(vault_path: &std::path::Path, out: Output, opts: &OpenOptions) -> Result<()> {
+    // Don't create a vault just to report that there isn't one
+    let vault = match ClawBox::builder(vault_path).options(opts.clone()).create(false).open() {
+        Ok(vault) => Some(vault),
+        Err(clawbox_core::Error::VaultNotFound { .. }) => None,
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(vault) = &vault {
+        warn_permissions(vault.permission_report());
+    }
+    let vault = match vault {
+        Some(vault) if vault.is_initialized()? => vault,
+        _ => {
+            if out.json {
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "vault": vault_path,
+                        "initialized": false,
+                    })
+                );
             } else {
-                // Auto sync
-                println!("  {} iCloud sync ready", style("✓").green());
-                println!("  Use --push to upload or --pull to download");
+                println!("{} Vault: {}", style("◆").cyan(), vault_path.display());
+                println!("  Initialized:    no (run 'clawbox init')");
             }
+            return Ok(());
+        }
+    };
+
+    let stats = vault.stats()?;
+    let lockout = vault.unlock_lockout()?;
+    let retry_after = lockout.retry_after(chrono::Utc::now());
+    let session = agent::session_expires_in(vault_path);
+    let unlocked = session.is_some();
+
+    #[cfg(target_os = "macos")]
+    let sync = {
+        let sync = clawbox_core::icloud::ICloudSync::new(vault_path.to_path_buf(), &vault.vault_id()?);
+        if sync.is_available() {
+            Some((sync.local_version().unwrap_or(0), sync.remote_version().unwrap_or(0)))
+        } else {
+            None
+        }
+    };
+    #[cfg(not(target_os = "macos"))]
+    let sync: Option<(u64, u64)> = None;
+
+    if out.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "vault": vault_path,
+                "initialized": true,
+                "unlocked": unlocked,
+                "locks_in_secs": session.map(|d| d.as_secs()),
+                "failed_unlocks": lockout.failures,
+                "unlock_retry_after_secs": retry_after.map(|d| d.as_secs().max(1)),
+                "secrets": {
+                    "total": stats.secret_count(),
+                    "public": stats.public_count,
+                    "normal": stats.normal_count,
+                    "sensitive": stats.sensitive_count,
+                    "critical": stats.critical_count,
+                },
+                "db_size": stats.db_size,
+                "last_write": stats.last_write.map(|t| t.to_rfc3339()),
+                "audit_entries": stats.audit_entries,
+                "schema_version": stats.schema_version,
+                "sqlite": stats.pragmas,
+                "insecure_permissions": vault.permission_report().problems,
+                "sync": sync.map(|(local, remote)| serde_json::json!({
+                    "local_version": local,
+                    "remote_version": remote,
+                })),
+            }))?
+        );
+        return Ok(());
+    }
+
+    println!("{} Vault: {}", style("◆").cyan(), vault_path.display());
+    println!("  Initialized:    yes");
+    match session {
+        Some(remaining) => println!(
+            "  State:          {} (locks in {} min)",
+            style("unlocked").yellow(),
+            remaining.as_secs().div_ceil(60)
+        ),
+        None => println!("  State:          {}", style("locked").green()),
+    }
+    match retry_after {
+        Some(wait) => println!(
+            "  Unlock:         {} after {} wrong passwords (try again in {} s)",
+            style("refused").red(),
+            lockout.failures,
+            wait.as_secs().max(1)
+        ),
+        None if lockout.failures > 0 => {
+            println!("  Unlock:         {} wrong passwords since the last unlock", lockout.failures)
         }
+        None => {}
+    }
+    println!(
+        "  Secrets:        {} (public {}, normal {}, sensitive {}, critical {})",
+        stats.secret_count(),
+        stats.public_count,
+        stats.normal_count,
+        stats.sensitive_count,
+        stats.critical_count
+    );
+    println!("  Database size:  {}", format_size(stats.db_size));
+    println!(
+        "  Last write:     {}",
+        stats
+            .last_write
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "never".to_string())
+    );
+    println!("  Audit entries:  {}", stats.audit_entries);
+    println!("  Schema version: {}", stats.schema_version);
+    if let Some((local, remote)) = sync {
+        println!("  Sync:           local v{}, remote v{}", local, remote);
     }
 
     Ok(())
 }
 
+/// Write a secret into exported data (`SecretValue` doesn't serialize by itself)
+fn serialize_exposed<S: serde::Serializer>(value: &SecretValue, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(value.expose())
+}
+
+/// Open the vault, acting as `actor`, with the hooks from the config file
+fn open_vault(path: &Path, actor: &ActorInfo, opts: &OpenOptions, hooks: &[Hook]) -> Result<ClawBox> {
+    let vault = ClawBox::builder(path).options(opts.clone()).actor(actor.clone()).hooks(hooks.to_vec()).open()?;
+    warn_permissions(vault.permission_report());
+    Ok(vault)
+}
+
+/// Warn on stderr about vault files that other users can access
+fn warn_permissions(report: &clawbox_core::permissions::PermissionReport) {
+    for problem in &report.problems {
+        eprintln!(
+            "{} {} has mode {:03o}, so other users can access it; run 'clawbox verify --fix-permissions'",
+            style("Warning:").yellow(),
+            problem.path.display(),
+            problem.mode
+        );
+    }
+}
+
+/// Read a secret, asking for the master password again if its access level
+/// requires it; `reauth` keeps the password for the rest of the command
+fn read_secret(
+    vault: &ClawBox,
+    path: &str,
+    reauth: &mut Option<zeroize::Zeroizing<String>>,
+) -> Result<Option<SecretValue>> {
+    match vault.get_secret(path) {
+        Err(clawbox_core::Error::ReauthRequired { .. }) => {
+            let password = match reauth {
+                Some(password) => password,
+                None => reauth.insert(zeroize::Zeroizing::new(password::read(
+                    password::PASSWORD_VAR,
+                    &format!("Re-enter master password to read {}: ", path),
+                )?)),
+            };
+            Ok(vault.get_with_reauth(path, password)?)
+        }
+        result => Ok(result?),
+    }
+}
+
 fn unlock_vault(vault: &mut ClawBox) -> Result<()> {
+    unlock_vault_with(vault, password::PASSWORD_VAR, "Enter master password: ")
+}
+
+/// Unlock via the session agent, falling back to a password from `env_var` or a prompt
+fn unlock_vault_with(vault: &mut ClawBox, env_var: &str, prompt: &str) -> Result<()> {
     if !vault.is_initialized()? {
-        anyhow::bail!("Vault not initialized. Run 'clawbox init' first.");
+        return Err(anyhow::Error::new(clawbox_core::Error::VaultNotFound {
+            path: vault.path().to_string_lossy().to_string(),
+        })
+        .context("Vault not initialized. Run 'clawbox init' first."));
     }
 
     if !vault.is_unlocked() {
-        let password = get_password("Enter master password: ")?;
+        // An agent given a token is held to its scope, even while the
+        // vault's owner has a session open
+        if let Some(token) = std::env::var("CLAWBOX_TOKEN").ok().filter(|token| !token.is_empty()) {
+            vault.unlock_with_token(&token).context("Failed to unlock vault with CLAWBOX_TOKEN")?;
+            return Ok(());
+        }
+
+        // Reuse the key held by the session agent, if any
+        if let Some(key) = agent::fetch(vault.path()) {
+            if vault.unlock_with_key(&key).is_ok() {
+                return Ok(());
+            }
+        }
+
+        // Or the one in the session cache, if enabled
+        if let Some(key) = keycache::fetch(vault.path()) {
+            if vault.unlock_with_key(&key).is_ok() {
+                return Ok(());
+            }
+            // Stale, e.g. the password changed
+            let _ = keycache::clear(Some(vault.path()));
+        }
+
+        // Don't ask for a password that would not be tried
+        vault.check_unlock_allowed()?;
+        let password = password::read(env_var, prompt)?;
         vault.unlock(&password).context("Failed to unlock vault")?;
+        // Best effort: without a cache the next command just asks again
+        if let Ok(key) = vault.export_key() {
+            let _ = keycache::store(vault.path(), &key);
+        }
     }
 
     Ok(())
@@ -0,0 +1,51 @@
+//! Masked previews of secret values for terminal output
+
+/// Stands in for the hidden part of a value
+const HIDDEN: &str = "••••";
+
+/// A preview that identifies a value without revealing it, like
+/// `sk_live_••••abcd`
+///
+/// Keeps a prefix ending in `_` or `-` from the first half of the value (as
+/// in API keys) and the last four characters, only for values long enough
+/// that they give little away.
+pub fn preview(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < 12 {
+        return HIDDEN.to_string();
+    }
+    let prefix_len = chars[..chars.len() / 2]
+        .iter()
+        .rposition(|c| matches!(c, '_' | '-'))
+        .map_or(0, |i| i + 1);
+    let prefix: String = chars[..prefix_len].iter().collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}{}{}", prefix, HIDDEN, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MaskFrom;
+    use clawbox_core::AccessLevel;
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("sk_live_51Habcdefghijklmnabcd"), "sk_live_••••abcd");
+        assert_eq!(preview("ghp-0123456789abcdef"), "ghp-••••cdef");
+        assert_eq!(preview("correct horse battery"), "••••tery");
+        // Short values show nothing at all
+        assert_eq!(preview("hunter2"), "••••");
+        assert_eq!(preview(""), "••••");
+    }
+
+    #[test]
+    fn test_mask_from() {
+        assert!(!MaskFrom::Sensitive.masks(AccessLevel::Normal));
+        assert!(MaskFrom::Sensitive.masks(AccessLevel::Sensitive));
+        assert!(MaskFrom::Sensitive.masks(AccessLevel::Critical));
+        assert!(!MaskFrom::Critical.masks(AccessLevel::Sensitive));
+        assert!(MaskFrom::Public.masks(AccessLevel::Public));
+        assert!(!MaskFrom::Never.masks(AccessLevel::Critical));
+    }
+}
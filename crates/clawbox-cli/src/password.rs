@@ -0,0 +1,187 @@
+//! Where passwords come from
+//!
+//! The master password is read from `--password-file` (or the file named by
+//! `CLAWBOX_PASSWORD_FILE`), then `CLAWBOX_PASSWORD`, then the terminal or
+//! stdin. Password variables are taken out of the environment at startup, so
+//! child processes such as clipboard helpers never inherit them.
+
+use anyhow::{Context, Result};
+use console::style;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Once, OnceLock};
+use zeroize::Zeroizing;
+
+/// The master password
+pub const PASSWORD_VAR: &str = "CLAWBOX_PASSWORD";
+/// A file holding the master password
+pub const PASSWORD_FILE_VAR: &str = "CLAWBOX_PASSWORD_FILE";
+/// The new master password for `passwd`
+pub const NEW_PASSWORD_VAR: &str = "CLAWBOX_NEW_PASSWORD";
+/// The duress password for `init --duress`
+pub const DURESS_PASSWORD_VAR: &str = "CLAWBOX_DURESS_PASSWORD";
+/// The destination vault's master password for `copy-to`
+pub const DEST_PASSWORD_VAR: &str = "CLAWBOX_DEST_PASSWORD";
+
+/// Variables taken out of the environment at startup
+const SCRUBBED_VARS: [&str; 4] = [PASSWORD_VAR, NEW_PASSWORD_VAR, DURESS_PASSWORD_VAR, DEST_PASSWORD_VAR];
+
+static SOURCES: OnceLock<Sources> = OnceLock::new();
+static ENV_WARNING: Once = Once::new();
+
+/// Password inputs gathered at startup
+#[derive(Default)]
+pub struct Sources {
+    /// Password file for the master password
+    file: Option<PathBuf>,
+    /// Password variables that were set
+    vars: HashMap<&'static str, Zeroizing<String>>,
+    /// Keep stderr free of warnings (it carries JSON errors)
+    json: bool,
+}
+
+/// Where one password is read from
+#[derive(Debug, PartialEq, Eq)]
+pub enum Source<'a> {
+    File(&'a Path),
+    Var(&'a str),
+    Prompt,
+}
+
+impl Sources {
+    /// Where to read the password otherwise named by `var`; only the master
+    /// password comes from the password file, which wins over the variable
+    pub fn resolve<'a>(&'a self, var: &'a str) -> Source<'a> {
+        match &self.file {
+            Some(file) if var == PASSWORD_VAR => Source::File(file),
+            _ if self.vars.contains_key(var) => Source::Var(var),
+            _ => Source::Prompt,
+        }
+    }
+}
+
+/// `--password-file`, or else a non-empty `CLAWBOX_PASSWORD_FILE`
+fn password_file(flag: Option<PathBuf>, var: Option<OsString>) -> Option<PathBuf> {
+    flag.or_else(|| var.filter(|file| !file.is_empty()).map(PathBuf::from))
+}
+
+/// Record `--password-file` and take the password variables out of the
+/// environment; call before any threads start
+pub fn init(file: Option<PathBuf>, json: bool) {
+    let file = password_file(file, std::env::var_os(PASSWORD_FILE_VAR));
+    let mut vars = HashMap::new();
+    for var in SCRUBBED_VARS {
+        if let Ok(value) = std::env::var(var) {
+            vars.insert(var, Zeroizing::new(value));
+        }
+        std::env::remove_var(var);
+    }
+    let _ = SOURCES.set(Sources { file, vars, json });
+}
+
+/// Read the password otherwise named by `var`, prompting with `prompt` if
+/// neither a password file nor the variable supplies it
+pub fn read(var: &str, prompt: &str) -> Result<String> {
+    let sources = SOURCES.get_or_init(Sources::default);
+    match sources.resolve(var) {
+        Source::File(path) => read_file(path),
+        Source::Var(var) => {
+            if !sources.json {
+                ENV_WARNING.call_once(|| {
+                    eprintln!(
+                        "{} {} exposes the password to every process started with it, and often to CI logs; \
+                         use --password-file instead",
+                        style("Warning:").yellow(),
+                        var
+                    );
+                });
+            }
+            Ok(sources.vars[var].to_string())
+        }
+        Source::Prompt if atty::is(atty::Stream::Stdin) => Ok(rpassword::prompt_password(prompt)?),
+        Source::Prompt => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            Ok(line.trim().to_string())
+        }
+    }
+}
+
+/// Read the first line of a password file, which must not be readable by
+/// other users; pipes such as `/dev/fd/3` are read as they are
+pub fn read_file(path: &Path) -> Result<String> {
+    let mut file =
+        File::open(path).with_context(|| format!("Failed to open password file {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let meta = file.metadata()?;
+        if meta.is_file() && meta.permissions().mode() & 0o077 != 0 {
+            anyhow::bail!(
+                "Password file {} is accessible to other users; run 'chmod 600' on it",
+                path.display()
+            );
+        }
+    }
+    let mut contents = Zeroizing::new(String::new());
+    file.read_to_string(&mut contents)
+        .with_context(|| format!("Failed to read password file {}", path.display()))?;
+    match contents.lines().next() {
+        Some(password) if !password.is_empty() => Ok(password.to_string()),
+        _ => anyhow::bail!("Password file {} is empty", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn sources(file: Option<&str>, vars: &[&'static str]) -> Sources {
+        Sources {
+            file: file.map(PathBuf::from),
+            vars: vars.iter().map(|var| (*var, Zeroizing::new("pw".to_string()))).collect(),
+            json: false,
+        }
+    }
+
+    #[test]
+    fn test_resolve_precedence() {
+        // The file wins over the variable, but only for the master password
+        let both = sources(Some("/run/pw"), &[PASSWORD_VAR, NEW_PASSWORD_VAR]);
+        assert_eq!(both.resolve(PASSWORD_VAR), Source::File(Path::new("/run/pw")));
+        assert_eq!(both.resolve(NEW_PASSWORD_VAR), Source::Var(NEW_PASSWORD_VAR));
+        assert_eq!(both.resolve(DURESS_PASSWORD_VAR), Source::Prompt);
+
+        let var_only = sources(None, &[PASSWORD_VAR]);
+        assert_eq!(var_only.resolve(PASSWORD_VAR), Source::Var(PASSWORD_VAR));
+        assert_eq!(sources(None, &[]).resolve(PASSWORD_VAR), Source::Prompt);
+
+        // The flag wins over CLAWBOX_PASSWORD_FILE, which counts only when set
+        let flag = Some(PathBuf::from("/flag"));
+        assert_eq!(password_file(flag.clone(), Some("/var".into())), flag);
+        assert_eq!(password_file(None, Some("/var".into())), Some(PathBuf::from("/var")));
+        assert_eq!(password_file(None, Some("".into())), None);
+        assert_eq!(password_file(None, None), None);
+    }
+
+    #[test]
+    fn test_read_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("password");
+        std::fs::write(&path, "correct horse\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(read_file(&path).unwrap(), "correct horse");
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(read_file(&path).unwrap_err().to_string().contains("chmod 600"));
+
+        std::fs::write(&path, "\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(read_file(&path).unwrap_err().to_string().contains("empty"));
+        assert!(read_file(&dir.path().join("missing")).is_err());
+    }
+}
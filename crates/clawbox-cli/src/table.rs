@@ -0,0 +1,99 @@
+//! Aligned text tables that fit the terminal width
+
+use console::{measure_text_width, pad_str, Alignment};
+
+/// A table column
+pub struct Column {
+    pub header: &'static str,
+    pub align: Alignment,
+    /// Narrowest the column may be truncated to; `None` never truncates
+    pub min_width: Option<usize>,
+}
+
+impl Column {
+    pub fn new(header: &'static str) -> Self {
+        Self { header, align: Alignment::Left, min_width: None }
+    }
+
+    pub fn right(mut self) -> Self {
+        self.align = Alignment::Right;
+        self
+    }
+
+    pub fn shrink_to(mut self, min_width: usize) -> Self {
+        self.min_width = Some(min_width);
+        self
+    }
+}
+
+const GAP: &str = "  ";
+
+/// Render rows under a header line
+///
+/// With `max_width`, truncatable columns are narrowed (the rightmost first)
+/// until the table fits; truncated cells end in `…`. Widths are measured in
+/// terminal columns, so multi-byte and wide characters are handled.
+pub fn render(columns: &[Column], rows: &[Vec<String>], max_width: Option<usize>) -> String {
+    let mut widths: Vec<usize> = columns.iter().map(|c| measure_text_width(c.header)).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(measure_text_width(cell));
+        }
+    }
+
+    if let Some(max_width) = max_width {
+        let total = |widths: &[usize]| widths.iter().sum::<usize>() + GAP.len() * (widths.len().saturating_sub(1));
+        for (i, column) in columns.iter().enumerate().rev() {
+            let excess = total(&widths).saturating_sub(max_width);
+            if excess == 0 {
+                break;
+            }
+            if let Some(min) = column.min_width {
+                widths[i] = widths[i].saturating_sub(excess).max(min.min(widths[i]));
+            }
+        }
+    }
+
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        let cells: Vec<_> = cells
+            .zip(columns.iter().zip(&widths))
+            .map(|(cell, (column, &width))| {
+                // pad_str would also truncate a cell that fits exactly
+                let truncate = (measure_text_width(cell) > width).then_some("…");
+                pad_str(cell, width, column.align, truncate).into_owned()
+            })
+            .collect();
+        let mut line = cells.join(GAP).trim_end().to_string();
+        line.push('\n');
+        line
+    };
+
+    let mut out = line(&mut columns.iter().map(|c| c.header));
+    for row in rows {
+        out.push_str(&line(&mut row.iter().map(String::as_str)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_truncates_to_width() {
+        let columns = [Column::new("PATH").shrink_to(6), Column::new("SIZE").right()];
+        let rows = vec![
+            vec!["db/password".to_string(), "12".to_string()],
+            vec!["ünïcödé/kéy".to_string(), "3".to_string()],
+        ];
+
+        let full = render(&columns, &rows, None);
+        assert_eq!(full, "PATH         SIZE\ndb/password    12\nünïcödé/kéy     3\n");
+
+        let narrow = render(&columns, &rows, Some(14));
+        assert_eq!(narrow, "PATH      SIZE\ndb/pass…    12\nünïcödé…     3\n");
+        for line in narrow.lines() {
+            assert!(measure_text_width(line) <= 14);
+        }
+    }
+}
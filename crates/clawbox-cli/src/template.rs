@@ -0,0 +1,147 @@
+//! Secret templates
+//!
+//! `clawbox template render` fills placeholders of the form
+//! `{{ clawbox "db/password" }}` with secret values. A literal `{{` is written
+//! as `\{{`; a backslash anywhere else is copied through unchanged.
+
+use anyhow::Result;
+
+/// A piece of a parsed template
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Text(String),
+    Secret(String),
+}
+
+/// A parsed template
+#[derive(Debug)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// 1-based line and column of a byte offset
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+/// Parse the inside of a placeholder: `clawbox "path"`
+fn parse_reference(inner: &str) -> Option<String> {
+    let rest = inner.trim().strip_prefix("clawbox")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let path = rest.trim().strip_prefix('"')?.strip_suffix('"')?;
+    if path.is_empty() || path.contains('"') {
+        return None;
+    }
+    Some(path.to_string())
+}
+
+impl Template {
+    /// Parse template source
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = vec![];
+        let mut text = String::new();
+        let mut pos = 0;
+
+        while let Some(found) = source[pos..].find("{{") {
+            let start = pos + found;
+
+            if source[..start].ends_with('\\') {
+                text.push_str(&source[pos..start - 1]);
+                text.push_str("{{");
+                pos = start + 2;
+                continue;
+            }
+
+            text.push_str(&source[pos..start]);
+            let (line, column) = position(source, start);
+            let end = source[start + 2..].find("}}").ok_or_else(|| {
+                anyhow::anyhow!("Unterminated placeholder at line {}, column {}", line, column)
+            })?;
+            let inner = &source[start + 2..start + 2 + end];
+            let path = parse_reference(inner).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid placeholder '{{{{{}}}}}' at line {}, column {} (expected {{{{ clawbox \"path\" }}}})",
+                    inner,
+                    line,
+                    column
+                )
+            })?;
+
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            segments.push(Segment::Secret(path));
+            pos = start + 2 + end + 2;
+        }
+
+        text.push_str(&source[pos..]);
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Distinct secret paths referenced, in order of first use
+    pub fn references(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = vec![];
+        for segment in &self.segments {
+            if let Segment::Secret(path) = segment {
+                if !paths.contains(&path.as_str()) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths
+    }
+
+    /// Render with a value lookup; every reference must resolve
+    pub fn render<'a>(&self, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<String> {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Secret(path) => out.push_str(
+                    lookup(path).ok_or_else(|| anyhow::anyhow!("Secret not found: {}", path))?,
+                ),
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_render() {
+        let template =
+            Template::parse("user={{ clawbox \"db/user\" }}\npass={{clawbox \"db/pw\"}} \\{{ x }}")
+                .unwrap();
+        assert_eq!(template.references(), vec!["db/user", "db/pw"]);
+
+        let rendered = template
+            .render(|path| match path {
+                "db/user" => Some("admin"),
+                "db/pw" => Some("s3cret"),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(rendered, "user=admin\npass=s3cret {{ x }}");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Template::parse("a\nb {{ clawbox \"x\"").unwrap_err();
+        assert!(err.to_string().contains("line 2, column 3"));
+
+        assert!(Template::parse("{{ env \"HOME\" }}").is_err());
+        assert!(Template::parse("{{ clawbox db/password }}").is_err());
+        assert!(Template::parse("{{ clawbox \"\" }}").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+//! Process plumbing for `clawbox sync --watch`
+//!
+//! The watcher writes a pidfile next to the vault so a second watcher (or a
+//! service manager) can tell one is running, and turns SIGTERM, SIGINT and
+//! SIGHUP into a `WatchEvent::Stop` so the loop finishes its current sync
+//! and cleans up instead of dying mid-write.
+
+use anyhow::{Context, Result};
+use clawbox_core::watch::WatchEvent;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+
+/// Pidfile of a running watcher, removed on drop
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Claim `path` for this process, unless a live watcher holds it
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(pid) = std::fs::read_to_string(path).ok().and_then(|pid| pid.trim().parse::<libc::pid_t>().ok()) {
+            // SAFETY: signal 0 only checks that the process exists
+            if pid > 0 && unsafe { libc::kill(pid, 0) } == 0 {
+                anyhow::bail!("Already watching this vault (pid {}, see {})", pid, path.display());
+            }
+        }
+        std::fs::write(path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Could not write {}", path.display()))?;
+        Ok(Self { path: path.to_path_buf() })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Send `WatchEvent::Stop` when the process is asked to terminate
+///
+/// Blocks the signals in the calling thread, so call it before starting any
+/// other thread: they inherit the mask and leave the signals to the one
+/// thread waiting for them.
+pub fn stop_on_signals(events: Sender<WatchEvent>) -> Result<()> {
+    // SAFETY: the set is initialised by sigemptyset before use, and
+    // pthread_sigmask only changes this thread's mask
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        for signal in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+            libc::sigaddset(&mut signals, signal);
+        }
+        if libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut()) != 0 {
+            anyhow::bail!("Could not block termination signals");
+        }
+        signals
+    };
+    std::thread::Builder::new()
+        .name("clawbox-signals".into())
+        .spawn(move || {
+            let mut signal = 0;
+            // SAFETY: `signals` is a valid set blocked in every thread
+            while unsafe { libc::sigwait(&signals, &mut signal) } != 0 {}
+            let _ = events.send(WatchEvent::Stop);
+        })
+        .context("Could not start signal handler")?;
+    Ok(())
+}
@@ -0,0 +1,1722 @@
+//! End-to-end tests running the `clawbox` binary
+
+use assert_cmd::Command;
+use tempfile::TempDir;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+fn clawbox(vault: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("clawbox").unwrap();
+    cmd.arg("--vault").arg(vault.path());
+    cmd.env("CLAWBOX_PASSWORD", PASSWORD);
+    cmd
+}
+
+fn init_vault() -> TempDir {
+    let vault = TempDir::new().unwrap();
+    clawbox(&vault).arg("init").assert().success();
+    vault
+}
+
+#[test]
+fn test_vault_from_first_release_opens() {
+    let vault = TempDir::new().unwrap();
+    // The tables as the first release created them
+    rusqlite::Connection::open(vault.path().join("vault.db"))
+        .unwrap()
+        .execute_batch(
+            r#"
+            CREATE TABLE vault_meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+            CREATE TABLE secrets (
+                id TEXT PRIMARY KEY, path TEXT UNIQUE NOT NULL, encrypted_value BLOB NOT NULL,
+                access_level INTEGER NOT NULL DEFAULT 1, tags TEXT, note TEXT, ttl_expires_at INTEGER,
+                created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL, created_by TEXT NOT NULL
+            );
+            CREATE INDEX idx_secrets_path ON secrets(path);
+            CREATE INDEX idx_secrets_ttl ON secrets(ttl_expires_at);
+            CREATE TABLE audit_log (
+                id TEXT PRIMARY KEY, timestamp INTEGER NOT NULL, actor TEXT NOT NULL, action TEXT NOT NULL,
+                key_path TEXT NOT NULL, success INTEGER NOT NULL, error_message TEXT, source TEXT NOT NULL,
+                hash TEXT, prev_hash TEXT
+            );
+            "#,
+        )
+        .unwrap();
+
+    clawbox(&vault).arg("status").assert().success();
+    clawbox(&vault).arg("init").assert().success();
+    clawbox(&vault).args(["set", "stripe/key", "sk_live", "--access", "sensitive"]).assert().success();
+    clawbox(&vault).args(["set", "db/url", "postgres://"]).assert().success();
+    clawbox(&vault).args(["list", "--access", "sensitive"]).assert().success().stdout("stripe/key\n");
+}
+
+#[test]
+fn test_get_quiet_prints_only_value() {
+    let vault = init_vault();
+    clawbox(&vault)
+        .args(["--quiet", "set", "github/token", "ghp_123"])
+        .assert()
+        .success()
+        .stdout("");
+
+    clawbox(&vault)
+        .args(["get", "github/token", "--quiet"])
+        .assert()
+        .success()
+        .stdout("ghp_123\n");
+}
+
+#[test]
+fn test_exit_code_not_found() {
+    let vault = init_vault();
+    clawbox(&vault)
+        .args(["--quiet", "get", "missing/key"])
+        .assert()
+        .code(3)
+        .stdout("");
+}
+
+#[test]
+fn test_exit_code_invalid_password() {
+    let vault = init_vault();
+    clawbox(&vault)
+        .env("CLAWBOX_PASSWORD", "wrong-password")
+        .args(["get", "github/token"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_exit_code_not_initialized() {
+    let vault = TempDir::new().unwrap();
+    clawbox(&vault).args(["list"]).assert().code(2);
+}
+
+#[test]
+fn test_exit_code_io() {
+    let vault = init_vault();
+    let output = vault.path().join("no-such-dir").join("export.json");
+    clawbox(&vault)
+        .args(["export", "--plaintext-ok"])
+        .arg(&output)
+        .assert()
+        .code(5);
+}
+
+#[test]
+fn test_exit_code_usage() {
+    let vault = TempDir::new().unwrap();
+    clawbox(&vault).arg("frobnicate").assert().code(64);
+}
+
+#[test]
+fn test_actor_access_levels() {
+    let vault = init_vault();
+    for level in ["public", "normal", "sensitive", "critical"] {
+        clawbox(&vault).args(["set", level, "v", "--access", level]).assert().success();
+    }
+
+    for (level, code) in [("public", 0), ("normal", 0), ("sensitive", 6), ("critical", 6)] {
+        clawbox(&vault).args(["--actor", "ai:bot", "get", level]).assert().code(code);
+        clawbox(&vault).args(["--actor", "app:menubar", "get", level]).assert().code(code);
+        clawbox(&vault).args(["get", level]).assert().success();
+    }
+    clawbox(&vault).args(["--actor", "robot", "get", "public"]).assert().code(64);
+
+    let entries = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    let denied: Vec<_> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|entry| entry["actor"]["identifier"] == "bot" && entry["success"] == false)
+        .collect();
+    assert_eq!(denied.len(), 2);
+    assert!(denied.iter().all(|entry| entry["error_message"].as_str().unwrap().contains("Access denied")));
+}
+
+#[test]
+fn test_scoped_token() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "llm/openai", "sk-1"]).assert().success();
+    clawbox(&vault).args(["set", "db/url", "postgres://"]).assert().success();
+
+    // Only public or normal; a typo must not create a token at all
+    for level in ["publc", "sensitive"] {
+        clawbox(&vault)
+            .args(["token", "create", "typo", "--path", "llm/*", "--max-access", level])
+            .assert()
+            .code(64)
+            .stdout("");
+    }
+    let created = stdout_json(clawbox(&vault).args(["--json", "token", "create", "bot", "--path", "llm/*", "--expires", "30d"]));
+    let token = created["token"].as_str().unwrap().to_string();
+    assert!(token.starts_with("cbt_"));
+
+    let with_token = |args: &[&str]| {
+        let mut cmd = clawbox(&vault);
+        cmd.env_remove("CLAWBOX_PASSWORD").env("CLAWBOX_TOKEN", &token).args(args);
+        cmd
+    };
+    with_token(&["get", "llm/openai"]).assert().success().stdout("sk-1\n");
+    with_token(&["get", "db/url"]).assert().code(6);
+    with_token(&["set", "llm/new", "v"]).assert().code(6);
+    with_token(&["token", "list"]).assert().code(6);
+
+    let tokens = stdout_json(clawbox(&vault).args(["--json", "token", "list"]));
+    assert_eq!(tokens.as_array().unwrap().len(), 1);
+    assert_eq!(tokens[0]["name"], "bot");
+    assert_eq!(tokens[0]["paths"], serde_json::json!(["llm"]));
+
+    clawbox(&vault).args(["token", "revoke", "bot"]).assert().success();
+    clawbox(&vault).args(["token", "revoke", "bot"]).assert().code(3);
+    with_token(&["get", "llm/openai"]).assert().code(6);
+}
+
+#[test]
+fn test_read_rate_limit() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "llm/openai", "sk-1"]).assert().success();
+    clawbox(&vault)
+        .args(["policy", "set", "rate-limit", "--actor-type", "ai", "--per-minute", "2", "--access", "normal"])
+        .assert()
+        .success();
+    clawbox(&vault).args(["policy", "set", "rate-limit", "--actor-type", "robot", "--per-minute", "2"]).assert().failure();
+    // A misspelt level is refused, not taken as Normal
+    clawbox(&vault)
+        .args(["policy", "set", "rate-limit", "--actor-type", "ai", "--per-minute", "1", "--access", "sensitve"])
+        .assert()
+        .code(64);
+
+    let policies = stdout_json(clawbox(&vault).args(["--json", "policy", "show"]));
+    assert_eq!(policies["rate_limits"], serde_json::json!([{"actor": "ai", "access": "normal", "per_minute": 2}]));
+
+    for _ in 0..2 {
+        clawbox(&vault).args(["--actor", "ai:bot", "get", "llm/openai"]).assert().success();
+    }
+    let output = clawbox(&vault).args(["--actor", "ai:bot", "get", "llm/openai"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(9));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("rate limit"));
+    clawbox(&vault).args(["get", "llm/openai"]).assert().success();
+
+    clawbox(&vault).args(["policy", "set", "rate-limit", "--actor-type", "ai", "--off"]).assert().success();
+    clawbox(&vault).args(["--actor", "ai:bot", "get", "llm/openai"]).assert().success();
+}
+
+fn stdout_json(cmd: &mut Command) -> serde_json::Value {
+    let output = cmd.output().unwrap();
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+#[test]
+fn test_json_output_for_mutating_commands() {
+    let vault = init_vault();
+
+    let set = stdout_json(clawbox(&vault).args(["--json", "set", "github/token", "ghp_123"]));
+    assert_eq!(set, serde_json::json!({"ok": true, "action": "set", "path": "github/token"}));
+
+    let delete = stdout_json(clawbox(&vault).args(["--json", "delete", "github/token", "--force"]));
+    assert_eq!(delete, serde_json::json!([{"path": "github/token", "status": "deleted"}]));
+}
+
+#[test]
+fn test_delete_multiple_and_missing() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a", "1"]).assert().success();
+    clawbox(&vault).args(["set", "b", "2"]).assert().success();
+
+    // The prompt lists every path and goes to stderr
+    let output = clawbox(&vault)
+        .args(["delete", "a", "b"])
+        .write_stdin("n\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("  a\n") && stderr.contains("  b\n"));
+
+    let output = clawbox(&vault)
+        .args(["--json", "delete", "a", "missing", "b", "--force"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        results,
+        serde_json::json!([
+            {"path": "a", "status": "deleted"},
+            {"path": "missing", "status": "not_found"},
+            {"path": "b", "status": "deleted"},
+        ])
+    );
+    clawbox(&vault).args(["get", "b"]).assert().code(3);
+
+    clawbox(&vault)
+        .args(["delete", "missing", "--force"])
+        .assert()
+        .code(3);
+    clawbox(&vault)
+        .args(["delete", "missing", "--force", "--ignore-missing"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_json_error_on_stderr() {
+    let vault = init_vault();
+    let output = clawbox(&vault)
+        .args(["--json", "get", "missing/key"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(output.stdout.is_empty());
+    let err: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(err["ok"], false);
+    assert_eq!(err["code"], 3);
+}
+
+#[test]
+fn test_sensitive_values_unmasked_when_piped() {
+    let vault = init_vault();
+    clawbox(&vault)
+        .args(["set", "stripe/live", "sk_live_51Habcdefghijklmnabcd", "--access", "sensitive"])
+        .assert()
+        .success();
+
+    // Scripts reading stdout get the value; masking only applies to terminals
+    clawbox(&vault)
+        .args(["get", "stripe/live"])
+        .assert()
+        .success()
+        .stdout("sk_live_51Habcdefghijklmnabcd\n");
+    clawbox(&vault)
+        .args(["get", "stripe/live", "--show"])
+        .assert()
+        .success()
+        .stdout("sk_live_51Habcdefghijklmnabcd\n");
+}
+
+#[test]
+fn test_status_without_password() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+
+    let status = stdout_json(
+        clawbox(&vault)
+            .env_remove("CLAWBOX_PASSWORD")
+            .args(["--json", "status"]),
+    );
+    assert_eq!(status["initialized"], true);
+    assert_eq!(status["unlocked"], false);
+    assert_eq!(status["secrets"]["total"], 1);
+    assert_eq!(status["secrets"]["normal"], 1);
+    assert_eq!(status["sqlite"]["page_size"], 4096);
+    assert_eq!(status["sqlite"]["synchronous"], "full");
+    assert_eq!(status["sqlite"]["journal_mode"], "delete");
+
+    let empty = TempDir::new().unwrap();
+    let status = stdout_json(clawbox(&empty).args(["--json", "status"]));
+    assert_eq!(status["initialized"], false);
+    assert!(!empty.path().join("vault.db").exists());
+}
+
+#[test]
+fn test_wrong_passwords_lock_out_unlock() {
+    let vault = init_vault();
+    for _ in 0..5 {
+        clawbox(&vault)
+            .env("CLAWBOX_PASSWORD", "wrong")
+            .args(["get", "a/b"])
+            .assert()
+            .code(4);
+    }
+
+    // The right password is not even tried during the cooldown
+    let output = clawbox(&vault).args(["get", "a/b"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(8));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Too many wrong passwords"));
+
+    let status = stdout_json(clawbox(&vault).args(["--json", "status"]));
+    assert_eq!(status["failed_unlocks"], 5);
+    assert!(status["unlock_retry_after_secs"].as_u64().unwrap() > 0);
+}
+
+#[test]
+fn test_unlock_session_skips_password() {
+    let vault = init_vault();
+    let runtime = TempDir::new().unwrap();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+
+    // Without a session, no password means failure
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .env_remove("CLAWBOX_PASSWORD")
+        .args(["get", "a/b"])
+        .assert()
+        .code(4);
+
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .args(["unlock", "--timeout", "5"])
+        .assert()
+        .success();
+
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .env_remove("CLAWBOX_PASSWORD")
+        .args(["get", "a/b"])
+        .assert()
+        .success()
+        .stdout("value\n");
+
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .arg("lock")
+        .assert()
+        .success();
+
+    // Locking again reports that nothing was unlocked
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .arg("lock")
+        .assert()
+        .code(2);
+
+    let audit = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    assert!(audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|entry| entry["action"] == "Lock"));
+
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .env_remove("CLAWBOX_PASSWORD")
+        .args(["get", "a/b"])
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_session_cache_expires_and_lock_deletes_it() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let vault = init_vault();
+    let runtime = TempDir::new().unwrap();
+    let config = runtime.path().join("config.toml");
+    std::fs::write(&config, "session_cache = true\nsession_ttl = \"3s\"\n").unwrap();
+    let cached = |password: bool| {
+        let mut cmd = clawbox(&vault);
+        cmd.env("XDG_RUNTIME_DIR", runtime.path()).env("CLAWBOX_CONFIG", &config);
+        if !password {
+            cmd.env_remove("CLAWBOX_PASSWORD");
+        }
+        cmd
+    };
+    let cache_files = || -> Vec<_> {
+        std::fs::read_dir(runtime.path().join("clawbox/session-cache"))
+            .map(|dir| dir.map(|entry| entry.unwrap().path()).filter(|path| path.extension().unwrap() == "json").collect())
+            .unwrap_or_default()
+    };
+
+    // Off by default
+    clawbox(&vault).env("XDG_RUNTIME_DIR", runtime.path()).args(["set", "a/b", "value"]).assert().success();
+    assert!(cache_files().is_empty());
+
+    // A password unlock fills the cache, and the next command needs none
+    cached(true).args(["list"]).assert().success();
+    let files = cache_files();
+    assert_eq!(files.len(), 1);
+    assert_eq!(std::fs::metadata(&files[0]).unwrap().permissions().mode() & 0o777, 0o600);
+    cached(false).args(["get", "a/b"]).assert().success().stdout("value\n");
+
+    std::thread::sleep(std::time::Duration::from_secs(4));
+    cached(false).args(["get", "a/b"]).assert().code(4);
+    assert!(cache_files().is_empty());
+
+    cached(true).args(["list"]).assert().success();
+    let locked = stdout_json(cached(false).args(["--json", "lock"]));
+    assert_eq!(locked["locked"].as_array().unwrap().len(), 1);
+    assert!(cache_files().is_empty());
+    cached(false).args(["get", "a/b"]).assert().code(4);
+}
+
+#[test]
+fn test_sessions_list_and_revoke() {
+    let vault = init_vault();
+    let runtime = TempDir::new().unwrap();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+    let session = || {
+        let mut cmd = clawbox(&vault);
+        cmd.env("XDG_RUNTIME_DIR", runtime.path());
+        cmd
+    };
+
+    let sessions = stdout_json(session().args(["--json", "sessions"]));
+    assert_eq!(sessions, serde_json::json!([]));
+
+    session().args(["unlock", "--timeout", "5"]).assert().success();
+    session().env_remove("CLAWBOX_PASSWORD").args(["get", "a/b"]).assert().success();
+
+    let sessions = stdout_json(session().args(["--json", "sessions"]));
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0]["origin"]["pid"].is_number());
+    assert!(sessions[0]["last_used"].is_number());
+    let id = sessions[0]["id"].as_str().unwrap().to_string();
+
+    // AI agents can't enumerate or revoke sessions
+    session().args(["--actor", "ai:bot", "sessions"]).assert().code(6);
+
+    session().args(["sessions", "revoke", "00000000"]).assert().code(3);
+    session().args(["sessions", "revoke", &id]).assert().success();
+    session().env_remove("CLAWBOX_PASSWORD").args(["get", "a/b"]).assert().code(4);
+
+    let audit = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    let revoke = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["action"] == "Lock")
+        .unwrap();
+    assert_eq!(revoke["metadata"]["session"], id.as_str());
+    assert_eq!(revoke["actor"]["actor_type"], "human");
+}
+
+#[test]
+fn test_critical_get_asks_for_password_again() {
+    let vault = init_vault();
+    let runtime = TempDir::new().unwrap();
+    clawbox(&vault).args(["set", "recovery/codes", "1234", "--access", "critical"]).assert().success();
+    clawbox(&vault)
+        .env("XDG_RUNTIME_DIR", runtime.path())
+        .args(["unlock", "--timeout", "5"])
+        .assert()
+        .success();
+
+    // The session unlocks the vault, but the read still needs the password
+    let session = || {
+        let mut cmd = clawbox(&vault);
+        cmd.env("XDG_RUNTIME_DIR", runtime.path()).env_remove("CLAWBOX_PASSWORD");
+        cmd
+    };
+    session().args(["get", "recovery/codes"]).write_stdin("wrong\n").assert().code(4);
+    session()
+        .args(["get", "recovery/codes"])
+        .write_stdin(format!("{}\n", PASSWORD))
+        .assert()
+        .success()
+        .stdout("1234\n");
+    clawbox(&vault).env("XDG_RUNTIME_DIR", runtime.path()).arg("lock").assert().success();
+
+    let audit = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    let reauths: Vec<bool> = audit
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|entry| entry["action"] == "Reauth")
+        .map(|entry| entry["success"].as_bool().unwrap())
+        .collect();
+    assert_eq!(reauths.len(), 2);
+    assert_eq!(reauths.iter().filter(|success| **success).count(), 1);
+}
+
+#[test]
+fn test_vault_path_precedence() {
+    let from_env = TempDir::new().unwrap();
+    let from_flag = TempDir::new().unwrap();
+
+    // CLAWBOX_VAULT is used when --vault is absent
+    let status = stdout_json(
+        Command::cargo_bin("clawbox")
+            .unwrap()
+            .env("CLAWBOX_VAULT", from_env.path())
+            .args(["--json", "status"]),
+    );
+    assert_eq!(status["vault"], from_env.path().to_str().unwrap());
+
+    // --vault wins over CLAWBOX_VAULT
+    let status = stdout_json(
+        Command::cargo_bin("clawbox")
+            .unwrap()
+            .env("CLAWBOX_VAULT", from_env.path())
+            .arg("--vault")
+            .arg(from_flag.path())
+            .args(["--json", "status"]),
+    );
+    assert_eq!(status["vault"], from_flag.path().to_str().unwrap());
+}
+
+#[test]
+fn test_vault_path_tilde_and_env_expansion() {
+    let home = TempDir::new().unwrap();
+    let cwd = TempDir::new().unwrap();
+
+    Command::cargo_bin("clawbox")
+        .unwrap()
+        .current_dir(cwd.path())
+        .env("HOME", home.path())
+        .env("CLAWBOX_PASSWORD", PASSWORD)
+        .args(["--vault", "~/vaults/work", "init"])
+        .assert()
+        .success();
+    assert!(home.path().join("vaults/work/vault.db").exists());
+    assert!(!cwd.path().join("~").exists());
+
+    Command::cargo_bin("clawbox")
+        .unwrap()
+        .env("VAULT_ROOT", home.path())
+        .env("CLAWBOX_PASSWORD", PASSWORD)
+        .args(["--vault", "${VAULT_ROOT}/vaults/work", "list"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_vault_path_must_be_directory() {
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("not-a-dir");
+    std::fs::write(&file, "x").unwrap();
+
+    Command::cargo_bin("clawbox")
+        .unwrap()
+        .arg("--vault")
+        .arg(&file)
+        .arg("status")
+        .assert()
+        .code(1);
+}
+
+#[test]
+fn test_init_rejects_weak_password() {
+    let vault = TempDir::new().unwrap();
+    clawbox(&vault)
+        .env("CLAWBOX_PASSWORD", "abc")
+        .arg("init")
+        .assert()
+        .code(1);
+    assert!(!vault.path().join("vault.db").exists());
+
+    clawbox(&vault)
+        .env("CLAWBOX_PASSWORD", "abc")
+        .args(["init", "--allow-weak-password"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_passwd_changes_password() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+
+    clawbox(&vault)
+        .env("CLAWBOX_NEW_PASSWORD", "password")
+        .arg("passwd")
+        .assert()
+        .code(1);
+
+    clawbox(&vault)
+        .env("CLAWBOX_NEW_PASSWORD", "plinth-orbit-mackerel-sundial-42")
+        .arg("passwd")
+        .assert()
+        .success();
+
+    clawbox(&vault).args(["get", "a/b"]).assert().code(4);
+    clawbox(&vault)
+        .env("CLAWBOX_PASSWORD", "plinth-orbit-mackerel-sundial-42")
+        .args(["get", "a/b"])
+        .assert()
+        .success()
+        .stdout("value\n");
+}
+
+#[test]
+fn test_init_twice_refuses() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+
+    clawbox(&vault).arg("init").assert().code(1);
+    clawbox(&vault)
+        .args(["get", "a/b"])
+        .assert()
+        .success()
+        .stdout("value\n");
+
+    // --force needs the vault path typed back
+    clawbox(&vault)
+        .args(["init", "--force"])
+        .write_stdin("nope\n")
+        .assert()
+        .code(1);
+    clawbox(&vault).args(["get", "a/b"]).assert().success();
+
+    clawbox(&vault)
+        .args(["init", "--force"])
+        .write_stdin(format!("{}\n", vault.path().display()))
+        .assert()
+        .success();
+    // The old secrets are gone, not left behind unreadable
+    clawbox(&vault).args(["get", "a/b"]).assert().code(3);
+
+    // --yes skips the confirmation in scripts
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+    clawbox(&vault).args(["init", "--yes"]).assert().code(64);
+    clawbox(&vault).args(["init", "--force", "--yes"]).assert().success();
+    clawbox(&vault).args(["get", "a/b"]).assert().code(3);
+
+    let backups = std::fs::read_dir(vault.path())
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("vault.db.pre-reinit-")
+        })
+        .count();
+    assert_eq!(backups, 2);
+}
+
+#[test]
+fn test_init_duress_opens_decoy() {
+    const DURESS: &str = "a different duress passphrase";
+    let vault = TempDir::new().unwrap();
+    clawbox(&vault).args(["init", "--duress"]).env("CLAWBOX_DURESS_PASSWORD", PASSWORD).assert().code(1);
+    clawbox(&vault).args(["init", "--duress"]).env("CLAWBOX_DURESS_PASSWORD", DURESS).assert().success();
+    clawbox(&vault).args(["set", "bank/pin", "1234"]).assert().success();
+
+    let decoy = |args: &[&str]| {
+        let mut cmd = clawbox(&vault);
+        cmd.env("CLAWBOX_PASSWORD", DURESS).args(args);
+        cmd
+    };
+    decoy(&["get", "bank/pin"]).assert().code(3);
+    decoy(&["set", "bank/pin", "0000"]).assert().success();
+    decoy(&["get", "bank/pin"]).assert().success().stdout("0000\n");
+    assert_eq!(stdout_json(&mut decoy(&["--json", "list"])).as_array().unwrap().len(), 1);
+    let audit = stdout_json(&mut decoy(&["--json", "audit"]));
+    assert!(audit.as_array().unwrap().iter().all(|e| e["key_path"] == "bank/pin"));
+    clawbox(&vault).args(["get", "bank/pin"]).assert().success().stdout("1234\n");
+
+    // Adding it to an initialized vault replaces the decoy
+    clawbox(&vault).args(["init", "--duress"]).env("CLAWBOX_DURESS_PASSWORD", DURESS).assert().success();
+    decoy(&["get", "bank/pin"]).assert().code(3);
+    clawbox(&vault).args(["get", "bank/pin"]).assert().success().stdout("1234\n");
+}
+
+#[test]
+fn test_read_only_flag() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "db/url", "postgres://"]).assert().success();
+    let db = vault.path().join("vault.db");
+    let before = std::fs::read(&db).unwrap();
+
+    clawbox(&vault).args(["--read-only", "get", "db/url"]).assert().success().stdout("postgres://\n");
+    clawbox(&vault).args(["--read-only", "list"]).assert().success();
+    clawbox(&vault).args(["--read-only", "audit"]).assert().success();
+    clawbox(&vault).args(["--read-only", "set", "db/url", "changed"]).assert().code(10);
+    clawbox(&vault).args(["--read-only", "delete", "--force", "db/url"]).assert().code(10);
+    clawbox(&vault).args(["--read-only", "init", "--force"]).assert().code(10);
+    assert_eq!(std::fs::read(&db).unwrap(), before);
+
+    let missing = TempDir::new().unwrap();
+    clawbox(&missing).args(["--read-only", "get", "db/url"]).assert().code(2);
+    assert!(!missing.path().join("vault.db").exists());
+}
+
+#[test]
+fn test_profiles_and_copy_to() {
+    let work = init_vault();
+    let personal = TempDir::new().unwrap();
+    let other_password = "plinth-orbit-mackerel-sundial-42";
+    clawbox(&personal)
+        .env("CLAWBOX_PASSWORD", other_password)
+        .arg("init")
+        .assert()
+        .success();
+
+    let config_dir = TempDir::new().unwrap();
+    let config = config_dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "default_profile = \"work\"\n\n[profiles.work]\nvault = {:?}\n\n[profiles.personal]\nvault = {:?}\n",
+            work.path(),
+            personal.path()
+        ),
+    )
+    .unwrap();
+
+    let cmd = || {
+        let mut cmd = Command::cargo_bin("clawbox").unwrap();
+        cmd.env("CLAWBOX_CONFIG", &config)
+            .env("CLAWBOX_PASSWORD", PASSWORD)
+            .env_remove("CLAWBOX_VAULT");
+        cmd
+    };
+
+    // The default profile is used without --vault
+    cmd()
+        .args(["set", "github/token", "ghp_123", "--access", "sensitive", "--tags", "dev"])
+        .assert()
+        .success();
+
+    cmd()
+        .args(["--profile", "work", "copy-to", "--profile", "personal", "github/token"])
+        .env("CLAWBOX_DEST_PASSWORD", other_password)
+        .assert()
+        .success();
+
+    // Copying again without --overwrite refuses
+    cmd()
+        .args(["copy-to", "--profile", "personal", "github/token"])
+        .env("CLAWBOX_DEST_PASSWORD", other_password)
+        .assert()
+        .code(1);
+
+    let listed = stdout_json(
+        cmd()
+            .env("CLAWBOX_PASSWORD", other_password)
+            .args(["--profile", "personal", "--json", "list"]),
+    );
+    assert_eq!(listed[0]["path"], "github/token");
+    assert_eq!(listed[0]["access"], "Sensitive");
+    assert_eq!(listed[0]["tags"], serde_json::json!(["dev"]));
+
+    cmd()
+        .args(["--profile", "nope", "list"])
+        .assert()
+        .code(1);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_with_prefix() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "app/db/url", "postgres://prod"]).assert().success();
+    clawbox(&vault).args(["set", "app/api-key", "k1"]).assert().success();
+    clawbox(&vault).args(["set", "other/token", "nope"]).assert().success();
+
+    clawbox(&vault)
+        .args(["run", "--prefix", "app/", "--", "sh", "-c", "echo \"$DB_URL $API_KEY ${OTHER_TOKEN:-unset}\""])
+        .assert()
+        .success()
+        .stdout("postgres://prod k1 unset\n");
+    // The command's exit code is passed through
+    clawbox(&vault).args(["run", "--prefix", "app", "--", "sh", "-c", "exit 42"]).assert().code(42);
+
+    let audit = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    let reads: Vec<_> = audit.as_array().unwrap().iter().filter(|entry| entry["action"] == "Read").collect();
+    assert_eq!(reads.len(), 2);
+    assert_eq!(reads[0]["key_path"], "app/*");
+    assert_eq!(reads[0]["metadata"]["count"], 2);
+}
+
+#[test]
+fn test_template_render() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "db/password", "hunter2"]).assert().success();
+
+    let dir = TempDir::new().unwrap();
+    let template = dir.path().join("config.tmpl");
+    std::fs::write(&template, "pw={{ clawbox \"db/password\" }}\nraw=\\{{ literal }}\n").unwrap();
+
+    clawbox(&vault)
+        .args(["template", "render", "--check"])
+        .arg(&template)
+        .assert()
+        .success();
+
+    clawbox(&vault)
+        .args(["template", "render"])
+        .arg(&template)
+        .assert()
+        .success()
+        .stdout("pw=hunter2\nraw={{ literal }}\n");
+
+    let output = dir.path().join("config.ini");
+    clawbox(&vault)
+        .args(["template", "render"])
+        .arg(&template)
+        .arg("--out")
+        .arg(&output)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), "pw=hunter2\nraw={{ literal }}\n");
+    assert_eq!(std::fs::metadata(&output).unwrap().permissions().mode() & 0o777, 0o600);
+
+    // Missing references fail without writing anything
+    std::fs::write(&template, "{{ clawbox \"db/user\" }}").unwrap();
+    std::fs::remove_file(&output).unwrap();
+    clawbox(&vault)
+        .args(["template", "render"])
+        .arg(&template)
+        .arg("--out")
+        .arg(&output)
+        .assert()
+        .code(3);
+    assert!(!output.exists());
+    clawbox(&vault)
+        .args(["template", "render", "--check"])
+        .arg(&template)
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_clipboard_auto_clear() {
+    let vault = init_vault();
+    let dir = TempDir::new().unwrap();
+    let clip = dir.path().join("clip");
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[clipboard]\nclear_after = 1\ncopy_command = [\"sh\", \"-c\", \"cat > '{0}'\"]\npaste_command = [\"cat\", \"{0}\"]\n",
+            clip.display()
+        ),
+    )
+    .unwrap();
+    let cmd = || {
+        let mut cmd = clawbox(&vault);
+        cmd.env("CLAWBOX_CONFIG", &config).env("XDG_RUNTIME_DIR", dir.path());
+        cmd
+    };
+
+    cmd().args(["set", "a/b", "s3cret"]).assert().success();
+    let output = cmd().args(["get", "a/b", "--clipboard"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("s3cret"));
+    assert_eq!(std::fs::read_to_string(&clip).unwrap(), "s3cret");
+
+    // The detached clearer wipes it and records the wipe
+    let started = std::time::Instant::now();
+    while std::fs::read_to_string(&clip).unwrap() != "" {
+        assert!(started.elapsed().as_secs() < 10, "clipboard was not cleared");
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let entries = stdout_json(cmd().args(["--json", "audit"]));
+    assert!(entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|entry| entry["action"] == "ClipboardClear" && entry["key_path"] == "a/b"));
+
+    // Something copied since is left alone
+    cmd().args(["get", "a/b", "--clipboard"]).assert().success();
+    std::fs::write(&clip, "other").unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(2));
+    assert_eq!(std::fs::read_to_string(&clip).unwrap(), "other");
+
+    // `lock --all` wipes what we copied with --no-clear
+    cmd().args(["get", "a/b", "--clipboard", "--no-clear"]).assert().success();
+    cmd().args(["lock", "--all"]).assert().code(2);
+    assert_eq!(std::fs::read_to_string(&clip).unwrap(), "");
+}
+
+#[test]
+fn test_password_file() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a/b", "s3cret"]).assert().success();
+    let dir = TempDir::new().unwrap();
+    let file = dir.path().join("password");
+    std::fs::write(&file, format!("{}\n", PASSWORD)).unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    // The file wins over CLAWBOX_PASSWORD, and CLAWBOX_PASSWORD_FILE works too
+    let output = clawbox(&vault)
+        .env("CLAWBOX_PASSWORD", "wrong")
+        .arg("--password-file")
+        .arg(&file)
+        .args(["get", "a/b"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "s3cret\n");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Warning"));
+    clawbox(&vault)
+        .env_remove("CLAWBOX_PASSWORD")
+        .env("CLAWBOX_PASSWORD_FILE", &file)
+        .args(["get", "a/b"])
+        .assert()
+        .success();
+
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o644)).unwrap();
+    let output = clawbox(&vault).arg("--password-file").arg(&file).args(["get", "a/b"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("chmod 600"));
+
+    // CLAWBOX_PASSWORD still works, with a warning, and children don't see it
+    let clip = dir.path().join("clip");
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[clipboard]\ncopy_command = [\"sh\", \"-c\", \"echo ${{CLAWBOX_PASSWORD-unset}} > '{0}'\"]\npaste_command = [\"cat\", \"{0}\"]\n",
+            clip.display()
+        ),
+    )
+    .unwrap();
+    let output = clawbox(&vault)
+        .env("CLAWBOX_CONFIG", &config)
+        .args(["get", "a/b", "--clipboard", "--no-clear"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Warning: CLAWBOX_PASSWORD"));
+    assert_eq!(std::fs::read_to_string(&clip).unwrap(), "unset\n");
+}
+
+#[test]
+fn test_hook_on_critical_read() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "bank/pin", "1234", "--access", "critical"]).assert().success();
+    clawbox(&vault).args(["set", "db/url", "postgres://"]).assert().success();
+
+    // A webhook receiver that answers one request
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let receiver = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader.get_mut().write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+        serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+    });
+
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[hooks]\non_critical_read = \"{}\"\n\n[[hooks.rules]]\nname = \"ai-writes\"\nrun = \"exit 1\"\nactions = [\"write\"]\nactors = [\"ai\"]\n",
+            url
+        ),
+    )
+    .unwrap();
+    let cmd = || {
+        let mut cmd = clawbox(&vault);
+        cmd.env("CLAWBOX_CONFIG", &config);
+        cmd
+    };
+
+    cmd().args(["get", "db/url"]).assert().success();
+    let output = cmd().args(["get", "bank/pin"]).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1234\n");
+    let event = receiver.join().unwrap();
+    assert_eq!(event["key_path"], "bank/pin");
+    assert_eq!(event["access"], "critical");
+
+    // A failing hook doesn't fail the write, and both outcomes are recorded
+    cmd().args(["--actor", "ai:agent", "set", "db/url", "mysql://"]).assert().success();
+    let entries = stdout_json(cmd().args(["--json", "audit"]));
+    let entries = entries.as_array().unwrap();
+    let hooks_of = |action: &str, path: &str| {
+        entries
+            .iter()
+            .find(|entry| entry["action"] == action && entry["key_path"] == path && entry["success"] == true)
+            .unwrap()["metadata"]["hooks"][0]
+            .clone()
+    };
+    assert_eq!(hooks_of("Read", "bank/pin")["ok"], true);
+    assert_eq!(hooks_of("Write", "db/url")["hook"], "ai-writes");
+    assert_eq!(hooks_of("Write", "db/url")["ok"], false);
+    assert!(hooks_of("Read", "db/url").is_null());
+
+    std::fs::write(&config, "[[hooks.rules]]\nrun = \"true\"\nlevels = [\"secret\"]\n").unwrap();
+    let output = cmd().args(["get", "db/url"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown access level"));
+}
+
+#[test]
+fn test_export_needs_consent() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "db/url", "postgres://"]).assert().success();
+    clawbox(&vault).args(["set", "stripe/key", "sk_live", "--access", "sensitive"]).assert().success();
+    clawbox(&vault).args(["set", "bank/pin", "1234", "--access", "critical"]).assert().success();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("out.json");
+
+    // Asks first, saying how much and how sensitive
+    let result = clawbox(&vault).arg("export").arg(&output).write_stdin("n\n").output().unwrap();
+    assert!(result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("1 Critical secret(s) left out"), "{}", stderr);
+    assert!(stderr.contains("Write 2 secrets (highest access level: Sensitive) UNENCRYPTED"), "{}", stderr);
+    assert!(!output.exists());
+
+    clawbox(&vault).arg("export").arg(&output).write_stdin("y\n").assert().success();
+    let exported: serde_json::Value = serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    assert_eq!(exported.as_array().unwrap().len(), 2);
+    assert_eq!(std::fs::metadata(&output).unwrap().permissions().mode() & 0o777, 0o600);
+
+    clawbox(&vault).args(["export", "--plaintext-ok", "--include-critical"]).arg(&output).assert().success();
+    let exported: serde_json::Value = serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    assert!(exported.as_array().unwrap().iter().any(|secret| secret["path"] == "bank/pin"));
+
+    // One entry per export, with where it went and what it held
+    let entries = stdout_json(clawbox(&vault).args(["--json", "audit"]));
+    let exports: Vec<_> = entries.as_array().unwrap().iter().filter(|entry| entry["action"] == "Export").collect();
+    assert_eq!(exports.len(), 2, "{:#?}", exports);
+    assert_eq!(exports[0]["key_path"], output.display().to_string());
+    assert_eq!(exports[0]["metadata"]["count"], 3);
+    assert_eq!(exports[0]["metadata"]["plaintext"], true);
+    assert_eq!(exports[0]["metadata"]["format"], "json");
+    assert_eq!(exports[1]["metadata"]["skipped_critical"], 1);
+    assert!(!entries.as_array().unwrap().iter().any(|entry| entry["action"] == "Read"));
+
+    let yaml = dir.path().join("out.yaml");
+    clawbox(&vault).args(["export", "--plaintext-ok", "--format", "yaml"]).arg(&yaml).assert().success();
+    assert_eq!(
+        std::fs::read_to_string(&yaml).unwrap(),
+        "# ClawBox Export\ndb/url:\n  value: \"postgres://\"\nstripe/key:\n  value: \"sk_live\"\n"
+    );
+
+    let parallel = dir.path().join("parallel.yaml");
+    clawbox(&vault).args(["export", "--plaintext-ok", "--format", "yaml", "--jobs", "4"]).arg(&parallel).assert().success();
+    assert_eq!(std::fs::read(&parallel).unwrap(), std::fs::read(&yaml).unwrap());
+}
+
+#[test]
+fn test_history_and_rollback() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "db/password", "first"]).assert().success();
+    clawbox(&vault).args(["--actor", "ai:bot", "set", "db/password", "second"]).assert().success();
+
+    let history = stdout_json(clawbox(&vault).args(["--json", "history", "db/password"]));
+    let history = history.as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["version"], 2);
+    assert_eq!(history[0]["current"], true);
+    // Each version names whoever wrote it
+    assert_eq!(history[0]["created_by"], "ai:bot");
+    assert!(history[1]["created_by"].as_str().unwrap().starts_with("human:"));
+    let output = clawbox(&vault).args(["history", "db/password"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("ai:bot"));
+    assert!(!history[0].to_string().contains("second"));
+
+    // Declining the confirmation changes nothing
+    clawbox(&vault)
+        .args(["rollback", "db/password", "--previous"])
+        .write_stdin("n\n")
+        .assert()
+        .success();
+    clawbox(&vault)
+        .args(["--quiet", "get", "db/password"])
+        .assert()
+        .stdout("second\n");
+
+    let result = stdout_json(
+        clawbox(&vault).args(["--json", "rollback", "db/password", "--version", "1", "--force"]),
+    );
+    assert_eq!(result["version"], 1);
+    clawbox(&vault)
+        .args(["--quiet", "get", "db/password"])
+        .assert()
+        .stdout("first\n");
+
+    let entries = stdout_json(clawbox(&vault).args(["--json", "audit", "--key", "db/password"]));
+    assert!(entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|entry| entry["action"] == "Rollback" && entry["key_path"] == "db/password@v1"));
+
+    clawbox(&vault)
+        .args(["rollback", "db/password", "--version", "7", "--force"])
+        .assert()
+        .code(3);
+    clawbox(&vault)
+        .args(["rollback", "db/password"])
+        .assert()
+        .code(1);
+
+    // A hard delete takes the history with it
+    clawbox(&vault).args(["delete", "db/password", "--hard", "--force"]).assert().success();
+    clawbox(&vault).args(["history", "db/password"]).assert().code(3);
+    clawbox(&vault)
+        .args(["rollback", "db/password", "--version", "1", "--force"])
+        .assert()
+        .code(3);
+}
+
+#[test]
+fn test_prune() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "tmp/key", "x", "--ttl", "0s"]).assert().success();
+    for value in ["1", "2", "3"] {
+        clawbox(&vault).args(["set", "db/password", value]).assert().success();
+    }
+
+    let report = stdout_json(clawbox(&vault).args(["--json", "prune", "--dry-run", "--keep-versions", "2"]));
+    assert_eq!(report["expired_secrets"], serde_json::json!(["tmp/key"]));
+    assert_eq!(report["old_versions"], 1);
+    clawbox(&vault)
+        .args(["prune", "--dry-run", "--keep-versions", "2"])
+        .assert()
+        .code(1);
+
+    clawbox(&vault)
+        .args(["prune", "--keep-versions", "2", "--compact"])
+        .assert()
+        .success();
+    clawbox(&vault).args(["get", "tmp/key"]).assert().code(3);
+    let history = stdout_json(clawbox(&vault).args(["--json", "history", "db/password"]));
+    assert_eq!(history.as_array().unwrap().len(), 2);
+
+    // Nothing left to do
+    clawbox(&vault)
+        .args(["prune", "--dry-run", "--keep-versions", "2"])
+        .assert()
+        .success();
+    clawbox(&vault).args(["set", "a", "b", "--ttl", "7x"]).assert().code(64);
+}
+
+#[test]
+fn test_get_output_modes() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "docker/pass", "p@ss word"]).assert().success();
+
+    // Default: the value and a single trailing newline
+    clawbox(&vault)
+        .args(["get", "docker/pass"])
+        .assert()
+        .success()
+        .stdout("p@ss word\n");
+    clawbox(&vault)
+        .args(["get", "docker/pass", "-n"])
+        .assert()
+        .success()
+        .stdout("p@ss word");
+    clawbox(&vault)
+        .args(["get", "docker/pass", "--base64", "--raw"])
+        .assert()
+        .success()
+        .stdout("cEBzcyB3b3Jk");
+
+    // A password prompt never lands on stdout
+    clawbox(&vault)
+        .env_remove("CLAWBOX_PASSWORD")
+        .args(["get", "docker/pass"])
+        .write_stdin(format!("{}\n", PASSWORD))
+        .assert()
+        .success()
+        .stdout("p@ss word\n");
+}
+
+#[test]
+fn test_import_rejects_unsafe_paths() {
+    let vault = init_vault();
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("secrets.json");
+
+    // A crafted file writes nothing, not even the entries before the bad one
+    std::fs::write(&input, r#"[{"path": "ok/one", "value": "1"}, {"path": "../../x", "value": "2"}]"#).unwrap();
+    clawbox(&vault).arg("import").arg(&input).assert().code(1);
+    clawbox(&vault).args(["get", "ok/one"]).assert().code(3);
+
+    std::fs::write(&input, r#"[{"path": "bad\nline", "value": "1"}]"#).unwrap();
+    clawbox(&vault).arg("import").arg(&input).assert().code(1);
+
+    // Harmless slips are normalized rather than rejected
+    std::fs::write(&input, r#"[{"path": " ok//two ", "value": "2"}]"#).unwrap();
+    let result = stdout_json(clawbox(&vault).args(["--json", "import"]).arg(&input));
+    assert_eq!(result["items"][0]["path"], "ok/two");
+    clawbox(&vault).args(["get", "ok/two"]).assert().success().stdout("2\n");
+}
+
+#[test]
+fn test_large_import_is_one_transaction() {
+    let vault = init_vault();
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("secrets.json");
+    let secrets: Vec<_> = (0..10_000)
+        .map(|i| serde_json::json!({ "path": format!("bulk/{:05}", i), "value": format!("value {}", i), "tags": ["bulk"] }))
+        .collect();
+    std::fs::write(&input, serde_json::to_string(&secrets).unwrap()).unwrap();
+
+    // About 2 seconds here, against 27 with a commit per secret and per
+    // audit entry; the bound only catches a return to that
+    let start = std::time::Instant::now();
+    let output = clawbox(&vault).arg("import").arg(&input).output().unwrap();
+    assert!(output.status.success());
+    assert!(start.elapsed() < std::time::Duration::from_secs(15), "import took {:?}", start.elapsed());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Imported 1000/10000") && stderr.contains("Imported 10000/10000"));
+
+    clawbox(&vault).args(["get", "bulk/09999"]).assert().success().stdout("value 9999\n");
+    let listed = stdout_json(clawbox(&vault).args(["--json", "list", "--tag", "bulk"]));
+    assert_eq!(listed.as_array().unwrap().len(), 10_000);
+    let audit = stdout_json(clawbox(&vault).args(["--json", "audit", "--key", "bulk/00042"]));
+    assert_eq!(audit.as_array().unwrap().len(), 1);
+    assert_eq!(stdout_json(clawbox(&vault).args(["--json", "verify"]))["audit_chain_valid"], true);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_loose_permissions_warn_refuse_and_fix() {
+    use std::os::unix::fs::PermissionsExt;
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "a/b", "value"]).assert().success();
+    let db = vault.path().join("vault.db");
+    std::fs::set_permissions(&db, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+    let output = clawbox(&vault).args(["get", "a/b"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("has mode 644"));
+    clawbox(&vault).args(["--strict", "get", "a/b"]).assert().code(6);
+
+    let report = stdout_json(clawbox(&vault).args(["--json", "verify"]));
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["permissions"][0]["mode"], 0o644);
+
+    let report = stdout_json(clawbox(&vault).args(["--json", "verify", "--fix-permissions"]));
+    assert_eq!(report["ok"], true);
+    assert_eq!(report["fixed_permissions"].as_array().unwrap().len(), 1);
+    assert_eq!(std::fs::metadata(&db).unwrap().permissions().mode() & 0o777, 0o600);
+    clawbox(&vault).args(["--strict", "get", "a/b"]).assert().success().stdout("value\n");
+}
+
+#[test]
+fn test_import_over_secrets_can_be_undone() {
+    let vault = init_vault();
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("secrets.json");
+    clawbox(&vault).args(["set", "db/url", "postgres://prod"]).assert().success();
+
+    std::fs::write(&input, r#"[{"path": "db/url", "value": "postgres://stale"}]"#).unwrap();
+    let result = stdout_json(clawbox(&vault).args(["--json", "import"]).arg(&input));
+    let name = result["snapshot"].as_str().unwrap().to_string();
+    clawbox(&vault).args(["get", "db/url"]).assert().success().stdout("postgres://stale\n");
+
+    let snapshots = stdout_json(clawbox(&vault).args(["--json", "snapshots"]));
+    assert_eq!(snapshots[0]["name"], name.as_str());
+    assert_eq!(snapshots[0]["operation"], "import");
+
+    let result = stdout_json(clawbox(&vault).args(["--json", "snapshots", "restore", &name]));
+    assert!(result["replaced"].as_str().unwrap().ends_with("-restore.db"));
+    clawbox(&vault).args(["get", "db/url"]).assert().success().stdout("postgres://prod\n");
+
+    // Turned off, imports take none
+    clawbox(&vault).args(["--actor", "ai:bot", "policy", "set", "snapshots", "--keep", "0"]).assert().code(6);
+    clawbox(&vault).args(["policy", "set", "snapshots", "--keep", "0"]).assert().success();
+    assert_eq!(stdout_json(clawbox(&vault).args(["--json", "snapshots"])), serde_json::json!([]));
+    let result = stdout_json(clawbox(&vault).args(["--json", "import"]).arg(&input));
+    assert!(result["snapshot"].is_null());
+}
+
+#[test]
+fn test_env_import_export_round_trip() {
+    let vault = init_vault();
+    let dir = TempDir::new().unwrap();
+    let input = dir.path().join("app.env");
+    let original = "DATABASE_URL=\"postgres://u:p@db/app\"\nAWS_SECRET_ACCESS_KEY=abc123\nQUOTED=\"say \\\"hi\\\" \\\\ bye\"\n";
+    std::fs::write(&input, original).unwrap();
+
+    clawbox(&vault)
+        .args(["import", "--format", "env", "--env-prefix", "app/"])
+        .arg(&input)
+        .assert()
+        .success();
+    clawbox(&vault)
+        .args(["get", "app/aws_secret_access_key"])
+        .assert()
+        .success()
+        .stdout("abc123\n");
+    clawbox(&vault)
+        .args(["get", "app/quoted"])
+        .assert()
+        .success()
+        .stdout("say \"hi\" \\ bye\n");
+
+    let output = dir.path().join("out.env");
+    clawbox(&vault)
+        .args(["export", "--plaintext-ok", "--format", "env"])
+        .arg(&output)
+        .assert()
+        .success();
+    // Same names and values; unquoted values come back quoted
+    let mut exported: Vec<_> = std::fs::read_to_string(&output).unwrap().lines().map(String::from).collect();
+    exported.sort();
+    assert_eq!(
+        exported,
+        vec![
+            "AWS_SECRET_ACCESS_KEY=\"abc123\"",
+            "DATABASE_URL=\"postgres://u:p@db/app\"",
+            "QUOTED=\"say \\\"hi\\\" \\\\ bye\"",
+        ]
+    );
+
+    // Without a prefix, --no-split keeps the name as one segment
+    let other = init_vault();
+    clawbox(&other)
+        .args(["import", "--format", "env", "--no-split", "--keep-case"])
+        .arg(&input)
+        .assert()
+        .success();
+    clawbox(&other)
+        .args(["get", "DATABASE_URL"])
+        .assert()
+        .success()
+        .stdout("postgres://u:p@db/app\n");
+}
+
+#[test]
+fn test_list_sorting_and_plain_output() {
+    let vault = init_vault();
+    clawbox(&vault).args(["set", "b/key", "12345", "--access", "critical"]).assert().success();
+    clawbox(&vault).args(["set", "a/key", "1", "--access", "public", "--tags", "x,y"]).assert().success();
+    clawbox(&vault).args(["set", "c/key", "12"]).assert().success();
+
+    // Not a terminal: bare paths
+    clawbox(&vault)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout("a/key\nb/key\nc/key\n");
+    clawbox(&vault)
+        .args(["list", "--sort", "access", "--reverse"])
+        .assert()
+        .success()
+        .stdout("b/key\nc/key\na/key\n");
+
+    let listed = stdout_json(clawbox(&vault).args(["--json", "list", "--sort", "access"]));
+    assert_eq!(listed[0]["path"], "a/key");
+    assert_eq!(listed[0]["tags"], serde_json::json!(["x", "y"]));
+    assert_eq!(listed[2]["size"], 5);
+    assert!(listed[2]["updated_at"].is_string());
+
+    // By path, written as each row is read, in the same format
+    let streamed = clawbox(&vault).args(["--json", "list"]).output().unwrap();
+    let streamed: serde_json::Value = serde_json::from_slice(&streamed.stdout).unwrap();
+    let paths: Vec<_> = streamed.as_array().unwrap().iter().map(|secret| secret["path"].clone()).collect();
+    assert_eq!(paths, ["a/key", "b/key", "c/key"]);
+    assert_eq!(streamed[0], listed[0]);
+    clawbox(&vault).args(["--json", "list", "none/*"]).assert().success().stdout("[]\n");
+
+    // Filters, streamed or sorted
+    clawbox(&vault).args(["list", "--tag", "y"]).assert().success().stdout("a/key\n");
+    clawbox(&vault).args(["list", "--tag", "x,missing"]).assert().success().stdout("");
+    clawbox(&vault).args(["list", "--access", "critical,normal"]).assert().success().stdout("b/key\nc/key\n");
+    // A misspelt level is a usage error, not a listing of Normal secrets
+    clawbox(&vault).args(["list", "--access", "critcal"]).assert().code(64).stdout("");
+    clawbox(&vault)
+        .args(["list", "--access", "critical", "--access", "public", "--sort", "access"])
+        .assert()
+        .success()
+        .stdout("a/key\nb/key\n");
+}
+
+#[test]
+fn test_set_stdin_json() {
+    let vault = init_vault();
+
+    let results = stdout_json(
+        clawbox(&vault)
+            .args(["--json", "set", "--stdin-json", "--access", "sensitive", "--tags", "infra"])
+            .write_stdin(r#"{"db/url": "postgres://db/app", "db/pass": "x"}"#),
+    );
+    assert_eq!(
+        results,
+        serde_json::json!([
+            {"path": "db/pass", "status": "set"},
+            {"path": "db/url", "status": "set"},
+        ])
+    );
+    let info = stdout_json(clawbox(&vault).args(["--json", "list"]));
+    for secret in info.as_array().unwrap() {
+        assert_eq!(secret["access"], "Sensitive");
+        assert_eq!(secret["tags"], serde_json::json!(["infra"]));
+    }
+
+    // One bad entry means nothing is written
+    let output = clawbox(&vault)
+        .args(["--json", "set", "--stdin-json"])
+        .write_stdin(r#"[{"path": "ok/one", "value": "1"}, {"path": "../bad", "value": "2"}, {"path": "ok/two", "value": "3", "access": "secret"}]"#)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(results[0]["status"], "not_written");
+    assert_eq!(results[1]["status"], "invalid");
+    assert!(results[2]["error"].as_str().unwrap().contains("unknown access level"));
+    clawbox(&vault).args(["get", "ok/one"]).assert().code(3);
+
+    // The export format feeds straight back in, keeping per-secret metadata
+    let dir = TempDir::new().unwrap();
+    let export = dir.path().join("secrets.json");
+    clawbox(&vault).args(["set", "api/key", "k", "--note", "prod"]).assert().success();
+    clawbox(&vault).args(["export", "--plaintext-ok"]).arg(&export).assert().success();
+    let other = init_vault();
+    clawbox(&other)
+        .args(["set", "--stdin-json"])
+        .write_stdin(std::fs::read(&export).unwrap())
+        .assert()
+        .success();
+    clawbox(&other).args(["get", "db/url"]).assert().success().stdout("postgres://db/app\n");
+    let reexport = dir.path().join("again.json");
+    clawbox(&other).args(["export", "--plaintext-ok"]).arg(&reexport).assert().success();
+    let secrets: serde_json::Value = serde_json::from_slice(&std::fs::read(&reexport).unwrap()).unwrap();
+    let original: serde_json::Value = serde_json::from_slice(&std::fs::read(&export).unwrap()).unwrap();
+    assert_eq!(secrets, original);
+
+    clawbox(&vault).args(["set", "--stdin-json", "path"]).assert().code(64);
+}
+
+#[test]
+fn test_sync_resolve_conflicts() {
+    let vault = init_vault();
+    let copies = ["a.conflict-desk-20260101T000000Z", "b.conflict-desk-20260101T000000Z"];
+    clawbox(&vault).args(["set", "a", "local-a"]).assert().success();
+    clawbox(&vault).args(["set", "b", "local-b"]).assert().success();
+    for (copy, value) in copies.iter().zip(["remote-a", "remote-b"]) {
+        clawbox(&vault).args(["set", copy, value, "--tags", "sync-conflict"]).assert().success();
+    }
+
+    let output = clawbox(&vault).args(["--json", "sync", "--resolve"]).output().unwrap();
+    let listed: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(listed[0]["path"], "a");
+    assert_eq!(listed[1]["copy"], copies[1]);
+
+    // Keep the remote value of a, the local value of b
+    clawbox(&vault).args(["sync", "--resolve"]).write_stdin("r\nl\n").assert().success();
+    clawbox(&vault).args(["get", "a", "--quiet"]).assert().success().stdout("remote-a\n");
+    clawbox(&vault).args(["get", "b", "--quiet"]).assert().success().stdout("local-b\n");
+    for copy in copies {
+        clawbox(&vault).args(["get", copy]).assert().code(3);
+    }
+    let output = clawbox(&vault).args(["sync", "--resolve"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No sync conflicts"));
+}
+
+#[test]
+fn test_sync_push_pull_round_trip() {
+    // A fake home whose iCloud Drive folder stands in for the container
+    let home = TempDir::new().unwrap();
+    std::fs::create_dir_all(home.path().join("Library/Mobile Documents")).unwrap();
+    let container = home.path().join("Library/Mobile Documents/iCloud~com~harrishan~ClawBox/Documents");
+    let sync = |vault: &TempDir, args: &[&str]| {
+        let mut cmd = clawbox(vault);
+        cmd.env("HOME", home.path()).args(["--json", "sync"]).args(args);
+        cmd.output().unwrap()
+    };
+
+    let laptop = init_vault();
+    let output = sync(&laptop, &["--pull"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No remote vault found"));
+
+    // Each vault syncs through its own folder in the container
+    let status: serde_json::Value = serde_json::from_slice(&sync(&laptop, &["--status"]).stdout).unwrap();
+    let vault_id = status["vault_id"].as_str().unwrap().to_string();
+    let remote = container.join(&vault_id);
+    assert_eq!(status["location"].as_str(), remote.to_str());
+
+    // A second device with the same vault
+    let desktop = TempDir::new().unwrap();
+    std::fs::copy(laptop.path().join("vault.db"), desktop.path().join("vault.db")).unwrap();
+
+    clawbox(&laptop).args(["set", "github/token", "ghp_synced"]).assert().success();
+    // Another device is mid-sync
+    std::fs::create_dir_all(&remote).unwrap();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    std::fs::write(remote.join("sync.lock"), format!("desk\n{}\n", now)).unwrap();
+    let output = sync(&laptop, &["--push"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Another device (desk) is syncing"));
+    assert!(!remote.join("vault.encrypted").exists());
+    std::fs::remove_file(remote.join("sync.lock")).unwrap();
+
+    let output = sync(&laptop, &["--push"]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["result"], "pushed");
+
+    // Only ciphertext reaches the container, compressed first
+    let uploaded = std::fs::read(remote.join("vault.encrypted")).unwrap();
+    assert_eq!(result["uploaded_size"].as_u64(), Some(uploaded.len() as u64));
+    assert!(result["uploaded_size"].as_u64() < result["database_size"].as_u64());
+    assert!(!uploaded.starts_with(b"SQLite format 3"));
+    assert!(!uploaded.windows(10).any(|w| w == b"ghp_synced"));
+
+    let output = sync(&desktop, &[]);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["result"], "pulled");
+    clawbox(&desktop).args(["get", "github/token", "--quiet"]).assert().success().stdout("ghp_synced\n");
+    let result: serde_json::Value = serde_json::from_slice(&sync(&desktop, &[]).stdout).unwrap();
+    assert_eq!(result["result"], "up_to_date");
+
+    // Status comes from the state stored in the vault
+    let status: serde_json::Value = serde_json::from_slice(&sync(&desktop, &["--status"]).stdout).unwrap();
+    assert_eq!((status["local_version"].as_u64(), status["remote_version"].as_u64()), (Some(1), Some(1)));
+    assert!(status["last_sync"].is_string());
+
+    // The pull kept the desktop's earlier database, which can be swapped back
+    let backups: serde_json::Value = serde_json::from_slice(&sync(&desktop, &["--list-backups"]).stdout).unwrap();
+    let name = backups[0]["name"].as_str().unwrap().to_string();
+    assert_eq!(backups.as_array().unwrap().len(), 1);
+    clawbox(&desktop).args(["restore-backup", "vault-19700101T000000.db"]).assert().failure();
+    let output = clawbox(&desktop).args(["--json", "restore-backup", &name]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let restored: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(restored["replaced"].is_string());
+    clawbox(&desktop).args(["get", "github/token"]).assert().failure();
+
+    // A push without the audit log is marked so pulls keep their own
+    assert!(sync(&laptop, &["--push", "--no-audit"]).status.success());
+    let meta: serde_json::Value = serde_json::from_slice(&std::fs::read(remote.join("vault.meta")).unwrap()).unwrap();
+    assert_eq!(meta["audit_excluded"], true);
+    assert!(sync(&desktop, &["--pull"]).status.success());
+    clawbox(&desktop).args(["get", "github/token", "--quiet"]).assert().success().stdout("ghp_synced\n");
+}
+
+#[test]
+fn test_sync_dir_backend_merges_devices() {
+    let shared = TempDir::new().unwrap();
+    let config = shared.path().join("config.toml");
+    let folder = shared.path().join("Sync");
+    std::fs::create_dir(&folder).unwrap();
+    std::fs::write(&config, format!("[sync]\nbackend = \"dir\"\npath = {:?}\n", folder)).unwrap();
+    let sync = |vault: &TempDir| {
+        let output = clawbox(vault).env("CLAWBOX_CONFIG", &config).args(["--json", "sync"]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap()["result"].clone()
+    };
+
+    let laptop = init_vault();
+    let desktop = TempDir::new().unwrap();
+    std::fs::copy(laptop.path().join("vault.db"), desktop.path().join("vault.db")).unwrap();
+
+    clawbox(&laptop).args(["set", "from/laptop", "1"]).assert().success();
+    assert_eq!(sync(&laptop), "pushed");
+    assert_eq!(sync(&desktop), "pulled");
+
+    // Both devices add a secret before syncing
+    clawbox(&laptop).args(["set", "laptop/second", "2"]).assert().success();
+    clawbox(&desktop).args(["set", "from/desktop", "3"]).assert().success();
+    assert_eq!(sync(&desktop), "pushed");
+
+    // A dry run lists both sides' changes and fails, leaving the vault as is
+    let output = clawbox(&laptop).env("CLAWBOX_CONFIG", &config).args(["--json", "sync", "--diff"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", String::from_utf8_lossy(&output.stderr));
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let differences: Vec<_> = diff["differences"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| (d["path"].as_str().unwrap(), d["change"].as_str().unwrap(), d["newer"].as_str().unwrap()))
+        .collect();
+    assert_eq!(differences, [("from/desktop", "added", "remote"), ("laptop/second", "removed", "local")]);
+    clawbox(&laptop).args(["get", "from/desktop"]).assert().failure();
+
+    assert_eq!(sync(&laptop), "merged");
+    assert_eq!(sync(&desktop), "pulled");
+    let output = clawbox(&desktop).env("CLAWBOX_CONFIG", &config).args(["sync", "--diff"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No differences"));
+    for vault in [&laptop, &desktop] {
+        for (path, value) in [("from/laptop", "1\n"), ("laptop/second", "2\n"), ("from/desktop", "3\n")] {
+            clawbox(vault).args(["get", path, "--quiet"]).assert().success().stdout(value);
+        }
+    }
+
+    // Both test vaults run on this host, so they share one registry entry
+    let devices = |vault: &TempDir| {
+        let output = clawbox(vault).env("CLAWBOX_CONFIG", &config).args(["--json", "sync", "--devices"]).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout).unwrap()
+    };
+    let listed = devices(&laptop);
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0]["last_push"].is_string() && listed[0]["last_pull"].is_string());
+    let device = listed[0]["device_id"].as_str().unwrap().to_string();
+    let forget = |id: &str| clawbox(&desktop).env("CLAWBOX_CONFIG", &config).args(["sync", "--forget-device", id]).output().unwrap();
+    assert!(!forget("old-mac").status.success());
+    assert!(forget(&device).status.success());
+    assert!(devices(&desktop).is_empty());
+
+    // Only the vault file, its version and the registry are left behind
+    let mut files: Vec<_> = std::fs::read_dir(&folder)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["devices.json", "vault.encrypted", "vault.meta"]);
+    clawbox(&laptop).env("CLAWBOX_CONFIG", &config).args(["sync", "--push"]).assert().failure();
+}
+
+#[test]
+fn test_sync_watch() {
+    let shared = TempDir::new().unwrap();
+    let config = shared.path().join("config.toml");
+    let folder = shared.path().join("Sync");
+    std::fs::create_dir(&folder).unwrap();
+    std::fs::write(&config, format!("[sync]\nbackend = \"dir\"\npath = {:?}\n", folder)).unwrap();
+    let laptop = init_vault();
+    let desktop = TempDir::new().unwrap();
+    std::fs::copy(laptop.path().join("vault.db"), desktop.path().join("vault.db")).unwrap();
+    let wait_for = |what: &str, done: &dyn Fn() -> bool| {
+        let started = std::time::Instant::now();
+        while !done() {
+            assert!(started.elapsed() < std::time::Duration::from_secs(20), "timed out waiting for {}", what);
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+    };
+
+    let watcher = std::process::Command::new(assert_cmd::cargo::cargo_bin("clawbox"))
+        .arg("--vault")
+        .arg(laptop.path())
+        .args(["--json", "sync", "--watch"])
+        .env("CLAWBOX_PASSWORD", PASSWORD)
+        .env("CLAWBOX_CONFIG", &config)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pidfile = laptop.path().join("sync-watch.pid");
+    wait_for("the pidfile", &|| pidfile.exists());
+    let output = clawbox(&laptop).env("CLAWBOX_CONFIG", &config).args(["sync", "--watch"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Already watching"));
+
+    // A local change is pushed, a remote one pulled
+    clawbox(&laptop).args(["set", "from/laptop", "1"]).assert().success();
+    wait_for("the push", &|| folder.join("vault.meta").exists());
+    clawbox(&desktop).env("CLAWBOX_CONFIG", &config).arg("sync").assert().success();
+    clawbox(&desktop).args(["set", "from/desktop", "2"]).assert().success();
+    clawbox(&desktop).env("CLAWBOX_CONFIG", &config).arg("sync").assert().success();
+    wait_for("the pull", &|| {
+        clawbox(&laptop).args(["get", "from/desktop", "--quiet"]).output().unwrap().stdout == b"2\n"
+    });
+
+    // SIGTERM stops it cleanly
+    let status = std::process::Command::new("kill").arg(watcher.id().to_string()).status().unwrap();
+    assert!(status.success());
+    let output = watcher.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(!pidfile.exists());
+    let results: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["result"].as_str().unwrap().to_string())
+        // Reads write audit entries, which may set off a sync with nothing to do
+        .filter(|result| result != "up_to_date")
+        .collect();
+    assert_eq!(results, ["pushed", "pulled"]);
+}
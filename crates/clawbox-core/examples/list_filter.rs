@@ -0,0 +1,62 @@
+//! Time listing the secrets with one tag or at one access level through
+//! the indexes, against listing everything and filtering afterwards
+//!
+//! ```sh
+//! cargo run --release -p clawbox-core --example list_filter [secrets]
+//! ```
+
+use clawbox_core::storage::{SecretStore, SqliteStore};
+use clawbox_core::{AccessLevel, ListFilter, SecretInfo};
+use std::time::Instant;
+
+fn main() -> clawbox_core::Result<()> {
+    let count: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(20_000);
+    let dir = tempfile::TempDir::new()?;
+    let mut store = SqliteStore::open(&dir.path().join("vault.db"))?;
+
+    // One secret in a hundred has the tag, one in fifty is Critical
+    let infos: Vec<(String, SecretInfo)> = (0..count)
+        .map(|i| {
+            let info = SecretInfo {
+                path: String::new(),
+                access: if i % 50 == 0 { AccessLevel::Critical } else { AccessLevel::Normal },
+                tags: if i % 100 == 0 { vec!["rare".to_string(), "team".to_string()] } else { vec!["team".to_string()] },
+                note: None,
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+                expires_at: None,
+                size: 64,
+            };
+            (format!("app/service{}/key", i), info)
+        })
+        .collect();
+    let value = [0u8; 64];
+    let items: Vec<(&str, &[u8], &SecretInfo)> =
+        infos.iter().map(|(path, info)| (path.as_str(), &value[..], info)).collect();
+    store.set_many(&items)?;
+
+    let tag = ListFilter { tags: vec!["rare".to_string()], ..Default::default() };
+    let access = ListFilter { access: vec![AccessLevel::Critical], ..Default::default() };
+    for (name, filter) in [("--tag rare", tag), ("--access critical", access)] {
+        let start = Instant::now();
+        let mut found = 0;
+        store.for_each_secret(&filter, &mut |_| {
+            found += 1;
+            Ok(())
+        })?;
+        let indexed = start.elapsed();
+
+        let start = Instant::now();
+        let scanned = store
+            .list(None)?
+            .into_iter()
+            .filter(|info| filter.tags.iter().all(|tag| info.tags.contains(tag)))
+            .filter(|info| filter.access.is_empty() || filter.access.contains(&info.access))
+            .count();
+        let scan = start.elapsed();
+
+        assert_eq!(found, scanned);
+        println!("{:<18} {:>5} of {} secrets: {:>9.1?} indexed, {:>9.1?} listing all", name, found, count, indexed, scan);
+    }
+    Ok(())
+}
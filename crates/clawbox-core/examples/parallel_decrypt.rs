@@ -0,0 +1,37 @@
+//! Compare `get_prefix` decrypting on one thread with `set_decrypt_jobs`
+//!
+//! ```sh
+//! cargo run --release -p clawbox-core --features parallel --example parallel_decrypt [secrets] [jobs]
+//! ```
+
+use clawbox_core::ClawBox;
+use std::time::Instant;
+
+fn main() -> clawbox_core::Result<()> {
+    let count: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(20_000);
+    let jobs: usize = std::env::args().nth(2).and_then(|n| n.parse().ok()).unwrap_or(0);
+    let dir = tempfile::TempDir::new()?;
+    let mut vault = ClawBox::open(dir.path())?;
+    vault.init("benchmark password")?;
+
+    let values: Vec<(String, String)> = (0..count).map(|i| (format!("app/service{}/key", i), "v".repeat(256))).collect();
+    let items: Vec<(&str, &[u8], _)> =
+        values.iter().map(|(path, value)| (path.as_str(), value.as_bytes(), Default::default())).collect();
+    vault.set_many(&items)?;
+
+    let start = Instant::now();
+    let serial = vault.get_prefix("app/")?;
+    let serial_time = start.elapsed();
+
+    vault.set_decrypt_jobs(jobs);
+    let start = Instant::now();
+    let parallel = vault.get_prefix("app/")?;
+    let parallel_time = start.elapsed();
+
+    assert!(serial.iter().zip(&parallel).all(|(a, b)| a.0 == b.0 && a.1.expose() == b.1.expose()));
+    println!("{} secrets, jobs = {}", parallel.len(), jobs);
+    println!("serial:   {:>10.1?}", serial_time);
+    println!("parallel: {:>10.1?}", parallel_time);
+    println!("speedup:  {:>9.1}x", serial_time.as_secs_f64() / parallel_time.as_secs_f64());
+    Ok(())
+}
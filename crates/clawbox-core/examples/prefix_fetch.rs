@@ -0,0 +1,38 @@
+//! Compare `get_prefix` with listing a subtree and reading it path by path
+//!
+//! ```sh
+//! cargo run --release -p clawbox-core --example prefix_fetch [secrets]
+//! ```
+
+use clawbox_core::ClawBox;
+use std::time::Instant;
+
+fn main() -> clawbox_core::Result<()> {
+    let count: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(5000);
+    let dir = tempfile::TempDir::new()?;
+    let mut vault = ClawBox::open(dir.path())?;
+    vault.init("benchmark password")?;
+
+    let values: Vec<(String, String)> = (0..count).map(|i| (format!("app/service{}/key", i), format!("value-{}", i))).collect();
+    let items: Vec<(&str, &[u8], _)> =
+        values.iter().map(|(path, value)| (path.as_str(), value.as_bytes(), Default::default())).collect();
+    vault.set_many(&items)?;
+
+    let start = Instant::now();
+    let mut naive = 0;
+    for info in vault.list(Some("app/*"))? {
+        naive += vault.get_secret(&info.path)?.is_some() as usize;
+    }
+    let naive_time = start.elapsed();
+
+    let start = Instant::now();
+    let fetched = vault.get_prefix("app/")?.len();
+    let prefix_time = start.elapsed();
+
+    assert_eq!(naive, fetched);
+    println!("{} secrets", fetched);
+    println!("list + get_secret: {:>10.1?}", naive_time);
+    println!("get_prefix:        {:>10.1?}", prefix_time);
+    println!("speedup:           {:>9.1}x", naive_time.as_secs_f64() / prefix_time.as_secs_f64());
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! Time sealing and opening stored values with one buffer, against the
+//! copies the stored format used to take: the nonce and ciphertext built
+//! apart and joined on the way in, sliced back into two `Vec`s on the way out
+//!
+//! ```sh
+//! cargo run --release -p clawbox-core --example seal_copies [values] [bytes]
+//! ```
+
+use clawbox_core::crypto::{self, DerivedKey, EncryptedData};
+use std::time::{Duration, Instant};
+
+/// Nonce and ciphertext in separate buffers, joined
+fn seal_copying(plaintext: &[u8], key: &DerivedKey) -> clawbox_core::Result<Vec<u8>> {
+    let encrypted = crypto::encrypt(plaintext, key)?;
+    let mut data = encrypted.nonce().to_vec();
+    data.extend_from_slice(encrypted.ciphertext());
+    Ok(data)
+}
+
+/// Both parts copied out before decrypting
+fn open_copying(data: &[u8], key: &DerivedKey) -> clawbox_core::Result<Vec<u8>> {
+    let (nonce, ciphertext) = (data[..12].to_vec(), data[12..].to_vec());
+    crypto::decrypt(&EncryptedData::from_parts(&nonce, &ciphertext), key)
+}
+
+fn time<T>(f: impl FnOnce() -> clawbox_core::Result<T>) -> clawbox_core::Result<(T, Duration)> {
+    let start = Instant::now();
+    let result = f()?;
+    Ok((result, start.elapsed()))
+}
+
+fn main() -> clawbox_core::Result<()> {
+    let mut args = std::env::args().skip(1).map(|n| n.parse().ok());
+    let count: usize = args.next().flatten().unwrap_or(10_000);
+    let size: usize = args.next().flatten().unwrap_or(256);
+    let key = DerivedKey::try_from_slice(&[7; 32])?;
+    let values: Vec<Vec<u8>> = (0..count).map(|i| vec![i as u8; size]).collect();
+
+    let (copied, seal_before) = time(|| values.iter().map(|value| seal_copying(value, &key)).collect::<Result<Vec<_>, _>>())?;
+    let (sealed, seal_after) = time(|| values.iter().map(|value| crypto::seal_value(value, &key)).collect::<Result<Vec<_>, _>>())?;
+    let (_, open_before) = time(|| copied.iter().map(|data| open_copying(data, &key)).collect::<Result<Vec<_>, _>>())?;
+    let (_, open_after) = time(|| sealed.iter().map(|data| crypto::open_value(data, &key)).collect::<Result<Vec<_>, _>>())?;
+
+    println!("{} values of {} bytes", count, size);
+    println!("seal, copying:  {:>10.1?}", seal_before);
+    println!("seal_value:     {:>10.1?}", seal_after);
+    println!("open, copying:  {:>10.1?}", open_before);
+    println!("open_value:     {:>10.1?}", open_after);
+    Ok(())
+}
@@ -0,0 +1,48 @@
+//! Time the statements `SqliteStore` runs for every secret, without the
+//! encryption and audit logging `ClawBox` adds: a `set_many` batch, then a
+//! `get`, `get_info` and `get_meta` per path
+//!
+//! ```sh
+//! cargo run --release -p clawbox-core --example statement_cache [secrets]
+//! ```
+
+use clawbox_core::storage::{SecretStore, SqliteStore};
+use clawbox_core::{AccessLevel, SecretInfo};
+use std::time::Instant;
+
+fn main() -> clawbox_core::Result<()> {
+    let count: usize = std::env::args().nth(1).and_then(|n| n.parse().ok()).unwrap_or(1000);
+    let dir = tempfile::TempDir::new()?;
+    let mut store = SqliteStore::open(&dir.path().join("vault.db"))?;
+
+    let values: Vec<(String, Vec<u8>)> = (0..count).map(|i| (format!("app/service{}/key", i), vec![0; 64])).collect();
+    let info = SecretInfo {
+        path: String::new(),
+        access: AccessLevel::Normal,
+        tags: vec![],
+        note: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        expires_at: None,
+        size: 64,
+    };
+    let items: Vec<(&str, &[u8], &SecretInfo)> =
+        values.iter().map(|(path, value)| (path.as_str(), value.as_slice(), &info)).collect();
+
+    let start = Instant::now();
+    store.set_many(&items)?;
+    let write_time = start.elapsed();
+
+    let start = Instant::now();
+    for (path, _) in &values {
+        store.get(path)?;
+        store.get_info(path)?;
+        store.get_meta("schema_version")?;
+    }
+    let read_time = start.elapsed();
+
+    println!("{} secrets", count);
+    println!("set_many:             {:>10.1?}", write_time);
+    println!("get+get_info+get_meta: {:>9.1?}", read_time);
+    Ok(())
+}
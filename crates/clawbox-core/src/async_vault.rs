@@ -0,0 +1,229 @@
+//! Async access to a vault, for tokio services
+//!
+//! `AsyncClawBox` shares one `ClawBox` behind a mutex and runs every call on
+//! tokio's blocking pool with `spawn_blocking`, so the runtime's workers
+//! never wait on SQLite, AES or Argon2. Calls on one vault still run one at
+//! a time; `unlock_async` is the exception, deriving the key without holding
+//! the vault so that other calls can go on (and fail with
+//! `Error::VaultLocked`) meanwhile.
+//!
+//! # Cancellation
+//!
+//! Dropping a future returned here does not stop the operation: once
+//! started, it runs to completion on the blocking pool and only its result
+//! is lost. A dropped `set_async` may still write the secret, and a dropped
+//! `unlock_async` may still unlock the vault; call `lock_async` to undo the
+//! latter.
+
+use crate::{crypto, ClawBox, DeleteMode, Error, OpenOptions, Result, SecretInfo, SecretValue, SetOptions};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use zeroize::Zeroizing;
+
+/// A vault shared between tasks; clones refer to the same vault
+#[derive(Clone)]
+pub struct AsyncClawBox {
+    vault: Arc<Mutex<ClawBox>>,
+}
+
+impl AsyncClawBox {
+    /// Share a vault that is already open
+    pub fn new(vault: ClawBox) -> Self {
+        Self { vault: Arc::new(Mutex::new(vault)) }
+    }
+
+    /// Open or create a vault at `path`; see `ClawBox::open`
+    pub async fn open_async(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::open_with_async(path, OpenOptions::default()).await
+    }
+
+    /// Open or create a vault at `path` with `opts`; see `ClawBox::open_with`
+    pub async fn open_with_async(path: impl Into<PathBuf>, opts: OpenOptions) -> Result<Self> {
+        let path = path.into();
+        let vault = blocking(move || ClawBox::open_with(path, &opts)).await?;
+        Ok(Self::new(vault))
+    }
+
+    /// Run `f` with the vault on the blocking pool, for anything without an
+    /// async method of its own
+    pub async fn call_async<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ClawBox) -> Result<T> + Send + 'static,
+    {
+        let vault = self.vault.clone();
+        blocking(move || f(&mut vault.lock().unwrap_or_else(PoisonError::into_inner))).await
+    }
+
+    /// Unlock with the master (or duress) password; see `ClawBox::unlock`
+    ///
+    /// The slow key derivation runs without holding the vault, as with
+    /// `ClawBox::unlock_derived_keys`, so other calls aren't held up by it.
+    pub async fn unlock_async(&self, password: &str) -> Result<()> {
+        let password = Zeroizing::new(password.to_string());
+        let (salt, duress_salt) = self
+            .call_async(|vault| {
+                // Refuse now rather than after deriving the key
+                vault.check_unlock_allowed()?;
+                Ok((vault.key_salt()?, vault.duress_key_salt()?))
+            })
+            .await?;
+        let (key, duress_key) = blocking(move || {
+            let key = crypto::derive_key(&password, &salt)?;
+            let duress_key = match &duress_salt {
+                Some(salt) => Some(crypto::derive_key(&password, salt)?),
+                None => None,
+            };
+            Ok((key, duress_key))
+        })
+        .await?;
+        self.call_async(move |vault| vault.unlock_derived_keys(key, duress_key)).await
+    }
+
+    /// Lock the vault; see `ClawBox::lock`
+    pub async fn lock_async(&self) -> Result<()> {
+        self.call_async(|vault| {
+            vault.lock();
+            Ok(())
+        })
+        .await
+    }
+
+    /// Whether the vault is unlocked; see `ClawBox::is_unlocked`
+    pub async fn is_unlocked_async(&self) -> Result<bool> {
+        self.call_async(|vault| Ok(vault.is_unlocked())).await
+    }
+
+    /// Get a secret value; see `ClawBox::get_secret`
+    pub async fn get_async(&self, path: &str) -> Result<Option<SecretValue>> {
+        let path = path.to_string();
+        self.call_async(move |vault| vault.get_secret(&path)).await
+    }
+
+    /// Get a secret value as bytes; see `ClawBox::get_bytes`
+    pub async fn get_bytes_async(&self, path: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let path = path.to_string();
+        self.call_async(move |vault| vault.get_bytes(&path)).await
+    }
+
+    /// Every secret below `prefix`; see `ClawBox::get_prefix`
+    pub async fn get_prefix_async(&self, prefix: &str) -> Result<Vec<(String, SecretValue)>> {
+        let prefix = prefix.to_string();
+        self.call_async(move |vault| vault.get_prefix(&prefix)).await
+    }
+
+    /// Set a secret value; see `ClawBox::set`
+    pub async fn set_async(&self, path: &str, value: &str, opts: SetOptions) -> Result<()> {
+        let path = path.to_string();
+        let value = Zeroizing::new(value.to_string());
+        self.call_async(move |vault| vault.set(&path, &value, opts)).await
+    }
+
+    /// Delete a secret; see `ClawBox::delete`
+    pub async fn delete_async(&self, path: &str, mode: DeleteMode) -> Result<bool> {
+        let path = path.to_string();
+        self.call_async(move |vault| vault.delete(&path, mode)).await
+    }
+
+    /// Metadata of the secrets matching `pattern`; see `ClawBox::list`
+    pub async fn list_async(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        let pattern = pattern.map(str::to_string);
+        self.call_async(move |vault| vault.list(pattern.as_deref())).await
+    }
+}
+
+/// Run `f` on tokio's blocking pool; a panic in it becomes an error
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| Err(Error::Other(format!("Vault task failed: {}", e))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn unlocked_vault() -> (TempDir, AsyncClawBox) {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = AsyncClawBox::open_async(temp_dir.path()).await.unwrap();
+        vault.call_async(|vault| vault.init("test-password")).await.unwrap();
+        (temp_dir, vault)
+    }
+
+    #[tokio::test]
+    async fn test_async_round_trip() {
+        let (_temp_dir, vault) = unlocked_vault().await;
+        vault.set_async("db/url", "postgres://", Default::default()).await.unwrap();
+        vault.set_async("db/user", "app", Default::default()).await.unwrap();
+
+        assert_eq!(vault.get_async("db/url").await.unwrap().as_deref(), Some("postgres://"));
+        assert_eq!(vault.get_bytes_async("db/user").await.unwrap().as_deref().map(Vec::as_slice), Some(&b"app"[..]));
+        assert_eq!(vault.get_prefix_async("db").await.unwrap().len(), 2);
+        assert_eq!(vault.list_async(Some("db/*")).await.unwrap().len(), 2);
+        assert!(vault.delete_async("db/user", DeleteMode::Soft).await.unwrap());
+        assert!(vault.get_async("db/user").await.unwrap().is_none());
+
+        vault.lock_async().await.unwrap();
+        assert!(matches!(vault.get_async("db/url").await, Err(Error::VaultLocked)));
+        assert!(matches!(vault.unlock_async("wrong").await, Err(Error::InvalidPassword)));
+        vault.unlock_async("test-password").await.unwrap();
+        assert!(vault.is_unlocked_async().await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_gets_during_unlock() {
+        let (_temp_dir, vault) = unlocked_vault().await;
+        vault.set_async("db/url", "postgres://", Default::default()).await.unwrap();
+        vault.lock_async().await.unwrap();
+
+        // Reads keep being answered while the key is derived
+        let unlock = tokio::spawn({
+            let vault = vault.clone();
+            async move { vault.unlock_async("test-password").await }
+        });
+        let mut answered_while_unlocking = 0;
+        while !unlock.is_finished() {
+            let reads: Vec<_> = (0..4)
+                .map(|_| {
+                    let vault = vault.clone();
+                    tokio::spawn(async move { vault.get_async("db/url").await })
+                })
+                .collect();
+            for read in reads {
+                match read.await.unwrap() {
+                    Err(Error::VaultLocked) => answered_while_unlocking += 1,
+                    Ok(value) => assert_eq!(value.as_deref(), Some("postgres://")),
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+        unlock.await.unwrap().unwrap();
+        assert!(answered_while_unlocking > 0);
+
+        let reads: Vec<_> = (0..16)
+            .map(|_| {
+                let vault = vault.clone();
+                tokio::spawn(async move { vault.get_async("db/url").await })
+            })
+            .collect();
+        for read in reads {
+            assert_eq!(read.await.unwrap().unwrap().as_deref(), Some("postgres://"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_call_runs_to_completion() {
+        let (_temp_dir, vault) = unlocked_vault().await;
+        let set = vault.set_async("db/url", "postgres://", Default::default());
+        // Polled once, so the write has been handed to the blocking pool
+        assert!(tokio::time::timeout(std::time::Duration::ZERO, set).await.is_err());
+        for _ in 0..500 {
+            if vault.get_async("db/url").await.unwrap().is_some() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("the dropped write never happened");
+    }
+}
@@ -3,8 +3,9 @@
 //! Provides tamper-evident logging of all vault operations.
 
 use crate::{Actor, Result};
-use chrono::{DateTime, Utc};
-use rusqlite::{Connection, params};
+use crate::storage::{keyspace_meta_key, AUDIT_ANCHOR_KEY, MAIN_KEYSPACE};
+use chrono::{DateTime, SubsecRound, Utc};
+use rusqlite::{Connection, OptionalExtension, params};
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 
@@ -18,6 +19,21 @@ pub enum Action {
     Unlock,
     Lock,
     Init,
+    ChangePassword,
+    ClipboardClear,
+    Rollback,
+    Prune,
+    Rename,
+    Copy,
+    SyncConflict,
+    /// A sync that moved data, or failed
+    Sync,
+    /// The master password was checked again to read a secret
+    Reauth,
+    /// A secret was hard-deleted along with its history
+    Purge,
+    CreateToken,
+    RevokeToken,
 }
 
 impl Action {
@@ -30,9 +46,22 @@ impl Action {
             Action::Unlock => "unlock",
             Action::Lock => "lock",
             Action::Init => "init",
+            Action::ChangePassword => "change_password",
+            Action::ClipboardClear => "clipboard_clear",
+            Action::Rollback => "rollback",
+            Action::Prune => "prune",
+            Action::Rename => "rename",
+            Action::Copy => "copy",
+            Action::SyncConflict => "sync_conflict",
+            Action::Sync => "sync",
+            Action::Reauth => "reauth",
+            Action::Purge => "purge",
+            Action::CreateToken => "create_token",
+            Action::RevokeToken => "revoke_token",
         }
     }
     
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Self> {
         match s {
             "read" => Some(Action::Read),
@@ -42,6 +71,18 @@ impl Action {
             "unlock" => Some(Action::Unlock),
             "lock" => Some(Action::Lock),
             "init" => Some(Action::Init),
+            "change_password" => Some(Action::ChangePassword),
+            "clipboard_clear" => Some(Action::ClipboardClear),
+            "rollback" => Some(Action::Rollback),
+            "prune" => Some(Action::Prune),
+            "rename" => Some(Action::Rename),
+            "copy" => Some(Action::Copy),
+            "sync_conflict" => Some(Action::SyncConflict),
+            "sync" => Some(Action::Sync),
+            "reauth" => Some(Action::Reauth),
+            "purge" => Some(Action::Purge),
+            "create_token" => Some(Action::CreateToken),
+            "revoke_token" => Some(Action::RevokeToken),
             _ => None,
         }
     }
@@ -68,6 +109,9 @@ pub struct AuditEntry {
     pub source: Source,
     pub hash: Option<String>,
     pub prev_hash: Option<String>,
+    /// Added after the fact, such as how hooks went; not covered by the hash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
 }
 
 impl AuditEntry {
@@ -75,7 +119,8 @@ impl AuditEntry {
     pub fn new(action: Action, key_path: &str, success: bool) -> Self {
         Self {
             id: uuid::Uuid::new_v4().to_string(),
-            timestamp: Utc::now(),
+            // Stored with second precision; the hash must match what is read back
+            timestamp: Utc::now().trunc_subsecs(0),
             actor: ActorInfo::default(),
             action,
             key_path: key_path.to_string(),
@@ -86,6 +131,7 @@ impl AuditEntry {
                 .unwrap_or_default() },
             hash: None,
             prev_hash: None,
+            metadata: None,
         }
     }
     
@@ -100,6 +146,12 @@ impl AuditEntry {
         self.actor = actor;
         self
     }
+
+    /// Set metadata
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
     
     /// Compute hash for integrity
     fn compute_hash(&self, prev_hash: Option<&str>) -> String {
@@ -139,10 +191,20 @@ impl From<&Actor> for ActorInfo {
                 actor_type: "app".to_string(),
                 identifier: name.clone(),
             },
+            Actor::System { component } => ActorInfo {
+                actor_type: "system".to_string(),
+                identifier: component.clone(),
+            },
         }
     }
 }
 
+impl From<Actor> for ActorInfo {
+    fn from(actor: Actor) -> Self {
+        ActorInfo::from(&actor)
+    }
+}
+
 impl ActorInfo {
     pub fn human() -> Self {
         Self {
@@ -157,6 +219,51 @@ impl ActorInfo {
             identifier: agent.to_string(),
         }
     }
+
+    pub fn app(name: &str) -> Self {
+        Self {
+            actor_type: "app".to_string(),
+            identifier: name.to_string(),
+        }
+    }
+
+    /// Whoever holds the access token `name`
+    pub fn token(name: &str) -> Self {
+        Self {
+            actor_type: "token".to_string(),
+            identifier: name.to_string(),
+        }
+    }
+
+    pub fn system(component: &str) -> Self {
+        Self {
+            actor_type: "system".to_string(),
+            identifier: component.to_string(),
+        }
+    }
+
+    /// Whether access levels restrict this actor's reads
+    pub fn is_restricted(&self) -> bool {
+        !matches!(self.actor_type.as_str(), "human" | "system")
+    }
+}
+
+impl std::str::FromStr for ActorInfo {
+    type Err = crate::Error;
+
+    /// `human`, `human:<name>`, `ai:<agent>` or `app:<name>`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "human" => Ok(Self::human()),
+            Some(("human", name)) if !name.is_empty() => Ok(Self { actor_type: "human".to_string(), identifier: name.to_string() }),
+            Some(("ai", agent)) if !agent.is_empty() => Ok(Self::ai(agent)),
+            Some(("app", name)) if !name.is_empty() => Ok(Self::app(name)),
+            _ => Err(crate::Error::InvalidArgument(format!(
+                "invalid actor {:?}; expected human, human:<name>, ai:<agent> or app:<name>",
+                s
+            ))),
+        }
+    }
 }
 
 /// Audit filter for queries
@@ -170,24 +277,43 @@ pub struct AuditFilter {
     pub limit: Option<usize>,
 }
 
+// Fixed statements, prepared once per connection with `prepare_cached`;
+// `AuditLogger::query` builds its SQL from the filter and prepares it fresh
+const SELECT_LAST_HASH: &str =
+    "SELECT hash FROM audit_log WHERE keyspace = ? ORDER BY timestamp DESC, rowid DESC LIMIT 1";
+const INSERT_ENTRY: &str = "INSERT INTO audit_log \
+                            (id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash, keyspace, metadata) \
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+/// Rows per `INSERT` in `AuditLogger::log_many`, keeping the 12 parameters
+/// a row under SQLite's oldest limit of 999
+const INSERT_BATCH: usize = 80;
+const COUNT_ENTRIES: &str = "SELECT COUNT(*) FROM audit_log WHERE keyspace = ?";
+
 /// Audit logger with SQLite backend
+///
+/// Each keyspace (see `SqliteStore::keyspace`) has its own log with its own
+/// hash chain.
 pub struct AuditLogger<'a> {
     conn: &'a Connection,
+    keyspace: u8,
 }
 
 impl<'a> AuditLogger<'a> {
-    /// Create a new audit logger
+    /// Create a new audit logger for the main keyspace
     pub fn new(conn: &'a Connection) -> Self {
-        Self { conn }
+        Self::for_keyspace(conn, MAIN_KEYSPACE)
+    }
+
+    /// Create a new audit logger for `keyspace`
+    pub fn for_keyspace(conn: &'a Connection, keyspace: u8) -> Self {
+        Self { conn, keyspace }
     }
     
     /// Get the last hash for chain integrity
-    fn get_last_hash(&self) -> Result<Option<String>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT hash FROM audit_log ORDER BY timestamp DESC LIMIT 1"
-        )?;
+    pub fn get_last_hash(&self) -> Result<Option<String>> {
+        let mut stmt = self.conn.prepare_cached(SELECT_LAST_HASH)?;
         
-        let result = stmt.query_row([], |row| row.get(0));
+        let result = stmt.query_row([self.keyspace], |row| row.get(0));
         match result {
             Ok(hash) => Ok(Some(hash)),
             Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
@@ -197,6 +323,13 @@ impl<'a> AuditLogger<'a> {
 
     /// Log an audit entry
     pub fn log(&self, mut entry: AuditEntry) -> Result<()> {
+        // Hold the write lock from reading the last hash to appending, so
+        // that another connection can't append after the same entry
+        let tx = match self.conn.is_autocommit() {
+            true => Some(rusqlite::Transaction::new_unchecked(self.conn, rusqlite::TransactionBehavior::Immediate)?),
+            false => None,
+        };
+
         // Get previous hash for chain
         let prev_hash = self.get_last_hash()?;
         entry.prev_hash = prev_hash.clone();
@@ -205,32 +338,74 @@ impl<'a> AuditLogger<'a> {
         let actor_json = serde_json::to_string(&entry.actor)?;
         let source_json = serde_json::to_string(&entry.source)?;
         
-        self.conn.execute(
-            r#"INSERT INTO audit_log 
-               (id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash)
-               VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
-            params![
-                entry.id,
-                entry.timestamp.timestamp(),
-                actor_json,
-                entry.action.as_str(),
-                entry.key_path,
-                entry.success,
-                entry.error_message,
-                source_json,
-                entry.hash,
-                entry.prev_hash,
-            ],
-        )?;
-        
+        self.conn.prepare_cached(INSERT_ENTRY)?.execute(params![
+            entry.id,
+            entry.timestamp.timestamp(),
+            actor_json,
+            entry.action.as_str(),
+            entry.key_path,
+            entry.success,
+            entry.error_message,
+            source_json,
+            entry.hash,
+            entry.prev_hash,
+            self.keyspace,
+            entry.metadata.as_ref().map(|metadata| metadata.to_string()),
+        ])?;
+        if let Some(tx) = tx {
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    /// Log several audit entries, in order, with a few multi-row `INSERT`s
+    ///
+    /// The chain is hashed in memory from the last stored hash, so the
+    /// result is the same as logging each in turn.
+    pub fn log_many(&self, entries: &[AuditEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let tx = match self.conn.is_autocommit() {
+            true => Some(rusqlite::Transaction::new_unchecked(self.conn, rusqlite::TransactionBehavior::Immediate)?),
+            false => None,
+        };
+
+        let mut prev_hash = self.get_last_hash()?;
+        for batch in entries.chunks(INSERT_BATCH) {
+            let mut values: Vec<rusqlite::types::Value> = Vec::with_capacity(batch.len() * 12);
+            for entry in batch {
+                let hash = entry.compute_hash(prev_hash.as_deref());
+                values.extend([
+                    entry.id.clone().into(),
+                    entry.timestamp.timestamp().into(),
+                    serde_json::to_string(&entry.actor)?.into(),
+                    entry.action.as_str().to_string().into(),
+                    entry.key_path.clone().into(),
+                    entry.success.into(),
+                    entry.error_message.clone().into(),
+                    serde_json::to_string(&entry.source)?.into(),
+                    hash.clone().into(),
+                    prev_hash.replace(hash).into(),
+                    i64::from(self.keyspace).into(),
+                    entry.metadata.as_ref().map(|metadata| metadata.to_string()).into(),
+                ]);
+            }
+            let sql = format!("{}{}", INSERT_ENTRY, ", (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)".repeat(batch.len() - 1));
+            // All but the last batch share one statement
+            self.conn.prepare_cached(&sql)?.execute(rusqlite::params_from_iter(values))?;
+        }
+        if let Some(tx) = tx {
+            tx.commit()?;
+        }
         Ok(())
     }
 
     /// Query audit log
     pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
         let mut sql = String::from(
-            "SELECT id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash 
-             FROM audit_log WHERE 1=1"
+            "SELECT id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash, metadata
+             FROM audit_log WHERE keyspace = ?"
         );
         
         if filter.key_path.is_some() {
@@ -249,7 +424,7 @@ impl<'a> AuditLogger<'a> {
             sql.push_str(" AND action = ?");
         }
         
-        sql.push_str(" ORDER BY timestamp DESC");
+        sql.push_str(" ORDER BY timestamp DESC, rowid DESC");
         
         if let Some(limit) = filter.limit {
             sql.push_str(&format!(" LIMIT {}", limit));
@@ -258,7 +433,7 @@ impl<'a> AuditLogger<'a> {
         let mut stmt = self.conn.prepare(&sql)?;
         
         // Build params dynamically
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(self.keyspace)];
         
         if let Some(ref path) = filter.key_path {
             params.push(Box::new(format!("%{}%", path)));
@@ -286,10 +461,11 @@ impl<'a> AuditLogger<'a> {
             let actor_json: String = row.get(2)?;
             let action_str: String = row.get(3)?;
             let source_json: String = row.get(7)?;
+            let metadata: Option<String> = row.get(10)?;
             
             entries.push(AuditEntry {
                 id: row.get(0)?,
-                timestamp: DateTime::from_timestamp(ts, 0).unwrap_or_default().into(),
+                timestamp: DateTime::from_timestamp(ts, 0).unwrap_or_default(),
                 actor: serde_json::from_str(&actor_json).unwrap_or_default(),
                 action: Action::from_str(&action_str).unwrap_or(Action::Read),
                 key_path: row.get(4)?,
@@ -298,12 +474,19 @@ impl<'a> AuditLogger<'a> {
                 source: serde_json::from_str(&source_json).unwrap_or(Source::CLI { pwd: String::new() }),
                 hash: row.get(8)?,
                 prev_hash: row.get(9)?,
+                metadata: metadata.and_then(|metadata| serde_json::from_str(&metadata).ok()),
             });
         }
         
         Ok(entries)
     }
     
+    /// Total number of audit entries
+    pub fn count(&self) -> Result<usize> {
+        let count: i64 = self.conn.prepare_cached(COUNT_ENTRIES)?.query_row([self.keyspace], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
     /// Verify audit log integrity
     pub fn verify_integrity(&self) -> Result<bool> {
         let entries = self.query(&AuditFilter::default())?;
@@ -311,7 +494,16 @@ impl<'a> AuditLogger<'a> {
         // Entries are in DESC order, reverse for verification
         let entries: Vec<_> = entries.into_iter().rev().collect();
         
-        let mut prev_hash: Option<String> = None;
+        // After pruning, the chain starts from the last removed entry's hash
+        let anchor: Option<Vec<u8>> = self
+            .conn
+            .query_row(
+                "SELECT value FROM vault_meta WHERE key = ?",
+                [keyspace_meta_key(self.keyspace, AUDIT_ANCHOR_KEY)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let mut prev_hash = anchor.and_then(|a| String::from_utf8(a).ok());
         for entry in entries {
             let computed = entry.compute_hash(prev_hash.as_deref());
             if entry.hash.as_ref() != Some(&computed) {
@@ -0,0 +1,192 @@
+//! Local backups of the vault database
+//!
+//! Copies taken before the database is replaced (e.g. by a sync pull) go to
+//! `backups/vault-<timestamp>.db` in the vault directory, readable only by
+//! the owner. Only the newest few are kept, so a run of bad pulls can't
+//! push every good copy out but the directory doesn't grow forever either.
+
+use crate::storage::SqliteStore;
+use crate::{Error, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory in the vault directory that holds backups
+pub const BACKUP_DIR: &str = "backups";
+/// Backups kept unless configured otherwise
+pub const DEFAULT_KEEP: usize = 10;
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// A backup of the vault database
+#[derive(Debug, Clone, Serialize)]
+pub struct Backup {
+    /// File name in `BACKUP_DIR`, e.g. `vault-20240601T120304.db`
+    pub name: String,
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Copy the vault database to a new backup, then delete all but the newest
+/// `keep`
+///
+/// Returns `None` if there is no database to back up yet.
+pub fn create(vault_dir: &Path, keep: usize) -> Result<Option<Backup>> {
+    let db = vault_dir.join("vault.db");
+    if !db.exists() {
+        return Ok(None);
+    }
+    let dir = vault_dir.join(BACKUP_DIR);
+    fs::create_dir_all(&dir)?;
+    set_mode(&dir, 0o700)?;
+
+    // Backups taken in the same second are numbered after the newest, so
+    // the order survives pruning
+    let now = Utc::now().timestamp();
+    let stamp = DateTime::from_timestamp(now, 0).unwrap_or_default().format(TIMESTAMP_FORMAT).to_string();
+    let taken = list(vault_dir)?.into_iter().filter(|backup| backup.created_at.timestamp() == now);
+    let path = match taken.map(|backup| sequence(&backup.name)).max() {
+        None => dir.join(format!("vault-{}.db", stamp)),
+        Some(n) => dir.join(format!("vault-{}-{}.db", stamp, n + 1)),
+    };
+    fs::copy(&db, &path)?;
+    set_mode(&path, 0o600)?;
+
+    let backup = read(&path).ok_or_else(|| Error::Other(format!("Could not read backup {}", path.display())))?;
+    prune(vault_dir, keep.max(1))?;
+    Ok(Some(backup))
+}
+
+/// Backups of a vault, newest first
+pub fn list(vault_dir: &Path) -> Result<Vec<Backup>> {
+    let entries = match fs::read_dir(vault_dir.join(BACKUP_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut backups = vec![];
+    for entry in entries {
+        backups.extend(read(&entry?.path()));
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse((backup.created_at, sequence(&backup.name))));
+    Ok(backups)
+}
+
+/// Delete all but the newest `keep` backups, returning the deleted ones
+pub fn prune(vault_dir: &Path, keep: usize) -> Result<Vec<Backup>> {
+    let stale: Vec<Backup> = list(vault_dir)?.into_iter().skip(keep).collect();
+    for backup in &stale {
+        fs::remove_file(&backup.path)?;
+    }
+    Ok(stale)
+}
+
+/// Put a backup in place of the vault database
+///
+/// The backup must open as a vault database with readable metadata; the
+/// database it replaces is kept as a new backup, which is returned. The
+/// caller reopens the database afterwards (see `ClawBox::restore_backup`).
+pub fn restore(vault_dir: &Path, name: &str, keep: usize) -> Result<Option<Backup>> {
+    let backup = list(vault_dir)?
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .ok_or_else(|| Error::InvalidArgument(format!("No backup named {}", name)))?;
+    verify(&backup.path)?;
+
+    let data = fs::read(&backup.path)?;
+    let replaced = create(vault_dir, keep.max(2))?;
+    crate::sync::write_atomic(&vault_dir.join("vault.db"), &data)?;
+    Ok(replaced)
+}
+
+/// Check that a backup opens as a vault database
+pub(crate) fn verify(path: &Path) -> Result<()> {
+    let invalid = |reason: String| Error::InvalidArgument(format!("{} is not a usable vault backup: {}", path.display(), reason));
+    // Open a copy: opening creates any missing tables, which must not
+    // change the backup itself
+    let probe = path.with_file_name(format!(".{}.probe", path.file_name().and_then(|n| n.to_str()).unwrap_or("backup")));
+    fs::copy(path, &probe)?;
+    let result = SqliteStore::open(&probe).and_then(|store| store.get_meta("salt"));
+    let _ = fs::remove_file(&probe);
+    match result {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(invalid("it has no master key".to_string())),
+        Err(e) => Err(invalid(e.to_string())),
+    }
+}
+
+/// The backup at `path`, if its name is one `create` writes
+fn read(path: &Path) -> Option<Backup> {
+    let name = path.file_name()?.to_str()?;
+    let stamp = name.strip_prefix("vault-")?.strip_suffix(".db")?;
+    let stamp = stamp.split_once('-').map_or(stamp, |(stamp, _)| stamp);
+    let created_at = NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok()?.and_utc();
+    let size = fs::metadata(path).ok()?.len();
+    Some(Backup { name: name.to_string(), path: path.to_path_buf(), created_at, size })
+}
+
+/// Order of backups taken in the same second (`vault-<stamp>-<n>.db`)
+fn sequence(name: &str) -> u32 {
+    name.trim_end_matches(".db").rsplit_once('-').and_then(|(_, n)| n.parse().ok()).unwrap_or(1)
+}
+
+#[cfg(unix)]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(mode))?)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClawBox;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_backups_rotate_and_restore() {
+        let temp = TempDir::new().unwrap();
+        assert!(create(temp.path(), 3).unwrap().is_none());
+        let mut vault = ClawBox::open(temp.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "good", Default::default()).unwrap();
+
+        let good = create(temp.path(), 3).unwrap().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&good.path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        for _ in 0..3 {
+            create(temp.path(), 3).unwrap();
+        }
+        // Same-second backups get distinct names; the oldest is pruned
+        let backups = list(temp.path()).unwrap();
+        assert_eq!(backups.len(), 3);
+        assert!(backups.iter().all(|backup| backup.name != good.name));
+
+        let good = create(temp.path(), 3).unwrap().unwrap();
+        vault.set("db/url", "bad", Default::default()).unwrap();
+        let replaced = vault.restore_backup(&good.name, 3).unwrap().unwrap();
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("good"));
+        assert!(list(temp.path()).unwrap().iter().any(|backup| backup.name == replaced.name));
+
+        // Only files that open as a vault are swapped in
+        fs::write(temp.path().join(BACKUP_DIR).join("vault-20240601T120000.db"), b"garbage").unwrap();
+        assert!(matches!(vault.restore_backup("vault-20240601T120000.db", 3), Err(Error::InvalidArgument(_))));
+        assert!(matches!(vault.restore_backup("../vault.db", 3), Err(Error::InvalidArgument(_))));
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("good"));
+
+        // Swapping the database needs the key, like any other write
+        vault.set("db/url", "newer", Default::default()).unwrap();
+        vault.lock();
+        assert!(matches!(vault.restore_backup(&good.name, 3), Err(Error::VaultLocked)));
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("newer"));
+    }
+}
@@ -0,0 +1,101 @@
+//! Encrypted export bundles
+//!
+//! A bundle carries secrets with their metadata from one vault to another,
+//! encrypted under a key derived from its own passphrase rather than either
+//! vault's master password:
+//!
+//! ```text
+//! "CLAWBOXB" | format version (1) | salt (32) | nonce (12) | AES-256-GCM(JSON)
+//! ```
+
+use crate::crypto;
+use crate::{AccessLevel, Error, Result};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8; 8] = b"CLAWBOXB";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + SALT_LEN + NONCE_LEN;
+
+/// One secret in a bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSecret {
+    pub path: String,
+    pub value: Vec<u8>,
+    /// Access level as stored in the database (0 = public … 3 = critical)
+    pub access: u8,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    /// Unix seconds
+    pub expires_at: Option<i64>,
+}
+
+impl BundleSecret {
+    pub fn access_level(&self) -> AccessLevel {
+        match self.access {
+            0 => AccessLevel::Public,
+            2 => AccessLevel::Sensitive,
+            3 => AccessLevel::Critical,
+            _ => AccessLevel::Normal,
+        }
+    }
+}
+
+/// What to do when an imported path already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictMode {
+    /// Replace the existing secret (a new version is kept in its history)
+    #[default]
+    Overwrite,
+    /// Keep the existing secret
+    Skip,
+}
+
+/// Options for `ClawBox::export_bundle`
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Include Critical secrets, which are left out by default
+    pub include_critical: bool,
+}
+
+/// Result of `ClawBox::import_bundle`
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub imported: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Name of the snapshot taken before existing secrets were overwritten
+    pub snapshot: Option<String>,
+}
+
+/// Encrypt secrets into a bundle
+pub fn seal(secrets: &[BundleSecret], passphrase: &str) -> Result<Vec<u8>> {
+    let json = Zeroizing::new(serde_json::to_vec(secrets)?);
+    let salt = crypto::generate_salt();
+    let key = crypto::derive_key(passphrase, &salt)?;
+
+    let mut bundle = Vec::with_capacity(HEADER_LEN + json.len() + 16);
+    bundle.extend_from_slice(MAGIC);
+    bundle.push(FORMAT_VERSION);
+    bundle.extend_from_slice(&salt);
+    crypto::encrypt_into(&json, &key, &mut bundle)?;
+    Ok(bundle)
+}
+
+/// Decrypt a bundle; a wrong passphrase yields `Error::InvalidPassword`
+pub fn open(bundle: &[u8], passphrase: &str) -> Result<Vec<BundleSecret>> {
+    if bundle.len() < HEADER_LEN || &bundle[..MAGIC.len()] != MAGIC {
+        return Err(Error::Other("Not a ClawBox export bundle".to_string()));
+    }
+    let version = bundle[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(Error::Other(format!("Unsupported bundle format version {}", version)));
+    }
+
+    let salt = &bundle[MAGIC.len() + 1..MAGIC.len() + 1 + SALT_LEN];
+    let key = crypto::derive_key(passphrase, salt)?;
+    let sealed = &bundle[HEADER_LEN - NONCE_LEN..];
+    let json = Zeroizing::new(crypto::open(sealed, &key).map_err(|_| Error::InvalidPassword)?);
+    Ok(serde_json::from_slice(&json)?)
+}
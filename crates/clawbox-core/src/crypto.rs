@@ -3,15 +3,23 @@
 //! Uses:
 //! - Argon2id for key derivation
 //! - AES-256-GCM for encryption
+//! - HKDF-SHA256 for keys derived from the vault key (e.g. for sync)
+//!
+//! Secret values are stored as a nonce followed by the ciphertext, or, above
+//! `CHUNK_SIZE`, in the chunked format of `encrypt_stream`; `seal_value`
+//! and `open_value` pick the format.
 
 use crate::{Error, Result};
 use aes_gcm::{
-    aead::{Aead, KeyInit},
-    Aes256Gcm, Nonce,
+    aead::{consts::U12, Aead, AeadInPlace, KeyInit},
+    Aes256Gcm, Nonce, Tag,
 };
 use argon2::Argon2;
+use hkdf::Hkdf;
 use rand::RngCore;
-use zeroize::Zeroize;
+use sha2::Sha256;
+use std::io::{self, Read, Write};
+use zeroize::{Zeroize, Zeroizing};
 
 /// Argon2id parameters
 const ARGON2_MEMORY_KB: u32 = 65536; // 64 MB
@@ -20,8 +28,23 @@ const ARGON2_PARALLELISM: u32 = 4;
 
 /// Key sizes
 const SALT_LEN: usize = 32;
-const KEY_LEN: usize = 32; // 256 bits
+pub const KEY_LEN: usize = 32; // 256 bits
 const NONCE_LEN: usize = 12; // 96 bits for GCM
+const TAG_LEN: usize = 16;
+
+/// Plaintext bytes per chunk of the chunked format; larger values are
+/// stored chunked
+pub const CHUNK_SIZE: usize = 64 * 1024;
+/// Start of a value in the chunked format
+pub const CHUNKED_MAGIC: [u8; 4] = *b"CBS1";
+/// Random part of each chunk's nonce, the rest being the chunk's number
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 4;
+const CHUNKED_HEADER_LEN: usize = CHUNKED_MAGIC.len() + NONCE_PREFIX_LEN;
+/// Set in a chunk's length for the last chunk
+const LAST_CHUNK: u32 = 1 << 31;
+
+/// Minimum master password score (0-4) accepted by default
+pub const DEFAULT_MIN_PASSWORD_SCORE: u8 = 3;
 
 /// Derived key with zeroize on drop
 #[derive(Zeroize)]
@@ -36,24 +59,101 @@ impl DerivedKey {
         &self.bytes
     }
     
-    /// Create from raw bytes (for sync)
-    pub fn from_bytes(bytes: Vec<u8>) -> Self {
-        let mut key_bytes = [0u8; KEY_LEN];
-        let len = bytes.len().min(KEY_LEN);
-        key_bytes[..len].copy_from_slice(&bytes[..len]);
-        Self { bytes: key_bytes }
+    /// Create from raw bytes, rejecting anything that isn't exactly one key long
+    pub fn try_from_slice(bytes: &[u8]) -> Result<Self> {
+        let key_bytes: [u8; KEY_LEN] = bytes.try_into().map_err(|_| {
+            Error::Other(format!(
+                "Invalid key length: expected {} bytes, got {}",
+                KEY_LEN,
+                bytes.len()
+            ))
+        })?;
+        Ok(Self { bytes: key_bytes })
     }
-    
+
     /// Export bytes (for sync)
     pub fn to_bytes(&self) -> Vec<u8> {
         self.bytes.to_vec()
     }
 }
 
-/// Encrypted data container
+/// HKDF context for the sync key
+const SYNC_KEY_INFO: &[u8] = b"clawbox sync key v1";
+
+/// Key for data leaving the device through sync, derived from the vault key
+///
+/// Sync backends get this instead of the vault key itself, so a leaked sync
+/// copy or backend never exposes the key that opens the local vault.
+/// Zeroized on drop.
+pub struct SyncKey {
+    key: DerivedKey,
+}
+
+impl SyncKey {
+    /// Derive the sync key for a vault key
+    pub(crate) fn derive(vault_key: &DerivedKey) -> Self {
+        let mut bytes = [0u8; KEY_LEN];
+        Hkdf::<Sha256>::new(None, vault_key.as_bytes())
+            .expand(SYNC_KEY_INFO, &mut bytes)
+            .expect("KEY_LEN is a valid HKDF-SHA256 output length");
+        let key = DerivedKey { bytes };
+        bytes.zeroize();
+        Self { key }
+    }
+
+    /// The key to encrypt sync data with (internal use only)
+    pub(crate) fn key(&self) -> &DerivedKey {
+        &self.key
+    }
+}
+
+/// HKDF context for keys that wrap the vault key for access tokens
+const TOKEN_KEY_INFO: &[u8] = b"clawbox token key v1";
+
+/// Key that wraps the vault key for an access token
+///
+/// Tokens are random, so a fast derivation is enough.
+pub(crate) fn token_wrapping_key(token: &[u8]) -> DerivedKey {
+    let mut bytes = [0u8; KEY_LEN];
+    Hkdf::<Sha256>::new(None, token)
+        .expand(TOKEN_KEY_INFO, &mut bytes)
+        .expect("KEY_LEN is a valid HKDF-SHA256 output length");
+    let key = DerivedKey { bytes };
+    bytes.zeroize();
+    key
+}
+
+/// A nonce followed by the ciphertext (with its tag), in one buffer
+///
+/// Most stored formats keep the two together, so `encrypt` writes them
+/// that way and `into_bytes` hands the buffer over without copying.
+#[derive(Debug, Clone)]
 pub struct EncryptedData {
-    pub nonce: Vec<u8>,
-    pub ciphertext: Vec<u8>,
+    bytes: Vec<u8>,
+}
+
+impl EncryptedData {
+    /// Join a nonce and ciphertext kept apart
+    pub fn from_parts(nonce: &[u8], ciphertext: &[u8]) -> Self {
+        Self { bytes: [nonce, ciphertext].concat() }
+    }
+
+    pub fn nonce(&self) -> &[u8] {
+        &self.bytes[..NONCE_LEN.min(self.bytes.len())]
+    }
+
+    pub fn ciphertext(&self) -> &[u8] {
+        &self.bytes[NONCE_LEN.min(self.bytes.len())..]
+    }
+
+    /// The nonce and ciphertext, as `open` takes them
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
 }
 
 /// Generate random salt
@@ -63,6 +163,40 @@ pub fn generate_salt() -> Vec<u8> {
     salt
 }
 
+/// Password strength estimate
+#[derive(Debug, Clone)]
+pub struct PasswordStrength {
+    /// 0 (trivially guessable) to 4 (very hard to guess)
+    pub score: u8,
+    /// What is wrong with the password, if anything specific
+    pub warning: Option<String>,
+    /// Actionable advice for a stronger password
+    pub suggestions: Vec<String>,
+}
+
+impl PasswordStrength {
+    /// Whether the password meets the given minimum score
+    pub fn is_acceptable(&self, min_score: u8) -> bool {
+        self.score >= min_score
+    }
+}
+
+/// Estimate password strength with zxcvbn
+pub fn assess_password(password: &str) -> PasswordStrength {
+    let entropy = zxcvbn::zxcvbn(password, &["clawbox", "vault", "secret"]);
+    let feedback = entropy.feedback();
+
+    PasswordStrength {
+        score: u8::from(entropy.score()),
+        warning: feedback
+            .and_then(|f| f.warning())
+            .map(|w| w.to_string()),
+        suggestions: feedback
+            .map(|f| f.suggestions().iter().map(|s| s.to_string()).collect())
+            .unwrap_or_default(),
+    }
+}
+
 /// Derive encryption key from password using Argon2id
 pub fn derive_key(password: &str, salt: &[u8]) -> Result<DerivedKey> {
     let argon2 = Argon2::new(
@@ -87,35 +221,216 @@ pub fn derive_key(password: &str, salt: &[u8]) -> Result<DerivedKey> {
 
 /// Encrypt data using AES-256-GCM
 pub fn encrypt(plaintext: &[u8], key: &DerivedKey) -> Result<EncryptedData> {
-    let cipher = Aes256Gcm::new_from_slice(key.as_bytes())
-        .map_err(|e| Error::Encryption(e.to_string()))?;
-
-    let mut nonce_bytes = [0u8; NONCE_LEN];
-    rand::thread_rng().fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
+    let mut bytes = Vec::with_capacity(NONCE_LEN + plaintext.len() + TAG_LEN);
+    encrypt_into(plaintext, key, &mut bytes)?;
+    Ok(EncryptedData { bytes })
+}
 
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext)
+/// Encrypt data using AES-256-GCM, appending the nonce and ciphertext to
+/// `out`, e.g. after a header
+///
+/// The plaintext is copied once, into `out`, and encrypted there.
+pub fn encrypt_into(plaintext: &[u8], key: &DerivedKey, out: &mut Vec<u8>) -> Result<()> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_bytes())
         .map_err(|e| Error::Encryption(e.to_string()))?;
 
-    Ok(EncryptedData {
-        nonce: nonce_bytes.to_vec(),
-        ciphertext,
-    })
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    out.reserve(NONCE_LEN + plaintext.len() + TAG_LEN);
+    out.extend_from_slice(&nonce);
+    let start = out.len();
+    out.extend_from_slice(plaintext);
+    match cipher.encrypt_in_place_detached(Nonce::from_slice(&nonce), &[], &mut out[start..]) {
+        Ok(tag) => {
+            out.extend_from_slice(&tag);
+            Ok(())
+        }
+        Err(e) => {
+            // Don't leave the plaintext behind
+            out[start..].zeroize();
+            out.truncate(start - NONCE_LEN);
+            Err(Error::Encryption(e.to_string()))
+        }
+    }
 }
 
 /// Decrypt data using AES-256-GCM
 pub fn decrypt(encrypted: &EncryptedData, key: &DerivedKey) -> Result<Vec<u8>> {
+    open(encrypted.as_bytes(), key)
+}
+
+/// Decrypt a nonce followed by its ciphertext, as `encrypt` and
+/// `EncryptedData::into_bytes` give them, without copying the input
+pub fn open(data: &[u8], key: &DerivedKey) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(Error::Decryption("Invalid data format".to_string()));
+    }
     let cipher = Aes256Gcm::new_from_slice(key.as_bytes())
         .map_err(|e| Error::Decryption(e.to_string()))?;
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| Error::Decryption(e.to_string()))
+}
 
-    let nonce = Nonce::from_slice(&encrypted.nonce);
+/// Encrypt everything `reader` yields into `writer` in the chunked format,
+/// returning the plaintext length
+///
+/// The format is `CHUNKED_MAGIC` and a random 8-byte nonce prefix, then
+/// for each `CHUNK_SIZE` of plaintext (the last one shorter, possibly
+/// empty) its length as a big-endian u32, the ciphertext and the tag. A
+/// chunk's nonce is the prefix followed by its number, and the number and
+/// whether it is the last chunk (the top bit of the length) are
+/// authenticated with it, so chunks can't be reordered, dropped or moved
+/// between values. Only two chunks of plaintext are held at a time.
+pub fn encrypt_stream(mut reader: impl Read, mut writer: impl Write, key: &DerivedKey) -> Result<u64> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_bytes())
+        .map_err(|e| Error::Encryption(e.to_string()))?;
+    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+    rand::thread_rng().fill_bytes(&mut prefix);
+    writer.write_all(&CHUNKED_MAGIC)?;
+    writer.write_all(&prefix)?;
+
+    let mut chunk = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut next = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut len = fill_chunk(&mut reader, &mut chunk)?;
+    let mut total = 0;
+    for counter in 0..=u32::MAX {
+        // Reading ahead tells whether this chunk is the last
+        let next_len = if len == CHUNK_SIZE { fill_chunk(&mut reader, &mut next)? } else { 0 };
+        let last = next_len == 0;
+        let tag = cipher
+            .encrypt_in_place_detached(&chunk_nonce(&prefix, counter), &chunk_aad(counter, last), &mut chunk[..len])
+            .map_err(|e| Error::Encryption(e.to_string()))?;
+        writer.write_all(&(len as u32 | if last { LAST_CHUNK } else { 0 }).to_be_bytes())?;
+        writer.write_all(&chunk[..len])?;
+        writer.write_all(&tag)?;
+        total += len as u64;
+        if last {
+            return Ok(total);
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+    }
+    Err(Error::Encryption("Value too large to encrypt".to_string()))
+}
 
-    let plaintext = cipher
-        .decrypt(nonce, encrypted.ciphertext.as_ref())
+/// Decrypt a value in the chunked format from `reader` into `writer`,
+/// returning the plaintext length
+///
+/// Fails if a chunk is out of place, missing or altered, or anything follows
+/// the last one. Chunks before a bad one have been written by then; the
+/// caller should discard the output on error.
+pub fn decrypt_stream(mut reader: impl Read, mut writer: impl Write, key: &DerivedKey) -> Result<u64> {
+    let cipher = Aes256Gcm::new_from_slice(key.as_bytes())
         .map_err(|e| Error::Decryption(e.to_string()))?;
+    let mut header = [0u8; CHUNKED_HEADER_LEN];
+    read_frame(&mut reader, &mut header)?;
+    if header[..CHUNKED_MAGIC.len()] != CHUNKED_MAGIC {
+        return Err(Error::Decryption("Not a chunked value".to_string()));
+    }
+    let prefix = &header[CHUNKED_MAGIC.len()..];
 
-    Ok(plaintext)
+    let mut chunk = Zeroizing::new(vec![0u8; CHUNK_SIZE]);
+    let mut total = 0;
+    for counter in 0..=u32::MAX {
+        let mut len = [0u8; 4];
+        read_frame(&mut reader, &mut len)?;
+        let len = u32::from_be_bytes(len);
+        let last = len & LAST_CHUNK != 0;
+        let len = (len & !LAST_CHUNK) as usize;
+        if len > CHUNK_SIZE || (!last && len < CHUNK_SIZE) {
+            return Err(Error::Decryption(format!("Invalid length of chunk {}", counter)));
+        }
+        let mut tag = Tag::default();
+        read_frame(&mut reader, &mut chunk[..len])?;
+        read_frame(&mut reader, &mut tag)?;
+        cipher
+            .decrypt_in_place_detached(&chunk_nonce(prefix, counter), &chunk_aad(counter, last), &mut chunk[..len], &tag)
+            .map_err(|_| Error::Decryption(format!("Chunk {} failed authentication", counter)))?;
+        writer.write_all(&chunk[..len])?;
+        total += len as u64;
+        if last {
+            return match reader.read(&mut [0u8; 1])? {
+                0 => Ok(total),
+                _ => Err(Error::Decryption("Data after the last chunk".to_string())),
+            };
+        }
+    }
+    Err(Error::Decryption("Too many chunks".to_string()))
+}
+
+/// Read up to a chunk of plaintext, stopping short only at the end
+fn fill_chunk(reader: &mut impl Read, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < chunk.len() {
+        match reader.read(&mut chunk[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read part of a chunked value, which ending early means it was truncated
+fn read_frame(reader: &mut impl Read, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Error::Decryption("Encrypted value is truncated".to_string()),
+        _ => e.into(),
+    })
+}
+
+fn chunk_nonce(prefix: &[u8], counter: u32) -> Nonce<U12> {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..].copy_from_slice(&counter.to_be_bytes());
+    nonce.into()
+}
+
+fn chunk_aad(counter: u32, last: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..4].copy_from_slice(&CHUNKED_MAGIC);
+    aad[4..8].copy_from_slice(&counter.to_be_bytes());
+    aad[8] = last as u8;
+    aad
+}
+
+/// Stored length of a `len`-byte value in the chunked format
+fn chunked_len(len: usize) -> usize {
+    CHUNKED_HEADER_LEN + len + len.div_ceil(CHUNK_SIZE).max(1) * (4 + TAG_LEN)
+}
+
+/// Plaintext length of a stored chunked value, if well formed; see also
+/// `storage::info_columns!`
+fn chunked_plaintext_len(stored: usize) -> usize {
+    let framed = stored.saturating_sub(CHUNKED_HEADER_LEN);
+    framed.saturating_sub(framed.div_ceil(CHUNK_SIZE + 4 + TAG_LEN) * (4 + TAG_LEN))
+}
+
+/// Encrypt a secret value into the stored format: nonce and ciphertext, or
+/// chunked (see `encrypt_stream`) when longer than `CHUNK_SIZE`
+pub fn seal_value(plaintext: &[u8], key: &DerivedKey) -> Result<Vec<u8>> {
+    if plaintext.len() <= CHUNK_SIZE {
+        return Ok(encrypt(plaintext, key)?.into_bytes());
+    }
+    let mut data = Vec::with_capacity(chunked_len(plaintext.len()));
+    encrypt_stream(plaintext, &mut data, key)?;
+    Ok(data)
+}
+
+/// Decrypt a secret value in either stored format
+pub fn open_value(data: &[u8], key: &DerivedKey) -> Result<Vec<u8>> {
+    if data.starts_with(&CHUNKED_MAGIC) {
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(chunked_plaintext_len(data.len())));
+        match decrypt_stream(data, &mut *plaintext, key) {
+            Ok(_) => return Ok(std::mem::take(&mut *plaintext)),
+            // Unless it is a nonce that happens to start the same way
+            Err(e) => return open(data, key).map_err(|_| e),
+        }
+    }
+    open(data, key)
 }
 
 #[cfg(test)]
@@ -133,6 +448,28 @@ mod tests {
         let decrypted = decrypt(&encrypted, &key).unwrap();
 
         assert_eq!(plaintext.as_slice(), decrypted.as_slice());
+
+        // One buffer, nonce first, however it is taken apart
+        assert_eq!(encrypted.as_bytes().len(), NONCE_LEN + plaintext.len() + TAG_LEN);
+        let parts = EncryptedData::from_parts(encrypted.nonce(), encrypted.ciphertext());
+        assert_eq!(decrypt(&parts, &key).unwrap(), plaintext);
+        assert_eq!(open(&encrypted.into_bytes(), &key).unwrap(), plaintext);
+        assert!(open(&[0; NONCE_LEN - 1], &key).is_err());
+
+        let mut framed = b"header".to_vec();
+        encrypt_into(plaintext, &key, &mut framed).unwrap();
+        assert_eq!(open(&framed[6..], &key).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_assess_password() {
+        let weak = assess_password("a");
+        assert_eq!(weak.score, 0);
+        assert!(!weak.is_acceptable(DEFAULT_MIN_PASSWORD_SCORE));
+        assert!(!weak.suggestions.is_empty());
+
+        let strong = assess_password("plinth-orbit-mackerel-sundial-42");
+        assert!(strong.is_acceptable(DEFAULT_MIN_PASSWORD_SCORE));
     }
 
     #[test]
@@ -147,4 +484,126 @@ mod tests {
         // Decryption with wrong key should fail
         assert!(decrypt(&encrypted, &key2).is_err());
     }
+
+    #[test]
+    fn test_sync_key() {
+        let vault_key = DerivedKey::try_from_slice(&[7; KEY_LEN]).unwrap();
+        let sync_key = SyncKey::derive(&vault_key);
+        assert_eq!(sync_key.key().as_bytes(), SyncKey::derive(&vault_key).key().as_bytes());
+        assert_ne!(sync_key.key().as_bytes(), vault_key.as_bytes());
+
+        let encrypted = encrypt(b"synced", sync_key.key()).unwrap();
+        assert!(decrypt(&encrypted, &vault_key).is_err());
+    }
+
+    fn test_value(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Where each chunk of a chunked value starts, and the end
+    fn frame_offsets(data: &[u8]) -> Vec<usize> {
+        let mut offsets = vec![CHUNKED_HEADER_LEN];
+        loop {
+            let at = *offsets.last().unwrap();
+            let len = u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+            offsets.push(at + 4 + (len & !LAST_CHUNK) as usize + TAG_LEN);
+            if len & LAST_CHUNK != 0 {
+                return offsets;
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunked_boundary_sizes() {
+        let key = DerivedKey::try_from_slice(&[7; KEY_LEN]).unwrap();
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, CHUNK_SIZE + 1, 2 * CHUNK_SIZE, 2 * CHUNK_SIZE + 1] {
+            let value = test_value(len);
+            let mut data = vec![];
+            assert_eq!(encrypt_stream(value.as_slice(), &mut data, &key).unwrap(), len as u64);
+            assert_eq!(data.len(), chunked_len(len));
+            assert_eq!(chunked_plaintext_len(data.len()), len);
+            assert_eq!(frame_offsets(&data).len() - 1, len.div_ceil(CHUNK_SIZE).max(1));
+
+            let mut plaintext = vec![];
+            assert_eq!(decrypt_stream(data.as_slice(), &mut plaintext, &key).unwrap(), len as u64);
+            assert_eq!(plaintext, value);
+
+            // Values above a chunk are stored chunked, the rest as before
+            let sealed = seal_value(&value, &key).unwrap();
+            assert_eq!(sealed.capacity(), sealed.len());
+            match len > CHUNK_SIZE {
+                true => assert!(sealed.starts_with(&CHUNKED_MAGIC)),
+                false => assert_eq!(sealed.len(), NONCE_LEN + len + TAG_LEN),
+            }
+            assert_eq!(open_value(&sealed, &key).unwrap(), value);
+        }
+
+        // Values stored before chunking still open
+        let encrypted = encrypt(b"legacy", &key).unwrap();
+        assert_eq!(open_value(&encrypted.into_bytes(), &key).unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn test_chunked_rejects_tampering() {
+        let key = DerivedKey::try_from_slice(&[7; KEY_LEN]).unwrap();
+        let value = test_value(2 * CHUNK_SIZE + 1);
+        let data = seal_value(&value, &key).unwrap();
+        let offsets = frame_offsets(&data);
+        let frame = |i: usize| &data[offsets[i]..offsets[i + 1]];
+        let fails = |data: &[u8]| open_value(data, &key).unwrap_err().to_string();
+
+        // Swapped chunks
+        let swapped = [&data[..offsets[0]], frame(1), frame(0), frame(2)].concat();
+        assert!(fails(&swapped).contains("Chunk 0 failed authentication"));
+
+        // The last chunk dropped, or cut short
+        assert!(fails(&data[..offsets[2]]).contains("truncated"));
+        assert!(fails(&data[..data.len() - 1]).contains("truncated"));
+        assert!(fails(&data[..offsets[1] + 10]).contains("truncated"));
+
+        // A full chunk claiming to be the last
+        let mut early_end = data[..offsets[1]].to_vec();
+        early_end[offsets[0]] |= 0x80;
+        assert!(fails(&early_end).contains("Chunk 0 failed authentication"));
+
+        // A chunk from another value of the same length
+        let other = seal_value(&value, &key).unwrap();
+        let other_offsets = frame_offsets(&other);
+        let spliced = [&data[..offsets[1]], &other[other_offsets[1]..other_offsets[2]], frame(2)].concat();
+        assert!(fails(&spliced).contains("Chunk 1 failed authentication"));
+
+        // Anything after the last chunk
+        assert!(fails(&[data.as_slice(), b"x"].concat()).contains("after the last chunk"));
+
+        // A short chunk that isn't the last
+        let mut short = data[..offsets[0]].to_vec();
+        short.extend_from_slice(&1u32.to_be_bytes());
+        short.extend_from_slice(&[0; 1 + TAG_LEN]);
+        assert!(fails(&short).contains("Invalid length of chunk 0"));
+
+        assert_eq!(open_value(&data, &key).unwrap(), value);
+    }
+
+    #[test]
+    fn test_stream_short_reads() {
+        /// Yields at most 1000 bytes per read
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = buf.len().min(1000).min(self.0.len());
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let key = DerivedKey::try_from_slice(&[7; KEY_LEN]).unwrap();
+        let value = test_value(3 * CHUNK_SIZE + 17);
+        let mut data = vec![];
+        encrypt_stream(Trickle(&value), &mut data, &key).unwrap();
+        assert_eq!(frame_offsets(&data).len() - 1, 4);
+        let mut plaintext = vec![];
+        decrypt_stream(Trickle(&data), &mut plaintext, &key).unwrap();
+        assert_eq!(plaintext, value);
+    }
 }
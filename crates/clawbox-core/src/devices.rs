@@ -0,0 +1,132 @@
+//! Registry of the devices that sync a vault
+//!
+//! `devices.json` sits next to the synced vault file, sealed with the sync
+//! key like the vault itself, and records when each device last synced,
+//! pushed and pulled. It is only informational: a sync that moved the vault
+//! succeeds even if the registry can't be updated.
+
+use crate::crypto::SyncKey;
+use crate::sync::{open_sealed, seal, write_atomic, SyncResult};
+use crate::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Registry file in a sync folder
+pub const DEVICES_FILE: &str = "devices.json";
+
+/// A device that has synced the vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Device {
+    pub device_id: String,
+    pub hostname: String,
+    /// Last sync of any kind, including ones with nothing to do
+    pub last_seen: DateTime<Utc>,
+    pub last_push: Option<DateTime<Utc>>,
+    pub last_pull: Option<DateTime<Utc>>,
+    /// ClawBox version of the last sync
+    pub version: String,
+}
+
+/// Devices by id
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeviceRegistry {
+    devices: BTreeMap<String, Device>,
+}
+
+impl DeviceRegistry {
+    /// Devices, most recently seen first
+    pub fn devices(&self) -> Vec<Device> {
+        let mut devices: Vec<Device> = self.devices.values().cloned().collect();
+        devices.sort_by_key(|device| std::cmp::Reverse(device.last_seen));
+        devices
+    }
+
+    /// Note a sync by this device with the given result
+    pub fn record(&mut self, result: SyncResult) {
+        if result == SyncResult::Unavailable {
+            return;
+        }
+        let now = Utc::now();
+        let device_id = crate::icloud::get_device_id();
+        let device = self.devices.entry(device_id.clone()).or_insert_with(|| Device {
+            device_id,
+            hostname: String::new(),
+            last_seen: now,
+            last_push: None,
+            last_pull: None,
+            version: String::new(),
+        });
+        device.hostname = hostname::get().map_or_else(|_| "unknown".to_string(), |h| h.to_string_lossy().to_string());
+        device.last_seen = now;
+        device.version = env!("CARGO_PKG_VERSION").to_string();
+        if matches!(result, SyncResult::Pushed | SyncResult::Merged) {
+            device.last_push = Some(now);
+        }
+        if matches!(result, SyncResult::Pulled | SyncResult::Merged) {
+            device.last_pull = Some(now);
+        }
+    }
+
+    /// Drop a device, e.g. one that was retired; false if it isn't listed
+    pub fn forget(&mut self, device_id: &str) -> bool {
+        self.devices.remove(device_id).is_some()
+    }
+
+    /// The registry in a sync folder, empty if there is none yet
+    pub fn load(dir: &Path, key: &SyncKey) -> Result<Self> {
+        match std::fs::read(dir.join(DEVICES_FILE)) {
+            Ok(data) => open_sealed(&data, key),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read, change and write back the registry in a sync folder; the
+    /// caller holds the folder's `SyncLock`
+    pub(crate) fn update<T>(dir: &Path, key: &SyncKey, f: impl FnOnce(&mut Self) -> T) -> Result<T> {
+        let mut registry = Self::load(dir, key)?;
+        let result = f(&mut registry);
+        write_atomic(&dir.join(DEVICES_FILE), &seal(&registry, key)?)?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::DerivedKey;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_registry_records_and_forgets() {
+        let dir = TempDir::new().unwrap();
+        let key = SyncKey::derive(&DerivedKey::try_from_slice(&[7; 32]).unwrap());
+        assert!(DeviceRegistry::load(dir.path(), &key).unwrap().devices().is_empty());
+
+        DeviceRegistry::update(dir.path(), &key, |registry| registry.record(SyncResult::Pushed)).unwrap();
+        let devices = DeviceRegistry::load(dir.path(), &key).unwrap().devices();
+        assert_eq!(devices.len(), 1);
+        let pushed = devices[0].clone();
+        assert_eq!(pushed.device_id, crate::icloud::get_device_id());
+        assert!(pushed.last_push.is_some() && pushed.last_pull.is_none());
+
+        // Later syncs keep what earlier ones recorded
+        DeviceRegistry::update(dir.path(), &key, |registry| registry.record(SyncResult::Pulled)).unwrap();
+        DeviceRegistry::update(dir.path(), &key, |registry| registry.record(SyncResult::UpToDate)).unwrap();
+        let device = DeviceRegistry::load(dir.path(), &key).unwrap().devices().remove(0);
+        assert_eq!(device.last_push, pushed.last_push);
+        assert!(device.last_pull.is_some() && device.last_seen >= device.last_pull.unwrap());
+
+        // Sealed with the sync key
+        let data = std::fs::read(dir.path().join(DEVICES_FILE)).unwrap();
+        assert!(!data.windows(b"device_id".len()).any(|w| w == b"device_id"));
+        let other = SyncKey::derive(&DerivedKey::try_from_slice(&[8; 32]).unwrap());
+        assert!(DeviceRegistry::load(dir.path(), &other).is_err());
+
+        assert!(!DeviceRegistry::update(dir.path(), &key, |registry| registry.forget("old-mac")).unwrap());
+        assert!(DeviceRegistry::update(dir.path(), &key, |registry| registry.forget(&device.device_id)).unwrap());
+        assert!(DeviceRegistry::load(dir.path(), &key).unwrap().devices().is_empty());
+    }
+}
@@ -14,18 +14,82 @@ pub enum Error {
     #[error("Vault not found at {path}")]
     VaultNotFound { path: String },
 
+    #[error("Vault already initialized at {path}")]
+    AlreadyInitialized { path: String },
+
+    #[error("Secret already exists: {path}")]
+    AlreadyExists { path: String },
+
+    /// A key path that cannot be stored; `path` is the input as given
+    #[error("Invalid key path {path:?}: {reason}")]
+    InvalidPath { path: String, reason: String },
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("{path} is not valid UTF-8; read it as bytes")]
+    NotUtf8 { path: String },
+
     #[error("Secret not found: {path}")]
     SecretNotFound { path: String },
 
+    #[error("Version {version} of {path} not found")]
+    VersionNotFound { path: String, version: u32 },
+
     #[error("Invalid master password")]
     InvalidPassword,
 
+    /// Too many wrong passwords in a row; `unlock` refuses to try any
+    /// password until the cooldown ends
+    #[error("Too many wrong passwords; try again in {} seconds", .retry_after.as_secs().max(1))]
+    TooManyAttempts { retry_after: std::time::Duration },
+
     #[error("Access denied: {reason}")]
     AccessDenied { reason: String },
 
+    /// The actor has read as many secrets at this access level in the last
+    /// minute as the vault's rate limit allows
+    #[error("Read rate limit reached; try again in {} seconds", .retry_after.as_secs().max(1))]
+    RateLimited { retry_after: std::time::Duration },
+
     #[error("Approval timeout")]
     ApprovalTimeout,
 
+    /// Files of the vault can be accessed by other users, and the vault
+    /// was opened with `OpenOptions::enforce_permissions`
+    #[error("Insecure permissions: {0}")]
+    InsecurePermissions(crate::permissions::PermissionReport),
+
+    /// The vault was opened with `ClawBox::open_read_only`
+    #[error("Vault is open read-only")]
+    ReadOnly,
+
+    /// The secret's access level needs the master password at read time;
+    /// read it with `ClawBox::get_with_reauth`
+    #[error("Reading {path} requires the master password again")]
+    ReauthRequired { path: String },
+
+    #[error("iCloud not available")]
+    ICloudUnavailable,
+
+    /// Another device pushed while we were syncing; syncing again merges it
+    #[error("The remote vault changed during sync; sync again")]
+    RemoteChanged,
+
+    /// The synced copy doesn't match its checksum: torn by a concurrent
+    /// write or not fully downloaded yet
+    #[error("The remote vault is incomplete or still being uploaded; try again in a moment")]
+    RemoteIncomplete,
+
+    /// iCloud Drive has only a placeholder for the file and couldn't be
+    /// made to download it in time
+    #[error("{path} is in iCloud but not downloaded to this device yet; open the folder in Finder to download it, then sync again")]
+    ICloudNotDownloaded { path: String },
+
+    /// A sync meta file that is neither JSON nor the older line format
+    #[error("Unreadable sync metadata {path}: {reason}")]
+    InvalidSyncMeta { path: String, reason: String },
+
     #[error("Encryption error: {0}")]
     Encryption(String),
 
@@ -44,3 +108,14 @@ pub enum Error {
     #[error("{0}")]
     Other(String),
 }
+
+impl Error {
+    /// Whether the vault database is locked by another connection
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            Error::Database(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
+    }
+}
@@ -0,0 +1,208 @@
+//! Git repository sync backend
+//!
+//! Commits `vault.encrypted` and `vault.meta` to a branch of a (private)
+//! repository, so every push is also a versioned backup. Runs the `git`
+//! command line in a local clone, which means remotes, SSH keys and
+//! credential helpers work as they do for the user's other repositories.
+
+use crate::sync::{SyncBackend, META_FILE, VAULT_FILE};
+use crate::{Error, Result};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+/// Sync through a branch of a git repository
+pub struct GitBackend {
+    remote: String,
+    branch: String,
+    /// Local clone used to fetch and commit
+    workdir: PathBuf,
+}
+
+impl GitBackend {
+    /// Backend for `branch` of the repository at `remote`, cloned into `workdir`
+    pub fn new(remote: &str, branch: &str, workdir: impl Into<PathBuf>) -> Self {
+        Self {
+            remote: remote.to_string(),
+            branch: branch.to_string(),
+            workdir: workdir.into(),
+        }
+    }
+
+    pub fn remote(&self) -> &str {
+        &self.remote
+    }
+
+    fn git(&self, args: &[&str]) -> Result<Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.workdir)
+            .args(args)
+            // Fail instead of waiting for a password nobody will type
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| Error::Io(std::io::Error::new(e.kind(), format!("Could not run git: {}", e))))
+    }
+
+    /// Run git and fail with its message unless it succeeds
+    fn run(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = self.git(args)?;
+        if !output.status.success() {
+            return Err(git_error(args, &output));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Create the clone on first use and point it at `remote`
+    fn prepare(&self) -> Result<()> {
+        if !self.workdir.join(".git").exists() {
+            std::fs::create_dir_all(&self.workdir)?;
+            self.run(&["init", "--quiet"])?;
+        }
+        if self.git(&["remote", "get-url", "origin"])?.status.success() {
+            self.run(&["remote", "set-url", "origin", &self.remote])?;
+        } else {
+            self.run(&["remote", "add", "origin", &self.remote])?;
+        }
+        Ok(())
+    }
+
+    /// Fetch the branch; `false` if the remote doesn't have it yet
+    fn fetch(&self) -> Result<bool> {
+        self.prepare()?;
+        let output = self.git(&["fetch", "--quiet", "origin", &self.branch])?;
+        if output.status.success() {
+            return Ok(true);
+        }
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("couldn't find remote ref") {
+            return Ok(false);
+        }
+        Err(git_error(&["fetch"], &output))
+    }
+
+    /// A file as of the fetched branch
+    fn read(&self, file: &str) -> Result<Option<Vec<u8>>> {
+        let output = self.git(&["show", &format!("FETCH_HEAD:{}", file)])?;
+        Ok(output.status.success().then_some(output.stdout))
+    }
+
+    fn version(&self, exists: bool) -> Result<u64> {
+        if !exists {
+            return Ok(0);
+        }
+        Ok(self
+            .read(META_FILE)?
+            .and_then(|meta| String::from_utf8_lossy(&meta).trim().parse().ok())
+            .unwrap_or(0))
+    }
+}
+
+impl SyncBackend for GitBackend {
+    fn is_connected(&self) -> bool {
+        Command::new("git")
+            .args(["ls-remote", "--quiet", "--exit-code", "--heads", &self.remote])
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            // 2: reachable, but no branches yet
+            .is_ok_and(|status| matches!(status.code(), Some(0 | 2)))
+    }
+
+    fn get_remote_version(&self) -> Result<u64> {
+        let exists = self.fetch()?;
+        self.version(exists)
+    }
+
+    fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+        let exists = self.fetch()?;
+        if self.version(exists)? >= version {
+            return Err(Error::RemoteChanged);
+        }
+        if exists {
+            self.run(&["checkout", "--quiet", "--force", "-B", &self.branch, "FETCH_HEAD"])?;
+        } else {
+            self.run(&["checkout", "--quiet", "--force", "--orphan", &self.branch])?;
+        }
+        std::fs::write(self.workdir.join(VAULT_FILE), data)?;
+        std::fs::write(self.workdir.join(META_FILE), version.to_string())?;
+        self.run(&["add", "--", VAULT_FILE, META_FILE])?;
+
+        let device = crate::icloud::get_device_id();
+        let message = format!("Sync vault version {} from {}", version, device);
+        // Commit as the user where git knows them, as ClawBox otherwise
+        let mut commit = vec![];
+        if !self.git(&["config", "user.email"])?.status.success() {
+            commit.extend(["-c", "user.name=ClawBox", "-c", "user.email=clawbox@localhost"]);
+        }
+        commit.extend(["commit", "--quiet", "--no-verify", "-m", &message]);
+        self.run(&commit)?;
+
+        let refspec = format!("HEAD:refs/heads/{}", self.branch);
+        let output = self.git(&["push", "--quiet", "--porcelain", "origin", &refspec])?;
+        if output.status.success() {
+            return Ok(());
+        }
+        // Someone pushed since our fetch: let the sync merge and retry
+        let report = String::from_utf8_lossy(&output.stdout);
+        if report.contains("[rejected]") || report.contains("non-fast-forward") || report.contains("fetch first") {
+            return Err(Error::RemoteChanged);
+        }
+        Err(git_error(&["push"], &output))
+    }
+
+    fn download(&self) -> Result<(Vec<u8>, u64)> {
+        let exists = self.fetch()?;
+        let data = exists
+            .then(|| self.read(VAULT_FILE))
+            .transpose()?
+            .flatten()
+            .ok_or_else(|| Error::Other(format!("No remote vault found in {} ({})", self.remote, self.branch)))?;
+        Ok((data, self.version(exists)?))
+    }
+}
+
+fn git_error(args: &[&str], output: &Output) -> Error {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let command = args.iter().find(|arg| !arg.starts_with('-') && !arg.contains('=')).unwrap_or(&"");
+    Error::Other(format!("git {} failed: {}", command, stderr.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_push_fetch_and_rejected_push() {
+        let temp = TempDir::new().unwrap();
+        let remote = temp.path().join("remote.git");
+        let status = Command::new("git").args(["init", "--quiet", "--bare"]).arg(&remote).status().unwrap();
+        assert!(status.success());
+        let remote = remote.to_str().unwrap();
+        let laptop = GitBackend::new(remote, "vault", temp.path().join("laptop"));
+        let desktop = GitBackend::new(remote, "vault", temp.path().join("desktop"));
+
+        assert!(laptop.is_connected());
+        assert!(!GitBackend::new("/nonexistent/repo.git", "vault", temp.path().join("x")).is_connected());
+        assert_eq!(laptop.get_remote_version().unwrap(), 0);
+        assert!(desktop.download().unwrap_err().to_string().contains("No remote vault found"));
+
+        laptop.upload(b"sealed-1", 1).unwrap();
+        assert_eq!(desktop.download().unwrap(), (b"sealed-1".to_vec(), 1));
+        laptop.upload(b"sealed-2", 2).unwrap();
+
+        // The desktop's view is stale: it must merge first
+        assert!(matches!(desktop.upload(b"sealed-x", 2), Err(Error::RemoteChanged)));
+        desktop.upload(b"sealed-3", 3).unwrap();
+        assert_eq!(laptop.download().unwrap(), (b"sealed-3".to_vec(), 3));
+
+        // Every push is a commit naming the device and version
+        let log = laptop.run(&["log", "--format=%s", "FETCH_HEAD"]).unwrap();
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(log.lines().count(), 3);
+        assert!(log.starts_with(&format!("Sync vault version 3 from {}", crate::icloud::get_device_id())));
+    }
+}
@@ -0,0 +1,386 @@
+//! Hooks run when audited operations succeed
+//!
+//! A hook is a shell command or a webhook, called with a JSON description of
+//! each audit entry whose action, secret access level and actor type match
+//! its conditions; `Hook::on_critical_read`, for example, notifies someone
+//! whenever a Critical secret is read or exported. Hooks run on a background
+//! thread and can never fail the operation itself. Once they finish, how they
+//! went is added to the entry's `metadata` under `hooks`; metadata is not
+//! covered by the audit log's hash chain.
+
+use crate::audit::{Action, AuditEntry};
+use crate::{AccessLevel, Error, Result};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Attempts made to deliver a webhook
+pub const WEBHOOK_ATTEMPTS: u32 = 4;
+/// Wait before the first webhook retry, doubled for each one after it
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+/// How long a hook command may run before it is killed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a hook sends an entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookTarget {
+    /// Run with `sh -c`, the event JSON on stdin and its main fields in
+    /// `CLAWBOX_HOOK_*` variables
+    Command(String),
+    /// POST the event JSON to this URL
+    Webhook(String),
+}
+
+impl HookTarget {
+    /// A webhook for `https://` URLs (`http://` only to this machine),
+    /// otherwise a command
+    pub fn parse(target: &str) -> Result<Self> {
+        let invalid = |reason: &str| Err(Error::InvalidArgument(format!("hook {:?}: {}", target, reason)));
+        let host = match target.strip_prefix("http://") {
+            Some(rest) => rest.split(['/', '?']).next().unwrap_or_default(),
+            None if target.starts_with("https://") => "",
+            None if target.trim().is_empty() => return invalid("empty command"),
+            None => return Ok(Self::Command(target.to_string())),
+        };
+        let loopback = ["localhost", "127.0.0.1", "[::1]"]
+            .iter()
+            .any(|name| host == *name || host.starts_with(&format!("{}:", name)));
+        if target.starts_with("http://") && !loopback {
+            return invalid("webhooks must use https");
+        }
+        if cfg!(not(feature = "webhook")) {
+            return invalid("webhooks need the `webhook` feature");
+        }
+        Ok(Self::Webhook(target.to_string()))
+    }
+}
+
+/// A hook and the audit entries it runs for
+#[derive(Debug, Clone)]
+pub struct Hook {
+    /// Name recorded with the outcome
+    pub name: String,
+    pub target: HookTarget,
+    /// Actions that trigger the hook; empty matches any
+    pub actions: Vec<Action>,
+    /// Access levels of the secret involved; empty matches any entry, also
+    /// those not about a secret
+    pub levels: Vec<AccessLevel>,
+    /// Actor types (`human`, `ai`, `app`, `token`, `system`); empty matches any
+    pub actor_types: Vec<String>,
+}
+
+impl Hook {
+    /// Call `target` whenever a Critical secret is read or exported
+    pub fn on_critical_read(target: HookTarget) -> Self {
+        Self {
+            name: "on_critical_read".to_string(),
+            target,
+            actions: vec![Action::Read, Action::Export],
+            levels: vec![AccessLevel::Critical],
+            actor_types: vec![],
+        }
+    }
+
+    /// Whether the hook runs for `entry`, about a secret at `level`
+    pub fn matches(&self, entry: &AuditEntry, level: Option<AccessLevel>) -> bool {
+        entry.success
+            && (self.actions.is_empty() || self.actions.contains(&entry.action))
+            && (self.levels.is_empty() || level.is_some_and(|level| self.levels.contains(&level)))
+            && (self.actor_types.is_empty() || self.actor_types.contains(&entry.actor.actor_type))
+    }
+}
+
+/// How one hook went, as recorded in the entry's metadata
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct HookOutcome {
+    pub hook: String,
+    pub ok: bool,
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A vault handle's hooks and the threads running them
+#[derive(Default)]
+pub(crate) struct HookRunner {
+    hooks: Vec<Hook>,
+    threads: RefCell<Vec<JoinHandle<()>>>,
+}
+
+impl HookRunner {
+    pub fn set(&mut self, hooks: Vec<Hook>) {
+        self.hooks = hooks;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Whether matching needs the access level of the secret involved
+    pub fn needs_level(&self) -> bool {
+        self.hooks.iter().any(|hook| !hook.levels.is_empty())
+    }
+
+    /// Run the hooks matching `entry` on a background thread, then record
+    /// how they went in the entry in the database at `db_path`, if given
+    pub fn dispatch(&self, entry: &AuditEntry, level: Option<AccessLevel>, db_path: Option<PathBuf>) {
+        let hooks: Vec<Hook> = self.hooks.iter().filter(|hook| hook.matches(entry, level)).cloned().collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let event = event(entry, level);
+        let id = entry.id.clone();
+        let thread = std::thread::spawn(move || {
+            let outcomes: Vec<HookOutcome> = hooks.iter().map(|hook| run(hook, &event)).collect();
+            if let Some(db_path) = db_path {
+                let metadata = serde_json::json!({ "hooks": outcomes });
+                let _ = crate::storage::set_audit_metadata(&db_path, &id, &metadata);
+            }
+        });
+        let mut threads = self.threads.borrow_mut();
+        threads.retain(|thread| !thread.is_finished());
+        threads.push(thread);
+    }
+
+    /// Wait for every hook started so far to finish
+    pub fn wait(&self) {
+        for thread in self.threads.borrow_mut().drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for HookRunner {
+    fn drop(&mut self) {
+        self.wait();
+    }
+}
+
+/// What hooks are told about an entry; never a secret's value
+fn event(entry: &AuditEntry, level: Option<AccessLevel>) -> serde_json::Value {
+    let access = level.map(|level| format!("{:?}", level).to_lowercase());
+    let host = hostname::get().map(|host| host.to_string_lossy().into_owned()).unwrap_or_default();
+    let actor = format!("{}:{}", entry.actor.actor_type, entry.actor.identifier);
+    serde_json::json!({
+        // Shown as is by Slack and similar chat webhooks
+        "text": format!(
+            "ClawBox: {} {} {}{} on {}",
+            actor,
+            entry.action.as_str(),
+            access.as_deref().map(|access| format!("{} secret ", access)).unwrap_or_default(),
+            entry.key_path,
+            host
+        ),
+        "id": entry.id,
+        "timestamp": entry.timestamp.to_rfc3339(),
+        "action": entry.action.as_str(),
+        "key_path": entry.key_path,
+        "access": access,
+        "actor": { "type": entry.actor.actor_type, "identifier": entry.actor.identifier },
+        "host": host,
+    })
+}
+
+fn run(hook: &Hook, event: &serde_json::Value) -> HookOutcome {
+    let (result, attempts) = match &hook.target {
+        HookTarget::Command(command) => (run_command(command, event), 1),
+        HookTarget::Webhook(url) => post_with_retry(url, &event.to_string()),
+    };
+    HookOutcome { hook: hook.name.clone(), ok: result.is_ok(), attempts, error: result.err() }
+}
+
+fn run_command(command: &str, event: &serde_json::Value) -> std::result::Result<(), String> {
+    let field = |name: &str| event[name].as_str().unwrap_or_default().to_string();
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("CLAWBOX_HOOK_ACTION", field("action"))
+        .env("CLAWBOX_HOOK_PATH", field("key_path"))
+        .env("CLAWBOX_HOOK_ACCESS", field("access"))
+        .env("CLAWBOX_HOOK_ACTOR", format!("{}:{}", event["actor"]["type"].as_str().unwrap_or_default(), event["actor"]["identifier"].as_str().unwrap_or_default()))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("could not run hook command: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may well not read it
+        let _ = stdin.write_all(event.to_string().as_bytes());
+    }
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("hook command failed ({})", status)),
+            Ok(None) if started.elapsed() >= COMMAND_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("hook command timed out after {}s", COMMAND_TIMEOUT.as_secs()));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+/// POST `body` to `url`, retrying with exponential backoff while the server
+/// is unreachable or answers with 429 or a 5xx status; returns the result and
+/// the number of attempts made
+fn post_with_retry(url: &str, body: &str) -> (std::result::Result<(), String>, u32) {
+    let mut delay = RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match post(url, body) {
+            Ok(()) => return (Ok(()), attempt),
+            Err((error, retry)) if !retry || attempt == WEBHOOK_ATTEMPTS => return (Err(error), attempt),
+            Err(_) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// One POST; failures say whether they are worth retrying
+#[cfg(feature = "webhook")]
+fn post(url: &str, body: &str) -> std::result::Result<(), (String, bool)> {
+    let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(10)).build();
+    match agent.post(url).set("Content-Type", "application/json").send_string(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, _)) => Err((format!("webhook answered {}", status), status == 429 || status >= 500)),
+        Err(e) => Err((format!("webhook failed: {}", e), true)),
+    }
+}
+
+#[cfg(not(feature = "webhook"))]
+fn post(_url: &str, _body: &str) -> std::result::Result<(), (String, bool)> {
+    Err(("webhooks need the `webhook` feature".to_string(), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::ActorInfo;
+
+    fn entry(action: Action, actor: ActorInfo) -> AuditEntry {
+        AuditEntry::new(action, "exchange/api-key", true).with_actor(actor)
+    }
+
+    #[test]
+    fn test_matches() {
+        let hook = Hook::on_critical_read(HookTarget::Command("true".to_string()));
+        let read = entry(Action::Read, ActorInfo::ai("agent"));
+        assert!(hook.matches(&read, Some(AccessLevel::Critical)));
+        assert!(!hook.matches(&read, Some(AccessLevel::Sensitive)));
+        assert!(!hook.matches(&read, None));
+        assert!(hook.matches(&entry(Action::Export, ActorInfo::human()), Some(AccessLevel::Critical)));
+        assert!(!hook.matches(&entry(Action::Write, ActorInfo::human()), Some(AccessLevel::Critical)));
+        assert!(!hook.matches(&AuditEntry::new(Action::Read, "x", false), Some(AccessLevel::Critical)));
+
+        let ai_only = Hook { actor_types: vec!["ai".to_string()], levels: vec![], ..hook };
+        assert!(ai_only.matches(&read, None));
+        assert!(!ai_only.matches(&entry(Action::Read, ActorInfo::human()), None));
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(HookTarget::parse("notify-send hi").unwrap(), HookTarget::Command("notify-send hi".to_string()));
+        assert!(HookTarget::parse(" ").is_err());
+        assert!(HookTarget::parse("http://example.com/hook").is_err());
+        #[cfg(feature = "webhook")]
+        {
+            let url = "https://hooks.example.com/T000/B000";
+            assert_eq!(HookTarget::parse(url).unwrap(), HookTarget::Webhook(url.to_string()));
+            assert!(HookTarget::parse("http://127.0.0.1:8080/hook").is_ok());
+            assert!(HookTarget::parse("http://localhost.example.com/hook").is_err());
+        }
+    }
+
+    #[test]
+    fn test_command_gets_event() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let out = dir.path().join("out");
+        let command = format!("cat > '{0}'; echo >> '{0}'; echo \"$CLAWBOX_HOOK_ACTOR $CLAWBOX_HOOK_ACCESS\" >> '{0}'", out.display());
+        let hook = Hook::on_critical_read(HookTarget::Command(command));
+        let read = entry(Action::Read, ActorInfo::ai("agent"));
+        let outcome = run(&hook, &event(&read, Some(AccessLevel::Critical)));
+        assert_eq!(outcome, HookOutcome { hook: "on_critical_read".to_string(), ok: true, attempts: 1, error: None });
+
+        let written = std::fs::read_to_string(&out).unwrap();
+        let (json, vars) = written.split_once('\n').unwrap();
+        let event: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(event["key_path"], "exchange/api-key");
+        assert_eq!(event["access"], "critical");
+        assert_eq!(vars, "ai:agent critical\n");
+
+        let failing = Hook::on_critical_read(HookTarget::Command("exit 3".to_string()));
+        assert!(!run(&failing, &event).ok);
+    }
+
+    /// Answer one request per status in `statuses`, returning the bodies
+    /// received
+    #[cfg(feature = "webhook")]
+    fn serve(statuses: &'static [&'static str]) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Read};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut bodies = vec![];
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+            bodies
+        });
+        (url, server)
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_webhook_retries() {
+        let (url, server) = serve(&["500 Internal Server Error", "503 Service Unavailable", "200 OK"]);
+        let hook = Hook::on_critical_read(HookTarget::parse(&url).unwrap());
+        let read = entry(Action::Read, ActorInfo::human());
+        let outcome = run(&hook, &event(&read, Some(AccessLevel::Critical)));
+        assert!(outcome.ok, "{:?}", outcome.error);
+        assert_eq!(outcome.attempts, 3);
+
+        let bodies = server.join().unwrap();
+        let sent: serde_json::Value = serde_json::from_str(&bodies[2]).unwrap();
+        assert_eq!(sent["id"], read.id.as_str());
+        assert!(sent["text"].as_str().unwrap().contains("read critical secret exchange/api-key"));
+
+        // A client error is not retried
+        let (url, server) = serve(&["404 Not Found"]);
+        let (result, attempts) = post_with_retry(&url, "{}");
+        server.join().unwrap();
+        assert!(result.unwrap_err().contains("404"));
+        assert_eq!(attempts, 1);
+    }
+}
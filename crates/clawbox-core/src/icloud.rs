@@ -1,89 +1,194 @@
 //! iCloud Sync for ClawBox
 //!
-//! Provides automatic sync across macOS/iOS devices via iCloud Drive.
+//! Provides automatic sync across macOS/iOS devices via iCloud Drive. On
+//! other platforms iCloud Drive is simply never available.
 
 #![allow(unexpected_cfgs)]
 
-use crate::{crypto, Result, Error};
+use crate::{backup, crypto, Result, Error};
+use crate::crypto::SyncKey;
+use crate::devices::{Device, DeviceRegistry, DEVICES_FILE};
+
+use crate::storage::SqliteStore;
+use crate::sync::{write_atomic, SyncLock, SyncSecret, META_FILE, VAULT_FILE};
+pub use crate::sync::{SyncMeta, SyncResult};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
 /// iCloud container identifier
 const ICLOUD_CONTAINER: &str = "iCloud~com~harrishan~ClawBox";
+/// Start of vault files written with a header; older ones are just the
+/// nonce and ciphertext
+const PAYLOAD_MAGIC: &[u8] = b"CLBX";
+/// Header flag: the database was deflated before encryption
+const FLAG_DEFLATE: u8 = 1;
+/// How long `pull` waits for iCloud to download an evicted file by default
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Get unique device identifier
+pub(crate) fn get_device_id() -> String {
+    // Use hostname as device ID
+    hostname::get()
+        .map(|h| h.to_string_lossy().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
 
-/// Sync file names
-const VAULT_FILE: &str = "vault.encrypted";
-const META_FILE: &str = "vault.meta";
-#[allow(dead_code)]
-const LOCK_FILE: &str = "sync.lock";
-
-/// Sync metadata
-#[derive(Debug, Clone)]
-pub struct SyncMeta {
-    pub version: u64,
-    pub timestamp: u64,
-    pub device_id: String,
+/// File operations of a push, replaceable to inject failures in tests
+trait SyncIo {
+    /// Replace `path` with `data` (see `write_atomic`)
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()>;
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+    fn remove(&self, path: &Path) -> Result<()>;
+    /// Ask iCloud Drive to download `path`; false if that isn't possible here
+    fn download(&self, path: &Path) -> Result<bool>;
 }
 
-impl SyncMeta {
-    pub fn new(version: u64) -> Self {
-        Self {
-            version,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            device_id: get_device_id(),
-        }
+struct FileIo;
+
+impl SyncIo for FileIo {
+    fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+        write_atomic(path, data)
     }
 
-    pub fn from_file(path: &Path) -> Result<Self> {
-        let content = fs::read_to_string(path)?;
-        let mut lines = content.lines();
-        
-        Ok(Self {
-            version: lines.next()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0),
-            timestamp: lines.next()
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0),
-            device_id: lines.next()
-                .unwrap_or("")
-                .to_string(),
-        })
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        Ok(fs::read(path)?)
     }
 
-    pub fn to_file(&self, path: &Path) -> Result<()> {
-        let content = format!("{}\n{}\n{}", self.version, self.timestamp, self.device_id);
-        fs::write(path, content)?;
-        Ok(())
+    fn remove(&self, path: &Path) -> Result<()> {
+        Ok(fs::remove_file(path)?)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn download(&self, path: &Path) -> Result<bool> {
+        let status = std::process::Command::new("brctl")
+            .arg("download")
+            .arg(path)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+        Ok(status.is_ok_and(|status| status.success()))
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn download(&self, _path: &Path) -> Result<bool> {
+        Ok(false)
     }
 }
 
-/// Get unique device identifier
-fn get_device_id() -> String {
-    // Use hostname as device ID
-    hostname::get()
-        .map(|h| h.to_string_lossy().to_string())
-        .unwrap_or_else(|_| "unknown".to_string())
+/// The stub iCloud Drive leaves in place of a file it has evicted or not
+/// downloaded yet: `.vault.encrypted.icloud` for `vault.encrypted`
+fn placeholder(path: &Path) -> PathBuf {
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    path.with_file_name(format!(".{}.icloud", name))
+}
+
+/// Sizes of a push, before and after compression and encryption
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushStats {
+    /// Bytes of database pushed (without the audit log if it was left out)
+    pub database_size: u64,
+    /// Bytes written to iCloud Drive
+    pub uploaded_size: u64,
+}
+
+/// Header, nonce and ciphertext of the vault file for `data`, deflated
+/// first unless that doesn't make it smaller
+fn seal_payload(data: &[u8], key: &SyncKey) -> Result<Vec<u8>> {
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data)?;
+    let deflated = encoder.finish()?;
+    let (flags, plaintext) = if deflated.len() < data.len() { (FLAG_DEFLATE, &deflated[..]) } else { (0, data) };
+
+    let mut payload = PAYLOAD_MAGIC.to_vec();
+    payload.push(flags);
+    crypto::encrypt_into(plaintext, key.key(), &mut payload)?;
+    Ok(payload)
+}
+
+/// The database in a vault file written by `seal_payload`, or by versions
+/// that wrote no header
+fn open_payload(payload: &[u8], key: &SyncKey) -> Result<Vec<u8>> {
+    use flate2::read::DeflateDecoder;
+    use std::io::Read;
+
+    let decrypt = |sealed: &[u8]| -> Result<Vec<u8>> {
+        if sealed.len() < 12 {
+            return Err(Error::RemoteIncomplete);
+        }
+        crypto::open(sealed, key.key()).map_err(|_| {
+            Error::Decryption(
+                "The iCloud copy was encrypted with a different key (another vault, or an older ClawBox that used the vault key); push to replace it".to_string(),
+            )
+        })
+    };
+    let (flags, plaintext) = match payload.strip_prefix(PAYLOAD_MAGIC).and_then(|rest| rest.split_first()) {
+        // A headerless file whose random nonce happens to start with the
+        // magic still opens as one
+        Some((&flags, sealed)) => match decrypt(sealed) {
+            Ok(plaintext) => (flags, plaintext),
+            Err(_) => (0, decrypt(payload)?),
+        },
+        None => (0, decrypt(payload)?),
+    };
+    if flags & FLAG_DEFLATE == 0 {
+        return Ok(plaintext);
+    }
+    let mut data = Vec::new();
+    DeflateDecoder::new(&plaintext[..])
+        .read_to_end(&mut data)
+        .map_err(|e| Error::Decryption(format!("The iCloud copy doesn't decompress: {}", e)))?;
+    Ok(data)
 }
 
+/// Callback of `ICloudSync::set_download_progress`
+type DownloadProgress = dyn Fn(&Path, Duration);
+
 /// iCloud sync manager
 pub struct ICloudSync {
+    /// This vault's folder in the container
     icloud_path: Option<PathBuf>,
+    /// Where all vaults shared one set of files before they had their own
+    /// folders; adopted by the vault whose key opens them
+    legacy_path: Option<PathBuf>,
     local_vault_path: PathBuf,
-    encryption_key: Option<Vec<u8>>,
+    encryption_key: Option<SyncKey>,
+    /// Backups of the local vault kept by `pull`
+    keep_backups: usize,
+    /// Whether `push` uploads the audit log along with the secrets
+    include_audit: bool,
+    /// How long to wait for iCloud to download an evicted file
+    download_timeout: Duration,
+    /// Called about once a second while waiting for a download
+    on_download: Option<Box<DownloadProgress>>,
+    /// Sizes of the last successful push
+    last_push: std::cell::Cell<Option<PushStats>>,
+    io: Box<dyn SyncIo>,
 }
 
 impl ICloudSync {
-    /// Create new iCloud sync manager
-    pub fn new(local_vault_path: PathBuf) -> Self {
+    /// Create new iCloud sync manager for the vault `vault_id` (see
+    /// `ClawBox::vault_id`), whose files go in a folder of that name
+    pub fn new(local_vault_path: PathBuf, vault_id: &str) -> Self {
+        let container = Self::find_icloud_path();
         Self {
-            icloud_path: Self::find_icloud_path(),
+            icloud_path: container.as_ref().and_then(|container| {
+                let dir = container.join(vault_id);
+                fs::create_dir_all(&dir).ok()?;
+                Some(dir)
+            }),
+            legacy_path: container,
             local_vault_path,
             encryption_key: None,
+            keep_backups: backup::DEFAULT_KEEP,
+            include_audit: true,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            on_download: None,
+            last_push: Default::default(),
+            io: Box::new(FileIo),
         }
     }
 
@@ -109,16 +214,43 @@ impl ICloudSync {
         self.icloud_path.is_some()
     }
 
-    /// Get iCloud path
+    /// Get this vault's folder in iCloud Drive
     pub fn icloud_path(&self) -> Option<&Path> {
         self.icloud_path.as_deref()
     }
 
-    /// Set encryption key for sync
-    pub fn set_key(&mut self, key: Vec<u8>) {
+    /// Set encryption key for sync (see `ClawBox::sync_key`)
+    pub fn set_key(&mut self, key: SyncKey) {
         self.encryption_key = Some(key);
     }
 
+    /// Keep this many backups of the local vault when pulling (at least one)
+    pub fn set_keep_backups(&mut self, keep: usize) {
+        self.keep_backups = keep;
+    }
+
+    /// Push the audit log along with the secrets (the default), or upload a
+    /// copy of the database with it emptied
+    ///
+    /// The audit log is usually the larger part of the vault file and only
+    /// describes this device. Without it other devices can't see what
+    /// happened here; a pull of such a copy keeps the local audit log.
+    pub fn set_include_audit(&mut self, include: bool) {
+        self.include_audit = include;
+    }
+
+    /// Wait this long for iCloud to download a file that is only a
+    /// placeholder on this device (see `DEFAULT_DOWNLOAD_TIMEOUT`)
+    pub fn set_download_timeout(&mut self, timeout: Duration) {
+        self.download_timeout = timeout;
+    }
+
+    /// Call `progress` with the file and the time waited so far, about once
+    /// a second, while waiting for iCloud to download a file
+    pub fn set_download_progress(&mut self, progress: impl Fn(&Path, Duration) + 'static) {
+        self.on_download = Some(Box::new(progress));
+    }
+
     /// Get local vault version
     pub fn local_version(&self) -> Result<u64> {
         let meta_path = self.local_vault_path.join("sync.meta");
@@ -130,12 +262,31 @@ impl ICloudSync {
         }
     }
 
+    /// Local edits not yet pushed; 0 if there is no local vault yet
+    pub fn pending_changes(&self) -> Result<u64> {
+        self.local_store()?.map_or(Ok(0), |store| store.pending_changes())
+    }
+
+    /// The local database, if there is one to count edits in
+    fn local_store(&self) -> Result<Option<SqliteStore>> {
+        let vault_db = self.local_vault_path.join("vault.db");
+        let mut header = [0; 16];
+        match fs::File::open(&vault_db).and_then(|mut file| std::io::Read::read_exact(&mut file, &mut header)) {
+            Ok(()) if &header == b"SQLite format 3\0" => Ok(Some(SqliteStore::open(&vault_db)?)),
+            Ok(()) => Ok(None),
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::NotFound | std::io::ErrorKind::UnexpectedEof) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Get remote (iCloud) vault version
     pub fn remote_version(&self) -> Result<u64> {
         let icloud_path = self.icloud_path.as_ref()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
+            .ok_or(Error::ICloudUnavailable)?;
+        self.adopt_legacy()?;
         
         let meta_path = icloud_path.join(META_FILE);
+        self.ensure_local(&meta_path)?;
         if meta_path.exists() {
             let meta = SyncMeta::from_file(&meta_path)?;
             Ok(meta.version)
@@ -159,78 +310,167 @@ impl ICloudSync {
     }
 
     /// Push local vault to iCloud
-    pub fn push(&self) -> Result<()> {
+    ///
+    /// The database is compressed before it is encrypted; the returned
+    /// sizes show by how much.
+    pub fn push(&self) -> Result<PushStats> {
         let icloud_path = self.icloud_path.as_ref()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
+            .ok_or(Error::ICloudUnavailable)?;
 
         let key = self.encryption_key.as_ref()
             .ok_or_else(|| Error::Other("Encryption key not set".to_string()))?;
+        self.adopt_legacy()?;
+        let _lock = SyncLock::acquire(icloud_path)?;
 
-        // Read local vault
+        // Read local vault; edits made after this point stay pending
         let vault_db = self.local_vault_path.join("vault.db");
-        let vault_data = fs::read(&vault_db)?;
-
-        // Encrypt vault data
-        let encrypted = crypto::encrypt(&vault_data, &crypto::DerivedKey::from_bytes(key.clone()))?;
-        
-        // Combine nonce + ciphertext
-        let mut sync_data = encrypted.nonce;
-        sync_data.extend(encrypted.ciphertext);
-
-        // Write to iCloud
-        fs::write(icloud_path.join(VAULT_FILE), sync_data)?;
+        let pending = self.pending_changes()?;
+        let vault_data = if self.include_audit {
+            fs::read(&vault_db)?
+        } else {
+            self.with_scratch(|scratch| {
+                SqliteStore::open(&vault_db)?.backup_to(scratch)?;
+                let store = SqliteStore::open(scratch)?;
+                store.clear_audit_log()?;
+                store.compact()?;
+                Ok(fs::read(scratch)?)
+            })?
+        };
+
+        let sync_data = seal_payload(&vault_data, key)?;
+
+        // The vault file, then its meta, then ours: a reader that sees the
+        // remote meta also sees the data it describes, and this device only
+        // counts the push as done once both are in place
+        let vault_file = icloud_path.join(VAULT_FILE);
+        self.io.write(&vault_file, &sync_data)?;
+        if self.io.read(&vault_file)? != sync_data {
+            return Err(Error::RemoteIncomplete);
+        }
 
-        // Update meta
         let local_version = self.local_version()? + 1;
-        let meta = SyncMeta::new(local_version);
-        meta.to_file(&icloud_path.join(META_FILE))?;
-        meta.to_file(&self.local_vault_path.join("sync.meta"))?;
+        let mut meta = SyncMeta::new(local_version).with_checksum(&sync_data);
+        meta.audit_excluded = !self.include_audit;
+        let remote_meta = icloud_path.join(META_FILE);
+        let previous = self.io.read(&remote_meta).ok();
+        self.io.write(&remote_meta, meta.encode().as_bytes())?;
+        if let Err(err) = self.io.write(&self.local_vault_path.join("sync.meta"), meta.encode().as_bytes()) {
+            // Put the remote meta back, so the next sync sees a remote this
+            // device hasn't caught up with instead of one it pushed
+            let _ = match previous {
+                Some(previous) => self.io.write(&remote_meta, &previous),
+                None => self.io.remove(&remote_meta),
+            };
+            return Err(err);
+        }
+        if pending > 0 {
+            if let Some(mut store) = self.local_store()? {
+                store.settle_pending_changes(pending)?;
+            }
+        }
 
-        Ok(())
+        let stats = PushStats { database_size: vault_data.len() as u64, uploaded_size: sync_data.len() as u64 };
+        self.last_push.set(Some(stats));
+        self.record_device(icloud_path, SyncResult::Pushed);
+        Ok(stats)
+    }
+
+    /// Sizes of the last push by `push` or `sync`
+    pub fn last_push(&self) -> Option<PushStats> {
+        self.last_push.get()
     }
 
     /// Pull vault from iCloud
+    ///
+    /// The local vault is backed up first (see `backup::create`).
     pub fn pull(&self) -> Result<()> {
         let icloud_path = self.icloud_path.as_ref()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
+            .ok_or(Error::ICloudUnavailable)?;
+        self.adopt_legacy()?;
+        let _lock = SyncLock::acquire(icloud_path)?;
+        let (remote_meta, vault_data) = self.read_remote(icloud_path)?;
+
+        // Backup local vault
+        let vault_db = self.local_vault_path.join("vault.db");
+        backup::create(&self.local_vault_path, self.keep_backups)?;
+
+        // A copy pushed without its audit log keeps ours
+        let vault_data = if remote_meta.audit_excluded && vault_db.exists() {
+            self.with_scratch(|scratch| {
+                write_atomic(scratch, &vault_data)?;
+                SqliteStore::open(scratch)?.copy_audit_log_from(&vault_db)?;
+                Ok(fs::read(scratch)?)
+            })?
+        } else {
+            vault_data
+        };
+
+        // Write decrypted vault; whatever the pushing device had pending is
+        // in the copy it pushed
+        write_atomic(&vault_db, &vault_data)?;
+        if let Some(mut store) = self.local_store()? {
+            let pending = store.pending_changes()?;
+            if pending > 0 {
+                store.settle_pending_changes(pending)?;
+            }
+        }
+
+        // Update local meta
+        remote_meta.to_file(&self.local_vault_path.join("sync.meta"))?;
+        self.record_device(icloud_path, SyncResult::Pulled);
+
+        Ok(())
+    }
 
+    /// Every secret in the iCloud copy, read from a decrypted snapshot that
+    /// is removed again; the local vault is not touched
+    pub fn remote_records(&self) -> Result<Vec<SyncSecret>> {
+        let icloud_path = self.icloud_path.as_ref()
+            .ok_or(Error::ICloudUnavailable)?;
+        self.adopt_legacy()?;
+        let vault_data = {
+            let _lock = SyncLock::acquire(icloud_path)?;
+            self.read_remote(icloud_path)?.1
+        };
+
+        self.with_scratch(|snapshot| {
+            write_atomic(snapshot, &vault_data)?;
+            SqliteStore::open(snapshot)?.sync_records()
+        })
+    }
+
+    /// Run `f` with the path of a scratch database next to the local vault,
+    /// removed afterwards
+    fn with_scratch<T>(&self, f: impl FnOnce(&Path) -> Result<T>) -> Result<T> {
+        let scratch = self.local_vault_path.join(format!(".vault.db.scratch.{}.tmp", std::process::id()));
+        let result = f(&scratch);
+        let _ = fs::remove_file(&scratch);
+        result
+    }
+
+    /// Read, check and decrypt the iCloud copy; the caller holds the lock
+    fn read_remote(&self, icloud_path: &Path) -> Result<(SyncMeta, Vec<u8>)> {
         let key = self.encryption_key.as_ref()
             .ok_or_else(|| Error::Other("Encryption key not set".to_string()))?;
 
         // Read encrypted vault from iCloud
         let vault_file = icloud_path.join(VAULT_FILE);
+        self.ensure_local(&vault_file)?;
+        self.ensure_local(&icloud_path.join(META_FILE))?;
         if !vault_file.exists() {
             return Err(Error::Other("No remote vault found".to_string()));
         }
 
+        let remote_meta = SyncMeta::from_file(&icloud_path.join(META_FILE))?;
         let sync_data = fs::read(&vault_file)?;
         
-        if sync_data.len() < 12 {
-            return Err(Error::Other("Invalid sync data".to_string()));
+        // Check before touching the local vault: a torn or half-downloaded
+        // file would otherwise show up as a decryption failure
+        if sync_data.len() < 12 || !remote_meta.matches(&sync_data) {
+            return Err(Error::RemoteIncomplete);
         }
 
-        // Decrypt
-        let nonce = sync_data[..12].to_vec();
-        let ciphertext = sync_data[12..].to_vec();
-        
-        let encrypted = crypto::EncryptedData { nonce, ciphertext };
-        let vault_data = crypto::decrypt(&encrypted, &crypto::DerivedKey::from_bytes(key.clone()))?;
-
-        // Backup local vault
-        let vault_db = self.local_vault_path.join("vault.db");
-        if vault_db.exists() {
-            let backup = self.local_vault_path.join("vault.db.backup");
-            fs::copy(&vault_db, &backup)?;
-        }
-
-        // Write decrypted vault
-        fs::write(&vault_db, vault_data)?;
-
-        // Update local meta
-        let remote_meta = SyncMeta::from_file(&icloud_path.join(META_FILE))?;
-        remote_meta.to_file(&self.local_vault_path.join("sync.meta"))?;
-
-        Ok(())
+        Ok((remote_meta, open_payload(&sync_data, key)?))
     }
 
     /// Auto-sync (pull if remote newer, push if local newer)
@@ -245,53 +485,150 @@ impl ICloudSync {
         if remote > local {
             self.pull()?;
             Ok(SyncResult::Pulled)
-        } else if local > remote {
+        } else if local > remote || self.pending_changes()? > 0 || !self.remote_intact()? {
+            // Local edits since the last push go up; so does a repair of an
+            // interrupted push, which leaves a vault file its meta doesn't
+            // describe
             self.push()?;
             Ok(SyncResult::Pushed)
         } else {
+            let icloud_path = self.icloud_path.as_ref()
+                .ok_or(Error::ICloudUnavailable)?;
+            if let Ok(_lock) = SyncLock::acquire(icloud_path) {
+                self.record_device(icloud_path, SyncResult::UpToDate);
+            }
             Ok(SyncResult::UpToDate)
         }
     }
 
+    /// Note this device's sync in the device registry; the caller holds the
+    /// lock
+    fn record_device(&self, icloud_path: &Path, result: SyncResult) {
+        if let Some(key) = &self.encryption_key {
+            // Informational only: the push or pull itself already succeeded
+            let _ = DeviceRegistry::update(icloud_path, key, |registry| registry.record(result));
+        }
+    }
+
+    /// Devices that synced this vault through iCloud, most recently seen
+    /// first
+    pub fn devices(&self) -> Result<Vec<Device>> {
+        let icloud_path = self.icloud_path.as_ref()
+            .ok_or(Error::ICloudUnavailable)?;
+        let key = self.encryption_key.as_ref()
+            .ok_or_else(|| Error::Other("Encryption key not set".to_string()))?;
+        self.ensure_local(&icloud_path.join(DEVICES_FILE))?;
+        Ok(DeviceRegistry::load(icloud_path, key)?.devices())
+    }
+
+    /// Remove a device from the registry; false if it isn't listed
+    pub fn forget_device(&self, device_id: &str) -> Result<bool> {
+        let icloud_path = self.icloud_path.as_ref()
+            .ok_or(Error::ICloudUnavailable)?;
+        let key = self.encryption_key.as_ref()
+            .ok_or_else(|| Error::Other("Encryption key not set".to_string()))?;
+        self.ensure_local(&icloud_path.join(DEVICES_FILE))?;
+        let _lock = SyncLock::acquire(icloud_path)?;
+        DeviceRegistry::update(icloud_path, key, |registry| registry.forget(device_id))
+    }
+
+    /// Whether the iCloud vault file is the one its meta describes (or
+    /// neither exists)
+    fn remote_intact(&self) -> Result<bool> {
+        let icloud_path = self.icloud_path.as_ref()
+            .ok_or(Error::ICloudUnavailable)?;
+        let (vault_file, meta_file) = (icloud_path.join(VAULT_FILE), icloud_path.join(META_FILE));
+        self.ensure_local(&vault_file)?;
+        self.ensure_local(&meta_file)?;
+        Ok(match (vault_file.exists(), meta_file.exists()) {
+            (false, false) => true,
+            (true, true) => SyncMeta::from_file(&meta_file)?.matches(&fs::read(&vault_file)?),
+            _ => false,
+        })
+    }
+
+    /// Move the files of a container from before vaults had their own
+    /// folders into this vault's, if they are this vault's
+    ///
+    /// Only files that decrypt with this vault's sync key are taken, so a
+    /// second vault never adopts the first one's copy; without a key nothing
+    /// is moved yet.
+    fn adopt_legacy(&self) -> Result<()> {
+        let (Some(icloud_path), Some(legacy_path), Some(key)) = (&self.icloud_path, &self.legacy_path, &self.encryption_key) else {
+            return Ok(());
+        };
+        let (old_vault, old_meta) = (legacy_path.join(VAULT_FILE), legacy_path.join(META_FILE));
+        if icloud_path == legacy_path || icloud_path.join(VAULT_FILE).exists() {
+            return Ok(());
+        }
+        self.ensure_local(&old_vault)?;
+        self.ensure_local(&old_meta)?;
+        if !old_vault.exists() {
+            return Ok(());
+        }
+
+        let _legacy_lock = SyncLock::acquire(legacy_path)?;
+        let _lock = SyncLock::acquire(icloud_path)?;
+        if open_payload(&fs::read(&old_vault)?, key).is_err() {
+            return Ok(());
+        }
+        // The vault file first, as in a push: its meta then describes it
+        fs::rename(&old_vault, icloud_path.join(VAULT_FILE))?;
+        if old_meta.exists() {
+            fs::rename(&old_meta, icloud_path.join(META_FILE))?;
+        }
+        Ok(())
+    }
+
+    /// Make sure `path` is on this device, not just a placeholder for a file
+    /// iCloud Drive holds, downloading it if needed
+    ///
+    /// A placeholder alone would look like a missing file, or a zero-byte one
+    /// while the download is still starting.
+    fn ensure_local(&self, path: &Path) -> Result<()> {
+        let stub = placeholder(path);
+        if !stub.exists() {
+            return Ok(());
+        }
+        let not_downloaded = || Error::ICloudNotDownloaded { path: path.display().to_string() };
+        if !self.io.download(path)? {
+            return Err(not_downloaded());
+        }
+
+        let started = Instant::now();
+        loop {
+            if !stub.exists() && path.exists() {
+                return Ok(());
+            }
+            let waited = started.elapsed();
+            if waited >= self.download_timeout {
+                return Err(not_downloaded());
+            }
+            if let Some(progress) = &self.on_download {
+                progress(path, waited);
+            }
+            std::thread::sleep(Duration::from_secs(1).min(self.download_timeout - waited));
+        }
+    }
+
     /// Watch for iCloud changes (returns when change detected)
+    ///
+    /// See `watch::SyncWatcher` for keeping a vault in sync.
     #[cfg(feature = "watch")]
     pub fn watch(&self) -> Result<()> {
-        use notify::{Watcher, RecursiveMode, watcher};
+        use crate::watch::{watch_files, WatchEvent};
         use std::sync::mpsc::channel;
-        use std::time::Duration;
 
         let icloud_path = self.icloud_path.as_ref()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
+            .ok_or(Error::ICloudUnavailable)?;
 
         let (tx, rx) = channel();
-        let mut watcher = watcher(tx, Duration::from_secs(2))?;
-        
-        watcher.watch(icloud_path, RecursiveMode::NonRecursive)?;
+        let _watcher = watch_files(&self.local_vault_path, Some(icloud_path), tx)?;
 
         // Wait for change
-        rx.recv().map_err(|e| Error::Other(e.to_string()))?;
-        
-        Ok(())
-    }
-}
+        while rx.recv().map_err(|e| Error::Other(e.to_string()))? != WatchEvent::Remote {}
 
-/// Sync result
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum SyncResult {
-    Pulled,
-    Pushed,
-    UpToDate,
-    Unavailable,
-}
-
-impl std::fmt::Display for SyncResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            SyncResult::Pulled => write!(f, "Pulled from iCloud"),
-            SyncResult::Pushed => write!(f, "Pushed to iCloud"),
-            SyncResult::UpToDate => write!(f, "Already up to date"),
-            SyncResult::Unavailable => write!(f, "iCloud not available"),
-        }
+        Ok(())
     }
 }
 
@@ -304,4 +641,379 @@ mod tests {
         let id = get_device_id();
         assert!(!id.is_empty());
     }
+
+    #[test]
+    fn test_payloads_compress_and_legacy_ones_open() {
+        let key = SyncKey::derive(&crypto::DerivedKey::try_from_slice(&[7; 32]).unwrap());
+        let database = b"INSERT INTO audit_log VALUES ('get', 'db/url');".repeat(2000);
+        let sealed = seal_payload(&database, &key).unwrap();
+        assert_eq!((&sealed[..4], sealed[4]), (PAYLOAD_MAGIC, FLAG_DEFLATE));
+        assert!(sealed.len() < database.len() / 10, "{} bytes", sealed.len());
+        assert_eq!(open_payload(&sealed, &key).unwrap(), database);
+
+        // Data that doesn't shrink is stored as is
+        let noise: Vec<u8> = (0..4096).map(|_| rand::random()).collect();
+        let sealed = seal_payload(&noise, &key).unwrap();
+        assert_eq!(sealed[4], 0);
+        assert_eq!(open_payload(&sealed, &key).unwrap(), noise);
+
+        // Files from before the header: nonce and ciphertext only
+        let temp = tempfile::TempDir::new().unwrap();
+        let (local, container) = (temp.path().join("local"), temp.path().join("container"));
+        fs::create_dir_all(&local).unwrap();
+        fs::create_dir_all(&container).unwrap();
+        let encrypted = crypto::encrypt(&database, key.key()).unwrap();
+        let legacy = encrypted.into_bytes();
+        fs::write(container.join(VAULT_FILE), &legacy).unwrap();
+        SyncMeta::new(1).with_checksum(&legacy).to_file(&container.join(META_FILE)).unwrap();
+        let sync = ICloudSync {
+            icloud_path: Some(container.clone()),
+            legacy_path: None,
+            local_vault_path: local.clone(),
+            encryption_key: Some(key),
+            keep_backups: backup::DEFAULT_KEEP,
+            include_audit: true,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            on_download: None,
+            last_push: Default::default(),
+            io: Box::new(FileIo),
+        };
+        assert_eq!(sync.sync().unwrap(), SyncResult::Pulled);
+        assert_eq!(fs::read(local.join("vault.db")).unwrap(), database);
+
+        // and the next push writes the compressed format
+        let stats = sync.push().unwrap();
+        assert_eq!(stats.database_size, database.len() as u64);
+        assert_eq!(stats.uploaded_size, fs::metadata(container.join(VAULT_FILE)).unwrap().len());
+        assert!(stats.uploaded_size < stats.database_size / 10);
+    }
+
+    #[test]
+    fn test_pull_rejects_torn_vault_file() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (local, container) = (temp.path().join("local"), temp.path().join("container"));
+        fs::create_dir_all(&local).unwrap();
+        fs::create_dir_all(&container).unwrap();
+        fs::write(local.join("vault.db"), b"local database").unwrap();
+        let sync = ICloudSync {
+            icloud_path: Some(container.clone()),
+            legacy_path: None,
+            local_vault_path: local.clone(),
+            encryption_key: Some(SyncKey::derive(&crypto::DerivedKey::try_from_slice(&[7; 32]).unwrap())),
+            keep_backups: backup::DEFAULT_KEEP,
+            include_audit: true,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            on_download: None,
+            last_push: Default::default(),
+            io: Box::new(FileIo),
+        };
+
+        sync.push().unwrap();
+        let meta = SyncMeta::from_file(&container.join(META_FILE)).unwrap();
+        assert_eq!((meta.version, meta.checksum.as_deref().map(str::len)), (1, Some(64)));
+
+        // Another device's upload is only half there
+        let pushed = fs::read(container.join(VAULT_FILE)).unwrap();
+        fs::write(container.join(VAULT_FILE), &pushed[..pushed.len() / 2]).unwrap();
+        fs::write(local.join("vault.db"), b"newer local database").unwrap();
+        assert!(matches!(sync.pull(), Err(Error::RemoteIncomplete)));
+        assert_eq!(fs::read(local.join("vault.db")).unwrap(), b"newer local database");
+
+        fs::write(container.join(VAULT_FILE), &pushed).unwrap();
+        sync.pull().unwrap();
+        assert_eq!(fs::read(local.join("vault.db")).unwrap(), b"local database");
+        let backups = backup::list(&local).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read(&backups[0].path).unwrap(), b"newer local database");
+
+        // Metas from older versions carry no checksum
+        fs::write(container.join(META_FILE), "1\n0\nold-mac").unwrap();
+        assert!(SyncMeta::from_file(&container.join(META_FILE)).unwrap().checksum.is_none());
+        sync.pull().unwrap();
+    }
+
+    #[test]
+    fn test_push_without_audit_keeps_local_audit_log() {
+        use crate::audit::AuditLogger;
+        use crate::ClawBox;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let (laptop, desktop, container) = (temp.path().join("laptop"), temp.path().join("desktop"), temp.path().join("container"));
+        fs::create_dir_all(&container).unwrap();
+        let mut vault = ClawBox::open(&laptop).unwrap();
+        vault.init("test-password").unwrap();
+        fs::create_dir_all(&desktop).unwrap();
+        fs::copy(laptop.join("vault.db"), desktop.join("vault.db")).unwrap();
+        for n in 0..3 {
+            vault.set(&format!("laptop/{}", n), "1", Default::default()).unwrap();
+        }
+        let sync_key = || SyncKey::derive(&crypto::DerivedKey::try_from_slice(&vault.export_key().unwrap()).unwrap());
+        let sync = |dir: &Path, include_audit: bool| ICloudSync {
+            icloud_path: Some(container.clone()),
+            legacy_path: None,
+            local_vault_path: dir.to_path_buf(),
+            encryption_key: Some(sync_key()),
+            keep_backups: backup::DEFAULT_KEEP,
+            include_audit,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            on_download: None,
+            last_push: Default::default(),
+            io: Box::new(FileIo),
+        };
+        let audit = |dir: &Path| {
+            let store = SqliteStore::open(&dir.join("vault.db")).unwrap();
+            let logger = AuditLogger::new(store.connection());
+            (logger.count().unwrap(), logger.verify_integrity().unwrap())
+        };
+        let mut other = ClawBox::open(&desktop).unwrap();
+        other.unlock("test-password").unwrap();
+        other.set("desktop/only", "1", Default::default()).unwrap();
+        drop(other);
+        let (laptop_audit, desktop_audit) = (audit(&laptop), audit(&desktop));
+        assert_ne!(laptop_audit, desktop_audit);
+
+        // A pull of a copy pushed without the audit log takes the secrets
+        // but keeps this device's log
+        sync(&laptop, false).push().unwrap();
+        assert!(SyncMeta::from_file(&container.join(META_FILE)).unwrap().audit_excluded);
+        let payload = temp.path().join("payload");
+        fs::create_dir_all(&payload).unwrap();
+        fs::write(payload.join("vault.db"), sync(&desktop, true).read_remote(&container).unwrap().1).unwrap();
+        assert_eq!(audit(&payload), (0, true));
+        sync(&desktop, true).pull().unwrap();
+        assert_eq!(audit(&desktop), desktop_audit);
+        let mut other = ClawBox::open(&desktop).unwrap();
+        other.unlock("test-password").unwrap();
+        assert_eq!(other.get_secret("laptop/2").unwrap().as_deref(), Some("1"));
+        assert!(other.get_secret("desktop/only").unwrap().is_none());
+        drop(other);
+
+        // With it, the pulled copy brings the other device's log
+        sync(&laptop, true).push().unwrap();
+        assert!(!SyncMeta::from_file(&container.join(META_FILE)).unwrap().audit_excluded);
+        sync(&desktop, false).pull().unwrap();
+        assert_eq!(audit(&desktop), audit(&laptop));
+    }
+
+    /// Fails the first `op` ("write" or "read") on the file named `file`:
+    /// writes fail without a trace, as atomic writes do; reads return half
+    /// the data, like a torn upload
+    struct FailingIo {
+        op: &'static str,
+        file: &'static str,
+        failed: std::cell::Cell<bool>,
+    }
+
+    impl FailingIo {
+        fn fails(&self, op: &str, path: &Path) -> bool {
+            let hit = !self.failed.get() && op == self.op && path.file_name().is_some_and(|name| name == self.file);
+            self.failed.set(self.failed.get() || hit);
+            hit
+        }
+    }
+
+    impl SyncIo for FailingIo {
+        fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            if self.fails("write", path) {
+                return Err(std::io::Error::other("injected write failure").into());
+            }
+            FileIo.write(path, data)
+        }
+
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            let data = FileIo.read(path)?;
+            Ok(if self.fails("read", path) { data[..data.len() / 2].to_vec() } else { data })
+        }
+
+        fn remove(&self, path: &Path) -> Result<()> {
+            FileIo.remove(path)
+        }
+
+        fn download(&self, _path: &Path) -> Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn test_interrupted_push_recovers() {
+        let key = || SyncKey::derive(&crypto::DerivedKey::try_from_slice(&[7; 32]).unwrap());
+        for (op, file) in [("write", VAULT_FILE), ("read", VAULT_FILE), ("write", META_FILE), ("write", "sync.meta")] {
+            let temp = tempfile::TempDir::new().unwrap();
+            let (laptop, desktop, container) = (temp.path().join("laptop"), temp.path().join("desktop"), temp.path().join("container"));
+            for dir in [&laptop, &desktop, &container] {
+                fs::create_dir_all(dir).unwrap();
+            }
+            let sync = |dir: &Path, io: Box<dyn SyncIo>| ICloudSync {
+                icloud_path: Some(container.clone()),
+                legacy_path: None,
+                local_vault_path: dir.to_path_buf(),
+                encryption_key: Some(key()),
+                keep_backups: backup::DEFAULT_KEEP,
+                include_audit: true,
+                download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+                on_download: None,
+                last_push: Default::default(),
+                io,
+            };
+            fs::write(laptop.join("vault.db"), b"first").unwrap();
+            sync(&laptop, Box::new(FileIo)).push().unwrap();
+            sync(&desktop, Box::new(FileIo)).pull().unwrap();
+
+            fs::write(laptop.join("vault.db"), b"second").unwrap();
+            let failing = FailingIo { op, file, failed: Default::default() };
+            assert!(sync(&laptop, Box::new(failing)).push().is_err(), "{} {}", op, file);
+            // Neither side counts the push as done
+            let laptop_sync = sync(&laptop, Box::new(FileIo));
+            assert_eq!((laptop_sync.local_version().unwrap(), laptop_sync.remote_version().unwrap()), (1, 1), "{} {}", op, file);
+
+            // The next sync repairs a vault file its meta doesn't describe
+            // rather than pulling, and a retried push starts clean
+            let repaired = (op, file) != ("write", VAULT_FILE);
+            let expected = if repaired { SyncResult::Pushed } else { SyncResult::UpToDate };
+            assert_eq!(laptop_sync.sync().unwrap(), expected, "{} {}", op, file);
+            if !repaired {
+                laptop_sync.push().unwrap();
+            }
+            assert_eq!(laptop_sync.sync().unwrap(), SyncResult::UpToDate);
+            assert_eq!(sync(&desktop, Box::new(FileIo)).sync().unwrap(), SyncResult::Pulled);
+            assert_eq!(fs::read(desktop.join("vault.db")).unwrap(), b"second");
+        }
+    }
+
+    /// iCloud Drive on a device that only has placeholders: `evict` moves a
+    /// file aside behind its stub, and `download` brings it back shortly
+    /// after (`Some(true)`), never (`Some(false)`) or can't be asked (`None`)
+    struct EvictedIo {
+        aside: PathBuf,
+        downloads: Option<bool>,
+    }
+
+    impl EvictedIo {
+        fn evict(aside: &Path, path: &Path) {
+            fs::create_dir_all(aside).unwrap();
+            fs::rename(path, aside.join(path.file_name().unwrap())).unwrap();
+            fs::write(placeholder(path), b"stub").unwrap();
+        }
+    }
+
+    impl SyncIo for EvictedIo {
+        fn write(&self, path: &Path, data: &[u8]) -> Result<()> {
+            FileIo.write(path, data)
+        }
+
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            FileIo.read(path)
+        }
+
+        fn remove(&self, path: &Path) -> Result<()> {
+            FileIo.remove(path)
+        }
+
+        fn download(&self, path: &Path) -> Result<bool> {
+            if self.downloads == Some(true) {
+                let (from, to) = (self.aside.join(path.file_name().unwrap()), path.to_path_buf());
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(100));
+                    if fs::rename(&from, &to).is_ok() {
+                        fs::remove_file(placeholder(&to)).unwrap();
+                    }
+                });
+            }
+            Ok(self.downloads.is_some())
+        }
+    }
+
+    #[test]
+    fn test_pull_downloads_evicted_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let (laptop, desktop, container) = (temp.path().join("laptop"), temp.path().join("desktop"), temp.path().join("container"));
+        let aside = temp.path().join("aside");
+        for dir in [&laptop, &desktop, &container] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        let sync = |dir: &Path, downloads: Option<bool>| ICloudSync {
+            icloud_path: Some(container.clone()),
+            legacy_path: None,
+            local_vault_path: dir.to_path_buf(),
+            encryption_key: Some(SyncKey::derive(&crypto::DerivedKey::try_from_slice(&[7; 32]).unwrap())),
+            keep_backups: backup::DEFAULT_KEEP,
+            include_audit: true,
+            download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            on_download: None,
+            last_push: Default::default(),
+            io: Box::new(EvictedIo { aside: aside.clone(), downloads }),
+        };
+        fs::write(laptop.join("vault.db"), b"first").unwrap();
+        sync(&laptop, None).push().unwrap();
+        sync(&desktop, None).pull().unwrap();
+        fs::write(laptop.join("vault.db"), b"second").unwrap();
+        sync(&laptop, None).push().unwrap();
+        EvictedIo::evict(&aside, &container.join(VAULT_FILE));
+        EvictedIo::evict(&aside, &container.join(META_FILE));
+
+        // Neither mistaken for a missing remote (and pushed over) nor read
+        for downloads in [None, Some(false)] {
+            let mut stuck = sync(&desktop, downloads);
+            stuck.set_download_timeout(Duration::ZERO);
+            assert!(matches!(stuck.sync(), Err(Error::ICloudNotDownloaded { .. })), "{:?}", downloads);
+            assert!(matches!(stuck.pull(), Err(Error::ICloudNotDownloaded { .. })), "{:?}", downloads);
+        }
+        assert!(!container.join(VAULT_FILE).exists());
+        assert_eq!(fs::read(desktop.join("vault.db")).unwrap(), b"first");
+
+        let waits = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut desktop_sync = sync(&desktop, Some(true));
+        let counter = waits.clone();
+        desktop_sync.set_download_progress(move |_, _| counter.set(counter.get() + 1));
+        assert_eq!(desktop_sync.sync().unwrap(), SyncResult::Pulled);
+        assert_eq!(fs::read(desktop.join("vault.db")).unwrap(), b"second");
+        assert!(waits.get() > 0);
+    }
+
+    #[test]
+    fn test_vault_adopts_only_its_own_legacy_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let container = temp.path().join("container");
+        let sync = |local: &str, folder: Option<&str>, key: u8| {
+            let local = temp.path().join(local);
+            fs::create_dir_all(&local).unwrap();
+            let icloud_path = folder.map_or(container.clone(), |folder| container.join(folder));
+            fs::create_dir_all(&icloud_path).unwrap();
+            ICloudSync {
+                icloud_path: Some(icloud_path),
+                legacy_path: folder.map(|_| container.clone()),
+                local_vault_path: local,
+                encryption_key: Some(SyncKey::derive(&crypto::DerivedKey::try_from_slice(&[key; 32]).unwrap())),
+                keep_backups: backup::DEFAULT_KEEP,
+                include_audit: true,
+                download_timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+                on_download: None,
+                last_push: Default::default(),
+                io: Box::new(FileIo),
+            }
+        };
+        // Work pushed before vaults had folders
+        let work = sync("work", None, 7);
+        fs::write(work.local_vault_path.join("vault.db"), b"work database").unwrap();
+        work.push().unwrap();
+
+        // The personal vault leaves it alone and gets a folder of its own
+        let personal = sync("personal", Some("personal-id"), 8);
+        fs::write(personal.local_vault_path.join("vault.db"), b"personal database").unwrap();
+        assert_eq!(personal.remote_version().unwrap(), 0);
+        personal.push().unwrap();
+        assert!(container.join(VAULT_FILE).exists());
+        assert!(container.join("personal-id").join(VAULT_FILE).exists());
+
+        // Work takes its files over on its next sync
+        let work = sync("work", Some("work-id"), 7);
+        assert_eq!(work.sync().unwrap(), SyncResult::UpToDate);
+        assert!(!container.join(VAULT_FILE).exists() && !container.join(META_FILE).exists());
+        let laptop = sync("laptop", Some("work-id"), 7);
+        assert_eq!(laptop.sync().unwrap(), SyncResult::Pulled);
+        assert_eq!(fs::read(laptop.local_vault_path.join("vault.db")).unwrap(), b"work database");
+        let desktop = sync("desktop", Some("personal-id"), 8);
+        assert_eq!(desktop.sync().unwrap(), SyncResult::Pulled);
+        assert_eq!(fs::read(desktop.local_vault_path.join("vault.db")).unwrap(), b"personal database");
+    }
 }
@@ -0,0 +1,208 @@
+//! Opaque blobs in the macOS Keychain
+//!
+//! Items are generic passwords that are readable only while the Mac is
+//! unlocked and never leave this device (no iCloud Keychain, no backups
+//! restored elsewhere). The Keychain doesn't interpret the data; callers
+//! decide what goes in and when it stops being valid.
+
+use crate::{Error, Result};
+use std::ffi::c_void;
+use std::ptr;
+use zeroize::Zeroizing;
+
+type CFTypeRef = *const c_void;
+type CFIndex = isize;
+type OSStatus = i32;
+
+const ERR_SEC_SUCCESS: OSStatus = 0;
+const ERR_SEC_ITEM_NOT_FOUND: OSStatus = -25300;
+const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+#[repr(C)]
+struct CFDictionaryCallBacks {
+    _private: [u8; 0],
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFTypeDictionaryKeyCallBacks: CFDictionaryCallBacks;
+    static kCFTypeDictionaryValueCallBacks: CFDictionaryCallBacks;
+    static kCFBooleanTrue: CFTypeRef;
+
+    fn CFStringCreateWithBytes(
+        alloc: CFTypeRef,
+        bytes: *const u8,
+        len: CFIndex,
+        encoding: u32,
+        external: u8,
+    ) -> CFTypeRef;
+    fn CFDataCreate(alloc: CFTypeRef, bytes: *const u8, len: CFIndex) -> CFTypeRef;
+    fn CFDataGetBytePtr(data: CFTypeRef) -> *const u8;
+    fn CFDataGetLength(data: CFTypeRef) -> CFIndex;
+    fn CFDictionaryCreate(
+        alloc: CFTypeRef,
+        keys: *const CFTypeRef,
+        values: *const CFTypeRef,
+        count: CFIndex,
+        key_callbacks: *const CFDictionaryCallBacks,
+        value_callbacks: *const CFDictionaryCallBacks,
+    ) -> CFTypeRef;
+    fn CFRelease(cf: CFTypeRef);
+}
+
+#[link(name = "Security", kind = "framework")]
+extern "C" {
+    static kSecClass: CFTypeRef;
+    static kSecClassGenericPassword: CFTypeRef;
+    static kSecAttrService: CFTypeRef;
+    static kSecAttrAccount: CFTypeRef;
+    static kSecAttrAccessible: CFTypeRef;
+    static kSecAttrAccessibleWhenUnlockedThisDeviceOnly: CFTypeRef;
+    static kSecValueData: CFTypeRef;
+    static kSecReturnData: CFTypeRef;
+
+    fn SecItemAdd(attributes: CFTypeRef, result: *mut CFTypeRef) -> OSStatus;
+    fn SecItemCopyMatching(query: CFTypeRef, result: *mut CFTypeRef) -> OSStatus;
+    fn SecItemDelete(query: CFTypeRef) -> OSStatus;
+}
+
+/// An owned Core Foundation object, released on drop
+struct Owned(CFTypeRef);
+
+impl Owned {
+    fn new(cf: CFTypeRef) -> Result<Self> {
+        match cf.is_null() {
+            true => Err(Error::Other("Could not allocate a Keychain query".to_string())),
+            false => Ok(Self(cf)),
+        }
+    }
+
+    fn string(s: &str) -> Result<Self> {
+        // SAFETY: the bytes are valid UTF-8 of the given length
+        Self::new(unsafe {
+            CFStringCreateWithBytes(ptr::null(), s.as_ptr(), s.len() as CFIndex, CF_STRING_ENCODING_UTF8, 0)
+        })
+    }
+
+    fn data(bytes: &[u8]) -> Result<Self> {
+        // SAFETY: CFDataCreate copies `bytes`
+        Self::new(unsafe { CFDataCreate(ptr::null(), bytes.as_ptr(), bytes.len() as CFIndex) })
+    }
+
+    fn dictionary(pairs: &[(CFTypeRef, CFTypeRef)]) -> Result<Self> {
+        let keys: Vec<CFTypeRef> = pairs.iter().map(|(k, _)| *k).collect();
+        let values: Vec<CFTypeRef> = pairs.iter().map(|(_, v)| *v).collect();
+        // SAFETY: keys and values are live CF objects, retained by the dictionary
+        Self::new(unsafe {
+            CFDictionaryCreate(
+                ptr::null(),
+                keys.as_ptr(),
+                values.as_ptr(),
+                pairs.len() as CFIndex,
+                &kCFTypeDictionaryKeyCallBacks,
+                &kCFTypeDictionaryValueCallBacks,
+            )
+        })
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        // SAFETY: we hold the only reference we created
+        unsafe { CFRelease(self.0) }
+    }
+}
+
+fn check(status: OSStatus, what: &str) -> Result<()> {
+    match status {
+        ERR_SEC_SUCCESS => Ok(()),
+        status => Err(Error::Other(format!("Could not {} the Keychain item (OSStatus {})", what, status))),
+    }
+}
+
+/// Generic password items under one Keychain service name
+#[derive(Debug, Clone)]
+pub struct Keychain {
+    service: String,
+}
+
+impl Keychain {
+    pub fn new(service: &str) -> Self {
+        Self { service: service.to_string() }
+    }
+
+    /// Store `data` as `account`, replacing any previous item
+    pub fn store(&self, account: &str, data: &[u8]) -> Result<()> {
+        self.delete(Some(account))?;
+
+        let service = Owned::string(&self.service)?;
+        let account = Owned::string(account)?;
+        let data = Owned::data(data)?;
+        // SAFETY: the Security constants are static CF strings
+        let attributes = unsafe {
+            Owned::dictionary(&[
+                (kSecClass, kSecClassGenericPassword),
+                (kSecAttrService, service.0),
+                (kSecAttrAccount, account.0),
+                (kSecAttrAccessible, kSecAttrAccessibleWhenUnlockedThisDeviceOnly),
+                (kSecValueData, data.0),
+            ])?
+        };
+        // SAFETY: `attributes` is a valid dictionary; no result is requested
+        check(unsafe { SecItemAdd(attributes.0, ptr::null_mut()) }, "store")
+    }
+
+    /// The data stored as `account`, if any
+    pub fn fetch(&self, account: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let service = Owned::string(&self.service)?;
+        let account = Owned::string(account)?;
+        // SAFETY: the Security constants are static CF objects
+        let query = unsafe {
+            Owned::dictionary(&[
+                (kSecClass, kSecClassGenericPassword),
+                (kSecAttrService, service.0),
+                (kSecAttrAccount, account.0),
+                (kSecReturnData, kCFBooleanTrue),
+            ])?
+        };
+
+        let mut result: CFTypeRef = ptr::null();
+        // SAFETY: `query` is a valid dictionary; on success we own `result`
+        match unsafe { SecItemCopyMatching(query.0, &mut result) } {
+            ERR_SEC_ITEM_NOT_FOUND => Ok(None),
+            status => {
+                check(status, "read")?;
+                let data = Owned::new(result)?;
+                // SAFETY: with kSecReturnData a match is a CFData we own
+                let bytes = unsafe {
+                    let len = CFDataGetLength(data.0) as usize;
+                    match len {
+                        0 => &[][..],
+                        len => std::slice::from_raw_parts(CFDataGetBytePtr(data.0), len),
+                    }
+                };
+                Ok(Some(Zeroizing::new(bytes.to_vec())))
+            }
+        }
+    }
+
+    /// Delete the item stored as `account`, or every item of this service
+    /// if `None`; returns whether anything was deleted
+    pub fn delete(&self, account: Option<&str>) -> Result<bool> {
+        let service = Owned::string(&self.service)?;
+        let account = account.map(Owned::string).transpose()?;
+        // SAFETY: the Security constants are static CF strings
+        let mut pairs = unsafe { vec![(kSecClass, kSecClassGenericPassword), (kSecAttrService, service.0)] };
+        if let Some(account) = &account {
+            // SAFETY: as above
+            pairs.push((unsafe { kSecAttrAccount }, account.0));
+        }
+        let query = Owned::dictionary(&pairs)?;
+
+        // SAFETY: `query` is a valid dictionary
+        match unsafe { SecItemDelete(query.0) } {
+            ERR_SEC_ITEM_NOT_FOUND => Ok(false),
+            status => check(status, "delete").map(|()| true),
+        }
+    }
+}
@@ -18,20 +18,39 @@
 //! vault.unlock("master-password")?;
 //!
 //! vault.set("github/token", "ghp_xxx", Default::default())?;
-//! let token = vault.get("github/token")?;
+//! let token = vault.get_secret("github/token")?;
 //! ```
 
 pub mod crypto;
 pub mod storage;
 pub mod audit;
+pub mod bundle;
+pub mod backup;
+pub mod snapshot;
+pub mod permissions;
+pub mod generator;
 pub mod vault;
+pub mod secret;
 pub mod error;
 pub mod sync;
-#[cfg(target_os = "macos")]
+pub mod devices;
 pub mod icloud;
+pub mod watch;
+pub mod hooks;
+#[cfg(feature = "async")]
+pub mod async_vault;
+#[cfg(feature = "webdav")]
+pub mod webdav;
+#[cfg(feature = "git-sync")]
+pub mod git_sync;
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+pub mod keychain;
 
 pub use error::{Error, Result};
-pub use vault::ClawBox;
+pub use vault::{ClawBox, ClawBoxBuilder};
+#[cfg(feature = "async")]
+pub use async_vault::AsyncClawBox;
+pub use secret::SecretValue;
 
 /// Access level for secrets
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -53,10 +72,34 @@ pub enum Actor {
     Human { device: String },
     AI { agent: String },
     App { name: String },
+    /// ClawBox itself, e.g. an idle timer
+    System { component: String },
 }
 
+/// Outcome of an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    Approved,
+    Denied,
+    /// Nobody answered in time
+    TimedOut,
+}
+
+/// A read that needs a human's approval, passed to the approval handler
+#[derive(Debug)]
+pub struct ApprovalRequest<'a> {
+    pub path: &'a str,
+    pub actor: &'a audit::ActorInfo,
+    pub access: AccessLevel,
+}
+
+/// Decides approval requests, typically by asking the user
+///
+/// Called on the thread doing the read, while the vault is borrowed.
+pub type ApprovalHandler = Box<dyn Fn(&ApprovalRequest) -> Approval + Send>;
+
 /// Options for setting a secret
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct SetOptions {
     pub access: AccessLevel,
     pub ttl: Option<std::time::Duration>,
@@ -64,6 +107,245 @@ pub struct SetOptions {
     pub note: Option<String>,
 }
 
+/// Which secrets `ClawBox::for_each_secret` goes through; the default
+/// matches every one
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    /// Path pattern, `*` as wildcard
+    pub pattern: Option<String>,
+    /// Only secrets carrying every one of these tags
+    pub tags: Vec<String>,
+    /// Only secrets at one of these levels; empty for any
+    pub access: Vec<AccessLevel>,
+}
+
+impl ListFilter {
+    /// Secrets whose path matches `pattern`, or all if `None`
+    pub fn pattern(pattern: Option<&str>) -> Self {
+        Self { pattern: pattern.map(str::to_string), ..Default::default() }
+    }
+}
+
+/// How `ClawBox::delete` removes a secret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteMode {
+    /// Remove the secret but keep its history, so `rollback` can restore it
+    #[default]
+    Soft,
+    /// Remove the secret and all its versions, then vacuum the database so
+    /// no ciphertext is left behind in freed pages
+    Hard,
+}
+
+/// Options for opening a vault; see `ClawBox::open_with` and
+/// `ClawBox::builder`
+#[derive(Debug, Clone)]
+pub struct OpenOptions {
+    /// Lock after this long without an operation; see `ClawBox::set_auto_lock`
+    pub auto_lock: Option<std::time::Duration>,
+    /// Open an existing vault without ever writing to it; see
+    /// `ClawBox::open_read_only`
+    pub read_only: bool,
+    /// Refuse to open a vault whose files other users can access, with
+    /// `Error::InsecurePermissions`, instead of only reporting them in
+    /// `ClawBox::permission_report`
+    pub enforce_permissions: bool,
+    /// SQLite settings for the vault database; see `storage::StoreOptions`
+    pub store: storage::StoreOptions,
+    /// Create the vault if it doesn't exist (the default); otherwise fail
+    /// with `Error::VaultNotFound` without creating anything
+    pub create: bool,
+    /// Who the vault is used by; `None` is the local human. See
+    /// `ClawBox::set_actor`
+    pub actor: Option<audit::ActorInfo>,
+    /// Hooks run for matching audit entries; see `ClawBox::set_hooks`
+    pub hooks: Vec<hooks::Hook>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            auto_lock: None,
+            read_only: false,
+            enforce_permissions: false,
+            store: storage::StoreOptions::default(),
+            create: true,
+            actor: None,
+            hooks: vec![],
+        }
+    }
+}
+
+/// What an access token may read; see `ClawBox::create_token`
+///
+/// Tokens only ever read: writes are refused, and so are Sensitive and
+/// Critical secrets whatever `max_access` says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenScope {
+    /// Readable subtrees: `llm` (or `llm/`, `llm/*`) covers `llm/openai` and
+    /// everything else below `llm`
+    pub path_prefixes: Vec<String>,
+    /// Highest access level readable, at most Normal
+    pub max_access: AccessLevel,
+    /// The token stops working then
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl TokenScope {
+    /// Whether `path` falls under one of the prefixes
+    pub fn covers(&self, path: &str) -> bool {
+        self.path_prefixes.iter().any(|prefix| {
+            path.strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+
+    /// Whether a secret at `access` may be read
+    pub fn allows(&self, access: AccessLevel) -> bool {
+        (access as u8) <= (self.max_access as u8).min(AccessLevel::Normal as u8)
+    }
+}
+
+/// An access token as listed by `ClawBox::tokens`; the token itself is only
+/// shown once, by `create_token`
+#[derive(Debug, Clone)]
+pub struct TokenInfo {
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Wrong passwords given to `ClawBox::unlock`, kept in the vault so that
+/// restarting the process doesn't reset them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnlockLockout {
+    /// Wrong passwords since the last successful unlock
+    pub failures: u32,
+    /// Unlocking is refused until then
+    pub locked_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl UnlockLockout {
+    /// Time left at `now` before unlocking may be tried again, if any
+    pub fn retry_after(&self, now: chrono::DateTime<chrono::Utc>) -> Option<std::time::Duration> {
+        (self.locked_until? - now).to_std().ok().filter(|left| !left.is_zero())
+    }
+}
+
+/// Aggregate vault statistics
+///
+/// Computed from metadata only, so it is available while the vault is locked.
+#[derive(Debug, Clone, Default)]
+pub struct VaultStats {
+    pub public_count: usize,
+    pub normal_count: usize,
+    pub sensitive_count: usize,
+    pub critical_count: usize,
+    /// Size of the database file(s) on disk in bytes
+    pub db_size: u64,
+    pub last_write: Option<chrono::DateTime<chrono::Utc>>,
+    pub audit_entries: usize,
+    pub schema_version: u32,
+    /// SQLite settings in effect; see `storage::SqliteStore::pragmas`
+    pub pragmas: storage::StorePragmas,
+}
+
+impl VaultStats {
+    /// Total number of secrets
+    pub fn secret_count(&self) -> usize {
+        self.public_count + self.normal_count + self.sensitive_count + self.critical_count
+    }
+}
+
+/// Result of `ClawBox::verify`
+#[derive(Debug, Clone, Default)]
+pub struct IntegrityReport {
+    /// Problems found by SQLite's integrity check (empty if none)
+    pub database_errors: Vec<String>,
+    pub audit_chain_valid: bool,
+    /// Whether stored values were decrypted; this needs the vault unlocked
+    pub values_checked: bool,
+    /// Secrets, and versions as `path@vN`, whose value fails to decrypt
+    pub undecryptable: Vec<String>,
+    pub schema_version: u32,
+}
+
+impl IntegrityReport {
+    /// No problems found (values that were not checked count as fine)
+    pub fn is_ok(&self) -> bool {
+        self.database_errors.is_empty()
+            && self.audit_chain_valid
+            && self.undecryptable.is_empty()
+            && self.schema_version <= storage::SCHEMA_VERSION
+    }
+}
+
+/// How `ClawBox::export_plaintext` hands out secrets
+#[derive(Debug, Clone, Default)]
+pub struct PlaintextExportOptions {
+    /// The user agreed to the secrets being written unencrypted; the export
+    /// is refused without it
+    pub plaintext_ok: bool,
+    /// Include Critical secrets, which are left out by default
+    pub include_critical: bool,
+    /// Where the secrets go, such as a file path, for the audit log
+    pub destination: String,
+    /// Format they are written in, for the audit log
+    pub format: String,
+}
+
+/// What `ClawBox::export_plaintext` would hand out, for asking the user first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExportSummary {
+    pub count: usize,
+    /// Highest access level among the secrets included
+    pub highest: Option<AccessLevel>,
+    /// Critical secrets left out
+    pub skipped_critical: usize,
+    /// Whether exporting needs the master password again
+    pub needs_reauth: bool,
+}
+
+/// What to remove in `ClawBox::prune`
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Versions to keep per secret, newest first (`None` keeps all)
+    pub keep_versions: Option<usize>,
+    /// Also remove audit entries older than this
+    pub audit_before: Option<chrono::DateTime<chrono::Utc>>,
+    /// Only report what would be removed
+    pub dry_run: bool,
+}
+
+/// What `ClawBox::prune` removed, or would remove on a dry run
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    pub expired_secrets: Vec<String>,
+    pub old_versions: usize,
+    pub audit_entries: usize,
+}
+
+impl PruneReport {
+    /// Whether nothing was (or would be) removed
+    pub fn is_empty(&self) -> bool {
+        self.expired_secrets.is_empty() && self.old_versions == 0 && self.audit_entries == 0
+    }
+}
+
+/// One stored version of a secret (without value)
+#[derive(Debug, Clone)]
+pub struct SecretVersion {
+    pub version: u32,
+    pub access: AccessLevel,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Who wrote it, as `<actor type>:<identifier>` (e.g. `ai:claude`);
+    /// `sync` for versions pulled by a sync, plain `human` for ones written
+    /// before writers were recorded
+    pub created_by: String,
+    /// Short keyed hash of the value, to tell versions apart without revealing them
+    pub fingerprint: String,
+}
+
 /// Secret metadata (without value)
 #[derive(Debug, Clone)]
 pub struct SecretInfo {
@@ -73,4 +355,8 @@ pub struct SecretInfo {
     pub note: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// When the secret expires and becomes eligible for `prune`
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Size of the value in bytes
+    pub size: usize,
 }
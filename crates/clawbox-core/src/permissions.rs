@@ -0,0 +1,172 @@
+//! Checks that a vault's files are private to their owner
+//!
+//! The vault directory, `backups/` and `snapshots/` should be `0700` and
+//! every database copy and sync file in them `0600`. A copy made with `cp`,
+//! an unpacked archive or a sync tool that ignores modes easily loosens
+//! them; `ClawBox::open` checks on every open and `fix` tightens them
+//! again. Other platforms have no Unix modes, and nothing is reported there.
+
+use crate::{backup, snapshot, storage, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Files in the vault directory that must be readable only by the owner
+const PRIVATE_FILES: &[&str] =
+    &["vault.db", "vault.db-wal", "vault.db-shm", "vault.db-journal", "sync.meta", storage::AUDIT_SIDECAR_FILE];
+/// Prefix of the copies `ClawBox::reinit` leaves next to the database
+const REINIT_PREFIX: &str = "vault.db.pre-reinit-";
+
+/// A file or directory that other users can access
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PermissionProblem {
+    pub path: PathBuf,
+    /// Permission bits found, e.g. `0o644`
+    pub mode: u32,
+    /// Permission bits it should have: `0o600`, or `0o700` for directories
+    pub expected: u32,
+}
+
+/// Result of `check` and `fix`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PermissionReport {
+    pub problems: Vec<PermissionProblem>,
+}
+
+impl PermissionReport {
+    /// Nothing is accessible to other users
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl std::fmt::Display for PermissionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, problem) in self.problems.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} is {:03o} (should be {:03o})", problem.path.display(), problem.mode, problem.expected)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the vault's files and directories that other users can access
+pub fn check(vault_dir: &Path) -> Result<PermissionReport> {
+    let mut problems = vec![];
+    for (path, expected) in private_paths(vault_dir)? {
+        if let Some(mode) = mode(&path)? {
+            if mode & 0o077 != 0 {
+                problems.push(PermissionProblem { path, mode, expected });
+            }
+        }
+    }
+    Ok(PermissionReport { problems })
+}
+
+/// Set everything `check` reports to `0600` or `0700`, returning what was
+/// changed
+pub fn fix(vault_dir: &Path) -> Result<PermissionReport> {
+    let report = check(vault_dir)?;
+    for problem in &report.problems {
+        backup::set_mode(&problem.path, problem.expected)?;
+    }
+    Ok(report)
+}
+
+/// The vault's private files and directories that exist, with the mode
+/// each should have
+fn private_paths(vault_dir: &Path) -> Result<Vec<(PathBuf, u32)>> {
+    if !vault_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut paths = vec![(vault_dir.to_path_buf(), 0o700)];
+    paths.extend(PRIVATE_FILES.iter().map(|name| (vault_dir.join(name), 0o600)));
+    for entry in std::fs::read_dir(vault_dir)? {
+        let path = entry?.path();
+        if path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(REINIT_PREFIX)) {
+            paths.push((path, 0o600));
+        }
+    }
+    for dir in [backup::BACKUP_DIR, snapshot::SNAPSHOT_DIR] {
+        let dir = vault_dir.join(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        paths.push((dir.clone(), 0o700));
+        for entry in std::fs::read_dir(&dir)? {
+            paths.push((entry?.path(), 0o600));
+        }
+    }
+    Ok(paths)
+}
+
+/// Permission bits of `path`, or `None` if it doesn't exist (or has none)
+#[cfg(unix)]
+fn mode(path: &Path) -> Result<Option<u32>> {
+    use std::os::unix::fs::PermissionsExt;
+    match std::fs::symlink_metadata(path) {
+        // A link's own mode means nothing; what it points to isn't ours
+        Ok(meta) if meta.file_type().is_symlink() => Ok(None),
+        Ok(meta) => Ok(Some(meta.permissions().mode() & 0o777)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(not(unix))]
+fn mode(_path: &Path) -> Result<Option<u32>> {
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{ClawBox, Error, OpenOptions};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_loose_permissions_detected_and_fixed() {
+        let temp = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.snapshot(snapshot::Operation::Import).unwrap();
+        assert!(vault.permission_report().is_ok());
+        drop(vault);
+        assert!(check(temp.path()).unwrap().is_ok());
+
+        // As if copied with a 022 umask
+        let snapshot = snapshot::list(temp.path()).unwrap().remove(0).path;
+        backup::set_mode(temp.path(), 0o755).unwrap();
+        backup::set_mode(&temp.path().join("vault.db"), 0o644).unwrap();
+        backup::set_mode(&temp.path().join(snapshot::SNAPSHOT_DIR), 0o755).unwrap();
+        backup::set_mode(&snapshot, 0o640).unwrap();
+        fs::write(temp.path().join("sync.meta"), "{}").unwrap();
+        backup::set_mode(&temp.path().join("sync.meta"), 0o666).unwrap();
+
+        // Opening warns by default and refuses when enforcing
+        let vault = ClawBox::open(temp.path()).unwrap();
+        let report = vault.permission_report().clone();
+        drop(vault);
+        let mut found: Vec<(PathBuf, u32, u32)> =
+            report.problems.iter().map(|p| (p.path.clone(), p.mode, p.expected)).collect();
+        found.sort();
+        let mut expected = vec![
+            (temp.path().to_path_buf(), 0o755, 0o700),
+            (temp.path().join("vault.db"), 0o644, 0o600),
+            (temp.path().join("sync.meta"), 0o666, 0o600),
+            (temp.path().join(snapshot::SNAPSHOT_DIR), 0o755, 0o700),
+            (snapshot.clone(), 0o640, 0o600),
+        ];
+        expected.sort();
+        assert_eq!(found, expected);
+        let opts = OpenOptions { enforce_permissions: true, ..Default::default() };
+        assert!(matches!(ClawBox::open_with(temp.path(), &opts), Err(Error::InsecurePermissions(_))));
+
+        assert_eq!(fix(temp.path()).unwrap().problems.len(), 5);
+        assert!(check(temp.path()).unwrap().is_ok());
+        let mut vault = ClawBox::open_with(temp.path(), &opts).unwrap();
+        vault.unlock("test-password").unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+//! Secret values handed out by the vault
+//!
+//! A `SecretValue` wipes its memory when dropped and never prints its
+//! contents, so a struct holding one can be logged with `{:?}` safely.
+
+use std::fmt;
+use std::ops::Deref;
+
+use zeroize::Zeroizing;
+
+/// A decrypted secret, zeroized on drop
+///
+/// Derefs to `str` for reading; `expose` makes the access explicit where
+/// that reads better, e.g. before handing the value to another API.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretValue(Zeroizing<String>);
+
+impl SecretValue {
+    pub fn new(value: String) -> Self {
+        Self(Zeroizing::new(value))
+    }
+
+    /// Take ownership of decrypted bytes, wiping them if they aren't UTF-8
+    pub fn from_utf8(bytes: Vec<u8>) -> Option<Self> {
+        match String::from_utf8(bytes) {
+            Ok(value) => Some(Self::new(value)),
+            Err(e) => {
+                drop(Zeroizing::new(e.into_bytes()));
+                None
+            }
+        }
+    }
+
+    /// The plaintext
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Deref for SecretValue {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretValue {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl PartialEq<str> for SecretValue {
+    fn eq(&self, other: &str) -> bool {
+        self.expose() == other
+    }
+}
+
+impl PartialEq<&str> for SecretValue {
+    fn eq(&self, other: &&str) -> bool {
+        self.expose() == *other
+    }
+}
+
+impl fmt::Debug for SecretValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretValue(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let value = SecretValue::new("hunter2".to_string());
+        assert_eq!(value, "hunter2");
+        assert_eq!(value.len(), 7);
+        assert!(!format!("{:?}", Some(&value)).contains("hunter2"));
+        assert!(SecretValue::from_utf8(vec![0xff, 0xfe]).is_none());
+    }
+}
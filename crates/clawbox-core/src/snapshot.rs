@@ -0,0 +1,248 @@
+//! Automatic snapshots of the vault database
+//!
+//! Before an operation that would lose data if it went wrong (changing the
+//! password, re-initializing, importing over existing secrets, pulling
+//! remote changes, restoring a snapshot) the vault copies its database to
+//! `snapshots/<timestamp>-<operation>.db` in the vault directory, readable
+//! only by the owner, and names the copy in the operation's audit entry.
+//! Only the newest few are kept (see `ClawBox::snapshot_keep`).
+//!
+//! Snapshots never leave this machine: syncs carry secret records, or
+//! `vault.db` alone for iCloud.
+
+use crate::storage::SqliteStore;
+use crate::{backup, Error, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directory in the vault directory that holds snapshots
+pub const SNAPSHOT_DIR: &str = "snapshots";
+/// Snapshots kept unless the vault's policy says otherwise
+pub const DEFAULT_KEEP: usize = 5;
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// An operation a snapshot is taken before
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    ChangePassword,
+    Reinit,
+    /// An import that may overwrite existing secrets
+    Import,
+    /// A sync that brings in remote changes
+    Pull,
+    /// Restoring another snapshot
+    Restore,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::ChangePassword => "change-password",
+            Operation::Reinit => "reinit",
+            Operation::Import => "import",
+            Operation::Pull => "pull",
+            Operation::Restore => "restore",
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "change-password" => Some(Operation::ChangePassword),
+            "reinit" => Some(Operation::Reinit),
+            "import" => Some(Operation::Import),
+            "pull" => Some(Operation::Pull),
+            "restore" => Some(Operation::Restore),
+            _ => None,
+        }
+    }
+}
+
+/// A snapshot of the vault database
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    /// File name in `SNAPSHOT_DIR`, e.g. `20240601T120304-change-password.db`
+    pub name: String,
+    pub path: PathBuf,
+    pub operation: Operation,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Copy the database behind `store` to a new snapshot taken before
+/// `operation`, then delete all but the newest `keep` (at least one)
+pub(crate) fn create(store: &SqliteStore, vault_dir: &Path, operation: Operation, keep: usize) -> Result<Snapshot> {
+    let dir = vault_dir.join(SNAPSHOT_DIR);
+    fs::create_dir_all(&dir)?;
+    backup::set_mode(&dir, 0o700)?;
+
+    // Snapshots taken in the same second are numbered after the newest, so
+    // the order survives pruning
+    let now = Utc::now().timestamp();
+    let stamp = DateTime::from_timestamp(now, 0).unwrap_or_default().format(TIMESTAMP_FORMAT).to_string();
+    let taken = list(vault_dir)?.into_iter().filter(|snapshot| snapshot.created_at.timestamp() == now);
+    let path = match taken.map(|snapshot| sequence(&snapshot.name)).max() {
+        None => dir.join(format!("{}-{}.db", stamp, operation.as_str())),
+        Some(n) => dir.join(format!("{}.{}-{}.db", stamp, n + 1, operation.as_str())),
+    };
+    // A consistent copy, even with writes pending in the WAL
+    store.backup_to(&path)?;
+
+    let snapshot = read(&path).ok_or_else(|| Error::Other(format!("Could not read snapshot {}", path.display())))?;
+    prune(vault_dir, keep.max(1))?;
+    Ok(snapshot)
+}
+
+/// Snapshots of a vault, newest first
+pub fn list(vault_dir: &Path) -> Result<Vec<Snapshot>> {
+    let entries = match fs::read_dir(vault_dir.join(SNAPSHOT_DIR)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut snapshots = vec![];
+    for entry in entries {
+        snapshots.extend(read(&entry?.path()));
+    }
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse((snapshot.created_at, sequence(&snapshot.name))));
+    Ok(snapshots)
+}
+
+/// Delete all but the newest `keep` snapshots, returning the deleted ones
+pub fn prune(vault_dir: &Path, keep: usize) -> Result<Vec<Snapshot>> {
+    let stale: Vec<Snapshot> = list(vault_dir)?.into_iter().skip(keep).collect();
+    for snapshot in &stale {
+        fs::remove_file(&snapshot.path)?;
+    }
+    Ok(stale)
+}
+
+/// The snapshot named `name`, checked to open as a vault database
+pub(crate) fn find(vault_dir: &Path, name: &str) -> Result<Snapshot> {
+    let snapshot = list(vault_dir)?
+        .into_iter()
+        .find(|snapshot| snapshot.name == name)
+        .ok_or_else(|| Error::InvalidArgument(format!("No snapshot named {}", name)))?;
+    backup::verify(&snapshot.path)?;
+    Ok(snapshot)
+}
+
+/// The snapshot at `path`, if its name is one `create` writes
+fn read(path: &Path) -> Option<Snapshot> {
+    let name = path.file_name()?.to_str()?;
+    let (stamp, operation) = name.strip_suffix(".db")?.split_once('-')?;
+    let stamp = stamp.split_once('.').map_or(stamp, |(stamp, _)| stamp);
+    let created_at = NaiveDateTime::parse_from_str(stamp, TIMESTAMP_FORMAT).ok()?.and_utc();
+    let operation = Operation::from_str(operation)?;
+    let size = fs::metadata(path).ok()?.len();
+    Some(Snapshot { name: name.to_string(), path: path.to_path_buf(), operation, created_at, size })
+}
+
+/// Order of snapshots taken in the same second (`<stamp>.<n>-<operation>.db`)
+fn sequence(name: &str) -> u32 {
+    name.split_once('-')
+        .and_then(|(stamp, _)| stamp.split_once('.'))
+        .and_then(|(_, n)| n.parse().ok())
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ClawBox;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_snapshots_rotate() {
+        let temp = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set_snapshot_keep(3).unwrap();
+
+        let first = vault.snapshot(Operation::Import).unwrap().unwrap();
+        assert_eq!(first.operation, Operation::Import);
+        assert!(first.name.ends_with("-import.db"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&first.path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        for _ in 0..3 {
+            vault.snapshot(Operation::Pull).unwrap();
+        }
+        // Same-second snapshots get distinct names; the oldest is pruned
+        let snapshots = list(temp.path()).unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert!(snapshots.iter().all(|snapshot| snapshot.name != first.name));
+        assert_eq!(prune(temp.path(), 1).unwrap().len(), 2);
+
+        // Snapshots stay out of what a sync sends
+        let remote = TempDir::new().unwrap();
+        let backend = crate::sync::DirBackend::new(remote.path());
+        vault.enable_sync().unwrap();
+        let mut manager = vault.sync_manager().unwrap();
+        vault.sync_with(&mut manager, &backend).unwrap();
+        assert!(!list(temp.path()).unwrap().is_empty());
+        let names: Vec<String> = walk(remote.path());
+        assert!(names.iter().all(|name| !name.contains(SNAPSHOT_DIR) && !name.ends_with(".db")), "{:?}", names);
+
+        // Turned off, existing ones are deleted and nothing is taken
+        vault.set_snapshot_keep(0).unwrap();
+        assert!(vault.snapshot(Operation::Import).unwrap().is_none());
+        assert!(list(temp.path()).unwrap().is_empty());
+    }
+
+    fn walk(dir: &Path) -> Vec<String> {
+        let mut names = vec![];
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            names.push(path.display().to_string());
+            if path.is_dir() {
+                names.extend(walk(&path));
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_restore_after_failed_change_password() {
+        let temp = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp.path()).unwrap();
+        vault.init("old-password").unwrap();
+        vault.set("db/url", "postgres://prod", Default::default()).unwrap();
+        vault.change_password("old-password", "new-password").unwrap();
+
+        // A buggy re-encryption: the rows no longer open with either key
+        let conn = rusqlite::Connection::open(temp.path().join("vault.db")).unwrap();
+        conn.execute("UPDATE secrets SET encrypted_value = zeroblob(40)", []).unwrap();
+        drop(conn);
+        assert!(vault.get_secret("db/url").is_err());
+
+        // The change was recorded with the snapshot taken before it
+        let audit = vault.audit(&Default::default()).unwrap();
+        let change = audit.iter().find(|entry| entry.action == crate::audit::Action::ChangePassword).unwrap();
+        let name = change.metadata.as_ref().unwrap()["snapshot"].as_str().unwrap().to_string();
+        assert!(name.ends_with("-change-password.db"));
+
+        let replaced = vault.restore_snapshot(&name).unwrap().unwrap();
+        assert_eq!(replaced.operation, Operation::Restore);
+        assert!(!vault.is_unlocked());
+        vault.unlock("old-password").unwrap();
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("postgres://prod"));
+
+        // Only files that open as a vault are swapped in
+        fs::write(temp.path().join(SNAPSHOT_DIR).join("20240601T120000-import.db"), b"garbage").unwrap();
+        assert!(matches!(vault.restore_snapshot("20240601T120000-import.db"), Err(Error::InvalidArgument(_))));
+        assert!(matches!(vault.restore_snapshot("../vault.db"), Err(Error::InvalidArgument(_))));
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("postgres://prod"));
+
+        // Swapping the database needs the key, like any other write
+        let taken = list(temp.path()).unwrap().len();
+        vault.lock();
+        assert!(matches!(vault.restore_snapshot(&name), Err(Error::VaultLocked)));
+        assert_eq!(list(temp.path()).unwrap().len(), taken);
+    }
+}
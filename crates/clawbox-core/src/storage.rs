@@ -2,9 +2,66 @@
 //!
 //! Uses SQLite for persistent storage
 
-use crate::{AccessLevel, Error, Result, SecretInfo};
-use rusqlite::Connection;
-use std::os::unix::fs::PermissionsExt;
+use crate::audit::{AuditEntry, AuditLogger};
+use crate::sync::{SyncChanges, SyncSecret};
+use crate::{
+    AccessLevel, Error, ListFilter, PruneOptions, PruneReport, Result, SecretInfo, TokenInfo, TokenScope, UnlockLockout,
+};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, TransactionBehavior};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+/// Current database schema version, recorded in `vault_meta`
+pub const SCHEMA_VERSION: u32 = 6;
+/// Keyspace of the secrets the master password opens
+pub const MAIN_KEYSPACE: u8 = 0;
+/// Keyspace of the decoy secrets the duress password opens; see
+/// `ClawBox::set_duress_password`
+pub const DURESS_KEYSPACE: u8 = 1;
+/// `created_by` of secrets written without a writer named, as by
+/// `SecretStore::set`
+const LOCAL_WRITER: &str = "human";
+/// `vault_meta` key counting local edits not yet pushed by a sync
+const PENDING_CHANGES_KEY: &str = "pending_changes";
+/// `vault_meta` keys of the wrong passwords in a row and the cooldown they
+/// triggered, as a Unix timestamp
+const UNLOCK_FAILURES_KEY: &str = "unlock_failures";
+const UNLOCK_LOCKED_UNTIL_KEY: &str = "unlock_locked_until";
+/// `vault_meta` key of the hash the audit chain starts from after pruning
+pub const AUDIT_ANCHOR_KEY: &str = "audit_anchor";
+
+/// Reads counted by `SqliteStore::take_read` in one calendar minute and the
+/// one before it
+#[derive(Debug, Clone, Copy, Default)]
+struct ReadCounts {
+    /// Unix time in minutes
+    minute: i64,
+    previous: u32,
+    current: u32,
+}
+
+impl ReadCounts {
+    /// The counts as of Unix time `at`, in seconds
+    fn at(self, at: i64) -> Self {
+        let minute = at.div_euclid(60);
+        let (previous, current) = match minute - self.minute {
+            0 => (self.previous, self.current),
+            1 => (self.current, 0),
+            _ => (0, 0),
+        };
+        Self { minute, previous, current }
+    }
+
+    /// Reads in the minute up to Unix time `at`
+    fn estimate(self, at: i64) -> f64 {
+        let counts = self.at(at);
+        let remaining = (60 - at.rem_euclid(60)) as f64 / 60.0;
+        counts.previous as f64 * remaining + counts.current as f64
+    }
+}
 
 /// Initialize database schema
 pub fn init_schema(conn: &Connection) -> Result<()> {
@@ -17,7 +74,8 @@ pub fn init_schema(conn: &Connection) -> Result<()> {
 
         CREATE TABLE IF NOT EXISTS secrets (
             id TEXT PRIMARY KEY,
-            path TEXT UNIQUE NOT NULL,
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            path TEXT NOT NULL,
             encrypted_value BLOB NOT NULL,
             access_level INTEGER NOT NULL DEFAULT 1,
             tags TEXT,
@@ -25,12 +83,23 @@ pub fn init_schema(conn: &Connection) -> Result<()> {
             ttl_expires_at INTEGER,
             created_at INTEGER NOT NULL,
             updated_at INTEGER NOT NULL,
-            created_by TEXT NOT NULL
+            created_by TEXT NOT NULL,
+            UNIQUE(keyspace, path)
         );
 
         CREATE INDEX IF NOT EXISTS idx_secrets_path ON secrets(path);
         CREATE INDEX IF NOT EXISTS idx_secrets_ttl ON secrets(ttl_expires_at);
 
+        -- One row per tag of each secret, kept up to date by the triggers
+        -- in SECRET_TAG_TRIGGERS, so listings by tag needn't read every row
+        CREATE TABLE IF NOT EXISTS secret_tags (
+            secret_id TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            PRIMARY KEY (secret_id, tag)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_secret_tags_tag ON secret_tags(tag);
+
         CREATE TABLE IF NOT EXISTS audit_log (
             id TEXT PRIMARY KEY,
             timestamp INTEGER NOT NULL,
@@ -41,65 +110,780 @@ pub fn init_schema(conn: &Connection) -> Result<()> {
             error_message TEXT,
             source TEXT NOT NULL,
             hash TEXT,
-            prev_hash TEXT
+            prev_hash TEXT,
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            metadata TEXT
         );
 
         CREATE INDEX IF NOT EXISTS idx_audit_timestamp ON audit_log(timestamp);
         CREATE INDEX IF NOT EXISTS idx_audit_key_path ON audit_log(key_path);
+
+        CREATE TABLE IF NOT EXISTS secret_versions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            path TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            encrypted_value BLOB NOT NULL,
+            access_level INTEGER NOT NULL,
+            tags TEXT,
+            note TEXT,
+            ttl_expires_at INTEGER,
+            created_at INTEGER NOT NULL,
+            created_by TEXT NOT NULL,
+            UNIQUE(keyspace, path, version)
+        );
+
+        CREATE TABLE IF NOT EXISTS access_tokens (
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            name TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            wrapped_key BLOB NOT NULL,
+            path_prefixes TEXT NOT NULL,
+            max_access INTEGER NOT NULL,
+            expires_at INTEGER,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (keyspace, name)
+        );
+
+        CREATE TABLE IF NOT EXISTS read_rates (
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            actor TEXT NOT NULL,
+            access_level INTEGER NOT NULL,
+            minute INTEGER NOT NULL,
+            previous INTEGER NOT NULL,
+            current INTEGER NOT NULL,
+            PRIMARY KEY (keyspace, actor, access_level)
+        );
         "#,
     )?;
 
+    migrate(conn)?;
+    // After migrating, which may add the `keyspace` column and rebuild
+    // `secrets` with its index and triggers
+    conn.execute_batch(SECRETS_ACCESS_INDEX)?;
+    conn.execute_batch(SECRET_TAG_TRIGGERS)?;
+    Ok(())
+}
+
+/// Lets listings by access level skip other levels; see `list_query`
+const SECRETS_ACCESS_INDEX: &str =
+    "CREATE INDEX IF NOT EXISTS idx_secrets_access ON secrets(keyspace, access_level, path);";
+
+/// Keep `secret_tags` in step with every write to `secrets`
+const SECRET_TAG_TRIGGERS: &str = r#"
+    CREATE TRIGGER IF NOT EXISTS secret_tags_insert AFTER INSERT ON secrets BEGIN
+        INSERT OR IGNORE INTO secret_tags (secret_id, tag)
+        SELECT NEW.id, value FROM json_each(CASE WHEN json_valid(NEW.tags) THEN NEW.tags END) WHERE type = 'text';
+    END;
+    CREATE TRIGGER IF NOT EXISTS secret_tags_update AFTER UPDATE OF id, tags ON secrets BEGIN
+        DELETE FROM secret_tags WHERE secret_id = OLD.id;
+        INSERT OR IGNORE INTO secret_tags (secret_id, tag)
+        SELECT NEW.id, value FROM json_each(CASE WHEN json_valid(NEW.tags) THEN NEW.tags END) WHERE type = 'text';
+    END;
+    CREATE TRIGGER IF NOT EXISTS secret_tags_delete AFTER DELETE ON secrets BEGIN
+        DELETE FROM secret_tags WHERE secret_id = OLD.id;
+    END;
+    "#;
+
+/// Bring databases written by older builds up to `SCHEMA_VERSION`
+fn migrate(conn: &Connection) -> Result<()> {
+    let stored: Option<u32> = conn
+        .query_row(
+            "SELECT value FROM vault_meta WHERE key = 'schema_version'",
+            [],
+            |row| row.get::<_, Vec<u8>>(0),
+        )
+        .ok()
+        .and_then(|v| String::from_utf8(v).ok())
+        .and_then(|v| v.parse().ok());
+    let stored = stored.unwrap_or(0);
+    if stored >= SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    if stored < 2 {
+        // Secrets written before version history start out as version 1
+        conn.execute(
+            r#"
+            INSERT INTO secret_versions (path, version, encrypted_value, access_level, tags, note, created_at, created_by)
+            SELECT path, 1, encrypted_value, access_level, tags, note, updated_at, created_by FROM secrets
+            WHERE path NOT IN (SELECT path FROM secret_versions)
+            "#,
+            [],
+        )?;
+    }
+
+    if stored < 3 && !has_column(conn, "secrets", "keyspace")? {
+        add_keyspaces(conn)?;
+    }
+
+    if stored < 4 && !has_column(conn, "audit_log", "metadata")? {
+        conn.execute("ALTER TABLE audit_log ADD COLUMN metadata TEXT", [])?;
+    }
+
+    if stored < 5 {
+        conn.execute("DELETE FROM secret_tags", [])?;
+        conn.execute(
+            r#"
+            INSERT OR IGNORE INTO secret_tags (secret_id, tag)
+            SELECT secrets.id, tag.value
+            FROM secrets, json_each(CASE WHEN json_valid(secrets.tags) THEN secrets.tags END) AS tag
+            WHERE tag.type = 'text'
+            "#,
+            [],
+        )?;
+    }
+
+    if stored < 6 && !has_column(conn, "secret_versions", "ttl_expires_at")? {
+        // Older versions keep no expiry
+        conn.execute("ALTER TABLE secret_versions ADD COLUMN ttl_expires_at INTEGER", [])?;
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO vault_meta (key, value) VALUES ('schema_version', ?)",
+        [SCHEMA_VERSION.to_string().into_bytes()],
+    )?;
+    Ok(())
+}
+
+/// `key` for vault metadata of `keyspace`; see `SqliteStore::keyspace_meta_key`
+pub(crate) fn keyspace_meta_key(keyspace: u8, key: &str) -> String {
+    match keyspace {
+        MAIN_KEYSPACE => key.to_string(),
+        keyspace => format!("keyspace{}.{}", keyspace, key),
+    }
+}
+
+/// Merge `metadata` into that of audit entry `id` through a connection of
+/// its own, from a thread that doesn't have the vault's
+pub(crate) fn set_audit_metadata(db_path: &std::path::Path, id: &str, metadata: &serde_json::Value) -> Result<()> {
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.execute("UPDATE audit_log SET metadata = json_patch(COALESCE(metadata, '{}'), ?) WHERE id = ?", rusqlite::params![metadata.to_string(), id])?;
+    Ok(())
+}
+
+/// Whether `table` has a column named `column`
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    for name in names {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Tag every row with the keyspace it belongs to, all of them the main one
+///
+/// Paths and token names are unique per keyspace, which SQLite can only
+/// change by rebuilding those tables. Rate limit counts are dropped.
+fn add_keyspaces(conn: &Connection) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(
+        r#"
+        CREATE TABLE secrets_new (
+            id TEXT PRIMARY KEY,
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            path TEXT NOT NULL,
+            encrypted_value BLOB NOT NULL,
+            access_level INTEGER NOT NULL DEFAULT 1,
+            tags TEXT,
+            note TEXT,
+            ttl_expires_at INTEGER,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            created_by TEXT NOT NULL,
+            UNIQUE(keyspace, path)
+        );
+        INSERT INTO secrets_new (id, path, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, updated_at, created_by)
+        SELECT id, path, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, updated_at, created_by FROM secrets;
+        DROP TABLE secrets;
+        ALTER TABLE secrets_new RENAME TO secrets;
+        CREATE INDEX idx_secrets_path ON secrets(path);
+        CREATE INDEX idx_secrets_ttl ON secrets(ttl_expires_at);
+        CREATE INDEX idx_secrets_access ON secrets(keyspace, access_level, path);
+
+        CREATE TABLE secret_versions_new (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            path TEXT NOT NULL,
+            version INTEGER NOT NULL,
+            encrypted_value BLOB NOT NULL,
+            access_level INTEGER NOT NULL,
+            tags TEXT,
+            note TEXT,
+            created_at INTEGER NOT NULL,
+            created_by TEXT NOT NULL,
+            UNIQUE(keyspace, path, version)
+        );
+        INSERT INTO secret_versions_new (id, path, version, encrypted_value, access_level, tags, note, created_at, created_by)
+        SELECT id, path, version, encrypted_value, access_level, tags, note, created_at, created_by FROM secret_versions;
+        DROP TABLE secret_versions;
+        ALTER TABLE secret_versions_new RENAME TO secret_versions;
+
+        ALTER TABLE audit_log ADD COLUMN keyspace INTEGER NOT NULL DEFAULT 0;
+
+        CREATE TABLE access_tokens_new (
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            name TEXT NOT NULL,
+            token_hash TEXT UNIQUE NOT NULL,
+            wrapped_key BLOB NOT NULL,
+            path_prefixes TEXT NOT NULL,
+            max_access INTEGER NOT NULL,
+            expires_at INTEGER,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (keyspace, name)
+        );
+        INSERT INTO access_tokens_new (name, token_hash, wrapped_key, path_prefixes, max_access, expires_at, created_at)
+        SELECT name, token_hash, wrapped_key, path_prefixes, max_access, expires_at, created_at FROM access_tokens;
+        DROP TABLE access_tokens;
+        ALTER TABLE access_tokens_new RENAME TO access_tokens;
+
+        DROP TABLE read_rates;
+        CREATE TABLE read_rates (
+            keyspace INTEGER NOT NULL DEFAULT 0,
+            actor TEXT NOT NULL,
+            access_level INTEGER NOT NULL,
+            minute INTEGER NOT NULL,
+            previous INTEGER NOT NULL,
+            current INTEGER NOT NULL,
+            PRIMARY KEY (keyspace, actor, access_level)
+        );
+        "#,
+    )?;
+    tx.commit()?;
     Ok(())
 }
 
+/// Plaintext size of a stored value, for `info_columns!`
+///
+/// Most values are a 12-byte nonce plus the ciphertext, which carries a
+/// 16-byte tag, so the stored length minus 28. Chunked ones (starting with
+/// `crypto::CHUNKED_MAGIC`, "CBS1") have a 12-byte header and add a 4-byte
+/// length and a tag to every chunk of up to 64 KiB. Macros rather than
+/// consts so the statements below can `concat!` them.
+macro_rules! stored_size {
+    () => {
+        "CASE WHEN substr(encrypted_value, 1, 4) = X'43425331' \
+         THEN (length(encrypted_value) - 12) - 20 * ((length(encrypted_value) - 12 + 65555) / 65556) \
+         ELSE MAX(length(encrypted_value) - 28, 0) END"
+    };
+}
+
+/// Columns read by `row_to_info`
+macro_rules! info_columns {
+    () => {
+        concat!("path, access_level, tags, note, created_at, updated_at, ttl_expires_at, ", stored_size!())
+    };
+}
+
+/// Columns read by `row_to_version`
+macro_rules! version_columns {
+    () => {
+        concat!(
+            "path, access_level, tags, note, created_at, created_at, ttl_expires_at, ",
+            stored_size!(),
+            ", version, encrypted_value, created_by"
+        )
+    };
+}
+
+/// An access token as stored: its hash and the vault key it unwraps
+pub struct StoredToken {
+    pub info: TokenInfo,
+    pub token_hash: String,
+    /// The vault key sealed with a key derived from the token
+    pub wrapped_key: Vec<u8>,
+}
+
+/// Columns read by `row_to_token`
+macro_rules! token_columns {
+    () => {
+        "name, path_prefixes, max_access, expires_at, created_at, token_hash, wrapped_key"
+    };
+}
+
+// Statements run on every read and write, prepared once per connection with
+// `prepare_cached`. The SQL text is the cache key, so it is fixed here and
+// the keyspace is always a parameter. One-off maintenance statements
+// (migrations, prune, VACUUM, ATTACH) are prepared fresh.
+const SELECT_META: &str = "SELECT value FROM vault_meta WHERE key = ?";
+const UPSERT_META: &str = "INSERT OR REPLACE INTO vault_meta (key, value) VALUES (?, ?)";
+const DELETE_META: &str = "DELETE FROM vault_meta WHERE key = ?";
+const SELECT_VALUE: &str = "SELECT encrypted_value FROM secrets WHERE keyspace = ? AND path = ?";
+const SELECT_INFO: &str = concat!("SELECT ", info_columns!(), " FROM secrets WHERE keyspace = ? AND path = ?");
+const SELECT_INFO_AND_VALUE: &str =
+    concat!("SELECT ", info_columns!(), ", encrypted_value FROM secrets WHERE keyspace = ? AND path = ?");
+const SELECT_PREFIX: &str = concat!(
+    "SELECT ",
+    info_columns!(),
+    ", encrypted_value FROM secrets WHERE keyspace = ? AND path LIKE ? ESCAPE '\\' ORDER BY path"
+);
+const SECRET_EXISTS: &str = "SELECT 1 FROM secrets WHERE keyspace = ? AND path = ?";
+const DELETE_SECRET: &str = "DELETE FROM secrets WHERE keyspace = ? AND path = ?";
+const DELETE_VERSIONS: &str = "DELETE FROM secret_versions WHERE keyspace = ? AND path = ?";
+const UPSERT_SECRET: &str = r#"
+    INSERT INTO secrets (id, path, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, updated_at, created_by, keyspace)
+    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ON CONFLICT(keyspace, path) DO UPDATE SET
+        encrypted_value = excluded.encrypted_value,
+        access_level = excluded.access_level,
+        tags = excluded.tags,
+        note = excluded.note,
+        ttl_expires_at = excluded.ttl_expires_at,
+        updated_at = excluded.updated_at
+    "#;
+const INSERT_VERSION: &str = r#"
+    INSERT INTO secret_versions (path, version, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, created_by, keyspace)
+    SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9
+    FROM secret_versions WHERE keyspace = ?9 AND path = ?1
+    "#;
+const SELECT_VERSIONS: &str =
+    concat!("SELECT ", version_columns!(), " FROM secret_versions WHERE keyspace = ? AND path = ? ORDER BY version DESC");
+const SELECT_VERSION: &str =
+    concat!("SELECT ", version_columns!(), " FROM secret_versions WHERE keyspace = ? AND path = ? AND version = ?");
+const SELECT_READ_RATE: &str =
+    "SELECT minute, previous, current FROM read_rates WHERE keyspace = ? AND actor = ? AND access_level = ?";
+const UPSERT_READ_RATE: &str = "INSERT OR REPLACE INTO read_rates (keyspace, actor, access_level, minute, previous, current) \
+                                VALUES (?, ?, ?, ?, ?, ?)";
+const INSERT_TOKEN: &str = "INSERT OR IGNORE INTO access_tokens \
+                            (keyspace, name, token_hash, wrapped_key, path_prefixes, max_access, expires_at, created_at) \
+                            VALUES (?, ?, ?, ?, ?, ?, ?, ?)";
+const SELECT_TOKEN_BY_HASH: &str = concat!("SELECT ", token_columns!(), ", keyspace FROM access_tokens WHERE token_hash = ?");
+const SELECT_TOKENS: &str = concat!("SELECT ", token_columns!(), " FROM access_tokens WHERE keyspace = ? ORDER BY name");
+const DELETE_TOKEN: &str = "DELETE FROM access_tokens WHERE keyspace = ? AND name = ?";
+const DELETE_TOKENS: &str = "DELETE FROM access_tokens WHERE keyspace = ?";
+const COUNT_BY_ACCESS: &str = "SELECT access_level, COUNT(*) FROM secrets WHERE keyspace = ? GROUP BY access_level";
+const LAST_WRITE: &str = "SELECT MAX(updated_at) FROM secrets WHERE keyspace = ?";
+const SELECT_RECORDS: &str = "SELECT path, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, updated_at \
+                              FROM secrets WHERE keyspace = 0 ORDER BY path";
+const WRITE_RECORD: &str = r#"
+    INSERT INTO secrets (id, path, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, updated_at, created_by)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 'sync')
+    ON CONFLICT(keyspace, path) DO UPDATE SET
+        encrypted_value = excluded.encrypted_value,
+        access_level = excluded.access_level,
+        tags = excluded.tags,
+        note = excluded.note,
+        ttl_expires_at = excluded.ttl_expires_at,
+        created_at = excluded.created_at,
+        updated_at = excluded.updated_at
+    WHERE encrypted_value IS NOT excluded.encrypted_value
+        OR access_level IS NOT excluded.access_level
+        OR tags IS NOT excluded.tags
+        OR note IS NOT excluded.note
+        OR ttl_expires_at IS NOT excluded.ttl_expires_at
+        OR updated_at IS NOT excluded.updated_at
+    "#;
+const INSERT_RECORD_VERSION: &str = r#"
+    INSERT INTO secret_versions (path, version, encrypted_value, access_level, tags, note, ttl_expires_at, created_at, created_by)
+    SELECT ?1, COALESCE(MAX(version), 0) + 1, ?2, ?3, ?4, ?5, ?6, ?7, 'sync'
+    FROM secret_versions WHERE keyspace = 0 AND path = ?1
+    "#;
+/// Statements `prepare_cached` keeps per connection; the ones above plus
+/// the audit log's, with room to spare
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/// How long a connection waits for another connection's lock, in this
+/// process or another, before giving up with `SQLITE_BUSY`
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Idle read connections a store keeps open for its `StoreReader`s
+const READ_POOL_SIZE: usize = 4;
+/// Largest page cache and memory map `StoreOptions` allows, in bytes
+const MAX_MAPPED_BYTES: u64 = 1 << 30;
+
+/// SQLite settings applied to a store's connections right after they are
+/// opened; see `SqliteStore::open_with`
+///
+/// The defaults are SQLite's own. On slow storage, such as an iCloud folder
+/// or a network mount, a larger cache and `Synchronous::Normal` trade some
+/// durability for fewer round trips; memory mapping is best left off there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreOptions {
+    /// Bytes per database page, a power of two from 512 to 65536; only a
+    /// new database takes it, an existing one keeps its own
+    pub page_size: u32,
+    /// Page cache as SQLite reads it: negative in KiB, positive in pages;
+    /// at most 1 GiB either way
+    pub cache_size: i64,
+    /// Bytes of the database file to memory-map, 0 (off) to 1 GiB
+    pub mmap_size: u64,
+    /// When SQLite waits for the disk; see `Synchronous`
+    pub synchronous: Synchronous,
+    /// Pages the write-ahead log may reach before it is checkpointed, at
+    /// most 1,000,000; 0 leaves checkpoints to SQLite's callers
+    pub wal_autocheckpoint: u32,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self { page_size: 4096, cache_size: -2000, mmap_size: 0, synchronous: Synchronous::Full, wal_autocheckpoint: 1000 }
+    }
+}
+
+impl StoreOptions {
+    /// Fail with `Error::InvalidArgument` naming the first setting out of range
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |name: &str, allowed: &str| Err(Error::InvalidArgument(format!("{} must be {}", name, allowed)));
+        if !(512..=65536).contains(&self.page_size) || !self.page_size.is_power_of_two() {
+            return invalid("page_size", "a power of two from 512 to 65536");
+        }
+        let cache_bytes = match self.cache_size {
+            kib if kib < 0 => kib.unsigned_abs().saturating_mul(1024),
+            pages => pages.unsigned_abs().saturating_mul(self.page_size as u64),
+        };
+        if cache_bytes > MAX_MAPPED_BYTES {
+            return invalid("cache_size", "at most 1 GiB");
+        }
+        if self.mmap_size > MAX_MAPPED_BYTES {
+            return invalid("mmap_size", "at most 1 GiB");
+        }
+        if self.wal_autocheckpoint > 1_000_000 {
+            return invalid("wal_autocheckpoint", "at most 1000000 pages");
+        }
+        Ok(())
+    }
+
+    /// Set the pragmas on `conn`; `page_size` and `wal_autocheckpoint` only
+    /// matter where the database is written
+    fn apply(&self, conn: &Connection, writable: bool) -> Result<()> {
+        if writable {
+            conn.pragma_update(None, "page_size", self.page_size)?;
+            conn.pragma_update_and_check(None, "wal_autocheckpoint", self.wal_autocheckpoint, |_| Ok(()))?;
+        }
+        conn.pragma_update(None, "cache_size", self.cache_size)?;
+        conn.pragma_update_and_check(None, "mmap_size", self.mmap_size, |_| Ok(()))?;
+        conn.pragma_update(None, "synchronous", self.synchronous.as_str())?;
+        Ok(())
+    }
+}
+
+/// SQLite's `synchronous` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Synchronous {
+    /// Never wait; a power loss can corrupt the database
+    Off,
+    /// Wait at the critical moments only; in WAL mode a power loss can undo
+    /// the last commits but not corrupt the database
+    Normal,
+    /// Wait on every commit
+    #[default]
+    Full,
+    /// `Full`, and also for the journal's directory entry
+    Extra,
+}
+
+impl Synchronous {
+    fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+
+    fn from_level(level: i64) -> Option<Self> {
+        Some(match level {
+            0 => Synchronous::Off,
+            1 => Synchronous::Normal,
+            2 => Synchronous::Full,
+            3 => Synchronous::Extra,
+            _ => return None,
+        })
+    }
+}
+
+/// The settings a store's connection is running with, as SQLite reports
+/// them; see `SqliteStore::pragmas`
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize)]
+pub struct StorePragmas {
+    pub page_size: u32,
+    pub cache_size: i64,
+    pub mmap_size: u64,
+    pub synchronous: Option<Synchronous>,
+    pub wal_autocheckpoint: u32,
+    pub journal_mode: String,
+}
+
 /// Secret store trait
 pub trait SecretStore {
     fn get(&self, path: &str) -> Result<Option<Vec<u8>>>;
     fn set(&mut self, path: &str, value: &[u8], info: &SecretInfo) -> Result<()>;
     fn delete(&mut self, path: &str) -> Result<bool>;
-    fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>>;
+
+    /// Call `f` with the metadata of every secret `filter` matches, by
+    /// path, reading one row at a time; stops at the first error `f` returns
+    fn for_each_secret(&self, filter: &ListFilter, f: &mut dyn FnMut(SecretInfo) -> Result<()>) -> Result<()>;
+
+    /// Metadata of every secret matching `pattern` (`*` as wildcard), by path
+    fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        let mut secrets = vec![];
+        self.for_each_secret(&ListFilter::pattern(pattern), &mut |info| {
+            secrets.push(info);
+            Ok(())
+        })?;
+        Ok(secrets)
+    }
+}
+
+/// A stored version of a secret, still encrypted
+#[derive(Debug, Clone)]
+pub struct StoredVersion {
+    pub version: u32,
+    pub value: Vec<u8>,
+    /// Metadata at the time of this version; `created_at` and `updated_at`
+    /// are both the time the version was written
+    pub info: SecretInfo,
+    pub created_by: String,
 }
 
 /// SQLite-based secret store
+///
+/// Secrets, their versions, the audit log and access tokens belong to a
+/// keyspace; the store only sees those of its current keyspace, which is
+/// `MAIN_KEYSPACE` until changed with `set_keyspace`.
 pub struct SqliteStore {
     conn: Connection,
+    keyspace: Cell<u8>,
+    /// Where audit entries go when the database is opened read-only
+    audit_sidecar: Option<std::path::PathBuf>,
+    readers: Arc<ReadPool>,
+    /// All of `vault_meta`, read at open and kept current by this store's
+    /// writes, with the `data_version` it was read at; see `refresh_meta`
+    meta: RefCell<(HashMap<String, Vec<u8>>, i64)>,
+    options: StoreOptions,
 }
 
-/// Validate a secret key path
-pub fn validate_key_path(path: &str) -> Result<()> {
-    // Check for empty path
+/// Read-only connections to a store's database, opened as needed and shared
+/// by its `StoreReader`s
+struct ReadPool {
+    path: std::path::PathBuf,
+    idle: Mutex<Vec<Connection>>,
+    options: StoreOptions,
+}
+
+impl ReadPool {
+    fn new(path: &std::path::Path, options: &StoreOptions) -> Arc<Self> {
+        Arc::new(Self { path: path.to_path_buf(), idle: Mutex::new(vec![]), options: options.clone() })
+    }
+
+    fn take(&self) -> Result<Connection> {
+        if let Some(conn) = self.idle.lock().unwrap_or_else(PoisonError::into_inner).pop() {
+            return Ok(conn);
+        }
+        let conn = Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        self.options.apply(&conn, false)?;
+        Ok(conn)
+    }
+
+    /// Keep `conn` for the next reader, unless enough are idle already
+    fn put(&self, conn: Connection) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < READ_POOL_SIZE {
+            idle.push(conn);
+        }
+    }
+}
+
+/// Reads secrets and metadata of a `SqliteStore` through connections of
+/// its own, from any thread
+///
+/// Made by `SqliteStore::reader`; clones share the same connections, and
+/// none of them waits for the store or its other readers, only for SQLite's
+/// own locks while a write commits. Reads the keyspace that was current
+/// when it was made, and sees everything committed before each read starts.
+#[derive(Clone)]
+pub struct StoreReader {
+    pool: Arc<ReadPool>,
+    keyspace: u8,
+}
+
+impl StoreReader {
+    /// A read transaction, so that several reads see the same state
+    ///
+    /// The state is the one as of its first read. Writers, here or in other
+    /// processes, can't commit until it is dropped, so keep it short.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let conn = self.pool.take()?;
+        conn.execute_batch("BEGIN")?;
+        Ok(Snapshot { pool: self.pool.clone(), conn: Some(conn), keyspace: self.keyspace })
+    }
+
+    /// Encrypted value of a secret
+    pub fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        self.snapshot()?.get(path)
+    }
+
+    /// Metadata of a secret
+    pub fn get_info(&self, path: &str) -> Result<Option<SecretInfo>> {
+        self.snapshot()?.get_info(path)
+    }
+
+    /// See `SqliteStore::get_prefix`
+    pub fn get_prefix(&self, prefix: &str) -> Result<Vec<(SecretInfo, Vec<u8>)>> {
+        self.snapshot()?.get_prefix(prefix)
+    }
+
+    /// See `SecretStore::for_each_secret`
+    pub fn for_each_secret(&self, filter: &ListFilter, f: &mut dyn FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+        self.snapshot()?.for_each_secret(filter, f)
+    }
+
+    /// See `SecretStore::list`
+    pub fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        self.snapshot()?.list(pattern)
+    }
+
+    /// Vault metadata
+    pub fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        self.snapshot()?.get_meta(key)
+    }
+}
+
+/// A read transaction on one of a store's read connections; see
+/// `StoreReader::snapshot`
+pub struct Snapshot {
+    pool: Arc<ReadPool>,
+    conn: Option<Connection>,
+    keyspace: u8,
+}
+
+impl Snapshot {
+    fn conn(&self) -> &Connection {
+        self.conn.as_ref().expect("connection taken before drop")
+    }
+
+    /// Encrypted value of a secret
+    pub fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        select_value(self.conn(), self.keyspace, path)
+    }
+
+    /// Metadata of a secret
+    pub fn get_info(&self, path: &str) -> Result<Option<SecretInfo>> {
+        select_info(self.conn(), self.keyspace, path)
+    }
+
+    /// See `SqliteStore::get_prefix`
+    pub fn get_prefix(&self, prefix: &str) -> Result<Vec<(SecretInfo, Vec<u8>)>> {
+        select_prefix(self.conn(), self.keyspace, prefix)
+    }
+
+    /// See `SecretStore::for_each_secret`
+    pub fn for_each_secret(&self, filter: &ListFilter, f: &mut dyn FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+        each_secret(self.conn(), self.keyspace, filter, f)
+    }
+
+    /// See `SecretStore::list`
+    pub fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        let mut secrets = vec![];
+        self.for_each_secret(&ListFilter::pattern(pattern), &mut |info| {
+            secrets.push(info);
+            Ok(())
+        })?;
+        Ok(secrets)
+    }
+
+    /// Vault metadata
+    pub fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        select_meta(self.conn(), key)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            // A connection stuck in its transaction is closed instead
+            if conn.execute_batch("ROLLBACK").is_ok() {
+                self.pool.put(conn);
+            }
+        }
+    }
+}
+
+/// File next to a read-only database that audit entries are appended to
+pub const AUDIT_SIDECAR_FILE: &str = "audit-read-only.jsonl";
+
+/// Longest key path accepted for a new secret, in bytes
+pub const MAX_KEY_PATH_LEN: usize = 256;
+
+/// What makes `path` unsafe to store, if anything
+fn key_path_problem(path: &str) -> Option<&'static str> {
     if path.is_empty() {
-        return Err(Error::Other("Key path cannot be empty".to_string()));
+        Some("cannot be empty")
+    } else if path.contains("..") {
+        Some("cannot contain '..'")
+    } else if path.starts_with('/') {
+        Some("cannot start with '/'")
+    } else if path.contains('\0') {
+        Some("cannot contain null bytes")
+    } else if path.chars().any(|c| c.is_control()) {
+        Some("cannot contain control characters")
+    } else {
+        None
     }
-    
-    // Check for path traversal
-    if path.contains("..") {
-        return Err(Error::Other("Key path cannot contain '..'".to_string()));
+}
+
+fn invalid_path(path: &str, reason: &str) -> Error {
+    Error::InvalidPath { path: path.to_string(), reason: reason.to_string() }
+}
+
+/// Validate a secret key path as stored
+///
+/// New paths go through `normalize_and_validate_path`; this only keeps out
+/// paths that would be unsafe anywhere, such as from a synced vault.
+pub fn validate_key_path(path: &str) -> Result<()> {
+    match key_path_problem(path) {
+        Some(reason) => Err(invalid_path(path, reason)),
+        None => Ok(()),
     }
-    
-    // Check for absolute paths
-    if path.starts_with('/') {
-        return Err(Error::Other("Key path cannot start with '/'".to_string()));
+}
+
+/// Normalize the path of a secret about to be created, and check it
+///
+/// Trims surrounding whitespace and collapses repeated slashes, then rejects
+/// paths that `validate_key_path` would, end in '/' or are longer than
+/// `MAX_KEY_PATH_LEN`. Errors carry `path` as given. Stored secrets whose
+/// paths break these rules stay readable and deletable under them.
+pub fn normalize_and_validate_path(path: &str) -> Result<String> {
+    let mut normalized = String::with_capacity(path.len());
+    for c in path.trim().chars() {
+        if !(c == '/' && normalized.ends_with('/')) {
+            normalized.push(c);
+        }
     }
-    
-    // Check for null bytes
-    if path.contains('\0') {
-        return Err(Error::Other("Key path cannot contain null bytes".to_string()));
+
+    if let Some(reason) = key_path_problem(&normalized) {
+        return Err(invalid_path(path, reason));
     }
-    
-    // Check for control characters
-    if path.chars().any(|c| c.is_control()) {
-        return Err(Error::Other("Key path cannot contain control characters".to_string()));
+    if normalized.ends_with('/') {
+        return Err(invalid_path(path, "cannot end with '/'"));
     }
-    
-    Ok(())
+    if normalized.len() > MAX_KEY_PATH_LEN {
+        return Err(invalid_path(path, &format!("longer than {} bytes", MAX_KEY_PATH_LEN)));
+    }
+    Ok(normalized)
 }
 
 impl SqliteStore {
     /// Open or create a store at the given path
     pub fn open(path: &std::path::Path) -> Result<Self> {
+        Self::open_with(path, &StoreOptions::default())
+    }
+
+    /// Open or create a store at the given path with the SQLite settings
+    /// in `options`, which must pass `StoreOptions::validate`
+    pub fn open_with(path: &std::path::Path, options: &StoreOptions) -> Result<Self> {
+        options.validate()?;
         let is_new = !path.exists();
         let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        options.apply(&conn, true)?;
         init_schema(&conn)?;
         
         // Set restrictive permissions on new database files (Unix only)
@@ -110,137 +894,1141 @@ impl SqliteStore {
             }
         }
         
-        Ok(Self { conn })
+        let store = Self {
+            conn,
+            keyspace: Cell::new(MAIN_KEYSPACE),
+            audit_sidecar: None,
+            readers: ReadPool::new(path, options),
+            meta: RefCell::default(),
+            options: options.clone(),
+        };
+        store.reload_meta()?;
+        Ok(store)
+    }
+
+    /// Open an existing database without ever writing to it
+    ///
+    /// Audit entries are appended to `AUDIT_SIDECAR_FILE` in the same
+    /// directory instead. Databases that need a schema upgrade must be opened
+    /// with `open` once first.
+    pub fn open_read_only(path: &std::path::Path) -> Result<Self> {
+        Self::open_read_only_with(path, &StoreOptions::default())
+    }
+
+    /// `open_read_only` with the SQLite settings in `options`
+    pub fn open_read_only_with(path: &std::path::Path, options: &StoreOptions) -> Result<Self> {
+        options.validate()?;
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        options.apply(&conn, false)?;
+        let store = Self {
+            conn,
+            keyspace: Cell::new(MAIN_KEYSPACE),
+            audit_sidecar: Some(path.with_file_name(AUDIT_SIDECAR_FILE)),
+            readers: ReadPool::new(path, options),
+            meta: RefCell::default(),
+            options: options.clone(),
+        };
+        store.reload_meta()?;
+        if store.schema_version()? < SCHEMA_VERSION {
+            return Err(Error::Other(
+                "The vault database needs an upgrade; open it read-write once first".to_string(),
+            ));
+        }
+        Ok(store)
+    }
+
+    /// Whether the database was opened with `open_read_only`
+    pub fn is_read_only(&self) -> bool {
+        self.audit_sidecar.is_some()
+    }
+
+    /// The settings the store was opened with
+    pub fn options(&self) -> &StoreOptions {
+        &self.options
+    }
+
+    /// The SQLite settings in effect on the store's connection, which can
+    /// differ from `options`: an existing database keeps its page size, and
+    /// SQLite may cap the memory map
+    pub fn pragmas(&self) -> Result<StorePragmas> {
+        let pragma = |name: &str| self.conn.pragma_query_value(None, name, |row| row.get::<_, i64>(0));
+        Ok(StorePragmas {
+            page_size: pragma("page_size")? as u32,
+            cache_size: pragma("cache_size")?,
+            mmap_size: pragma("mmap_size")? as u64,
+            synchronous: Synchronous::from_level(pragma("synchronous")?),
+            wal_autocheckpoint: pragma("wal_autocheckpoint")? as u32,
+            journal_mode: self.conn.pragma_query_value(None, "journal_mode", |row| row.get(0))?,
+        })
+    }
+
+    /// A handle for reading the current keyspace from other threads, on
+    /// connections separate from this store's
+    pub fn reader(&self) -> StoreReader {
+        StoreReader { pool: self.readers.clone(), keyspace: self.keyspace() }
+    }
+
+    /// Record `entry` in the current keyspace's audit log, or append it to
+    /// the sidecar file if the database is read-only
+    pub fn log_audit(&self, entry: AuditEntry) -> Result<()> {
+        let Some(sidecar) = &self.audit_sidecar else {
+            return self.audit_logger().log(entry);
+        };
+        let mut line = serde_json::to_value(&entry)?;
+        line["keyspace"] = self.keyspace().into();
+        let mut line = serde_json::to_vec(&line)?;
+        line.push(b'\n');
+
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).mode(0o600).open(sidecar)?;
+        std::io::Write::write_all(&mut file, &line)?;
+        Ok(())
+    }
+
+    /// Record several entries as `log_audit` does, in one transaction
+    pub fn log_audit_many(&self, entries: &[AuditEntry]) -> Result<()> {
+        if self.audit_sidecar.is_none() {
+            return self.audit_logger().log_many(entries);
+        }
+        entries.iter().try_for_each(|entry| self.log_audit(entry.clone()))
+    }
+
+    /// Keyspace the store reads and writes
+    pub fn keyspace(&self) -> u8 {
+        self.keyspace.get()
+    }
+
+    /// Switch to another keyspace, e.g. when the vault is unlocked or locked
+    pub fn set_keyspace(&self, keyspace: u8) {
+        self.keyspace.set(keyspace);
+    }
+
+    /// `key` for vault metadata kept separately per keyspace, such as
+    /// policies and the audit anchor
+    pub fn keyspace_meta_key(&self, key: &str) -> String {
+        keyspace_meta_key(self.keyspace(), key)
+    }
+
+    /// Delete everything stored in `keyspace`: its secrets and their
+    /// history, audit log, access tokens and metadata
+    pub fn clear_keyspace(&self, keyspace: u8) -> Result<()> {
+        let tx = rusqlite::Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        for table in ["secrets", "secret_versions", "audit_log", "access_tokens", "read_rates"] {
+            tx.execute(&format!("DELETE FROM {} WHERE keyspace = ?", table), [keyspace])?;
+        }
+        if keyspace != MAIN_KEYSPACE {
+            tx.execute("DELETE FROM vault_meta WHERE key LIKE ?", [keyspace_meta_key(keyspace, "%")])?;
+        }
+        tx.commit()?;
+        self.reload_meta()
+    }
+
+    /// Delete the secrets, history, audit log, access tokens and read counts
+    /// of `keyspace` and write `meta` in their place, in one transaction;
+    /// its other metadata, such as policies, stays
+    pub fn reset_keyspace(&self, keyspace: u8, meta: &[(String, Vec<u8>)]) -> Result<()> {
+        let tx = rusqlite::Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        for table in ["secrets", "secret_versions", "audit_log", "access_tokens", "read_rates"] {
+            tx.execute(&format!("DELETE FROM {} WHERE keyspace = ?", table), [keyspace])?;
+        }
+        // The new audit log starts a chain of its own
+        tx.execute("DELETE FROM vault_meta WHERE key = ?", [keyspace_meta_key(keyspace, AUDIT_ANCHOR_KEY)])?;
+        for (key, value) in meta {
+            tx.execute("INSERT OR REPLACE INTO vault_meta (key, value) VALUES (?, ?)", rusqlite::params![key, value])?;
+        }
+        tx.commit()?;
+        self.reload_meta()
+    }
+
+    /// Audit logger for the current keyspace
+    pub fn audit_logger(&self) -> AuditLogger<'_> {
+        AuditLogger::for_keyspace(&self.conn, self.keyspace())
     }
 
-    /// Get vault metadata
+    /// Get vault metadata, from the copy read at open
     pub fn get_meta(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let mut stmt = self.conn.prepare("SELECT value FROM vault_meta WHERE key = ?")?;
-        let result = stmt.query_row([key], |row| row.get(0));
-        
-        match result {
-            Ok(value) => Ok(Some(value)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
+        Ok(self.meta.borrow().0.get(key).cloned())
+    }
+
+    /// Read `vault_meta` again if another connection (another process, or a
+    /// sync's own) committed to the database since it was read; returns
+    /// whether it did
+    ///
+    /// Costs one `PRAGMA data_version` when nothing changed. A database file
+    /// replaced under the store needs `open` instead (see `ClawBox::reload`).
+    pub fn refresh_meta(&self) -> Result<bool> {
+        if self.data_version()? == self.meta.borrow().1 {
+            return Ok(false);
+        }
+        self.reload_meta()?;
+        Ok(true)
+    }
+
+    /// Read `vault_meta` again, after writing it other than through
+    /// `set_meta`, e.g. with `connection()`
+    pub fn reload_meta(&self) -> Result<()> {
+        let version = self.data_version()?;
+        *self.meta.borrow_mut() = (select_all_meta(&self.conn)?, version);
+        Ok(())
+    }
+
+    /// A number stored as text in `vault_meta`; unreadable values count as unset
+    ///
+    /// These are counters every process using the vault updates, so they are
+    /// read from the database rather than the copy.
+    fn get_meta_number<T: std::str::FromStr>(&self, key: &str) -> Result<Option<T>> {
+        Ok(select_meta(&self.conn, key)?
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok()))
+    }
+
+    /// Local edits made since the last sync that pushed them; none outside
+    /// the main keyspace
+    pub fn pending_changes(&self) -> Result<u64> {
+        if self.keyspace() != MAIN_KEYSPACE {
+            return Ok(0);
+        }
+        Ok(self.get_meta_number(PENDING_CHANGES_KEY)?.unwrap_or(0))
+    }
+
+    /// Count `n` more local edits for the next sync to push
+    ///
+    /// Only the main keyspace syncs, so edits elsewhere don't count.
+    pub fn add_pending_changes(&mut self, n: u64) -> Result<()> {
+        if self.keyspace() != MAIN_KEYSPACE {
+            return Ok(());
+        }
+        let pending = self.pending_changes()?.saturating_add(n);
+        self.set_meta(PENDING_CHANGES_KEY, pending.to_string().as_bytes())
+    }
+
+    /// Settle the `pushed` edits that were pending when a sync took the
+    /// copy it pushed; edits made since then stay pending
+    pub fn settle_pending_changes(&mut self, pushed: u64) -> Result<()> {
+        if self.keyspace() != MAIN_KEYSPACE {
+            return Ok(());
+        }
+        let pending = self.pending_changes()?.saturating_sub(pushed);
+        self.set_meta(PENDING_CHANGES_KEY, pending.to_string().as_bytes())
+    }
+
+    /// Wrong passwords given since the last successful unlock
+    pub fn unlock_lockout(&self) -> Result<UnlockLockout> {
+        Ok(UnlockLockout {
+            failures: self.get_meta_number(UNLOCK_FAILURES_KEY)?.unwrap_or(0),
+            locked_until: self
+                .get_meta_number(UNLOCK_LOCKED_UNTIL_KEY)?
+                .and_then(|at| chrono::DateTime::from_timestamp(at, 0)),
+        })
+    }
+
+    /// Persist the unlock lockout, both fields in one transaction
+    pub fn set_unlock_lockout(&self, lockout: &UnlockLockout) -> Result<()> {
+        let tx = rusqlite::Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let mut upsert = tx.prepare_cached(UPSERT_META)?;
+        upsert.execute(rusqlite::params![UNLOCK_FAILURES_KEY, lockout.failures.to_string().as_bytes()])?;
+        match lockout.locked_until {
+            Some(at) => upsert.execute(rusqlite::params![UNLOCK_LOCKED_UNTIL_KEY, at.timestamp().to_string().as_bytes()])?,
+            None => tx.prepare_cached(DELETE_META)?.execute([UNLOCK_LOCKED_UNTIL_KEY])?,
+        };
+        drop(upsert);
+        tx.commit()?;
+        self.reload_meta()
+    }
+
+    /// Count a read by `actor` of an `access` secret, unless `per_minute`
+    /// reads were already counted in the last minute; then returns how long
+    /// until the next one is allowed
+    ///
+    /// The last minute is estimated from the counts of the current and the
+    /// previous calendar minute, weighting the previous one by how much of
+    /// it is still inside the window. Counts are kept in the database so
+    /// every process using the vault shares them.
+    pub fn take_read(
+        &self,
+        actor: &str,
+        access: AccessLevel,
+        per_minute: u32,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Option<std::time::Duration>> {
+        let tx = rusqlite::Transaction::new_unchecked(&self.conn, TransactionBehavior::Immediate)?;
+        let counts: ReadCounts = tx
+            .prepare_cached(SELECT_READ_RATE)?
+            .query_row(rusqlite::params![self.keyspace(), actor, access as i32], |row| {
+                Ok(ReadCounts { minute: row.get(0)?, previous: row.get(1)?, current: row.get(2)? })
+            })
+            .optional()?
+            .unwrap_or_default();
+
+        let now = now.timestamp();
+        let allowed = |at: i64| counts.estimate(at) + 1.0 <= per_minute as f64;
+        if !allowed(now) {
+            // Both counts have aged out two minutes on
+            let wait = (1..=120).find(|&secs| allowed(now + secs)).unwrap_or(120);
+            return Ok(Some(std::time::Duration::from_secs(wait as u64)));
         }
+
+        let counts = counts.at(now);
+        tx.prepare_cached(UPSERT_READ_RATE)?.execute(rusqlite::params![
+            self.keyspace(),
+            actor,
+            access as i32,
+            counts.minute,
+            counts.previous,
+            counts.current + 1
+        ])?;
+        tx.commit()?;
+        Ok(None)
     }
 
     /// Set vault metadata
     pub fn set_meta(&mut self, key: &str, value: &[u8]) -> Result<()> {
-        self.conn.execute(
-            "INSERT OR REPLACE INTO vault_meta (key, value) VALUES (?, ?)",
-            rusqlite::params![key, value],
-        )?;
+        self.conn.prepare_cached(UPSERT_META)?.execute(rusqlite::params![key, value])?;
+        self.meta.get_mut().0.insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    /// Remove vault metadata
+    pub fn delete_meta(&mut self, key: &str) -> Result<()> {
+        self.conn.prepare_cached(DELETE_META)?.execute([key])?;
+        self.meta.get_mut().0.remove(key);
         Ok(())
     }
     
-    /// Get database connection reference (for audit logging)
-    pub fn connection(&self) -> &Connection {
-        &self.conn
+    /// Get metadata for a single secret
+    pub fn get_info(&self, path: &str) -> Result<Option<SecretInfo>> {
+        select_info(&self.conn, self.keyspace(), path)
     }
-}
 
-impl SecretStore for SqliteStore {
-    fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
-        let mut stmt = self
+    /// Metadata and encrypted value of every secret below `prefix`, by path,
+    /// in one query; an empty prefix covers the whole keyspace
+    pub fn get_prefix(&self, prefix: &str) -> Result<Vec<(SecretInfo, Vec<u8>)>> {
+        select_prefix(&self.conn, self.keyspace(), prefix)
+    }
+
+    /// Replace every secret's encrypted value and the given metadata in one transaction
+    ///
+    /// Used when the vault key changes: `reencrypt` maps each old ciphertext to
+    /// its new one, and nothing is written unless every secret succeeds.
+    /// Access tokens are removed, since they unwrap the old key. Only the
+    /// current keyspace is touched.
+    /// `progress` is called with `(done, total)` after each value.
+    pub fn rekey(
+        &mut self,
+        mut reencrypt: impl FnMut(&[u8]) -> Result<Vec<u8>>,
+        meta: &[(&str, &[u8])],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<usize> {
+        let keyspace = self.keyspace();
+        // Take the write lock up front so a busy vault fails before any work
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        // `secrets.id` is text, `secret_versions.id` an integer
+        let mut values: Vec<(&str, rusqlite::types::Value, Vec<u8>)> = vec![];
+        for table in ["secrets", "secret_versions"] {
+            let mut stmt = tx.prepare(&format!("SELECT id, encrypted_value FROM {} WHERE keyspace = {}", table, keyspace))?;
+            let rows = stmt.query_map([], |row| Ok((table, row.get(0)?, row.get(1)?)))?;
+            for row in rows {
+                values.push(row?);
+            }
+        }
+        let secrets = values.iter().filter(|(table, _, _)| *table == "secrets").count();
+
+        let total = values.len();
+        progress(0, total);
+        for (done, (table, id, value)) in values.iter().enumerate() {
+            let value = reencrypt(value)?;
+            let sql = match *table {
+                "secrets" => "UPDATE secrets SET encrypted_value = ? WHERE id = ?",
+                _ => "UPDATE secret_versions SET encrypted_value = ? WHERE id = ?",
+            };
+            tx.prepare_cached(sql)?.execute(rusqlite::params![value, id])?;
+            progress(done + 1, total);
+        }
+
+        for (key, value) in meta {
+            tx.prepare_cached(UPSERT_META)?.execute(rusqlite::params![key, value])?;
+        }
+        // Access tokens wrap the old key
+        tx.prepare_cached(DELETE_TOKENS)?.execute([keyspace])?;
+
+        tx.commit()?;
+        self.reload_meta()?;
+        Ok(secrets)
+    }
+
+    /// Add an access token; fails if the name is taken
+    pub fn insert_token(&mut self, token: &StoredToken) -> Result<()> {
+        let info = &token.info;
+        let inserted = self.conn.prepare_cached(INSERT_TOKEN)?.execute(rusqlite::params![
+                self.keyspace(),
+                info.name,
+                token.token_hash,
+                token.wrapped_key,
+                serde_json::to_string(&info.scope.path_prefixes)?,
+                info.scope.max_access as i32,
+                info.scope.expires_at.map(|t| t.timestamp()),
+            info.created_at.timestamp(),
+        ])?;
+        match inserted {
+            0 => Err(Error::InvalidArgument(format!("a token named {:?} already exists", info.name))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The access token whose hash is `token_hash`, in any keyspace, and
+    /// the keyspace it belongs to
+    pub fn token_by_hash(&self, token_hash: &str) -> Result<Option<(StoredToken, u8)>> {
+        let found = self
             .conn
-            .prepare("SELECT encrypted_value FROM secrets WHERE path = ?")?;
-        
-        let result = stmt.query_row([path], |row| row.get(0));
-        
-        match result {
-            Ok(value) => Ok(Some(value)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.into()),
+            .prepare_cached(SELECT_TOKEN_BY_HASH)?
+            .query_row([token_hash], |row| Ok((row_to_token(row), row.get::<_, u8>(7)?)))
+            .optional()?;
+        match found {
+            Some((token, keyspace)) => Ok(Some((token?, keyspace))),
+            None => Ok(None),
         }
     }
 
-    fn set(&mut self, path: &str, value: &[u8], info: &SecretInfo) -> Result<()> {
-        // Validate key path
-        validate_key_path(path)?;
-        
-        let id = uuid::Uuid::new_v4().to_string();
-        let now = chrono::Utc::now().timestamp();
-        let tags_json = serde_json::to_string(&info.tags)?;
-        let access_level = info.access as i32;
+    /// Every access token, by name
+    pub fn tokens(&self) -> Result<Vec<TokenInfo>> {
+        let mut stmt = self.conn.prepare_cached(SELECT_TOKENS)?;
+        let rows = stmt.query_map([self.keyspace()], |row| Ok(row_to_token(row)))?;
+        rows.map(|row| Ok(row??.info)).collect()
+    }
 
-        self.conn.execute(
-            r#"
-            INSERT INTO secrets (id, path, encrypted_value, access_level, tags, note, created_at, updated_at, created_by)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
-            ON CONFLICT(path) DO UPDATE SET
-                encrypted_value = excluded.encrypted_value,
-                access_level = excluded.access_level,
-                tags = excluded.tags,
-                note = excluded.note,
-                updated_at = excluded.updated_at
-            "#,
-            rusqlite::params![
-                id,
-                path,
-                value,
-                access_level,
-                tags_json,
-                info.note,
-                now,
-                now,
-                "human"
-            ],
+    /// Remove one access token by name, or all of them if `None`; returns how
+    /// many were removed
+    pub fn delete_tokens(&mut self, name: Option<&str>) -> Result<usize> {
+        Ok(match name {
+            Some(name) => self.conn.prepare_cached(DELETE_TOKEN)?.execute(rusqlite::params![self.keyspace(), name])?,
+            None => self.conn.prepare_cached(DELETE_TOKENS)?.execute([self.keyspace()])?,
+        })
+    }
+
+    /// Set several secrets in one transaction; nothing is written if any fails
+    pub fn set_many(&mut self, items: &[(&str, &[u8], &SecretInfo)]) -> Result<()> {
+        self.set_many_audited(items, LOCAL_WRITER, &[], |_, _| {})
+    }
+
+    /// Like `set_many`, recording `created_by` as the writer, appending
+    /// `audit` to the audit log in the same transaction and reporting
+    /// `(done, total)` secrets written
+    pub fn set_many_audited(
+        &mut self,
+        items: &[(&str, &[u8], &SecretInfo)],
+        created_by: &str,
+        audit: &[AuditEntry],
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for (done, (path, value, info)) in items.iter().enumerate() {
+            upsert(&tx, keyspace, path, value, info, created_by)?;
+            progress(done + 1, items.len());
+        }
+        AuditLogger::for_keyspace(&tx, keyspace).log_many(audit)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Delete several secrets in one transaction; returns which ones existed
+    pub fn delete_many(&mut self, paths: &[&str]) -> Result<Vec<bool>> {
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut existed = Vec::with_capacity(paths.len());
+        for path in paths {
+            existed.push(tx.prepare_cached(DELETE_SECRET)?.execute(rusqlite::params![keyspace, path])? > 0);
+        }
+        tx.commit()?;
+        Ok(existed)
+    }
+
+    /// Delete secrets and every stored version of them in one transaction,
+    /// then vacuum
+    ///
+    /// Deleted rows are overwritten (`secure_delete`) and the vacuum leaves no
+    /// freed pages, so nothing of the values stays in the file. Returns which
+    /// paths had a secret or versions stored.
+    pub fn purge_many(&mut self, paths: &[&str]) -> Result<Vec<bool>> {
+        let keyspace = self.keyspace();
+        self.conn.pragma_update(None, "secure_delete", true)?;
+        let purged: Result<Vec<bool>> = (|| {
+            let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let mut existed = Vec::with_capacity(paths.len());
+            for path in paths {
+                let params = rusqlite::params![keyspace, path];
+                let secrets = tx.prepare_cached(DELETE_SECRET)?.execute(params)?;
+                let versions = tx.prepare_cached(DELETE_VERSIONS)?.execute(params)?;
+                existed.push(secrets + versions > 0);
+            }
+            tx.commit()?;
+            Ok(existed)
+        })();
+        self.conn.pragma_update(None, "secure_delete", false)?;
+        let existed = purged?;
+        self.compact()?;
+        Ok(existed)
+    }
+
+    /// Delete every secret whose path starts with `prefix` in one transaction
+    ///
+    /// The prefix is matched literally (no wildcards); an empty prefix matches
+    /// everything. Returns the deleted paths, sorted.
+    pub fn delete_matching(&mut self, prefix: &str) -> Result<Vec<String>> {
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let paths: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT path FROM secrets WHERE keyspace = ?2 AND substr(path, 1, length(?1)) = ?1 ORDER BY path",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![prefix, keyspace], |row| row.get(0))?;
+            rows.collect::<std::result::Result<_, _>>()?
+        };
+        tx.execute(
+            "DELETE FROM secrets WHERE keyspace = ?2 AND substr(path, 1, length(?1)) = ?1",
+            rusqlite::params![prefix, keyspace],
         )?;
+        tx.commit()?;
+        Ok(paths)
+    }
 
+    /// Move a secret and its history to a new path in one transaction
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        validate_key_path(new)?;
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if path_exists(&tx, keyspace, new)? {
+            return Err(Error::AlreadyExists { path: new.to_string() });
+        }
+        let moved = tx.execute(
+            "UPDATE secrets SET path = ?2, updated_at = ?3 WHERE keyspace = ?4 AND path = ?1",
+            rusqlite::params![old, new, chrono::Utc::now().timestamp(), keyspace],
+        )?;
+        if moved == 0 {
+            return Err(Error::SecretNotFound { path: old.to_string() });
+        }
+
+        // Versions outlive secrets, so `new` may already have history; continue after it
+        let offset: i64 = tx.query_row(
+            "SELECT COALESCE(MAX(version), 0) FROM secret_versions WHERE keyspace = ? AND path = ?",
+            rusqlite::params![keyspace, new],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "UPDATE secret_versions SET path = ?2, version = version + ?3 WHERE keyspace = ?4 AND path = ?1",
+            rusqlite::params![old, new, offset, keyspace],
+        )?;
+        tx.commit()?;
         Ok(())
     }
 
-    fn delete(&mut self, path: &str) -> Result<bool> {
-        let affected = self
-            .conn
-            .execute("DELETE FROM secrets WHERE path = ?", [path])?;
-        Ok(affected > 0)
+    /// Copy a secret with its metadata to another path in one transaction
+    ///
+    /// `reseal` turns the stored value into the one written at `dst`. Fails
+    /// with `Error::AlreadyExists` if `dst` exists and `overwrite` is false.
+    pub fn copy(
+        &mut self,
+        src: &str,
+        dst: &str,
+        overwrite: bool,
+        created_by: &str,
+        reseal: impl FnOnce(&[u8]) -> Result<Vec<u8>>,
+    ) -> Result<()> {
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let source = {
+            let mut stmt = tx.prepare_cached(SELECT_INFO_AND_VALUE)?;
+            let mut rows = stmt.query(rusqlite::params![keyspace, src])?;
+            match rows.next()? {
+                Some(row) => Some((row_to_info(row)?, row.get::<_, Vec<u8>>(8)?)),
+                None => None,
+            }
+        };
+        let (info, value) = source.ok_or_else(|| Error::SecretNotFound { path: src.to_string() })?;
+        if !overwrite && path_exists(&tx, keyspace, dst)? {
+            return Err(Error::AlreadyExists { path: dst.to_string() });
+        }
+
+        upsert(&tx, keyspace, dst, &reseal(&value)?, &info, created_by)?;
+        tx.commit()?;
+        Ok(())
     }
 
-    fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
-        let sql = match pattern {
-            Some(_) => "SELECT path, access_level, tags, note, created_at, updated_at FROM secrets WHERE path LIKE ?",
-            None => "SELECT path, access_level, tags, note, created_at, updated_at FROM secrets",
+    /// All stored versions of a secret, newest first
+    ///
+    /// Versions outlive the secret itself, so a deleted secret still has history.
+    pub fn list_versions(&self, path: &str) -> Result<Vec<StoredVersion>> {
+        let mut stmt = self.conn.prepare_cached(SELECT_VERSIONS)?;
+        let mut rows = stmt.query(rusqlite::params![self.keyspace(), path])?;
+
+        let mut versions = Vec::new();
+        while let Some(row) = rows.next()? {
+            versions.push(row_to_version(row)?);
+        }
+        Ok(versions)
+    }
+
+    /// A single stored version of a secret
+    pub fn get_version(&self, path: &str, version: u32) -> Result<Option<StoredVersion>> {
+        let mut stmt = self.conn.prepare_cached(SELECT_VERSION)?;
+        let mut rows = stmt.query(rusqlite::params![self.keyspace(), path, version])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(row_to_version(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove expired secrets, surplus versions and old audit entries in one transaction
+    ///
+    /// The transaction takes SQLite's write lock up front, so concurrent
+    /// writers wait rather than interleave. On a dry run it is rolled back.
+    pub fn prune(&mut self, now: chrono::DateTime<chrono::Utc>, opts: &PruneOptions) -> Result<PruneReport> {
+        let keyspace = self.keyspace();
+        let anchor_key = self.keyspace_meta_key(AUDIT_ANCHOR_KEY);
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let expired_secrets = {
+            let mut stmt = tx.prepare(
+                "SELECT path FROM secrets WHERE keyspace = ? AND ttl_expires_at IS NOT NULL AND ttl_expires_at <= ? \
+                 ORDER BY path",
+            )?;
+            let rows = stmt.query_map(rusqlite::params![keyspace, now.timestamp()], |row| row.get(0))?;
+            rows.collect::<std::result::Result<_, _>>()?
         };
+        tx.execute(
+            "DELETE FROM secrets WHERE keyspace = ? AND ttl_expires_at IS NOT NULL AND ttl_expires_at <= ?",
+            rusqlite::params![keyspace, now.timestamp()],
+        )?;
+        let mut report = PruneReport { expired_secrets, ..Default::default() };
 
-        let mut stmt = self.conn.prepare(sql)?;
-        
-        let rows = if let Some(p) = pattern {
-            let pattern = p.replace('*', "%");
-            stmt.query([pattern])?
+        if let Some(keep) = opts.keep_versions {
+            report.old_versions = tx.execute(
+                r#"
+                DELETE FROM secret_versions WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, ROW_NUMBER() OVER (PARTITION BY path ORDER BY version DESC) AS rn
+                        FROM secret_versions WHERE keyspace = ?2
+                    ) WHERE rn > ?1
+                )
+                "#,
+                rusqlite::params![keep as i64, keyspace],
+            )?;
+        }
+
+        if let Some(before) = opts.audit_before {
+            report.audit_entries = tx.execute(
+                "DELETE FROM audit_log WHERE keyspace = ? AND timestamp < ?",
+                rusqlite::params![keyspace, before.timestamp()],
+            )?;
+
+            if report.audit_entries > 0 {
+                // Remember where the surviving hash chain starts
+                let anchor: Option<String> = tx
+                    .query_row(
+                        "SELECT prev_hash FROM audit_log WHERE keyspace = ?1 AND prev_hash IS NOT NULL
+                         AND prev_hash NOT IN (SELECT hash FROM audit_log WHERE keyspace = ?1 AND hash IS NOT NULL)",
+                        [keyspace],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                match anchor {
+                    Some(anchor) => tx.execute(
+                        "INSERT OR REPLACE INTO vault_meta (key, value) VALUES (?, ?)",
+                        rusqlite::params![anchor_key, anchor.into_bytes()],
+                    )?,
+                    None => tx.execute("DELETE FROM vault_meta WHERE key = ?", [&anchor_key])?,
+                };
+            }
+        }
+
+        if opts.dry_run {
+            tx.rollback()?;
         } else {
-            stmt.query([])?
-        };
+            tx.commit()?;
+            self.reload_meta()?;
+        }
+        Ok(report)
+    }
 
-        let mut results = Vec::new();
-        let mut rows = rows;
-        
+    /// Delete the whole audit log, e.g. from a copy made for sync
+    pub fn clear_audit_log(&self) -> Result<()> {
+        self.conn.execute_batch(
+            "DELETE FROM audit_log; DELETE FROM vault_meta WHERE key LIKE '%audit_anchor';",
+        )?;
+        self.reload_meta()
+    }
+
+    /// Replace the audit log with the one in the database at `other`,
+    /// returning the number of entries copied
+    ///
+    /// The entries keep their hashes, so the copied chain still verifies.
+    pub fn copy_audit_log_from(&mut self, other: &std::path::Path) -> Result<usize> {
+        let other = other
+            .to_str()
+            .ok_or_else(|| Error::Other("Database path is not valid UTF-8".to_string()))?;
+        self.conn.execute("ATTACH DATABASE ? AS other", [other])?;
+        let copied = (|| -> Result<usize> {
+            let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            tx.execute("DELETE FROM audit_log", [])?;
+            let copied = tx.execute(
+                "INSERT INTO audit_log (id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash, keyspace)
+                 SELECT id, timestamp, actor, action, key_path, success, error_message, source, hash, prev_hash, keyspace
+                 FROM other.audit_log",
+                [],
+            )?;
+            tx.execute("DELETE FROM vault_meta WHERE key LIKE '%audit_anchor'", [])?;
+            tx.execute(
+                "INSERT INTO vault_meta (key, value) SELECT key, value FROM other.vault_meta WHERE key LIKE '%audit_anchor'",
+                [],
+            )?;
+            tx.commit()?;
+            Ok(copied)
+        })();
+        self.conn.execute("DETACH DATABASE other", [])?;
+        self.reload_meta()?;
+        copied
+    }
+
+    /// Counter that changes whenever another connection (another process)
+    /// commits to the database, but not on this connection's own writes
+    pub fn data_version(&self) -> Result<i64> {
+        Ok(self.conn.prepare_cached("PRAGMA data_version")?.query_row([], |row| row.get(0))?)
+    }
+
+    /// Rebuild the database file, dropping free pages
+    pub fn compact(&self) -> Result<()> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Write a consistent copy of the database to `dest`, which must not
+    /// exist yet; on unix only the owner can read it
+    pub fn backup_to(&self, dest: &std::path::Path) -> Result<()> {
+        let dest_str = dest
+            .to_str()
+            .ok_or_else(|| Error::Other("Backup path is not valid UTF-8".to_string()))?;
+        // VACUUM INTO fills an empty file, keeping the mode it was created with
+        let mut file = std::fs::OpenOptions::new();
+        file.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut file, 0o600);
+        file.open(dest)?;
+        if let Err(e) = self.conn.execute("VACUUM INTO ?", [dest_str]) {
+            let _ = std::fs::remove_file(dest);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Schema version recorded in the database
+    pub fn schema_version(&self) -> Result<u32> {
+        Ok(self
+            .get_meta("schema_version")?
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0))
+    }
+
+    /// Count secrets per access level, indexed by `AccessLevel as usize`
+    pub fn count_by_access(&self) -> Result<[usize; 4]> {
+        let mut stmt = self.conn.prepare_cached(COUNT_BY_ACCESS)?;
+        let mut rows = stmt.query([self.keyspace()])?;
+
+        let mut counts = [0usize; 4];
         while let Some(row) = rows.next()? {
-            let access_level: i32 = row.get(1)?;
-            let tags_json: String = row.get(2)?;
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-            
-            results.push(SecretInfo {
-                path: row.get(0)?,
-                access: match access_level {
+            let level: i64 = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            // Unknown levels are read back as Normal, so count them there too
+            let index = if (0..4).contains(&level) { level as usize } else { 1 };
+            counts[index] += count as usize;
+        }
+        Ok(counts)
+    }
+
+    /// Most recent secret modification time
+    pub fn last_write(&self) -> Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let ts: Option<i64> = self.conn.prepare_cached(LAST_WRITE)?.query_row([self.keyspace()], |row| row.get(0))?;
+        Ok(ts.and_then(|ts| chrono::DateTime::from_timestamp(ts, 0)))
+    }
+
+    /// Every secret of the main keyspace, which is all that syncs, as a
+    /// sync record, ordered by path
+    pub fn sync_records(&self) -> Result<Vec<SyncSecret>> {
+        read_records(&self.conn)
+    }
+
+    /// Merge synced secrets into the main keyspace in one transaction
+    ///
+    /// `merge` gets every local secret as a sync record and returns the
+    /// changes to make. Reading and writing happen in the same transaction,
+    /// so nothing written concurrently is lost. Written records keep their
+    /// timestamps, and secrets whose value changes get a new version as usual.
+    pub fn merge_records(&mut self, merge: impl FnOnce(Vec<SyncSecret>) -> Result<SyncChanges>) -> Result<()> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let changes = merge(read_records(&tx)?)?;
+        for path in &changes.delete {
+            tx.prepare_cached(DELETE_SECRET)?.execute(rusqlite::params![MAIN_KEYSPACE, path])?;
+        }
+        for record in &changes.write {
+            write_record(&tx, record)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Problems reported by SQLite's `PRAGMA integrity_check`, empty if none
+    pub fn integrity_check(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare("PRAGMA integrity_check")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut problems = vec![];
+        for row in rows {
+            let row = row?;
+            if row != "ok" {
+                problems.push(row);
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Every stored ciphertext of the keyspace, labelled by path (`path@vN`
+    /// for versions)
+    pub fn sealed_values(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut values = vec![];
+        let mut stmt = self.conn.prepare("SELECT path, encrypted_value FROM secrets WHERE keyspace = ? ORDER BY path")?;
+        for row in stmt.query_map([self.keyspace()], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            values.push(row?);
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT path || '@v' || version, encrypted_value FROM secret_versions WHERE keyspace = ? ORDER BY path, version",
+        )?;
+        for row in stmt.query_map([self.keyspace()], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            values.push(row?);
+        }
+        Ok(values)
+    }
+
+    /// Get database connection reference (for audit logging)
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// `SecretStore::set`, recording `created_by` (e.g. `ai:claude`) as the
+    /// writer of the secret and its new version
+    pub fn set_as(&mut self, path: &str, value: &[u8], info: &SecretInfo, created_by: &str) -> Result<()> {
+        let keyspace = self.keyspace();
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        upsert(&tx, keyspace, path, value, info, created_by)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl SecretStore for SqliteStore {
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        select_value(&self.conn, self.keyspace(), path)
+    }
+
+    fn set(&mut self, path: &str, value: &[u8], info: &SecretInfo) -> Result<()> {
+        self.set_as(path, value, info, LOCAL_WRITER)
+    }
+
+    fn delete(&mut self, path: &str) -> Result<bool> {
+        let affected = self.conn.prepare_cached(DELETE_SECRET)?.execute(rusqlite::params![self.keyspace(), path])?;
+        Ok(affected > 0)
+    }
+
+    fn for_each_secret(&self, filter: &ListFilter, f: &mut dyn FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+        each_secret(&self.conn, self.keyspace(), filter, f)
+    }
+}
+
+// The reads `SqliteStore` shares with `Snapshot`
+
+fn select_all_meta(conn: &Connection) -> Result<HashMap<String, Vec<u8>>> {
+    let mut stmt = conn.prepare("SELECT key, CAST(value AS BLOB) FROM vault_meta")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+fn select_meta(conn: &Connection, key: &str) -> Result<Option<Vec<u8>>> {
+    Ok(conn.prepare_cached(SELECT_META)?.query_row([key], |row| row.get(0)).optional()?)
+}
+
+fn select_value(conn: &Connection, keyspace: u8, path: &str) -> Result<Option<Vec<u8>>> {
+    Ok(conn.prepare_cached(SELECT_VALUE)?.query_row(rusqlite::params![keyspace, path], |row| row.get(0)).optional()?)
+}
+
+fn select_info(conn: &Connection, keyspace: u8, path: &str) -> Result<Option<SecretInfo>> {
+    let mut stmt = conn.prepare_cached(SELECT_INFO)?;
+    let mut rows = stmt.query(rusqlite::params![keyspace, path])?;
+
+    match rows.next()? {
+        Some(row) => Ok(Some(row_to_info(row)?)),
+        None => Ok(None),
+    }
+}
+
+fn select_prefix(conn: &Connection, keyspace: u8, prefix: &str) -> Result<Vec<(SecretInfo, Vec<u8>)>> {
+    let below = match prefix {
+        "" => String::new(),
+        prefix => format!("{}/", prefix),
+    };
+    let mut stmt = conn.prepare_cached(SELECT_PREFIX)?;
+    let mut rows = stmt.query(rusqlite::params![keyspace, format!("{}%", escape_like(&below))])?;
+
+    let mut secrets = vec![];
+    while let Some(row) = rows.next()? {
+        let info = row_to_info(row)?;
+        // LIKE ignores ASCII case
+        if info.path.starts_with(&below) {
+            secrets.push((info, row.get(8)?));
+        }
+    }
+    Ok(secrets)
+}
+
+/// The query listing the secrets `filter` matches, its parameters in the
+/// order `list_params` gives them
+///
+/// Left to itself, SQLite prefers walking the keyspace by path, which
+/// saves sorting but reads every secret. With tags, the secrets having the
+/// first one are found through `idx_secret_tags_tag` instead (`CROSS JOIN`
+/// fixes the order) and the others looked up per secret; levels alone go
+/// through `idx_secrets_access` (`INDEXED BY`). Statements differ only in
+/// how many tags and levels they have, which keeps them few enough to cache.
+pub(crate) fn list_query(filter: &ListFilter) -> String {
+    let mut sql = match (filter.tags.is_empty(), filter.access.is_empty()) {
+        (true, true) => format!("SELECT {} FROM secrets WHERE", info_columns!()),
+        (true, false) => format!("SELECT {} FROM secrets INDEXED BY idx_secrets_access WHERE", info_columns!()),
+        (false, _) => format!(
+            "SELECT {} FROM secret_tags AS tagged CROSS JOIN secrets ON secrets.id = tagged.secret_id \
+             WHERE tagged.tag = ? AND",
+            info_columns!()
+        ),
+    };
+    sql.push_str(" keyspace = ?");
+    if filter.pattern.is_some() {
+        sql.push_str(" AND path LIKE ?");
+    }
+    for _ in filter.tags.iter().skip(1) {
+        sql.push_str(" AND secrets.id IN (SELECT secret_id FROM secret_tags WHERE tag = ?)");
+    }
+    if !filter.access.is_empty() {
+        let levels = vec!["?"; filter.access.len()].join(", ");
+        sql.push_str(&format!(" AND access_level IN ({})", levels));
+    }
+    sql.push_str(" ORDER BY path");
+    sql
+}
+
+/// Parameters of `list_query`
+pub(crate) fn list_params(keyspace: u8, filter: &ListFilter) -> Vec<rusqlite::types::Value> {
+    let mut params: Vec<rusqlite::types::Value> = filter.tags.first().map(|tag| tag.clone().into()).into_iter().collect();
+    params.push(keyspace.into());
+    if let Some(pattern) = &filter.pattern {
+        params.push(pattern.replace('*', "%").into());
+    }
+    params.extend(filter.tags.iter().skip(1).map(|tag| tag.clone().into()));
+    params.extend(filter.access.iter().map(|&access| (access as i32).into()));
+    params
+}
+
+fn each_secret(conn: &Connection, keyspace: u8, filter: &ListFilter, f: &mut dyn FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+    let mut stmt = conn.prepare_cached(&list_query(filter))?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(list_params(keyspace, filter)))?;
+
+    while let Some(row) = rows.next()? {
+        f(row_to_info(row)?)?;
+    }
+    Ok(())
+}
+
+/// Insert or update a secret and record it as a new version
+fn upsert(conn: &Connection, keyspace: u8, path: &str, value: &[u8], info: &SecretInfo, created_by: &str) -> Result<()> {
+    // Validate key path
+    validate_key_path(path)?;
+    
+    let id = uuid::Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp();
+    let tags_json = serde_json::to_string(&info.tags)?;
+    let access_level = info.access as i32;
+
+    conn.prepare_cached(UPSERT_SECRET)?.execute(rusqlite::params![
+        id,
+        path,
+        value,
+        access_level,
+        tags_json,
+        info.note,
+        info.expires_at.map(|t| t.timestamp()),
+        now,
+        now,
+        created_by,
+        keyspace
+    ])?;
+
+    // Every write is also kept as a new version
+    conn.prepare_cached(INSERT_VERSION)?
+        .execute(rusqlite::params![
+            path,
+            value,
+            access_level,
+            tags_json,
+            info.note,
+            info.expires_at.map(|t| t.timestamp()),
+            now,
+            created_by,
+            keyspace
+        ])?;
+
+    Ok(())
+}
+
+/// Length of the nonce prefixed to every stored value
+const NONCE_LEN: usize = 12;
+
+/// Every secret as a sync record, ordered by path
+fn read_records(conn: &Connection) -> Result<Vec<SyncSecret>> {
+    let mut stmt = conn.prepare_cached(SELECT_RECORDS)?;
+    let rows = stmt.query_map([], |row| {
+        let data: Vec<u8> = row.get(1)?;
+        let tags: Option<String> = row.get(3)?;
+        Ok((data, tags, SyncSecret {
+            path: row.get(0)?,
+            encrypted_value: vec![],
+            nonce: vec![],
+            access_level: row.get(2)?,
+            tags: vec![],
+            note: row.get(4)?,
+            expires_at: row.get(5)?,
+            created_at: row.get(6)?,
+            updated_at: row.get(7)?,
+        }))
+    })?;
+
+    let mut records = vec![];
+    for row in rows {
+        let (mut data, tags, mut record) = row?;
+        if data.len() < NONCE_LEN {
+            return Err(Error::Decryption(format!("{} has an invalid stored value", record.path)));
+        }
+        record.nonce = data.drain(..NONCE_LEN).collect();
+        record.encrypted_value = data;
+        record.tags = tags.and_then(|t| serde_json::from_str(&t).ok()).unwrap_or_default();
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Insert or update a secret from a sync record, keeping its timestamps
+///
+/// A new version is recorded only if the value or metadata changed.
+fn write_record(conn: &Connection, record: &SyncSecret) -> Result<()> {
+    validate_key_path(&record.path)?;
+    let value = [record.nonce.as_slice(), &record.encrypted_value].concat();
+    let tags_json = serde_json::to_string(&record.tags)?;
+
+    let changed = conn.prepare_cached(WRITE_RECORD)?.execute(rusqlite::params![
+        uuid::Uuid::new_v4().to_string(),
+        record.path,
+        value,
+        record.access_level,
+        tags_json,
+        record.note,
+        record.expires_at,
+        record.created_at,
+        record.updated_at,
+    ])?;
+
+    if changed > 0 {
+        conn.prepare_cached(INSERT_RECORD_VERSION)?.execute(rusqlite::params![
+            record.path,
+            value,
+            record.access_level,
+            tags_json,
+            record.note,
+            record.expires_at,
+            record.updated_at
+        ])?;
+    }
+    Ok(())
+}
+
+/// `s` with the wildcards of a `LIKE ... ESCAPE '\'` pattern escaped
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Whether a secret exists at `path` in `keyspace`
+fn path_exists(conn: &Connection, keyspace: u8, path: &str) -> Result<bool> {
+    Ok(conn.prepare_cached(SECRET_EXISTS)?.query_row(rusqlite::params![keyspace, path], |_| Ok(())).optional()?.is_some())
+}
+
+/// Build `SecretInfo` from a row of `info_columns!`
+fn row_to_info(row: &rusqlite::Row) -> Result<SecretInfo> {
+    let access_level: i32 = row.get(1)?;
+    let tags_json: String = row.get(2)?;
+    let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+
+    Ok(SecretInfo {
+        path: row.get(0)?,
+        access: access_from_int(access_level),
+        tags,
+        note: row.get(3)?,
+        created_at: chrono::DateTime::from_timestamp(row.get(4)?, 0)
+            .unwrap_or_default(),
+        updated_at: chrono::DateTime::from_timestamp(row.get(5)?, 0)
+            .unwrap_or_default(),
+        expires_at: row
+            .get::<_, Option<i64>>(6)?
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0)),
+        size: row.get::<_, i64>(7)? as usize,
+    })
+}
+
+/// Access level stored as an integer; unknown values read as Normal
+fn access_from_int(level: i32) -> AccessLevel {
+    match level {
+        0 => AccessLevel::Public,
+        1 => AccessLevel::Normal,
+        2 => AccessLevel::Sensitive,
+        3 => AccessLevel::Critical,
+        _ => AccessLevel::Normal,
+    }
+}
+
+/// Build `StoredToken` from a row of `token_columns!`
+fn row_to_token(row: &rusqlite::Row) -> Result<StoredToken> {
+    let prefixes: String = row.get(1)?;
+    Ok(StoredToken {
+        info: TokenInfo {
+            name: row.get(0)?,
+            scope: TokenScope {
+                path_prefixes: serde_json::from_str(&prefixes)?,
+                // Unknown levels must not widen a token
+                max_access: match row.get::<_, i32>(2)? {
                     0 => AccessLevel::Public,
-                    1 => AccessLevel::Normal,
-                    2 => AccessLevel::Sensitive,
-                    3 => AccessLevel::Critical,
                     _ => AccessLevel::Normal,
                 },
-                tags,
-                note: row.get(3)?,
-                created_at: chrono::DateTime::from_timestamp(row.get(4)?, 0)
-                    .unwrap_or_default()
-                    .into(),
-                updated_at: chrono::DateTime::from_timestamp(row.get(5)?, 0)
-                    .unwrap_or_default()
-                    .into(),
-            });
-        }
+                expires_at: row
+                    .get::<_, Option<i64>>(3)?
+                    .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0)),
+            },
+            created_at: chrono::DateTime::from_timestamp(row.get(4)?, 0).unwrap_or_default(),
+        },
+        token_hash: row.get(5)?,
+        wrapped_key: row.get(6)?,
+    })
+}
 
-        Ok(results)
-    }
+/// Build `StoredVersion` from a row of `version_columns!`
+fn row_to_version(row: &rusqlite::Row) -> Result<StoredVersion> {
+    Ok(StoredVersion {
+        info: row_to_info(row)?,
+        version: row.get(8)?,
+        value: row.get(9)?,
+        created_by: row.get(10)?,
+    })
 }
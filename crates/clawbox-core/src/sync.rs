@@ -2,33 +2,75 @@
 //!
 //! Provides end-to-end encrypted sync using iCloud or custom backends.
 
+use crate::audit::AuditLogger;
+use crate::crypto::{self, DerivedKey, SyncKey};
+use crate::devices::DeviceRegistry;
+use crate::storage::SqliteStore;
 use crate::{Result, Error};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// `vault_meta` key the sync state is persisted under
+const STATE_KEY: &str = "sync_state";
+/// Tag of the copies `ConflictResolution::KeepBoth` writes
+pub const CONFLICT_TAG: &str = "sync-conflict";
+const CONFLICT_MARKER: &str = ".conflict-";
+/// Lock file created in a sync folder while a device writes or reads it
+pub const LOCK_FILE: &str = "sync.lock";
+/// Locks older than this are left over from a crashed sync
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(5 * 60);
+/// Rounds of merging before giving up on a remote that keeps changing
+const SYNC_ATTEMPTS: usize = 3;
+const NONCE_LEN: usize = 12;
 
 /// Sync state for a vault
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SyncState {
     pub last_sync: Option<DateTime<Utc>>,
+    /// Version of the data this vault last pushed or pulled
     pub local_version: u64,
+    /// Remote version seen at the last sync
     pub remote_version: u64,
     pub sync_enabled: bool,
     pub conflict_count: usize,
+    /// How secrets changed on both sides are merged
+    pub resolution: ConflictResolution,
+    /// Digest of each secret as of the last sync, to tell which side changed it
+    pub synced: BTreeMap<String, String>,
 }
 
-impl Default for SyncState {
-    fn default() -> Self {
-        Self {
-            last_sync: None,
-            local_version: 0,
-            remote_version: 0,
-            sync_enabled: false,
-            conflict_count: 0,
+/// Outcome of a sync
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncResult {
+    Pulled,
+    Pushed,
+    UpToDate,
+    Unavailable,
+    /// Remote changes were applied and the merged result pushed back
+    Merged,
+}
+
+impl std::fmt::Display for SyncResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncResult::Pulled => write!(f, "Pulled from iCloud"),
+            SyncResult::Pushed => write!(f, "Pushed to iCloud"),
+            SyncResult::UpToDate => write!(f, "Already up to date"),
+            SyncResult::Unavailable => write!(f, "iCloud not available"),
+            SyncResult::Merged => write!(f, "Merged with iCloud"),
         }
     }
 }
 
-/// Sync conflict
+/// A secret changed on both sides since the last sync
+///
+/// Both values are reported whatever the resolution, so a value that was
+/// replaced isn't lost silently.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncConflict {
     pub path: String,
@@ -36,14 +78,162 @@ pub struct SyncConflict {
     pub remote_value: String,
     pub local_updated: DateTime<Utc>,
     pub remote_updated: DateTime<Utc>,
+    /// Where `ConflictResolution::KeepBoth` wrote the remote value
+    #[serde(default)]
+    pub conflict_copy: Option<String>,
+}
+
+/// Result of `SyncManager::sync`
+#[derive(Debug, Clone)]
+pub struct SyncReport {
+    pub result: SyncResult,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+impl SyncReport {
+    fn new(result: SyncResult) -> Self {
+        Self { result, conflicts: vec![] }
+    }
+}
+
+/// Changes a merge makes to the local vault, applied by
+/// `SqliteStore::merge_records`
+#[derive(Debug, Default)]
+pub struct SyncChanges {
+    pub write: Vec<SyncSecret>,
+    pub delete: Vec<String>,
+}
+
+/// How a path differs between this vault and the remote, seen from here
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffChange {
+    /// Only the remote has it
+    Added,
+    /// Only this vault has it
+    Removed,
+    /// Both have it, with different values or metadata
+    Changed,
+}
+
+/// One side of a sync
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncSide {
+    Local,
+    Remote,
+}
+
+/// A path that differs between this vault and the remote (see `diff`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SyncDiff {
+    pub path: String,
+    pub change: DiffChange,
+    /// Side that changed the path last (added, edited or deleted it); `None`
+    /// when both edited it in the same second
+    pub newer: Option<SyncSide>,
+    /// Whether the values differ, not just tags, notes or other metadata
+    pub value_changed: bool,
+    pub local_updated: Option<DateTime<Utc>>,
+    pub remote_updated: Option<DateTime<Utc>>,
 }
 
 /// Resolution strategy
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum ConflictResolution {
+    /// Keep whichever side was written last (this vault on a tie)
+    #[default]
+    LastWriterWins,
     KeepLocal,
     KeepRemote,
-    KeepBoth,  // Creates path.conflict
+    /// Keep the local value and write the remote one to a copy tagged
+    /// `CONFLICT_TAG` at `path.conflict-<device>-<timestamp>`
+    KeepBoth,
+}
+
+/// Path of a `KeepBoth` copy of `path` holding a value written on `device`
+pub fn conflict_path(path: &str, device: &str, updated_at: DateTime<Utc>) -> String {
+    let device: String = device
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '-' })
+        .collect();
+    let device = if device.is_empty() { "unknown" } else { &device };
+    format!("{}{}{}-{}", path, CONFLICT_MARKER, device, updated_at.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Path a `KeepBoth` copy was made from
+pub fn conflict_origin(copy: &str) -> Option<&str> {
+    copy.rfind(CONFLICT_MARKER).map(|i| &copy[..i]).filter(|path| !path.is_empty())
+}
+
+/// Exclusive hold on a sync folder, released on drop
+///
+/// The lock file is created with `O_EXCL` and names the device and the time
+/// it was taken, so two devices never interleave writes to the vault file and
+/// its version.
+#[derive(Debug)]
+pub struct SyncLock {
+    path: PathBuf,
+}
+
+impl SyncLock {
+    /// Take the lock on `dir`, replacing a stale one
+    ///
+    /// Fails if another sync holds it.
+    pub fn acquire(dir: &Path) -> Result<Self> {
+        let path = dir.join(LOCK_FILE);
+        let contents = format!("{}\n{}\n", crate::icloud::get_device_id(), Utc::now().timestamp());
+        for _ in 0..2 {
+            match std::fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    use std::io::Write;
+                    let lock = Self { path };
+                    file.write_all(contents.as_bytes())?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let (device, taken_at) = read_lock(&path)?;
+                    let age = Utc::now().signed_duration_since(taken_at).to_std().unwrap_or_default();
+                    if age < LOCK_STALE_AFTER {
+                        return Err(Error::Other(format!(
+                            "Another device ({}) is syncing; try again in a few minutes",
+                            device
+                        )));
+                    }
+                    match std::fs::remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(Error::Other("Another device is syncing; try again in a few minutes".to_string()))
+    }
+}
+
+impl Drop for SyncLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Device and time recorded in a lock file, falling back to its mtime
+fn read_lock(path: &Path) -> Result<(String, DateTime<Utc>)> {
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    let mut lines = contents.lines();
+    let device = lines.next().filter(|d| !d.is_empty()).unwrap_or("unknown").to_string();
+    let taken_at = match lines.next().and_then(|ts| ts.trim().parse().ok()) {
+        Some(ts) => timestamp(ts),
+        None => match std::fs::metadata(path) {
+            Ok(meta) => meta.modified()?.into(),
+            // Released meanwhile
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DateTime::<Utc>::default(),
+            Err(e) => return Err(e.into()),
+        },
+    };
+    Ok((device, taken_at))
 }
 
 /// Sync backend trait
@@ -59,13 +249,227 @@ pub trait SyncBackend: Send + Sync {
     
     /// Download encrypted vault
     fn download(&self) -> Result<(Vec<u8>, u64)>;
+
+    /// The device registry kept with the remote vault; `None` if this
+    /// backend keeps none
+    fn devices(&self, _key: &SyncKey) -> Result<Option<DeviceRegistry>> {
+        Ok(None)
+    }
+
+    /// Change the device registry under the backend's lock; false if this
+    /// backend keeps none
+    fn update_devices(&self, _key: &SyncKey, _update: &mut dyn FnMut(&mut DeviceRegistry)) -> Result<bool> {
+        Ok(false)
+    }
+}
+
+/// Encrypted vault file in a sync folder
+pub(crate) const VAULT_FILE: &str = "vault.encrypted";
+/// `SyncMeta` of the vault file
+pub(crate) const META_FILE: &str = "vault.meta";
+/// `SyncMeta::format_version` this build writes
+pub const META_FORMAT: u32 = 1;
+/// Last line of a line-format meta whose vault file has no audit log
+const LEGACY_NO_AUDIT: &str = "no-audit";
+
+/// Metadata written next to a synced vault file
+///
+/// Stored as JSON. Metas written by older versions are lines of text (the
+/// version, then optionally the timestamp, device, checksum and `no-audit`);
+/// those still parse and are replaced with JSON on the next write.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncMeta {
+    /// Layout of this meta; newer layouts are refused rather than guessed at
+    pub format_version: u32,
+    pub version: u64,
+    pub timestamp: u64,
+    pub device_id: String,
+    /// Hex SHA-256 of the vault file this meta describes (absent in metas
+    /// written by older versions)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// The vault file was pushed without its audit log (see
+    /// `ICloudSync::set_include_audit`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub audit_excluded: bool,
+}
+
+impl SyncMeta {
+    pub fn new(version: u64) -> Self {
+        Self {
+            format_version: META_FORMAT,
+            version,
+            timestamp: Utc::now().timestamp().max(0) as u64,
+            device_id: crate::icloud::get_device_id(),
+            checksum: None,
+            audit_excluded: false,
+        }
+    }
+
+    /// Record the checksum of the vault file `data`
+    pub fn with_checksum(mut self, data: &[u8]) -> Self {
+        self.checksum = Some(format!("{:x}", Sha256::digest(data)));
+        self
+    }
+
+    /// Whether `data` is the vault file this meta was written for
+    pub fn matches(&self, data: &[u8]) -> bool {
+        self.checksum.as_ref().is_none_or(|expected| *expected == format!("{:x}", Sha256::digest(data)))
+    }
+
+    /// Parse a meta in either format; `path` only names it in errors
+    pub fn parse(content: &str, path: &Path) -> Result<Self> {
+        let invalid = |reason: String| Error::InvalidSyncMeta { path: path.display().to_string(), reason };
+        if !content.trim_start().starts_with('{') {
+            return Self::parse_lines(content).ok_or_else(|| invalid("neither JSON nor the line format".to_string()));
+        }
+        let meta: Self = serde_json::from_str(content).map_err(|e| invalid(e.to_string()))?;
+        if meta.format_version > META_FORMAT {
+            return Err(invalid(format!(
+                "format {} was written by a newer ClawBox (this one reads up to {})",
+                meta.format_version, META_FORMAT
+            )));
+        }
+        Ok(meta)
+    }
+
+    /// The line format of older versions
+    fn parse_lines(content: &str) -> Option<Self> {
+        let mut lines = content.lines().map(str::trim);
+        let version = lines.next()?.parse().ok()?;
+        let timestamp = match lines.next() {
+            Some(line) => line.parse().ok()?,
+            None => 0,
+        };
+        let device_id = lines.next().unwrap_or("").to_string();
+        let checksum = lines.next().filter(|line| !line.is_empty()).map(str::to_string);
+        if checksum.as_ref().is_some_and(|sum| sum.len() != 64 || !sum.bytes().all(|b| b.is_ascii_hexdigit())) {
+            return None;
+        }
+        let audit_excluded = match lines.next() {
+            Some(LEGACY_NO_AUDIT) => true,
+            Some(_) => return None,
+            None => false,
+        };
+        if lines.next().is_some() {
+            return None;
+        }
+        Some(Self { format_version: 0, version, timestamp, device_id, checksum, audit_excluded })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?, path)
+    }
+
+    /// Like `from_file`, but a missing file is `None`
+    pub(crate) fn read_optional(path: &Path) -> Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content, path).map(Some),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write as JSON in the current format, whatever format it was read in
+    pub fn to_file(&self, path: &Path) -> Result<()> {
+        write_atomic(path, self.encode().as_bytes())
+    }
+
+    pub(crate) fn encode(&self) -> String {
+        let meta = Self { format_version: META_FORMAT, ..self.clone() };
+        serde_json::to_string_pretty(&meta).expect("sync meta serializes")
+    }
+}
+
+/// Sync through any shared folder (Syncthing, Dropbox, a network drive)
+///
+/// Keeps `vault.encrypted` and `vault.meta` in the folder like the iCloud
+/// backend. Files are written to a temporary name and renamed into place, so
+/// a folder synced mid-write never holds a partial file.
+pub struct DirBackend {
+    dir: PathBuf,
+}
+
+impl DirBackend {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl SyncBackend for DirBackend {
+    fn is_connected(&self) -> bool {
+        self.dir.is_dir()
+    }
+
+    fn get_remote_version(&self) -> Result<u64> {
+        Ok(SyncMeta::read_optional(&self.dir.join(META_FILE))?.map_or(0, |meta| meta.version))
+    }
+
+    fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = SyncLock::acquire(&self.dir)?;
+        if self.get_remote_version()? >= version {
+            return Err(Error::RemoteChanged);
+        }
+        // The version goes last: a reader that sees it also sees the data
+        write_atomic(&self.dir.join(VAULT_FILE), data)?;
+        SyncMeta::new(version).with_checksum(data).to_file(&self.dir.join(META_FILE))
+    }
+
+    fn download(&self) -> Result<(Vec<u8>, u64)> {
+        let _lock = SyncLock::acquire(&self.dir)?;
+        let data = std::fs::read(self.dir.join(VAULT_FILE))?;
+        let meta = SyncMeta::read_optional(&self.dir.join(META_FILE))?;
+        if meta.as_ref().is_some_and(|meta| !meta.matches(&data)) {
+            return Err(Error::RemoteIncomplete);
+        }
+        Ok((data, meta.map_or(0, |meta| meta.version)))
+    }
+
+    fn devices(&self, key: &SyncKey) -> Result<Option<DeviceRegistry>> {
+        DeviceRegistry::load(&self.dir, key).map(Some)
+    }
+
+    fn update_devices(&self, key: &SyncKey, update: &mut dyn FnMut(&mut DeviceRegistry)) -> Result<bool> {
+        std::fs::create_dir_all(&self.dir)?;
+        let _lock = SyncLock::acquire(&self.dir)?;
+        DeviceRegistry::update(&self.dir, key, update)?;
+        Ok(true)
+    }
+}
+
+/// Replace a file by writing a temporary file next to it and renaming it;
+/// the new file is readable only by the owner
+pub(crate) fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("sync");
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let result = options
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    Ok(result?)
 }
 
-/// iCloud sync backend
+/// iCloud sync backend: a `DirBackend` on the app's iCloud Drive container
 #[cfg(target_os = "macos")]
 pub struct ICloudBackend {
     container_id: String,
-    vault_file: String,
 }
 
 #[cfg(target_os = "macos")]
@@ -73,7 +477,6 @@ impl ICloudBackend {
     pub fn new(container_id: &str) -> Self {
         Self {
             container_id: container_id.to_string(),
-            vault_file: "vault.encrypted".to_string(),
         }
     }
     
@@ -86,49 +489,36 @@ impl ICloudBackend {
             .join(format!("iCloud~{}", container))
             .join("Documents"))
     }
+
+    fn dir(&self) -> Result<DirBackend> {
+        self.icloud_path().map(DirBackend::new).ok_or(Error::ICloudUnavailable)
+    }
 }
 
 #[cfg(target_os = "macos")]
 impl SyncBackend for ICloudBackend {
     fn is_connected(&self) -> bool {
-        self.icloud_path()
-            .map(|p| p.exists())
-            .unwrap_or(false)
+        self.dir().is_ok_and(|dir| dir.is_connected())
     }
     
     fn get_remote_version(&self) -> Result<u64> {
-        let path = self.icloud_path()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
-        
-        let meta_path = path.join("vault.meta");
-        if !meta_path.exists() {
-            return Ok(0);
-        }
-        
-        let content = std::fs::read_to_string(&meta_path)?;
-        let version: u64 = content.trim().parse().unwrap_or(0);
-        Ok(version)
+        self.dir()?.get_remote_version()
     }
     
     fn upload(&self, data: &[u8], version: u64) -> Result<()> {
-        let path = self.icloud_path()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
-        
-        std::fs::create_dir_all(&path)?;
-        std::fs::write(path.join(&self.vault_file), data)?;
-        std::fs::write(path.join("vault.meta"), version.to_string())?;
-        
-        Ok(())
+        self.dir()?.upload(data, version)
     }
     
     fn download(&self) -> Result<(Vec<u8>, u64)> {
-        let path = self.icloud_path()
-            .ok_or_else(|| Error::Other("iCloud not available".to_string()))?;
-        
-        let data = std::fs::read(path.join(&self.vault_file))?;
-        let version = self.get_remote_version()?;
-        
-        Ok((data, version))
+        self.dir()?.download()
+    }
+
+    fn devices(&self, key: &SyncKey) -> Result<Option<DeviceRegistry>> {
+        self.dir()?.devices(key)
+    }
+
+    fn update_devices(&self, key: &SyncKey, update: &mut dyn FnMut(&mut DeviceRegistry)) -> Result<bool> {
+        self.dir()?.update_devices(key, update)
     }
 }
 
@@ -137,11 +527,14 @@ impl SyncBackend for ICloudBackend {
 pub struct SyncBundle {
     pub version: u64,
     pub timestamp: DateTime<Utc>,
+    /// Device that pushed the bundle
+    #[serde(default)]
+    pub device: String,
     pub secrets: Vec<SyncSecret>,
     pub audit_hash: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SyncSecret {
     pub path: String,
     pub encrypted_value: Vec<u8>,
@@ -149,10 +542,223 @@ pub struct SyncSecret {
     pub access_level: u8,
     pub tags: Vec<String>,
     pub note: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
     pub created_at: i64,
     pub updated_at: i64,
 }
 
+/// Digest of a record, to compare it with its state at the last sync
+fn digest(record: &SyncSecret) -> String {
+    format!("{:x}", Sha256::digest(serde_json::to_vec(record).unwrap_or_default()))
+}
+
+/// Digest of every record, by path
+fn digests(records: &[SyncSecret]) -> BTreeMap<String, String> {
+    records.iter().map(|record| (record.path.clone(), digest(record))).collect()
+}
+
+/// Outcome of merging local and remote records
+struct Merge {
+    changes: SyncChanges,
+    /// Records after the merge, ordered by path
+    merged: Vec<SyncSecret>,
+    conflicts: Vec<SyncConflict>,
+}
+
+/// Merge local and remote records path by path against their state at the
+/// last sync (`base`)
+///
+/// A change on one side is taken as is, including deletions. If both sides
+/// changed a secret differently, `resolution` decides and the pair is
+/// reported as a conflict; an edit always wins over a deletion. `device`
+/// names the remote side in `KeepBoth` copies.
+fn merge(
+    base: &BTreeMap<String, String>,
+    local: Vec<SyncSecret>,
+    remote: &[SyncSecret],
+    key: &DerivedKey,
+    resolution: ConflictResolution,
+    device: &str,
+) -> Result<Merge> {
+    let mut local: HashMap<String, SyncSecret> = local.into_iter().map(|r| (r.path.clone(), r)).collect();
+    let remote: HashMap<&str, &SyncSecret> = remote.iter().map(|r| (r.path.as_str(), r)).collect();
+    let paths: BTreeSet<String> = local
+        .keys()
+        .cloned()
+        .chain(remote.keys().map(|path| path.to_string()))
+        .chain(base.keys().cloned())
+        .collect();
+    // Copies are added under paths no secret uses, even after earlier conflicts
+    let mut taken: BTreeSet<String> = paths.clone();
+
+    let mut result = Merge { changes: SyncChanges::default(), merged: vec![], conflicts: vec![] };
+    for path in paths {
+        let ours = local.remove(&path);
+        let theirs = remote.get(path.as_str()).copied();
+        let synced = base.get(&path);
+        let ours_digest = ours.as_ref().map(digest);
+        let theirs_digest = theirs.map(digest);
+        let take_theirs = if theirs_digest.as_ref() == synced || theirs_digest == ours_digest {
+            false
+        } else if ours_digest.as_ref() == synced {
+            true
+        } else {
+            match (&ours, theirs) {
+                (Some(ours), Some(theirs)) => {
+                    let conflict_copy = (resolution == ConflictResolution::KeepBoth).then(|| {
+                        let copy = keep_copy(&mut taken, theirs, device);
+                        result.merged.push(copy.clone());
+                        result.changes.write.push(copy.clone());
+                        copy.path
+                    });
+                    result.conflicts.push(SyncConflict {
+                        path: path.clone(),
+                        local_value: open_value(ours, key)?,
+                        remote_value: open_value(theirs, key)?,
+                        local_updated: timestamp(ours.updated_at),
+                        remote_updated: timestamp(theirs.updated_at),
+                        conflict_copy,
+                    });
+                    match resolution {
+                        ConflictResolution::LastWriterWins => theirs.updated_at > ours.updated_at,
+                        ConflictResolution::KeepRemote => true,
+                        ConflictResolution::KeepLocal | ConflictResolution::KeepBoth => false,
+                    }
+                }
+                (None, _) => true,
+                (Some(_), None) => false,
+            }
+        };
+
+        let chosen = if take_theirs {
+            match theirs {
+                Some(theirs) => result.changes.write.push(theirs.clone()),
+                None => result.changes.delete.push(path),
+            }
+            theirs.cloned()
+        } else {
+            ours
+        };
+        result.merged.extend(chosen);
+    }
+    result.merged.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(result)
+}
+
+/// Compare local and remote records path by path without changing either
+///
+/// Which side is newer follows `merge`: a side that still matches its state
+/// at the last sync (`base`) is older, whatever the timestamps say; only
+/// when both changed does the later `updated_at` decide. Values are
+/// compared by their decrypted fingerprint, so a value re-encrypted on one
+/// side still counts as unchanged; one that can't be decrypted with `key`
+/// counts as changed.
+fn diff(
+    base: &BTreeMap<String, String>,
+    local: &[SyncSecret],
+    remote: &[SyncSecret],
+    key: &DerivedKey,
+) -> Vec<SyncDiff> {
+    let local: BTreeMap<&str, &SyncSecret> = local.iter().map(|r| (r.path.as_str(), r)).collect();
+    let remote: BTreeMap<&str, &SyncSecret> = remote.iter().map(|r| (r.path.as_str(), r)).collect();
+    let paths: BTreeSet<&str> = local.keys().chain(remote.keys()).copied().collect();
+
+    let mut diffs = vec![];
+    for path in paths {
+        let (ours, theirs) = (local.get(path).copied(), remote.get(path).copied());
+        let (ours_digest, theirs_digest) = (ours.map(digest), theirs.map(digest));
+        if ours_digest == theirs_digest {
+            continue;
+        }
+        let synced = base.get(path);
+        let newer = if ours_digest.as_ref() == synced {
+            Some(SyncSide::Remote)
+        } else if theirs_digest.as_ref() == synced {
+            Some(SyncSide::Local)
+        } else {
+            match (ours, theirs) {
+                (Some(ours), Some(theirs)) => match ours.updated_at.cmp(&theirs.updated_at) {
+                    std::cmp::Ordering::Less => Some(SyncSide::Remote),
+                    std::cmp::Ordering::Greater => Some(SyncSide::Local),
+                    std::cmp::Ordering::Equal => None,
+                },
+                // Added on one side since the last sync
+                (Some(_), None) => Some(SyncSide::Local),
+                (None, _) => Some(SyncSide::Remote),
+            }
+        };
+        let (change, value_changed) = match (ours, theirs) {
+            (Some(ours), Some(theirs)) => {
+                let fingerprints = (fingerprint(ours, key), fingerprint(theirs, key));
+                (DiffChange::Changed, fingerprints.0.is_none() || fingerprints.0 != fingerprints.1)
+            }
+            (None, _) => (DiffChange::Added, true),
+            (Some(_), None) => (DiffChange::Removed, true),
+        };
+        diffs.push(SyncDiff {
+            path: path.to_string(),
+            change,
+            newer,
+            value_changed,
+            local_updated: ours.map(|r| timestamp(r.updated_at)),
+            remote_updated: theirs.map(|r| timestamp(r.updated_at)),
+        });
+    }
+    diffs
+}
+
+/// Digest of a record's decrypted value
+fn fingerprint(record: &SyncSecret, key: &DerivedKey) -> Option<String> {
+    let value = zeroize::Zeroizing::new(crypto::open_value(&stored_value(record), key).ok()?);
+    Some(format!("{:x}", Sha256::digest(&value)))
+}
+
+/// A copy of `record` under an unused conflict path, tagged `CONFLICT_TAG`
+fn keep_copy(taken: &mut BTreeSet<String>, record: &SyncSecret, device: &str) -> SyncSecret {
+    let base = conflict_path(&record.path, device, timestamp(record.updated_at));
+    let path = (1..)
+        .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+        .find(|path| !taken.contains(path))
+        .unwrap_or(base);
+    taken.insert(path.clone());
+
+    let mut copy = record.clone();
+    copy.path = path;
+    if !copy.tags.iter().any(|tag| tag == CONFLICT_TAG) {
+        copy.tags.push(CONFLICT_TAG.to_string());
+    }
+    copy
+}
+
+/// Decrypt a record's value for a conflict report
+fn open_value(record: &SyncSecret, key: &DerivedKey) -> Result<String> {
+    Ok(String::from_utf8_lossy(&crypto::open_value(&stored_value(record), key)?).into_owned())
+}
+
+/// The value of `record` as the store keeps it
+fn stored_value(record: &SyncSecret) -> Vec<u8> {
+    [record.nonce.as_slice(), &record.encrypted_value].concat()
+}
+
+fn timestamp(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default()
+}
+
+/// Encrypt a bundle (or anything else kept next to it, like the device
+/// registry) with the sync key as nonce + ciphertext
+pub(crate) fn seal(value: &impl Serialize, key: &SyncKey) -> Result<Vec<u8>> {
+    Ok(crypto::encrypt(&serde_json::to_vec(value)?, key.key())?.into_bytes())
+}
+
+/// Decrypt what `seal` wrote
+pub(crate) fn open_sealed<T: serde::de::DeserializeOwned>(data: &[u8], key: &SyncKey) -> Result<T> {
+    if data.len() < NONCE_LEN {
+        return Err(Error::Decryption("Invalid sync data".to_string()));
+    }
+    Ok(serde_json::from_slice(&crypto::open(data, key.key())?)?)
+}
+
 /// Sync manager
 pub struct SyncManager {
     state: SyncState,
@@ -164,12 +770,31 @@ impl SyncManager {
             state: SyncState::default(),
         }
     }
+
+    /// Load the sync state persisted in a vault
+    pub fn load(store: &SqliteStore) -> Result<Self> {
+        let state = match store.get_meta(STATE_KEY)? {
+            Some(json) => serde_json::from_slice(&json)?,
+            None => SyncState::default(),
+        };
+        Ok(Self { state })
+    }
+
+    /// Persist the sync state in a vault
+    pub fn save(&self, store: &mut SqliteStore) -> Result<()> {
+        store.set_meta(STATE_KEY, &serde_json::to_vec(&self.state)?)
+    }
     
     /// Get sync state
     pub fn state(&self) -> &SyncState {
         &self.state
     }
     
+    /// Choose how secrets changed on both sides are merged
+    pub fn set_resolution(&mut self, resolution: ConflictResolution) {
+        self.state.resolution = resolution;
+    }
+
     /// Enable sync
     pub fn enable(&mut self) {
         self.state.sync_enabled = true;
@@ -179,22 +804,130 @@ impl SyncManager {
     pub fn disable(&mut self) {
         self.state.sync_enabled = false;
     }
+
+    /// Note a sync that moved the whole vault at `version`, e.g. an iCloud
+    /// push or pull
+    pub fn record(&mut self, version: u64) {
+        self.state.local_version = version;
+        self.state.remote_version = version;
+        self.state.last_sync = Some(Utc::now());
+    }
     
-    /// Perform sync
-    pub fn sync(&mut self, _backend: &dyn SyncBackend) -> Result<()> {
+    /// What a sync would change, without changing anything (see `diff`)
+    ///
+    /// `key` is the vault key. A remote nothing was pushed to yet has no
+    /// secrets.
+    pub fn diff(&self, store: &SqliteStore, key: &DerivedKey, backend: &dyn SyncBackend) -> Result<Vec<SyncDiff>> {
+        if !backend.is_connected() {
+            return Err(Error::Other("Sync backend not reachable".to_string()));
+        }
+        let remote = match backend.get_remote_version()? {
+            0 => vec![],
+            _ => open_sealed::<SyncBundle>(&backend.download()?.0, &SyncKey::derive(key))?.secrets,
+        };
+        Ok(diff(&self.state.synced, &store.sync_records()?, &remote, key))
+    }
+
+    /// Compare this vault with a full remote copy of it, e.g. an iCloud
+    /// snapshot, that isn't tracked path by path
+    pub fn diff_records(&self, store: &SqliteStore, key: &DerivedKey, remote: &[SyncSecret]) -> Result<Vec<SyncDiff>> {
+        Ok(diff(&self.state.synced, &store.sync_records()?, remote, key))
+    }
+
+    /// Sync a vault's secrets with a backend and persist the new state
+    ///
+    /// If the remote changed since the last sync its secrets are merged into
+    /// the vault path by path (see `merge`) in one storage transaction; the
+    /// result is pushed back if it differs from the remote. `key` is the
+    /// vault key; bundles are encrypted with the sync key derived from it. If
+    /// another device pushes in the meantime (`Error::RemoteChanged`), its
+    /// changes are merged and the push retried.
+    pub fn sync(&mut self, store: &mut SqliteStore, key: &DerivedKey, backend: &dyn SyncBackend) -> Result<SyncReport> {
         if !self.state.sync_enabled {
             return Err(Error::Other("Sync not enabled".to_string()));
         }
-        
-        // TODO: Implement full sync logic
-        // 1. Get local and remote versions
-        // 2. If remote > local, download and merge
-        // 3. If local > remote, upload
-        // 4. If equal, no-op
-        // 5. Handle conflicts
-        
+        if !backend.is_connected() {
+            return Ok(SyncReport::new(SyncResult::Unavailable));
+        }
+
+        let sync_key = SyncKey::derive(key);
+        let mut attempt = 1;
+        let report = loop {
+            match self.sync_once(store, key, &sync_key, backend) {
+                Err(Error::RemoteChanged) if attempt < SYNC_ATTEMPTS => attempt += 1,
+                result => break result?,
+            }
+        };
+        // The registry is informational: the sync itself already succeeded
+        let _ = backend.update_devices(&sync_key, &mut |registry| registry.record(report.result));
+        Ok(report)
+    }
+
+    fn sync_once(
+        &mut self,
+        store: &mut SqliteStore,
+        key: &DerivedKey,
+        sync_key: &SyncKey,
+        backend: &dyn SyncBackend,
+    ) -> Result<SyncReport> {
+        // Edits made during this sync stay pending for the next one
+        let pending = store.pending_changes()?;
+        let remote_version = backend.get_remote_version()?;
+        let remote = if remote_version != self.state.remote_version {
+            let (data, _) = backend.download()?;
+            Some(open_sealed::<SyncBundle>(&data, sync_key)?)
+        } else {
+            None
+        };
+
+        let mut merged = vec![];
+        let mut conflicts = vec![];
+        let mut pulled = false;
+        store.merge_records(|local| {
+            let Some(remote) = &remote else {
+                merged = local;
+                return Ok(SyncChanges::default());
+            };
+            let merge = merge(&self.state.synced, local, &remote.secrets, key, self.state.resolution, &remote.device)?;
+            pulled = !merge.changes.write.is_empty() || !merge.changes.delete.is_empty();
+            (merged, conflicts) = (merge.merged, merge.conflicts);
+            Ok(merge.changes)
+        })?;
+
+        // The remote holds what we saw at the last sync unless it was fetched
+        let synced = digests(&merged);
+        let pushed = match &remote {
+            Some(remote) => synced != digests(&remote.secrets),
+            None => pending > 0 || synced != self.state.synced,
+        };
+        let mut version = remote_version;
+        if pushed {
+            version = remote_version.max(self.state.local_version) + 1;
+            let bundle = SyncBundle {
+                version,
+                timestamp: Utc::now(),
+                device: crate::icloud::get_device_id(),
+                secrets: merged,
+                audit_hash: AuditLogger::new(store.connection()).get_last_hash()?.unwrap_or_default(),
+            };
+            backend.upload(&seal(&bundle, sync_key)?, version)?;
+        }
+
+        self.state.local_version = version;
+        self.state.remote_version = version;
+        self.state.synced = synced;
+        self.state.conflict_count = conflicts.len();
         self.state.last_sync = Some(Utc::now());
-        Ok(())
+        self.save(store)?;
+        store.settle_pending_changes(pending)?;
+
+        let result = match (pulled, pushed) {
+            (false, false) => SyncResult::UpToDate,
+            (true, false) => SyncResult::Pulled,
+            (false, true) => SyncResult::Pushed,
+            (true, true) => SyncResult::Merged,
+        };
+        Ok(SyncReport { result, conflicts })
     }
 }
 
@@ -203,3 +936,146 @@ impl Default for SyncManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sync_lock() {
+        let dir = TempDir::new().unwrap();
+        let lock = SyncLock::acquire(dir.path()).unwrap();
+        let err = SyncLock::acquire(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("is syncing"), "{}", err);
+        drop(lock);
+        assert!(!dir.path().join(LOCK_FILE).exists());
+
+        // A lock left behind by a crashed sync is taken over
+        let stale = Utc::now().timestamp() - LOCK_STALE_AFTER.as_secs() as i64 - 1;
+        std::fs::write(dir.path().join(LOCK_FILE), format!("desk\n{}\n", stale)).unwrap();
+        let _lock = SyncLock::acquire(dir.path()).unwrap();
+        let (device, _) = read_lock(&dir.path().join(LOCK_FILE)).unwrap();
+        assert_eq!(device, crate::icloud::get_device_id());
+    }
+
+    fn record(key: &DerivedKey, path: &str, value: &str, updated_at: i64) -> SyncSecret {
+        let encrypted = crypto::encrypt(value.as_bytes(), key).unwrap();
+        SyncSecret {
+            path: path.to_string(),
+            encrypted_value: encrypted.ciphertext().to_vec(),
+            nonce: encrypted.nonce().to_vec(),
+            access_level: 1,
+            tags: vec![],
+            note: None,
+            expires_at: None,
+            created_at: 0,
+            updated_at,
+        }
+    }
+
+    #[test]
+    fn test_sync_meta_upgrades_legacy_and_rejects_garbage() {
+        let dir = TempDir::new().unwrap();
+        let backend = DirBackend::new(dir.path());
+        let meta_file = dir.path().join(META_FILE);
+
+        // A folder last synced by an older version holds the bare version
+        std::fs::write(&meta_file, "3\n").unwrap();
+        assert_eq!(backend.get_remote_version().unwrap(), 3);
+        backend.upload(b"vault", 4).unwrap();
+        let meta = SyncMeta::from_file(&meta_file).unwrap();
+        assert_eq!((meta.format_version, meta.version), (META_FORMAT, 4));
+        assert!(std::fs::read_to_string(&meta_file).unwrap().trim_start().starts_with('{'));
+        assert_eq!(backend.download().unwrap(), (b"vault".to_vec(), 4));
+
+        // The iCloud line format, with every optional line
+        let sum = SyncMeta::new(1).with_checksum(b"vault").checksum.unwrap();
+        std::fs::write(&meta_file, format!("7\n1700000000\nold-mac\n{}\nno-audit", sum)).unwrap();
+        let legacy = SyncMeta::from_file(&meta_file).unwrap();
+        assert_eq!((legacy.version, legacy.timestamp, legacy.device_id.as_str()), (7, 1_700_000_000, "old-mac"));
+        assert!(legacy.audit_excluded && legacy.matches(b"vault"));
+        legacy.to_file(&meta_file).unwrap();
+        assert_eq!(SyncMeta::from_file(&meta_file).unwrap(), SyncMeta { format_version: META_FORMAT, ..legacy });
+
+        for garbage in ["", "garbage", "7\nyesterday", "7\n0\nmac\nnot-a-checksum", "{\"version\": 7", r#"{"version": 7}"#] {
+            std::fs::write(&meta_file, garbage).unwrap();
+            assert!(matches!(backend.get_remote_version(), Err(Error::InvalidSyncMeta { .. })), "{:?}", garbage);
+        }
+        let future = SyncMeta { format_version: META_FORMAT + 1, ..SyncMeta::new(8) };
+        std::fs::write(&meta_file, serde_json::to_string(&future).unwrap()).unwrap();
+        let err = SyncMeta::from_file(&meta_file).unwrap_err();
+        assert!(err.to_string().contains("newer ClawBox"), "{}", err);
+    }
+
+    #[test]
+    fn test_merge_edit_wins_over_delete() {
+        let key = DerivedKey::try_from_slice(&[7; 32]).unwrap();
+        let (kept, edited) = (record(&key, "kept", "v1", 1), record(&key, "edited", "v1", 1));
+        let base = digests(&[edited.clone(), kept.clone()]);
+
+        // Deleted here and edited remotely, or the other way round
+        let remote = [record(&key, "edited", "v2", 2), kept.clone()];
+        let result = merge(&base, vec![kept.clone()], &remote, &key, ConflictResolution::KeepBoth, "desk").unwrap();
+        assert_eq!(result.changes.write, remote[..1]);
+        assert!(result.changes.delete.is_empty() && result.conflicts.is_empty());
+        assert_eq!(result.merged, remote);
+
+        let local = vec![record(&key, "edited", "v2", 2), kept.clone()];
+        let result = merge(&base, local.clone(), &[], &key, ConflictResolution::KeepBoth, "desk").unwrap();
+        assert!(result.changes.write.is_empty());
+        assert_eq!(result.changes.delete, ["kept"]);
+        assert_eq!(result.merged, local[..1]);
+    }
+
+    #[test]
+    fn test_diff_reports_each_side() {
+        let key = DerivedKey::try_from_slice(&[7; 32]).unwrap();
+        let synced = ["same", "edited/here", "edited/there", "edited/both", "deleted/here", "deleted/there", "retagged"]
+            .map(|path| record(&key, path, "v1", 1));
+        let base = digests(&synced);
+        let [same, edited_here, edited_there, _, deleted_here, deleted_there, retagged] = synced;
+        let mut tagged = retagged.clone();
+        tagged.tags.push("prod".to_string());
+
+        let local = [
+            same.clone(),
+            edited_there.clone(),
+            record(&key, "edited/here", "v2", 2),
+            record(&key, "edited/both", "v2", 3),
+            deleted_there,
+            record(&key, "added/here", "v1", 2),
+            tagged,
+        ];
+        let remote = [
+            same,
+            record(&key, "edited/there", "v2", 2),
+            edited_here,
+            record(&key, "edited/both", "v3", 2),
+            deleted_here,
+            record(&key, "added/there", "v1", 2),
+            retagged,
+        ];
+        let diffs: Vec<_> = diff(&base, &local, &remote, &key)
+            .into_iter()
+            .map(|diff| (diff.path, diff.change, diff.newer, diff.value_changed))
+            .collect();
+        let expected = [
+            ("added/here", DiffChange::Removed, Some(SyncSide::Local), true),
+            ("added/there", DiffChange::Added, Some(SyncSide::Remote), true),
+            ("deleted/here", DiffChange::Added, Some(SyncSide::Local), true),
+            ("deleted/there", DiffChange::Removed, Some(SyncSide::Remote), true),
+            ("edited/both", DiffChange::Changed, Some(SyncSide::Local), true),
+            ("edited/here", DiffChange::Changed, Some(SyncSide::Local), true),
+            ("edited/there", DiffChange::Changed, Some(SyncSide::Remote), true),
+            ("retagged", DiffChange::Changed, Some(SyncSide::Local), false),
+        ]
+        .map(|(path, change, newer, value_changed)| (path.to_string(), change, newer, value_changed));
+        assert_eq!(diffs, expected);
+
+        // Without a shared history, the later write is newer
+        let diffs = diff(&BTreeMap::new(), &local[3..4], &remote[3..4], &key);
+        assert_eq!(diffs[0].newer, Some(SyncSide::Local));
+        assert_eq!(diffs[0].remote_updated, Some(timestamp(2)));
+    }
+}
@@ -1,60 +1,497 @@
 //! Vault management for ClawBox
 
 use crate::{
-    audit::{Action, AuditEntry, AuditFilter, AuditLogger, ActorInfo},
-    crypto::{self, DerivedKey, EncryptedData},
+    audit::{Action, AuditEntry, AuditFilter, ActorInfo},
+    backup::{self, Backup},
+    bundle::{self, BundleSecret, ConflictMode, ExportOptions, ImportReport},
+    crypto::{self, DerivedKey, EncryptedData, SyncKey},
+    devices::Device,
     error::Error,
-    storage::{SecretStore, SqliteStore},
-    Result, SecretInfo, SetOptions,
+    hooks::{Hook, HookRunner},
+    permissions::{self, PermissionReport},
+    secret::SecretValue,
+    snapshot::{self, Operation, Snapshot},
+    storage::{
+        self, keyspace_meta_key, normalize_and_validate_path, SecretStore, SqliteStore, StoreReader, StoredToken,
+        DURESS_KEYSPACE, MAIN_KEYSPACE,
+    },
+    sync::{self, SyncBackend, SyncDiff, SyncManager, SyncReport, SyncResult, SyncSecret, SyncState},
+    AccessLevel, Approval, ApprovalHandler, ApprovalRequest, DeleteMode, ExportSummary, IntegrityReport, ListFilter,
+    OpenOptions,
+    PlaintextExportOptions, PruneOptions, PruneReport, Result,
+    SecretInfo, SecretVersion, SetOptions, TokenInfo, TokenScope, UnlockLockout, VaultStats,
 };
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, Ref, RefCell};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+/// `vault_meta` key of the id that tells vaults apart in a shared sync location
+const VAULT_ID_KEY: &str = "vault_id";
+/// `vault_meta` key of the number of snapshots kept
+const SNAPSHOT_KEEP_KEY: &str = "snapshot_keep";
+/// Encrypted under each keyspace's key, so a password can be checked
+const VERIFICATION_TOKEN: &[u8] = b"clawbox-verification-token";
+
+/// Wrong passwords in a row that `unlock` accepts before refusing to try more
+pub const UNLOCK_ATTEMPTS: u32 = 5;
+/// Cooldown after `UNLOCK_ATTEMPTS` wrong passwords, doubled for each one
+/// after that up to `MAX_UNLOCK_COOLDOWN`
+const UNLOCK_COOLDOWN: Duration = Duration::from_secs(30);
+const MAX_UNLOCK_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+/// How long unlocking is refused after `failures` wrong passwords in a row
+fn unlock_cooldown(failures: u32) -> Option<Duration> {
+    let doublings = failures.checked_sub(UNLOCK_ATTEMPTS)?;
+    let cooldown = UNLOCK_COOLDOWN.saturating_mul(2u32.saturating_pow(doublings.min(31)));
+    Some(cooldown.min(MAX_UNLOCK_COOLDOWN))
+}
+
+/// `vault_meta` key of whether reading `access` secrets needs the master
+/// password again
+fn reauth_key(access: AccessLevel) -> &'static str {
+    match access {
+        AccessLevel::Public => "public_requires_reauth",
+        AccessLevel::Normal => "normal_requires_reauth",
+        AccessLevel::Sensitive => "sensitive_requires_reauth",
+        AccessLevel::Critical => "critical_requires_reauth",
+    }
+}
+
+/// `vault_meta` key of the read rate limit for `actor_type` at `access`
+fn rate_limit_key(actor_type: &str, access: AccessLevel) -> String {
+    format!("{}_{}_reads_per_minute", actor_type, format!("{:?}", access).to_lowercase())
+}
+
+/// Actor types a read rate limit can be set for
+const RATE_LIMITED_ACTORS: [&str; 4] = ["human", "ai", "app", "token"];
+
+/// The vault key, dropped by auto-lock once unused for too long
+///
+/// Kept apart from the rest of `ClawBox` so a borrowed key doesn't borrow the
+/// whole vault.
+struct KeyHolder {
+    key: RefCell<Option<DerivedKey>>,
+    auto_lock: Option<Duration>,
+    /// Last operation that used the key
+    last_used: Cell<Instant>,
+}
+
+impl KeyHolder {
+    /// The key, counting as activity for auto-lock
+    fn get(&self, store: &SqliteStore) -> Result<Ref<'_, DerivedKey>> {
+        self.lock_if_idle(store);
+        let key = Ref::filter_map(self.key.borrow(), Option::as_ref).map_err(|_| Error::VaultLocked)?;
+        self.last_used.set(Instant::now());
+        Ok(key)
+    }
+
+    /// Drop the key if auto-lock's window has passed since it was last used,
+    /// recording a lock by the system
+    fn lock_if_idle(&self, store: &SqliteStore) {
+        let Some(after) = self.auto_lock else { return };
+        if self.last_used.get().elapsed() < after {
+            return;
+        }
+        // A read already holding the key finishes first
+        if let Ok(mut key) = self.key.try_borrow_mut() {
+            if key.take().is_some() {
+                let entry = AuditEntry::new(Action::Lock, "vault", true).with_actor(ActorInfo::system("auto-lock"));
+                let _ = store.log_audit(entry);
+                store.set_keyspace(MAIN_KEYSPACE);
+            }
+        }
+    }
+}
+
+/// Prefix of access tokens, so they are recognizable in configs and logs
+const TOKEN_PREFIX: &str = "cbt_";
+
+/// The access token a handle was unlocked with
+struct TokenGrant {
+    info: TokenInfo,
+    /// Actor to go back to once the token no longer applies
+    previous_actor: ActorInfo,
+}
+
+/// Lists secrets the way `ClawBox::list` does, on read connections of its
+/// own, so other threads can list without holding the `ClawBox`
+///
+/// Made by `ClawBox::reader`, which checks that the vault is unlocked; the
+/// reader doesn't notice the vault being locked afterwards, so drop it once
+/// the listing is done.
+#[derive(Clone)]
+pub struct VaultReader {
+    store: StoreReader,
+    /// Scope of the access token the vault was unlocked with
+    scope: Option<TokenScope>,
+}
+
+impl VaultReader {
+    /// See `ClawBox::list`
+    pub fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        let mut secrets = vec![];
+        self.for_each_secret(&ListFilter::pattern(pattern), |info| {
+            secrets.push(info);
+            Ok(())
+        })?;
+        Ok(secrets)
+    }
+
+    /// See `ClawBox::for_each_secret`
+    pub fn for_each_secret(&self, filter: &ListFilter, mut f: impl FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+        self.store.for_each_secret(filter, &mut |info| match &self.scope {
+            Some(scope) if !(scope.covers(&info.path) && scope.allows(info.access)) => Ok(()),
+            _ => f(info),
+        })
+    }
+}
 
 /// Main ClawBox vault
 pub struct ClawBox {
     path: PathBuf,
     store: SqliteStore,
-    key: Option<DerivedKey>,
+    key: KeyHolder,
+    approver: Option<ApprovalHandler>,
+    /// Who reads and writes through this handle, for access checks and the
+    /// audit log
+    actor: ActorInfo,
+    /// Wall-clock time for the unlock lockout; tests replace it
+    clock: Box<dyn Fn() -> chrono::DateTime<chrono::Utc> + Send>,
+    /// Set while unlocked with an access token, which limits what can be read
+    token: Option<TokenGrant>,
+    hooks: HookRunner,
+    /// What the permission check found when the vault was opened
+    permissions: PermissionReport,
+    /// Threads to decrypt on in prefix reads and exports; see
+    /// `set_decrypt_jobs`
+    jobs: usize,
+}
+
+/// Opens a vault with options set one by one; see `ClawBox::builder`
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ClawBoxBuilder {
+    path: PathBuf,
+    opts: OpenOptions,
+}
+
+impl ClawBoxBuilder {
+    /// Replace every option with `opts`
+    pub fn options(mut self, opts: OpenOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// See `OpenOptions::read_only`
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.opts.read_only = read_only;
+        self
+    }
+
+    /// See `OpenOptions::auto_lock`
+    pub fn auto_lock(mut self, after: Duration) -> Self {
+        self.opts.auto_lock = Some(after);
+        self
+    }
+
+    /// See `OpenOptions::actor`
+    pub fn actor(mut self, actor: impl Into<ActorInfo>) -> Self {
+        self.opts.actor = Some(actor.into());
+        self
+    }
+
+    /// See `OpenOptions::hooks`
+    pub fn hooks(mut self, hooks: Vec<Hook>) -> Self {
+        self.opts.hooks = hooks;
+        self
+    }
+
+    /// See `OpenOptions::store`
+    pub fn store_options(mut self, store: storage::StoreOptions) -> Self {
+        self.opts.store = store;
+        self
+    }
+
+    /// See `OpenOptions::enforce_permissions`
+    pub fn enforce_permissions(mut self, enforce: bool) -> Self {
+        self.opts.enforce_permissions = enforce;
+        self
+    }
+
+    /// See `OpenOptions::create`
+    pub fn create(mut self, create: bool) -> Self {
+        self.opts.create = create;
+        self
+    }
+
+    /// Open the vault; see `ClawBox::open_with`
+    pub fn open(self) -> Result<ClawBox> {
+        ClawBox::open_with(&self.path, &self.opts)
+    }
 }
 
 impl ClawBox {
     /// Open or create a vault at the given path
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(path, &OpenOptions::default())
+    }
+
+    /// Set up opening the vault at `path`, starting from the defaults of
+    /// `open`
+    ///
+    /// ```rust,ignore
+    /// let vault = ClawBox::builder("~/.clawbox")
+    ///     .read_only(true)
+    ///     .actor(Actor::AI { agent: "deploy-bot".into() })
+    ///     .open()?;
+    /// ```
+    pub fn builder(path: impl AsRef<Path>) -> ClawBoxBuilder {
+        ClawBoxBuilder { path: path.as_ref().to_path_buf(), opts: OpenOptions::default() }
+    }
+
+    /// Open an existing vault that this handle can read but never modify
+    ///
+    /// The database is opened read-only: anything that would write to it,
+    /// such as `set`, `delete`, `rename`, `import_bundle` or
+    /// `change_password`, fails with `Error::ReadOnly`, and so do reads that
+    /// a rate limit applies to, since they can't be counted. Audit entries
+    /// go to `storage::AUDIT_SIDECAR_FILE` next to the database instead of
+    /// the audit log, and wrong passwords don't count towards the unlock
+    /// lockout.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with(path, &OpenOptions { read_only: true, ..Default::default() })
+    }
+
+    /// Open or create a vault at the given path with `opts`
+    ///
+    /// The vault's files are checked for permissions that let other users
+    /// in; see `permission_report`.
+    pub fn open_with(path: impl AsRef<Path>, opts: &OpenOptions) -> Result<Self> {
+        opts.store.validate()?;
         let path = path.as_ref().to_path_buf();
         let db_path = path.join("vault.db");
 
-        // Create directory if needed
-        std::fs::create_dir_all(&path)?;
+        if (opts.read_only || !opts.create) && !db_path.is_file() {
+            return Err(Error::VaultNotFound { path: path.to_string_lossy().to_string() });
+        }
+        if !opts.read_only {
+            // A new vault's directory is private to the owner; an existing
+            // directory only becomes the vault's if nothing else is in it
+            let fresh = std::fs::read_dir(&path).map_or(true, |mut entries| entries.next().is_none());
+            let mut dir = std::fs::DirBuilder::new();
+            dir.recursive(true);
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut dir, 0o700);
+            dir.create(&path)?;
+            if fresh {
+                backup::set_mode(&path, 0o700)?;
+            }
+        }
 
-        let store = SqliteStore::open(&db_path)?;
+        let permissions = permissions::check(&path)?;
+        if opts.enforce_permissions && !permissions.is_ok() {
+            return Err(Error::InsecurePermissions(permissions));
+        }
+        let store = match opts.read_only {
+            true => SqliteStore::open_read_only_with(&db_path, &opts.store)?,
+            false => SqliteStore::open_with(&db_path, &opts.store)?,
+        };
 
-        Ok(Self {
+        let mut vault = Self {
             path,
             store,
-            key: None,
-        })
+            key: KeyHolder {
+                key: RefCell::new(None),
+                auto_lock: opts.auto_lock,
+                last_used: Cell::new(Instant::now()),
+            },
+            approver: None,
+            actor: opts.actor.clone().unwrap_or_else(ActorInfo::human),
+            clock: Box::new(chrono::Utc::now),
+            token: None,
+            hooks: HookRunner::default(),
+            permissions,
+            jobs: 1,
+        };
+        if !opts.hooks.is_empty() {
+            vault.set_hooks(opts.hooks.clone());
+        }
+        Ok(vault)
+    }
+
+    /// Files and directories of the vault that other users could access
+    /// when it was opened
+    ///
+    /// The vault opens anyway unless `OpenOptions::enforce_permissions` is
+    /// set; callers should warn about these and offer `fix_permissions`.
+    pub fn permission_report(&self) -> &PermissionReport {
+        &self.permissions
+    }
+
+    /// Make the vault directory, `backups/` and `snapshots/` `0700` and the
+    /// files in them `0600`, returning what was changed
+    pub fn fix_permissions(&mut self) -> Result<PermissionReport> {
+        self.ensure_writable()?;
+        let fixed = permissions::fix(&self.path)?;
+        self.permissions = permissions::check(&self.path)?;
+        Ok(fixed)
     }
 
     /// Initialize a new vault with master password
+    ///
+    /// Fails with `Error::AlreadyInitialized` if the vault already has a key,
+    /// since a new salt would orphan every existing secret.
     pub fn init(&mut self, password: &str) -> Result<()> {
+        self.ensure_writable()?;
+        if self.is_initialized()? {
+            return Err(Error::AlreadyInitialized {
+                path: self.path.to_string_lossy().to_string(),
+            });
+        }
+
+        self.write_key_material(password, MAIN_KEYSPACE, None)
+    }
+
+    /// Re-initialize an existing vault with a new master password
+    ///
+    /// A copy of the database is written to `vault.db.pre-reinit-<timestamp>`
+    /// (mode 0600) first and its path returned, so the old secrets can still
+    /// be recovered with the old password, and a snapshot is taken. The
+    /// secrets, their history, the audit log and access tokens are then
+    /// deleted in the same transaction that writes the new key material;
+    /// policies stay. Unlocked with the
+    /// duress password, only the decoy secrets are affected.
+    pub fn reinit(&mut self, password: &str) -> Result<PathBuf> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let keyspace = self.store.keyspace();
+        if keyspace == MAIN_KEYSPACE {
+            self.check_differs_from_duress(password)?;
+        }
+        let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S").to_string();
+        let mut backup = self.path.join(format!("vault.db.pre-reinit-{}", stamp));
+        for n in 2.. {
+            if !backup.exists() {
+                break;
+            }
+            backup = self.path.join(format!("vault.db.pre-reinit-{}-{}", stamp, n));
+        }
+        self.store.backup_to(&backup)?;
+        let snapshot = self.snapshot(Operation::Reinit)?;
+
+        self.lock();
+        self.write_key_material(password, keyspace, Some(snapshot.as_ref()))?;
+        Ok(backup)
+    }
+
+    /// Set a second password that `unlock` accepts, opening a separate,
+    /// initially empty set of decoy secrets instead of the real ones
+    ///
+    /// Meant for being forced to unlock the vault: the decoy session reads
+    /// and writes only the decoy secrets, tokens and audit log, and can't
+    /// sync or restore backups. Setting it again replaces the decoy secrets
+    /// with an empty set. Requires a session unlocked with the master
+    /// password, which `password` must differ from.
+    pub fn set_duress_password(&mut self, password: &str) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.ensure_main_keyspace()?;
+        let salt = self.key_salt()?;
+        if self.verify_key_in(&crypto::derive_key(password, &salt)?, MAIN_KEYSPACE).is_ok() {
+            return Err(Error::InvalidArgument("The duress password must differ from the master password".to_string()));
+        }
+
+        let salt = crypto::generate_salt();
+        let key = crypto::derive_key(password, &salt)?;
+        let verification = crypto::encrypt(VERIFICATION_TOKEN, &key)?;
+        let result = self.store.clear_keyspace(DURESS_KEYSPACE).and_then(|()| {
+            self.store.set_meta(&keyspace_meta_key(DURESS_KEYSPACE, "salt"), &salt)?;
+            self.store.set_meta(&keyspace_meta_key(DURESS_KEYSPACE, "verification_nonce"), verification.nonce())?;
+            self.store.set_meta(&keyspace_meta_key(DURESS_KEYSPACE, "verification_data"), verification.ciphertext())
+        });
+        let success = result.is_ok();
+        let error = result.as_ref().err().map(ToString::to_string);
+        self.log_audit(Action::ChangePassword, "duress password", success, error.as_deref());
+        result
+    }
+
+    /// Whether a duress password is set; works while locked
+    ///
+    /// Anyone with the database file can tell, see `set_duress_password`.
+    pub fn has_duress_password(&self) -> Result<bool> {
+        Ok(self.duress_key_salt()?.is_some())
+    }
+
+    /// Fail with `Error::InvalidArgument` if `password` is the duress password
+    fn check_differs_from_duress(&self, password: &str) -> Result<()> {
+        let Some(salt) = self.duress_key_salt()? else { return Ok(()) };
+        match self.verify_key_in(&crypto::derive_key(password, &salt)?, DURESS_KEYSPACE) {
+            Ok(()) => Err(Error::InvalidArgument("The master password must differ from the duress password".to_string())),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Fail with `Error::ReadOnly` on a handle from `open_read_only`
+    fn ensure_writable(&self) -> Result<()> {
+        match self.store.is_read_only() {
+            true => Err(Error::ReadOnly),
+            false => Ok(()),
+        }
+    }
+
+    /// Fail with `Error::AccessDenied` in a session unlocked with the duress
+    /// password, for operations that would reach beyond the decoy secrets
+    fn ensure_main_keyspace(&self) -> Result<()> {
+        match self.store.keyspace() {
+            MAIN_KEYSPACE => Ok(()),
+            _ => Err(Error::AccessDenied { reason: "not available in this session".to_string() }),
+        }
+    }
+
+    /// Generate a salt and verification token for `password` in `keyspace`
+    /// and unlock with it; when re-initializing, `reinit` holds the snapshot
+    /// taken first, if any
+    fn write_key_material(&mut self, password: &str, keyspace: u8, reinit: Option<Option<&Snapshot>>) -> Result<()> {
+        // A reinitialized vault keeps its id; a new one gets a random id
+        let id = match self.vault_id() {
+            Ok(id) => id,
+            Err(Error::VaultNotFound { .. }) => uuid::Uuid::new_v4().to_string(),
+            Err(e) => return Err(e),
+        };
+        self.store.set_meta(VAULT_ID_KEY, id.as_bytes())?;
+
         // Generate salt
         let salt = crypto::generate_salt();
-        self.store.set_meta("salt", &salt)?;
 
         // Derive key
         let key = crypto::derive_key(password, &salt)?;
 
         // Create verification token
-        let verification = b"clawbox-verification-token";
-        let encrypted = crypto::encrypt(verification, &key)?;
-        
-        // Store verification data
-        self.store.set_meta("verification_nonce", &encrypted.nonce)?;
-        self.store.set_meta("verification_data", &encrypted.ciphertext)?;
+        let encrypted = crypto::encrypt(VERIFICATION_TOKEN, &key)?;
 
-        self.key = Some(key);
+        // Store salt and verification data, replacing everything the old
+        // key encrypted if re-initializing
+        let meta = [
+            (keyspace_meta_key(keyspace, "salt"), salt),
+            (keyspace_meta_key(keyspace, "verification_nonce"), encrypted.nonce().to_vec()),
+            (keyspace_meta_key(keyspace, "verification_data"), encrypted.ciphertext().to_vec()),
+        ];
+        match reinit {
+            Some(_) => self.store.reset_keyspace(keyspace, &meta)?,
+            None => {
+                for (key, value) in &meta {
+                    self.store.set_meta(key, value)?;
+                }
+            }
+        }
+
+        self.set_key(key, keyspace);
         
         // Log audit
-        self.log_audit(Action::Init, "vault", true, None);
+        self.log_audit_snapshot(&self.actor, Action::Init, "vault", None, reinit.flatten());
         
         Ok(())
     }
@@ -64,205 +501,4005 @@ impl ClawBox {
         Ok(self.store.get_meta("salt")?.is_some())
     }
 
-    /// Unlock vault with master password
-    pub fn unlock(&mut self, password: &str) -> Result<()> {
-        let salt = self
-            .store
-            .get_meta("salt")?
-            .ok_or(Error::VaultNotFound {
-                path: self.path.to_string_lossy().to_string(),
-            })?;
+    /// Stable id of this vault, shared by its copies on every device
+    ///
+    /// Random for vaults initialized since ids were introduced; older vaults
+    /// derive one from their salt, so copies made before then agree on it.
+    /// Works while locked.
+    pub fn vault_id(&self) -> Result<String> {
+        if let Some(id) = self.store.get_meta(VAULT_ID_KEY)? {
+            return String::from_utf8(id).map_err(|_| Error::Other("Vault id is not valid UTF-8".to_string()));
+        }
+        let digest = Sha256::new().chain_update(b"clawbox vault id").chain_update(self.key_salt()?).finalize();
+        Ok(uuid::Builder::from_random_bytes(digest[..16].try_into().expect("16 bytes")).into_uuid().to_string())
+    }
 
-        let key = crypto::derive_key(password, &salt)?;
+    /// Check `password` against the one the vault is unlocked with, without
+    /// changing its state
+    ///
+    /// Wrong passwords count towards the same lockout as `unlock`'s, so an
+    /// unlocked handle can't be used to guess the password faster.
+    fn verify_password(&self, password: &str) -> Result<()> {
+        self.check_unlock_allowed()?;
+        let keyspace = self.store.keyspace();
+        let salt = self.store.get_meta(&keyspace_meta_key(keyspace, "salt"))?.ok_or(Error::InvalidPassword)?;
+        match self.verify_key_in(&crypto::derive_key(password, &salt)?, keyspace) {
+            Err(Error::InvalidPassword) => {
+                if !self.store.is_read_only() {
+                    self.record_unlock_failure()?;
+                }
+                Err(Error::InvalidPassword)
+            }
+            Err(e) => Err(e),
+            Ok(()) => self.clear_unlock_failures(),
+        }
+    }
 
-        // Verify password
-        let nonce = self.store.get_meta("verification_nonce")?
-            .ok_or(Error::InvalidPassword)?;
-        let ciphertext = self.store.get_meta("verification_data")?
-            .ok_or(Error::InvalidPassword)?;
+    /// Unlock vault with master password, or with the duress password into
+    /// the decoy secrets (see `set_duress_password`)
+    ///
+    /// After `UNLOCK_ATTEMPTS` wrong passwords in a row, fails with
+    /// `Error::TooManyAttempts` without trying the password until a cooldown
+    /// ends; the cooldown doubles with every further wrong password.
+    pub fn unlock(&mut self, password: &str) -> Result<()> {
+        self.check_unlock_allowed()?;
+        let key = crypto::derive_key(password, &self.key_salt()?)?;
+        // Derived even for the master password, so both take equally long
+        let duress_key = match self.duress_key_salt()? {
+            Some(salt) => Some(crypto::derive_key(password, &salt)?),
+            None => None,
+        };
+        self.unlock_derived_keys(key, duress_key)
+    }
 
-        let encrypted = EncryptedData { nonce, ciphertext };
-        let decrypted = crypto::decrypt(&encrypted, &key)
-            .map_err(|_| Error::InvalidPassword)?;
+    /// Wrong passwords since the last successful unlock; works while locked
+    pub fn unlock_lockout(&self) -> Result<UnlockLockout> {
+        self.store.unlock_lockout()
+    }
 
-        if decrypted.as_slice() != b"clawbox-verification-token" {
-            return Err(Error::InvalidPassword);
+    /// Fail with `Error::TooManyAttempts` while wrong passwords keep `unlock`
+    /// from trying another, e.g. before prompting for one
+    pub fn check_unlock_allowed(&self) -> Result<()> {
+        // The password may have changed elsewhere since the vault was opened
+        self.store.refresh_meta()?;
+        match self.unlock_lockout()?.retry_after((self.clock)()) {
+            Some(retry_after) => Err(Error::TooManyAttempts { retry_after }),
+            None => Ok(()),
         }
+    }
 
-        self.key = Some(key);
-        Ok(())
+    /// Count a wrong password, starting or extending the cooldown
+    fn record_unlock_failure(&self) -> Result<()> {
+        let mut lockout = self.unlock_lockout()?;
+        lockout.failures = lockout.failures.saturating_add(1);
+        if let Some(cooldown) = unlock_cooldown(lockout.failures) {
+            let cooldown = chrono::Duration::from_std(cooldown).expect("cooldown fits");
+            lockout.locked_until = Some((self.clock)() + cooldown);
+        }
+        self.store.set_unlock_lockout(&lockout)
     }
 
-    /// Lock the vault
-    pub fn lock(&mut self) {
-        if let Some(key) = self.key.take() {
-            // Key will be zeroized on drop
-            drop(key);
+    /// Forget the wrong passwords counted so far, after a right one
+    fn clear_unlock_failures(&self) -> Result<()> {
+        if !self.store.is_read_only() && self.unlock_lockout()? != UnlockLockout::default() {
+            self.store.set_unlock_lockout(&UnlockLockout::default())?;
         }
+        Ok(())
     }
 
-    /// Check if vault is unlocked
-    pub fn is_unlocked(&self) -> bool {
-        self.key.is_some()
+    /// Salt the master key is derived from
+    ///
+    /// With `unlock_derived`, lets a caller run the slow derivation without
+    /// holding the vault.
+    pub fn key_salt(&self) -> Result<Vec<u8>> {
+        self.store.refresh_meta()?;
+        self.store.get_meta("salt")?.ok_or(Error::VaultNotFound {
+            path: self.path.to_string_lossy().to_string(),
+        })
     }
 
-    /// Get a secret value
-    pub fn get(&self, path: &str) -> Result<Option<String>> {
-        let key = self.key.as_ref().ok_or(Error::VaultLocked)?;
+    /// Salt a duress password's key is derived from, if one is set
+    ///
+    /// Like `key_salt`, for a caller deriving keys itself; see
+    /// `unlock_derived_keys`.
+    pub fn duress_key_salt(&self) -> Result<Option<Vec<u8>>> {
+        self.store.get_meta(&keyspace_meta_key(DURESS_KEYSPACE, "salt"))
+    }
 
-        let encrypted_data = self.store.get(path)?;
-        
-        match encrypted_data {
-            Some(data) => {
-                // Parse encrypted data (nonce + ciphertext)
-                if data.len() < 12 {
-                    self.log_audit(Action::Read, path, false, Some("Invalid data format"));
-                    return Err(Error::Decryption("Invalid data format".to_string()));
+    /// Unlock with a key derived from the master password and `key_salt`
+    pub fn unlock_derived(&mut self, key: DerivedKey) -> Result<()> {
+        self.unlock_derived_keys(key, None)
+    }
+
+    /// Unlock with keys derived from a password and `key_salt`, and from the
+    /// same password and `duress_key_salt` if there is one
+    pub fn unlock_derived_keys(&mut self, key: DerivedKey, duress_key: Option<DerivedKey>) -> Result<()> {
+        self.check_unlock_allowed()?;
+        let (key, keyspace) = match self.verify_key_in(&key, MAIN_KEYSPACE) {
+            Ok(()) => (key, MAIN_KEYSPACE),
+            Err(Error::InvalidPassword) => match duress_key {
+                Some(duress_key) if self.verify_key_in(&duress_key, DURESS_KEYSPACE).is_ok() => {
+                    (duress_key, DURESS_KEYSPACE)
                 }
-                
-                let nonce = data[..12].to_vec();
-                let ciphertext = data[12..].to_vec();
-                
-                let encrypted = EncryptedData { nonce, ciphertext };
-                let plaintext = crypto::decrypt(&encrypted, key)?;
-                
-                let value = String::from_utf8(plaintext)
-                    .map_err(|e| Error::Decryption(e.to_string()))?;
-                
-                self.log_audit(Action::Read, path, true, None);
-                Ok(Some(value))
-            }
-            None => {
-                self.log_audit(Action::Read, path, false, Some("Not found"));
-                Ok(None)
-            }
-        }
+                _ => {
+                    // Whoever can read the file can try passwords offline anyway
+                    if !self.store.is_read_only() {
+                        self.record_unlock_failure()?;
+                    }
+                    return Err(Error::InvalidPassword);
+                }
+            },
+            Err(e) => return Err(e),
+        };
+        self.clear_unlock_failures()?;
+        self.set_key(key, keyspace);
+        Ok(())
     }
 
-    /// Set a secret value
-    pub fn set(&mut self, path: &str, value: &str, opts: SetOptions) -> Result<()> {
-        let key = self.key.as_ref().ok_or(Error::VaultLocked)?;
+    /// Change the master password, re-encrypting every secret under the new key
+    ///
+    /// Unlocked with the duress password, changes that one and re-encrypts
+    /// the decoy secrets instead.
+    pub fn change_password(&mut self, old_password: &str, new_password: &str) -> Result<()> {
+        self.change_password_with_progress(old_password, new_password, |_, _| {})
+    }
 
-        // Encrypt value
-        let encrypted = crypto::encrypt(value.as_bytes(), key)?;
-        
-        // Combine nonce + ciphertext for storage
-        let mut data = encrypted.nonce;
-        data.extend(encrypted.ciphertext);
+    /// Like `change_password`, reporting `(done, total)` values re-encrypted
+    pub fn change_password_with_progress(
+        &mut self,
+        old_password: &str,
+        new_password: &str,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let keyspace = self.store.keyspace();
+        let salt = self
+            .store
+            .get_meta(&keyspace_meta_key(keyspace, "salt"))?
+            .ok_or(Error::VaultNotFound {
+                path: self.path.to_string_lossy().to_string(),
+            })?;
 
-        // Create secret info
-        let info = SecretInfo {
-            path: path.to_string(),
-            access: opts.access,
-            tags: opts.tags,
-            note: opts.note,
-            created_at: chrono::Utc::now(),
-            updated_at: chrono::Utc::now(),
-        };
+        let old_key = crypto::derive_key(old_password, &salt)?;
+        self.verify_key_in(&old_key, keyspace)?;
+        if keyspace == MAIN_KEYSPACE {
+            self.check_differs_from_duress(new_password)?;
+        }
+        // Vaults from before ids were stored derive theirs from the salt
+        let id = self.vault_id()?;
+        let snapshot = self.snapshot(Operation::ChangePassword)?;
+
+        let new_salt = crypto::generate_salt();
+        let new_key = crypto::derive_key(new_password, &new_salt)?;
+        let verification = crypto::encrypt(VERIFICATION_TOKEN, &new_key)?;
 
-        match self.store.set(path, &data, &info) {
+        let (salt_key, nonce_key, data_key) = (
+            keyspace_meta_key(keyspace, "salt"),
+            keyspace_meta_key(keyspace, "verification_nonce"),
+            keyspace_meta_key(keyspace, "verification_data"),
+        );
+        let result = self.store.rekey(
+            |data| seal(&new_key, &open_sealed(&old_key, data)?),
+            &[
+                (VAULT_ID_KEY, id.as_bytes()),
+                (&salt_key, &new_salt),
+                (&nonce_key, verification.nonce()),
+                (&data_key, verification.ciphertext()),
+            ],
+            progress,
+        );
+
+        match result {
             Ok(_) => {
-                self.log_audit(Action::Write, path, true, None);
+                self.set_key(new_key, keyspace);
+                self.log_audit_snapshot(&self.actor, Action::ChangePassword, "vault", None, snapshot.as_ref());
                 Ok(())
             }
             Err(e) => {
-                self.log_audit(Action::Write, path, false, Some(&e.to_string()));
+                self.log_audit_snapshot(&self.actor, Action::ChangePassword, "vault", Some(&e.to_string()), snapshot.as_ref());
                 Err(e)
             }
         }
     }
 
-    /// Delete a secret
-    pub fn delete(&mut self, path: &str) -> Result<bool> {
-        if !self.is_unlocked() {
-            return Err(Error::VaultLocked);
+    /// Unlock vault with a previously derived key (e.g. held by a session agent)
+    pub fn unlock_with_key(&mut self, key_bytes: &[u8]) -> Result<()> {
+        if !self.is_initialized()? {
+            return Err(Error::VaultNotFound {
+                path: self.path.to_string_lossy().to_string(),
+            });
         }
-        match self.store.delete(path) {
-            Ok(deleted) => {
-                self.log_audit(Action::Delete, path, deleted, if deleted { None } else { Some("Not found") });
-                Ok(deleted)
+
+        let key = DerivedKey::try_from_slice(key_bytes)?;
+        self.store.refresh_meta()?;
+        let keyspace = self.keyspace_of(&key)?;
+
+        self.set_key(key, keyspace);
+        Ok(())
+    }
+
+    /// Create an access token named `name` that can read what `scope` allows
+    ///
+    /// The token is returned only here; the vault keeps a hash of it and the
+    /// vault key wrapped with a key derived from it. `unlock_with_token` then
+    /// opens the vault in a read-only mode limited to the scope, and every
+    /// access is audited as actor `token:<name>`. Scopes are enforced by
+    /// ClawBox, not by encryption: a token together with a copy of the vault
+    /// database can recover the vault key. Changing the master password
+    /// revokes every token.
+    pub fn create_token(&mut self, name: &str, scope: TokenScope) -> Result<SecretValue> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let key = &*self.key.get(&self.store)?;
+        let key_path = format!("token:{}", name);
+
+        let created = check_token_name(name).and_then(|()| normalize_scope(scope)).and_then(|scope| {
+            let mut secret = Zeroizing::new([0u8; 32]);
+            rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut *secret);
+            let token = SecretValue::new(format!("{}{}", TOKEN_PREFIX, hex(&*secret)));
+
+            let vault_key = Zeroizing::new(key.to_bytes());
+            let stored = StoredToken {
+                info: TokenInfo { name: name.to_string(), scope, created_at: (self.clock)() },
+                token_hash: token_hash(&token),
+                wrapped_key: seal(&crypto::token_wrapping_key(token.as_bytes()), &vault_key)?,
+            };
+            self.store.insert_token(&stored)?;
+            Ok(token)
+        });
+
+        match created {
+            Ok(token) => {
+                self.log_audit(Action::CreateToken, &key_path, true, None);
+                Ok(token)
             }
             Err(e) => {
-                self.log_audit(Action::Delete, path, false, Some(&e.to_string()));
+                self.log_audit(Action::CreateToken, &key_path, false, Some(&e.to_string()));
                 Err(e)
             }
         }
     }
 
-    /// List all secrets
-    pub fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
-        if !self.is_unlocked() {
-            return Err(Error::VaultLocked);
-        }
-        self.store.list(pattern)
+    /// Access tokens, by name
+    pub fn tokens(&self) -> Result<Vec<TokenInfo>> {
+        self.ensure_full_access()?;
+        self.ensure_unlocked()?;
+        self.store.tokens()
     }
 
-    /// Get vault path
-    pub fn path(&self) -> &Path {
-        &self.path
-    }
-    
-    /// Query audit log
-    pub fn audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
-        let logger = AuditLogger::new(self.store.connection());
-        logger.query(filter)
-    }
-    
-    /// Verify audit log integrity
-    pub fn verify_audit_integrity(&self) -> Result<bool> {
-        let logger = AuditLogger::new(self.store.connection());
-        logger.verify_integrity()
+    /// Revoke the access token `name`; returns whether it existed
+    ///
+    /// Handles already unlocked with it keep their key until locked.
+    pub fn revoke_token(&mut self, name: &str) -> Result<bool> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        let key_path = format!("token:{}", name);
+        let revoked = self.store.delete_tokens(Some(name))? > 0;
+        match revoked {
+            true => self.log_audit(Action::RevokeToken, &key_path, true, None),
+            false => self.log_audit(Action::RevokeToken, &key_path, false, Some("Not found")),
+        }
+        Ok(revoked)
     }
-    
-    /// Log an audit entry
-    fn log_audit(&self, action: Action, key_path: &str, success: bool, error: Option<&str>) {
-        let logger = AuditLogger::new(self.store.connection());
-        let mut entry = AuditEntry::new(action, key_path, success)
-            .with_actor(ActorInfo::human());
-        if let Some(err) = error {
-            entry = entry.with_error(err);
+
+    /// Unlock with an access token from `create_token`, into a mode that can
+    /// only read the secrets its scope allows
+    ///
+    /// Unknown, revoked and expired tokens fail with `Error::AccessDenied`.
+    /// Locking, or unlocking again some other way, ends the restriction.
+    pub fn unlock_with_token(&mut self, token: &str) -> Result<()> {
+        if !self.is_initialized()? {
+            return Err(Error::VaultNotFound {
+                path: self.path.to_string_lossy().to_string(),
+            });
         }
-        let _ = logger.log(entry);
+
+        let Some((stored, keyspace)) = self.store.token_by_hash(&token_hash(token))? else {
+            let e = Error::AccessDenied { reason: "unknown or revoked access token".to_string() };
+            self.log_audit_as(&ActorInfo::token("unknown"), Action::Unlock, "vault", false, Some(&e.to_string()));
+            return Err(e);
+        };
+        let actor = ActorInfo::token(&stored.info.name);
+
+        let unwrapped = match stored.info.scope.expires_at {
+            Some(expires_at) if expires_at <= (self.clock)() => Err(Error::AccessDenied {
+                reason: format!("access token {:?} has expired", stored.info.name),
+            }),
+            _ => open_sealed(&crypto::token_wrapping_key(token.as_bytes()), &stored.wrapped_key)
+                .map(Zeroizing::new)
+                .and_then(|bytes| DerivedKey::try_from_slice(&bytes))
+                .and_then(|key| self.verify_key_in(&key, keyspace).map(|()| key))
+                .map_err(|_| Error::AccessDenied {
+                    reason: format!("access token {:?} no longer opens this vault", stored.info.name),
+                }),
+        };
+        let key = match unwrapped {
+            Ok(key) => key,
+            Err(e) => {
+                self.log_audit_as(&actor, Action::Unlock, "vault", false, Some(&e.to_string()));
+                return Err(e);
+            }
+        };
+
+        self.set_key(key, keyspace);
+        let previous_actor = std::mem::replace(&mut self.actor, actor);
+        self.token = Some(TokenGrant { info: stored.info, previous_actor });
+        self.log_audit(Action::Unlock, "vault", true, None);
+        Ok(())
     }
-}
 
-impl Drop for ClawBox {
-    fn drop(&mut self) {
-        self.lock();
+    /// The access token this handle is unlocked with, if any
+    pub fn token(&self) -> Option<&TokenInfo> {
+        self.token.as_ref().map(|grant| &grant.info)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
+    /// Export the raw vault key so it can be cached outside this process
+    pub fn export_key(&self) -> Result<Zeroizing<Vec<u8>>> {
+        self.ensure_full_access()?;
+        let key = &*self.key.get(&self.store)?;
+        Ok(Zeroizing::new(key.to_bytes()))
+    }
 
-    #[test]
-    fn test_vault_lifecycle() {
-        let temp_dir = TempDir::new().unwrap();
-        let vault_path = temp_dir.path().join("test-vault");
+    /// Key for sync backends, derived from the vault key
+    ///
+    /// Sync never needs the vault key itself; every hand-off is recorded in
+    /// the audit log.
+    pub fn sync_key(&self) -> Result<SyncKey> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        self.log_audit_as(&ActorInfo::system("sync"), Action::Export, "sync key", true, None);
+        Ok(SyncKey::derive(key))
+    }
 
-        // Create and init vault
-        let mut vault = ClawBox::open(&vault_path).unwrap();
-        vault.init("test-password").unwrap();
+    /// Reopen the database after its file was replaced, e.g. by a sync pull
+    ///
+    /// Stays unlocked only if the current key also opens the new database.
+    pub fn reload(&mut self) -> Result<()> {
+        let keyspace = self.store.keyspace();
+        let db_path = self.path.join("vault.db");
+        let options = self.store.options().clone();
+        self.store = match self.store.is_read_only() {
+            true => SqliteStore::open_read_only_with(&db_path, &options)?,
+            false => SqliteStore::open_with(&db_path, &options)?,
+        };
+        if let Some(key) = self.key.key.get_mut().take() {
+            if self.verify_key_in(&key, keyspace).is_ok() {
+                self.set_key(key, keyspace);
+            }
+        }
+        Ok(())
+    }
 
-        // Set secret
-        vault.set("test/key", "secret-value", Default::default()).unwrap();
+    /// Check a key against the verification token stored for `keyspace`
+    fn verify_key_in(&self, key: &DerivedKey, keyspace: u8) -> Result<()> {
+        let nonce = self.store.get_meta(&keyspace_meta_key(keyspace, "verification_nonce"))?
+            .ok_or(Error::InvalidPassword)?;
+        let ciphertext = self.store.get_meta(&keyspace_meta_key(keyspace, "verification_data"))?
+            .ok_or(Error::InvalidPassword)?;
 
-        // Lock and unlock
-        vault.lock();
-        assert!(!vault.is_unlocked());
+        let encrypted = EncryptedData::from_parts(&nonce, &ciphertext);
+        let decrypted = crypto::decrypt(&encrypted, key)
+            .map_err(|_| Error::InvalidPassword)?;
 
-        vault.unlock("test-password").unwrap();
-        assert!(vault.is_unlocked());
+        if decrypted.as_slice() != VERIFICATION_TOKEN {
+            return Err(Error::InvalidPassword);
+        }
 
-        // Get secret
-        let value = vault.get("test/key").unwrap();
-        assert_eq!(value, Some("secret-value".to_string()));
+        Ok(())
+    }
+
+    /// The keyspace `key` opens
+    fn keyspace_of(&self, key: &DerivedKey) -> Result<u8> {
+        match self.verify_key_in(key, MAIN_KEYSPACE) {
+            Err(Error::InvalidPassword) => self.verify_key_in(key, DURESS_KEYSPACE).map(|()| DURESS_KEYSPACE),
+            verified => verified.map(|()| MAIN_KEYSPACE),
+        }
+    }
+
+    /// Lock the vault
+    pub fn lock(&mut self) {
+        if let Some(key) = self.key.key.get_mut().take() {
+            // Key will be zeroized on drop
+            drop(key);
+        }
+        self.end_token_grant();
+        self.store.set_keyspace(MAIN_KEYSPACE);
+    }
+
+    /// Lock once no operation has used the vault for `after` (`None`, the
+    /// default, keeps it unlocked until `lock`)
+    ///
+    /// Checked lazily: the first operation after the window has passed drops
+    /// the key, records a system-initiated lock and fails with
+    /// `Error::VaultLocked`.
+    pub fn set_auto_lock(&mut self, after: Option<Duration>) {
+        self.key.auto_lock = after;
+        self.key.last_used.set(Instant::now());
+    }
+
+    /// Time left before auto-lock; `None` while locked or without auto-lock
+    pub fn time_until_lock(&self) -> Option<Duration> {
+        self.key.lock_if_idle(&self.store);
+        let after = self.key.auto_lock?;
+        self.key.key.borrow().as_ref()?;
+        Some(after.saturating_sub(self.key.last_used.get().elapsed()))
+    }
+
+    /// Fail unless unlocked, counting as activity for auto-lock
+    fn ensure_unlocked(&self) -> Result<()> {
+        self.key.get(&self.store).map(drop)
+    }
+
+    fn set_key(&mut self, key: DerivedKey, keyspace: u8) {
+        self.store.set_keyspace(keyspace);
+        *self.key.key.get_mut() = Some(key);
+        self.key.last_used.set(Instant::now());
+        self.end_token_grant();
+    }
+
+    fn end_token_grant(&mut self) {
+        if let Some(grant) = self.token.take() {
+            self.actor = grant.previous_actor;
+        }
+    }
+
+    /// Fail with `Error::AccessDenied` on a handle unlocked with an access
+    /// token, which may only read
+    fn ensure_full_access(&self) -> Result<()> {
+        match &self.token {
+            Some(grant) => Err(Error::AccessDenied {
+                reason: format!("access token {:?} can only read secrets", grant.info.name),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail with `Error::AccessDenied` if `path` is outside the access
+    /// token's scope, logging the denied read
+    fn check_token_scope(&self, path: &str) -> Result<()> {
+        match &self.token {
+            Some(grant) if !grant.info.scope.covers(path) => {
+                let e = Error::AccessDenied {
+                    reason: format!("{} is outside the scope of access token {:?}", path, grant.info.name),
+                };
+                self.log_audit(Action::Read, path, false, Some(&e.to_string()));
+                Err(e)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Lock the vault because it sat idle, recording it as done by the system
+    ///
+    /// Does nothing if the vault is already locked.
+    pub fn auto_lock(&mut self) {
+        if self.is_unlocked() {
+            self.log_audit_as(&ActorInfo::system("auto-lock"), Action::Lock, "vault", true, None);
+            self.lock();
+        }
+    }
+
+    /// Record that a session holding this vault's key (e.g. in the agent) was locked
+    pub fn record_lock(&self) {
+        self.log_audit(Action::Lock, "vault", true, None);
+    }
+
+    /// Record that the session agent's session `id` for this vault was revoked
+    pub fn record_session_revoke(&self, id: &str) {
+        let entry = AuditEntry::new(Action::Lock, "vault", true)
+            .with_actor(self.actor.clone())
+            .with_metadata(serde_json::json!({ "session": id, "revoked": true }));
+        self.record_audit(entry, None);
+    }
+
+    /// Record that the session agent refused a process asking about this
+    /// vault; `metadata` describes the process
+    pub fn record_agent_refusal(&self, reason: &str, metadata: serde_json::Value) {
+        let entry = AuditEntry::new(Action::Unlock, "session agent", false)
+            .with_actor(ActorInfo::system("agent"))
+            .with_error(reason)
+            .with_metadata(metadata);
+        self.record_audit(entry, None);
+    }
+
+    /// Record that a secret copied to the clipboard was wiped from it
+    pub fn record_clipboard_clear(&self, path: &str) {
+        self.log_audit(Action::ClipboardClear, path, true, None);
+    }
+
+    /// Record an attempt to unlock with a raw key handed over by an app (e.g. after Touch ID)
+    pub fn record_key_unlock(&self, error: Option<&str>) {
+        self.log_audit(Action::Unlock, "vault key", error.is_none(), error);
+    }
+
+    /// Record that the raw vault key was handed to an app
+    pub fn record_key_export(&self) {
+        self.log_audit(Action::Export, "vault key", true, None);
+    }
+
+    /// Backups of this vault's database, newest first (see `backup`)
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        backup::list(&self.path)
+    }
+
+    /// Swap a backup in for the database, keeping the database it replaces
+    /// as a new backup (returned) and at most `keep` backups in all
+    ///
+    /// Stays unlocked only if the current key also opens the backup.
+    pub fn restore_backup(&mut self, name: &str, keep: usize) -> Result<Option<Backup>> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.ensure_main_keyspace()?;
+        let replaced = backup::restore(&self.path, name, keep)?;
+        self.reload()?;
+        self.log_audit(Action::Rollback, &format!("backup {}", name), true, None);
+        Ok(replaced)
+    }
+
+    /// Snapshots of this vault's database, newest first (see `snapshot`)
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>> {
+        snapshot::list(&self.path)
+    }
+
+    /// Copy the database to a new snapshot taken before `operation`, keeping
+    /// the newest `snapshot_keep`; `None` if snapshots are turned off
+    ///
+    /// The vault takes these itself before changing the password,
+    /// re-initializing, importing a bundle over existing secrets, syncing in
+    /// remote changes and restoring a snapshot, and names them in the audit
+    /// entries for those operations. Call it before other runs of writes
+    /// that should be undoable, such as an import made of separate `set`s.
+    pub fn snapshot(&self, operation: Operation) -> Result<Option<Snapshot>> {
+        self.ensure_writable()?;
+        match self.snapshot_keep()? {
+            0 => Ok(None),
+            keep => snapshot::create(&self.store, &self.path, operation, keep).map(Some),
+        }
+    }
+
+    /// Snapshots kept by `snapshot`; 0 means none are taken
+    pub fn snapshot_keep(&self) -> Result<usize> {
+        Ok(self
+            .store
+            .get_meta(SNAPSHOT_KEEP_KEY)?
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(snapshot::DEFAULT_KEEP))
+    }
+
+    /// Change how many snapshots are kept, deleting any beyond the new number
+    pub fn set_snapshot_keep(&mut self, keep: usize) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.ensure_main_keyspace()?;
+        if self.actor.is_restricted() {
+            return Err(Error::AccessDenied {
+                reason: format!("{} actors cannot change snapshot settings", self.actor.actor_type),
+            });
+        }
+        self.store.set_meta(SNAPSHOT_KEEP_KEY, keep.to_string().as_bytes())?;
+        snapshot::prune(&self.path, keep)?;
+        Ok(())
+    }
+
+    /// Delete all but the newest `keep` snapshots, returning the deleted ones
+    pub fn prune_snapshots(&self, keep: usize) -> Result<Vec<Snapshot>> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        snapshot::prune(&self.path, keep)
+    }
+
+    /// Swap a snapshot in for the database, first taking a snapshot of the
+    /// database it replaces (returned)
+    ///
+    /// Stays unlocked only if the current key also opens the snapshot.
+    pub fn restore_snapshot(&mut self, name: &str) -> Result<Option<Snapshot>> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.ensure_main_keyspace()?;
+        let restored = snapshot::find(&self.path, name)?;
+        let data = Zeroizing::new(std::fs::read(&restored.path)?);
+        let replaced = self.snapshot(Operation::Restore)?;
+        sync::write_atomic(&self.path.join("vault.db"), &data)?;
+        self.reload()?;
+        self.log_audit_snapshot(&self.actor, Action::Rollback, &format!("snapshot {}", name), None, replaced.as_ref());
+        Ok(replaced)
+    }
+
+    /// Check if vault is unlocked
+    ///
+    /// Doesn't count as activity for auto-lock, so UIs can poll it.
+    pub fn is_unlocked(&self) -> bool {
+        self.key.lock_if_idle(&self.store);
+        self.key.key.borrow().is_some()
+    }
+
+    /// Get a secret value, wiped from memory when dropped
+    pub fn get_secret(&self, path: &str) -> Result<Option<SecretValue>> {
+        self.checked_read(path, &self.actor, None)?.map(|value| utf8_secret(path, value)).transpose()
+    }
+
+    /// Get a secret value as a plain `String`
+    #[deprecated(note = "the returned String is never wiped; use `get_secret`")]
+    pub fn get(&self, path: &str) -> Result<Option<String>> {
+        Ok(self.get_secret(path)?.map(|value| value.expose().to_string()))
+    }
+
+    /// Get a secret value as raw bytes, as the current actor, wiped from
+    /// memory when dropped
+    pub fn get_bytes(&self, path: &str) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        self.get_bytes_as(path, &self.actor)
+    }
+
+    /// Act as `actor` from now on (the default is the local human)
+    ///
+    /// Reads are checked against its access level and every audit entry
+    /// names it. While unlocked with an access token the token is the actor;
+    /// `actor` takes over once the vault is locked.
+    pub fn set_actor(&mut self, actor: ActorInfo) {
+        match &mut self.token {
+            Some(grant) => grant.previous_actor = actor,
+            None => self.actor = actor,
+        }
+    }
+
+    /// Decrypt on `jobs` threads in `get_prefix`, `export_bundle` and
+    /// `export_plaintext` (1, the default, stays on the calling thread; 0
+    /// uses one per CPU)
+    ///
+    /// Only the decryption is spread out: the ciphertexts are still read
+    /// on this thread, and results come back in the same order as the
+    /// serial path gives them. Without the `parallel` feature this has no
+    /// effect.
+    pub fn set_decrypt_jobs(&mut self, jobs: usize) {
+        self.jobs = jobs;
+    }
+
+    /// Who this handle acts as
+    pub fn actor(&self) -> &ActorInfo {
+        &self.actor
+    }
+
+    /// How writes by this handle are recorded in `created_by`, e.g.
+    /// `ai:claude`; see `history`
+    fn writer(&self) -> String {
+        format!("{}:{}", self.actor.actor_type, self.actor.identifier)
+    }
+
+    /// Get a secret value on behalf of `actor`, enforcing its access level
+    ///
+    /// Humans may read anything. AI agents and apps may read Public and
+    /// Normal secrets; Sensitive ones need the approval handler's consent
+    /// (denied when none is set), and Critical ones are denied outright.
+    ///
+    /// Levels that require reauthentication (Critical by default) fail with
+    /// `Error::ReauthRequired`; see `get_with_reauth`.
+    pub fn get_bytes_as(&self, path: &str, actor: &ActorInfo) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        Ok(self.checked_read(path, actor, None)?.map(Zeroizing::new))
+    }
+
+    /// Get a secret value, checking the master password again first
+    ///
+    /// For secrets whose access level requires it (see `requires_reauth`);
+    /// the check is logged as its own audit entry before the read.
+    pub fn get_with_reauth(&self, path: &str, password: &str) -> Result<Option<SecretValue>> {
+        self.checked_read(path, &self.actor, Some(password))?
+            .map(|value| utf8_secret(path, value))
+            .transpose()
+    }
+
+    /// Whether reading `access` secrets needs the master password again,
+    /// even with the vault unlocked; by default only Critical ones do
+    pub fn requires_reauth(&self, access: AccessLevel) -> Result<bool> {
+        Ok(match self.store.get_meta(&self.store.keyspace_meta_key(reauth_key(access)))? {
+            Some(value) => value == b"true",
+            None => access == AccessLevel::Critical,
+        })
+    }
+
+    /// Change whether reading `access` secrets needs the master password
+    /// again; takes the master password, so an unlocked vault alone can't
+    /// turn the check off
+    pub fn set_requires_reauth(&mut self, access: AccessLevel, required: bool, password: &str) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.verify_password(password)?;
+        let value: &[u8] = if required { b"true" } else { b"false" };
+        self.store.set_meta(&self.store.keyspace_meta_key(reauth_key(access)), value)
+    }
+
+    /// Most `access` secrets each actor of `actor_type` may read per minute;
+    /// `None`, the default, means no limit
+    pub fn read_rate_limit(&self, actor_type: &str, access: AccessLevel) -> Result<Option<u32>> {
+        Ok(self
+            .store
+            .get_meta(&self.store.keyspace_meta_key(&rate_limit_key(actor_type, access)))?
+            .and_then(|v| String::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok()))
+    }
+
+    /// Limit how many `access` secrets each actor of `actor_type` (`human`,
+    /// `ai`, `app` or `token`) may read per minute, or lift the limit
+    ///
+    /// Every actor, e.g. `ai:claude`, has its own allowance, shared by all
+    /// processes using the vault. Reads over it fail with
+    /// `Error::RateLimited` and are audited. Only a human can change limits.
+    pub fn set_read_rate_limit(&mut self, actor_type: &str, access: AccessLevel, per_minute: Option<u32>) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        if self.actor.is_restricted() {
+            return Err(Error::AccessDenied {
+                reason: format!("{} actors cannot change rate limits", self.actor.actor_type),
+            });
+        }
+        if !RATE_LIMITED_ACTORS.contains(&actor_type) {
+            return Err(Error::InvalidArgument(format!(
+                "unknown actor type {:?}; use human, ai, app or token",
+                actor_type
+            )));
+        }
+        match per_minute {
+            Some(0) => Err(Error::InvalidArgument("a rate limit must allow at least one read".to_string())),
+            Some(n) => self.store.set_meta(&self.store.keyspace_meta_key(&rate_limit_key(actor_type, access)), n.to_string().as_bytes()),
+            None => self.store.delete_meta(&self.store.keyspace_meta_key(&rate_limit_key(actor_type, access))),
+        }
+    }
+
+    /// Count a read of an `access` secret against `actor`'s rate limit
+    fn take_read(&self, access: AccessLevel, actor: &ActorInfo) -> Result<()> {
+        let Some(per_minute) = self.read_rate_limit(&actor.actor_type, access)? else {
+            return Ok(());
+        };
+        // A read that can't be counted can't be allowed
+        self.ensure_writable()?;
+        let actor_id = format!("{}:{}", actor.actor_type, actor.identifier);
+        match self.store.take_read(&actor_id, access, per_minute, (self.clock)())? {
+            Some(retry_after) => Err(Error::RateLimited { retry_after }),
+            None => Ok(()),
+        }
+    }
+
+    /// Read for `actor` after its access checks; `password` reauthenticates
+    /// for levels that need it
+    fn checked_read(&self, path: &str, actor: &ActorInfo, password: Option<&str>) -> Result<Option<Vec<u8>>> {
+        self.ensure_unlocked()?;
+        // Nobody else reads through a token
+        let actor = if self.token.is_some() { &self.actor } else { actor };
+        self.check_token_scope(path)?;
+        if let Some(info) = self.store.get_info(path)? {
+            if let Err(e) = self.check_access(path, info.access, actor) {
+                self.log_audit_as(actor, Action::Read, path, false, Some(&e.to_string()));
+                return Err(e);
+            }
+            match password {
+                Some(password) => {
+                    if let Err(e) = self.verify_password(password) {
+                        self.log_audit_as(actor, Action::Reauth, path, false, Some(&e.to_string()));
+                        return Err(e);
+                    }
+                    self.log_audit_as(actor, Action::Reauth, path, true, None);
+                }
+                None if self.requires_reauth(info.access)? => {
+                    let e = Error::ReauthRequired { path: path.to_string() };
+                    self.log_audit_as(actor, Action::Read, path, false, Some(&e.to_string()));
+                    return Err(e);
+                }
+                None => {}
+            }
+            if let Err(e) = self.take_read(info.access, actor) {
+                self.log_audit_as(actor, Action::Read, path, false, Some(&e.to_string()));
+                return Err(e);
+            }
+        }
+        self.read(path, actor)
+    }
+
+    /// Set (or clear) the handler asked to approve Sensitive reads by AI
+    /// agents and apps
+    pub fn set_approval_handler(&mut self, handler: Option<ApprovalHandler>) {
+        self.approver = handler;
+    }
+
+    /// Set the hooks run for matching audit entries, replacing any set before
+    ///
+    /// They run on background threads; dropping the vault waits for them.
+    pub fn set_hooks(&mut self, hooks: Vec<Hook>) {
+        self.hooks.set(hooks);
+    }
+
+    /// Wait for the hooks started so far to finish and record how they went
+    pub fn wait_for_hooks(&self) {
+        self.hooks.wait();
+    }
+
+    fn check_access(&self, path: &str, access: AccessLevel, actor: &ActorInfo) -> Result<()> {
+        if let Some(grant) = &self.token {
+            return match grant.info.scope.allows(access) {
+                true => Ok(()),
+                false => Err(Error::AccessDenied {
+                    reason: format!("{} is {:?}, beyond what access token {:?} may read", path, access, grant.info.name),
+                }),
+            };
+        }
+        if !actor.is_restricted() {
+            return Ok(());
+        }
+        match access {
+            AccessLevel::Public | AccessLevel::Normal => Ok(()),
+            AccessLevel::Critical => Err(Error::AccessDenied {
+                reason: format!("{} is Critical and can only be read by a human", path),
+            }),
+            AccessLevel::Sensitive => {
+                let request = ApprovalRequest { path, actor, access };
+                match self.approver.as_ref().map(|approve| approve(&request)) {
+                    Some(Approval::Approved) => Ok(()),
+                    Some(Approval::TimedOut) => Err(Error::ApprovalTimeout),
+                    Some(Approval::Denied) => Err(Error::AccessDenied {
+                        reason: format!("reading {} was not approved", path),
+                    }),
+                    None => Err(Error::AccessDenied {
+                        reason: format!("{} is Sensitive and no approver is available", path),
+                    }),
+                }
+            }
+        }
+    }
+
+    fn read(&self, path: &str, actor: &ActorInfo) -> Result<Option<Vec<u8>>> {
+        let key = &*self.key.get(&self.store)?;
+
+        match self.store.get(path)? {
+            Some(data) => match open_sealed(key, &data) {
+                Ok(plaintext) => {
+                    self.log_audit_as(actor, Action::Read, path, true, None);
+                    Ok(Some(plaintext))
+                }
+                Err(e) => {
+                    self.log_audit_as(actor, Action::Read, path, false, Some(&e.to_string()));
+                    Err(e)
+                }
+            },
+            None => {
+                self.log_audit_as(actor, Action::Read, path, false, Some("Not found"));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Get several secret values at once, in the order requested
+    ///
+    /// Logs a single audit entry covering all requested paths. Access is
+    /// checked for every path before any value is decrypted, so one denied
+    /// path, or one that requires reauthentication, fails the whole read.
+    pub fn get_many(&self, paths: &[&str]) -> Result<Vec<Option<SecretValue>>> {
+        let key = &*self.key.get(&self.store)?;
+        let key_path = paths.join(",");
+
+        for path in paths {
+            if let Some(grant) = self.token.as_ref().filter(|grant| !grant.info.scope.covers(path)) {
+                let e = Error::AccessDenied {
+                    reason: format!("{} is outside the scope of access token {:?}", path, grant.info.name),
+                };
+                self.log_audit(Action::Read, &key_path, false, Some(&e.to_string()));
+                return Err(e);
+            }
+            if let Some(info) = self.store.get_info(path)? {
+                let checked = self
+                    .check_access(path, info.access, &self.actor)
+                    .and_then(|()| match self.requires_reauth(info.access)? {
+                        true => Err(Error::ReauthRequired { path: path.to_string() }),
+                        false => Ok(()),
+                    })
+                    .and_then(|()| self.take_read(info.access, &self.actor));
+                if let Err(e) = checked {
+                    self.log_audit(Action::Read, &key_path, false, Some(&e.to_string()));
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut values = Vec::with_capacity(paths.len());
+        let mut missing = vec![];
+        for path in paths {
+            let value = match self.store.get(path)? {
+                Some(data) => {
+                    let plaintext = open_sealed(key, &data).and_then(|p| utf8_secret(path, p));
+                    match plaintext {
+                        Ok(value) => Some(value),
+                        Err(e) => {
+                            self.log_audit(Action::Read, &key_path, false, Some(&e.to_string()));
+                            return Err(e);
+                        }
+                    }
+                }
+                None => {
+                    missing.push(*path);
+                    None
+                }
+            };
+            values.push(value);
+        }
+
+        if missing.is_empty() {
+            self.log_audit(Action::Read, &key_path, true, None);
+        } else {
+            let msg = format!("Not found: {}", missing.join(", "));
+            self.log_audit(Action::Read, &key_path, false, Some(&msg));
+        }
+        Ok(values)
+    }
+
+    /// Every secret below `prefix`, decrypted, sorted by path
+    ///
+    /// `app`, `app/` and `app/*` all cover `app/db/url` and everything else
+    /// below `app`; an empty prefix covers the whole vault. The secrets are
+    /// loaded in one query and logged as one audit entry for `app/*` with
+    /// their number. Access is checked for each as `get_secret` would, and
+    /// any refusal fails the whole read, including `Error::ReauthRequired`
+    /// (see `get_prefix_with_reauth`). Secrets outside an access token's
+    /// scope are left out.
+    pub fn get_prefix(&self, prefix: &str) -> Result<Vec<(String, SecretValue)>> {
+        self.prefix_read(prefix, None)
+    }
+
+    /// `get_prefix`, checking the master password again first if any of
+    /// the secrets requires it
+    pub fn get_prefix_with_reauth(&self, prefix: &str, password: &str) -> Result<Vec<(String, SecretValue)>> {
+        self.prefix_read(prefix, Some(password))
+    }
+
+    fn prefix_read(&self, prefix: &str, password: Option<&str>) -> Result<Vec<(String, SecretValue)>> {
+        let key = &*self.key.get(&self.store)?;
+        let prefix = prefix.trim_end_matches('*').trim_end_matches('/');
+        let key_path = match prefix {
+            "" => "*".to_string(),
+            prefix => format!("{}/*", prefix),
+        };
+        let fail = |e: Error| {
+            self.log_audit(Action::Read, &key_path, false, Some(&e.to_string()));
+            e
+        };
+
+        let mut rows = self.store.get_prefix(prefix)?;
+        if let Some(grant) = &self.token {
+            rows.retain(|(info, _)| grant.info.scope.covers(&info.path));
+        }
+        let mut reauthenticated = false;
+        for (info, _) in &rows {
+            self.check_access(&info.path, info.access, &self.actor).map_err(fail)?;
+            if !reauthenticated && self.requires_reauth(info.access)? {
+                let password = password.ok_or_else(|| fail(Error::ReauthRequired { path: info.path.clone() }))?;
+                let verified = self.verify_password(password);
+                self.log_audit(Action::Reauth, &key_path, verified.is_ok(), verified.as_ref().err().map(|e| e.to_string()).as_deref());
+                verified?;
+                reauthenticated = true;
+            }
+            self.take_read(info.access, &self.actor).map_err(fail)?;
+        }
+
+        let highest = rows.iter().map(|(info, _)| info.access).max_by_key(|access| *access as u8);
+        let sealed: Vec<(&str, &[u8])> = rows.iter().map(|(info, data)| (info.path.as_str(), data.as_slice())).collect();
+        let values = open_all(key, self.jobs, &sealed).map_err(fail)?;
+        let secrets: Vec<(String, SecretValue)> = rows.into_iter().map(|(info, _)| info.path).zip(values).collect();
+        let entry = AuditEntry::new(Action::Read, &key_path, true)
+            .with_actor(self.actor.clone())
+            .with_metadata(serde_json::json!({ "prefix": prefix, "count": secrets.len() }));
+        self.record_audit(entry, highest);
+        Ok(secrets)
+    }
+
+    /// Get a secret's metadata without decrypting it
+    pub fn info(&self, path: &str) -> Result<Option<SecretInfo>> {
+        self.ensure_unlocked()?;
+        self.check_token_scope(path)?;
+        self.store.get_info(path)
+    }
+
+    /// Get a secret value together with its metadata
+    pub fn get_with_info(&self, path: &str) -> Result<Option<(SecretValue, SecretInfo)>> {
+        self.ensure_unlocked()?;
+        self.check_token_scope(path)?;
+
+        let info = match self.store.get_info(path)? {
+            Some(info) => info,
+            None => {
+                self.log_audit(Action::Read, path, false, Some("Not found"));
+                return Ok(None);
+            }
+        };
+
+        Ok(self.get_secret(path)?.map(|value| (value, info)))
+    }
+
+    /// Set a secret value
+    pub fn set(&mut self, path: &str, value: &str, opts: SetOptions) -> Result<()> {
+        self.set_bytes(path, value.as_bytes(), opts)
+    }
+
+    /// Set a secret value from raw bytes, which need not be UTF-8
+    ///
+    /// `path` is normalized first; see `normalize_and_validate_path`.
+    pub fn set_bytes(&mut self, path: &str, value: &[u8], opts: SetOptions) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let key = &*self.key.get(&self.store)?;
+        let path = &normalize_and_validate_path(path)?;
+
+        let data = seal(key, value)?;
+        let info = new_info(path, value, &opts);
+
+        match self.store.set_as(path, &data, &info, &self.writer()) {
+            Ok(_) => {
+                self.log_audit(Action::Write, path, true, None);
+                self.store.add_pending_changes(1)
+            }
+            Err(e) => {
+                self.log_audit(Action::Write, path, false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Set several secrets in one transaction
+    ///
+    /// Nothing is written if any path is invalid or the write fails. Paths
+    /// are normalized as in `set_bytes`.
+    pub fn set_many<V: AsRef<[u8]>>(&mut self, items: &[(&str, V, SetOptions)]) -> Result<()> {
+        self.set_many_with_progress(items, |_, _| {})
+    }
+
+    /// Like `set_many`, reporting `(done, total)` secrets written
+    ///
+    /// The audit entries are written with the secrets, in the same
+    /// transaction, so a large import costs one commit.
+    pub fn set_many_with_progress<V: AsRef<[u8]>>(
+        &mut self,
+        items: &[(&str, V, SetOptions)],
+        progress: impl FnMut(usize, usize),
+    ) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let key = &*self.key.get(&self.store)?;
+
+        let paths = items
+            .iter()
+            .map(|(path, _, _)| normalize_and_validate_path(path))
+            .collect::<Result<Vec<_>>>()?;
+        let sealed = items
+            .iter()
+            .zip(&paths)
+            .map(|((_, value, opts), path)| {
+                let value = value.as_ref();
+                Ok((seal(key, value)?, new_info(path, value, opts)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let records: Vec<(&str, &[u8], &SecretInfo)> = paths
+            .iter()
+            .zip(&sealed)
+            .map(|(path, (data, info))| (path.as_str(), data.as_slice(), info))
+            .collect();
+        let entries: Vec<AuditEntry> = paths
+            .iter()
+            .map(|path| AuditEntry::new(Action::Write, path, true).with_actor(self.actor.clone()))
+            .collect();
+
+        match self.store.set_many_audited(&records, &self.writer(), &entries, progress) {
+            Ok(()) => {
+                for (entry, (_, info)) in entries.iter().zip(&sealed) {
+                    self.dispatch_hooks(entry, Some(info.access));
+                }
+                self.store.add_pending_changes(items.len() as u64)
+            }
+            Err(e) => {
+                let failed: Vec<AuditEntry> =
+                    entries.into_iter().map(|entry| AuditEntry { success: false, ..entry }.with_error(&e.to_string())).collect();
+                let _ = self.store.log_audit_many(&failed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Export secrets into an encrypted bundle
+    ///
+    /// Critical secrets are left out unless `opts.include_critical` is set;
+    /// as with `export_plaintext`, the actor's access checks and rate limits
+    /// apply to every secret included, and if any of them needs
+    /// reauthentication (see `set_requires_reauth`), `password` must be the
+    /// master password. Returns the bundle and the paths it contains.
+    pub fn export_bundle(
+        &self,
+        passphrase: &str,
+        opts: &ExportOptions,
+        password: Option<&str>,
+    ) -> Result<(Vec<u8>, Vec<String>)> {
+        self.ensure_full_access()?;
+        let key = &*self.key.get(&self.store)?;
+
+        let infos: Vec<SecretInfo> = self
+            .store
+            .list(None)?
+            .into_iter()
+            .filter(|info| info.access != AccessLevel::Critical || opts.include_critical)
+            .collect();
+        if let Err(e) = self.check_export(&infos, password) {
+            self.log_audit(Action::Export, "bundle", false, Some(&e.to_string()));
+            return Err(e);
+        }
+        let mut rows = vec![];
+        for info in infos {
+            if let Some(data) = self.store.get(&info.path)? {
+                rows.push((info, data));
+            }
+        }
+        let sealed: Vec<&[u8]> = rows.iter().map(|(_, data)| data.as_slice()).collect();
+        let values = open_all_bytes(key, self.jobs, &sealed)?;
+
+        let mut secrets = vec![];
+        for ((info, _), value) in rows.into_iter().zip(values) {
+            secrets.push(BundleSecret {
+                value,
+                access: info.access as u8,
+                tags: info.tags,
+                note: info.note,
+                expires_at: info.expires_at.map(|t| t.timestamp()),
+                path: info.path,
+            });
+        }
+
+        let bundle = bundle::seal(&secrets, passphrase)?;
+        let paths: Vec<String> = secrets.into_iter().map(|s| s.path).collect();
+        for path in &paths {
+            self.log_audit(Action::Export, path, true, None);
+        }
+        Ok((bundle, paths))
+    }
+
+    /// What `export_plaintext` would hand out, from metadata alone
+    pub fn export_summary(&self, include_critical: bool) -> Result<ExportSummary> {
+        self.ensure_full_access()?;
+        let mut summary = ExportSummary::default();
+        self.for_each_secret(&ListFilter::default(), |info| {
+            if info.access == AccessLevel::Critical && !include_critical {
+                summary.skipped_critical += 1;
+                return Ok(());
+            }
+            summary.count += 1;
+            summary.highest = summary.highest.into_iter().chain([info.access]).max_by_key(|access| *access as u8);
+            summary.needs_reauth = summary.needs_reauth || self.requires_reauth(info.access)?;
+            Ok(())
+        })?;
+        Ok(summary)
+    }
+
+    /// Decrypt secrets to be written out unencrypted, e.g. to a JSON file
+    ///
+    /// Refused unless `opts.plaintext_ok` records the user's consent.
+    /// Critical secrets are left out unless `opts.include_critical` is set;
+    /// the actor's access checks and rate limits apply to every secret
+    /// included, and if any of them needs reauthentication (see
+    /// `set_requires_reauth`), `password` must be the master password.
+    /// Rather than a read per secret, one `Export` entry is logged for
+    /// `opts.destination`, with the format and counts in its metadata.
+    pub fn export_plaintext(
+        &self,
+        opts: &PlaintextExportOptions,
+        password: Option<&str>,
+    ) -> Result<Vec<(SecretInfo, SecretValue)>> {
+        let mut secrets = vec![];
+        self.export_plaintext_each(opts, password, |info, value| {
+            secrets.push((info, value));
+            Ok(())
+        })?;
+        Ok(secrets)
+    }
+
+    /// `export_plaintext`, handing each secret to `f` as soon as it is
+    /// decrypted instead of collecting them; returns how many were handed out
+    ///
+    /// Every access check passes before `f` first runs. An error from `f`
+    /// stops the export and is logged like any other failure.
+    pub fn export_plaintext_each(
+        &self,
+        opts: &PlaintextExportOptions,
+        password: Option<&str>,
+        mut f: impl FnMut(SecretInfo, SecretValue) -> Result<()>,
+    ) -> Result<usize> {
+        self.ensure_full_access()?;
+        self.ensure_unlocked()?;
+        if !opts.plaintext_ok {
+            return Err(Error::InvalidArgument(
+                "writing secrets unencrypted needs the user's explicit consent".to_string(),
+            ));
+        }
+
+        let (infos, skipped): (Vec<SecretInfo>, Vec<SecretInfo>) = self
+            .store
+            .list(None)?
+            .into_iter()
+            .partition(|info| info.access != AccessLevel::Critical || opts.include_critical);
+        let highest = infos.iter().map(|info| info.access).max_by_key(|access| *access as u8);
+        let result = self.decrypt_for_export(&infos, password, &mut f);
+
+        let mut entry = AuditEntry::new(Action::Export, &opts.destination, result.is_ok())
+            .with_actor(self.actor.clone())
+            .with_metadata(serde_json::json!({
+                "format": opts.format,
+                "plaintext": true,
+                "count": infos.len(),
+                "skipped_critical": skipped.len(),
+                "highest_access": highest.map(|access| format!("{:?}", access).to_lowercase()),
+            }));
+        if let Err(e) = &result {
+            entry = entry.with_error(&e.to_string());
+        }
+        self.record_audit(entry, highest);
+        result
+    }
+
+    /// Run the actor's access checks, reauthentication and rate limits for
+    /// every secret about to be exported
+    fn check_export(&self, infos: &[SecretInfo], password: Option<&str>) -> Result<()> {
+        let mut reauthenticated = false;
+        for info in infos {
+            self.check_access(&info.path, info.access, &self.actor)?;
+            if !reauthenticated && self.requires_reauth(info.access)? {
+                let password = password.ok_or_else(|| Error::ReauthRequired { path: info.path.clone() })?;
+                let verified = self.verify_password(password);
+                self.log_audit(Action::Reauth, &info.path, verified.is_ok(), verified.as_ref().err().map(|e| e.to_string()).as_deref());
+                verified?;
+                reauthenticated = true;
+            }
+            self.take_read(info.access, &self.actor)?;
+        }
+        Ok(())
+    }
+
+    fn decrypt_for_export(
+        &self,
+        infos: &[SecretInfo],
+        password: Option<&str>,
+        f: &mut dyn FnMut(SecretInfo, SecretValue) -> Result<()>,
+    ) -> Result<usize> {
+        self.check_export(infos, password)?;
+
+        // Serially each secret is handed out as soon as it is decrypted;
+        // with more jobs a batch is decrypted at a time
+        let key = &*self.key.get(&self.store)?;
+        let batch = if decrypt_threads(self.jobs) == 1 { 1 } else { DECRYPT_BATCH };
+        let mut count = 0;
+        for infos in infos.chunks(batch) {
+            let mut rows = Vec::with_capacity(infos.len());
+            for info in infos {
+                if let Some(data) = self.store.get(&info.path)? {
+                    rows.push((info, data));
+                }
+            }
+            let sealed: Vec<(&str, &[u8])> = rows.iter().map(|(info, data)| (info.path.as_str(), data.as_slice())).collect();
+            for ((info, _), value) in rows.iter().zip(open_all(key, self.jobs, &sealed)?) {
+                f((*info).clone(), value)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Import an encrypted bundle in one transaction
+    pub fn import_bundle(&mut self, bundle: &[u8], passphrase: &str, mode: ConflictMode) -> Result<ImportReport> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+
+        let mut report = ImportReport::default();
+        let mut items = vec![];
+        let mut overwrites = false;
+        for mut secret in bundle::open(bundle, passphrase)? {
+            // Nothing is written if any path in the bundle is invalid
+            secret.path = normalize_and_validate_path(&secret.path)?;
+            if self.store.get_info(&secret.path)?.is_some() {
+                if mode == ConflictMode::Skip {
+                    report.skipped.push(secret.path);
+                    continue;
+                }
+                overwrites = true;
+            }
+            let opts = SetOptions {
+                access: secret.access_level(),
+                ttl: secret.expires_at.map(|expires_at| {
+                    let remaining = expires_at - chrono::Utc::now().timestamp();
+                    std::time::Duration::from_secs(remaining.max(0) as u64)
+                }),
+                tags: secret.tags,
+                note: secret.note,
+            };
+            items.push((secret.path, Zeroizing::new(secret.value), opts));
+        }
+
+        let refs: Vec<(&str, &[u8], SetOptions)> = items
+            .iter()
+            .map(|(path, value, opts)| (path.as_str(), value.as_slice(), opts.clone()))
+            .collect();
+        if overwrites {
+            report.snapshot = self.snapshot(Operation::Import)?.map(|snapshot| snapshot.name);
+        }
+        self.set_many(&refs)?;
+        report.imported = items.into_iter().map(|(path, _, _)| path).collect();
+        Ok(report)
+    }
+
+    /// Delete a secret
+    ///
+    /// `DeleteMode::Hard` also removes its history, so it cannot be rolled
+    /// back, and is audited as a purge.
+    pub fn delete(&mut self, path: &str, mode: DeleteMode) -> Result<bool> {
+        Ok(self.delete_many(&[path], mode)?[0])
+    }
+
+    /// Delete several secrets atomically; returns which ones existed
+    ///
+    /// With `DeleteMode::Hard`, a path whose secret is already deleted but
+    /// whose history remains counts as existing.
+    pub fn delete_many(&mut self, paths: &[&str], mode: DeleteMode) -> Result<Vec<bool>> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        let (action, result) = match mode {
+            DeleteMode::Soft => (Action::Delete, self.store.delete_many(paths)),
+            DeleteMode::Hard => (Action::Purge, self.store.purge_many(paths)),
+        };
+        match result {
+            Ok(existed) => {
+                for (path, deleted) in paths.iter().zip(&existed) {
+                    self.log_audit(action, path, *deleted, if *deleted { None } else { Some("Not found") });
+                }
+                self.store.add_pending_changes(existed.iter().filter(|deleted| **deleted).count() as u64)?;
+                Ok(existed)
+            }
+            Err(e) => {
+                for path in paths {
+                    self.log_audit(action, path, false, Some(&e.to_string()));
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Delete every secret whose path starts with `prefix`, atomically
+    ///
+    /// The prefix is literal, so `"db/"` spares `"dbx"` while `"db"` does not.
+    /// One audit entry is written for the whole operation, keyed `<prefix>*`.
+    /// Returns the deleted paths.
+    pub fn delete_matching(&mut self, prefix: &str) -> Result<Vec<String>> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        let key = format!("{}*", prefix);
+        match self.store.delete_matching(prefix) {
+            Ok(deleted) => {
+                self.log_audit(Action::Delete, &key, true, None);
+                self.store.add_pending_changes(deleted.len() as u64)?;
+                Ok(deleted)
+            }
+            Err(e) => {
+                self.log_audit(Action::Delete, &key, false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Move a secret, with its metadata and history, to a new path
+    ///
+    /// Fails with `Error::AlreadyExists` if `new` is taken. `new` is
+    /// normalized as in `set_bytes`; `old` is used as stored.
+    pub fn rename(&mut self, old: &str, new: &str) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        let new = &normalize_and_validate_path(new)?;
+        let key = format!("{} -> {}", old, new);
+        match self.store.rename(old, new) {
+            Ok(()) => {
+                self.log_audit(Action::Rename, &key, true, None);
+                self.store.add_pending_changes(1)
+            }
+            Err(e) => {
+                self.log_audit(Action::Rename, &key, false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Copy a secret and its metadata to another path
+    ///
+    /// The copy starts a history of its own. Fails with `Error::AlreadyExists`
+    /// if `dst` is taken and `overwrite` is false. `dst` is normalized as in
+    /// `set_bytes`.
+    pub fn copy(&mut self, src: &str, dst: &str, overwrite: bool) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        let key = &*self.key.get(&self.store)?;
+        let dst = &normalize_and_validate_path(dst)?;
+        // Seal under a fresh nonce rather than duplicate the ciphertext
+        let result = self.store.copy(src, dst, overwrite, &self.writer(), |data| {
+            let value = Zeroizing::new(open_sealed(key, data)?);
+            seal(key, &value)
+        });
+
+        let audit_key = format!("{} -> {}", src, dst);
+        match result {
+            Ok(()) => {
+                self.log_audit(Action::Copy, &audit_key, true, None);
+                self.store.add_pending_changes(1)
+            }
+            Err(e) => {
+                self.log_audit(Action::Copy, &audit_key, false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// List the stored versions of a secret, newest first
+    ///
+    /// Values are never returned, only a keyed fingerprint of each.
+    pub fn history(&self, path: &str) -> Result<Vec<SecretVersion>> {
+        self.ensure_full_access()?;
+        let key = &*self.key.get(&self.store)?;
+
+        self.store
+            .list_versions(path)?
+            .into_iter()
+            .map(|stored| {
+                let plaintext = Zeroizing::new(open_sealed(key, &stored.value)?);
+                Ok(SecretVersion {
+                    version: stored.version,
+                    access: stored.info.access,
+                    created_at: stored.info.created_at,
+                    created_by: stored.created_by,
+                    fingerprint: fingerprint(key, &plaintext),
+                })
+            })
+            .collect()
+    }
+
+    /// Restore an earlier version of a secret, value and metadata
+    ///
+    /// The restored value is written as a new version, so a rollback can itself
+    /// be rolled back. The version's expiry carries over, and its value is
+    /// restored byte for byte, UTF-8 or not. Works for deleted secrets too.
+    pub fn rollback(&mut self, path: &str, version: u32) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        let rollback_path = format!("{}@v{}", path, version);
+
+        let stored = match self.store.get_version(path, version)? {
+            Some(stored) => stored,
+            None => {
+                self.log_audit(Action::Rollback, &rollback_path, false, Some("Not found"));
+                return Err(Error::VersionNotFound { path: path.to_string(), version });
+            }
+        };
+
+        let value = Zeroizing::new(open_sealed(&*self.key.get(&self.store)?, &stored.value)?);
+        // The version's expiry carries over; one already past restores the
+        // secret expired
+        let ttl = stored
+            .info
+            .expires_at
+            .map(|expires_at| (expires_at - chrono::Utc::now()).to_std().unwrap_or_default());
+        let opts = SetOptions {
+            access: stored.info.access,
+            ttl,
+            tags: stored.info.tags,
+            note: stored.info.note,
+        };
+
+        match self.set_bytes(path, &value, opts) {
+            Ok(()) => {
+                self.log_audit(Action::Rollback, &rollback_path, true, None);
+                Ok(())
+            }
+            Err(e) => {
+                self.log_audit(Action::Rollback, &rollback_path, false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// List all secrets
+    ///
+    /// Unlocked with an access token, only those the token may read.
+    pub fn list(&self, pattern: Option<&str>) -> Result<Vec<SecretInfo>> {
+        let mut secrets = vec![];
+        self.for_each_secret(&ListFilter::pattern(pattern), |info| {
+            secrets.push(info);
+            Ok(())
+        })?;
+        Ok(secrets)
+    }
+
+    /// Call `f` with the secrets `filter` matches, as `list` would return
+    /// them, one at a time and without holding them all in memory; stops at
+    /// the first error `f` returns
+    ///
+    /// Secrets come by path. `f` runs while the query is still open, so it
+    /// must not write to the vault.
+    pub fn for_each_secret(&self, filter: &ListFilter, mut f: impl FnMut(SecretInfo) -> Result<()>) -> Result<()> {
+        self.ensure_unlocked()?;
+        self.store.for_each_secret(filter, &mut |info| match &self.token {
+            Some(grant) if !(grant.info.scope.covers(&info.path) && grant.info.scope.allows(info.access)) => Ok(()),
+            _ => f(info),
+        })
+    }
+
+    /// A handle that lists secrets from any thread while this one is busy;
+    /// see `VaultReader`
+    pub fn reader(&self) -> Result<VaultReader> {
+        self.ensure_unlocked()?;
+        Ok(VaultReader { store: self.store.reader(), scope: self.token.as_ref().map(|grant| grant.info.scope.clone()) })
+    }
+
+    /// Remove expired secrets, old versions and (optionally) old audit entries
+    pub fn prune(&mut self, opts: &PruneOptions) -> Result<PruneReport> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+
+        match self.store.prune(chrono::Utc::now(), opts) {
+            Ok(report) => {
+                if !opts.dry_run {
+                    self.log_audit(Action::Prune, "vault", true, None);
+                }
+                Ok(report)
+            }
+            Err(e) => {
+                self.log_audit(Action::Prune, "vault", false, Some(&e.to_string()));
+                Err(e)
+            }
+        }
+    }
+
+    /// Vacuum the database to reclaim space freed by deletions
+    pub fn compact(&self) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.store.compact()
+    }
+
+    /// Sync state persisted in this vault
+    pub fn sync_manager(&self) -> Result<SyncManager> {
+        SyncManager::load(&self.store)
+    }
+
+    /// Persist sync state, e.g. after enabling sync
+    pub fn save_sync_state(&mut self, manager: &SyncManager) -> Result<()> {
+        self.ensure_writable()?;
+        manager.save(&mut self.store)
+    }
+
+    /// Sync state as of the last sync (the default if this vault never
+    /// synced); works while locked
+    pub fn sync_status(&self) -> Result<SyncState> {
+        Ok(self.sync_manager()?.state().clone())
+    }
+
+    /// Edits made since the last sync that pushed them; works while locked
+    pub fn pending_changes(&self) -> Result<u64> {
+        self.store.pending_changes()
+    }
+
+    /// Turn sync on for this vault
+    pub fn enable_sync(&mut self) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_main_keyspace()?;
+        let mut manager = self.sync_manager()?;
+        manager.enable();
+        self.save_sync_state(&manager)
+    }
+
+    /// Turn sync off for this vault; `sync_with` fails until it is enabled again
+    pub fn disable_sync(&mut self) -> Result<()> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_main_keyspace()?;
+        let mut manager = self.sync_manager()?;
+        manager.disable();
+        self.save_sync_state(&manager)
+    }
+
+    /// Changes whenever another process writes to this vault (see
+    /// `SqliteStore::data_version`)
+    pub fn data_version(&self) -> Result<i64> {
+        self.store.data_version()
+    }
+
+    /// Pick up vault settings (policies, sync state, ...) another process
+    /// wrote since they were read; see `SqliteStore::refresh_meta`
+    ///
+    /// Settings are read once when the vault is opened, so a vault kept open
+    /// for long should call this before each operation.
+    pub fn refresh_meta(&self) -> Result<bool> {
+        self.store.refresh_meta()
+    }
+
+    /// Record a sync that moved the whole database at `version` (see
+    /// `SyncManager::record`)
+    pub fn record_sync(&mut self, version: u64) -> Result<()> {
+        let mut manager = self.sync_manager()?;
+        manager.record(version);
+        self.save_sync_state(&manager)
+    }
+
+    /// Sync this vault's secrets with a backend (see `SyncManager::sync`)
+    ///
+    /// Syncs that move data or fail are recorded in the audit log, as is
+    /// each conflict, along with the copy written for it under
+    /// `ConflictResolution::KeepBoth`.
+    pub fn sync_with(&mut self, manager: &mut SyncManager, backend: &dyn SyncBackend) -> Result<SyncReport> {
+        self.ensure_full_access()?;
+        self.ensure_writable()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        let actor = ActorInfo::system("sync");
+        // Remote changes may be merged in; an unreachable backend fails below
+        let snapshot = match backend.get_remote_version() {
+            Ok(version) if version != manager.state().remote_version => self.snapshot(Operation::Pull)?,
+            _ => None,
+        };
+        let report = match manager.sync(&mut self.store, key, backend) {
+            Ok(report) => report,
+            Err(err) => {
+                self.log_audit_snapshot(&actor, Action::Sync, "vault", Some(&err.to_string()), snapshot.as_ref());
+                return Err(err);
+            }
+        };
+        if !matches!(report.result, SyncResult::UpToDate | SyncResult::Unavailable) {
+            self.log_audit_snapshot(&actor, Action::Sync, "vault", None, snapshot.as_ref());
+        }
+        for conflict in &report.conflicts {
+            self.log_audit_as(&actor, Action::SyncConflict, &conflict.path, true, None);
+            if let Some(copy) = &conflict.conflict_copy {
+                self.log_audit_as(&actor, Action::Write, copy, true, None);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Devices that synced through `backend`, most recently seen first
+    ///
+    /// Fails for backends that keep no device registry (WebDAV and git).
+    pub fn sync_devices(&self, backend: &dyn SyncBackend) -> Result<Vec<Device>> {
+        self.ensure_full_access()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        let registry = backend.devices(&SyncKey::derive(key))?.ok_or_else(no_device_registry)?;
+        Ok(registry.devices())
+    }
+
+    /// Remove a device from `backend`'s registry, e.g. one that was retired;
+    /// false if it isn't listed
+    pub fn forget_sync_device(&self, backend: &dyn SyncBackend, device_id: &str) -> Result<bool> {
+        self.ensure_full_access()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        let mut forgotten = false;
+        if !backend.update_devices(&SyncKey::derive(key), &mut |registry| forgotten = registry.forget(device_id))? {
+            return Err(no_device_registry());
+        }
+        Ok(forgotten)
+    }
+
+    /// What syncing with a backend would change (see `SyncManager::diff`)
+    ///
+    /// Nothing is written, not even to the audit log.
+    pub fn sync_diff(&self, backend: &dyn SyncBackend) -> Result<Vec<SyncDiff>> {
+        self.ensure_full_access()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        self.sync_manager()?.diff(&self.store, key, backend)
+    }
+
+    /// Compare this vault with the secrets of a full remote copy, e.g.
+    /// `ICloudSync::remote_records`
+    pub fn sync_diff_records(&self, remote: &[SyncSecret]) -> Result<Vec<SyncDiff>> {
+        self.ensure_full_access()?;
+        self.ensure_main_keyspace()?;
+        let key = &*self.key.get(&self.store)?;
+        self.sync_manager()?.diff_records(&self.store, key, remote)
+    }
+
+    /// Copies written by `ConflictResolution::KeepBoth` that are still unresolved
+    pub fn conflict_copies(&self) -> Result<Vec<SecretInfo>> {
+        Ok(self
+            .list(None)?
+            .into_iter()
+            .filter(|info| info.tags.iter().any(|tag| tag == sync::CONFLICT_TAG) && sync::conflict_origin(&info.path).is_some())
+            .collect())
+    }
+
+    /// Settle a conflict copy, returning the path it was made from
+    ///
+    /// Keeping the remote value moves it to that path (keeping the path's
+    /// metadata if it still exists); either way the copy is deleted.
+    pub fn resolve_conflict(&mut self, copy: &str, keep_remote: bool) -> Result<String> {
+        self.ensure_full_access()?;
+        let info = self
+            .info(copy)?
+            .filter(|info| info.tags.iter().any(|tag| tag == sync::CONFLICT_TAG))
+            .ok_or_else(|| Error::SecretNotFound { path: copy.to_string() })?;
+        let path = sync::conflict_origin(copy)
+            .ok_or_else(|| Error::InvalidArgument(format!("{} is not a sync conflict copy", copy)))?
+            .to_string();
+
+        if keep_remote {
+            let value = self.get_bytes(copy)?.ok_or_else(|| Error::SecretNotFound { path: copy.to_string() })?;
+            let current = self.info(&path)?;
+            let source = current.as_ref().unwrap_or(&info);
+            let opts = SetOptions {
+                access: source.access,
+                ttl: source.expires_at.and_then(|at| (at - chrono::Utc::now()).to_std().ok()),
+                tags: source.tags.iter().filter(|tag| *tag != sync::CONFLICT_TAG).cloned().collect(),
+                note: source.note.clone(),
+            };
+            self.set_bytes(&path, &value, opts)?;
+        }
+        self.delete(copy, DeleteMode::Soft)?;
+        Ok(path)
+    }
+
+    /// Get vault path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Schema version recorded in the vault database
+    ///
+    /// A value above `storage::SCHEMA_VERSION` means the vault was written by
+    /// a newer build.
+    pub fn schema_version(&self) -> Result<u32> {
+        self.store.schema_version()
+    }
+
+    /// Collect vault statistics (works while locked)
+    pub fn stats(&self) -> Result<VaultStats> {
+        let counts = self.store.count_by_access()?;
+        let db_path = self.path.join("vault.db");
+        let db_size = ["", "-wal", "-shm"]
+            .iter()
+            .filter_map(|suffix| {
+                let mut name = db_path.clone().into_os_string();
+                name.push(suffix);
+                std::fs::metadata(name).ok()
+            })
+            .map(|m| m.len())
+            .sum();
+
+        Ok(VaultStats {
+            public_count: counts[0],
+            normal_count: counts[1],
+            sensitive_count: counts[2],
+            critical_count: counts[3],
+            db_size,
+            last_write: self.store.last_write()?,
+            audit_entries: self.store.audit_logger().count()?,
+            schema_version: self.store.schema_version()?,
+            pragmas: self.store.pragmas()?,
+        })
+    }
+    
+    /// Run every integrity check: the database, the audit chain and, while
+    /// unlocked, the decryption of every stored value and version
+    pub fn verify(&self) -> Result<IntegrityReport> {
+        let key = self.key.get(&self.store).ok();
+        let undecryptable = match &key {
+            Some(key) => self
+                .store
+                .sealed_values()?
+                .into_iter()
+                .filter(|(_, data)| open_sealed(key, data).map(Zeroizing::new).is_err())
+                .map(|(label, _)| label)
+                .collect(),
+            None => vec![],
+        };
+
+        Ok(IntegrityReport {
+            database_errors: self.store.integrity_check()?,
+            audit_chain_valid: self.verify_audit_integrity()?,
+            values_checked: key.is_some(),
+            undecryptable,
+            schema_version: self.store.schema_version()?,
+        })
+    }
+
+    /// Query audit log
+    pub fn audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let logger = self.store.audit_logger();
+        logger.query(filter)
+    }
+    
+    /// Verify audit log integrity
+    pub fn verify_audit_integrity(&self) -> Result<bool> {
+        let logger = self.store.audit_logger();
+        logger.verify_integrity()
+    }
+    
+    /// Log an audit entry for the current actor
+    fn log_audit(&self, action: Action, key_path: &str, success: bool, error: Option<&str>) {
+        self.log_audit_as(&self.actor, action, key_path, success, error);
+    }
+
+    /// Log an audit entry for a specific actor
+    fn log_audit_as(&self, actor: &ActorInfo, action: Action, key_path: &str, success: bool, error: Option<&str>) {
+        let mut entry = AuditEntry::new(action, key_path, success)
+            .with_actor(actor.clone());
+        if let Some(err) = error {
+            entry = entry.with_error(err);
+        }
+        self.record_audit(entry, None);
+    }
+
+    /// Log an audit entry for `actor` that names the snapshot taken before
+    /// the operation, if any
+    fn log_audit_snapshot(&self, actor: &ActorInfo, action: Action, key_path: &str, error: Option<&str>, snapshot: Option<&Snapshot>) {
+        let mut entry = AuditEntry::new(action, key_path, error.is_none()).with_actor(actor.clone());
+        if let Some(err) = error {
+            entry = entry.with_error(err);
+        }
+        if let Some(snapshot) = snapshot {
+            entry = entry.with_metadata(serde_json::json!({ "snapshot": snapshot.name }));
+        }
+        self.record_audit(entry, None);
+    }
+
+    /// Log `entry`, then start the hooks it matches; `level` is the access
+    /// level it is about, looked up from its path if not given
+    fn record_audit(&self, entry: AuditEntry, level: Option<AccessLevel>) {
+        if !entry.success || self.hooks.is_empty() {
+            let _ = self.store.log_audit(entry);
+            return;
+        }
+
+        let level = match level {
+            None if self.hooks.needs_level() => self.store.get_info(&entry.key_path).ok().flatten().map(|info| info.access),
+            level => level,
+        };
+        let _ = self.store.log_audit(entry.clone());
+        self.dispatch_hooks(&entry, level);
+    }
+
+    /// Start the hooks a logged, successful `entry` matches
+    fn dispatch_hooks(&self, entry: &AuditEntry, level: Option<AccessLevel>) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        // The sidecar of a read-only vault has no room for outcomes
+        let db_path = (!self.store.is_read_only()).then(|| self.path.join("vault.db"));
+        self.hooks.dispatch(entry, level, db_path);
+    }
+}
+
+/// Metadata for a value about to be written
+fn new_info(path: &str, value: &[u8], opts: &SetOptions) -> SecretInfo {
+    let now = chrono::Utc::now();
+    SecretInfo {
+        path: path.to_string(),
+        access: opts.access,
+        tags: opts.tags.clone(),
+        note: opts.note.clone(),
+        created_at: now,
+        updated_at: now,
+        expires_at: opts
+            .ttl
+            .and_then(|ttl| chrono::Duration::from_std(ttl).ok())
+            .map(|ttl| now + ttl),
+        size: value.len(),
+    }
+}
+
+fn no_device_registry() -> Error {
+    Error::Other("This sync backend keeps no device registry".to_string())
+}
+
+/// Token names appear in audit entries as `token:<name>`
+fn check_token_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    match valid {
+        true => Ok(()),
+        false => Err(Error::InvalidArgument(format!(
+            "invalid token name {:?}; use up to 64 letters, digits, '-', '_' and '.'",
+            name
+        ))),
+    }
+}
+
+/// Check a scope for `create_token`, turning `llm/*` and `llm/` into `llm`
+fn normalize_scope(mut scope: TokenScope) -> Result<TokenScope> {
+    if scope.path_prefixes.is_empty() {
+        return Err(Error::InvalidArgument("a token needs at least one path prefix".to_string()));
+    }
+    if (scope.max_access as u8) > (AccessLevel::Normal as u8) {
+        return Err(Error::InvalidArgument(
+            "tokens can't read Sensitive or Critical secrets".to_string(),
+        ));
+    }
+    for prefix in &mut scope.path_prefixes {
+        let trimmed = prefix.trim().trim_end_matches('*').trim_end_matches('/');
+        *prefix = normalize_and_validate_path(trimmed)?;
+    }
+    Ok(scope)
+}
+
+/// How a token is looked up; the token itself is never stored
+fn token_hash(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"clawbox-token");
+    hasher.update(token.as_bytes());
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Short fingerprint of a value, keyed so it can't be brute-forced offline
+fn fingerprint(key: &DerivedKey, plaintext: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"clawbox-fingerprint");
+    hasher.update(key.as_bytes());
+    hasher.update(plaintext);
+    let digest = hasher.finalize();
+    hex(&digest[..6])
+}
+
+/// Encrypt a value into the stored format, chunked if large
+fn seal(key: &DerivedKey, plaintext: &[u8]) -> Result<Vec<u8>> {
+    crypto::seal_value(plaintext, key)
+}
+
+/// Decrypt a value in the stored format
+fn open_sealed(key: &DerivedKey, data: &[u8]) -> Result<Vec<u8>> {
+    crypto::open_value(data, key)
+}
+
+/// Wrap a decrypted value read from `path`, which must be UTF-8
+fn utf8_secret(path: &str, value: Vec<u8>) -> Result<SecretValue> {
+    SecretValue::from_utf8(value).ok_or_else(|| Error::NotUtf8 { path: path.to_string() })
+}
+
+/// Secrets an export decrypts at a time when spread over several threads
+const DECRYPT_BATCH: usize = 1024;
+
+/// Fewer values than this are decrypted on the calling thread whatever
+/// `jobs` says; starting the threads would cost more than it saves
+const PARALLEL_MIN: usize = 64;
+
+/// How many threads `jobs` (see `ClawBox::set_decrypt_jobs`) stands for
+fn decrypt_threads(jobs: usize) -> usize {
+    if !cfg!(feature = "parallel") {
+        return 1;
+    }
+    match jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    }
+}
+
+/// Decrypt each `(path, data)` into a UTF-8 secret, in order, on up to
+/// `jobs` threads; the first failure in order is the one returned
+fn open_all(key: &DerivedKey, jobs: usize, sealed: &[(&str, &[u8])]) -> Result<Vec<SecretValue>> {
+    map_all(jobs, sealed, |(path, data)| utf8_secret(path, open_sealed(key, data)?))
+}
+
+/// `open_all` for values that need not be UTF-8
+fn open_all_bytes(key: &DerivedKey, jobs: usize, sealed: &[&[u8]]) -> Result<Vec<Vec<u8>>> {
+    map_all(jobs, sealed, |data| open_sealed(key, data))
+}
+
+fn map_all<T: Sync, U: Send>(jobs: usize, items: &[T], f: impl Fn(&T) -> Result<U> + Sync) -> Result<Vec<U>> {
+    let threads = decrypt_threads(jobs);
+    if threads == 1 || items.len() < PARALLEL_MIN {
+        return items.iter().map(f).collect();
+    }
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|e| Error::Other(format!("Failed to start decryption threads: {}", e)))?;
+        let results: Vec<Result<U>> = pool.install(|| items.par_iter().map(&f).collect());
+        results.into_iter().collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    unreachable!("decrypt_threads is 1 without the parallel feature")
+}
+
+impl Drop for ClawBox {
+    fn drop(&mut self) {
+        self.lock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_vault_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault_path = temp_dir.path().join("test-vault");
+
+        // Create and init vault
+        let mut vault = ClawBox::open(&vault_path).unwrap();
+        vault.init("test-password").unwrap();
+
+        // Set secret
+        vault.set("test/key", "secret-value", Default::default()).unwrap();
+
+        // Lock and unlock
+        vault.lock();
+        assert!(!vault.is_unlocked());
+
+        vault.unlock("test-password").unwrap();
+        assert!(vault.is_unlocked());
+
+        // Get secret
+        let value = vault.get_secret("test/key").unwrap();
+        assert_eq!(value.as_deref(), Some("secret-value"));
 
         // Delete secret
-        assert!(vault.delete("test/key").unwrap());
-        assert!(vault.get("test/key").unwrap().is_none());
+        assert!(vault.delete("test/key", DeleteMode::Soft).unwrap());
+        assert!(vault.get_secret("test/key").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unlock_with_exported_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("test/key", "secret-value", Default::default()).unwrap();
+
+        let key = vault.export_key().unwrap();
+        vault.lock();
+        assert!(matches!(vault.export_key(), Err(Error::VaultLocked)));
+
+        let mut reopened = ClawBox::open(temp_dir.path()).unwrap();
+        reopened.unlock_with_key(&key).unwrap();
+        assert_eq!(reopened.get_secret("test/key").unwrap().as_deref(), Some("secret-value"));
+
+        let mut wrong = key.to_vec();
+        wrong[0] ^= 0xff;
+        reopened.lock();
+        assert!(matches!(reopened.unlock_with_key(&wrong), Err(Error::InvalidPassword)));
+        assert!(reopened.unlock_with_key(&key[..16]).is_err());
+    }
+
+    #[test]
+    fn test_vault_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        assert!(matches!(vault.vault_id(), Err(Error::VaultNotFound { .. })));
+        vault.init("test-password").unwrap();
+        let id = vault.vault_id().unwrap();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        let other = TempDir::new().unwrap();
+        let mut other = ClawBox::open(other.path()).unwrap();
+        other.init("test-password").unwrap();
+        assert_ne!(other.vault_id().unwrap(), id);
+
+        vault.change_password("test-password", "new-password").unwrap();
+        vault.reinit("newer-password").unwrap();
+        assert_eq!(vault.vault_id().unwrap(), id);
+
+        // Vaults from before ids derive a stable one that survives a new salt
+        vault.store.connection().execute("DELETE FROM vault_meta WHERE key = ?", [VAULT_ID_KEY]).unwrap();
+        vault.store.reload_meta().unwrap();
+        let derived = vault.vault_id().unwrap();
+        assert_ne!(derived, id);
+        assert_eq!(vault.vault_id().unwrap(), derived);
+        vault.change_password("newer-password", "password").unwrap();
+        assert_eq!(vault.vault_id().unwrap(), derived);
+    }
+
+    #[test]
+    fn test_sync_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let sync_key = vault.sync_key().unwrap();
+        assert_ne!(sync_key.key().as_bytes(), vault.export_key().unwrap().as_slice());
+        assert_eq!(sync_key.key().as_bytes(), vault.sync_key().unwrap().key().as_bytes());
+
+        let handed_out = vault.audit(&AuditFilter { action: Some(Action::Export), ..Default::default() }).unwrap();
+        assert_eq!(handed_out.len(), 2);
+        assert!(handed_out.iter().all(|entry| entry.key_path == "sync key" && entry.actor.actor_type == "system"));
+
+        vault.lock();
+        assert!(matches!(vault.sync_key(), Err(Error::VaultLocked)));
+    }
+
+    #[test]
+    fn test_change_password() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("old-password").unwrap();
+        vault.set("test/key", "secret-value", Default::default()).unwrap();
+
+        assert!(matches!(
+            vault.change_password("wrong-password", "new-password"),
+            Err(Error::InvalidPassword)
+        ));
+
+        vault.change_password("old-password", "new-password").unwrap();
+        assert_eq!(vault.get_secret("test/key").unwrap().as_deref(), Some("secret-value"));
+
+        vault.lock();
+        assert!(matches!(vault.unlock("old-password"), Err(Error::InvalidPassword)));
+        vault.unlock("new-password").unwrap();
+        assert_eq!(vault.get_secret("test/key").unwrap().as_deref(), Some("secret-value"));
+    }
+
+    #[test]
+    fn test_stats_while_locked() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        vault.set("a", "1", Default::default()).unwrap();
+        vault
+            .set("b", "2", SetOptions { access: crate::AccessLevel::Critical, ..Default::default() })
+            .unwrap();
+        vault.lock();
+
+        let stats = vault.stats().unwrap();
+        assert_eq!(stats.secret_count(), 2);
+        assert_eq!(stats.normal_count, 1);
+        assert_eq!(stats.critical_count, 1);
+        assert!(stats.db_size > 0);
+        assert!(stats.last_write.is_some());
+        assert!(stats.audit_entries >= 3);
+        assert_eq!(stats.schema_version, crate::storage::SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_store_options() {
+        use crate::storage::{StoreOptions, Synchronous};
+
+        let temp_dir = TempDir::new().unwrap();
+        let store = StoreOptions {
+            page_size: 8192,
+            cache_size: -8000,
+            mmap_size: 1 << 20,
+            synchronous: Synchronous::Normal,
+            wal_autocheckpoint: 500,
+        };
+        let opts = OpenOptions { store: store.clone(), ..Default::default() };
+        let mut vault = ClawBox::open_with(temp_dir.path(), &opts).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("a", "1", Default::default()).unwrap();
+
+        let pragmas = vault.stats().unwrap().pragmas;
+        assert_eq!(pragmas.page_size, 8192);
+        assert_eq!(pragmas.cache_size, -8000);
+        assert_eq!(pragmas.synchronous, Some(Synchronous::Normal));
+        assert_eq!(pragmas.wal_autocheckpoint, 500);
+        vault.reload().unwrap();
+        assert_eq!(vault.stats().unwrap().pragmas, pragmas);
+        drop(vault);
+
+        // An existing database keeps its page size; the rest follows the options
+        let vault = ClawBox::open_with(temp_dir.path(), &OpenOptions::default()).unwrap();
+        let pragmas = vault.stats().unwrap().pragmas;
+        assert_eq!(pragmas.page_size, 8192);
+        assert_eq!(pragmas.synchronous, Some(Synchronous::Full));
+        let vault = ClawBox::open_with(temp_dir.path(), &OpenOptions { read_only: true, ..opts }).unwrap();
+        assert_eq!(vault.stats().unwrap().pragmas.cache_size, -8000);
+
+        for bad in [
+            StoreOptions { page_size: 3000, ..Default::default() },
+            StoreOptions { page_size: 256, ..Default::default() },
+            StoreOptions { cache_size: -(2 << 20), ..Default::default() },
+            StoreOptions { cache_size: 1 << 20, ..Default::default() },
+            StoreOptions { mmap_size: 2 << 30, ..Default::default() },
+            StoreOptions { wal_autocheckpoint: 2_000_000, ..Default::default() },
+        ] {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("vault");
+            let opts = OpenOptions { store: bad, ..Default::default() };
+            assert!(matches!(ClawBox::open_with(&path, &opts), Err(Error::InvalidArgument(_))));
+            assert!(!path.exists());
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("vault");
+
+        // Without create, a missing vault is an error and nothing is made
+        assert!(matches!(ClawBox::builder(&path).create(false).open(), Err(Error::VaultNotFound { .. })));
+        assert!(!path.exists());
+
+        let mut vault = ClawBox::builder(&path)
+            .auto_lock(Duration::from_secs(60))
+            .actor(crate::Actor::AI { agent: "deploy-bot".into() })
+            .open()
+            .unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("a", "1", Default::default()).unwrap();
+        assert!(vault.time_until_lock().unwrap() <= Duration::from_secs(60));
+        let entries = vault.audit(&AuditFilter { action: Some(Action::Write), ..Default::default() }).unwrap();
+        assert_eq!(entries[0].actor.actor_type, "ai");
+        assert_eq!(entries[0].actor.identifier, "deploy-bot");
+        drop(vault);
+
+        let mut vault = ClawBox::builder(&path).create(false).read_only(true).open().unwrap();
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.get_secret("a").unwrap().as_deref(), Some("1"));
+        assert!(matches!(vault.set("a", "2", Default::default()), Err(Error::ReadOnly)));
+        assert_eq!(vault.actor().actor_type, "human");
+    }
+
+    #[test]
+    fn test_new_paths_are_normalized_and_checked() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        vault.set("  github//token ", "t", Default::default()).unwrap();
+        assert_eq!(vault.get_secret("github/token").unwrap().as_deref(), Some("t"));
+
+        let long = "x".repeat(crate::storage::MAX_KEY_PATH_LEN + 1);
+        for bad in ["../../x", "", "   ", "/etc/passwd", "a/b/", "line\nbreak", long.as_str()] {
+            match vault.set(bad, "v", Default::default()) {
+                Err(Error::InvalidPath { path, .. }) => assert_eq!(path, bad),
+                other => panic!("{:?} was accepted: {:?}", bad, other),
+            }
+        }
+        assert!(matches!(vault.rename("github/token", "a/../b"), Err(Error::InvalidPath { .. })));
+        assert!(matches!(vault.copy("github/token", "a\tb", false), Err(Error::InvalidPath { .. })));
+        let items = [("ok", "1", SetOptions::default()), ("bad/", "2", SetOptions::default())];
+        assert!(matches!(vault.set_many(&items), Err(Error::InvalidPath { .. })));
+        assert_eq!(vault.get_secret("ok").unwrap().as_deref(), None);
+
+        // Paths stored before the rules tightened stay readable and deletable
+        vault.store.rename("github/token", "legacy//token").unwrap();
+        assert_eq!(vault.get_secret("legacy//token").unwrap().as_deref(), Some("t"));
+        assert!(vault.delete("legacy//token", DeleteMode::Soft).unwrap());
+    }
+
+    #[test]
+    fn test_unlock_lockout_backs_off() {
+        use std::sync::{Arc, Mutex};
+
+        let temp_dir = TempDir::new().unwrap();
+        let now = Arc::new(Mutex::new(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()));
+        let open = || {
+            let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+            let clock = now.clone();
+            vault.clock = Box::new(move || *clock.lock().unwrap());
+            vault
+        };
+        let advance = |secs| *now.lock().unwrap() += chrono::Duration::seconds(secs);
+
+        let mut vault = open();
+        vault.init("test-password").unwrap();
+        vault.lock();
+        for _ in 0..UNLOCK_ATTEMPTS {
+            assert!(matches!(vault.unlock("wrong"), Err(Error::InvalidPassword)));
+        }
+
+        // Refused without trying the password, even the right one, and
+        // across reopening the vault
+        let mut vault = open();
+        match vault.unlock("test-password") {
+            Err(Error::TooManyAttempts { retry_after }) => assert_eq!(retry_after, Duration::from_secs(30)),
+            other => panic!("expected TooManyAttempts, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(vault.unlock_lockout().unwrap().failures, UNLOCK_ATTEMPTS);
+
+        // Each wrong password after a cooldown doubles the next one
+        advance(30);
+        assert!(matches!(vault.unlock("wrong"), Err(Error::InvalidPassword)));
+        advance(59);
+        assert!(matches!(vault.unlock("test-password"), Err(Error::TooManyAttempts { .. })));
+        advance(1);
+        assert!(matches!(vault.unlock("wrong"), Err(Error::InvalidPassword)));
+        match vault.check_unlock_allowed() {
+            Err(Error::TooManyAttempts { retry_after }) => assert_eq!(retry_after, Duration::from_secs(120)),
+            other => panic!("expected TooManyAttempts, got {:?}", other),
+        }
+        assert_eq!(unlock_cooldown(UNLOCK_ATTEMPTS + 20), Some(MAX_UNLOCK_COOLDOWN));
+
+        // The right password once the cooldown ends resets the count
+        advance(120);
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.unlock_lockout().unwrap(), UnlockLockout::default());
+        vault.lock();
+        assert!(matches!(vault.unlock("wrong"), Err(Error::InvalidPassword)));
+        vault.unlock("test-password").unwrap();
+
+        // Reauthenticating on an unlocked vault counts towards the same limit
+        vault.set("root/key", "k", SetOptions { access: AccessLevel::Critical, ..Default::default() }).unwrap();
+        for _ in 0..UNLOCK_ATTEMPTS {
+            assert!(matches!(vault.get_with_reauth("root/key", "wrong"), Err(Error::InvalidPassword)));
+        }
+        assert!(matches!(vault.get_with_reauth("root/key", "test-password"), Err(Error::TooManyAttempts { .. })));
+        let include_critical = ExportOptions { include_critical: true };
+        assert!(matches!(
+            vault.export_bundle("bundle pass", &include_critical, Some("test-password")),
+            Err(Error::TooManyAttempts { .. })
+        ));
+        advance(30);
+        assert_eq!(vault.get_with_reauth("root/key", "test-password").unwrap().as_deref(), Some("k"));
+        assert_eq!(vault.unlock_lockout().unwrap(), UnlockLockout::default());
+    }
+
+    #[test]
+    fn test_auto_lock_after_inactivity() {
+        let temp_dir = TempDir::new().unwrap();
+        let opts = OpenOptions { auto_lock: Some(Duration::from_millis(300)), ..Default::default() };
+        let mut vault = ClawBox::open_with(temp_dir.path(), &opts).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("a", "1", Default::default()).unwrap();
+        assert!(vault.time_until_lock().unwrap() <= Duration::from_millis(300));
+
+        // Each operation restarts the window
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(150));
+            assert_eq!(vault.get_secret("a").unwrap().as_deref(), Some("1"));
+        }
+
+        std::thread::sleep(Duration::from_millis(350));
+        assert!(matches!(vault.get_secret("a"), Err(Error::VaultLocked)));
+        assert!(!vault.is_unlocked());
+        assert_eq!(vault.time_until_lock(), None);
+
+        let locks = vault.audit(&AuditFilter { action: Some(Action::Lock), ..Default::default() }).unwrap();
+        assert_eq!(locks.len(), 1);
+        assert_eq!(locks[0].actor.actor_type, "system");
+        assert_eq!(locks[0].actor.identifier, "auto-lock");
+
+        // Turned off, the vault stays unlocked
+        vault.set_auto_lock(None);
+        vault.unlock("test-password").unwrap();
+        std::thread::sleep(Duration::from_millis(350));
+        assert_eq!(vault.get_secret("a").unwrap().as_deref(), Some("1"));
+        assert_eq!(vault.time_until_lock(), None);
+    }
+
+    #[test]
+    fn test_init_twice_fails() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("first-password").unwrap();
+        vault.set("test/key", "secret-value", Default::default()).unwrap();
+
+        assert!(matches!(
+            vault.init("second-password"),
+            Err(Error::AlreadyInitialized { .. })
+        ));
+
+        // The original key still works
+        vault.lock();
+        vault.unlock("first-password").unwrap();
+        assert_eq!(vault.get_secret("test/key").unwrap().as_deref(), Some("secret-value"));
+
+        let backup = vault.reinit("second-password").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&backup).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        vault.lock();
+        vault.unlock("second-password").unwrap();
+
+        // Nothing the old key encrypted is left behind, and the audit log starts over
+        assert!(vault.list(None).unwrap().is_empty());
+        let audit = vault.audit(&Default::default()).unwrap();
+        assert!(audit.iter().all(|entry| entry.key_path == "vault"));
+        assert!(vault.verify_audit_integrity().unwrap());
+
+        // The copy still opens with the old password
+        let old = TempDir::new().unwrap();
+        std::fs::copy(&backup, old.path().join("vault.db")).unwrap();
+        let mut old = ClawBox::open(old.path()).unwrap();
+        old.unlock("first-password").unwrap();
+        assert_eq!(old.get_secret("test/key").unwrap().as_deref(), Some("secret-value"));
+    }
+
+    #[test]
+    fn test_get_with_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let opts = SetOptions {
+            access: crate::AccessLevel::Sensitive,
+            tags: vec!["infra".to_string()],
+            note: Some("rotated monthly".to_string()),
+            ..Default::default()
+        };
+        vault.set("db_1/password", "hunter2", opts).unwrap();
+        vault.set("db_12/password", "other", Default::default()).unwrap();
+
+        let (value, info) = vault.get_with_info("db_1/password").unwrap().unwrap();
+        assert_eq!(value, "hunter2");
+        assert_eq!(info.access, crate::AccessLevel::Sensitive);
+        assert_eq!(info.tags, vec!["infra".to_string()]);
+        assert_eq!(info.note.as_deref(), Some("rotated monthly"));
+
+        assert!(vault.get_with_info("db/missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_many_single_audit_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("a", "1", Default::default()).unwrap();
+        vault.set("b", "2", Default::default()).unwrap();
+
+        let before = vault.stats().unwrap().audit_entries;
+        let values = vault.get_many(&["b", "missing", "a"]).unwrap();
+        let values: Vec<_> = values.iter().map(|v| v.as_deref()).collect();
+        assert_eq!(values, vec![Some("2"), None, Some("1")]);
+
+        let entries = vault.audit(&AuditFilter::default()).unwrap();
+        assert_eq!(entries.len(), before + 1);
+        assert_eq!(entries[0].key_path, "b,missing,a");
+        assert!(!entries[0].success);
+    }
+
+    #[test]
+    fn test_get_prefix() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        for path in ["app/db/url", "app/api_key", "apple/pie", "APP/upper", "a_b/x", "axb/y"] {
+            vault.set(path, path, Default::default()).unwrap();
+        }
+
+        let before = vault.stats().unwrap().audit_entries;
+        let secrets = vault.get_prefix("app/").unwrap();
+        let paths: Vec<&str> = secrets.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["app/api_key", "app/db/url"]);
+        assert_eq!(secrets[1].1.expose(), "app/db/url");
+        let entries = vault.audit(&AuditFilter::default()).unwrap();
+        assert_eq!(entries.len(), before + 1);
+        assert_eq!(entries[0].key_path, "app/*");
+        assert_eq!(entries[0].metadata.as_ref().unwrap()["count"], 2);
+
+        // LIKE wildcards in the prefix are matched literally
+        let secrets = vault.get_prefix("a_b/*").unwrap();
+        assert_eq!(secrets.iter().map(|(path, _)| path.as_str()).collect::<Vec<_>>(), vec!["a_b/x"]);
+        assert!(vault.get_prefix("missing").unwrap().is_empty());
+
+        // One refused secret fails the whole read
+        let opts = SetOptions { access: AccessLevel::Critical, ..Default::default() };
+        vault.set("app/root", "hunter2", opts).unwrap();
+        assert!(matches!(vault.get_prefix("app"), Err(Error::ReauthRequired { .. })));
+        assert_eq!(vault.get_prefix_with_reauth("app", "test-password").unwrap().len(), 3);
+        vault.set_actor(ActorInfo::ai("bot"));
+        assert!(matches!(vault.get_prefix("app"), Err(Error::AccessDenied { .. })));
+        let entries = vault.audit(&AuditFilter::default()).unwrap();
+        assert!(!entries[0].success);
+        assert_eq!(entries[0].key_path, "app/*");
+    }
+
+    #[test]
+    fn test_history_and_rollback() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        vault.set("db/password", "one", Default::default()).unwrap();
+        let opts = SetOptions { access: AccessLevel::Critical, ..Default::default() };
+        vault.set_actor(ActorInfo::ai("claude"));
+        vault.set("db/password", "two", opts).unwrap();
+        vault.set_actor(ActorInfo::human());
+
+        let history = vault.history("db/password").unwrap();
+        assert_eq!(history.iter().map(|v| v.version).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(history[0].access, AccessLevel::Critical);
+        assert_ne!(history[0].fingerprint, history[1].fingerprint);
+        assert_eq!(history[0].created_by, "ai:claude");
+        assert_eq!(history[1].created_by, format!("human:{}", ActorInfo::human().identifier));
+
+        vault.rollback("db/password", 1).unwrap();
+        assert_eq!(vault.get_secret("db/password").unwrap().as_deref(), Some("one"));
+        let info = vault.info("db/password").unwrap().unwrap();
+        assert_eq!(info.access, AccessLevel::Normal);
+        assert_eq!(info.size, 3);
+
+        let history = vault.history("db/password").unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].fingerprint, history[2].fingerprint);
+
+        assert!(matches!(
+            vault.rollback("db/password", 9),
+            Err(Error::VersionNotFound { version: 9, .. })
+        ));
+
+        // History survives deletion and a password change
+        vault.delete("db/password", DeleteMode::Soft).unwrap();
+        vault.change_password("test-password", "new-password").unwrap();
+        vault.rollback("db/password", 2).unwrap();
+        // Version 2 was Critical again
+        assert_eq!(vault.get_with_reauth("db/password", "new-password").unwrap().as_deref(), Some("two"));
+    }
+
+    #[test]
+    fn test_rollback_binary_value_and_expiry() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let key = b"\0\xffkey\xfe".to_vec();
+        let ttl = std::time::Duration::from_secs(3600);
+        vault.set_bytes("tls/key", &key, SetOptions { ttl: Some(ttl), ..Default::default() }).unwrap();
+        let expires_at = vault.info("tls/key").unwrap().unwrap().expires_at.unwrap();
+        vault.set("tls/key", "replaced", Default::default()).unwrap();
+
+        vault.rollback("tls/key", 1).unwrap();
+        assert_eq!(vault.get_bytes("tls/key").unwrap().as_deref(), Some(&key));
+        let info = vault.info("tls/key").unwrap().unwrap();
+        assert!((info.expires_at.unwrap() - expires_at).num_seconds().abs() <= 1);
+
+        // Versions written before they kept an expiry restore without one
+        let conn = vault.store.connection();
+        conn.execute_batch(
+            "ALTER TABLE secret_versions DROP COLUMN ttl_expires_at; \
+             UPDATE vault_meta SET value = CAST('5' AS BLOB) WHERE key = 'schema_version'",
+        )
+        .unwrap();
+        drop(vault);
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.schema_version().unwrap(), crate::storage::SCHEMA_VERSION);
+        vault.rollback("tls/key", 1).unwrap();
+        assert_eq!(vault.get_bytes("tls/key").unwrap().as_deref(), Some(&key));
+        assert_eq!(vault.info("tls/key").unwrap().unwrap().expires_at, None);
+    }
+
+    #[test]
+    fn test_prune() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let expired = SetOptions { ttl: Some(std::time::Duration::ZERO), ..Default::default() };
+        vault.set("tmp/key", "x", expired).unwrap();
+        for value in ["1", "2", "3"] {
+            vault.set("db/password", value, Default::default()).unwrap();
+        }
+
+        let opts = PruneOptions {
+            keep_versions: Some(1),
+            audit_before: Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+            dry_run: true,
+        };
+        assert!(vault.verify_audit_integrity().unwrap());
+        let report = vault.prune(&opts).unwrap();
+        assert_eq!(report.expired_secrets, vec!["tmp/key".to_string()]);
+        assert_eq!(report.old_versions, 2);
+        assert!(report.audit_entries > 0);
+        assert!(vault.info("tmp/key").unwrap().is_some());
+
+        let report = vault.prune(&PruneOptions { dry_run: false, ..opts }).unwrap();
+        assert!(!report.is_empty());
+        assert!(vault.info("tmp/key").unwrap().is_none());
+        assert_eq!(vault.history("db/password").unwrap().len(), 1);
+        assert_eq!(vault.get_secret("db/password").unwrap().as_deref(), Some("3"));
+
+        // The remaining audit chain still verifies
+        assert!(vault.verify_audit_integrity().unwrap());
+    }
+
+    #[test]
+    fn test_delete_many() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("a", "1", Default::default()).unwrap();
+        vault.set("b", "2", Default::default()).unwrap();
+
+        assert_eq!(vault.delete_many(&["a", "missing", "b"], DeleteMode::Soft).unwrap(), vec![true, false, true]);
+        assert!(vault.list(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_set_many_thousand_items() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        let values: Vec<(String, String)> = (0..1000).map(|i| (format!("bulk/key{}", i), format!("value-{}", i))).collect();
+        let items: Vec<(&str, &[u8], SetOptions)> =
+            values.iter().map(|(path, value)| (path.as_str(), value.as_bytes(), Default::default())).collect();
+
+        // Well under a second here; the bound leaves room for slow CI
+        // machines and only catches a pathological regression
+        vault.set("before", "x", Default::default()).unwrap();
+        let start = std::time::Instant::now();
+        let mut reported = vec![];
+        vault.set_many_with_progress(&items, |done, total| reported.push((done, total))).unwrap();
+        assert!(start.elapsed() < std::time::Duration::from_secs(30), "set_many took {:?}", start.elapsed());
+        assert_eq!(reported.len(), 1000);
+        assert_eq!(reported.last(), Some(&(1000, 1000)));
+
+        assert_eq!(vault.list(Some("bulk/*")).unwrap().len(), 1000);
+        assert_eq!(vault.get_secret("bulk/key999").unwrap().unwrap().expose(), "value-999");
+
+        // Logged in batches, chained on from the entry before and in order
+        vault.set("after", "x", Default::default()).unwrap();
+        assert!(vault.verify_audit_integrity().unwrap());
+        let writes = vault.audit(&AuditFilter { action: Some(Action::Write), ..Default::default() }).unwrap();
+        let paths: Vec<&str> = writes.iter().rev().map(|entry| entry.key_path.as_str()).collect();
+        assert_eq!(paths.len(), 1002);
+        assert_eq!((paths[0], paths[1], paths[1000], paths[1001]), ("before", "bulk/key0", "bulk/key999", "after"));
+    }
+
+    #[test]
+    fn test_for_each_secret_streams_rows() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        // Metadata is all that is read, so the values needn't decrypt
+        let info = SecretInfo {
+            path: String::new(),
+            access: AccessLevel::Normal,
+            tags: vec![],
+            note: None,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            expires_at: None,
+            size: 0,
+        };
+        let paths: Vec<String> = (0..50_000).map(|i| format!("rows/{:05}", i)).collect();
+        let items: Vec<(&str, &[u8], &SecretInfo)> = paths.iter().map(|path| (path.as_str(), &b"x"[..], &info)).collect();
+        vault.store.set_many(&items).unwrap();
+
+        let mut count = 0;
+        let mut last = String::new();
+        vault
+            .for_each_secret(&ListFilter::pattern(Some("rows/*")), |info| {
+                assert!(info.path > last, "{} came after {}", info.path, last);
+                last = info.path;
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 50_000);
+
+        // An error from the callback ends the walk
+        let mut seen = 0;
+        let stopped = vault.for_each_secret(&ListFilter::default(), |_| {
+            seen += 1;
+            match seen {
+                10 => Err(Error::Other("stop".to_string())),
+                _ => Ok(()),
+            }
+        });
+        assert!(matches!(stopped, Err(Error::Other(_))));
+        assert_eq!(seen, 10);
+    }
+
+    #[test]
+    fn test_readers_alongside_a_writer() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("rows/seed", "value", Default::default()).unwrap();
+        let reader = vault.reader().unwrap();
+        let db = temp_dir.path().join("vault.db");
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Half the readers share the vault's read connections, the others
+        // have a store of their own, like another process would
+        let readers: Vec<_> = (0..8)
+            .map(|i| {
+                let reader = reader.clone();
+                let db = db.clone();
+                let done = done.clone();
+                std::thread::spawn(move || -> Result<usize> {
+                    let store = (i % 2 == 1).then(|| SqliteStore::open(&db)).transpose()?;
+                    let mut reads = 0;
+                    while !done.load(Ordering::SeqCst) || reads == 0 {
+                        match &store {
+                            Some(store) => {
+                                assert!(!store.list(Some("rows/*"))?.is_empty());
+                                assert!(store.get("rows/seed")?.is_some());
+                                store.get_meta("salt")?;
+                            }
+                            None => {
+                                assert!(!reader.list(Some("rows/*"))?.is_empty());
+                            }
+                        }
+                        reads += 1;
+                    }
+                    Ok(reads)
+                })
+            })
+            .collect();
+
+        let writer = std::thread::spawn(move || -> Result<()> {
+            for i in 0..200 {
+                vault.set(&format!("rows/{:03}", i), "value", Default::default())?;
+                if i % 20 == 0 {
+                    vault.delete(&format!("rows/{:03}", i), DeleteMode::Soft)?;
+                }
+            }
+            Ok(())
+        });
+        let written = writer.join().unwrap();
+        done.store(true, Ordering::SeqCst);
+        // Any SQLITE_BUSY would surface here as Error::Database
+        written.unwrap();
+        for reader in readers {
+            assert!(reader.join().unwrap().unwrap() > 0);
+        }
+        assert_eq!(reader.list(Some("rows/*")).unwrap().len(), 191);
+    }
+
+    #[test]
+    fn test_list_filter_by_tag_and_access() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        let tagged = |access, tags: &[&str]| SetOptions {
+            access,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        };
+        vault.set("db/prod", "v", tagged(AccessLevel::Critical, &["infra", "prod"])).unwrap();
+        vault.set("db/dev", "v", tagged(AccessLevel::Normal, &["infra"])).unwrap();
+        vault.set("llm/openai", "v", tagged(AccessLevel::Sensitive, &["ai"])).unwrap();
+        vault.set("notes", "v", tagged(AccessLevel::Public, &[])).unwrap();
+
+        fn paths(vault: &ClawBox, filter: ListFilter) -> Vec<String> {
+            let mut paths = vec![];
+            vault
+                .for_each_secret(&filter, |info| {
+                    paths.push(info.path);
+                    Ok(())
+                })
+                .unwrap();
+            paths
+        }
+        let tags = |tags: &[&str]| ListFilter { tags: tags.iter().map(|tag| tag.to_string()).collect(), ..Default::default() };
+        assert_eq!(paths(&vault, tags(&["infra"])), ["db/dev", "db/prod"]);
+        assert_eq!(paths(&vault, tags(&["infra", "prod"])), ["db/prod"]);
+        assert!(paths(&vault, tags(&["missing"])).is_empty());
+        let access = ListFilter { access: vec![AccessLevel::Critical, AccessLevel::Sensitive], ..Default::default() };
+        assert_eq!(paths(&vault, access), ["db/prod", "llm/openai"]);
+        let both = ListFilter { pattern: Some("db/*".to_string()), access: vec![AccessLevel::Normal], ..tags(&["infra"]) };
+        assert_eq!(paths(&vault, both), ["db/dev"]);
+
+        // The tag table follows updates, renames and deletes
+        vault.set("db/dev", "v", tagged(AccessLevel::Normal, &["staging"])).unwrap();
+        vault.rename("llm/openai", "llm/anthropic").unwrap();
+        vault.delete("db/prod", DeleteMode::Soft).unwrap();
+        assert!(paths(&vault, tags(&["infra"])).is_empty());
+        assert_eq!(paths(&vault, tags(&["staging"])), ["db/dev"]);
+        assert_eq!(paths(&vault, tags(&["ai"])), ["llm/anthropic"]);
+        let rows: i64 = vault.store.connection().query_row("SELECT COUNT(*) FROM secret_tags", [], |row| row.get(0)).unwrap();
+        assert_eq!(rows, 2);
+
+        // Filters are answered from the indexes, never by reading every secret
+        let plan = |filter: &ListFilter| {
+            let conn = vault.store.connection();
+            let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", crate::storage::list_query(filter))).unwrap();
+            let params = crate::storage::list_params(MAIN_KEYSPACE, filter);
+            let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| row.get::<_, String>(3)).unwrap();
+            rows.map(|row| row.unwrap()).collect::<Vec<_>>().join("\n")
+        };
+        // (`keyspace=?` alone would be a walk through the whole keyspace)
+        for filter in [tags(&["infra"]), tags(&["infra", "prod"])] {
+            let by_tag = plan(&filter);
+            assert!(by_tag.contains("USING INDEX idx_secret_tags_tag (tag=?)"), "{}", by_tag);
+            assert!(!by_tag.contains("SCAN") && !by_tag.contains("(keyspace=?)"), "{}", by_tag);
+        }
+        let by_access = plan(&ListFilter { access: vec![AccessLevel::Critical, AccessLevel::Public], ..Default::default() });
+        assert!(by_access.contains("USING INDEX idx_secrets_access (keyspace=? AND access_level=?)"), "{}", by_access);
+        assert!(!by_access.contains("SCAN"), "{}", by_access);
+    }
+
+    #[test]
+    fn test_tag_table_filled_on_upgrade() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        let opts = SetOptions { tags: vec!["infra".to_string()], ..Default::default() };
+        vault.set("db/prod", "v", opts).unwrap();
+        // As written by a build without the tag table
+        let conn = vault.store.connection();
+        conn.execute_batch("DROP TABLE secret_tags; UPDATE vault_meta SET value = CAST('4' AS BLOB) WHERE key = 'schema_version'")
+            .unwrap();
+        drop(vault);
+
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.schema_version().unwrap(), crate::storage::SCHEMA_VERSION);
+        let filter = ListFilter { tags: vec!["infra".to_string()], ..Default::default() };
+        let mut paths = vec![];
+        vault
+            .for_each_secret(&filter, |info| {
+                paths.push(info.path);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(paths, ["db/prod"]);
+    }
+
+    #[test]
+    fn test_opens_vault_with_first_schema() {
+        let temp_dir = TempDir::new().unwrap();
+        // The tables as the first release created them, with one secret
+        let conn = rusqlite::Connection::open(temp_dir.path().join("vault.db")).unwrap();
+        conn.execute_batch(
+            r#"
+            CREATE TABLE vault_meta (key TEXT PRIMARY KEY, value BLOB NOT NULL);
+            CREATE TABLE secrets (
+                id TEXT PRIMARY KEY,
+                path TEXT UNIQUE NOT NULL,
+                encrypted_value BLOB NOT NULL,
+                access_level INTEGER NOT NULL DEFAULT 1,
+                tags TEXT,
+                note TEXT,
+                ttl_expires_at INTEGER,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                created_by TEXT NOT NULL
+            );
+            CREATE INDEX idx_secrets_path ON secrets(path);
+            CREATE INDEX idx_secrets_ttl ON secrets(ttl_expires_at);
+            CREATE TABLE audit_log (
+                id TEXT PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                actor TEXT NOT NULL,
+                action TEXT NOT NULL,
+                key_path TEXT NOT NULL,
+                success INTEGER NOT NULL,
+                error_message TEXT,
+                source TEXT NOT NULL,
+                hash TEXT,
+                prev_hash TEXT
+            );
+            CREATE INDEX idx_audit_timestamp ON audit_log(timestamp);
+            CREATE INDEX idx_audit_key_path ON audit_log(key_path);
+            INSERT INTO secrets VALUES ('1', 'legacy/key', x'00', 2, '["old"]', NULL, NULL, 0, 0, 'human');
+            "#,
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        assert_eq!(vault.schema_version().unwrap(), crate::storage::SCHEMA_VERSION);
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+
+        // Listing by level goes through the index the migration rebuilt
+        let mut paths = vec![];
+        let filter = ListFilter { access: vec![AccessLevel::Sensitive], ..Default::default() };
+        vault
+            .for_each_secret(&filter, |info| {
+                paths.push(info.path);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(paths, ["legacy/key"]);
+    }
+
+    #[test]
+    fn test_hard_delete_leaves_nothing_behind() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://old", Default::default()).unwrap();
+        vault.set("db/url", "postgres://new", Default::default()).unwrap();
+        vault.set("keep", "k", Default::default()).unwrap();
+        let ciphertext = vault.store.get("db/url").unwrap().unwrap();
+
+        assert!(vault.delete("db/url", DeleteMode::Hard).unwrap());
+
+        // No table keeps a row for the path; the audit log records the purge
+        let conn = vault.store.connection();
+        let tables: Vec<String> = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table'")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        for table in tables {
+            let has_path: bool = conn
+                .query_row(&format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = 'path'", table), [], |row| row.get(0))
+                .unwrap();
+            if has_path {
+                let rows: i64 = conn
+                    .query_row(&format!("SELECT COUNT(*) FROM {} WHERE path = 'db/url'", table), [], |row| row.get(0))
+                    .unwrap();
+                assert_eq!(rows, 0, "{} still has db/url", table);
+            }
+        }
+        let file = std::fs::read(temp_dir.path().join("vault.db")).unwrap();
+        assert!(!file.windows(ciphertext.len()).any(|window| window == ciphertext));
+        assert!(vault.history("db/url").unwrap().is_empty());
+        assert_eq!(vault.get_secret("keep").unwrap().as_deref(), Some("k"));
+        let purges = vault.audit(&AuditFilter { action: Some(Action::Purge), ..Default::default() }).unwrap();
+        assert_eq!(purges.len(), 1);
+        assert_eq!(purges[0].key_path, "db/url");
+
+        // A soft-deleted secret's history can be purged afterwards
+        vault.set("old", "x", Default::default()).unwrap();
+        assert!(vault.delete("old", DeleteMode::Soft).unwrap());
+        assert_eq!(vault.history("old").unwrap().len(), 1);
+        assert!(vault.delete("old", DeleteMode::Hard).unwrap());
+        assert!(vault.history("old").unwrap().is_empty());
+        assert!(!vault.delete("old", DeleteMode::Hard).unwrap());
+    }
+
+    #[test]
+    fn test_set_many_is_atomic() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let result = vault.set_many(&[
+            ("db/url", "postgres://", Default::default()),
+            ("../escape", "x", Default::default()),
+        ]);
+        assert!(result.is_err());
+        assert!(vault.list(None).unwrap().is_empty());
+
+        vault
+            .set_many(&[
+                ("db/url", "postgres://", Default::default()),
+                ("db/pass", "x", SetOptions { access: AccessLevel::Sensitive, ..Default::default() }),
+            ])
+            .unwrap();
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("postgres://"));
+        assert_eq!(vault.info("db/pass").unwrap().unwrap().access, AccessLevel::Sensitive);
+    }
+
+    #[test]
+    fn test_delete_matching() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        for path in ["db/url", "db/pass", "dbx", "db_%/x", "api/key"] {
+            vault.set(path, "v", Default::default()).unwrap();
+        }
+
+        // `_` and `%` are not wildcards
+        assert_eq!(vault.delete_matching("db_%").unwrap(), vec!["db_%/x"]);
+        assert_eq!(vault.delete_matching("db/").unwrap(), vec!["db/pass", "db/url"]);
+        assert!(vault.delete_matching("nothing/").unwrap().is_empty());
+
+        let left: Vec<_> = vault.list(None).unwrap().into_iter().map(|info| info.path).collect();
+        assert_eq!(left.len(), 2);
+        assert!(left.contains(&"dbx".to_string()) && left.contains(&"api/key".to_string()));
+    }
+
+    #[test]
+    fn test_rename_and_copy() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let opts = SetOptions { access: AccessLevel::Sensitive, tags: vec!["db".into()], ..Default::default() };
+        vault.set("db/old", "v1", opts.clone()).unwrap();
+        vault.set("db/old", "v2", opts).unwrap();
+        vault.set("taken", "x", Default::default()).unwrap();
+
+        assert!(matches!(vault.rename("db/old", "taken"), Err(Error::AlreadyExists { .. })));
+        assert!(matches!(vault.rename("missing", "db/new"), Err(Error::SecretNotFound { .. })));
+        vault.rename("db/old", "db/new").unwrap();
+        assert!(vault.get_secret("db/old").unwrap().is_none());
+        assert_eq!(vault.get_secret("db/new").unwrap().as_deref(), Some("v2"));
+        assert_eq!(vault.history("db/new").unwrap().len(), 2);
+
+        assert!(matches!(vault.copy("db/new", "taken", false), Err(Error::AlreadyExists { .. })));
+        vault.copy("db/new", "taken", true).unwrap();
+        vault.copy("db/new", "db/copy", false).unwrap();
+        assert_eq!(vault.get_secret("taken").unwrap().as_deref(), Some("v2"));
+        let (source, copy) = (vault.info("db/new").unwrap().unwrap(), vault.info("db/copy").unwrap().unwrap());
+        assert_eq!((copy.access, copy.tags), (source.access, source.tags));
+        assert_eq!(vault.history("db/copy").unwrap().len(), 1);
+        assert_eq!(vault.get_secret("db/new").unwrap().as_deref(), Some("v2"));
+    }
+
+    /// In-memory stand-in for iCloud
+    #[derive(Default)]
+    struct MemoryBackend(std::sync::Mutex<(Vec<u8>, u64)>);
+
+    impl SyncBackend for MemoryBackend {
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn get_remote_version(&self) -> Result<u64> {
+            Ok(self.0.lock().unwrap().1)
+        }
+
+        fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+            *self.0.lock().unwrap() = (data.to_vec(), version);
+            Ok(())
+        }
+
+        fn download(&self) -> Result<(Vec<u8>, u64)> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[test]
+    fn test_sync_merges_divergent_edits() {
+        use crate::sync::{ConflictResolution, SyncResult};
+
+        let temp_dir = TempDir::new().unwrap();
+        let (path_a, path_b) = (temp_dir.path().join("a"), temp_dir.path().join("b"));
+        ClawBox::open(&path_a).unwrap().init("test-password").unwrap();
+        // A second device holding the same vault (same salt and key)
+        std::fs::create_dir_all(&path_b).unwrap();
+        std::fs::copy(path_a.join("vault.db"), path_b.join("vault.db")).unwrap();
+
+        let backend = MemoryBackend::default();
+        let open = |path: &Path| {
+            let mut vault = ClawBox::open(path).unwrap();
+            vault.unlock("test-password").unwrap();
+            let mut manager = vault.sync_manager().unwrap();
+            manager.enable();
+            (vault, manager)
+        };
+        let (mut a, mut sync_a) = open(&path_a);
+        let (mut b, mut sync_b) = open(&path_b);
+        let sync = |vault: &mut ClawBox, manager: &mut SyncManager| vault.sync_with(manager, &backend).unwrap();
+
+        assert_eq!(sync(&mut a, &mut sync_a).result, SyncResult::UpToDate);
+        a.set("db/url", "postgres://a", Default::default()).unwrap();
+        a.set("shared", "v1", Default::default()).unwrap();
+        assert_eq!(sync(&mut a, &mut sync_a).result, SyncResult::Pushed);
+        assert_eq!(sync(&mut a, &mut sync_a).result, SyncResult::UpToDate);
+        assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pulled);
+        assert_eq!(b.get_secret("db/url").unwrap().as_deref(), Some("postgres://a"));
+
+        // Each side adds a secret and B deletes one: nothing is lost
+        a.set("from/a", "1", Default::default()).unwrap();
+        b.set("from/b", "2", Default::default()).unwrap();
+        b.delete("db/url", DeleteMode::Soft).unwrap();
+        assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pushed);
+        let report = sync(&mut a, &mut sync_a);
+        assert_eq!(report.result, SyncResult::Merged);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pulled);
+        for vault in [&a, &b] {
+            assert_eq!(vault.get_secret("from/a").unwrap().as_deref(), Some("1"));
+            assert_eq!(vault.get_secret("from/b").unwrap().as_deref(), Some("2"));
+            assert!(vault.get_secret("db/url").unwrap().is_none());
+        }
+
+        // Both sides change the same secret: the later write wins and the
+        // conflict is reported
+        b.set("shared", "from-b", Default::default()).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        a.set("shared", "from-a", Default::default()).unwrap();
+        assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pushed);
+        let report = sync(&mut a, &mut sync_a);
+        assert_eq!(report.result, SyncResult::Pushed);
+        assert_eq!(report.conflicts.len(), 1);
+        let conflict = &report.conflicts[0];
+        assert_eq!((conflict.path.as_str(), conflict.local_value.as_str()), ("shared", "from-a"));
+        assert_eq!(conflict.remote_value, "from-b");
+        assert_eq!(a.get_secret("shared").unwrap().as_deref(), Some("from-a"));
+        assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pulled);
+        assert_eq!(b.get_secret("shared").unwrap().as_deref(), Some("from-a"));
+        // B's value is still in its history
+        assert_eq!(b.history("shared").unwrap().len(), 3);
+
+        // Keep both: repeated conflicts each leave their own copy
+        sync_a.set_resolution(ConflictResolution::KeepBoth);
+        let mut copies = vec![];
+        for round in ["1", "2"] {
+            b.set("shared", &format!("b{}", round), Default::default()).unwrap();
+            a.set("shared", &format!("a{}", round), Default::default()).unwrap();
+            assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pushed);
+            // Writing the copy is a local change
+            let report = sync(&mut a, &mut sync_a);
+            assert_eq!(report.result, SyncResult::Merged);
+            copies.push(report.conflicts[0].conflict_copy.clone().unwrap());
+            assert_eq!(a.get_secret("shared").unwrap().as_deref(), Some(format!("a{}", round).as_str()));
+            assert_eq!(sync(&mut b, &mut sync_b).result, SyncResult::Pulled);
+        }
+        assert_ne!(copies[0], copies[1]);
+        assert!(copies[0].starts_with("shared.conflict-"));
+        assert_eq!(a.get_secret(&copies[0]).unwrap().as_deref(), Some("b1"));
+        assert_eq!(b.get_secret(&copies[1]).unwrap().as_deref(), Some("b2"));
+        let listed: Vec<String> = a.conflict_copies().unwrap().into_iter().map(|info| info.path).collect();
+        assert_eq!(listed, copies);
+        let logged = a.audit(&AuditFilter { action: Some(Action::SyncConflict), ..Default::default() }).unwrap();
+        assert_eq!(logged.len(), 3);
+
+        assert_eq!(a.resolve_conflict(&copies[1], true).unwrap(), "shared");
+        assert_eq!(a.get_secret("shared").unwrap().as_deref(), Some("b2"));
+        assert!(!a.info("shared").unwrap().unwrap().tags.contains(&sync::CONFLICT_TAG.to_string()));
+        a.resolve_conflict(&copies[0], false).unwrap();
+        assert_eq!(a.get_secret("shared").unwrap().as_deref(), Some("b2"));
+        assert!(a.conflict_copies().unwrap().is_empty());
+        assert!(matches!(a.resolve_conflict("shared", false), Err(Error::SecretNotFound { .. })));
+        sync_a.set_resolution(ConflictResolution::LastWriterWins);
+        sync(&mut a, &mut sync_a);
+
+        // State survives reopening the vault
+        drop(a);
+        let (mut a, mut sync_a) = open(&path_a);
+        assert_eq!(sync_a.state().conflict_count, 0);
+        assert_eq!(sync(&mut a, &mut sync_a).result, SyncResult::UpToDate);
+        a.lock();
+        assert!(matches!(a.sync_with(&mut sync_a, &backend), Err(Error::VaultLocked)));
+    }
+
+    #[test]
+    fn test_settings_refresh_after_outside_writes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set_snapshot_keep(3).unwrap();
+        assert_eq!(vault.snapshot_keep().unwrap(), 3);
+
+        // Another process changes a setting
+        let mut other = ClawBox::open(temp_dir.path()).unwrap();
+        other.unlock("test-password").unwrap();
+        other.set_snapshot_keep(5).unwrap();
+        assert_eq!(vault.snapshot_keep().unwrap(), 3);
+        assert!(vault.refresh_meta().unwrap());
+        assert_eq!(vault.snapshot_keep().unwrap(), 5);
+        drop(other);
+
+        // A pull replaces the file with a copy whose settings differ
+        let pulled = TempDir::new().unwrap();
+        std::fs::copy(temp_dir.path().join("vault.db"), pulled.path().join("vault.db")).unwrap();
+        let mut remote = ClawBox::open(pulled.path()).unwrap();
+        remote.unlock("test-password").unwrap();
+        remote.set_snapshot_keep(7).unwrap();
+        remote.change_password("test-password", "remote-password").unwrap();
+        drop(remote);
+        std::fs::copy(pulled.path().join("vault.db"), temp_dir.path().join("replaced.db")).unwrap();
+        std::fs::rename(temp_dir.path().join("replaced.db"), temp_dir.path().join("vault.db")).unwrap();
+
+        vault.reload().unwrap();
+        assert!(!vault.is_unlocked());
+        assert_eq!(vault.snapshot_keep().unwrap(), 7);
+        assert!(matches!(vault.unlock("test-password"), Err(Error::InvalidPassword)));
+        vault.unlock("remote-password").unwrap();
+    }
+
+    #[test]
+    fn test_sync_status_persists() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        // Vaults that never synced have no stored state
+        let status = vault.sync_status().unwrap();
+        assert!(!status.sync_enabled && status.last_sync.is_none() && status.local_version == 0);
+
+        vault.enable_sync().unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+        let backend = MemoryBackend::default();
+        let mut manager = vault.sync_manager().unwrap();
+        vault.sync_with(&mut manager, &backend).unwrap();
+        vault.record_sync(7).unwrap();
+
+        let reopened = ClawBox::open(temp_dir.path()).unwrap();
+        let status = reopened.sync_status().unwrap();
+        assert!(status.sync_enabled && status.last_sync.is_some());
+        assert_eq!((status.local_version, status.remote_version), (7, 7));
+        assert!(status.synced.contains_key("db/url"));
+
+        vault.disable_sync().unwrap();
+        let mut manager = vault.sync_manager().unwrap();
+        let err = vault.sync_with(&mut manager, &backend).unwrap_err();
+        assert!(err.to_string().contains("not enabled"), "{}", err);
+        // Settings are read at open, so another handle sees them once refreshed
+        assert!(reopened.sync_status().unwrap().sync_enabled);
+        assert!(reopened.refresh_meta().unwrap());
+        assert!(!reopened.sync_status().unwrap().sync_enabled);
+        assert!(!reopened.refresh_meta().unwrap());
+    }
+
+    /// Loses the first push to another device that uploads `racing` first
+    struct RacingBackend<'a> {
+        backend: &'a MemoryBackend,
+        racing: std::sync::Mutex<Option<(Vec<u8>, u64)>>,
+    }
+
+    impl SyncBackend for RacingBackend<'_> {
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn get_remote_version(&self) -> Result<u64> {
+            self.backend.get_remote_version()
+        }
+
+        fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+            if let Some(racing) = self.racing.lock().unwrap().take() {
+                *self.backend.0.lock().unwrap() = racing;
+                return Err(Error::RemoteChanged);
+            }
+            self.backend.upload(data, version)
+        }
+
+        fn download(&self) -> Result<(Vec<u8>, u64)> {
+            self.backend.download()
+        }
+    }
+
+    #[test]
+    fn test_sync_retries_when_remote_changes() {
+        use crate::sync::SyncResult;
+
+        let temp_dir = TempDir::new().unwrap();
+        let (path_a, path_b) = (temp_dir.path().join("a"), temp_dir.path().join("b"));
+        ClawBox::open(&path_a).unwrap().init("test-password").unwrap();
+        std::fs::create_dir_all(&path_b).unwrap();
+        std::fs::copy(path_a.join("vault.db"), path_b.join("vault.db")).unwrap();
+        let open = |path: &Path| {
+            let mut vault = ClawBox::open(path).unwrap();
+            vault.unlock("test-password").unwrap();
+            let mut manager = vault.sync_manager().unwrap();
+            manager.enable();
+            (vault, manager)
+        };
+        let (mut a, mut sync_a) = open(&path_a);
+        let (mut b, mut sync_b) = open(&path_b);
+
+        // B's push lands between A's check of the remote and its upload
+        let backend = MemoryBackend::default();
+        b.set("from/b", "2", Default::default()).unwrap();
+        b.sync_with(&mut sync_b, &backend).unwrap();
+        let racing = std::mem::take(&mut *backend.0.lock().unwrap());
+        a.set("from/a", "1", Default::default()).unwrap();
+        let racing = RacingBackend { backend: &backend, racing: std::sync::Mutex::new(Some(racing)) };
+        assert_eq!(a.sync_with(&mut sync_a, &racing).unwrap().result, SyncResult::Merged);
+        assert_eq!(a.get_secret("from/b").unwrap().as_deref(), Some("2"));
+        assert_eq!(backend.get_remote_version().unwrap(), 2);
+
+        assert_eq!(b.sync_with(&mut sync_b, &backend).unwrap().result, SyncResult::Pulled);
+        assert_eq!(b.get_secret("from/a").unwrap().as_deref(), Some("1"));
+    }
+
+    /// Counts uploads and can be taken offline
+    #[derive(Default)]
+    struct FlakyBackend {
+        backend: MemoryBackend,
+        offline: std::sync::atomic::AtomicBool,
+        uploads: std::sync::atomic::AtomicUsize,
+    }
+
+    impl SyncBackend for FlakyBackend {
+        fn is_connected(&self) -> bool {
+            !self.offline.load(std::sync::atomic::Ordering::SeqCst)
+        }
+
+        fn get_remote_version(&self) -> Result<u64> {
+            self.backend.get_remote_version()
+        }
+
+        fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+            self.uploads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.backend.upload(data, version)
+        }
+
+        fn download(&self) -> Result<(Vec<u8>, u64)> {
+            self.backend.download()
+        }
+    }
+
+    #[test]
+    fn test_sync_pushes_changes_made_offline() {
+        use crate::sync::SyncResult;
+        use std::sync::atomic::Ordering;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.enable_sync().unwrap();
+        let mut manager = vault.sync_manager().unwrap();
+        let backend = FlakyBackend::default();
+        assert_eq!(vault.sync_with(&mut manager, &backend).unwrap().result, SyncResult::UpToDate);
+        assert_eq!(vault.pending_changes().unwrap(), 0);
+
+        backend.offline.store(true, Ordering::SeqCst);
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+        vault.set("api/key", "k", Default::default()).unwrap();
+        vault.rename("api/key", "api/token").unwrap();
+        vault.delete("db/url", DeleteMode::Soft).unwrap();
+        assert_eq!(vault.sync_with(&mut manager, &backend).unwrap().result, SyncResult::Unavailable);
+        // Counted while locked too, e.g. for `sync --status`
+        vault.lock();
+        assert_eq!(vault.pending_changes().unwrap(), 4);
+        vault.unlock("test-password").unwrap();
+
+        backend.offline.store(false, Ordering::SeqCst);
+        assert_eq!(vault.sync_with(&mut manager, &backend).unwrap().result, SyncResult::Pushed);
+        assert_eq!(backend.uploads.load(Ordering::SeqCst), 1);
+        assert_eq!(backend.get_remote_version().unwrap(), 1);
+        assert_eq!(vault.pending_changes().unwrap(), 0);
+        assert_eq!(vault.sync_with(&mut manager, &backend).unwrap().result, SyncResult::UpToDate);
+        assert_eq!(backend.uploads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_verify() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "v1", Default::default()).unwrap();
+        vault.set("db/url", "v2", Default::default()).unwrap();
+
+        let report = vault.verify().unwrap();
+        assert!(report.is_ok() && report.values_checked);
+
+        vault.lock();
+        let report = vault.verify().unwrap();
+        assert!(report.is_ok() && !report.values_checked);
+
+        vault
+            .store
+            .connection()
+            .execute("UPDATE secret_versions SET encrypted_value = zeroblob(40) WHERE version = 1", [])
+            .unwrap();
+        vault.unlock("test-password").unwrap();
+        let report = vault.verify().unwrap();
+        assert_eq!(report.undecryptable, vec!["db/url@v1"]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn test_ai_access_enforcement() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        for (path, access) in [
+            ("open", AccessLevel::Normal),
+            ("guarded", AccessLevel::Sensitive),
+            ("root", AccessLevel::Critical),
+        ] {
+            vault.set(path, "v", SetOptions { access, ..Default::default() }).unwrap();
+        }
+        vault.set_requires_reauth(AccessLevel::Critical, false, "test-password").unwrap();
+        let agent = ActorInfo::ai("claude");
+
+        assert!(vault.get_bytes_as("open", &agent).unwrap().is_some());
+        assert!(matches!(vault.get_bytes_as("root", &agent), Err(Error::AccessDenied { .. })));
+        assert!(vault.get_bytes_as("root", &ActorInfo::human()).unwrap().is_some());
+
+        // No handler means no approval
+        assert!(matches!(vault.get_bytes_as("guarded", &agent), Err(Error::AccessDenied { .. })));
+
+        let asked = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+        let log = asked.clone();
+        vault.set_approval_handler(Some(Box::new(move |request| {
+            log.lock().unwrap().push((request.path.to_string(), request.actor.identifier.clone()));
+            Approval::Approved
+        })));
+        assert!(vault.get_bytes_as("guarded", &agent).unwrap().is_some());
+        assert!(vault.get_bytes_as("open", &agent).unwrap().is_some());
+        assert_eq!(*asked.lock().unwrap(), vec![("guarded".to_string(), "claude".to_string())]);
+
+        vault.set_approval_handler(Some(Box::new(|_| Approval::TimedOut)));
+        assert!(matches!(vault.get_bytes_as("guarded", &agent), Err(Error::ApprovalTimeout)));
+
+        let entries = vault.audit(&AuditFilter { actor_type: Some("ai".into()), ..Default::default() }).unwrap();
+        assert_eq!(entries.iter().filter(|e| e.success).count(), 3);
+        assert_eq!(entries.iter().filter(|e| !e.success).count(), 3);
+    }
+
+    #[test]
+    fn test_current_actor_access_matrix() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        let levels = [
+            ("public", AccessLevel::Public),
+            ("normal", AccessLevel::Normal),
+            ("sensitive", AccessLevel::Sensitive),
+            ("critical", AccessLevel::Critical),
+        ];
+        for (path, access) in levels {
+            vault.set(path, "v", SetOptions { access, ..Default::default() }).unwrap();
+        }
+        vault.set_requires_reauth(AccessLevel::Critical, false, "test-password").unwrap();
+        assert_eq!(vault.actor().actor_type, "human");
+
+        // (actor, readable without an approver, readable with one that approves)
+        let cases = [
+            ("human", [true, true, true, true], [true, true, true, true]),
+            ("ai:claude", [true, true, false, false], [true, true, true, false]),
+            ("app:menubar", [true, true, false, false], [true, true, true, false]),
+        ];
+        for (actor, unapproved, approved) in cases {
+            vault.set_actor(actor.parse().unwrap());
+            for (expected, approver) in [(unapproved, false), (approved, true)] {
+                let handler: Option<ApprovalHandler> = approver.then(|| Box::new(|_: &ApprovalRequest| Approval::Approved) as _);
+                vault.set_approval_handler(handler);
+                for ((path, _), readable) in levels.iter().zip(expected) {
+                    match vault.get_secret(path) {
+                        Ok(value) => assert!(readable && value.is_some(), "{} read {}", actor, path),
+                        Err(Error::AccessDenied { .. }) => assert!(!readable, "{} denied {}", actor, path),
+                        Err(e) => panic!("{} reading {}: {}", actor, path, e),
+                    }
+                }
+                // One denied path fails the whole batch
+                let all: Vec<&str> = levels.iter().map(|(path, _)| *path).collect();
+                assert_eq!(vault.get_many(&all).is_ok(), expected.iter().all(|readable| *readable), "{}", actor);
+            }
+        }
+
+        // Every decision is logged with the actor that asked
+        for (actor_type, allowed, denied) in [("human", 8, 0), ("ai", 5, 3), ("app", 5, 3)] {
+            let filter = AuditFilter { actor_type: Some(actor_type.into()), action: Some(Action::Read), ..Default::default() };
+            let entries = vault.audit(&filter).unwrap();
+            let single: Vec<_> = entries.iter().filter(|e| !e.key_path.contains(',')).collect();
+            assert_eq!(single.iter().filter(|e| e.success).count(), allowed, "{}", actor_type);
+            assert_eq!(single.iter().filter(|e| !e.success).count(), denied, "{}", actor_type);
+            assert!(single.iter().filter(|e| !e.success).all(|e| e.error_message.is_some()));
+        }
+        let writes = vault.audit(&AuditFilter { action: Some(Action::Write), ..Default::default() }).unwrap();
+        assert!(writes.iter().all(|e| e.actor.actor_type == "human"));
+
+        assert!(matches!("robot:x".parse::<ActorInfo>(), Err(Error::InvalidArgument(_))));
+        assert!(matches!("ai:".parse::<ActorInfo>(), Err(Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_critical_reads_require_reauth() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("recovery/codes", "1234", SetOptions { access: AccessLevel::Critical, ..Default::default() }).unwrap();
+        vault.set("db/url", "postgres://", SetOptions { access: AccessLevel::Sensitive, ..Default::default() }).unwrap();
+        assert!(vault.requires_reauth(AccessLevel::Critical).unwrap());
+        assert!(!vault.requires_reauth(AccessLevel::Sensitive).unwrap());
+
+        assert!(matches!(vault.get_secret("recovery/codes"), Err(Error::ReauthRequired { .. })));
+        assert!(matches!(vault.get_many(&["db/url", "recovery/codes"]), Err(Error::ReauthRequired { .. })));
+        assert!(matches!(vault.get_with_reauth("recovery/codes", "wrong"), Err(Error::InvalidPassword)));
+        assert_eq!(vault.get_with_reauth("recovery/codes", "test-password").unwrap().as_deref(), Some("1234"));
+        // Still needed on the next read
+        assert!(matches!(vault.get_secret("recovery/codes"), Err(Error::ReauthRequired { .. })));
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("postgres://"));
+
+        // Agents are refused before any password is checked
+        vault.set_actor(ActorInfo::ai("claude"));
+        assert!(matches!(vault.get_with_reauth("recovery/codes", "test-password"), Err(Error::AccessDenied { .. })));
+        vault.set_actor(ActorInfo::human());
+
+        let reauths = vault.audit(&AuditFilter { action: Some(Action::Reauth), ..Default::default() }).unwrap();
+        assert_eq!(reauths.len(), 2);
+        assert_eq!(reauths.iter().filter(|e| e.success).count(), 1);
+        assert!(reauths.iter().all(|e| e.key_path == "recovery/codes"));
+
+        // The policy takes the password to change and is per level
+        assert!(matches!(
+            vault.set_requires_reauth(AccessLevel::Critical, false, "wrong"),
+            Err(Error::InvalidPassword)
+        ));
+        vault.set_requires_reauth(AccessLevel::Critical, false, "test-password").unwrap();
+        vault.set_requires_reauth(AccessLevel::Sensitive, true, "test-password").unwrap();
+        assert_eq!(vault.get_secret("recovery/codes").unwrap().as_deref(), Some("1234"));
+        assert!(matches!(vault.get_secret("db/url"), Err(Error::ReauthRequired { .. })));
+        vault.lock();
+        assert!(matches!(vault.get_with_reauth("db/url", "test-password"), Err(Error::VaultLocked)));
+    }
+
+    #[test]
+    fn test_read_rate_limit() {
+        use std::sync::{Arc, Mutex};
+
+        let temp_dir = TempDir::new().unwrap();
+        // The start of a minute
+        let now = Arc::new(Mutex::new(chrono::DateTime::from_timestamp(1_700_000_040, 0).unwrap()));
+        let open = |actor: ActorInfo| {
+            let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+            let clock = now.clone();
+            vault.clock = Box::new(move || *clock.lock().unwrap());
+            vault.unlock("test-password").unwrap();
+            vault.set_actor(actor);
+            vault
+        };
+        let advance = |secs| *now.lock().unwrap() += chrono::Duration::seconds(secs);
+
+        ClawBox::open(temp_dir.path()).unwrap().init("test-password").unwrap();
+        let mut human = open(ActorInfo::human());
+        human.set("llm/openai", "sk-1", Default::default()).unwrap();
+        human.set("docs/readme", "hi", SetOptions { access: AccessLevel::Public, ..Default::default() }).unwrap();
+        assert_eq!(human.read_rate_limit("ai", AccessLevel::Normal).unwrap(), None);
+        assert!(matches!(human.set_read_rate_limit("robot", AccessLevel::Normal, Some(3)), Err(Error::InvalidArgument(_))));
+        assert!(matches!(human.set_read_rate_limit("ai", AccessLevel::Normal, Some(0)), Err(Error::InvalidArgument(_))));
+        human.set_read_rate_limit("ai", AccessLevel::Normal, Some(3)).unwrap();
+        assert_eq!(human.read_rate_limit("ai", AccessLevel::Normal).unwrap(), Some(3));
+
+        // Another handle stands in for another process
+        let agent = open(ActorInfo::ai("looper"));
+        assert!(agent.get_secret("llm/openai").unwrap().is_some());
+        assert!(agent.get_many(&["llm/openai"]).is_ok());
+        assert!(open(ActorInfo::ai("looper")).get_secret("llm/openai").is_ok());
+        match agent.get_secret("llm/openai") {
+            Err(Error::RateLimited { retry_after }) => assert_eq!(retry_after, Duration::from_secs(80)),
+            other => panic!("expected RateLimited, got {:?}", other),
+        }
+        assert!(matches!(agent.get_many(&["llm/openai"]), Err(Error::RateLimited { .. })));
+
+        // Other levels, other agents and humans have their own allowances
+        assert!(agent.get_secret("docs/readme").is_ok());
+        assert!(open(ActorInfo::ai("other")).get_secret("llm/openai").is_ok());
+        for _ in 0..5 {
+            assert!(human.get_secret("llm/openai").is_ok());
+        }
+
+        // The previous minute still counts, fading out over this one
+        advance(60);
+        assert!(matches!(agent.get_secret("llm/openai"), Err(Error::RateLimited { .. })));
+        advance(30);
+        assert!(agent.get_secret("llm/openai").is_ok());
+
+        let mut agent = agent;
+        assert!(matches!(agent.set_read_rate_limit("ai", AccessLevel::Normal, None), Err(Error::AccessDenied { .. })));
+        let denied = human
+            .audit(&AuditFilter { actor_type: Some("ai".into()), action: Some(Action::Read), ..Default::default() })
+            .unwrap()
+            .into_iter()
+            .filter(|e| !e.success)
+            .count();
+        assert_eq!(denied, 3);
+
+        human.set_read_rate_limit("ai", AccessLevel::Normal, None).unwrap();
+        agent.refresh_meta().unwrap();
+        for _ in 0..5 {
+            assert!(agent.get_secret("llm/openai").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_scoped_access_token() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("llm/openai", "sk-1", Default::default()).unwrap();
+        vault.set("llm/anthropic", "sk-2", SetOptions { access: AccessLevel::Sensitive, ..Default::default() }).unwrap();
+        vault.set("llmx/other", "x", Default::default()).unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+
+        let scope = |prefixes: &[&str], max_access| TokenScope {
+            path_prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+            max_access,
+            expires_at: None,
+        };
+        assert!(matches!(vault.create_token("llm", scope(&[], AccessLevel::Normal)), Err(Error::InvalidArgument(_))));
+        assert!(matches!(
+            vault.create_token("llm", scope(&["llm"], AccessLevel::Sensitive)),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(vault.create_token("a b", scope(&["llm"], AccessLevel::Normal)), Err(Error::InvalidArgument(_))));
+        let token = vault.create_token("llm", scope(&["llm/*"], AccessLevel::Normal)).unwrap();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert!(matches!(vault.create_token("llm", scope(&["db"], AccessLevel::Normal)), Err(Error::InvalidArgument(_))));
+        let tokens = vault.tokens().unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].scope.path_prefixes, ["llm"]);
+
+        let mut agent = ClawBox::open(temp_dir.path()).unwrap();
+        assert!(matches!(agent.unlock_with_token("cbt_0000"), Err(Error::AccessDenied { .. })));
+        agent.unlock_with_token(&token).unwrap();
+        assert_eq!(agent.actor().actor_type, "token");
+        assert_eq!(agent.token().unwrap().name, "llm");
+        assert_eq!(agent.get_secret("llm/openai").unwrap().as_deref(), Some("sk-1"));
+        for path in ["llm/anthropic", "llmx/other", "db/url"] {
+            assert!(matches!(agent.get_secret(path), Err(Error::AccessDenied { .. })), "{}", path);
+        }
+        assert!(matches!(agent.get_many(&["llm/openai", "db/url"]), Err(Error::AccessDenied { .. })));
+        let listed: Vec<String> = agent.list(None).unwrap().into_iter().map(|e| e.path).collect();
+        assert_eq!(listed, ["llm/openai"]);
+
+        // Read-only, whatever actor the embedder claims to be
+        agent.set_actor(ActorInfo::human());
+        assert!(matches!(agent.set("llm/new", "v", Default::default()), Err(Error::AccessDenied { .. })));
+        assert!(matches!(agent.get_secret("db/url"), Err(Error::AccessDenied { .. })));
+        assert!(matches!(agent.export_key(), Err(Error::AccessDenied { .. })));
+        assert!(matches!(agent.create_token("x", scope(&["db"], AccessLevel::Normal)), Err(Error::AccessDenied { .. })));
+
+        // Locking ends the restriction along with the key
+        agent.lock();
+        assert!(agent.token().is_none());
+        agent.unlock("test-password").unwrap();
+        assert_eq!(agent.get_secret("db/url").unwrap().as_deref(), Some("postgres://"));
+        agent.lock();
+
+        let audit = vault.audit(&AuditFilter { actor_type: Some("token".into()), ..Default::default() }).unwrap();
+        assert!(audit.iter().all(|e| e.actor.identifier == "llm" || !e.success));
+        assert!(audit.iter().any(|e| e.action == Action::Read && e.key_path == "db/url" && !e.success));
+
+        // Expired and revoked tokens stop working
+        let expired = vault
+            .create_token("old", TokenScope { expires_at: Some(chrono::Utc::now()), ..scope(&["llm"], AccessLevel::Normal) })
+            .unwrap();
+        assert!(matches!(agent.unlock_with_token(&expired), Err(Error::AccessDenied { .. })));
+        assert!(vault.revoke_token("llm").unwrap());
+        assert!(!vault.revoke_token("llm").unwrap());
+        assert!(matches!(agent.unlock_with_token(&token), Err(Error::AccessDenied { .. })));
+
+        // So does every token once the password changes
+        let token = vault.create_token("llm", scope(&["llm"], AccessLevel::Normal)).unwrap();
+        vault.change_password("test-password", "new-password").unwrap();
+        assert!(vault.tokens().unwrap().is_empty());
+        assert!(matches!(agent.unlock_with_token(&token), Err(Error::AccessDenied { .. })));
+    }
+
+    #[test]
+    fn test_duress_password_opens_decoy() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("bank/pin", "1234", Default::default()).unwrap();
+        vault.set("shared/path", "real", Default::default()).unwrap();
+        assert!(matches!(vault.set_duress_password("test-password"), Err(Error::InvalidArgument(_))));
+        vault.set_duress_password("duress-password").unwrap();
+        assert!(vault.has_duress_password().unwrap());
+        assert!(matches!(vault.change_password("test-password", "duress-password"), Err(Error::InvalidArgument(_))));
+
+        // The decoy starts empty and keeps its own secrets, history and audit log
+        let mut decoy = ClawBox::open(temp_dir.path()).unwrap();
+        decoy.unlock("duress-password").unwrap();
+        assert!(decoy.list(None).unwrap().is_empty());
+        assert_eq!(decoy.get_secret("bank/pin").unwrap(), None);
+        assert!(decoy.history("bank/pin").unwrap().is_empty());
+        assert!(decoy.audit(&AuditFilter::default()).unwrap().iter().all(|e| e.action == Action::Read));
+        decoy.set("shared/path", "decoy", Default::default()).unwrap();
+        decoy.set("mail/password", "hunter2", Default::default()).unwrap();
+        decoy.rename("mail/password", "bank/pin").unwrap();
+        let (bundle, paths) = decoy.export_bundle("bundle pass", &Default::default(), None).unwrap();
+        assert_eq!(paths, ["bank/pin", "shared/path"]);
+        assert_eq!(decoy.stats().unwrap().normal_count, 2);
+        assert!(decoy.audit(&AuditFilter::default()).unwrap().iter().all(|e| e.key_path != "vault" || e.success));
+        assert!(decoy.verify_audit_integrity().unwrap());
+        assert!(matches!(decoy.set_duress_password("other"), Err(Error::AccessDenied { .. })));
+        assert!(matches!(decoy.sync_key(), Err(Error::AccessDenied { .. })));
+        assert_eq!(decoy.pending_changes().unwrap(), 0);
+
+        // Tokens and a session key open the keyspace they were made in
+        let scope = TokenScope { path_prefixes: vec!["bank".into()], max_access: AccessLevel::Normal, expires_at: None };
+        let token = decoy.create_token("bank", scope).unwrap();
+        let key = decoy.export_key().unwrap();
+        decoy.lock();
+        decoy.unlock_with_key(&key).unwrap();
+        assert_eq!(decoy.get_secret("bank/pin").unwrap().as_deref(), Some("hunter2"));
+        decoy.unlock_with_token(&token).unwrap();
+        assert_eq!(decoy.get_secret("bank/pin").unwrap().as_deref(), Some("hunter2"));
+        assert!(vault.tokens().unwrap().is_empty());
+
+        // None of it reaches the real vault, and its audit log stays there
+        assert_eq!(vault.get_secret("bank/pin").unwrap().as_deref(), Some("1234"));
+        assert_eq!(vault.get_secret("shared/path").unwrap().as_deref(), Some("real"));
+        assert_eq!(vault.history("shared/path").unwrap().len(), 1);
+        let (real_bundle, _) = vault.export_bundle("bundle pass", &Default::default(), None).unwrap();
+        let other_dir = TempDir::new().unwrap();
+        let mut other = ClawBox::open(other_dir.path()).unwrap();
+        other.init("other-password").unwrap();
+        other.import_bundle(&bundle, "bundle pass", ConflictMode::Overwrite).unwrap();
+        assert_eq!(other.get_secret("bank/pin").unwrap().as_deref(), Some("hunter2"));
+        other.import_bundle(&real_bundle, "bundle pass", ConflictMode::Overwrite).unwrap();
+        assert_eq!(other.get_secret("bank/pin").unwrap().as_deref(), Some("1234"));
+        assert!(decoy.audit(&AuditFilter::default()).unwrap().iter().all(|e| e.key_path != "duress password"));
+        let real_audit = vault.audit(&AuditFilter::default()).unwrap();
+        assert!(real_audit.iter().any(|e| e.key_path == "duress password"));
+        assert!(real_audit.iter().all(|e| e.key_path != "mail/password"));
+        assert!(vault.verify_audit_integrity().unwrap());
+
+        // A password change in the decoy only re-encrypts the decoy
+        decoy.lock();
+        decoy.unlock("duress-password").unwrap();
+        decoy.change_password("duress-password", "duress-2").unwrap();
+        assert!(decoy.verify().unwrap().undecryptable.is_empty());
+        decoy.lock();
+        assert!(matches!(decoy.unlock("duress-password"), Err(Error::InvalidPassword)));
+        decoy.unlock("test-password").unwrap();
+        assert_eq!(decoy.get_secret("bank/pin").unwrap().as_deref(), Some("1234"));
+        assert!(decoy.verify().unwrap().undecryptable.is_empty());
+        decoy.lock();
+        decoy.unlock("duress-2").unwrap();
+        assert_eq!(decoy.get_secret("bank/pin").unwrap().as_deref(), Some("hunter2"));
+
+        // Setting the duress password again starts a new, empty decoy
+        vault.set_duress_password("duress-3").unwrap();
+        decoy.lock();
+        decoy.unlock("duress-3").unwrap();
+        assert!(decoy.list(None).unwrap().is_empty());
+        assert!(vault.list(None).unwrap().len() == 2);
+    }
+
+    #[test]
+    fn test_hooks_on_critical_read() {
+        use crate::hooks::HookTarget;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        let critical = SetOptions { access: AccessLevel::Critical, ..Default::default() };
+        vault.set("bank/pin", "1234", critical).unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+
+        let log = temp_dir.path().join("hook.log");
+        vault.set_hooks(vec![
+            Hook::on_critical_read(HookTarget::Command(format!("echo \"$CLAWBOX_HOOK_PATH\" >> '{}'", log.display()))),
+            Hook { name: "broken".to_string(), ..Hook::on_critical_read(HookTarget::Command("exit 1".to_string())) },
+        ]);
+
+        // A failing hook doesn't fail the read
+        assert_eq!(vault.get_with_reauth("bank/pin", "test-password").unwrap().unwrap().expose(), "1234");
+        vault.get_secret("db/url").unwrap();
+        vault.wait_for_hooks();
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "bank/pin\n");
+
+        let reads = vault.audit(&AuditFilter { action: Some(Action::Read), ..Default::default() }).unwrap();
+        let pin = reads.iter().find(|entry| entry.key_path == "bank/pin").unwrap();
+        let outcomes = &pin.metadata.as_ref().unwrap()["hooks"];
+        assert_eq!(outcomes[0]["ok"], true);
+        assert_eq!(outcomes[1]["hook"], "broken");
+        assert_eq!(outcomes[1]["ok"], false);
+        assert!(reads.iter().find(|entry| entry.key_path == "db/url").unwrap().metadata.is_none());
+        assert!(vault.verify_audit_integrity().unwrap());
+    }
+
+    #[test]
+    fn test_export_plaintext() {
+        use crate::hooks::HookTarget;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+        vault.set("stripe/key", "sk_live", SetOptions { access: AccessLevel::Sensitive, ..Default::default() }).unwrap();
+        vault.set("bank/pin", "1234", SetOptions { access: AccessLevel::Critical, ..Default::default() }).unwrap();
+        let log = temp_dir.path().join("hook.log");
+        vault.set_hooks(vec![Hook::on_critical_read(HookTarget::Command(format!("cat >> '{}'", log.display())))]);
+
+        let mut opts = PlaintextExportOptions { destination: "out.json".to_string(), format: "json".to_string(), ..Default::default() };
+        assert!(matches!(vault.export_plaintext(&opts, None), Err(Error::InvalidArgument(_))));
+
+        opts.plaintext_ok = true;
+        let summary = vault.export_summary(false).unwrap();
+        assert_eq!(
+            summary,
+            ExportSummary { count: 2, highest: Some(AccessLevel::Sensitive), skipped_critical: 1, needs_reauth: false }
+        );
+        assert!(vault.export_summary(true).unwrap().needs_reauth);
+        let exported = vault.export_plaintext(&opts, None).unwrap();
+        let paths: Vec<&str> = exported.iter().map(|(info, _)| info.path.as_str()).collect();
+        assert_eq!(paths, ["db/url", "stripe/key"]);
+
+        // Critical secrets also need the master password again
+        opts.include_critical = true;
+        assert!(matches!(vault.export_plaintext(&opts, None), Err(Error::ReauthRequired { .. })));
+        assert!(matches!(vault.export_plaintext(&opts, Some("wrong")), Err(Error::InvalidPassword)));
+        assert_eq!(vault.export_plaintext(&opts, Some("test-password")).unwrap().len(), 3);
+
+        // AI agents can't export what they can't read
+        vault.set_actor(ActorInfo::ai("agent"));
+        assert!(matches!(vault.export_plaintext(&opts, Some("test-password")), Err(Error::AccessDenied { .. })));
+        vault.set_actor(ActorInfo::human());
+        vault.wait_for_hooks();
+
+        // One entry per export, none per secret; the hook saw the Critical one
+        assert!(vault.audit(&AuditFilter { action: Some(Action::Read), ..Default::default() }).unwrap().is_empty());
+        let exports = vault.audit(&AuditFilter { action: Some(Action::Export), ..Default::default() }).unwrap();
+        assert_eq!(exports.len(), 5);
+        assert!(exports.iter().all(|entry| entry.key_path == "out.json"));
+        let full = exports.iter().find(|entry| entry.success && entry.metadata.as_ref().unwrap()["count"] == 3).unwrap();
+        let metadata = full.metadata.as_ref().unwrap();
+        assert_eq!(metadata["plaintext"], true);
+        assert_eq!(metadata["highest_access"], "critical");
+        assert_eq!(metadata["hooks"][0]["ok"], true);
+        assert_eq!(exports.iter().filter(|entry| entry.success).count(), 2);
+        assert_eq!(std::fs::read_to_string(&log).unwrap().lines().count(), 1);
+        assert!(vault.verify_audit_integrity().unwrap());
+    }
+
+    #[test]
+    fn test_read_only_vault() {
+        let temp_dir = TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("vault.db");
+        assert!(matches!(ClawBox::open_read_only(temp_dir.path()), Err(Error::VaultNotFound { .. })));
+        assert!(!db_path.exists());
+
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+        vault.set_read_rate_limit("app", AccessLevel::Normal, Some(10)).unwrap();
+        let (bundle, _) = vault.export_bundle("bundle pass", &Default::default(), None).unwrap();
+        let audit_entries = vault.audit(&AuditFilter::default()).unwrap().len();
+        drop(vault);
+        let before = std::fs::read(&db_path).unwrap();
+
+        let mut vault = ClawBox::open_read_only(temp_dir.path()).unwrap();
+        assert!(matches!(vault.unlock("wrong"), Err(Error::InvalidPassword)));
+        vault.unlock("test-password").unwrap();
+        assert_eq!(vault.get_secret("db/url").unwrap().as_deref(), Some("postgres://"));
+        assert_eq!(vault.list(None).unwrap().len(), 1);
+        assert_eq!(vault.audit(&AuditFilter::default()).unwrap().len(), audit_entries);
+        assert!(matches!(vault.set("db/url", "changed", Default::default()), Err(Error::ReadOnly)));
+        assert!(matches!(vault.delete("db/url", DeleteMode::Soft), Err(Error::ReadOnly)));
+        assert!(matches!(vault.rename("db/url", "db/other"), Err(Error::ReadOnly)));
+        assert!(matches!(vault.import_bundle(&bundle, "bundle pass", ConflictMode::Overwrite), Err(Error::ReadOnly)));
+        assert!(matches!(vault.change_password("test-password", "new-password"), Err(Error::ReadOnly)));
+        // Rate-limited reads can't be counted
+        assert!(matches!(vault.get_bytes_as("db/url", &ActorInfo::app("monitor")), Err(Error::ReadOnly)));
+        vault.lock();
+        drop(vault);
+
+        assert_eq!(std::fs::read(&db_path).unwrap(), before);
+        let sidecar = std::fs::read_to_string(temp_dir.path().join(crate::storage::AUDIT_SIDECAR_FILE)).unwrap();
+        assert!(sidecar.lines().any(|line| line.contains("\"db/url\"")));
+        assert_eq!(ClawBox::open(temp_dir.path()).unwrap().unlock_lockout().unwrap(), UnlockLockout::default());
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let key = [0u8, 159, 146, 150, 0, 255];
+        vault.set_bytes("binary/key", &key, Default::default()).unwrap();
+        assert_eq!(vault.get_bytes("binary/key").unwrap().as_deref(), Some(&key.to_vec()));
+        assert_eq!(vault.info("binary/key").unwrap().unwrap().size, key.len());
+        assert!(vault.get_secret("binary/key").is_err());
+
+        vault.set("text", "héllo", Default::default()).unwrap();
+        assert_eq!(vault.get_bytes("text").unwrap().as_deref(), Some(&"héllo".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_large_values_chunked() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let large: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        for len in [crypto::CHUNK_SIZE, crypto::CHUNK_SIZE + 1, large.len()] {
+            vault.set_bytes("blob", &large[..len], Default::default()).unwrap();
+            assert_eq!(vault.get_bytes("blob").unwrap().unwrap().as_slice(), &large[..len]);
+            assert_eq!(vault.info("blob").unwrap().unwrap().size, len);
+        }
+        assert!(vault.store.get("blob").unwrap().unwrap().starts_with(&crypto::CHUNKED_MAGIC));
+
+        // Re-encrypted under the new key like any other value
+        vault.change_password("test-password", "new-password").unwrap();
+        assert_eq!(*vault.get_bytes("blob").unwrap().unwrap(), large);
+        assert_eq!(vault.history("blob").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_parallel_decrypt_matches_serial() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+
+        let paths: Vec<String> = (0..300).map(|i| format!("app/{:04}", i)).collect();
+        let values: Vec<String> = (0..300).map(|i| "v".repeat(i % 97 + 1) + &i.to_string()).collect();
+        let items: Vec<(&str, &str, SetOptions)> =
+            paths.iter().zip(&values).map(|(path, value)| (path.as_str(), value.as_str(), SetOptions::default())).collect();
+        vault.set_many(&items).unwrap();
+        vault.set("app/large", &"x".repeat(crypto::CHUNK_SIZE * 2 + 5), Default::default()).unwrap();
+
+        let opts = PlaintextExportOptions { plaintext_ok: true, destination: "out.json".to_string(), ..Default::default() };
+        let read_all = |vault: &ClawBox| {
+            let prefix: Vec<(String, Vec<u8>)> =
+                vault.get_prefix("app").unwrap().into_iter().map(|(path, value)| (path, value.expose().as_bytes().to_vec())).collect();
+            let export: Vec<(String, Vec<u8>)> = vault
+                .export_plaintext(&opts, None)
+                .unwrap()
+                .into_iter()
+                .map(|(info, value)| (info.path, value.expose().as_bytes().to_vec()))
+                .collect();
+            let (bundle, _) = vault.export_bundle("bundle pass", &Default::default(), None).unwrap();
+            let bundled: Vec<(String, Vec<u8>)> =
+                bundle::open(&bundle, "bundle pass").unwrap().into_iter().map(|secret| (secret.path, secret.value)).collect();
+            (prefix, export, bundled)
+        };
+
+        let serial = read_all(&vault);
+        assert_eq!(serial.0.len(), 301);
+        assert_eq!(serial.0[0], ("app/0000".to_string(), b"v0".to_vec()));
+        for jobs in [0, 3] {
+            vault.set_decrypt_jobs(jobs);
+            assert!(read_all(&vault) == serial, "jobs = {}", jobs);
+        }
+    }
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://", SetOptions { tags: vec!["infra".into()], ..Default::default() }).unwrap();
+        vault.set("root/key", "k", SetOptions { access: AccessLevel::Critical, ..Default::default() }).unwrap();
+
+        let (bundle, paths) = vault.export_bundle("bundle pass", &Default::default(), None).unwrap();
+        assert_eq!(paths, vec!["db/url"]);
+        let (full, paths) = vault
+            .export_bundle("bundle pass", &ExportOptions { include_critical: true }, Some("test-password"))
+            .unwrap();
+        assert_eq!(paths.len(), 2);
+        // Critical secrets need the master password again, as for `get`
+        let include_critical = ExportOptions { include_critical: true };
+        assert!(matches!(vault.export_bundle("bundle pass", &include_critical, None), Err(Error::ReauthRequired { .. })));
+        assert!(matches!(
+            vault.export_bundle("bundle pass", &include_critical, Some("wrong")),
+            Err(Error::InvalidPassword)
+        ));
+        let reauths = vault.audit(&AuditFilter { action: Some(Action::Reauth), ..Default::default() }).unwrap();
+        assert_eq!(reauths.len(), 2);
+        assert_eq!(reauths.iter().filter(|e| e.success).count(), 1);
+
+        let other_dir = TempDir::new().unwrap();
+        let mut other = ClawBox::open(other_dir.path()).unwrap();
+        other.init("other-password").unwrap();
+        assert!(matches!(
+            other.import_bundle(&bundle, "wrong", ConflictMode::Overwrite),
+            Err(Error::InvalidPassword)
+        ));
+
+        other.set("db/url", "mine", Default::default()).unwrap();
+        let report = other.import_bundle(&full, "bundle pass", ConflictMode::Skip).unwrap();
+        assert_eq!(report.imported, vec!["root/key"]);
+        assert_eq!(report.skipped, vec!["db/url"]);
+        assert!(report.snapshot.is_none());
+        assert_eq!(other.get_secret("db/url").unwrap().as_deref(), Some("mine"));
+
+        let report = other.import_bundle(&bundle, "bundle pass", ConflictMode::Overwrite).unwrap();
+        assert!(report.snapshot.unwrap().ends_with("-import.db"));
+        assert_eq!(other.get_secret("db/url").unwrap().as_deref(), Some("postgres://"));
+        assert_eq!(other.info("db/url").unwrap().unwrap().tags, vec!["infra"]);
+        assert_eq!(other.info("root/key").unwrap().unwrap().access, AccessLevel::Critical);
+    }
+
+    #[test]
+    fn test_bundle_export_checks_actor() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut vault = ClawBox::open(temp_dir.path()).unwrap();
+        vault.init("test-password").unwrap();
+        vault.set("db/url", "postgres://", Default::default()).unwrap();
+        vault.set("db/password", "hunter2", SetOptions { access: AccessLevel::Sensitive, ..Default::default() }).unwrap();
+
+        // An agent can't export what it couldn't read with `get`
+        vault.set_actor("ai:claude".parse().unwrap());
+        assert!(matches!(vault.export_bundle("bundle pass", &Default::default(), None), Err(Error::AccessDenied { .. })));
+        let exports = vault.audit(&AuditFilter { action: Some(Action::Export), ..Default::default() }).unwrap();
+        assert!(exports.iter().all(|e| !e.success));
+
+        vault.set_approval_handler(Some(Box::new(|_: &ApprovalRequest| Approval::Approved)));
+        let (_, paths) = vault.export_bundle("bundle pass", &Default::default(), None).unwrap();
+        assert_eq!(paths.len(), 2);
     }
 
     #[test]
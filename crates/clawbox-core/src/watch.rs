@@ -0,0 +1,272 @@
+//! Background sync
+//!
+//! `SyncWatcher` keeps a vault in sync while it runs: it waits for events
+//! saying the remote copy or the local database changed, lets a burst of
+//! them settle, and syncs if anything really changed. Events come from a
+//! channel, so the file system (`watch_files`, with the `watch` feature), a
+//! signal handler or a test can all drive it.
+
+use crate::sync::{SyncBackend, SyncReport};
+use crate::{ClawBox, Result};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// Longest wait for events to settle, in debounce periods
+const SETTLE_LIMIT: u32 = 5;
+
+/// Something the watcher should react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// The remote copy may have changed
+    Remote,
+    /// The local database may have changed
+    Local,
+    /// Shut down
+    Stop,
+}
+
+/// Runs syncs in response to `WatchEvent`s
+pub struct SyncWatcher<'a> {
+    vault: &'a mut ClawBox,
+    backend: &'a dyn SyncBackend,
+    /// Quiet time after an event before syncing
+    debounce: Duration,
+    /// Check both sides this often even without events, for backends and
+    /// platforms that can't be watched
+    poll: Option<Duration>,
+    data_version: i64,
+}
+
+impl<'a> SyncWatcher<'a> {
+    /// Watcher for an unlocked vault
+    pub fn new(vault: &'a mut ClawBox, backend: &'a dyn SyncBackend) -> Result<Self> {
+        let data_version = vault.data_version()?;
+        Ok(Self {
+            vault,
+            backend,
+            debounce: Duration::from_secs(2),
+            poll: None,
+            data_version,
+        })
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll: Option<Duration>) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Sync once, then after every change until `WatchEvent::Stop` or the
+    /// sender goes away
+    ///
+    /// Each sync's outcome goes to `on_sync`; failed syncs (e.g. the network
+    /// is down) don't stop the loop.
+    pub fn run(&mut self, events: &Receiver<WatchEvent>, mut on_sync: impl FnMut(&Result<SyncReport>)) -> Result<()> {
+        on_sync(&self.sync());
+        loop {
+            let first = match self.poll {
+                Some(poll) => match events.recv_timeout(poll) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if self.needs_sync(true, true)? {
+                            on_sync(&self.sync());
+                        }
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                },
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => return Ok(()),
+                },
+            };
+            let Some((remote, local)) = self.settle(first, events) else {
+                return Ok(());
+            };
+            if self.needs_sync(remote, local)? {
+                on_sync(&self.sync());
+            }
+        }
+    }
+
+    /// Collect events until none arrived for `debounce`, or for at most
+    /// `SETTLE_LIMIT` debounces when they keep coming (reads write audit
+    /// entries, so a busy vault is never quiet); `None` on `Stop`
+    fn settle(&self, first: WatchEvent, events: &Receiver<WatchEvent>) -> Option<(bool, bool)> {
+        let (mut remote, mut local) = (false, false);
+        let deadline = Instant::now() + self.debounce * SETTLE_LIMIT;
+        let mut event = first;
+        loop {
+            match event {
+                WatchEvent::Stop => return None,
+                WatchEvent::Remote => remote = true,
+                WatchEvent::Local => local = true,
+            }
+            let wait = self.debounce.min(deadline.saturating_duration_since(Instant::now()));
+            event = match events.recv_timeout(wait) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => return Some((remote, local)),
+                Err(RecvTimeoutError::Disconnected) => return None,
+            };
+        }
+    }
+
+    /// Whether the events were more than the echo of our own last sync
+    fn needs_sync(&mut self, remote: bool, local: bool) -> Result<bool> {
+        // Our own writes don't move the data version, so this only sees
+        // other processes (e.g. `clawbox set`)
+        self.vault.refresh_meta()?;
+        let data_version = self.vault.data_version()?;
+        if local && data_version != self.data_version {
+            self.data_version = data_version;
+            return Ok(true);
+        }
+        if remote && self.backend.is_connected() {
+            let seen = self.vault.sync_status()?.remote_version;
+            // An unreachable remote is retried at the next event
+            return Ok(self.backend.get_remote_version().map_or(true, |version| version != seen));
+        }
+        Ok(false)
+    }
+
+    fn sync(&mut self) -> Result<SyncReport> {
+        // Other processes may have synced or changed the settings meanwhile
+        self.vault.refresh_meta()?;
+        let mut manager = self.vault.sync_manager()?;
+        self.vault.sync_with(&mut manager, self.backend)
+    }
+}
+
+/// Send `Remote` for changes in `remote_dir` and `Local` for changes to the
+/// vault database in `vault_dir`
+///
+/// Events stop when the returned watcher is dropped.
+#[cfg(feature = "watch")]
+pub fn watch_files(
+    vault_dir: &std::path::Path,
+    remote_dir: Option<&std::path::Path>,
+    events: std::sync::mpsc::Sender<WatchEvent>,
+) -> Result<notify::RecommendedWatcher> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    let watch_error = |e: notify::Error| crate::Error::Other(format!("Cannot watch for changes: {}", e));
+    // Events name paths the way the OS resolves them (e.g. /private/var on macOS)
+    let vault_dir = vault_dir.canonicalize()?;
+    let remote = remote_dir.map(|dir| dir.canonicalize()).transpose()?;
+    let remote_dir = remote.clone();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        for path in &event.paths {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+            let kind = if remote.as_ref().is_some_and(|remote| path.starts_with(remote)) {
+                // Lock files and half-written temporaries come and go mid-sync
+                if name == crate::sync::LOCK_FILE || name.ends_with(".tmp") {
+                    continue;
+                }
+                WatchEvent::Remote
+            } else if name.starts_with("vault.db") {
+                WatchEvent::Local
+            } else {
+                continue;
+            };
+            let _ = events.send(kind);
+        }
+    })
+    .map_err(watch_error)?;
+    watcher.watch(&vault_dir, RecursiveMode::NonRecursive).map_err(watch_error)?;
+    if let Some(remote_dir) = remote_dir {
+        watcher.watch(&remote_dir, RecursiveMode::NonRecursive).map_err(watch_error)?;
+    }
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::{Action, AuditFilter};
+    use crate::sync::{DirBackend, SyncResult};
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc::channel;
+    use tempfile::TempDir;
+
+    /// A write made by another process, e.g. `clawbox set`
+    fn set_elsewhere(path: &Path, key: &str, value: &str) {
+        let mut vault = ClawBox::open(path).unwrap();
+        vault.unlock("test-password").unwrap();
+        vault.set(key, value, Default::default()).unwrap();
+    }
+
+    #[test]
+    fn test_watcher_syncs_on_changes() {
+        let temp = TempDir::new().unwrap();
+        let (path_a, path_b, remote) = (temp.path().join("a"), temp.path().join("b"), temp.path().join("remote"));
+        ClawBox::open(&path_a).unwrap().init("test-password").unwrap();
+        std::fs::create_dir_all(&path_b).unwrap();
+        std::fs::create_dir_all(&remote).unwrap();
+        std::fs::copy(path_a.join("vault.db"), path_b.join("vault.db")).unwrap();
+        let open = |path: &Path| {
+            let mut vault = ClawBox::open(path).unwrap();
+            vault.unlock("test-password").unwrap();
+            vault.enable_sync().unwrap();
+            vault
+        };
+        let (mut a, mut b) = (open(&path_a), open(&path_b));
+        let backend = DirBackend::new(&remote);
+        let sync_b = |b: &mut ClawBox| b.sync_with(&mut b.sync_manager().unwrap(), &backend).unwrap().result;
+
+        // The first sync catches up with changes made while not watching
+        b.set("from/b", "1", Default::default()).unwrap();
+        assert_eq!(sync_b(&mut b), SyncResult::Pushed);
+        let (tx, rx) = channel();
+        let mut results = vec![];
+        let mut watcher = SyncWatcher::new(&mut a, &backend).unwrap().with_debounce(Duration::from_millis(10));
+        let events = {
+            let path_a: PathBuf = path_a.clone();
+            std::thread::spawn(move || {
+                // Echoes of that sync are ignored
+                tx.send(WatchEvent::Local).unwrap();
+                tx.send(WatchEvent::Remote).unwrap();
+                std::thread::sleep(Duration::from_millis(200));
+                // A burst of events for one change leads to one sync
+                set_elsewhere(&path_a, "from/a", "2");
+                for _ in 0..3 {
+                    tx.send(WatchEvent::Local).unwrap();
+                }
+                std::thread::sleep(Duration::from_millis(200));
+                tx.send(WatchEvent::Stop).unwrap();
+            })
+        };
+        watcher.run(&rx, |result| results.push(result.as_ref().unwrap().result)).unwrap();
+        events.join().unwrap();
+        assert_eq!(results, [SyncResult::Pulled, SyncResult::Pushed]);
+        assert_eq!(a.get_secret("from/b").unwrap().as_deref(), Some("1"));
+        assert_eq!(sync_b(&mut b), SyncResult::Pulled);
+        assert_eq!(b.get_secret("from/a").unwrap().as_deref(), Some("2"));
+
+        // A remote change is pulled; the sender going away stops the loop
+        b.set("from/b", "3", Default::default()).unwrap();
+        assert_eq!(sync_b(&mut b), SyncResult::Pushed);
+        let (tx, rx) = channel();
+        tx.send(WatchEvent::Remote).unwrap();
+        drop(tx);
+        let mut results = vec![];
+        SyncWatcher::new(&mut a, &backend)
+            .unwrap()
+            .with_debounce(Duration::from_millis(10))
+            .run(&rx, |result| results.push(result.as_ref().unwrap().result))
+            .unwrap();
+        assert_eq!(results, [SyncResult::Pulled]);
+        assert_eq!(a.get_secret("from/b").unwrap().as_deref(), Some("3"));
+
+        let synced = a.audit(&AuditFilter { action: Some(Action::Sync), ..Default::default() }).unwrap();
+        assert_eq!(synced.len(), 3);
+        assert!(synced.iter().all(|entry| entry.actor.actor_type == "system"));
+    }
+}
@@ -0,0 +1,414 @@
+//! WebDAV sync backend (Nextcloud, ownCloud, ...)
+//!
+//! Keeps `vault.encrypted` and `vault.meta` in a WebDAV collection, versioned
+//! like the folder backends. While a device writes or reads them it holds a
+//! WebDAV lock on the vault file if the server supports locking; uploads are
+//! also conditional on the ETag seen, so servers without locks can't
+//! interleave two pushes either.
+
+use crate::sync::{SyncBackend, META_FILE, VAULT_FILE};
+use crate::{Error, Result};
+use base64::Engine;
+use std::io::Read;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+/// Seconds a lock is held before the server drops it, should we crash
+const LOCK_TIMEOUT: u64 = 300;
+/// Largest vault file we download
+const MAX_DOWNLOAD: u64 = 256 * 1024 * 1024;
+
+const PROPFIND_ETAG: &str = r#"<?xml version="1.0" encoding="utf-8"?><d:propfind xmlns:d="DAV:"><d:prop><d:getetag/></d:prop></d:propfind>"#;
+
+/// Credentials sent with every request
+#[derive(Default)]
+pub enum WebDavAuth {
+    #[default]
+    None,
+    Basic { username: String, password: Zeroizing<String> },
+    Bearer(Zeroizing<String>),
+}
+
+/// Sync through a WebDAV collection
+pub struct WebDavBackend {
+    /// Collection URL, ending in `/`
+    base: String,
+    auth: WebDavAuth,
+    agent: ureq::Agent,
+}
+
+/// A lock on the vault file, released on drop
+struct DavLock<'a> {
+    backend: &'a WebDavBackend,
+    token: String,
+}
+
+impl Drop for DavLock<'_> {
+    fn drop(&mut self) {
+        let _ = self
+            .backend
+            .request("UNLOCK", VAULT_FILE)
+            .set("Lock-Token", &format!("<{}>", self.token))
+            .call();
+    }
+}
+
+impl WebDavBackend {
+    /// Backend for the collection at `url` (created on first upload)
+    pub fn new(url: &str, auth: WebDavAuth) -> Self {
+        let base = if url.ends_with('/') { url.to_string() } else { format!("{}/", url) };
+        let agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(60)).build();
+        Self { base, auth, agent }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.base
+    }
+
+    fn request(&self, method: &str, name: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}{}", self.base, name));
+        match &self.auth {
+            WebDavAuth::None => request,
+            WebDavAuth::Basic { username, password } => {
+                let credentials = Zeroizing::new(format!("{}:{}", username, password.as_str()));
+                let encoded = base64::engine::general_purpose::STANDARD.encode(credentials.as_bytes());
+                request.set("Authorization", &format!("Basic {}", encoded))
+            }
+            WebDavAuth::Bearer(token) => request.set("Authorization", &format!("Bearer {}", token.as_str())),
+        }
+    }
+
+    /// ETag of a file in the collection, `None` if it doesn't exist
+    fn etag(&self, name: &str) -> Result<Option<String>> {
+        let response = self
+            .request("PROPFIND", name)
+            .set("Depth", "0")
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_ETAG);
+        match response {
+            Ok(response) => {
+                let body = response.into_string()?;
+                Ok(Some(element_text(&body, "getetag").unwrap_or_default()))
+            }
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(self.error("PROPFIND", name, e)),
+        }
+    }
+
+    /// Lock the vault file, or `None` if the server doesn't support locking
+    fn lock(&self) -> Result<Option<DavLock<'_>>> {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?><d:lockinfo xmlns:d="DAV:"><d:lockscope><d:exclusive/></d:lockscope><d:locktype><d:write/></d:locktype><d:owner>clawbox</d:owner></d:lockinfo>"#;
+        let response = self
+            .request("LOCK", VAULT_FILE)
+            .set("Depth", "0")
+            .set("Timeout", &format!("Second-{}", LOCK_TIMEOUT))
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(body);
+        match response {
+            Ok(response) => {
+                let token = response
+                    .header("Lock-Token")
+                    .map(|token| token.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+                    .filter(|token| !token.is_empty())
+                    .ok_or_else(|| Error::Other("WebDAV server granted a lock without a token".to_string()))?;
+                Ok(Some(DavLock { backend: self, token }))
+            }
+            Err(ureq::Error::Status(423, _)) => {
+                Err(Error::Other("Another device is syncing; try again in a few minutes".to_string()))
+            }
+            // No locking (class 2) support
+            Err(ureq::Error::Status(405 | 501, _)) => Ok(None),
+            Err(e) => Err(self.error("LOCK", VAULT_FILE, e)),
+        }
+    }
+
+    /// Create the collection if it doesn't exist yet
+    fn ensure_collection(&self) -> Result<()> {
+        match self.request("MKCOL", "").call() {
+            // 405: it already exists
+            Ok(_) | Err(ureq::Error::Status(405, _)) => Ok(()),
+            Err(e) => Err(self.error("MKCOL", "", e)),
+        }
+    }
+
+    fn put(&self, name: &str, data: &[u8], precondition: Option<(&str, &str)>, lock: Option<&DavLock>) -> Result<()> {
+        let mut request = self.request("PUT", name).set("Content-Type", "application/octet-stream");
+        if let Some((header, value)) = precondition {
+            request = request.set(header, value);
+        }
+        if let Some(lock) = lock.filter(|_| name == VAULT_FILE) {
+            request = request.set("If", &format!("(<{}>)", lock.token));
+        }
+        match request.send_bytes(data) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(412, _)) => Err(Error::RemoteChanged),
+            Err(e) => Err(self.error("PUT", name, e)),
+        }
+    }
+
+    /// Translate a failed request into a vault error
+    fn error(&self, method: &str, name: &str, err: ureq::Error) -> Error {
+        let url = format!("{}{}", self.base, name);
+        match err {
+            ureq::Error::Status(401 | 403, _) => Error::AccessDenied {
+                reason: format!("WebDAV server refused the credentials for {}", url),
+            },
+            ureq::Error::Status(404, _) => Error::Other(format!("No remote vault found at {}", url)),
+            ureq::Error::Status(507, _) => Error::Io(std::io::Error::new(
+                std::io::ErrorKind::StorageFull,
+                format!("WebDAV server is out of space (507) storing {}", url),
+            )),
+            ureq::Error::Status(code, _) => {
+                Error::Io(std::io::Error::other(format!("WebDAV {} {} failed: HTTP {}", method, url, code)))
+            }
+            ureq::Error::Transport(transport) => {
+                Error::Io(std::io::Error::other(format!("WebDAV {} {} failed: {}", method, url, transport)))
+            }
+        }
+    }
+}
+
+impl SyncBackend for WebDavBackend {
+    fn is_connected(&self) -> bool {
+        self.request("PROPFIND", "")
+            .set("Depth", "0")
+            .set("Content-Type", "application/xml; charset=utf-8")
+            .send_string(PROPFIND_ETAG)
+            .is_ok()
+    }
+
+    fn get_remote_version(&self) -> Result<u64> {
+        match self.request("GET", META_FILE).call() {
+            Ok(response) => Ok(response.into_string()?.trim().parse().unwrap_or(0)),
+            Err(ureq::Error::Status(404, _)) => Ok(0),
+            Err(e) => Err(self.error("GET", META_FILE, e)),
+        }
+    }
+
+    fn upload(&self, data: &[u8], version: u64) -> Result<()> {
+        self.ensure_collection()?;
+        let lock = self.lock()?;
+        // Someone pushed since the caller read the version
+        if self.get_remote_version()? >= version {
+            return Err(Error::RemoteChanged);
+        }
+
+        // Only replace the file we saw (servers without an ETag get no check)
+        let etag = self.etag(VAULT_FILE)?;
+        let precondition = match &etag {
+            Some(etag) if !etag.is_empty() => Some(("If-Match", etag.as_str())),
+            Some(_) => None,
+            None => Some(("If-None-Match", "*")),
+        };
+        // The version goes last: a reader that sees it also sees the data
+        self.put(VAULT_FILE, data, precondition, lock.as_ref())?;
+        self.put(META_FILE, version.to_string().as_bytes(), None, lock.as_ref())
+    }
+
+    fn download(&self) -> Result<(Vec<u8>, u64)> {
+        let _lock = self.lock()?;
+        let response = self
+            .request("GET", VAULT_FILE)
+            .call()
+            .map_err(|e| self.error("GET", VAULT_FILE, e))?;
+        let mut data = vec![];
+        response.into_reader().take(MAX_DOWNLOAD).read_to_end(&mut data)?;
+        Ok((data, self.get_remote_version()?))
+    }
+}
+
+/// Text of the first `<name>` element in any namespace prefix
+fn element_text(xml: &str, name: &str) -> Option<String> {
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>')?;
+        let tag = &rest[..end];
+        let local = tag.split_whitespace().next().unwrap_or("").trim_end_matches('/');
+        let local = local.rsplit(':').next().unwrap_or(local);
+        if local == name && !tag.starts_with('/') {
+            if tag.ends_with('/') {
+                return Some(String::new());
+            }
+            let body = &rest[end + 1..];
+            let close = body.find("</")?;
+            return Some(body[..close].trim().to_string());
+        }
+        rest = &rest[end + 1..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Just enough of a WebDAV server to stand in for Nextcloud
+    #[derive(Default)]
+    struct Server {
+        files: HashMap<String, (Vec<u8>, u32)>,
+        lock: Option<String>,
+        supports_locks: bool,
+        /// Expected `Authorization` header
+        auth: Option<String>,
+        full: bool,
+        methods: Vec<String>,
+    }
+
+    impl Server {
+        fn handle(&mut self, method: &str, path: &str, headers: &HashMap<String, String>, body: Vec<u8>) -> (u16, Vec<(String, String)>, Vec<u8>) {
+            self.methods.push(method.to_string());
+            if self.auth.is_some() && headers.get("authorization") != self.auth.as_ref() {
+                return (401, vec![], vec![]);
+            }
+            let name = path.trim_start_matches("/dav/").to_string();
+            let etag = |file: &(Vec<u8>, u32)| format!("\"v{}\"", file.1);
+            match method {
+                "PROPFIND" if name.is_empty() => (207, vec![], b"<d:multistatus xmlns:d=\"DAV:\"/>".to_vec()),
+                "PROPFIND" => match self.files.get(&name) {
+                    Some(file) => {
+                        let xml = format!("<d:multistatus xmlns:d=\"DAV:\"><d:response><d:propstat><d:prop><d:getetag>{}</d:getetag></d:prop></d:propstat></d:response></d:multistatus>", etag(file));
+                        (207, vec![], xml.into_bytes())
+                    }
+                    None => (404, vec![], vec![]),
+                },
+                "MKCOL" => (405, vec![], vec![]),
+                "LOCK" if !self.supports_locks => (405, vec![], vec![]),
+                "LOCK" if self.lock.is_some() => (423, vec![], vec![]),
+                "LOCK" => {
+                    self.lock = Some("opaquelocktoken:1".to_string());
+                    (200, vec![("Lock-Token".into(), "<opaquelocktoken:1>".into())], vec![])
+                }
+                "UNLOCK" => {
+                    self.lock = None;
+                    (204, vec![], vec![])
+                }
+                "GET" => match self.files.get(&name) {
+                    Some(file) => (200, vec![], file.0.clone()),
+                    None => (404, vec![], vec![]),
+                },
+                "PUT" => {
+                    if self.full {
+                        return (507, vec![], vec![]);
+                    }
+                    if name == VAULT_FILE {
+                        if let Some(token) = &self.lock {
+                            if headers.get("if") != Some(&format!("(<{}>)", token)) {
+                                return (423, vec![], vec![]);
+                            }
+                        }
+                    }
+                    let current = self.files.get(&name);
+                    let precondition_failed = match (headers.get("if-match"), headers.get("if-none-match"), current) {
+                        (Some(expected), _, Some(file)) => *expected != etag(file),
+                        (Some(_), _, None) => true,
+                        (_, Some(_), Some(_)) => true,
+                        _ => false,
+                    };
+                    if precondition_failed {
+                        return (412, vec![], vec![]);
+                    }
+                    let generation = current.map_or(1, |file| file.1 + 1);
+                    self.files.insert(name, (body, generation));
+                    (201, vec![], vec![])
+                }
+                _ => (501, vec![], vec![]),
+            }
+        }
+    }
+
+    /// Serve `server` on a local port and return the collection URL
+    fn serve(server: Arc<Mutex<Server>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/dav", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let (method, path) = (parts.next().unwrap().to_string(), parts.next().unwrap().to_string());
+                let mut headers = HashMap::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(':').unwrap();
+                    headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+                }
+                let length = headers.get("content-length").map_or(0, |len| len.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, extra, body) = server.lock().unwrap().handle(&method, &path, &headers, body);
+                let mut response = format!("HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+                for (name, value) in extra {
+                    response.push_str(&format!("{}: {}\r\n", name, value));
+                }
+                response.push_str("\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_round_trip_with_locks() {
+        let server = Arc::new(Mutex::new(Server { supports_locks: true, ..Default::default() }));
+        let backend = WebDavBackend::new(&serve(server.clone()), WebDavAuth::Bearer(Zeroizing::new("t0ken".into())));
+        server.lock().unwrap().auth = Some("Bearer t0ken".into());
+
+        assert!(backend.is_connected());
+        assert_eq!(backend.get_remote_version().unwrap(), 0);
+        assert!(backend.download().unwrap_err().to_string().contains("No remote vault found"));
+        backend.upload(b"sealed-1", 1).unwrap();
+        backend.upload(b"sealed-2", 2).unwrap();
+        assert_eq!(backend.download().unwrap(), (b"sealed-2".to_vec(), 2));
+        assert!(server.lock().unwrap().lock.is_none());
+        assert!(server.lock().unwrap().methods.iter().filter(|m| *m == "LOCK").count() >= 3);
+
+        // A stale version means another device pushed in between
+        assert!(matches!(backend.upload(b"sealed-x", 2), Err(Error::RemoteChanged)));
+
+        server.lock().unwrap().lock = Some("opaquelocktoken:other".into());
+        assert!(backend.upload(b"sealed-3", 3).unwrap_err().to_string().contains("is syncing"));
+    }
+
+    #[test]
+    fn test_errors_and_servers_without_locks() {
+        let server = Arc::new(Mutex::new(Server::default()));
+        let url = serve(server.clone());
+        let auth = || WebDavAuth::Basic { username: "me".into(), password: Zeroizing::new("pw".into()) };
+        let backend = WebDavBackend::new(&url, auth());
+        backend.upload(b"sealed-1", 1).unwrap();
+        assert_eq!(backend.download().unwrap().1, 1);
+
+        server.lock().unwrap().auth = Some("Basic bWU6cHc=".into());
+        let wrong = WebDavBackend::new(&url, WebDavAuth::None);
+        assert!(!wrong.is_connected());
+        assert!(matches!(wrong.get_remote_version(), Err(Error::AccessDenied { .. })));
+        assert_eq!(backend.get_remote_version().unwrap(), 1);
+
+        server.lock().unwrap().full = true;
+        match backend.upload(b"sealed-2", 2) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::StorageFull),
+            other => panic!("expected a storage error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_element_text() {
+        assert_eq!(element_text("<D:prop><D:getetag>\"abc\"</D:getetag></D:prop>", "getetag").as_deref(), Some("\"abc\""));
+        assert_eq!(element_text("<prop><getetag/></prop>", "getetag").as_deref(), Some(""));
+        assert_eq!(element_text("<prop/>", "getetag"), None);
+    }
+}
@@ -0,0 +1,534 @@
+/*
+ * ClawBox C API
+ *
+ * Keep in sync with crates/clawbox-ffi/src/lib.rs.
+ */
+
+#ifndef CLAWBOX_H
+#define CLAWBOX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Define CLAWBOX_ALLOW_DEPRECATED to silence deprecation warnings */
+#if defined(__GNUC__) && !defined(CLAWBOX_ALLOW_DEPRECATED)
+#define CLAWBOX_DEPRECATED(msg) __attribute__((deprecated(msg)))
+#else
+#define CLAWBOX_DEPRECATED(msg)
+#endif
+
+/*
+ * Version of the C ABI described by this header. Check it against
+ * clawbox_abi_version() at startup: a mismatch means the library was built
+ * from a different header.
+ */
+#define CLAWBOX_ABI_VERSION 11
+
+/* Error codes */
+#define CLAWBOX_OK 0
+#define CLAWBOX_ERR_VAULT_LOCKED 1
+#define CLAWBOX_ERR_INVALID_PASSWORD 2
+#define CLAWBOX_ERR_NOT_FOUND 3
+#define CLAWBOX_ERR_IO 4
+/* Someone else is writing: the vault database, or the remote vault during a sync
+   (including a synced copy still being uploaded, or not yet downloaded from
+   iCloud); retry */
+#define CLAWBOX_ERR_BUSY 5
+#define CLAWBOX_ERR_NOT_INITIALIZED 6
+#define CLAWBOX_ERR_ALREADY_INITIALIZED 7
+#define CLAWBOX_ERR_ACCESS_DENIED 8
+#define CLAWBOX_ERR_APPROVAL_TIMEOUT 9
+/* Null, malformed or out-of-range argument, including invalid secret paths */
+#define CLAWBOX_ERR_INVALID_ARG 10
+/* Stored data that cannot be decrypted or parsed with the right key */
+#define CLAWBOX_ERR_CORRUPT 11
+#define CLAWBOX_ERR_CRYPTO 12
+#define CLAWBOX_ERR_DATABASE 13
+#define CLAWBOX_ERR_ICLOUD_UNAVAILABLE 14
+/* The destination path of a rename or copy is taken */
+#define CLAWBOX_ERR_EXISTS 15
+/* An asynchronous unlock was abandoned */
+#define CLAWBOX_ERR_CANCELLED 16
+/*
+ * A string argument, or a value read through a string function, is not valid
+ * UTF-8; use the *_bytes variants for arbitrary data
+ */
+#define CLAWBOX_ERR_INVALID_UTF8 17
+/* The secret's access level needs the master password again; read it with
+   clawbox_get_with_reauth */
+#define CLAWBOX_ERR_REAUTH_REQUIRED 18
+/* Too many wrong passwords in a row; clawbox_unlock_retry_after says when
+   unlocking may be tried again */
+#define CLAWBOX_ERR_TOO_MANY_ATTEMPTS 19
+/* The actor read as many secrets of this access level in the last minute as
+   the vault's rate limit allows; retry later */
+#define CLAWBOX_ERR_RATE_LIMITED 20
+/* The handle was opened with CLAWBOX_OPEN_READ_ONLY */
+#define CLAWBOX_ERR_READ_ONLY 21
+/* The caller's buffer can't hold the value; the size it needs was written
+   to out_written */
+#define CLAWBOX_ERR_BUFFER_TOO_SMALL 22
+#define CLAWBOX_ERR_UNKNOWN (-1)
+
+/*
+ * Functions returning a count, flag or version return -1 on failure instead
+ * of an error code; the code is then available from clawbox_last_error_code.
+ *
+ * Details of the most recent failure on the calling thread.
+ *
+ * The code is CLAWBOX_OK and the message NULL if nothing has failed on this
+ * thread yet. The message is owned by the library, stays valid until the
+ * next clawbox_* call on the same thread, and must not be freed.
+ */
+int clawbox_last_error_code(void);
+const char *clawbox_last_error_message(void);
+
+/*
+ * Opaque handle to a vault.
+ *
+ * A handle may be used from several threads at once; calls on it are
+ * serialized internally. Callbacks must not call back into the handle that
+ * invoked them.
+ */
+typedef struct ClawBoxHandle ClawBoxHandle;
+
+/* Progress callback: (done, total, context) */
+typedef void (*ClawBoxProgressCallback)(size_t done, size_t total, void *context);
+
+/* Completion callback of clawbox_unlock_async: (result, context) */
+typedef void (*ClawBoxUnlockCallback)(int result, void *context);
+
+/*
+ * Access levels. Writes reject any other value with CLAWBOX_ERR_INVALID_ARG
+ * rather than store a secret with weaker protection than intended.
+ */
+#define CLAWBOX_ACCESS_PUBLIC 0
+#define CLAWBOX_ACCESS_NORMAL 1
+#define CLAWBOX_ACCESS_SENSITIVE 2
+#define CLAWBOX_ACCESS_CRITICAL 3
+
+/* Approval callback: (path, actor, access_level, context), returning a
+ * CLAWBOX_APPROVAL_* value; anything else counts as a denial */
+typedef int (*ClawBoxApprovalCallback)(const char *path, const char *actor, int access_level, void *context);
+
+#define CLAWBOX_APPROVAL_DENY 0
+#define CLAWBOX_APPROVAL_APPROVE 1
+#define CLAWBOX_APPROVAL_TIMEOUT 2
+
+/* Strings owned by the caller; release with clawbox_free_string_array */
+typedef struct ClawBoxStringArray {
+    /* len strings (NULL when empty) */
+    char **ptr;
+    size_t len;
+} ClawBoxStringArray;
+
+/* Metadata of one secret, as returned by clawbox_list */
+typedef struct ClawBoxSecretInfo {
+    /* Secret path */
+    char *path;
+    /* Access level (a CLAWBOX_ACCESS_* value) */
+    int access_level;
+    /* Creation time (Unix seconds) */
+    int64_t created_at;
+    /* Last update time (Unix seconds) */
+    int64_t updated_at;
+    /* Number of entries in tags */
+    size_t tag_count;
+    /* Tags (NULL when tag_count is 0) */
+    char **tags;
+} ClawBoxSecretInfo;
+
+/*
+ * Full metadata of one secret, as returned by clawbox_get_info.
+ *
+ * Set struct_size to sizeof(ClawBoxSecretDetails) before the call. Fields
+ * added in later versions go at the end and are only written when
+ * struct_size covers them.
+ */
+typedef struct ClawBoxSecretDetails {
+    /* Size of the struct as the caller knows it */
+    size_t struct_size;
+    /* Secret path */
+    char *path;
+    /* Access level (a CLAWBOX_ACCESS_* value) */
+    int access_level;
+    /* Creation time (Unix seconds) */
+    int64_t created_at;
+    /* Last update time (Unix seconds) */
+    int64_t updated_at;
+    /* Expiry time (Unix seconds), 0 if the secret never expires */
+    int64_t expires_at;
+    /* Size of the value in bytes */
+    size_t size;
+    /* Note (NULL if none) */
+    char *note;
+    /* Number of entries in tags */
+    size_t tag_count;
+    /* Tags (NULL when tag_count is 0) */
+    char **tags;
+} ClawBoxSecretDetails;
+
+/*
+ * Options for writing a secret; pass NULL for defaults.
+ *
+ * Set struct_size to sizeof(ClawBoxSetOptions).
+ */
+typedef struct ClawBoxSetOptions {
+    /* Size of the struct as the caller knows it */
+    size_t struct_size;
+    /* Access level (a CLAWBOX_ACCESS_* value) */
+    int access_level;
+    /* Seconds until the secret expires, 0 for never */
+    uint64_t ttl_seconds;
+    /* Note (may be NULL) */
+    const char *note;
+    /* Number of entries in tags */
+    size_t tag_count;
+    /* Tags (may be NULL when tag_count is 0) */
+    const char *const *tags;
+} ClawBoxSetOptions;
+
+/* Options for clawbox_export; pass NULL for defaults */
+typedef struct ClawBoxExportOptions {
+    /* Size of the struct as the caller knows it */
+    size_t struct_size;
+    /* Non-zero to include Critical secrets, which are left out by default */
+    int include_critical;
+    /*
+     * Master password, read as by clawbox_get_with_reauth; needed when a
+     * secret included requires reauthentication (Critical ones, by default),
+     * otherwise may be NULL
+     */
+    const uint8_t *password;
+    size_t password_len;
+} ClawBoxExportOptions;
+
+/* Options for clawbox_generate_password; pass NULL for 24 characters of every class */
+typedef struct ClawBoxGeneratorOptions {
+    /* Size of the struct as the caller knows it */
+    size_t struct_size;
+    /* Number of characters */
+    size_t length;
+    /* CLAWBOX_GEN_* flags */
+    uint32_t flags;
+} ClawBoxGeneratorOptions;
+
+#define CLAWBOX_GEN_UPPERCASE (1u << 0)
+#define CLAWBOX_GEN_LOWERCASE (1u << 1)
+#define CLAWBOX_GEN_DIGITS (1u << 2)
+#define CLAWBOX_GEN_SYMBOLS (1u << 3)
+/* Leave out easily misread characters such as l, 1 and O */
+#define CLAWBOX_GEN_EXCLUDE_AMBIGUOUS (1u << 4)
+
+/*
+ * Vault statistics, as returned by clawbox_stats. Set struct_size to
+ * sizeof(ClawBoxStats) before the call.
+ */
+typedef struct ClawBoxStats {
+    /* Size of the struct as the caller knows it */
+    size_t struct_size;
+    /* Number of secrets per access level */
+    size_t public_count;
+    size_t normal_count;
+    size_t sensitive_count;
+    size_t critical_count;
+    /* Size of the database files on disk in bytes */
+    uint64_t db_size;
+    /* Number of audit log entries */
+    size_t audit_entries;
+    /* Time of the most recent write (Unix seconds), 0 if there are no secrets */
+    int64_t last_write;
+} ClawBoxStats;
+
+/* clawbox_import conflict modes */
+#define CLAWBOX_CONFLICT_OVERWRITE 0
+#define CLAWBOX_CONFLICT_SKIP 1
+
+/* Static string, do not free */
+const char *clawbox_version(void);
+int clawbox_abi_version(void);
+
+/*
+ * Schema version of an open vault (-1 on error), and the newest this
+ * library supports; a vault above that was written by a newer build.
+ */
+int clawbox_core_schema_version(ClawBoxHandle *handle);
+int clawbox_supported_schema_version(void);
+
+/* clawbox_open_ex flags */
+#define CLAWBOX_OPEN_ALLOW_RAW_KEY 1u
+/*
+ * Open an existing vault without ever writing to vault.db: writes fail with
+ * CLAWBOX_ERR_READ_ONLY, audit entries are appended to
+ * audit-read-only.jsonl next to it
+ */
+#define CLAWBOX_OPEN_READ_ONLY 2u
+
+/* Length of a raw vault key in bytes */
+#define CLAWBOX_KEY_LEN 32
+
+/* Returns NULL on failure */
+ClawBoxHandle *clawbox_open(const char *path);
+ClawBoxHandle *clawbox_open_ex(const char *path, uint32_t flags);
+void clawbox_close(ClawBoxHandle *handle);
+
+/* 1 or 0, or -1 on failure (see clawbox_last_error_code) */
+int clawbox_is_initialized(ClawBoxHandle *handle);
+/*
+ * Passwords are read from caller-owned UTF-8 buffers (not null-terminated).
+ * ClawBox copies them into memory it wipes before returning and keeps no
+ * other copy; wiping the buffer afterwards is up to the caller.
+ */
+int clawbox_init_bytes(ClawBoxHandle *handle, const uint8_t *password, size_t len);
+/* CLAWBOX_ERR_NOT_INITIALIZED if the vault has no master password yet */
+int clawbox_unlock_bytes(ClawBoxHandle *handle, const uint8_t *password, size_t len);
+/*
+ * Derives the key on an internal thread and returns at once. CLAWBOX_OK means
+ * the attempt started and callback will be called exactly once, on that
+ * thread, with the result; until then every other call on the handle fails
+ * with CLAWBOX_ERR_VAULT_LOCKED. clawbox_cancel_unlock, clawbox_lock and
+ * clawbox_close abandon the attempt: the callback then gets
+ * CLAWBOX_ERR_CANCELLED, possibly after the handle was freed, so context must
+ * outlive it.
+ */
+int clawbox_unlock_async(ClawBoxHandle *handle, const uint8_t *password, size_t len,
+                         ClawBoxUnlockCallback callback, void *context);
+int clawbox_cancel_unlock(ClawBoxHandle *handle);
+/*
+ * After 5 wrong passwords in a row, unlocking fails with
+ * CLAWBOX_ERR_TOO_MANY_ATTEMPTS without trying the password until a cooldown
+ * ends; it starts at 30 seconds and doubles with every further wrong
+ * password. Writes the seconds left, rounded up, or 0 if unlocking may be
+ * tried now. Works while locked.
+ */
+int clawbox_unlock_retry_after(const ClawBoxHandle *handle, uint64_t *out_seconds);
+/*
+ * Raw key access for Touch ID / Secure Enclave flows; both need a handle
+ * opened with CLAWBOX_OPEN_ALLOW_RAW_KEY, take exactly CLAWBOX_KEY_LEN bytes
+ * and are audited. clawbox_export_key requires an unlocked vault and writes
+ * into the caller's buffer, which the caller should wipe after use.
+ */
+int clawbox_unlock_with_key(ClawBoxHandle *handle, const uint8_t *key, size_t key_len);
+int clawbox_export_key(ClawBoxHandle *handle, uint8_t *out, size_t out_len);
+void clawbox_lock(ClawBoxHandle *handle);
+int clawbox_is_unlocked(const ClawBoxHandle *handle);
+
+/*
+ * Lock automatically after seconds without a successful call (0 disables).
+ * The timer runs on an internal thread; polling clawbox_is_unlocked or
+ * clawbox_time_until_lock does not restart it. Auto-locks are audited with
+ * the "system" actor. clawbox_time_until_lock rounds up and returns 0 when
+ * auto-lock is off or the vault is locked, or -1 on failure.
+ */
+int clawbox_set_auto_lock(ClawBoxHandle *handle, uint32_t seconds);
+int clawbox_time_until_lock(const ClawBoxHandle *handle);
+
+/* CLAWBOX_ERR_INVALID_PASSWORD for a wrong old password, CLAWBOX_ERR_BUSY if
+ * another process holds the vault lock; progress may be NULL */
+int clawbox_change_password_bytes(ClawBoxHandle *handle, const uint8_t *old_password, size_t old_len,
+                                  const uint8_t *new_password, size_t new_len,
+                                  ClawBoxProgressCallback progress, void *context);
+
+/* Null-terminated variants, kept for compatibility */
+CLAWBOX_DEPRECATED("use clawbox_init_bytes")
+int clawbox_init(ClawBoxHandle *handle, const char *password);
+CLAWBOX_DEPRECATED("use clawbox_unlock_bytes")
+int clawbox_unlock(ClawBoxHandle *handle, const char *password);
+CLAWBOX_DEPRECATED("use clawbox_change_password_bytes")
+int clawbox_change_password(ClawBoxHandle *handle, const char *old_password, const char *new_password);
+CLAWBOX_DEPRECATED("use clawbox_change_password_bytes")
+int clawbox_change_password_with_progress(ClawBoxHandle *handle, const char *old_password,
+                                          const char *new_password, ClawBoxProgressCallback progress,
+                                          void *context);
+
+/*
+ * The returned value must be released with clawbox_free_string, which wipes
+ * it (as clawbox_free_bytes does) before freeing
+ */
+int clawbox_get(ClawBoxHandle *handle, const char *path, char **out_value);
+void clawbox_free_string(char *s);
+
+/*
+ * Like clawbox_get, checking the master password first; needed for Critical
+ * secrets, which clawbox_get refuses with CLAWBOX_ERR_REAUTH_REQUIRED
+ */
+int clawbox_get_with_reauth(ClawBoxHandle *handle, const char *path, const uint8_t *password,
+                            size_t password_len, char **out_value);
+
+/*
+ * Read on behalf of an AI agent. Sensitive secrets need the approval
+ * callback's consent (denied when none is set); Critical ones are refused.
+ */
+int clawbox_get_as_agent(ClawBoxHandle *handle, const char *path, const char *agent, char **out_value);
+
+/*
+ * Act as actor ("human", "human:<name>", "ai:<agent>" or "app:<name>"; NULL
+ * for the local human) on this handle. Later reads are checked as by
+ * clawbox_get_as_agent and audit entries name the actor.
+ */
+int clawbox_set_actor(ClawBoxHandle *handle, const char *actor);
+
+/*
+ * callback may be NULL to remove it. It runs on the reading thread, one call
+ * at a time per handle, and must not call back into the same handle; context
+ * must be usable from any thread.
+ */
+int clawbox_set_approval_callback(ClawBoxHandle *handle, ClawBoxApprovalCallback callback, void *context);
+int clawbox_set(ClawBoxHandle *handle, const char *path, const char *value, int access_level);
+int clawbox_delete(ClawBoxHandle *handle, const char *path);
+
+/* clawbox_delete_ex flags */
+#define CLAWBOX_DELETE_HARD 1u
+
+/*
+ * CLAWBOX_DELETE_HARD also erases the secret's history and vacuums the
+ * database, so the value cannot be recovered or rolled back (this also works
+ * on a secret already deleted without it). Unknown flags are
+ * CLAWBOX_ERR_INVALID_ARG.
+ */
+int clawbox_delete_ex(ClawBoxHandle *handle, const char *path, uint32_t flags);
+
+/* Binary-safe variants; release the value with clawbox_free_bytes */
+int clawbox_get_bytes(ClawBoxHandle *handle, const char *path, uint8_t **out_ptr, size_t *out_len);
+void clawbox_free_bytes(uint8_t *ptr, size_t len);
+int clawbox_set_bytes(ClawBoxHandle *handle, const char *path, const uint8_t *value_ptr, size_t value_len,
+                      const ClawBoxSetOptions *options);
+
+/*
+ * Read into a buffer the caller owns and can reuse across calls, instead of
+ * one allocated per value. clawbox_get_into writes the value and a
+ * terminating NUL, clawbox_get_bytes_into just the value; *out_written is
+ * its length without the NUL. If buf_len is too small nothing is written to
+ * buf, *out_written is the buf_len needed and the call fails with
+ * CLAWBOX_ERR_BUFFER_TOO_SMALL; the read is still audited. buf may be NULL
+ * when buf_len is 0. Wipe the buffer once done with the value.
+ */
+int clawbox_get_into(ClawBoxHandle *handle, const char *path, char *buf, size_t buf_len, size_t *out_written);
+int clawbox_get_bytes_into(ClawBoxHandle *handle, const char *path, uint8_t *buf, size_t buf_len,
+                           size_t *out_written);
+
+/*
+ * Random passwords, at least one character from every enabled class. A zero
+ * length, no enabled class or a length above 4096 is CLAWBOX_ERR_INVALID_ARG.
+ * Release values with clawbox_free_string. clawbox_generate_and_set stores the
+ * password directly; out_value may be NULL if the caller does not need it.
+ */
+int clawbox_generate_password(const ClawBoxGeneratorOptions *options, char **out_value);
+int clawbox_generate_and_set(ClawBoxHandle *handle, const char *path, const ClawBoxGeneratorOptions *options,
+                             const ClawBoxSetOptions *set_options, char **out_value);
+
+/*
+ * Delete everything under a literal path prefix in one transaction. An empty
+ * prefix is rejected; clawbox_delete_all empties the vault. With strict
+ * non-zero, no match is CLAWBOX_ERR_NOT_FOUND. out_deleted may be NULL.
+ */
+int clawbox_delete_prefix(ClawBoxHandle *handle, const char *prefix, int strict, size_t *out_deleted);
+int clawbox_delete_all(ClawBoxHandle *handle, size_t *out_deleted);
+
+/*
+ * Rename keeps the secret's history. A taken destination is CLAWBOX_ERR_EXISTS
+ * (for copy, unless overwrite is non-zero); a missing source is
+ * CLAWBOX_ERR_NOT_FOUND.
+ */
+int clawbox_rename(ClawBoxHandle *handle, const char *old_path, const char *new_path);
+int clawbox_copy(ClawBoxHandle *handle, const char *src, const char *dst, int overwrite);
+
+/*
+ * Paths only; pattern may be NULL for all secrets. Every string and the array
+ * belong to the caller: release them with clawbox_free_string_array, which
+ * resets the struct so a second free is harmless.
+ */
+int clawbox_list_paths(ClawBoxHandle *handle, const char *pattern, ClawBoxStringArray *out_array);
+void clawbox_free_string_array(ClawBoxStringArray *array);
+
+/* pattern may be NULL for all secrets; release with clawbox_free_secret_list */
+int clawbox_list(ClawBoxHandle *handle, const char *pattern, ClawBoxSecretInfo **out_items, size_t *out_count);
+void clawbox_free_secret_list(ClawBoxSecretInfo *items, size_t count);
+
+/* Release the strings with clawbox_free_secret_info; the struct stays yours */
+int clawbox_get_info(ClawBoxHandle *handle, const char *path, ClawBoxSecretDetails *out_info);
+void clawbox_free_secret_info(ClawBoxSecretDetails *info);
+
+/*
+ * Audit log. filter_json is NULL or an object with any of "key", "since_unix",
+ * "limit" and "action"; the result is a JSON array (newest first) released
+ * with clawbox_free_string. Querying requires an unlocked vault.
+ */
+int clawbox_audit_query(ClawBoxHandle *handle, const char *filter_json, char **out_json);
+/* 1 if the hash chain is intact, 0 if not, or -1 on failure; works while locked */
+int clawbox_audit_verify(ClawBoxHandle *handle);
+
+/*
+ * Vault health. clawbox_stats reads aggregates only and works while locked.
+ * clawbox_verify runs every integrity check (value decryption only while
+ * unlocked) and returns CLAWBOX_OK even when it finds problems: the report is
+ * a JSON object whose "ok" field is the verdict, alongside "database_errors",
+ * "audit_chain_valid", "values_checked", "undecryptable", "schema_version"
+ * and "supported_schema_version". Release it with clawbox_free_string.
+ */
+int clawbox_stats(ClawBoxHandle *handle, ClawBoxStats *out_stats);
+int clawbox_verify(ClawBoxHandle *handle, char **out_report_json);
+
+/*
+ * Encrypted backup bundles, protected by their own passphrase. Release the
+ * exported bytes with clawbox_free_bytes. out_imported may be NULL.
+ */
+int clawbox_export(ClawBoxHandle *handle, const char *passphrase, const ClawBoxExportOptions *options,
+                   uint8_t **out_bytes, size_t *out_len);
+int clawbox_import(ClawBoxHandle *handle, const uint8_t *bytes, size_t len, const char *passphrase,
+                   int conflict_mode, size_t *out_imported);
+
+/*
+ * iCloud sync. Status works while locked and reports available = 0 without
+ * iCloud Drive; push and pull need an unlocked vault and fail with
+ * CLAWBOX_ERR_ICLOUD_UNAVAILABLE instead. Pull backs the local database up
+ * first (see clawbox_list_backups), and leaves the vault locked if the
+ * pulled copy has a different master password.
+ */
+int clawbox_sync_status(ClawBoxHandle *handle, uint64_t *out_local_version, uint64_t *out_remote_version,
+                        int *out_available);
+/*
+ * Sync state stored in the vault, as a JSON object with "enabled",
+ * "last_sync" (RFC 3339 or null), "local_version", "remote_version" and
+ * "conflicts". Works while locked; release it with clawbox_free_string.
+ */
+int clawbox_sync_state(ClawBoxHandle *handle, char **out_state_json);
+/* Turn sync on (enabled != 0) or off; the setting is stored in the vault */
+int clawbox_sync_set_enabled(ClawBoxHandle *handle, int enabled);
+int clawbox_sync_push(ClawBoxHandle *handle);
+int clawbox_sync_pull(ClawBoxHandle *handle);
+
+/*
+ * Backups of the local database taken before pulls, as a JSON array of
+ * {"name", "created_at" (RFC 3339), "size"}, newest first. Works while
+ * locked; release it with clawbox_free_string. Restoring needs the vault
+ * unlocked, keeps the replaced database as a new backup and fails with
+ * CLAWBOX_ERR_INVALID_ARG for an unknown name or a file that doesn't open
+ * as a vault.
+ */
+int clawbox_list_backups(ClawBoxHandle *handle, char **out_backups_json);
+int clawbox_restore_backup(ClawBoxHandle *handle, const char *name);
+
+/*
+ * Opaque blobs in the macOS Keychain, e.g. the key from clawbox_export_key
+ * for a "remember until quit" option. Items are readable only while the Mac
+ * is unlocked and never leave this device. Fetch fails with
+ * CLAWBOX_ERR_NOT_FOUND if there is no item; release the blob with
+ * clawbox_free_bytes. Delete removes one account, or every item stored
+ * through this API when account is NULL, and returns 1 if anything was
+ * deleted, 0 if not, -1 on failure. All three fail with CLAWBOX_ERR_UNKNOWN
+ * on other platforms.
+ */
+int clawbox_keychain_store(const char *account, const uint8_t *data, size_t len);
+int clawbox_keychain_fetch(const char *account, uint8_t **out_ptr, size_t *out_len);
+int clawbox_keychain_delete(const char *account);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CLAWBOX_H */
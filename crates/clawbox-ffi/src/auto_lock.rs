@@ -0,0 +1,148 @@
+//! Idle auto-lock for vault handles
+//!
+//! Each handle with auto-lock enabled has a timer thread that locks the vault
+//! once no call has succeeded for the configured time. Successful calls reset
+//! the timer through `begin_call`/`end_call`, which bracket every call made
+//! through `catch`.
+
+use clawbox_core::ClawBox;
+use std::cell::RefCell;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Auto-lock state of one handle
+#[derive(Default)]
+pub(crate) struct AutoLock {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// `None` while disabled
+    timeout: Option<Duration>,
+    last_activity: Option<Instant>,
+    /// The timer fired and nothing has happened since
+    expired: bool,
+    shutdown: bool,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl State {
+    fn deadline(&self) -> Option<Instant> {
+        Some(self.last_activity? + self.timeout?)
+    }
+}
+
+thread_local! {
+    /// Auto-lock of the handle used by the call in progress on this thread
+    static CURRENT: RefCell<Option<Arc<AutoLock>>> = const { RefCell::new(None) };
+}
+
+/// Forget the handle of a previous call on this thread
+pub(crate) fn begin_call() {
+    CURRENT.with(|current| current.borrow_mut().take());
+}
+
+/// Note that the call in progress on this thread uses `auto_lock`'s handle
+pub(crate) fn enter(auto_lock: &Arc<AutoLock>) {
+    CURRENT.with(|current| *current.borrow_mut() = Some(auto_lock.clone()));
+}
+
+/// Reset the timer of the handle used by the call just made, if it succeeded
+pub(crate) fn end_call(success: bool) {
+    if let Some(auto_lock) = CURRENT.with(|current| current.borrow_mut().take()) {
+        if success {
+            auto_lock.touch();
+        }
+    }
+}
+
+impl AutoLock {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Set the idle timeout (`None` disables it), starting the timer thread
+    /// on first use
+    pub fn configure(self: &Arc<Self>, timeout: Option<Duration>, vault: &Arc<Mutex<ClawBox>>) -> std::io::Result<()> {
+        let mut state = self.state();
+        if timeout.is_some() && state.timer.is_none() {
+            let (auto_lock, vault) = (self.clone(), vault.clone());
+            state.timer = Some(
+                std::thread::Builder::new()
+                    .name("clawbox-auto-lock".into())
+                    .spawn(move || auto_lock.run(&vault))?,
+            );
+        }
+        state.timeout = timeout;
+        state.last_activity = Some(Instant::now());
+        state.expired = false;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// Restart the countdown
+    pub fn touch(&self) {
+        let mut state = self.state();
+        state.last_activity = Some(Instant::now());
+        state.expired = false;
+        self.changed.notify_all();
+    }
+
+    /// Time left before the vault locks, or `None` if auto-lock is disabled
+    pub fn remaining(&self) -> Option<Duration> {
+        let state = self.state();
+        let deadline = state.deadline()?;
+        Some(if state.expired { Duration::ZERO } else { deadline.saturating_duration_since(Instant::now()) })
+    }
+
+    /// Stop the timer thread and wait for it to exit
+    pub fn shutdown(&self) {
+        let timer = {
+            let mut state = self.state();
+            state.shutdown = true;
+            self.changed.notify_all();
+            state.timer.take()
+        };
+        if let Some(timer) = timer {
+            let _ = timer.join();
+        }
+    }
+
+    fn run(&self, vault: &Mutex<ClawBox>) {
+        let mut state = self.state();
+        loop {
+            if state.shutdown {
+                return;
+            }
+            let deadline = match state.deadline() {
+                Some(deadline) if !state.expired => deadline,
+                _ => {
+                    state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+                    continue;
+                }
+            };
+            let now = Instant::now();
+            if now < deadline {
+                state = self
+                    .changed
+                    .wait_timeout(state, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+                continue;
+            }
+
+            // Calls take the vault before the state; do the same, then make
+            // sure no call succeeded while we waited for the vault
+            drop(state);
+            let mut vault = vault.lock().unwrap_or_else(PoisonError::into_inner);
+            state = self.state();
+            if !state.expired && state.deadline().is_some_and(|deadline| deadline <= Instant::now()) {
+                vault.auto_lock();
+                state.expired = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Error codes and the per-thread last error
+//!
+//! Every failing call stores its code and a message in thread-local storage,
+//! so C callers can show more than a bare number.
+
+use crate::auto_lock;
+use libc::{c_char, c_int};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::ptr;
+
+/// Version of the C ABI; bumped whenever a `repr(C)` struct, error code or
+/// function signature changes incompatibly
+pub const CLAWBOX_ABI_VERSION: c_int = 11;
+
+/// Error codes
+pub const CLAWBOX_OK: c_int = 0;
+pub const CLAWBOX_ERR_VAULT_LOCKED: c_int = 1;
+pub const CLAWBOX_ERR_INVALID_PASSWORD: c_int = 2;
+pub const CLAWBOX_ERR_NOT_FOUND: c_int = 3;
+pub const CLAWBOX_ERR_IO: c_int = 4;
+pub const CLAWBOX_ERR_BUSY: c_int = 5;
+pub const CLAWBOX_ERR_NOT_INITIALIZED: c_int = 6;
+pub const CLAWBOX_ERR_ALREADY_INITIALIZED: c_int = 7;
+pub const CLAWBOX_ERR_ACCESS_DENIED: c_int = 8;
+pub const CLAWBOX_ERR_APPROVAL_TIMEOUT: c_int = 9;
+pub const CLAWBOX_ERR_INVALID_ARG: c_int = 10;
+pub const CLAWBOX_ERR_CORRUPT: c_int = 11;
+pub const CLAWBOX_ERR_CRYPTO: c_int = 12;
+pub const CLAWBOX_ERR_DATABASE: c_int = 13;
+pub const CLAWBOX_ERR_ICLOUD_UNAVAILABLE: c_int = 14;
+pub const CLAWBOX_ERR_EXISTS: c_int = 15;
+pub const CLAWBOX_ERR_CANCELLED: c_int = 16;
+pub const CLAWBOX_ERR_INVALID_UTF8: c_int = 17;
+pub const CLAWBOX_ERR_REAUTH_REQUIRED: c_int = 18;
+pub const CLAWBOX_ERR_TOO_MANY_ATTEMPTS: c_int = 19;
+pub const CLAWBOX_ERR_RATE_LIMITED: c_int = 20;
+pub const CLAWBOX_ERR_READ_ONLY: c_int = 21;
+pub const CLAWBOX_ERR_BUFFER_TOO_SMALL: c_int = 22;
+pub const CLAWBOX_ERR_UNKNOWN: c_int = -1;
+
+/// A failed FFI call
+#[derive(Debug)]
+pub(crate) struct FfiError {
+    pub code: c_int,
+    pub message: String,
+}
+
+impl FfiError {
+    pub fn new(code: c_int, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// A null or otherwise unusable argument
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(CLAWBOX_ERR_INVALID_ARG, message)
+    }
+
+    /// A string argument that is not valid UTF-8; the bytes variants take
+    /// arbitrary data
+    pub fn invalid_utf8(name: &str) -> Self {
+        Self::new(CLAWBOX_ERR_INVALID_UTF8, format!("{} is not valid UTF-8", name))
+    }
+}
+
+/// Error code for a core error
+///
+/// Lists every variant, so adding one to the core fails to compile until it
+/// has been given a code here (and in the header).
+pub(crate) fn error_to_code(err: &clawbox_core::Error) -> c_int {
+    use clawbox_core::Error;
+
+    match err {
+        Error::Database(_) if err.is_busy() => CLAWBOX_ERR_BUSY,
+        Error::RemoteChanged | Error::RemoteIncomplete | Error::ICloudNotDownloaded { .. } => CLAWBOX_ERR_BUSY,
+        Error::VaultLocked => CLAWBOX_ERR_VAULT_LOCKED,
+        Error::VaultNotFound { .. } => CLAWBOX_ERR_NOT_INITIALIZED,
+        Error::AlreadyInitialized { .. } => CLAWBOX_ERR_ALREADY_INITIALIZED,
+        Error::AlreadyExists { .. } => CLAWBOX_ERR_EXISTS,
+        Error::InvalidPath { .. } | Error::InvalidArgument(_) => CLAWBOX_ERR_INVALID_ARG,
+        Error::SecretNotFound { .. } | Error::VersionNotFound { .. } => CLAWBOX_ERR_NOT_FOUND,
+        Error::InvalidPassword => CLAWBOX_ERR_INVALID_PASSWORD,
+        Error::TooManyAttempts { .. } => CLAWBOX_ERR_TOO_MANY_ATTEMPTS,
+        Error::RateLimited { .. } => CLAWBOX_ERR_RATE_LIMITED,
+        Error::ReadOnly => CLAWBOX_ERR_READ_ONLY,
+        Error::AccessDenied { .. } | Error::InsecurePermissions(_) => CLAWBOX_ERR_ACCESS_DENIED,
+        Error::ApprovalTimeout => CLAWBOX_ERR_APPROVAL_TIMEOUT,
+        Error::ReauthRequired { .. } => CLAWBOX_ERR_REAUTH_REQUIRED,
+        Error::ICloudUnavailable => CLAWBOX_ERR_ICLOUD_UNAVAILABLE,
+        Error::Encryption(_) => CLAWBOX_ERR_CRYPTO,
+        // Stored data that fails to decrypt or parse under the right key
+        Error::Decryption(_) | Error::Json(_) | Error::InvalidSyncMeta { .. } => CLAWBOX_ERR_CORRUPT,
+        Error::NotUtf8 { .. } => CLAWBOX_ERR_INVALID_UTF8,
+        Error::Database(_) => CLAWBOX_ERR_DATABASE,
+        Error::Io(_) => CLAWBOX_ERR_IO,
+        Error::Other(_) => CLAWBOX_ERR_UNKNOWN,
+    }
+}
+
+impl From<clawbox_core::Error> for FfiError {
+    fn from(err: clawbox_core::Error) -> Self {
+        Self::new(error_to_code(&err), err.to_string())
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<(c_int, CString)>> = const { RefCell::new(None) };
+}
+
+/// Record a failure for `clawbox_last_error_*` and return its code
+pub(crate) fn record(err: FfiError) -> c_int {
+    // Messages come from our own errors; drop interior NULs rather than lose them
+    let message = CString::new(err.message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((err.code, message)));
+    err.code
+}
+
+/// Run the body of a function returning an error code
+pub(crate) fn catch(f: impl FnOnce() -> Result<c_int, FfiError>) -> c_int {
+    call(f).unwrap_or_else(record)
+}
+
+/// Run the body of a function returning a non-negative value, or -1 on failure
+///
+/// Error codes are positive and could be mistaken for values, so the
+/// failure's code is only available from `clawbox_last_error_code`.
+pub(crate) fn catch_value(f: impl FnOnce() -> Result<c_int, FfiError>) -> c_int {
+    call(f).unwrap_or_else(|err| {
+        record(err);
+        -1
+    })
+}
+
+/// Run a call, counting it as activity for auto-lock if it succeeds
+fn call(f: impl FnOnce() -> Result<c_int, FfiError>) -> Result<c_int, FfiError> {
+    auto_lock::begin_call();
+    let result = f();
+    auto_lock::end_call(result.is_ok());
+    result
+}
+
+/// Code of the most recent failure on this thread (`CLAWBOX_OK` if none)
+#[no_mangle]
+pub extern "C" fn clawbox_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(CLAWBOX_OK, |(code, _)| *code))
+}
+
+/// Message of the most recent failure on this thread
+///
+/// Returns null if nothing has failed on this thread yet. The string is
+/// owned by the library: it stays valid until the next `clawbox_*` call on
+/// the same thread and must not be freed. Copy it if you need to keep it.
+#[no_mangle]
+pub extern "C" fn clawbox_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |(_, message)| message.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clawbox_core::Error;
+
+    /// The values are part of the ABI; the Swift error enum is generated from them
+    #[test]
+    fn test_error_codes_are_stable() {
+        let path = || "db/url".to_string();
+        let busy = rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(5), None);
+        let cases = [
+            (Error::VaultLocked, 1),
+            (Error::InvalidPassword, 2),
+            (Error::SecretNotFound { path: path() }, 3),
+            (Error::VersionNotFound { path: path(), version: 2 }, 3),
+            (Error::Io(std::io::Error::other("disk")), 4),
+            (Error::Database(busy), 5),
+            (Error::RemoteChanged, 5),
+            (Error::RemoteIncomplete, 5),
+            (Error::ICloudNotDownloaded { path: path() }, 5),
+            (Error::VaultNotFound { path: path() }, 6),
+            (Error::AlreadyInitialized { path: path() }, 7),
+            (Error::AccessDenied { reason: "no".into() }, 8),
+            (Error::ApprovalTimeout, 9),
+            (Error::InvalidPath { path: "../x".into(), reason: "bad".into() }, 10),
+            (Error::InvalidArgument("bad".into()), 10),
+            (Error::Decryption("bad tag".into()), 11),
+            (Error::Json(serde_json::from_str::<u8>("x").unwrap_err()), 11),
+            (Error::InvalidSyncMeta { path: path(), reason: "garbage".into() }, 11),
+            (Error::Encryption("bad params".into()), 12),
+            (Error::Database(rusqlite::Error::InvalidQuery), 13),
+            (Error::ICloudUnavailable, 14),
+            (Error::AlreadyExists { path: path() }, 15),
+            (Error::NotUtf8 { path: path() }, 17),
+            (Error::ReauthRequired { path: path() }, 18),
+            (Error::TooManyAttempts { retry_after: std::time::Duration::from_secs(30) }, 19),
+            (Error::RateLimited { retry_after: std::time::Duration::from_secs(5) }, 20),
+            (Error::ReadOnly, 21),
+            (Error::Other("?".into()), -1),
+        ];
+        for (err, code) in cases {
+            assert_eq!(error_to_code(&err), code, "{:?}", err);
+        }
+        assert_eq!(FfiError::invalid_argument("x is null").code, 10);
+        assert_eq!(CLAWBOX_ERR_BUFFER_TOO_SMALL, 22);
+        assert_eq!(CLAWBOX_ERR_CANCELLED, 16);
+        assert_eq!(FfiError::invalid_utf8("path").code, 17);
+        assert_eq!(CLAWBOX_OK, 0);
+    }
+}
@@ -2,58 +2,565 @@
 //!
 //! C-compatible API for Swift/Objective-C integration
 
-use clawbox_core::ClawBox;
-use libc::{c_char, c_int};
+mod auto_lock;
+mod error;
+
+pub use error::*;
+
+use clawbox_core::bundle::{ConflictMode, ExportOptions};
+use clawbox_core::generator::{self, GeneratorOptions};
+use clawbox_core::icloud::ICloudSync;
+use clawbox_core::audit::ActorInfo;
+use clawbox_core::{
+    AccessLevel, Approval, ApprovalHandler, ApprovalRequest, ClawBox, DeleteMode, SecretInfo, SecretValue,
+};
+use auto_lock::AutoLock;
+use error::{catch, catch_value, record, FfiError};
+use libc::{c_char, c_int, c_void};
 use std::ffi::{CStr, CString};
 use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use zeroize::Zeroizing;
 
 /// Opaque handle to ClawBox vault
+///
+/// Safe to share between threads: calls on one handle are serialized by an
+/// internal mutex, so a slow call (unlocking, changing the password) makes
+/// the others wait rather than run concurrently.
 pub struct ClawBoxHandle {
-    vault: ClawBox,
+    /// Shared with the thread of an asynchronous unlock
+    vault: Arc<Mutex<ClawBox>>,
+    /// Opened with `CLAWBOX_OPEN_ALLOW_RAW_KEY`
+    allow_raw_key: bool,
+    /// Cancellation flag of the asynchronous unlock in progress, if any
+    pending_unlock: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    auto_lock: Arc<AutoLock>,
 }
 
-/// Error codes
-pub const CLAWBOX_OK: c_int = 0;
-pub const CLAWBOX_ERR_VAULT_LOCKED: c_int = 1;
-pub const CLAWBOX_ERR_INVALID_PASSWORD: c_int = 2;
-pub const CLAWBOX_ERR_NOT_FOUND: c_int = 3;
-pub const CLAWBOX_ERR_IO: c_int = 4;
-pub const CLAWBOX_ERR_UNKNOWN: c_int = -1;
+/// `clawbox_open_ex` flags
+pub const CLAWBOX_OPEN_ALLOW_RAW_KEY: u32 = 1;
+pub const CLAWBOX_OPEN_READ_ONLY: u32 = 2;
 
-/// Open a vault at the given path
+/// Length of a raw vault key in bytes
+pub const CLAWBOX_KEY_LEN: usize = clawbox_core::crypto::KEY_LEN;
+
+// The handle crosses threads freely on the Swift side
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<ClawBoxHandle>();
+};
+
+/// Progress callback: `(done, total, context)`
+pub type ClawBoxProgressCallback =
+    Option<unsafe extern "C" fn(done: usize, total: usize, context: *mut c_void)>;
+
+/// Completion callback of `clawbox_unlock_async`: `(result, context)`
+pub type ClawBoxUnlockCallback = Option<unsafe extern "C" fn(result: c_int, context: *mut c_void)>;
+
+/// Approval callback: `(path, actor, access_level, context)`, returning one of
+/// the `CLAWBOX_APPROVAL_*` values
+pub type ClawBoxApprovalCallback = Option<
+    unsafe extern "C" fn(path: *const c_char, actor: *const c_char, access_level: c_int, context: *mut c_void) -> c_int,
+>;
+
+/// Approval callback results; anything else counts as a denial
+pub const CLAWBOX_APPROVAL_DENY: c_int = 0;
+pub const CLAWBOX_APPROVAL_APPROVE: c_int = 1;
+pub const CLAWBOX_APPROVAL_TIMEOUT: c_int = 2;
+
+/// Context pointer handed back to a C callback from whichever thread calls it
+struct CallbackContext(*mut c_void);
+
+// The caller promises the context may be used from any thread
+unsafe impl Send for CallbackContext {}
+
+/// Lock the vault behind a handle for the duration of one call
+///
+/// A panic can't leave a `ClawBox` half-updated in a way later calls would
+/// trip over, so a poisoned mutex is used as is.
 ///
 /// # Safety
-/// `path` must be a valid null-terminated C string
-#[no_mangle]
-pub unsafe extern "C" fn clawbox_open(path: *const c_char) -> *mut ClawBoxHandle {
-    if path.is_null() {
+/// `handle` must be null or a valid pointer returned by `clawbox_open`
+unsafe fn vault_of<'a>(handle: *const ClawBoxHandle) -> Result<MutexGuard<'a, ClawBox>, FfiError> {
+    let handle = handle.as_ref().ok_or_else(|| FfiError::invalid_argument("handle is null"))?;
+    // The vault counts as locked until an asynchronous unlock completes
+    if handle.pending_unlock.lock().unwrap_or_else(PoisonError::into_inner).is_some() {
+        return Err(FfiError::new(CLAWBOX_ERR_VAULT_LOCKED, "Vault is being unlocked"));
+    }
+    auto_lock::enter(&handle.auto_lock);
+    let vault = handle.vault.lock().unwrap_or_else(PoisonError::into_inner);
+    // The app may keep the vault open while the CLI changes its settings
+    vault.refresh_meta()?;
+    Ok(vault)
+}
+
+/// Abandon the asynchronous unlock in progress on a handle, if any
+fn cancel_pending_unlock(handle: &ClawBoxHandle) -> bool {
+    let pending = handle.pending_unlock.lock().unwrap_or_else(PoisonError::into_inner).take();
+    if let Some(cancelled) = &pending {
+        cancelled.store(true, Ordering::SeqCst);
+    }
+    pending.is_some()
+}
+
+/// Borrow a string argument
+///
+/// Every C string argument goes through here, so malformed input is always
+/// reported as `CLAWBOX_ERR_INVALID_UTF8`.
+///
+/// # Safety
+/// `s` must be null or a valid null-terminated C string
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if s.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is null", name)));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| FfiError::invalid_utf8(name))
+}
+
+/// Copy a password from a caller-owned buffer into memory wiped on drop
+///
+/// # Safety
+/// `ptr` must be null or point to `len` readable bytes
+unsafe fn password_arg(ptr: *const u8, len: usize, name: &str) -> Result<Zeroizing<Vec<u8>>, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is null", name)));
+    }
+    Ok(Zeroizing::new(std::slice::from_raw_parts(ptr, len).to_vec()))
+}
+
+/// View a password copied by `password_arg` as text, without copying it again
+fn password_str<'a>(password: &'a [u8], name: &str) -> Result<&'a str, FfiError> {
+    std::str::from_utf8(password).map_err(|_| FfiError::invalid_utf8(name))
+}
+
+/// Check an output pointer
+fn out_arg<T>(out: *mut T, name: &str) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::invalid_argument(format!("{} is null", name)));
+    }
+    Ok(())
+}
+
+/// Metadata of one secret, as returned by `clawbox_list`
+#[repr(C)]
+pub struct ClawBoxSecretInfo {
+    /// Secret path
+    pub path: *mut c_char,
+    /// Access level (one of the `CLAWBOX_ACCESS_*` values)
+    pub access_level: c_int,
+    /// Creation time (Unix seconds)
+    pub created_at: i64,
+    /// Last update time (Unix seconds)
+    pub updated_at: i64,
+    /// Number of entries in `tags`
+    pub tag_count: usize,
+    /// Tags (null when `tag_count` is 0)
+    pub tags: *mut *mut c_char,
+}
+
+/// Access levels
+pub const CLAWBOX_ACCESS_PUBLIC: c_int = 0;
+pub const CLAWBOX_ACCESS_NORMAL: c_int = 1;
+pub const CLAWBOX_ACCESS_SENSITIVE: c_int = 2;
+pub const CLAWBOX_ACCESS_CRITICAL: c_int = 3;
+
+fn access_level_to_int(access: AccessLevel) -> c_int {
+    match access {
+        AccessLevel::Public => CLAWBOX_ACCESS_PUBLIC,
+        AccessLevel::Normal => CLAWBOX_ACCESS_NORMAL,
+        AccessLevel::Sensitive => CLAWBOX_ACCESS_SENSITIVE,
+        AccessLevel::Critical => CLAWBOX_ACCESS_CRITICAL,
+    }
+}
+
+/// Hand a vector to C as a pointer to its first element (null when empty)
+fn into_raw_array<T>(items: Vec<T>) -> *mut T {
+    if items.is_empty() {
         return ptr::null_mut();
     }
+    Box::into_raw(items.into_boxed_slice()) as *mut T
+}
+
+/// Take back an array created by `into_raw_array`
+///
+/// # Safety
+/// `items` and `count` must come from the same `into_raw_array` call
+unsafe fn from_raw_array<T>(items: *mut T, count: usize) -> Vec<T> {
+    if items.is_null() {
+        return vec![];
+    }
+    Box::from_raw(ptr::slice_from_raw_parts_mut(items, count)).into_vec()
+}
+
+/// An array of strings owned by the caller, released with `clawbox_free_string_array`
+#[repr(C)]
+pub struct ClawBoxStringArray {
+    /// `len` strings (null when empty)
+    pub ptr: *mut *mut c_char,
+    pub len: usize,
+}
+
+impl ClawBoxStringArray {
+    fn new(strings: Vec<String>) -> Result<Self, FfiError> {
+        let strings = strings.into_iter().map(c_string).collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            len: strings.len(),
+            ptr: into_raw_array(strings.into_iter().map(CString::into_raw).collect()),
+        })
+    }
+}
+
+unsafe fn free_list_item(info: ClawBoxSecretInfo) {
+    drop(CString::from_raw(info.path));
+    for tag in from_raw_array(info.tags, info.tag_count) {
+        drop(CString::from_raw(tag));
+    }
+}
+
+/// Full metadata of one secret, as returned by `clawbox_get_info`
+///
+/// Versioned by size: callers set `struct_size` to `sizeof(ClawBoxSecretDetails)`
+/// before the call. Fields added later go at the end and are only written
+/// when `struct_size` covers them, so older callers keep working.
+#[repr(C)]
+pub struct ClawBoxSecretDetails {
+    /// Size of the struct as the caller knows it
+    pub struct_size: usize,
+    /// Secret path
+    pub path: *mut c_char,
+    /// Access level (one of the `CLAWBOX_ACCESS_*` values)
+    pub access_level: c_int,
+    /// Creation time (Unix seconds)
+    pub created_at: i64,
+    /// Last update time (Unix seconds)
+    pub updated_at: i64,
+    /// Expiry time (Unix seconds), 0 if the secret never expires
+    pub expires_at: i64,
+    /// Size of the value in bytes
+    pub size: usize,
+    /// Note (null if none)
+    pub note: *mut c_char,
+    /// Number of entries in `tags`
+    pub tag_count: usize,
+    /// Tags (null when `tag_count` is 0)
+    pub tags: *mut *mut c_char,
+}
+
+impl ClawBoxSecretDetails {
+    fn new(mut info: SecretInfo) -> Result<Self, FfiError> {
+        let note = info.note.take().map(c_string).transpose()?;
+        let expires_at = info.expires_at.map_or(0, |t| t.timestamp());
+        let size = info.size;
+        let item = ClawBoxSecretInfo::new(info)?;
+        Ok(Self {
+            struct_size: std::mem::size_of::<Self>(),
+            path: item.path,
+            access_level: item.access_level,
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+            expires_at,
+            size,
+            note: note.map_or(ptr::null_mut(), CString::into_raw),
+            tag_count: item.tag_count,
+            tags: item.tags,
+        })
+    }
+}
+
+/// Options for writing a secret
+///
+/// Versioned by size like `ClawBoxSecretDetails`; a null pointer means defaults.
+#[repr(C)]
+pub struct ClawBoxSetOptions {
+    /// Size of the struct as the caller knows it
+    pub struct_size: usize,
+    /// Access level (one of the `CLAWBOX_ACCESS_*` values)
+    pub access_level: c_int,
+    /// Seconds until the secret expires, 0 for never
+    pub ttl_seconds: u64,
+    /// Note (may be null)
+    pub note: *const c_char,
+    /// Number of entries in `tags`
+    pub tag_count: usize,
+    /// Tags (may be null when `tag_count` is 0)
+    pub tags: *const *const c_char,
+}
+
+/// Parse an access level, rejecting unknown values rather than weakening them
+fn access_level_from_int(access_level: c_int) -> Result<AccessLevel, FfiError> {
+    match access_level {
+        CLAWBOX_ACCESS_PUBLIC => Ok(AccessLevel::Public),
+        CLAWBOX_ACCESS_NORMAL => Ok(AccessLevel::Normal),
+        CLAWBOX_ACCESS_SENSITIVE => Ok(AccessLevel::Sensitive),
+        CLAWBOX_ACCESS_CRITICAL => Ok(AccessLevel::Critical),
+        _ => Err(FfiError::invalid_argument(format!(
+            "Unknown access level {}; use one of the CLAWBOX_ACCESS_* values",
+            access_level
+        ))),
+    }
+}
+
+/// Read caller-provided set options
+///
+/// # Safety
+/// `options` must be null or point to a valid `ClawBoxSetOptions`
+unsafe fn set_options(options: *const ClawBoxSetOptions) -> Result<clawbox_core::SetOptions, FfiError> {
+    let Some(options) = options.as_ref() else {
+        return Ok(Default::default());
+    };
+    if options.struct_size < std::mem::size_of::<ClawBoxSetOptions>() {
+        return Err(FfiError::invalid_argument("options->struct_size is too small"));
+    }
+    if options.tags.is_null() && options.tag_count > 0 {
+        return Err(FfiError::invalid_argument("options->tags is null"));
+    }
+
+    let tags = if options.tag_count == 0 {
+        vec![]
+    } else {
+        std::slice::from_raw_parts(options.tags, options.tag_count)
+            .iter()
+            .map(|&tag| str_arg(tag, "tag").map(str::to_string))
+            .collect::<Result<_, _>>()?
+    };
+    let note = if options.note.is_null() {
+        None
+    } else {
+        Some(str_arg(options.note, "note")?.to_string())
+    };
+
+    Ok(clawbox_core::SetOptions {
+        access: access_level_from_int(options.access_level)?,
+        ttl: (options.ttl_seconds > 0).then(|| std::time::Duration::from_secs(options.ttl_seconds)),
+        tags,
+        note,
+    })
+}
+
+/// Options for `clawbox_generate_password`; a null pointer means defaults
+/// (24 characters from every class)
+#[repr(C)]
+pub struct ClawBoxGeneratorOptions {
+    /// Size of the struct as the caller knows it
+    pub struct_size: usize,
+    /// Number of characters
+    pub length: usize,
+    /// `CLAWBOX_GEN_*` flags
+    pub flags: u32,
+}
+
+/// `ClawBoxGeneratorOptions` flags
+pub const CLAWBOX_GEN_UPPERCASE: u32 = 1;
+pub const CLAWBOX_GEN_LOWERCASE: u32 = 1 << 1;
+pub const CLAWBOX_GEN_DIGITS: u32 = 1 << 2;
+pub const CLAWBOX_GEN_SYMBOLS: u32 = 1 << 3;
+pub const CLAWBOX_GEN_EXCLUDE_AMBIGUOUS: u32 = 1 << 4;
 
-    let path_str = match CStr::from_ptr(path).to_str() {
-        Ok(s) => s,
-        Err(_) => return ptr::null_mut(),
+/// Read caller-provided generator options
+///
+/// # Safety
+/// `options` must be null or point to a valid `ClawBoxGeneratorOptions`
+unsafe fn generator_options(options: *const ClawBoxGeneratorOptions) -> Result<GeneratorOptions, FfiError> {
+    let Some(options) = options.as_ref() else {
+        return Ok(Default::default());
     };
+    if options.struct_size < std::mem::size_of::<ClawBoxGeneratorOptions>() {
+        return Err(FfiError::invalid_argument("options->struct_size is too small"));
+    }
+    let known = CLAWBOX_GEN_UPPERCASE
+        | CLAWBOX_GEN_LOWERCASE
+        | CLAWBOX_GEN_DIGITS
+        | CLAWBOX_GEN_SYMBOLS
+        | CLAWBOX_GEN_EXCLUDE_AMBIGUOUS;
+    if options.flags & !known != 0 {
+        return Err(FfiError::invalid_argument(format!("Unknown generator flags {:#x}", options.flags)));
+    }
+
+    Ok(GeneratorOptions {
+        length: options.length,
+        uppercase: options.flags & CLAWBOX_GEN_UPPERCASE != 0,
+        lowercase: options.flags & CLAWBOX_GEN_LOWERCASE != 0,
+        digits: options.flags & CLAWBOX_GEN_DIGITS != 0,
+        symbols: options.flags & CLAWBOX_GEN_SYMBOLS != 0,
+        exclude_ambiguous: options.flags & CLAWBOX_GEN_EXCLUDE_AMBIGUOUS != 0,
+    })
+}
+
+/// Options for `clawbox_export`; a null pointer means defaults
+#[repr(C)]
+pub struct ClawBoxExportOptions {
+    /// Size of the struct as the caller knows it
+    pub struct_size: usize,
+    /// Non-zero to include Critical secrets, which are left out by default
+    pub include_critical: c_int,
+    /// Master password, read as by `clawbox_get_with_reauth`; needed when a
+    /// secret included requires reauthentication (Critical ones, by
+    /// default), otherwise may be null. Read only when `struct_size` covers
+    /// it, so older callers keep working
+    pub password: *const u8,
+    pub password_len: usize,
+}
+
+/// Vault statistics, as returned by `clawbox_stats`
+///
+/// Versioned by size like `ClawBoxSecretDetails`: set `struct_size` to
+/// `sizeof(ClawBoxStats)` before the call.
+#[repr(C)]
+pub struct ClawBoxStats {
+    /// Size of the struct as the caller knows it
+    pub struct_size: usize,
+    /// Number of secrets per access level
+    pub public_count: usize,
+    pub normal_count: usize,
+    pub sensitive_count: usize,
+    pub critical_count: usize,
+    /// Size of the database files on disk in bytes
+    pub db_size: u64,
+    /// Number of audit log entries
+    pub audit_entries: usize,
+    /// Time of the most recent write (Unix seconds), 0 if there are no secrets
+    pub last_write: i64,
+}
+
+/// `clawbox_import` conflict modes
+pub const CLAWBOX_CONFLICT_OVERWRITE: c_int = 0;
+pub const CLAWBOX_CONFLICT_SKIP: c_int = 1;
 
-    match ClawBox::open(path_str) {
-        Ok(vault) => Box::into_raw(Box::new(ClawBoxHandle { vault })),
-        Err(_) => ptr::null_mut(),
+/// Copy a string for C
+fn c_string(s: String) -> Result<CString, FfiError> {
+    CString::new(s).map_err(|_| FfiError::new(CLAWBOX_ERR_UNKNOWN, "string contains a NUL byte"))
+}
+
+/// Copy a secret for C; our copy is wiped when `value` drops, and
+/// `clawbox_free_string` wipes the one handed out
+fn secret_c_string(value: &SecretValue) -> Result<CString, FfiError> {
+    if value.as_bytes().contains(&0) {
+        return Err(FfiError::new(CLAWBOX_ERR_UNKNOWN, "string contains a NUL byte"));
+    }
+    // Sized exactly, so adding the NUL or boxing never reallocates and
+    // leaves an unwiped copy behind
+    let mut bytes = Vec::with_capacity(value.len() + 1);
+    bytes.extend_from_slice(value.as_bytes());
+    bytes.push(0);
+    CString::from_vec_with_nul(bytes).map_err(|_| FfiError::new(CLAWBOX_ERR_UNKNOWN, "string contains a NUL byte"))
+}
+
+impl ClawBoxSecretInfo {
+    fn new(info: SecretInfo) -> Result<Self, FfiError> {
+        let tags = info.tags.into_iter().map(c_string).collect::<Result<Vec<_>, _>>()?;
+        let path = c_string(info.path)?;
+        let tag_count = tags.len();
+        Ok(Self {
+            path: path.into_raw(),
+            access_level: access_level_to_int(info.access),
+            created_at: info.created_at.timestamp(),
+            updated_at: info.updated_at.timestamp(),
+            tag_count,
+            tags: into_raw_array(tags.into_iter().map(CString::into_raw).collect()),
+        })
+    }
+}
+
+/// Open a vault at the given path
+///
+/// Returns null on failure; see `clawbox_last_error_message`.
+///
+/// # Safety
+/// `path` must be a valid null-terminated C string
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_open(path: *const c_char) -> *mut ClawBoxHandle {
+    clawbox_open_ex(path, 0)
+}
+
+/// Open a vault with `CLAWBOX_OPEN_*` flags
+///
+/// `CLAWBOX_OPEN_ALLOW_RAW_KEY` enables `clawbox_unlock_with_key` and
+/// `clawbox_export_key` on the handle; `CLAWBOX_OPEN_READ_ONLY` opens an
+/// existing vault read-only (see `ClawBox::open_read_only`). Unknown flags
+/// are rejected.
+///
+/// # Safety
+/// `path` must be a valid null-terminated C string
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_open_ex(path: *const c_char, flags: u32) -> *mut ClawBoxHandle {
+    let result = str_arg(path, "path").and_then(|path| {
+        if flags & !(CLAWBOX_OPEN_ALLOW_RAW_KEY | CLAWBOX_OPEN_READ_ONLY) != 0 {
+            return Err(FfiError::invalid_argument(format!("Unknown open flags {:#x}", flags)));
+        }
+        Ok(ClawBox::builder(path).read_only(flags & CLAWBOX_OPEN_READ_ONLY != 0).open()?)
+    });
+    match result {
+        Ok(vault) => Box::into_raw(Box::new(ClawBoxHandle {
+            vault: Arc::new(Mutex::new(vault)),
+            allow_raw_key: flags & CLAWBOX_OPEN_ALLOW_RAW_KEY != 0,
+            pending_unlock: Default::default(),
+            auto_lock: Default::default(),
+        })),
+        Err(err) => {
+            record(err);
+            ptr::null_mut()
+        }
     }
 }
 
 /// Close and free the vault handle
 ///
+/// An asynchronous unlock in progress is cancelled; its callback still runs.
+///
 /// # Safety
 /// `handle` must be a valid pointer returned by `clawbox_open`
 #[no_mangle]
 pub unsafe extern "C" fn clawbox_close(handle: *mut ClawBoxHandle) {
     if !handle.is_null() {
-        drop(Box::from_raw(handle));
+        let handle = Box::from_raw(handle);
+        cancel_pending_unlock(&handle);
+        handle.auto_lock.shutdown();
     }
 }
 
+/// Library version, e.g. `"0.1.0"`
+///
+/// The string is static and must not be freed.
+#[no_mangle]
+pub extern "C" fn clawbox_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// Version of the C ABI this library implements
+///
+/// Compare with `CLAWBOX_ABI_VERSION` from the header the caller was built
+/// against; a mismatch means structs or error codes may be laid out differently.
+#[no_mangle]
+pub extern "C" fn clawbox_abi_version() -> c_int {
+    CLAWBOX_ABI_VERSION
+}
+
+/// Newest vault schema version this library can read
+#[no_mangle]
+pub extern "C" fn clawbox_supported_schema_version() -> c_int {
+    clawbox_core::storage::SCHEMA_VERSION as c_int
+}
+
+/// Schema version recorded in an open vault
+///
+/// A value above `clawbox_supported_schema_version()` means the vault was
+/// written by a newer build. Returns -1 on failure; see `clawbox_last_error_code`.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_core_schema_version(handle: *mut ClawBoxHandle) -> c_int {
+    catch_value(|| {
+        let vault = vault_of(handle)?;
+        Ok(vault.schema_version()? as c_int)
+    })
+}
+
 /// Initialize a new vault with master password
 ///
+/// Deprecated: prefer `clawbox_init_bytes`, which lets the caller wipe the password.
+///
 /// # Safety
 /// `handle` and `password` must be valid pointers
 #[no_mangle]
@@ -61,24 +568,19 @@ pub unsafe extern "C" fn clawbox_init(
     handle: *mut ClawBoxHandle,
     password: *const c_char,
 ) -> c_int {
-    if handle.is_null() || password.is_null() {
-        return CLAWBOX_ERR_UNKNOWN;
-    }
-
-    let handle = &mut *handle;
-    let password_str = match CStr::from_ptr(password).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
-    };
-
-    match handle.vault.init(password_str) {
-        Ok(_) => CLAWBOX_OK,
-        Err(_) => CLAWBOX_ERR_IO,
-    }
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let password = str_arg(password, "password")?;
+        vault.init(password)?;
+        Ok(CLAWBOX_OK)
+    })
 }
 
 /// Unlock the vault
 ///
+/// Returns `CLAWBOX_ERR_NOT_INITIALIZED` if the vault has no master password yet.
+/// Deprecated: prefer `clawbox_unlock_bytes`.
+///
 /// # Safety
 /// `handle` and `password` must be valid pointers
 #[no_mangle]
@@ -86,164 +588,1663 @@ pub unsafe extern "C" fn clawbox_unlock(
     handle: *mut ClawBoxHandle,
     password: *const c_char,
 ) -> c_int {
-    if handle.is_null() || password.is_null() {
-        return CLAWBOX_ERR_UNKNOWN;
-    }
-
-    let handle = &mut *handle;
-    let password_str = match CStr::from_ptr(password).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
-    };
-
-    match handle.vault.unlock(password_str) {
-        Ok(_) => CLAWBOX_OK,
-        Err(clawbox_core::Error::InvalidPassword) => CLAWBOX_ERR_INVALID_PASSWORD,
-        Err(_) => CLAWBOX_ERR_UNKNOWN,
-    }
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let password = str_arg(password, "password")?;
+        vault.unlock(password)?;
+        Ok(CLAWBOX_OK)
+    })
 }
 
-/// Lock the vault
+/// Initialize a new vault, reading the master password from a byte buffer
+///
+/// The password must be UTF-8 and is not null-terminated. ClawBox copies it
+/// into memory that is wiped before the call returns and keeps no other copy;
+/// the buffer itself stays the caller's to wipe.
 ///
 /// # Safety
-/// `handle` must be a valid pointer
+/// `handle` must be a valid pointer; `password` must point to `len` readable bytes
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_lock(handle: *mut ClawBoxHandle) {
-    if !handle.is_null() {
-        let handle = &mut *handle;
-        handle.vault.lock();
-    }
+pub unsafe extern "C" fn clawbox_init_bytes(
+    handle: *mut ClawBoxHandle,
+    password: *const u8,
+    len: usize,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let password = password_arg(password, len, "password")?;
+        vault.init(password_str(&password, "password")?)?;
+        Ok(CLAWBOX_OK)
+    })
 }
 
-/// Check if vault is unlocked
+/// Unlock the vault, reading the master password from a byte buffer
+///
+/// Same contract as `clawbox_init_bytes`.
 ///
 /// # Safety
-/// `handle` must be a valid pointer
+/// `handle` must be a valid pointer; `password` must point to `len` readable bytes
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_is_unlocked(handle: *const ClawBoxHandle) -> c_int {
-    if handle.is_null() {
-        return 0;
-    }
-
-    let handle = &*handle;
-    if handle.vault.is_unlocked() { 1 } else { 0 }
+pub unsafe extern "C" fn clawbox_unlock_bytes(
+    handle: *mut ClawBoxHandle,
+    password: *const u8,
+    len: usize,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let password = password_arg(password, len, "password")?;
+        vault.unlock(password_str(&password, "password")?)?;
+        Ok(CLAWBOX_OK)
+    })
 }
 
-/// Get a secret value
+/// Unlock the vault on a background thread
+///
+/// Key derivation takes about a second; this returns at once and calls
+/// `callback` with the result code when done. The callback runs on an
+/// internal thread, not the caller's, so `context` must be usable from any
+/// thread. Until it runs, every other call on the handle fails with
+/// `CLAWBOX_ERR_VAULT_LOCKED` (and `clawbox_is_unlocked` returns 0).
+///
+/// Returns `CLAWBOX_OK` if the attempt started; the callback is then called
+/// exactly once, with `CLAWBOX_ERR_CANCELLED` if the attempt was abandoned
+/// by `clawbox_cancel_unlock`, `clawbox_lock` or `clawbox_close` (possibly
+/// after the handle has been freed). Any other return value means the
+/// attempt did not start and the callback will not be called.
 ///
 /// # Safety
-/// `handle`, `path`, and `out_value` must be valid pointers
+/// `handle` must be a valid pointer; `password` must point to `len` readable
+/// bytes; `callback` must be a valid function pointer
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_get(
+pub unsafe extern "C" fn clawbox_unlock_async(
     handle: *mut ClawBoxHandle,
-    path: *const c_char,
-    out_value: *mut *mut c_char,
+    password: *const u8,
+    len: usize,
+    callback: ClawBoxUnlockCallback,
+    context: *mut c_void,
 ) -> c_int {
-    if handle.is_null() || path.is_null() || out_value.is_null() {
-        return CLAWBOX_ERR_UNKNOWN;
-    }
+    catch(|| {
+        let (salt, duress_salt) = {
+            let vault = vault_of(handle)?;
+            // Refuse now rather than after deriving the key
+            vault.check_unlock_allowed()?;
+            (vault.key_salt()?, vault.duress_key_salt()?)
+        };
+        let handle = &*handle;
+        let password = password_arg(password, len, "password")?;
+        password_str(&password, "password")?;
+        let callback = callback.ok_or_else(|| FfiError::invalid_argument("callback is null"))?;
+        let context = CallbackContext(context);
 
-    let handle = &*handle;
-    let path_str = match CStr::from_ptr(path).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
-    };
+        let cancelled = Arc::new(AtomicBool::new(false));
+        {
+            let mut pending = handle.pending_unlock.lock().unwrap_or_else(PoisonError::into_inner);
+            if pending.is_some() {
+                return Err(FfiError::new(CLAWBOX_ERR_VAULT_LOCKED, "Vault is being unlocked"));
+            }
+            *pending = Some(cancelled.clone());
+        }
+
+        let vault = handle.vault.clone();
+        let pending = handle.pending_unlock.clone();
+        let auto_lock = handle.auto_lock.clone();
+        let worker = move || {
+            // Capture the wrapper, not its raw pointer
+            let context = context;
+            // Derive without holding any lock, so cancelling never waits for it
+            let keys = password_str(&password, "password").and_then(|password| {
+                let key = clawbox_core::crypto::derive_key(password, &salt)?;
+                let duress_key = match &duress_salt {
+                    Some(salt) => Some(clawbox_core::crypto::derive_key(password, salt)?),
+                    None => None,
+                };
+                Ok((key, duress_key))
+            });
 
-    match handle.vault.get(path_str) {
-        Ok(Some(value)) => {
-            match CString::new(value) {
-                Ok(c_str) => {
-                    *out_value = c_str.into_raw();
-                    CLAWBOX_OK
+            let result = {
+                let mut pending = pending.lock().unwrap_or_else(PoisonError::into_inner);
+                if cancelled.load(Ordering::SeqCst) {
+                    CLAWBOX_ERR_CANCELLED
+                } else {
+                    *pending = None;
+                    let mut vault = vault.lock().unwrap_or_else(PoisonError::into_inner);
+                    catch(|| {
+                        let (key, duress_key) = keys?;
+                        vault.unlock_derived_keys(key, duress_key)?;
+                        auto_lock.touch();
+                        Ok(CLAWBOX_OK)
+                    })
                 }
-                Err(_) => CLAWBOX_ERR_UNKNOWN,
-            }
+            };
+            // After `clawbox_close` this frees the vault before the caller hears back
+            drop((vault, pending, auto_lock));
+            callback(result, context.0);
+        };
+
+        if let Err(e) = std::thread::Builder::new().name("clawbox-unlock".into()).spawn(worker) {
+            handle.pending_unlock.lock().unwrap_or_else(PoisonError::into_inner).take();
+            return Err(FfiError::new(CLAWBOX_ERR_UNKNOWN, format!("Cannot start unlock thread: {}", e)));
         }
-        Ok(None) => CLAWBOX_ERR_NOT_FOUND,
-        Err(clawbox_core::Error::VaultLocked) => CLAWBOX_ERR_VAULT_LOCKED,
-        Err(_) => CLAWBOX_ERR_UNKNOWN,
-    }
+        Ok(CLAWBOX_OK)
+    })
 }
 
-/// Free a string returned by clawbox_get
+/// Seconds until unlocking may be tried again after too many wrong passwords
+///
+/// Writes 0 to `out_seconds` if unlocking may be tried now; otherwise unlock
+/// calls fail with `CLAWBOX_ERR_TOO_MANY_ATTEMPTS` without trying the
+/// password. Works while locked.
 ///
 /// # Safety
-/// `s` must be a valid pointer returned by clawbox_get
+/// `handle` must be a valid pointer; `out_seconds` must be a valid pointer
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_free_string(s: *mut c_char) {
-    if !s.is_null() {
-        drop(CString::from_raw(s));
+pub unsafe extern "C" fn clawbox_unlock_retry_after(handle: *const ClawBoxHandle, out_seconds: *mut u64) -> c_int {
+    catch(|| {
+        let handle = handle.as_ref().ok_or_else(|| FfiError::invalid_argument("handle is null"))?;
+        if out_seconds.is_null() {
+            return Err(FfiError::invalid_argument("out_seconds is null"));
+        }
+        // Not `vault_of`: asking must not count as activity
+        let vault = handle.vault.lock().unwrap_or_else(PoisonError::into_inner);
+        let retry_after = match vault.check_unlock_allowed() {
+            Ok(()) => std::time::Duration::ZERO,
+            Err(clawbox_core::Error::TooManyAttempts { retry_after }) => retry_after,
+            Err(e) => return Err(e.into()),
+        };
+        *out_seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Abandon the asynchronous unlock in progress, if any
+///
+/// The handle is usable (and still locked) as soon as this returns; the
+/// attempt's callback receives `CLAWBOX_ERR_CANCELLED`. Does nothing if no
+/// unlock is in progress.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_cancel_unlock(handle: *mut ClawBoxHandle) -> c_int {
+    catch(|| {
+        let handle = handle.as_ref().ok_or_else(|| FfiError::invalid_argument("handle is null"))?;
+        cancel_pending_unlock(handle);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Lock the vault automatically after `seconds` without a successful call
+///
+/// The timer runs on an internal thread and restarts with every call that
+/// succeeds, except `clawbox_is_unlocked` and `clawbox_time_until_lock`, so
+/// polling them does not keep the vault open. Locking wipes the key and is
+/// audited as done by the system. `seconds` = 0 disables auto-lock.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_set_auto_lock(handle: *mut ClawBoxHandle, seconds: u32) -> c_int {
+    catch(|| {
+        let handle = handle.as_ref().ok_or_else(|| FfiError::invalid_argument("handle is null"))?;
+        let timeout = (seconds > 0).then(|| std::time::Duration::from_secs(seconds.into()));
+        handle
+            .auto_lock
+            .configure(timeout, &handle.vault)
+            .map_err(|e| FfiError::new(CLAWBOX_ERR_UNKNOWN, format!("Cannot start auto-lock timer: {}", e)))?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Seconds until auto-lock, rounded up
+///
+/// Returns 0 if auto-lock is disabled or the vault is locked, or -1 on
+/// failure (see `clawbox_last_error_code`).
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_time_until_lock(handle: *const ClawBoxHandle) -> c_int {
+    catch_value(|| {
+        let handle = handle.as_ref().ok_or_else(|| FfiError::invalid_argument("handle is null"))?;
+        // Not `vault_of`: asking must not count as activity
+        if !handle.vault.lock().unwrap_or_else(PoisonError::into_inner).is_unlocked() {
+            return Ok(0);
+        }
+        let remaining = handle.auto_lock.remaining().unwrap_or_default();
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        Ok(seconds.try_into().unwrap_or(c_int::MAX))
+    })
+}
+
+/// Check that a handle was opened with `CLAWBOX_OPEN_ALLOW_RAW_KEY`
+///
+/// # Safety
+/// `handle` must be null or a valid pointer
+unsafe fn check_raw_key_allowed(handle: *const ClawBoxHandle) -> Result<(), FfiError> {
+    match handle.as_ref() {
+        Some(handle) if !handle.allow_raw_key => Err(FfiError::new(
+            CLAWBOX_ERR_ACCESS_DENIED,
+            "Raw key access is disabled; open the vault with CLAWBOX_OPEN_ALLOW_RAW_KEY",
+        )),
+        _ => Ok(()),
     }
 }
 
-/// Set a secret value
+/// Unlock the vault with a raw key, e.g. one released by the Secure Enclave
+///
+/// `key_len` must be `CLAWBOX_KEY_LEN`. The attempt is audited whether or not
+/// it succeeds. Requires a handle opened with `CLAWBOX_OPEN_ALLOW_RAW_KEY`.
 ///
 /// # Safety
-/// `handle`, `path`, and `value` must be valid pointers
+/// `handle` must be a valid pointer; `key` must point to `key_len` readable bytes
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_set(
+pub unsafe extern "C" fn clawbox_unlock_with_key(
     handle: *mut ClawBoxHandle,
-    path: *const c_char,
-    value: *const c_char,
-    access_level: c_int,
+    key: *const u8,
+    key_len: usize,
 ) -> c_int {
-    if handle.is_null() || path.is_null() || value.is_null() {
-        return CLAWBOX_ERR_UNKNOWN;
-    }
+    catch(|| {
+        check_raw_key_allowed(handle)?;
+        let mut vault = vault_of(handle)?;
+        if key.is_null() {
+            return Err(FfiError::invalid_argument("key is null"));
+        }
+        if key_len != CLAWBOX_KEY_LEN {
+            return Err(FfiError::invalid_argument(format!(
+                "key_len must be {} bytes, got {}",
+                CLAWBOX_KEY_LEN, key_len
+            )));
+        }
 
-    let handle = &mut *handle;
-    let path_str = match CStr::from_ptr(path).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
-    };
-    let value_str = match CStr::from_ptr(value).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
-    };
+        // The key is read in place; the core copies it straight into a zeroizing key
+        let result = vault.unlock_with_key(std::slice::from_raw_parts(key, key_len));
+        vault.record_key_unlock(result.as_ref().err().map(ToString::to_string).as_deref());
+        result?;
+        Ok(CLAWBOX_OK)
+    })
+}
 
-    let access = match access_level {
-        0 => clawbox_core::AccessLevel::Public,
-        1 => clawbox_core::AccessLevel::Normal,
-        2 => clawbox_core::AccessLevel::Sensitive,
-        3 => clawbox_core::AccessLevel::Critical,
-        _ => clawbox_core::AccessLevel::Normal,
-    };
+/// Copy the raw vault key into a caller-owned buffer
+///
+/// `out_len` must be `CLAWBOX_KEY_LEN`; the caller should wipe the buffer as
+/// soon as it has wrapped the key. Only works while unlocked, is audited, and
+/// requires a handle opened with `CLAWBOX_OPEN_ALLOW_RAW_KEY`.
+///
+/// # Safety
+/// `handle` must be a valid pointer; `out` must point to `out_len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_export_key(handle: *mut ClawBoxHandle, out: *mut u8, out_len: usize) -> c_int {
+    catch(|| {
+        check_raw_key_allowed(handle)?;
+        let vault = vault_of(handle)?;
+        out_arg(out, "out")?;
+        if out_len != CLAWBOX_KEY_LEN {
+            return Err(FfiError::invalid_argument(format!(
+                "out_len must be {} bytes, got {}",
+                CLAWBOX_KEY_LEN, out_len
+            )));
+        }
 
-    let opts = clawbox_core::SetOptions {
-        access,
-        ..Default::default()
-    };
+        let key = vault.export_key()?;
+        std::slice::from_raw_parts_mut(out, out_len).copy_from_slice(&key);
+        vault.record_key_export();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Check whether the vault has been initialized with a master password
+///
+/// Returns 1 or 0, or -1 on failure; see `clawbox_last_error_code`.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_is_initialized(handle: *mut ClawBoxHandle) -> c_int {
+    catch_value(|| {
+        let vault = vault_of(handle)?;
+        Ok(vault.is_initialized()? as c_int)
+    })
+}
 
-    match handle.vault.set(path_str, value_str, opts) {
-        Ok(_) => CLAWBOX_OK,
-        Err(clawbox_core::Error::VaultLocked) => CLAWBOX_ERR_VAULT_LOCKED,
-        Err(_) => CLAWBOX_ERR_UNKNOWN,
+/// Lock the vault, cancelling an asynchronous unlock in progress
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_lock(handle: *mut ClawBoxHandle) {
+    if let Some(handle) = handle.as_ref() {
+        cancel_pending_unlock(handle);
+    }
+    if let Ok(mut vault) = vault_of(handle) {
+        vault.lock();
     }
 }
 
-/// Delete a secret
+/// Check if vault is unlocked
 ///
 /// # Safety
-/// `handle` and `path` must be valid pointers
+/// `handle` must be a valid pointer
 #[no_mangle]
-pub unsafe extern "C" fn clawbox_delete(
-    handle: *mut ClawBoxHandle,
-    path: *const c_char,
-) -> c_int {
-    if handle.is_null() || path.is_null() {
-        return CLAWBOX_ERR_UNKNOWN;
+pub unsafe extern "C" fn clawbox_is_unlocked(handle: *const ClawBoxHandle) -> c_int {
+    match vault_of(handle) {
+        Ok(vault) if vault.is_unlocked() => 1,
+        _ => 0,
     }
+}
 
-    let handle = &mut *handle;
-    let path_str = match CStr::from_ptr(path).to_str() {
-        Ok(s) => s,
-        Err(_) => return CLAWBOX_ERR_UNKNOWN,
+/// Get a secret value
+///
+/// # Safety
+/// `handle`, `path`, and `out_value` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    out_value: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        out_arg(out_value, "out_value")?;
+
+        let value = vault.get_secret(path)?.ok_or_else(|| clawbox_core::Error::SecretNotFound {
+            path: path.to_string(),
+        })?;
+        *out_value = secret_c_string(&value)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Get a secret value after checking the master password again
+///
+/// Critical secrets need this: `clawbox_get` refuses them with
+/// `CLAWBOX_ERR_REAUTH_REQUIRED`. A wrong password fails with
+/// `CLAWBOX_ERR_INVALID_PASSWORD`; both outcomes are audited. The password
+/// is read as by `clawbox_unlock_bytes`.
+///
+/// # Safety
+/// `handle`, `path` and `out_value` must be valid pointers; `password` must
+/// point to `password_len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_with_reauth(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    password: *const u8,
+    password_len: usize,
+    out_value: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        let password = password_arg(password, password_len, "password")?;
+        out_arg(out_value, "out_value")?;
+
+        let value = vault.get_with_reauth(path, password_str(&password, "password")?)?.ok_or_else(|| {
+            clawbox_core::Error::SecretNotFound { path: path.to_string() }
+        })?;
+        *out_value = secret_c_string(&value)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Get a secret value on behalf of an AI agent, enforcing access levels
+///
+/// Public and Normal secrets are returned as by `clawbox_get`. Reading a
+/// Sensitive secret asks the approval callback and fails if it is denied,
+/// times out, or no callback is set; Critical secrets are always refused.
+///
+/// # Safety
+/// `handle`, `path`, `agent` and `out_value` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_as_agent(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    agent: *const c_char,
+    out_value: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        let agent = str_arg(agent, "agent")?;
+        out_arg(out_value, "out_value")?;
+
+        let mut value = vault.get_bytes_as(path, &ActorInfo::ai(agent))?.ok_or_else(|| {
+            clawbox_core::Error::SecretNotFound { path: path.to_string() }
+        })?;
+        // Moves the buffer out rather than copying it
+        let value = SecretValue::from_utf8(std::mem::take(&mut *value))
+            .ok_or_else(|| clawbox_core::Error::NotUtf8 { path: path.to_string() })?;
+        *out_value = secret_c_string(&value)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Act as `actor` on this handle: `human`, `human:<name>`, `ai:<agent>` or
+/// `app:<name>`; null goes back to the default, the local human
+///
+/// Later reads such as `clawbox_get` are checked against that identity as by
+/// `clawbox_get_as_agent`, and audit entries name it.
+///
+/// # Safety
+/// `handle` must be a valid pointer; `actor` must be null or a valid string
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_set_actor(handle: *mut ClawBoxHandle, actor: *const c_char) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let actor = if actor.is_null() { ActorInfo::human() } else { str_arg(actor, "actor")?.parse()? };
+        vault.set_actor(actor);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Set the callback asked to approve Sensitive reads by AI agents and apps
+///
+/// Pass a null `callback` to remove it, after which such reads are denied.
+/// The callback runs on the thread doing the read, one call at a time per
+/// handle, and must not call back into the same handle.
+/// The strings it receives are only valid for the duration of the call.
+///
+/// # Safety
+/// `handle` must be a valid pointer; `context` is passed to `callback`
+/// untouched, possibly from another thread, until the callback is replaced
+/// or the handle is closed
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_set_approval_callback(
+    handle: *mut ClawBoxHandle,
+    callback: ClawBoxApprovalCallback,
+    context: *mut c_void,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let context = CallbackContext(context);
+        let handler: Option<ApprovalHandler> = callback.map(|callback| {
+            Box::new(move |request: &ApprovalRequest| {
+                let (Ok(path), Ok(actor)) =
+                    (CString::new(request.path), CString::new(request.actor.identifier.as_str()))
+                else {
+                    return Approval::Denied;
+                };
+                // Use the whole wrapper so the closure captures it, not the raw pointer
+                let context = &context;
+                match callback(path.as_ptr(), actor.as_ptr(), access_level_to_int(request.access), context.0) {
+                    CLAWBOX_APPROVAL_APPROVE => Approval::Approved,
+                    CLAWBOX_APPROVAL_TIMEOUT => Approval::TimedOut,
+                    _ => Approval::Denied,
+                }
+            }) as ApprovalHandler
+        });
+        vault.set_approval_handler(handler);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Free a string returned by clawbox_get, wiping it first
+///
+/// # Safety
+/// `s` must be a valid pointer returned by clawbox_get
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(Zeroizing::new(CString::from_raw(s).into_bytes_with_nul()));
+    }
+}
+
+/// Get a secret value as bytes, which may contain NULs
+///
+/// On success `*out_ptr` points to `*out_len` bytes (null when the value is
+/// empty), which must be released with `clawbox_free_bytes`.
+///
+/// # Safety
+/// `handle`, `path`, `out_ptr` and `out_len` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_bytes(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        out_arg(out_ptr, "out_ptr")?;
+        out_arg(out_len, "out_len")?;
+
+        let value = vault.get_bytes(path)?.ok_or_else(|| clawbox_core::Error::SecretNotFound {
+            path: path.to_string(),
+        })?;
+        *out_len = value.len();
+        // An exactly sized copy boxes without reallocating; `value` is wiped
+        *out_ptr = into_raw_array(value.to_vec());
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Free bytes returned by clawbox_get_bytes, wiping them first
+///
+/// # Safety
+/// `ptr` and `len` must be exactly as returned by `clawbox_get_bytes`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_free_bytes(ptr: *mut u8, len: usize) {
+    drop(Zeroizing::new(from_raw_array(ptr, len)));
+}
+
+/// Get a secret value into a caller-owned buffer, followed by a NUL
+///
+/// Lets the caller reuse one buffer for many reads instead of freeing a
+/// string per value. On success `*out_written` is the value's length without
+/// the NUL. If `buf_len` can't hold the value and its NUL, `buf` is left
+/// untouched, `*out_written` is the size needed and the call fails with
+/// `CLAWBOX_ERR_BUFFER_TOO_SMALL`; the read has been audited either way.
+///
+/// # Safety
+/// `handle`, `path` and `out_written` must be valid pointers; `buf` must
+/// point to `buf_len` writable bytes, or may be null if `buf_len` is 0
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_into(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    buf: *mut c_char,
+    buf_len: usize,
+    out_written: *mut usize,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        out_arg(out_written, "out_written")?;
+        buf_arg(buf, buf_len)?;
+
+        let value = vault.get_secret(path)?.ok_or_else(|| clawbox_core::Error::SecretNotFound {
+            path: path.to_string(),
+        })?;
+        if value.as_bytes().contains(&0) {
+            return Err(FfiError::new(CLAWBOX_ERR_UNKNOWN, "string contains a NUL byte"));
+        }
+        copy_into(value.as_bytes(), true, buf as *mut u8, buf_len, out_written)
+    })
+}
+
+/// Get a secret value as bytes into a caller-owned buffer
+///
+/// `clawbox_get_into` without the NUL, for values that may contain NULs or
+/// not be UTF-8.
+///
+/// # Safety
+/// As for `clawbox_get_into`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_bytes_into(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    buf: *mut u8,
+    buf_len: usize,
+    out_written: *mut usize,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        out_arg(out_written, "out_written")?;
+        buf_arg(buf, buf_len)?;
+
+        let value = vault.get_bytes(path)?.ok_or_else(|| clawbox_core::Error::SecretNotFound {
+            path: path.to_string(),
+        })?;
+        copy_into(&value, false, buf, buf_len, out_written)
+    })
+}
+
+/// Fail unless `buf` can be written for `buf_len` bytes
+fn buf_arg<T>(buf: *mut T, buf_len: usize) -> Result<(), FfiError> {
+    if buf.is_null() && buf_len > 0 {
+        return Err(FfiError::invalid_argument("buf is null"));
+    }
+    Ok(())
+}
+
+/// Copy `value`, and a NUL after it if `nul`, into the caller's buffer
+///
+/// # Safety
+/// `buf` must point to `buf_len` writable bytes, or be null if `buf_len` is 0;
+/// `out_written` must be valid
+unsafe fn copy_into(
+    value: &[u8],
+    nul: bool,
+    buf: *mut u8,
+    buf_len: usize,
+    out_written: *mut usize,
+) -> Result<c_int, FfiError> {
+    let needed = value.len() + nul as usize;
+    if needed > buf_len {
+        *out_written = needed;
+        return Err(FfiError::new(
+            CLAWBOX_ERR_BUFFER_TOO_SMALL,
+            format!("buffer of {} bytes is too small, {} needed", buf_len, needed),
+        ));
+    }
+    if needed > 0 {
+        let out = std::slice::from_raw_parts_mut(buf, needed);
+        out[..value.len()].copy_from_slice(value);
+        if nul {
+            out[value.len()] = 0;
+        }
+    }
+    *out_written = value.len();
+    Ok(CLAWBOX_OK)
+}
+
+/// Set a secret value
+/// # Safety
+/// `handle`, `path`, and `value` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_set(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    value: *const c_char,
+    access_level: c_int,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        let value = str_arg(value, "value")?;
+
+        let opts = clawbox_core::SetOptions {
+            access: access_level_from_int(access_level)?,
+            ..Default::default()
+        };
+
+        vault.set(path, value, opts)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Set a secret value from bytes, which may contain NULs
+///
+/// # Safety
+/// `handle` and `path` must be valid pointers; `value_ptr` must point to
+/// `value_len` readable bytes (it may be null when `value_len` is 0);
+/// `options` must be null or point to a valid `ClawBoxSetOptions`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_set_bytes(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    value_ptr: *const u8,
+    value_len: usize,
+    options: *const ClawBoxSetOptions,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        let value = match (value_ptr.is_null(), value_len) {
+            (true, 0) => &[][..],
+            (true, _) => return Err(FfiError::invalid_argument("value_ptr is null")),
+            (false, len) => std::slice::from_raw_parts(value_ptr, len),
+        };
+        let opts = set_options(options)?;
+
+        vault.set_bytes(path, value, opts)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Generate a random password
+///
+/// Uses the same rules as the rest of ClawBox. On success `*out_value` must
+/// be released with `clawbox_free_string`. A zero length, no enabled
+/// character class or a length above 4096 is `CLAWBOX_ERR_INVALID_ARG`.
+///
+/// # Safety
+/// `options` must be null or point to a valid `ClawBoxGeneratorOptions`;
+/// `out_value` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_generate_password(
+    options: *const ClawBoxGeneratorOptions,
+    out_value: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        out_arg(out_value, "out_value")?;
+        let password = generator::generate(&generator_options(options)?)?;
+        *out_value = c_string(password.to_string())?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Generate a random password and store it in one call
+///
+/// The value never has to round-trip through the caller. If `out_value` is
+/// not null it receives a copy, to be released with `clawbox_free_string`;
+/// otherwise read it back with `clawbox_get` when needed.
+///
+/// # Safety
+/// `handle` and `path` must be valid pointers; `options` and `set_options`
+/// must be null or point to valid structs; `out_value` must be null or a
+/// valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_generate_and_set(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    options: *const ClawBoxGeneratorOptions,
+    set_options: *const ClawBoxSetOptions,
+    out_value: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        let opts = generator_options(options)?;
+        let set_opts = self::set_options(set_options)?;
+
+        let password = generator::generate(&opts)?;
+        vault.set(path, &password, set_opts)?;
+        if let Some(out_value) = out_value.as_mut() {
+            *out_value = c_string(password.to_string())?.into_raw();
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Delete a secret, keeping its history
+///
+/// # Safety
+/// `handle` and `path` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_delete(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+) -> c_int {
+    clawbox_delete_ex(handle, path, 0)
+}
+
+/// `clawbox_delete_ex` flags
+pub const CLAWBOX_DELETE_HARD: u32 = 1;
+
+/// Delete a secret with `CLAWBOX_DELETE_*` flags
+///
+/// `CLAWBOX_DELETE_HARD` also erases the secret's history and vacuums the
+/// database, so the value cannot be recovered or rolled back; it also
+/// purges the history of a secret that was already deleted. Unknown flags
+/// are rejected.
+///
+/// # Safety
+/// `handle` and `path` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_delete_ex(handle: *mut ClawBoxHandle, path: *const c_char, flags: u32) -> c_int {
+    catch(|| {
+        if flags & !CLAWBOX_DELETE_HARD != 0 {
+            return Err(FfiError::invalid_argument(format!("Unknown delete flags {:#x}", flags)));
+        }
+        let mut vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+
+        let mode = if flags & CLAWBOX_DELETE_HARD != 0 { DeleteMode::Hard } else { DeleteMode::Soft };
+        if !vault.delete(path, mode)? {
+            return Err(clawbox_core::Error::SecretNotFound { path: path.to_string() }.into());
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Delete every secret under a path prefix, in one transaction
+///
+/// The prefix is literal (`"db/"` deletes `db/url` but not `dbx`) and must not
+/// be empty; use `clawbox_delete_all` to empty the vault. When nothing matches
+/// the call succeeds with a count of 0, or fails with `CLAWBOX_ERR_NOT_FOUND`
+/// if `strict` is non-zero. `out_deleted` may be null.
+///
+/// # Safety
+/// `handle` and `prefix` must be valid pointers; `out_deleted` must be null or
+/// a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_delete_prefix(
+    handle: *mut ClawBoxHandle,
+    prefix: *const c_char,
+    strict: c_int,
+    out_deleted: *mut usize,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let prefix = str_arg(prefix, "prefix")?;
+        if prefix.is_empty() {
+            return Err(FfiError::invalid_argument("prefix is empty; use clawbox_delete_all"));
+        }
+
+        let deleted = vault.delete_matching(prefix)?;
+        if let Some(out_deleted) = out_deleted.as_mut() {
+            *out_deleted = deleted.len();
+        }
+        if deleted.is_empty() && strict != 0 {
+            return Err(FfiError::new(CLAWBOX_ERR_NOT_FOUND, format!("No secrets under '{}'", prefix)));
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Delete every secret in the vault, in one transaction
+///
+/// Version history is kept. `out_deleted` may be null.
+///
+/// # Safety
+/// `handle` must be a valid pointer; `out_deleted` must be null or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_delete_all(handle: *mut ClawBoxHandle, out_deleted: *mut usize) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let deleted = vault.delete_matching("")?;
+        if let Some(out_deleted) = out_deleted.as_mut() {
+            *out_deleted = deleted.len();
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Move a secret, with its metadata and history, to a new path
+///
+/// Fails with `CLAWBOX_ERR_EXISTS` if `new_path` is taken and
+/// `CLAWBOX_ERR_NOT_FOUND` if `old_path` does not exist.
+///
+/// # Safety
+/// `handle`, `old_path` and `new_path` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_rename(
+    handle: *mut ClawBoxHandle,
+    old_path: *const c_char,
+    new_path: *const c_char,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        vault.rename(str_arg(old_path, "old_path")?, str_arg(new_path, "new_path")?)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Copy a secret and its metadata to another path
+///
+/// Fails with `CLAWBOX_ERR_EXISTS` if `dst` is taken and `overwrite` is 0,
+/// and `CLAWBOX_ERR_NOT_FOUND` if `src` does not exist.
+///
+/// # Safety
+/// `handle`, `src` and `dst` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_copy(
+    handle: *mut ClawBoxHandle,
+    src: *const c_char,
+    dst: *const c_char,
+    overwrite: c_int,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        vault.copy(str_arg(src, "src")?, str_arg(dst, "dst")?, overwrite != 0)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// List the paths of secrets matching a pattern
+///
+/// On success the strings and the array in `*out_array` belong to the
+/// caller, who must release them with `clawbox_free_string_array`.
+///
+/// # Safety
+/// `handle` and `out_array` must be valid pointers; `pattern` must be null
+/// (all secrets) or a valid null-terminated C string (e.g. `github/*`)
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_list_paths(
+    handle: *mut ClawBoxHandle,
+    pattern: *const c_char,
+    out_array: *mut ClawBoxStringArray,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let pattern = if pattern.is_null() { None } else { Some(str_arg(pattern, "pattern")?) };
+        out_arg(out_array, "out_array")?;
+        // Other calls on the handle needn't wait for the listing
+        let reader = vault.reader()?;
+        drop(vault);
+
+        let paths = reader.list(pattern)?.into_iter().map(|info| info.path).collect();
+        out_array.write(ClawBoxStringArray::new(paths)?);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Free the strings and the array in a `ClawBoxStringArray`
+///
+/// The struct itself belongs to the caller; it is reset to an empty array,
+/// so freeing twice is harmless.
+///
+/// # Safety
+/// `array` must be null or point to an array filled by this library
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_free_string_array(array: *mut ClawBoxStringArray) {
+    let Some(array) = array.as_mut() else {
+        return;
     };
+    for s in from_raw_array(array.ptr, array.len) {
+        drop(CString::from_raw(s));
+    }
+    array.ptr = ptr::null_mut();
+    array.len = 0;
+}
+
+/// List secrets matching a pattern
+///
+/// On success `*out_items` points to `*out_count` entries (null when there
+/// are none), which must be released with `clawbox_free_secret_list`.
+///
+/// # Safety
+/// `handle`, `out_items` and `out_count` must be valid pointers; `pattern`
+/// must be null (all secrets) or a valid null-terminated C string (e.g. `github/*`)
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_list(
+    handle: *mut ClawBoxHandle,
+    pattern: *const c_char,
+    out_items: *mut *mut ClawBoxSecretInfo,
+    out_count: *mut usize,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let pattern = if pattern.is_null() { None } else { Some(str_arg(pattern, "pattern")?) };
+        out_arg(out_items, "out_items")?;
+        out_arg(out_count, "out_count")?;
+        let reader = vault.reader()?;
+        drop(vault);
+
+        let secrets = reader.list(pattern)?;
+        let mut items = Vec::with_capacity(secrets.len());
+        for secret in secrets {
+            match ClawBoxSecretInfo::new(secret) {
+                Ok(item) => items.push(item),
+                Err(err) => {
+                    for item in items {
+                        free_list_item(item);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        *out_count = items.len();
+        *out_items = into_raw_array(items);
+        Ok(CLAWBOX_OK)
+    })
+}
 
-    match handle.vault.delete(path_str) {
-        Ok(true) => CLAWBOX_OK,
-        Ok(false) => CLAWBOX_ERR_NOT_FOUND,
-        Err(clawbox_core::Error::VaultLocked) => CLAWBOX_ERR_VAULT_LOCKED,
-        Err(_) => CLAWBOX_ERR_UNKNOWN,
+/// Free a list returned by clawbox_list
+///
+/// # Safety
+/// `items` and `count` must be exactly as returned by `clawbox_list`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_free_secret_list(items: *mut ClawBoxSecretInfo, count: usize) {
+    for item in from_raw_array(items, count) {
+        free_list_item(item);
     }
 }
+
+/// Change the master password
+///
+/// Deprecated: prefer `clawbox_change_password_bytes`. Returns `CLAWBOX_ERR_INVALID_PASSWORD` if `old_password` is wrong and
+/// `CLAWBOX_ERR_BUSY` if another process holds the vault database lock.
+///
+/// # Safety
+/// `handle`, `old_password` and `new_password` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_change_password(
+    handle: *mut ClawBoxHandle,
+    old_password: *const c_char,
+    new_password: *const c_char,
+) -> c_int {
+    clawbox_change_password_with_progress(handle, old_password, new_password, None, ptr::null_mut())
+}
+
+/// Change the master password, reporting re-encryption progress
+///
+/// `progress` (may be null) is called on the calling thread with the number
+/// of values re-encrypted so far and the total, starting at `(0, total)`.
+/// It must not call back into the same handle, which is busy until this
+/// function returns. Deprecated: prefer `clawbox_change_password_bytes`.
+///
+/// # Safety
+/// `handle`, `old_password` and `new_password` must be valid pointers;
+/// `context` is passed to `progress` untouched
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_change_password_with_progress(
+    handle: *mut ClawBoxHandle,
+    old_password: *const c_char,
+    new_password: *const c_char,
+    progress: ClawBoxProgressCallback,
+    context: *mut c_void,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let old_password = str_arg(old_password, "old_password")?;
+        let new_password = str_arg(new_password, "new_password")?;
+
+        vault.change_password_with_progress(old_password, new_password, |done, total| {
+            if let Some(progress) = progress {
+                progress(done, total, context);
+            }
+        })?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Change the master password, reading both passwords from byte buffers
+///
+/// Same contract as `clawbox_init_bytes` for the passwords, and as
+/// `clawbox_change_password_with_progress` for `progress` (which may be null).
+///
+/// # Safety
+/// `handle` must be a valid pointer; `old_password` and `new_password` must
+/// point to `old_len` and `new_len` readable bytes; `context` is passed to
+/// `progress` untouched
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_change_password_bytes(
+    handle: *mut ClawBoxHandle,
+    old_password: *const u8,
+    old_len: usize,
+    new_password: *const u8,
+    new_len: usize,
+    progress: ClawBoxProgressCallback,
+    context: *mut c_void,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let old_password = password_arg(old_password, old_len, "old_password")?;
+        let new_password = password_arg(new_password, new_len, "new_password")?;
+
+        vault.change_password_with_progress(
+            password_str(&old_password, "old_password")?,
+            password_str(&new_password, "new_password")?,
+            |done, total| {
+                if let Some(progress) = progress {
+                    progress(done, total, context);
+                }
+            },
+        )?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Get a secret's metadata without its value
+///
+/// Set `out_info->struct_size` before calling. On success the strings in
+/// `*out_info` are owned by the caller and must be released with
+/// `clawbox_free_secret_info`.
+///
+/// # Safety
+/// `handle`, `path` and `out_info` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_get_info(
+    handle: *mut ClawBoxHandle,
+    path: *const c_char,
+    out_info: *mut ClawBoxSecretDetails,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let path = str_arg(path, "path")?;
+        out_arg(out_info, "out_info")?;
+        if (*out_info).struct_size < std::mem::size_of::<ClawBoxSecretDetails>() {
+            return Err(FfiError::invalid_argument("out_info->struct_size is too small"));
+        }
+
+        let info = vault.info(path)?.ok_or_else(|| clawbox_core::Error::SecretNotFound {
+            path: path.to_string(),
+        })?;
+        out_info.write(ClawBoxSecretDetails::new(info)?);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Free the strings in a struct filled by clawbox_get_info
+///
+/// The struct itself belongs to the caller; its pointers are reset to null,
+/// so freeing twice is harmless.
+///
+/// # Safety
+/// `info` must be null or point to a struct filled by `clawbox_get_info`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_free_secret_info(info: *mut ClawBoxSecretDetails) {
+    let Some(info) = info.as_mut() else {
+        return;
+    };
+    if !info.path.is_null() {
+        drop(CString::from_raw(info.path));
+    }
+    if !info.note.is_null() {
+        drop(CString::from_raw(info.note));
+    }
+    for tag in from_raw_array(info.tags, info.tag_count) {
+        drop(CString::from_raw(tag));
+    }
+    info.path = ptr::null_mut();
+    info.note = ptr::null_mut();
+    info.tags = ptr::null_mut();
+    info.tag_count = 0;
+}
+
+/// Filter accepted by `clawbox_audit_query`
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuditQuery {
+    key: Option<String>,
+    since_unix: Option<i64>,
+    limit: Option<usize>,
+    action: Option<String>,
+}
+
+/// Query the audit log
+///
+/// `filter_json` is null or a JSON object with any of `key` (secret path),
+/// `since_unix`, `limit` and `action` (e.g. `"read"`). On success
+/// `*out_json` is a JSON array of entries, newest first, to be released with
+/// `clawbox_free_string`. The log names every secret path, so like
+/// `clawbox_list` this requires an unlocked vault.
+///
+/// # Safety
+/// `handle` and `out_json` must be valid pointers; `filter_json` must be null
+/// or a valid null-terminated C string
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_audit_query(
+    handle: *mut ClawBoxHandle,
+    filter_json: *const c_char,
+    out_json: *mut *mut c_char,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_json, "out_json")?;
+        let query: AuditQuery = if filter_json.is_null() {
+            AuditQuery::default()
+        } else {
+            serde_json::from_str(str_arg(filter_json, "filter_json")?)
+                .map_err(|e| FfiError::invalid_argument(format!("Invalid filter: {}", e)))?
+        };
+
+        if !vault.is_unlocked() {
+            return Err(clawbox_core::Error::VaultLocked.into());
+        }
+
+        let filter = clawbox_core::audit::AuditFilter {
+            key_path: query.key,
+            since: query
+                .since_unix
+                .map(|since| {
+                    chrono::DateTime::from_timestamp(since, 0)
+                        .ok_or_else(|| FfiError::invalid_argument("Invalid filter: since_unix is out of range"))
+                })
+                .transpose()?,
+            action: query
+                .action
+                .map(|action| {
+                    clawbox_core::audit::Action::from_str(&action).ok_or_else(|| {
+                        FfiError::invalid_argument(format!("Invalid filter: unknown action '{}'", action))
+                    })
+                })
+                .transpose()?,
+            limit: query.limit,
+            ..Default::default()
+        };
+
+        let entries = vault.audit(&filter)?;
+        let json = serde_json::to_string(&entries)
+            .map_err(|e| FfiError::new(CLAWBOX_ERR_UNKNOWN, e.to_string()))?;
+        *out_json = c_string(json)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Check the audit log's hash chain
+///
+/// Returns 1 if intact, 0 if it has been tampered with, or -1 on failure
+/// (see `clawbox_last_error_code`). Works while locked: it reads hashes only.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_audit_verify(handle: *mut ClawBoxHandle) -> c_int {
+    catch_value(|| {
+        let vault = vault_of(handle)?;
+        Ok(vault.verify_audit_integrity()? as c_int)
+    })
+}
+
+/// Get vault statistics
+///
+/// Reads aggregates only (no value is decrypted), so it is cheap and works
+/// while the vault is locked.
+///
+/// # Safety
+/// `handle` and `out_stats` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_stats(handle: *mut ClawBoxHandle, out_stats: *mut ClawBoxStats) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_stats, "out_stats")?;
+        if (*out_stats).struct_size < std::mem::size_of::<ClawBoxStats>() {
+            return Err(FfiError::invalid_argument("out_stats->struct_size is too small"));
+        }
+
+        let stats = vault.stats()?;
+        out_stats.write(ClawBoxStats {
+            struct_size: std::mem::size_of::<ClawBoxStats>(),
+            public_count: stats.public_count,
+            normal_count: stats.normal_count,
+            sensitive_count: stats.sensitive_count,
+            critical_count: stats.critical_count,
+            db_size: stats.db_size,
+            audit_entries: stats.audit_entries,
+            last_write: stats.last_write.map_or(0, |t| t.timestamp()),
+        });
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Report written by `clawbox_verify`
+#[derive(serde::Serialize)]
+struct VerifyReport {
+    ok: bool,
+    database_errors: Vec<String>,
+    audit_chain_valid: bool,
+    values_checked: bool,
+    undecryptable: Vec<String>,
+    schema_version: u32,
+    supported_schema_version: u32,
+}
+
+/// Run every integrity check and describe the result as JSON
+///
+/// Checks the database, the audit chain and, while unlocked, that every
+/// stored value and version decrypts (which takes a while on large vaults).
+/// Returns `CLAWBOX_OK` whenever the checks ran, even if they found
+/// problems: `*out_report_json` is an object whose `ok` field is the overall
+/// verdict, to be released with `clawbox_free_string`.
+///
+/// # Safety
+/// `handle` and `out_report_json` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_verify(handle: *mut ClawBoxHandle, out_report_json: *mut *mut c_char) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_report_json, "out_report_json")?;
+
+        let report = vault.verify()?;
+        let report = VerifyReport {
+            ok: report.is_ok(),
+            database_errors: report.database_errors,
+            audit_chain_valid: report.audit_chain_valid,
+            values_checked: report.values_checked,
+            undecryptable: report.undecryptable,
+            schema_version: report.schema_version,
+            supported_schema_version: clawbox_core::storage::SCHEMA_VERSION,
+        };
+        let json = serde_json::to_string(&report).map_err(|e| FfiError::new(CLAWBOX_ERR_UNKNOWN, e.to_string()))?;
+        *out_report_json = c_string(json)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Export secrets into an encrypted bundle
+///
+/// The bundle is encrypted with `passphrase`, not the master password. With
+/// `include_critical`, Critical secrets also need `options->password`, and
+/// fail with `CLAWBOX_ERR_REAUTH_REQUIRED` without it, as for `clawbox_get`.
+/// On success `*out_bytes` points to `*out_len` bytes to be released with
+/// `clawbox_free_bytes`.
+///
+/// # Safety
+/// `handle`, `passphrase`, `out_bytes` and `out_len` must be valid pointers;
+/// `options` must be null or point to a valid `ClawBoxExportOptions`
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_export(
+    handle: *mut ClawBoxHandle,
+    passphrase: *const c_char,
+    options: *const ClawBoxExportOptions,
+    out_bytes: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        let passphrase = str_arg(passphrase, "passphrase")?;
+        out_arg(out_bytes, "out_bytes")?;
+        out_arg(out_len, "out_len")?;
+        let (opts, password) = match options.as_ref() {
+            None => (ExportOptions::default(), None),
+            Some(options) if options.struct_size < std::mem::offset_of!(ClawBoxExportOptions, password) => {
+                return Err(FfiError::invalid_argument("options->struct_size is too small"));
+            }
+            Some(options) => {
+                let covered = options.struct_size >= std::mem::size_of::<ClawBoxExportOptions>();
+                let password = match covered && !options.password.is_null() {
+                    true => Some(password_arg(options.password, options.password_len, "options->password")?),
+                    false => None,
+                };
+                (ExportOptions { include_critical: options.include_critical != 0 }, password)
+            }
+        };
+        let password = password.as_deref().map(|p| password_str(p, "options->password")).transpose()?;
+
+        let (bundle, _) = vault.export_bundle(passphrase, &opts, password)?;
+        *out_len = bundle.len();
+        *out_bytes = into_raw_array(bundle);
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Import a bundle written by `clawbox_export`, in one transaction
+///
+/// `conflict_mode` is `CLAWBOX_CONFLICT_OVERWRITE` or `CLAWBOX_CONFLICT_SKIP`.
+/// A wrong passphrase returns `CLAWBOX_ERR_INVALID_PASSWORD`. When
+/// `out_imported` is not null it receives the number of secrets written.
+///
+/// # Safety
+/// `handle` and `passphrase` must be valid pointers; `bytes` must point to
+/// `len` readable bytes; `out_imported` must be null or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_import(
+    handle: *mut ClawBoxHandle,
+    bytes: *const u8,
+    len: usize,
+    passphrase: *const c_char,
+    conflict_mode: c_int,
+    out_imported: *mut usize,
+) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        if bytes.is_null() {
+            return Err(FfiError::invalid_argument("bytes is null"));
+        }
+        let bundle = std::slice::from_raw_parts(bytes, len);
+        let passphrase = str_arg(passphrase, "passphrase")?;
+        let mode = match conflict_mode {
+            CLAWBOX_CONFLICT_OVERWRITE => ConflictMode::Overwrite,
+            CLAWBOX_CONFLICT_SKIP => ConflictMode::Skip,
+            other => return Err(FfiError::invalid_argument(format!("Unknown conflict mode {}", other))),
+        };
+
+        let report = vault.import_bundle(bundle, passphrase, mode)?;
+        if let Some(out_imported) = out_imported.as_mut() {
+            *out_imported = report.imported.len();
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// iCloud sync for a vault, keyed with its sync key
+fn icloud_sync(vault: &ClawBox) -> Result<ICloudSync, FfiError> {
+    let mut sync = ICloudSync::new(vault.path().to_path_buf(), &vault.vault_id()?);
+    if !sync.is_available() {
+        return Err(clawbox_core::Error::ICloudUnavailable.into());
+    }
+    sync.set_key(vault.sync_key()?);
+    Ok(sync)
+}
+
+/// Get the iCloud sync state
+///
+/// `*out_available` is 1 if iCloud Drive is available, else 0 (and the remote
+/// version 0). Works while locked.
+///
+/// # Safety
+/// `handle` and the out pointers must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_sync_status(
+    handle: *mut ClawBoxHandle,
+    out_local_version: *mut u64,
+    out_remote_version: *mut u64,
+    out_available: *mut c_int,
+) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_local_version, "out_local_version")?;
+        out_arg(out_remote_version, "out_remote_version")?;
+        out_arg(out_available, "out_available")?;
+
+        let sync = ICloudSync::new(vault.path().to_path_buf(), &vault.vault_id()?);
+        let available = sync.is_available();
+        *out_local_version = vault.sync_status()?.local_version;
+        *out_remote_version = if available { sync.remote_version()? } else { 0 };
+        *out_available = available as c_int;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Sync state written by `clawbox_sync_state`
+#[derive(serde::Serialize)]
+struct SyncStateReport {
+    enabled: bool,
+    last_sync: Option<String>,
+    local_version: u64,
+    remote_version: u64,
+    conflicts: usize,
+}
+
+/// Describe the sync state stored in the vault as JSON
+///
+/// `*out_state_json` is released with `clawbox_free_string`. Works while
+/// locked.
+///
+/// # Safety
+/// `handle` and `out_state_json` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_sync_state(handle: *mut ClawBoxHandle, out_state_json: *mut *mut c_char) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_state_json, "out_state_json")?;
+
+        let state = vault.sync_status()?;
+        let report = SyncStateReport {
+            enabled: state.sync_enabled,
+            last_sync: state.last_sync.map(|t| t.to_rfc3339()),
+            local_version: state.local_version,
+            remote_version: state.remote_version,
+            conflicts: state.conflict_count,
+        };
+        let json = serde_json::to_string(&report).map_err(|e| FfiError::new(CLAWBOX_ERR_UNKNOWN, e.to_string()))?;
+        *out_state_json = c_string(json)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Turn sync on (`enabled` non-zero) or off for the vault
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_sync_set_enabled(handle: *mut ClawBoxHandle, enabled: c_int) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        if enabled != 0 {
+            vault.enable_sync()?;
+        } else {
+            vault.disable_sync()?;
+        }
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Upload the vault to iCloud Drive, encrypted with the sync key
+///
+/// Requires an unlocked vault. Returns `CLAWBOX_ERR_ICLOUD_UNAVAILABLE`
+/// without iCloud Drive.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_sync_push(handle: *mut ClawBoxHandle) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let sync = icloud_sync(&vault)?;
+        sync.push()?;
+        vault.record_sync(sync.local_version()?)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Replace the local vault with the copy in iCloud Drive
+///
+/// The local database is first backed up (see `clawbox_list_backups`). Requires an
+/// unlocked vault; afterwards the vault stays unlocked only if the pulled
+/// copy has the same master password.
+///
+/// # Safety
+/// `handle` must be a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_sync_pull(handle: *mut ClawBoxHandle) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let sync = icloud_sync(&vault)?;
+        sync.pull()?;
+        vault.reload()?;
+        vault.record_sync(sync.local_version()?)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// A backup listed by `clawbox_list_backups`
+#[derive(serde::Serialize)]
+struct BackupReport {
+    name: String,
+    created_at: String,
+    size: u64,
+}
+
+/// Describe the backups taken before pulls as a JSON array, newest first
+///
+/// `*out_backups_json` is released with `clawbox_free_string`. Works while
+/// locked.
+///
+/// # Safety
+/// `handle` and `out_backups_json` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_list_backups(handle: *mut ClawBoxHandle, out_backups_json: *mut *mut c_char) -> c_int {
+    catch(|| {
+        let vault = vault_of(handle)?;
+        out_arg(out_backups_json, "out_backups_json")?;
+
+        let backups: Vec<BackupReport> = vault
+            .backups()?
+            .into_iter()
+            .map(|backup| BackupReport {
+                name: backup.name,
+                created_at: backup.created_at.to_rfc3339(),
+                size: backup.size,
+            })
+            .collect();
+        let json = serde_json::to_string(&backups).map_err(|e| FfiError::new(CLAWBOX_ERR_UNKNOWN, e.to_string()))?;
+        *out_backups_json = c_string(json)?.into_raw();
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Swap the backup `name` in for the vault database
+///
+/// The replaced database is kept as a new backup. Fails with
+/// `CLAWBOX_ERR_INVALID_ARG` if there is no such backup or it doesn't
+/// open as a vault. Afterwards the vault stays unlocked only if the backup
+/// has the same master password.
+///
+/// # Safety
+/// `handle` and `name` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_restore_backup(handle: *mut ClawBoxHandle, name: *const c_char) -> c_int {
+    catch(|| {
+        let mut vault = vault_of(handle)?;
+        let name = str_arg(name, "name")?;
+        vault.restore_backup(name, clawbox_core::backup::DEFAULT_KEEP)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Keychain service of the items stored through `clawbox_keychain_*`
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+const KEYCHAIN_SERVICE: &str = "dev.clawbox.app";
+
+#[cfg(all(feature = "keychain", target_os = "macos"))]
+fn app_keychain() -> Result<clawbox_core::keychain::Keychain, FfiError> {
+    Ok(clawbox_core::keychain::Keychain::new(KEYCHAIN_SERVICE))
+}
+
+/// Stands in for the Keychain where there is none; never constructed
+#[cfg(not(all(feature = "keychain", target_os = "macos")))]
+enum NoKeychain {}
+
+#[cfg(not(all(feature = "keychain", target_os = "macos")))]
+impl NoKeychain {
+    fn store(&self, _account: &str, _data: &[u8]) -> clawbox_core::Result<()> {
+        match *self {}
+    }
+
+    fn fetch(&self, _account: &str) -> clawbox_core::Result<Option<Zeroizing<Vec<u8>>>> {
+        match *self {}
+    }
+
+    fn delete(&self, _account: Option<&str>) -> clawbox_core::Result<bool> {
+        match *self {}
+    }
+}
+
+#[cfg(not(all(feature = "keychain", target_os = "macos")))]
+fn app_keychain() -> Result<NoKeychain, FfiError> {
+    Err(FfiError::new(CLAWBOX_ERR_UNKNOWN, "The Keychain is only available on macOS"))
+}
+
+/// Store an opaque blob in the Keychain as `account`, replacing any previous one
+///
+/// The item is readable only while the Mac is unlocked and never leaves this
+/// device. Meant for keeping a session across app launches, e.g. the key
+/// from `clawbox_export_key`; delete it when the session ends.
+///
+/// # Safety
+/// `account` must be a valid pointer; `data` must point to `len` readable
+/// bytes (or be null with `len` 0)
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_keychain_store(account: *const c_char, data: *const u8, len: usize) -> c_int {
+    catch(|| {
+        let account = str_arg(account, "account")?;
+        let data = match (data.is_null(), len) {
+            (true, 0) => &[][..],
+            (true, _) => return Err(FfiError::invalid_argument("data is null")),
+            (false, len) => std::slice::from_raw_parts(data, len),
+        };
+        app_keychain()?.store(account, data)?;
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Read the blob stored as `account`; `CLAWBOX_ERR_NOT_FOUND` if there is none
+///
+/// Release it with `clawbox_free_bytes`.
+///
+/// # Safety
+/// `account`, `out_ptr` and `out_len` must be valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_keychain_fetch(
+    account: *const c_char,
+    out_ptr: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    catch(|| {
+        let account = str_arg(account, "account")?;
+        out_arg(out_ptr, "out_ptr")?;
+        out_arg(out_len, "out_len")?;
+
+        let data = app_keychain()?
+            .fetch(account)?
+            .ok_or_else(|| FfiError::new(CLAWBOX_ERR_NOT_FOUND, format!("No Keychain item for {}", account)))?;
+        *out_len = data.len();
+        *out_ptr = into_raw_array(data.to_vec());
+        Ok(CLAWBOX_OK)
+    })
+}
+
+/// Delete the blob stored as `account`, or every blob stored through this
+/// API if `account` is null
+///
+/// Returns 1 if anything was deleted, 0 if not, -1 on failure.
+///
+/// # Safety
+/// `account` must be null or a valid pointer
+#[no_mangle]
+pub unsafe extern "C" fn clawbox_keychain_delete(account: *const c_char) -> c_int {
+    catch_value(|| {
+        let account = match account.is_null() {
+            true => None,
+            false => Some(str_arg(account, "account")?),
+        };
+        Ok(c_int::from(app_keychain()?.delete(account)?))
+    })
+}
@@ -0,0 +1,98 @@
+//! Counts the heap allocations behind the Mac app's list + preview pattern,
+//! reading a few hundred small secrets in a row, with `clawbox_get` and with
+//! `clawbox_get_into` reusing one buffer
+//!
+//! ```sh
+//! cargo test -p clawbox-ffi --test alloc_count -- --nocapture
+//! ```
+
+use clawbox_ffi::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::{c_char, CString};
+
+/// Counts allocations made on the current thread while `COUNTING` is set
+struct CountingAlloc;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.with(Cell::get) {
+            ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Allocations `f` makes on this thread
+fn count(f: impl FnOnce()) -> usize {
+    ALLOCATIONS.with(|n| n.set(0));
+    COUNTING.with(|c| c.set(true));
+    f();
+    COUNTING.with(|c| c.set(false));
+    ALLOCATIONS.with(Cell::get)
+}
+
+const SECRETS: usize = 300;
+
+#[test]
+fn test_get_into_allocates_less() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+    let paths: Vec<CString> = (0..SECRETS).map(|i| CString::new(format!("app/service{}/token", i)).unwrap()).collect();
+
+    unsafe {
+        let vault = clawbox_open(path.as_ptr());
+        assert!(!vault.is_null());
+        let password = b"correct horse battery staple";
+        assert_eq!(clawbox_init_bytes(vault, password.as_ptr(), password.len()), CLAWBOX_OK);
+        for (i, path) in paths.iter().enumerate() {
+            let value = CString::new(format!("token-{:032}", i)).unwrap();
+            assert_eq!(clawbox_set(vault, path.as_ptr(), value.as_ptr(), CLAWBOX_ACCESS_NORMAL), CLAWBOX_OK);
+        }
+
+        // Warm up the statement cache and read pool before counting
+        let mut buf = vec![0u8; 256];
+        let mut written = 0;
+        assert_eq!(clawbox_get_into(vault, paths[0].as_ptr(), buf.as_mut_ptr() as *mut c_char, buf.len(), &mut written), CLAWBOX_OK);
+
+        let with_get = count(|| {
+            for path in &paths {
+                let mut value = std::ptr::null_mut();
+                assert_eq!(clawbox_get(vault, path.as_ptr(), &mut value), CLAWBOX_OK);
+                clawbox_free_string(value);
+            }
+        });
+        let with_get_into = count(|| {
+            for path in &paths {
+                let result = clawbox_get_into(vault, path.as_ptr(), buf.as_mut_ptr() as *mut c_char, buf.len(), &mut written);
+                assert_eq!(result, CLAWBOX_OK);
+            }
+        });
+        clawbox_close(vault);
+
+        println!("{} reads", SECRETS);
+        println!("clawbox_get:      {:>6} allocations ({:.1} per read)", with_get, with_get as f64 / SECRETS as f64);
+        println!("clawbox_get_into: {:>6} allocations ({:.1} per read)", with_get_into, with_get_into as f64 / SECRETS as f64);
+        // The string clawbox_get hands out is one allocation per read
+        assert!(with_get_into + SECRETS <= with_get, "{} vs {}", with_get_into, with_get);
+    }
+}
@@ -0,0 +1,33 @@
+/* Access levels are validated, never defaulted */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    ClawBoxSecretDetails info = {.struct_size = sizeof(info)};
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "bad", "v", 4) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(strstr(clawbox_last_error_message(), "access level 4") != NULL);
+    CHECK(clawbox_set(vault, "bad", "v", -1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get(vault, "bad", &value) == CLAWBOX_ERR_NOT_FOUND);
+
+    ClawBoxSetOptions options = {.struct_size = sizeof(options), .access_level = 7};
+    CHECK(clawbox_set_bytes(vault, "bad", (const uint8_t *)"v", 1, &options) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get(vault, "bad", &value) == CLAWBOX_ERR_NOT_FOUND);
+
+    const int levels[] = {CLAWBOX_ACCESS_PUBLIC, CLAWBOX_ACCESS_NORMAL, CLAWBOX_ACCESS_SENSITIVE,
+                          CLAWBOX_ACCESS_CRITICAL};
+    for (size_t i = 0; i < sizeof(levels) / sizeof(levels[0]); i++) {
+        CHECK(clawbox_set(vault, "level", "v", levels[i]) == CLAWBOX_OK);
+        CHECK(clawbox_get_info(vault, "level", &info) == CLAWBOX_OK);
+        CHECK(info.access_level == levels[i]);
+        clawbox_free_secret_info(&info);
+
+        options.access_level = levels[i];
+        CHECK(clawbox_set_bytes(vault, "level", (const uint8_t *)"v", 1, &options) == CLAWBOX_OK);
+    }
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,111 @@
+/* clawbox_set_approval_callback / clawbox_get_as_agent / clawbox_set_actor */
+
+#include <pthread.h>
+
+#include "test.h"
+
+#define THREADS 4
+#define ROUNDS 10
+
+static ClawBoxHandle *vault;
+
+struct approver {
+    int answer;
+    int calls;
+    int active;
+    int overlapped;
+    char last_path[64];
+    char last_actor[64];
+    int last_access;
+};
+
+static int approve(const char *path, const char *actor, int access_level, void *context) {
+    struct approver *approver = context;
+    /* Calls on one handle never overlap, so these need no extra locking */
+    if (approver->active++) {
+        approver->overlapped = 1;
+    }
+    approver->calls++;
+    snprintf(approver->last_path, sizeof(approver->last_path), "%s", path);
+    snprintf(approver->last_actor, sizeof(approver->last_actor), "%s", actor);
+    approver->last_access = access_level;
+    approver->active--;
+    return approver->answer;
+}
+
+static void *reader(void *arg) {
+    (void)arg;
+    char *value = NULL;
+    for (int i = 0; i < ROUNDS; i++) {
+        CHECK(clawbox_get_as_agent(vault, "api/token", "agent", &value) == CLAWBOX_OK);
+        CHECK(strcmp(value, "t0k3n") == 0);
+        clawbox_free_string(value);
+    }
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "api/token", "t0k3n", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "root/key", "k", 3) == CLAWBOX_OK);
+
+    /* Normal needs no approval; Sensitive is denied without a callback */
+    CHECK(clawbox_get_as_agent(vault, "db/url", "agent", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+    CHECK(clawbox_get_as_agent(vault, "api/token", "agent", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(clawbox_get_as_agent(vault, "missing", "agent", &value) == CLAWBOX_ERR_NOT_FOUND);
+
+    struct approver approver = {.answer = CLAWBOX_APPROVAL_DENY};
+    CHECK(clawbox_set_approval_callback(vault, approve, &approver) == CLAWBOX_OK);
+
+    CHECK(clawbox_get_as_agent(vault, "api/token", "my-agent", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(approver.calls == 1);
+    CHECK(strcmp(approver.last_path, "api/token") == 0);
+    CHECK(strcmp(approver.last_actor, "my-agent") == 0);
+    CHECK(approver.last_access == 2);
+
+    approver.answer = CLAWBOX_APPROVAL_TIMEOUT;
+    CHECK(clawbox_get_as_agent(vault, "api/token", "my-agent", &value) == CLAWBOX_ERR_APPROVAL_TIMEOUT);
+
+    /* Critical is refused without asking */
+    approver.answer = CLAWBOX_APPROVAL_APPROVE;
+    CHECK(clawbox_get_as_agent(vault, "root/key", "my-agent", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(approver.calls == 2);
+
+    /* The callback is invoked one call at a time from several threads */
+    pthread_t threads[THREADS];
+    for (long i = 0; i < THREADS; i++) {
+        CHECK(pthread_create(&threads[i], NULL, reader, (void *)i) == 0);
+    }
+    for (int i = 0; i < THREADS; i++) {
+        CHECK(pthread_join(threads[i], NULL) == 0);
+    }
+    CHECK(approver.calls == 2 + THREADS * ROUNDS);
+    CHECK(!approver.overlapped);
+
+    /* Plain reads are the app's own and never ask */
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+    CHECK(approver.calls == 2 + THREADS * ROUNDS);
+
+    /* Removing the callback goes back to denying */
+    CHECK(clawbox_set_approval_callback(vault, NULL, NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get_as_agent(vault, "api/token", "agent", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+
+    /* Plain reads by an app acting for itself are checked like an agent's */
+    CHECK(clawbox_set_actor(vault, "app:menubar") == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+    CHECK(clawbox_get(vault, "api/token", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(clawbox_get(vault, "root/key", &value) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(clawbox_set_actor(vault, "robot") == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_set_actor(vault, NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "api/token", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+
+    clawbox_close(vault);
+    return 0;
+}
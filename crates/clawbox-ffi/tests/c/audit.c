@@ -0,0 +1,59 @@
+/* clawbox_audit_query / clawbox_audit_verify */
+
+#include <time.h>
+
+#include "test.h"
+
+static size_t count(const char *haystack, const char *needle) {
+    size_t n = 0;
+    for (const char *p = strstr(haystack, needle); p != NULL; p = strstr(p + 1, needle)) {
+        n++;
+    }
+    return n;
+}
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *json = NULL;
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "github/token", "ghp_123", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "aws/key", "AKIA", 1) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "github/token", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+
+    CHECK(clawbox_audit_query(vault, NULL, &json) == CLAWBOX_OK);
+    CHECK(json[0] == '[');
+    CHECK(count(json, "\"github/token\"") == 2);
+    CHECK(count(json, "\"aws/key\"") == 1);
+    clawbox_free_string(json);
+
+    CHECK(clawbox_audit_query(vault, "{\"key\": \"github/token\", \"action\": \"read\"}", &json) == CLAWBOX_OK);
+    CHECK(count(json, "\"key_path\"") == 1);
+    clawbox_free_string(json);
+
+    CHECK(clawbox_audit_query(vault, "{\"limit\": 2}", &json) == CLAWBOX_OK);
+    CHECK(count(json, "\"key_path\"") == 2);
+    clawbox_free_string(json);
+
+    char filter[64];
+    snprintf(filter, sizeof(filter), "{\"since_unix\": %lld}", (long long)time(NULL) + 3600);
+    CHECK(clawbox_audit_query(vault, filter, &json) == CLAWBOX_OK);
+    CHECK(strcmp(json, "[]") == 0);
+    clawbox_free_string(json);
+
+    CHECK(clawbox_audit_query(vault, "{\"action\": \"fly\"}", &json) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(strstr(clawbox_last_error_message(), "fly") != NULL);
+    CHECK(clawbox_audit_query(vault, "{\"colour\": 1}", &json) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_audit_query(vault, "not json", &json) == CLAWBOX_ERR_INVALID_ARG);
+
+    CHECK(clawbox_audit_verify(vault) == 1);
+
+    /* Verification works while locked, querying does not */
+    clawbox_lock(vault);
+    CHECK(clawbox_audit_verify(vault) == 1);
+    CHECK(clawbox_audit_query(vault, NULL, &json) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,53 @@
+/* clawbox_set_auto_lock / clawbox_time_until_lock */
+
+#include <unistd.h>
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    CHECK(clawbox_time_until_lock(vault) == 0);
+
+    CHECK(clawbox_set_auto_lock(vault, 2) == CLAWBOX_OK);
+    CHECK(clawbox_time_until_lock(vault) == 2);
+
+    /* Successful calls restart the countdown, polling does not */
+    usleep(1200 * 1000);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+    CHECK(clawbox_time_until_lock(vault) == 2);
+    usleep(1200 * 1000);
+    CHECK(clawbox_is_unlocked(vault) == 1);
+    CHECK(clawbox_time_until_lock(vault) == 1);
+    usleep(1500 * 1000);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+    CHECK(clawbox_time_until_lock(vault) == 0);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    /* Unlocking starts a new countdown */
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)PASSWORD, strlen(PASSWORD)) == CLAWBOX_OK);
+    CHECK(clawbox_time_until_lock(vault) == 2);
+
+    char *json = NULL;
+    CHECK(clawbox_audit_query(vault, "{\"action\": \"lock\"}", &json) == CLAWBOX_OK);
+    CHECK(strstr(json, "\"actor_type\":\"system\"") != NULL);
+    CHECK(strstr(json, "\"identifier\":\"auto-lock\"") != NULL);
+    clawbox_free_string(json);
+
+    /* 0 disables it */
+    CHECK(clawbox_set_auto_lock(vault, 0) == CLAWBOX_OK);
+    CHECK(clawbox_time_until_lock(vault) == 0);
+    usleep(2500 * 1000);
+    CHECK(clawbox_is_unlocked(vault) == 1);
+
+    CHECK(clawbox_time_until_lock(NULL) == -1);
+    CHECK(clawbox_set_auto_lock(NULL, 1) == CLAWBOX_ERR_INVALID_ARG);
+
+    /* Closing stops the timer */
+    CHECK(clawbox_set_auto_lock(vault, 60) == CLAWBOX_OK);
+    clawbox_close(vault);
+    return 0;
+}
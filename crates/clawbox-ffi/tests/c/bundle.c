@@ -0,0 +1,65 @@
+/* clawbox_export / clawbox_import */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    uint8_t *bundle = NULL;
+    size_t len = 0;
+    size_t imported = 0;
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "root/key", "k", 3) == CLAWBOX_OK);
+
+    /* Critical secrets need an explicit opt-in */
+    ClawBoxExportOptions options = {.struct_size = sizeof(options), .include_critical = 1};
+    CHECK(clawbox_export(vault, "bundle pass", NULL, &bundle, &len) == CLAWBOX_OK);
+    CHECK(len > 0);
+    CHECK(clawbox_delete(vault, "db/url") == CLAWBOX_OK);
+    CHECK(clawbox_delete(vault, "root/key") == CLAWBOX_OK);
+
+    CHECK(clawbox_import(vault, bundle, len, "wrong", CLAWBOX_CONFLICT_OVERWRITE, &imported) ==
+          CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_import(vault, bundle, len, "bundle pass", 7, &imported) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_import(vault, bundle, len, "bundle pass", CLAWBOX_CONFLICT_OVERWRITE, &imported) == CLAWBOX_OK);
+    CHECK(imported == 1);
+    CHECK(clawbox_get(vault, "root/key", &value) == CLAWBOX_ERR_NOT_FOUND);
+    clawbox_free_bytes(bundle, len);
+
+    CHECK(clawbox_set(vault, "root/key", "k", 3) == CLAWBOX_OK);
+    /* ...and the master password, as for clawbox_get */
+    CHECK(clawbox_export(vault, "bundle pass", &options, &bundle, &len) == CLAWBOX_ERR_REAUTH_REQUIRED);
+    options.password = (const uint8_t *)"wrong";
+    options.password_len = 5;
+    CHECK(clawbox_export(vault, "bundle pass", &options, &bundle, &len) == CLAWBOX_ERR_INVALID_PASSWORD);
+    options.password = (const uint8_t *)PASSWORD;
+    options.password_len = strlen(PASSWORD);
+    CHECK(clawbox_export(vault, "bundle pass", &options, &bundle, &len) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/url", "changed", 1) == CLAWBOX_OK);
+
+    CHECK(clawbox_import(vault, bundle, len, "bundle pass", CLAWBOX_CONFLICT_SKIP, &imported) == CLAWBOX_OK);
+    CHECK(imported == 0);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "changed") == 0);
+    clawbox_free_string(value);
+
+    CHECK(clawbox_import(vault, bundle, len, "bundle pass", CLAWBOX_CONFLICT_OVERWRITE, NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "postgres://") == 0);
+    clawbox_free_string(value);
+    clawbox_free_bytes(bundle, len);
+
+    /* Garbage is rejected */
+    const uint8_t junk[] = "not a bundle";
+    CHECK(clawbox_import(vault, junk, sizeof(junk), "bundle pass", CLAWBOX_CONFLICT_SKIP, NULL) != CLAWBOX_OK);
+
+    /* Both are audited */
+    char *json = NULL;
+    CHECK(clawbox_audit_query(vault, "{\"action\": \"export\"}", &json) == CLAWBOX_OK);
+    CHECK(strstr(json, "root/key") != NULL);
+    clawbox_free_string(json);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,52 @@
+/* clawbox_set_bytes / clawbox_get_bytes / clawbox_free_bytes */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    uint8_t *value = NULL;
+    size_t len = 0;
+
+    /* Embedded and trailing NULs survive */
+    const uint8_t key[] = {0x00, 0x9f, 'a', 0x00, 0xff, 0x00};
+    CHECK(clawbox_set_bytes(vault, "binary/key", key, sizeof(key), NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get_bytes(vault, "binary/key", &value, &len) == CLAWBOX_OK);
+    CHECK(len == sizeof(key));
+    CHECK(memcmp(value, key, len) == 0);
+    clawbox_free_bytes(value, len);
+
+    /* The string API refuses what it can't represent */
+    char *text = NULL;
+    CHECK(clawbox_get(vault, "binary/key", &text) == CLAWBOX_ERR_INVALID_UTF8);
+
+    /* Options are applied */
+    const char *tags[] = {"infra", "prod"};
+    ClawBoxSetOptions options = {
+        .struct_size = sizeof(options),
+        .access_level = 2,
+        .ttl_seconds = 3600,
+        .note = "signing key",
+        .tag_count = 2,
+        .tags = tags,
+    };
+    CHECK(clawbox_set_bytes(vault, "binary/signing", key, 3, &options) == CLAWBOX_OK);
+    ClawBoxSecretDetails info = {.struct_size = sizeof(info)};
+    CHECK(clawbox_get_info(vault, "binary/signing", &info) == CLAWBOX_OK);
+    CHECK(info.access_level == 2 && info.size == 3);
+    CHECK(info.expires_at > info.created_at);
+    CHECK(strcmp(info.note, "signing key") == 0);
+    CHECK(info.tag_count == 2 && strcmp(info.tags[1], "prod") == 0);
+    clawbox_free_secret_info(&info);
+
+    /* Empty values */
+    CHECK(clawbox_set_bytes(vault, "empty", NULL, 0, NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get_bytes(vault, "empty", &value, &len) == CLAWBOX_OK);
+    CHECK(len == 0 && value == NULL);
+    clawbox_free_bytes(value, len);
+
+    CHECK(clawbox_set_bytes(vault, "bad", NULL, 4, NULL) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get_bytes(vault, "missing", &value, &len) == CLAWBOX_ERR_NOT_FOUND);
+
+    clawbox_close(vault);
+    return 0;
+}
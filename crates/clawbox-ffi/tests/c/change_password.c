@@ -0,0 +1,46 @@
+/* clawbox_change_password / clawbox_change_password_with_progress */
+
+#include "test.h"
+
+struct progress {
+    size_t calls;
+    size_t last_done;
+    size_t total;
+};
+
+static void on_progress(size_t done, size_t total, void *context) {
+    struct progress *p = context;
+    CHECK(done <= total);
+    CHECK(p->calls == 0 || done == p->last_done + 1);
+    p->calls++;
+    p->last_done = done;
+    p->total = total;
+}
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "a", "1", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "b", "2", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "a", "3", 1) == CLAWBOX_OK);
+
+    CHECK(clawbox_change_password(vault, "wrong password", "new password 1") == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_change_password(vault, PASSWORD, "new password 1") == CLAWBOX_OK);
+
+    /* Two secrets and three versions */
+    struct progress p = {0};
+    CHECK(clawbox_change_password_with_progress(vault, "new password 1", "new password 2", on_progress, &p) ==
+          CLAWBOX_OK);
+    CHECK(p.total == 5 && p.last_done == 5 && p.calls == 6);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_unlock(vault, "new password 2") == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "a", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "3") == 0);
+    clawbox_free_string(value);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,27 @@
+/* clawbox_delete_ex with CLAWBOX_DELETE_HARD */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://old", CLAWBOX_ACCESS_NORMAL) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/url", "postgres://new", CLAWBOX_ACCESS_NORMAL) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "api/token", "t", CLAWBOX_ACCESS_NORMAL) == CLAWBOX_OK);
+
+    CHECK(clawbox_delete_ex(vault, "db/url", 2) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_delete_ex(vault, "db/url", CLAWBOX_DELETE_HARD) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_ERR_NOT_FOUND);
+    /* No history is left to purge */
+    CHECK(clawbox_delete_ex(vault, "db/url", CLAWBOX_DELETE_HARD) == CLAWBOX_ERR_NOT_FOUND);
+
+    /* A plain delete keeps the history, which a hard delete then erases */
+    CHECK(clawbox_delete(vault, "api/token") == CLAWBOX_OK);
+    CHECK(clawbox_delete(vault, "api/token") == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_delete_ex(vault, "api/token", CLAWBOX_DELETE_HARD) == CLAWBOX_OK);
+    CHECK(clawbox_delete_ex(vault, "api/token", CLAWBOX_DELETE_HARD) == CLAWBOX_ERR_NOT_FOUND);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,50 @@
+/* clawbox_delete_prefix / clawbox_delete_all */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    size_t deleted = 99;
+    size_t count = 0;
+    ClawBoxSecretInfo *items = NULL;
+
+    CHECK(clawbox_set(vault, "app/db/url", "u", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "app/db/pass", "p", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "app/dbx", "x", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "other", "o", 1) == CLAWBOX_OK);
+
+    /* No accidental wipe */
+    CHECK(clawbox_delete_prefix(vault, "", 0, &deleted) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(strstr(clawbox_last_error_message(), "clawbox_delete_all") != NULL);
+    CHECK(clawbox_delete_prefix(vault, NULL, 0, &deleted) == CLAWBOX_ERR_INVALID_ARG);
+
+    CHECK(clawbox_delete_prefix(vault, "app/db/", 0, &deleted) == CLAWBOX_OK);
+    CHECK(deleted == 2);
+    CHECK(clawbox_list(vault, NULL, &items, &count) == CLAWBOX_OK);
+    CHECK(count == 2);
+    clawbox_free_secret_list(items, count);
+
+    CHECK(clawbox_delete_prefix(vault, "app/db/", 0, &deleted) == CLAWBOX_OK);
+    CHECK(deleted == 0);
+    CHECK(clawbox_delete_prefix(vault, "app/db/", 1, NULL) == CLAWBOX_ERR_NOT_FOUND);
+
+    CHECK(clawbox_delete_all(vault, &deleted) == CLAWBOX_OK);
+    CHECK(deleted == 2);
+    CHECK(clawbox_list(vault, NULL, &items, &count) == CLAWBOX_OK);
+    CHECK(count == 0);
+
+    /* One audit entry per call */
+    char *json = NULL;
+    CHECK(clawbox_audit_query(vault, "{\"key\": \"app/db/*\", \"action\": \"delete\"}", &json) == CLAWBOX_OK);
+    const char *first = strstr(json, "\"app/db/*\"");
+    CHECK(first != NULL);
+    CHECK(strstr(first + 1, "\"app/db/*\"") != NULL);
+    CHECK(strstr(json, "app/db/url") == NULL);
+    clawbox_free_string(json);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_delete_prefix(vault, "app/", 0, NULL) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
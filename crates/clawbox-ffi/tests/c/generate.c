@@ -0,0 +1,64 @@
+/* clawbox_generate_password / clawbox_generate_and_set */
+
+#include <ctype.h>
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+    char *stored = NULL;
+
+    CHECK(clawbox_generate_password(NULL, &value) == CLAWBOX_OK);
+    CHECK(strlen(value) == 24);
+    clawbox_free_string(value);
+
+    ClawBoxGeneratorOptions options = {
+        .struct_size = sizeof(options),
+        .length = 32,
+        .flags = CLAWBOX_GEN_UPPERCASE | CLAWBOX_GEN_DIGITS | CLAWBOX_GEN_EXCLUDE_AMBIGUOUS,
+    };
+    CHECK(clawbox_generate_password(&options, &value) == CLAWBOX_OK);
+    CHECK(strlen(value) == 32);
+    for (const char *c = value; *c; c++) {
+        CHECK(isupper((unsigned char)*c) || isdigit((unsigned char)*c));
+        CHECK(strchr("IO01", *c) == NULL);
+    }
+    clawbox_free_string(value);
+
+    /* Invalid combinations */
+    options.length = 0;
+    CHECK(clawbox_generate_password(&options, &value) == CLAWBOX_ERR_INVALID_ARG);
+    options.length = 16;
+    options.flags = CLAWBOX_GEN_EXCLUDE_AMBIGUOUS;
+    CHECK(clawbox_generate_password(&options, &value) == CLAWBOX_ERR_INVALID_ARG);
+    options.flags = 1u << 31;
+    CHECK(clawbox_generate_password(&options, &value) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_generate_password(NULL, NULL) == CLAWBOX_ERR_INVALID_ARG);
+
+    options.flags = CLAWBOX_GEN_LOWERCASE | CLAWBOX_GEN_SYMBOLS;
+    ClawBoxSetOptions set_options = {.struct_size = sizeof(set_options), .access_level = CLAWBOX_ACCESS_SENSITIVE};
+    CHECK(clawbox_generate_and_set(vault, "db/pass", &options, &set_options, &value) == CLAWBOX_OK);
+    CHECK(strlen(value) == 16);
+    CHECK(clawbox_get(vault, "db/pass", &stored) == CLAWBOX_OK);
+    CHECK(strcmp(value, stored) == 0);
+    clawbox_free_string(value);
+    clawbox_free_string(stored);
+
+    ClawBoxSecretDetails info = {.struct_size = sizeof(info)};
+    CHECK(clawbox_get_info(vault, "db/pass", &info) == CLAWBOX_OK);
+    CHECK(info.access_level == CLAWBOX_ACCESS_SENSITIVE);
+    clawbox_free_secret_info(&info);
+
+    /* Nothing is stored when generation fails */
+    options.length = 0;
+    CHECK(clawbox_generate_and_set(vault, "db/other", &options, NULL, NULL) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get(vault, "db/other", &stored) == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_generate_and_set(vault, "db/other", NULL, NULL, NULL) == CLAWBOX_OK);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_generate_and_set(vault, "db/pass", NULL, NULL, NULL) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,36 @@
+/* clawbox_get_info / clawbox_free_secret_info */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    ClawBoxSecretDetails info = {.struct_size = sizeof(info)};
+
+    CHECK(clawbox_set(vault, "github/token", "ghp_123", 3) == CLAWBOX_OK);
+
+    CHECK(clawbox_get_info(vault, "github/token", &info) == CLAWBOX_OK);
+    CHECK(info.struct_size == sizeof(info));
+    CHECK(strcmp(info.path, "github/token") == 0);
+    CHECK(info.access_level == 3);
+    CHECK(info.created_at > 0 && info.updated_at >= info.created_at);
+    CHECK(info.expires_at == 0);
+    CHECK(info.size == strlen("ghp_123"));
+    CHECK(info.note == NULL);
+    CHECK(info.tag_count == 0 && info.tags == NULL);
+    clawbox_free_secret_info(&info);
+    CHECK(info.path == NULL);
+    /* Freeing twice is harmless */
+    clawbox_free_secret_info(&info);
+    clawbox_free_secret_info(NULL);
+
+    CHECK(clawbox_get_info(vault, "missing", &info) == CLAWBOX_ERR_NOT_FOUND);
+
+    ClawBoxSecretDetails old = {.struct_size = sizeof(size_t)};
+    CHECK(clawbox_get_info(vault, "github/token", &old) == CLAWBOX_ERR_INVALID_ARG);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_get_info(vault, "github/token", &info) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
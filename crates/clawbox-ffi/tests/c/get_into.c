@@ -0,0 +1,52 @@
+/* clawbox_get_into / clawbox_get_bytes_into with a reused buffer */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char buf[16];
+    size_t written = 0;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/user", "app", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/long", "a value longer than sixteen bytes", 1) == CLAWBOX_OK);
+
+    /* One buffer serves every read */
+    CHECK(clawbox_get_into(vault, "db/url", buf, sizeof(buf), &written) == CLAWBOX_OK);
+    CHECK(written == 11 && strcmp(buf, "postgres://") == 0);
+    CHECK(clawbox_get_into(vault, "db/user", buf, sizeof(buf), &written) == CLAWBOX_OK);
+    CHECK(written == 3 && strcmp(buf, "app") == 0);
+
+    /* Too small: the buffer is left alone and the size needed comes back */
+    CHECK(clawbox_get_into(vault, "db/long", buf, sizeof(buf), &written) == CLAWBOX_ERR_BUFFER_TOO_SMALL);
+    CHECK(written == 34);
+    CHECK(strcmp(buf, "app") == 0);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_BUFFER_TOO_SMALL);
+    char *large = malloc(written);
+    CHECK(clawbox_get_into(vault, "db/long", large, written, &written) == CLAWBOX_OK);
+    CHECK(strcmp(large, "a value longer than sixteen bytes") == 0);
+    free(large);
+
+    /* A size query with no buffer at all */
+    CHECK(clawbox_get_into(vault, "db/url", NULL, 0, &written) == CLAWBOX_ERR_BUFFER_TOO_SMALL);
+    CHECK(written == 12);
+    CHECK(clawbox_get_into(vault, "db/url", NULL, 4, &written) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get_into(vault, "db/url", buf, sizeof(buf), NULL) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_get_into(vault, "missing", buf, sizeof(buf), &written) == CLAWBOX_ERR_NOT_FOUND);
+
+    /* Bytes: no NUL, so a value fits exactly */
+    const uint8_t key[] = {0x00, 0x9f, 'a', 0x00};
+    uint8_t bytes[4];
+    CHECK(clawbox_set_bytes(vault, "binary/key", key, sizeof(key), NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get_bytes_into(vault, "binary/key", bytes, sizeof(bytes), &written) == CLAWBOX_OK);
+    CHECK(written == 4 && memcmp(bytes, key, 4) == 0);
+    CHECK(clawbox_get_bytes_into(vault, "binary/key", bytes, 3, &written) == CLAWBOX_ERR_BUFFER_TOO_SMALL);
+    CHECK(written == 4);
+    CHECK(clawbox_get_into(vault, "binary/key", buf, sizeof(buf), &written) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_set_bytes(vault, "empty", NULL, 0, NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get_bytes_into(vault, "empty", NULL, 0, &written) == CLAWBOX_OK);
+    CHECK(written == 0);
+
+    clawbox_close(vault);
+    return 0;
+}
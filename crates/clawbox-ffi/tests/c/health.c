@@ -0,0 +1,49 @@
+/* clawbox_stats / clawbox_verify */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    ClawBoxStats stats = {.struct_size = sizeof(stats)};
+    char *report = NULL;
+
+    CHECK(clawbox_stats(vault, &stats) == CLAWBOX_OK);
+    CHECK(stats.public_count + stats.normal_count + stats.sensitive_count + stats.critical_count == 0);
+    CHECK(stats.last_write == 0);
+
+    CHECK(clawbox_set(vault, "public", "p", 0) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "normal/a", "a", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "normal/b", "b", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "critical", "c", 3) == CLAWBOX_OK);
+
+    CHECK(clawbox_stats(vault, &stats) == CLAWBOX_OK);
+    CHECK(stats.public_count == 1);
+    CHECK(stats.normal_count == 2);
+    CHECK(stats.sensitive_count == 0);
+    CHECK(stats.critical_count == 1);
+    CHECK(stats.db_size > 0);
+    CHECK(stats.audit_entries > 0);
+    CHECK(stats.last_write > 0);
+
+    ClawBoxStats small = {.struct_size = sizeof(size_t)};
+    CHECK(clawbox_stats(vault, &small) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_stats(vault, NULL) == CLAWBOX_ERR_INVALID_ARG);
+
+    CHECK(clawbox_verify(vault, &report) == CLAWBOX_OK);
+    CHECK(strstr(report, "\"ok\":true") != NULL);
+    CHECK(strstr(report, "\"values_checked\":true") != NULL);
+    CHECK(strstr(report, "\"undecryptable\":[]") != NULL);
+    clawbox_free_string(report);
+
+    /* Both work while locked; values are then left unchecked */
+    clawbox_lock(vault);
+    CHECK(clawbox_stats(vault, &stats) == CLAWBOX_OK);
+    CHECK(stats.normal_count == 2);
+    CHECK(clawbox_verify(vault, &report) == CLAWBOX_OK);
+    CHECK(strstr(report, "\"ok\":true") != NULL);
+    CHECK(strstr(report, "\"values_checked\":false") != NULL);
+    clawbox_free_string(report);
+
+    clawbox_close(vault);
+    return 0;
+}
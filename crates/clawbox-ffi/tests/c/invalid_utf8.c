@@ -0,0 +1,32 @@
+/* Malformed UTF-8 is reported as CLAWBOX_ERR_INVALID_UTF8 everywhere */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    const char bad[] = {'d', 'b', '/', (char)0xc3, (char)0x28, 0};
+    char *value = NULL;
+
+    CHECK(clawbox_open(bad) == NULL);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(strstr(clawbox_last_error_message(), "path is not valid UTF-8") != NULL);
+
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    CHECK(clawbox_unlock(vault, bad) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)bad, strlen(bad)) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_set(vault, bad, "v", CLAWBOX_ACCESS_NORMAL) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_set(vault, "db/url", bad, CLAWBOX_ACCESS_NORMAL) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(strstr(clawbox_last_error_message(), "value is not valid UTF-8") != NULL);
+    CHECK(clawbox_get(vault, bad, &value) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_get_as_agent(vault, "db/url", bad, &value) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_delete(vault, bad) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(clawbox_rename(vault, "db/url", bad) == CLAWBOX_ERR_INVALID_UTF8);
+
+    /* The same bytes are fine as a binary value, but not through the string API */
+    CHECK(clawbox_set_bytes(vault, "db/raw", (const uint8_t *)bad, strlen(bad), NULL) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/raw", &value) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(strstr(clawbox_last_error_message(), "read it as bytes") != NULL);
+    CHECK(clawbox_get_as_agent(vault, "db/raw", "agent", &value) == CLAWBOX_ERR_INVALID_UTF8);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,26 @@
+/* clawbox_is_initialized and unlocking an uninitialized vault */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    ClawBoxHandle *vault = clawbox_open(argv[1]);
+    CHECK(vault != NULL);
+
+    CHECK(clawbox_is_initialized(vault) == 0);
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_ERR_NOT_INITIALIZED);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_NOT_INITIALIZED);
+
+    CHECK(clawbox_init(vault, PASSWORD) == CLAWBOX_OK);
+    CHECK(clawbox_is_initialized(vault) == 1);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_is_initialized(vault) == 1);
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+
+    CHECK(clawbox_is_initialized(NULL) == -1);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_INVALID_ARG);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,37 @@
+/* clawbox_keychain_store / fetch / delete */
+
+#include "test.h"
+
+#define ACCOUNT "clawbox-c-api-test"
+
+int main(void) {
+    uint8_t *data = NULL;
+    size_t len = 0;
+
+    CHECK(clawbox_keychain_store(NULL, (const uint8_t *)"x", 1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_keychain_store(ACCOUNT, NULL, 1) == CLAWBOX_ERR_INVALID_ARG);
+
+#ifdef __APPLE__
+    uint8_t key[32];
+    CHECK(clawbox_keychain_delete(ACCOUNT) >= 0);
+    CHECK(clawbox_keychain_fetch(ACCOUNT, &data, &len) == CLAWBOX_ERR_NOT_FOUND);
+
+    memset(key, 0x5a, sizeof key);
+    CHECK(clawbox_keychain_store(ACCOUNT, key, sizeof key) == CLAWBOX_OK);
+    CHECK(clawbox_keychain_store(ACCOUNT, key, sizeof key) == CLAWBOX_OK);
+    CHECK(clawbox_keychain_fetch(ACCOUNT, &data, &len) == CLAWBOX_OK);
+    CHECK(len == sizeof key && memcmp(data, key, len) == 0);
+    clawbox_free_bytes(data, len);
+
+    CHECK(clawbox_keychain_delete(ACCOUNT) == 1);
+    CHECK(clawbox_keychain_delete(ACCOUNT) == 0);
+    CHECK(clawbox_keychain_fetch(ACCOUNT, &data, &len) == CLAWBOX_ERR_NOT_FOUND);
+#else
+    CHECK(clawbox_keychain_store(ACCOUNT, (const uint8_t *)"x", 1) == CLAWBOX_ERR_UNKNOWN);
+    CHECK(clawbox_keychain_fetch(ACCOUNT, &data, &len) == CLAWBOX_ERR_UNKNOWN);
+    CHECK(clawbox_keychain_delete(ACCOUNT) == -1);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_UNKNOWN);
+#endif
+
+    return 0;
+}
@@ -0,0 +1,50 @@
+/* clawbox_last_error_code / clawbox_last_error_message */
+
+#include <pthread.h>
+
+#include "test.h"
+
+static void *other_thread(void *arg) {
+    (void)arg;
+    /* Errors are per thread */
+    CHECK(clawbox_last_error_code() == CLAWBOX_OK);
+    CHECK(clawbox_last_error_message() == NULL);
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    CHECK(clawbox_last_error_code() == CLAWBOX_OK);
+    CHECK(clawbox_last_error_message() == NULL);
+
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    clawbox_lock(vault);
+    CHECK(clawbox_unlock(vault, "wrong password") == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(strcmp(clawbox_last_error_message(), "Invalid master password") == 0);
+
+    CHECK(clawbox_get(vault, "github/token", &value) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(strcmp(clawbox_last_error_message(), "Vault is locked") == 0);
+
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "github/token", &value) == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(strstr(clawbox_last_error_message(), "github/token") != NULL);
+
+    CHECK(clawbox_set(vault, NULL, "x", 1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(strcmp(clawbox_last_error_message(), "path is null") == 0);
+
+    CHECK(clawbox_set(vault, "../escape", "x", 1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(strlen(clawbox_last_error_message()) > 0);
+
+    CHECK(clawbox_open(NULL) == NULL);
+    CHECK(strcmp(clawbox_last_error_message(), "path is null") == 0);
+
+    pthread_t thread;
+    CHECK(pthread_create(&thread, NULL, other_thread, NULL) == 0);
+    CHECK(pthread_join(thread, NULL) == 0);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,48 @@
+/* clawbox_list / clawbox_free_secret_list */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+
+    ClawBoxSecretInfo *items = (ClawBoxSecretInfo *)1;
+    size_t count = 42;
+
+    /* Locked vaults refuse to list */
+    clawbox_lock(vault);
+    CHECK(clawbox_list(vault, NULL, &items, &count) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+
+    /* Empty vault: no allocation */
+    CHECK(clawbox_list(vault, NULL, &items, &count) == CLAWBOX_OK);
+    CHECK(count == 0 && items == NULL);
+    clawbox_free_secret_list(items, count);
+
+    CHECK(clawbox_set(vault, "github/token", "ghp_123", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "github/user", "octocat", 0) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "aws/key", "AKIA", 1) == CLAWBOX_OK);
+
+    CHECK(clawbox_list(vault, NULL, &items, &count) == CLAWBOX_OK);
+    CHECK(count == 3);
+    for (size_t i = 0; i < count; i++) {
+        CHECK(items[i].path != NULL);
+        CHECK(items[i].created_at > 0 && items[i].updated_at >= items[i].created_at);
+        CHECK(items[i].tag_count == 0 && items[i].tags == NULL);
+    }
+    clawbox_free_secret_list(items, count);
+
+    CHECK(clawbox_list(vault, "github/*", &items, &count) == CLAWBOX_OK);
+    CHECK(count == 2);
+    for (size_t i = 0; i < count; i++) {
+        CHECK(strncmp(items[i].path, "github/", 7) == 0);
+        if (strcmp(items[i].path, "github/token") == 0) {
+            CHECK(items[i].access_level == 2);
+        } else {
+            CHECK(items[i].access_level == 0);
+        }
+    }
+    clawbox_free_secret_list(items, count);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,50 @@
+/* clawbox_list_paths / clawbox_free_string_array */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    ClawBoxStringArray paths = {0};
+
+    /* An empty vault gives an empty array, which is still safe to free */
+    CHECK(clawbox_list_paths(vault, NULL, &paths) == CLAWBOX_OK);
+    CHECK(paths.len == 0);
+    CHECK(paths.ptr == NULL);
+    clawbox_free_string_array(&paths);
+
+    CHECK(clawbox_set(vault, "github/token", "t", 1) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "github/ssh", "s", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/url", "u", 1) == CLAWBOX_OK);
+
+    CHECK(clawbox_list_paths(vault, NULL, &paths) == CLAWBOX_OK);
+    CHECK(paths.len == 3);
+    int found = 0;
+    for (size_t i = 0; i < paths.len; i++) {
+        found += strcmp(paths.ptr[i], "db/url") == 0;
+    }
+    CHECK(found == 1);
+    clawbox_free_string_array(&paths);
+    CHECK(paths.ptr == NULL && paths.len == 0);
+    clawbox_free_string_array(&paths);
+    clawbox_free_string_array(NULL);
+
+    CHECK(clawbox_list_paths(vault, "github/*", &paths) == CLAWBOX_OK);
+    CHECK(paths.len == 2);
+    for (size_t i = 0; i < paths.len; i++) {
+        CHECK(strncmp(paths.ptr[i], "github/", 7) == 0);
+    }
+    clawbox_free_string_array(&paths);
+
+    /* Repeated allocate/free cycles leak nothing (checked by LeakSanitizer) */
+    for (int i = 0; i < 50; i++) {
+        CHECK(clawbox_list_paths(vault, NULL, &paths) == CLAWBOX_OK);
+        clawbox_free_string_array(&paths);
+    }
+
+    CHECK(clawbox_list_paths(vault, NULL, NULL) == CLAWBOX_ERR_INVALID_ARG);
+    clawbox_lock(vault);
+    CHECK(clawbox_list_paths(vault, NULL, &paths) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
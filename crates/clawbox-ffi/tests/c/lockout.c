@@ -0,0 +1,31 @@
+/* Refusing to unlock after too many wrong passwords */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    const uint8_t *password = (const uint8_t *)PASSWORD;
+    uint64_t retry_after = 1;
+
+    CHECK(clawbox_unlock_retry_after(vault, &retry_after) == CLAWBOX_OK);
+    CHECK(retry_after == 0);
+    CHECK(clawbox_unlock_retry_after(vault, NULL) == CLAWBOX_ERR_INVALID_ARG);
+
+    clawbox_lock(vault);
+    for (int i = 0; i < 5; i++) {
+        CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)"wrong", 5) == CLAWBOX_ERR_INVALID_PASSWORD);
+    }
+
+    /* Even the right password is refused during the cooldown */
+    CHECK(clawbox_unlock_bytes(vault, password, strlen(PASSWORD)) == CLAWBOX_ERR_TOO_MANY_ATTEMPTS);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_TOO_MANY_ATTEMPTS);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+    CHECK(clawbox_unlock_retry_after(vault, &retry_after) == CLAWBOX_OK);
+    CHECK(retry_after > 0 && retry_after <= 30);
+
+    /* The asynchronous unlock is refused before it starts */
+    CHECK(clawbox_unlock_async(vault, password, strlen(PASSWORD), NULL, NULL) == CLAWBOX_ERR_TOO_MANY_ATTEMPTS);
+
+    clawbox_close(vault);
+    return 0;
+}
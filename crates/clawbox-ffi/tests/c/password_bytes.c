@@ -0,0 +1,54 @@
+/* Byte-buffer password calls */
+
+#include "test.h"
+
+#define NEW_PASSWORD "tr0ub4dor&3"
+
+static void count_progress(size_t done, size_t total, void *context) {
+    (void)done;
+    (void)total;
+    ++*(int *)context;
+}
+
+int main(int argc, char **argv) {
+    CHECK(argc == 2);
+    ClawBoxHandle *vault = clawbox_open(argv[1]);
+    CHECK(vault != NULL);
+
+    /* Buffers need not be null-terminated */
+    uint8_t password[sizeof(PASSWORD) - 1];
+    memcpy(password, PASSWORD, sizeof(password));
+    CHECK(clawbox_unlock_bytes(vault, password, sizeof(password)) == CLAWBOX_ERR_NOT_INITIALIZED);
+    CHECK(clawbox_init_bytes(vault, password, sizeof(password)) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "a", "1", 1) == CLAWBOX_OK);
+    clawbox_lock(vault);
+
+    CHECK(clawbox_unlock_bytes(vault, password, sizeof(password) - 1) == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_unlock_bytes(vault, NULL, 0) == CLAWBOX_ERR_INVALID_ARG);
+    const uint8_t not_utf8[] = {0xff, 0xfe};
+    CHECK(clawbox_unlock_bytes(vault, not_utf8, sizeof(not_utf8)) == CLAWBOX_ERR_INVALID_UTF8);
+    CHECK(strstr(clawbox_last_error_message(), "UTF-8") != NULL);
+
+    /* The null-terminated and byte variants agree */
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+    clawbox_lock(vault);
+    CHECK(clawbox_unlock_bytes(vault, password, sizeof(password)) == CLAWBOX_OK);
+
+    int calls = 0;
+    CHECK(clawbox_change_password_bytes(vault, password, sizeof(password), (const uint8_t *)NEW_PASSWORD,
+                                        strlen(NEW_PASSWORD), count_progress, &calls) == CLAWBOX_OK);
+    CHECK(calls > 0);
+    memset(password, 0, sizeof(password));
+
+    clawbox_lock(vault);
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)NEW_PASSWORD, strlen(NEW_PASSWORD)) == CLAWBOX_OK);
+
+    char *value = NULL;
+    CHECK(clawbox_get(vault, "a", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "1") == 0);
+    clawbox_free_string(value);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,52 @@
+/* clawbox_open_ex / clawbox_unlock_with_key / clawbox_export_key */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *plain = open_vault(argc, argv);
+    uint8_t key[CLAWBOX_KEY_LEN];
+    char *value = NULL;
+
+    CHECK(clawbox_set(plain, "db/url", "postgres://", 1) == CLAWBOX_OK);
+
+    /* Disabled unless asked for at open time */
+    CHECK(clawbox_export_key(plain, key, sizeof(key)) == CLAWBOX_ERR_ACCESS_DENIED);
+    CHECK(strstr(clawbox_last_error_message(), "CLAWBOX_OPEN_ALLOW_RAW_KEY") != NULL);
+    CHECK(clawbox_unlock_with_key(plain, key, sizeof(key)) == CLAWBOX_ERR_ACCESS_DENIED);
+    clawbox_close(plain);
+
+    CHECK(clawbox_open_ex(argv[1], 0x80) == NULL);
+
+    ClawBoxHandle *vault = clawbox_open_ex(argv[1], CLAWBOX_OPEN_ALLOW_RAW_KEY);
+    CHECK(vault != NULL);
+    CHECK(clawbox_export_key(vault, key, sizeof(key)) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)PASSWORD, strlen(PASSWORD)) == CLAWBOX_OK);
+
+    /* Fixed length only */
+    CHECK(clawbox_export_key(vault, key, sizeof(key) - 1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_export_key(vault, key, sizeof(key)) == CLAWBOX_OK);
+    clawbox_lock(vault);
+
+    CHECK(clawbox_unlock_with_key(vault, key, sizeof(key) - 1) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+    key[0] ^= 1;
+    CHECK(clawbox_unlock_with_key(vault, key, sizeof(key)) == CLAWBOX_ERR_INVALID_PASSWORD);
+    key[0] ^= 1;
+
+    CHECK(clawbox_unlock_with_key(vault, key, sizeof(key)) == CLAWBOX_OK);
+    memset(key, 0, sizeof(key));
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "postgres://") == 0);
+    clawbox_free_string(value);
+
+    /* Export and both kinds of unlock attempt are audited */
+    char *json = NULL;
+    CHECK(clawbox_audit_query(vault, "{\"key\": \"vault key\"}", &json) == CLAWBOX_OK);
+    CHECK(strstr(json, "\"Export\"") != NULL);
+    CHECK(strstr(json, "\"Unlock\"") != NULL);
+    CHECK(strstr(json, "\"success\":false") != NULL);
+    clawbox_free_string(json);
+
+    clawbox_close(vault);
+    return 0;
+}
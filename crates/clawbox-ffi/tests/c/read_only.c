@@ -0,0 +1,68 @@
+/* clawbox_open_ex with CLAWBOX_OPEN_READ_ONLY */
+
+#include "test.h"
+
+/* Whole contents of `dir`/`name`; sets `*len` */
+static char *read_file(const char *dir, const char *name, long *len) {
+    char path[4096];
+    snprintf(path, sizeof(path), "%s/%s", dir, name);
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    CHECK(fseek(file, 0, SEEK_END) == 0);
+    *len = ftell(file);
+    CHECK(fseek(file, 0, SEEK_SET) == 0);
+    char *data = malloc(*len > 0 ? *len : 1);
+    CHECK(data != NULL);
+    CHECK(fread(data, 1, *len, file) == (size_t)*len);
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *writer = open_vault(argc, argv);
+    CHECK(clawbox_set(writer, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    clawbox_close(writer);
+
+    long before_len = 0;
+    char *before = read_file(argv[1], "vault.db", &before_len);
+    CHECK(before != NULL);
+
+    ClawBoxHandle *vault = clawbox_open_ex(argv[1], CLAWBOX_OPEN_READ_ONLY);
+    CHECK(vault != NULL);
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)"wrong", 5) == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_unlock_bytes(vault, (const uint8_t *)PASSWORD, strlen(PASSWORD)) == CLAWBOX_OK);
+
+    char *value = NULL;
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "postgres://") == 0);
+    clawbox_free_string(value);
+
+    CHECK(clawbox_set(vault, "db/url", "changed", 1) == CLAWBOX_ERR_READ_ONLY);
+    CHECK(clawbox_delete(vault, "db/url") == CLAWBOX_ERR_READ_ONLY);
+    CHECK(clawbox_change_password_bytes(vault, (const uint8_t *)PASSWORD, strlen(PASSWORD),
+                                        (const uint8_t *)"new password", 12, NULL, NULL) == CLAWBOX_ERR_READ_ONLY);
+    clawbox_close(vault);
+
+    /* Not a byte of the database changed; the read went to the sidecar */
+    long after_len = 0;
+    char *after = read_file(argv[1], "vault.db", &after_len);
+    CHECK(after != NULL);
+    CHECK(after_len == before_len && memcmp(before, after, before_len) == 0);
+    free(before);
+    free(after);
+
+    long sidecar_len = 0;
+    char *sidecar = read_file(argv[1], "audit-read-only.jsonl", &sidecar_len);
+    CHECK(sidecar != NULL);
+    CHECK(sidecar_len > 0);
+    free(sidecar);
+
+    /* A vault that doesn't exist yet isn't created */
+    char missing[4096];
+    snprintf(missing, sizeof(missing), "%s/missing", argv[1]);
+    CHECK(clawbox_open_ex(missing, CLAWBOX_OPEN_READ_ONLY) == NULL);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_NOT_INITIALIZED);
+    return 0;
+}
@@ -0,0 +1,29 @@
+/* clawbox_get_with_reauth */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    const uint8_t *password = (const uint8_t *)PASSWORD;
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "recovery/codes", "1234", CLAWBOX_ACCESS_CRITICAL) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/url", "postgres://", CLAWBOX_ACCESS_SENSITIVE) == CLAWBOX_OK);
+
+    /* An unlocked vault is not enough for Critical secrets */
+    CHECK(clawbox_get(vault, "recovery/codes", &value) == CLAWBOX_ERR_REAUTH_REQUIRED);
+    CHECK(clawbox_get_with_reauth(vault, "recovery/codes", (const uint8_t *)"wrong", 5, &value) ==
+          CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_get_with_reauth(vault, "recovery/codes", password, strlen(PASSWORD), &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "1234") == 0);
+    clawbox_free_string(value);
+
+    /* Other levels read as usual, with or without the password */
+    CHECK(clawbox_get_with_reauth(vault, "db/url", password, strlen(PASSWORD), &value) == CLAWBOX_OK);
+    clawbox_free_string(value);
+    CHECK(clawbox_get_with_reauth(vault, "missing", password, strlen(PASSWORD), &value) == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_get_with_reauth(vault, "db/url", NULL, 0, &value) == CLAWBOX_ERR_INVALID_ARG);
+
+    clawbox_close(vault);
+    return 0;
+}
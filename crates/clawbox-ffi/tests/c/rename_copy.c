@@ -0,0 +1,50 @@
+/* clawbox_rename / clawbox_copy */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/old", "v1", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "db/old", "v2", 2) == CLAWBOX_OK);
+    CHECK(clawbox_set(vault, "taken", "x", 1) == CLAWBOX_OK);
+
+    CHECK(clawbox_rename(vault, "db/old", "taken") == CLAWBOX_ERR_EXISTS);
+    CHECK(clawbox_rename(vault, "missing", "db/new") == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_rename(vault, "db/old", "") == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_rename(vault, NULL, "db/new") == CLAWBOX_ERR_INVALID_ARG);
+
+    CHECK(clawbox_rename(vault, "db/old", "db/new") == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/old", &value) == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_get(vault, "db/new", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "v2") == 0);
+    clawbox_free_string(value);
+
+    CHECK(clawbox_copy(vault, "db/new", "taken", 0) == CLAWBOX_ERR_EXISTS);
+    CHECK(clawbox_copy(vault, "missing", "db/copy", 0) == CLAWBOX_ERR_NOT_FOUND);
+    CHECK(clawbox_copy(vault, "db/new", "taken", 1) == CLAWBOX_OK);
+    CHECK(clawbox_copy(vault, "db/new", "db/copy", 0) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "taken", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "v2") == 0);
+    clawbox_free_string(value);
+
+    /* The copy keeps the access level */
+    ClawBoxSecretDetails info = {.struct_size = sizeof(info)};
+    CHECK(clawbox_get_info(vault, "db/copy", &info) == CLAWBOX_OK);
+    CHECK(info.access_level == 2);
+    clawbox_free_secret_info(&info);
+
+    char *json = NULL;
+    CHECK(clawbox_audit_query(vault, "{\"key\": \"db/old -> db/new\"}", &json) == CLAWBOX_OK);
+    CHECK(strstr(json, "\"Rename\"") != NULL);
+    clawbox_free_string(json);
+    CHECK(clawbox_audit_verify(vault) == 1);
+
+    clawbox_lock(vault);
+    CHECK(clawbox_rename(vault, "db/new", "db/other") == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_copy(vault, "db/new", "db/other", 0) == CLAWBOX_ERR_VAULT_LOCKED);
+
+    clawbox_close(vault);
+    return 0;
+}
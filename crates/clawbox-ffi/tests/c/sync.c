@@ -0,0 +1,71 @@
+/* clawbox_sync_status / clawbox_sync_state / clawbox_sync_push / clawbox_sync_pull /
+ * clawbox_list_backups / clawbox_restore_backup */
+
+#include <sys/stat.h>
+#include <unistd.h>
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    uint64_t local = 99, remote = 99;
+    int available = -1;
+    char home[4096], path[4096];
+    char *value = NULL, *state = NULL, *backups = NULL, name[64];
+
+    /* iCloud Drive lives under $HOME; start with a home that has none */
+    CHECK(snprintf(home, sizeof(home), "%s/home", argv[1]) < (int)sizeof(home));
+    CHECK(mkdir(home, 0700) == 0);
+    CHECK(setenv("HOME", home, 1) == 0);
+
+    CHECK(clawbox_sync_status(vault, &local, &remote, &available) == CLAWBOX_OK);
+    CHECK(available == 0 && local == 0 && remote == 0);
+    CHECK(clawbox_sync_push(vault) == CLAWBOX_ERR_ICLOUD_UNAVAILABLE);
+    CHECK(clawbox_sync_pull(vault) == CLAWBOX_ERR_ICLOUD_UNAVAILABLE);
+
+    CHECK(snprintf(path, sizeof(path), "%s/Library", home) < (int)sizeof(path));
+    CHECK(mkdir(path, 0700) == 0);
+    CHECK(snprintf(path, sizeof(path), "%s/Library/Mobile Documents", home) < (int)sizeof(path));
+    CHECK(mkdir(path, 0700) == 0);
+
+    CHECK(clawbox_set(vault, "db/url", "pushed", 1) == CLAWBOX_OK);
+    CHECK(clawbox_sync_push(vault) == CLAWBOX_OK);
+    CHECK(clawbox_sync_status(vault, &local, &remote, &available) == CLAWBOX_OK);
+    CHECK(available == 1 && local == 1 && remote == 1);
+    CHECK(clawbox_sync_state(vault, &state) == CLAWBOX_OK);
+    CHECK(strstr(state, "\"local_version\":1") != NULL);
+    CHECK(strstr(state, "\"last_sync\":null") == NULL);
+    clawbox_free_string(state);
+
+    /* Pull replaces local changes, keeping a backup, and stays unlocked */
+    CHECK(clawbox_set(vault, "db/url", "local edit", 1) == CLAWBOX_OK);
+    CHECK(clawbox_sync_pull(vault) == CLAWBOX_OK);
+    CHECK(clawbox_list_backups(vault, &backups) == CLAWBOX_OK);
+    CHECK(sscanf(backups, "[{\"name\":\"%63[^\"]\"", name) == 1);
+    clawbox_free_string(backups);
+    CHECK(snprintf(path, sizeof(path), "%s/backups/%s", argv[1], name) < (int)sizeof(path));
+    CHECK(access(path, F_OK) == 0);
+    CHECK(clawbox_is_unlocked(vault) == 1);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "pushed") == 0);
+    clawbox_free_string(value);
+
+    /* The local edit comes back from the backup */
+    CHECK(clawbox_restore_backup(vault, "vault-19700101T000000.db") == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_restore_backup(vault, name) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "local edit") == 0);
+    clawbox_free_string(value);
+
+    /* Moving data needs the key; status does not */
+    clawbox_lock(vault);
+    CHECK(clawbox_sync_push(vault) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_sync_status(vault, &local, &remote, &available) == CLAWBOX_OK);
+    CHECK(clawbox_sync_set_enabled(vault, 1) == CLAWBOX_OK);
+    CHECK(clawbox_sync_state(vault, &state) == CLAWBOX_OK);
+    CHECK(strstr(state, "\"enabled\":true") != NULL);
+    clawbox_free_string(state);
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,39 @@
+/* Shared helpers for the C API tests (see tests/c_api.rs) */
+
+#ifndef CLAWBOX_TEST_H
+#define CLAWBOX_TEST_H
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* The tests still cover the deprecated null-terminated password calls */
+#define CLAWBOX_ALLOW_DEPRECATED
+#include "clawbox.h"
+
+#define PASSWORD "correct horse battery staple"
+
+#define CHECK(cond)                                                          \
+    do {                                                                     \
+        if (!(cond)) {                                                       \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, \
+                    #cond);                                                  \
+            if (clawbox_last_error_message() != NULL) {                      \
+                fprintf(stderr, "last error: %s\n",                          \
+                        clawbox_last_error_message());                       \
+            }                                                                \
+            exit(1);                                                         \
+        }                                                                    \
+    } while (0)
+
+/* Open and initialize a fresh vault in the directory given as argv[1] */
+static inline ClawBoxHandle *open_vault(int argc, char **argv) {
+    CHECK(argc == 2);
+    ClawBoxHandle *vault = clawbox_open(argv[1]);
+    CHECK(vault != NULL);
+    CHECK(clawbox_init_bytes(vault, (const uint8_t *)PASSWORD, strlen(PASSWORD)) == CLAWBOX_OK);
+    CHECK(clawbox_is_unlocked(vault) == 1);
+    return vault;
+}
+
+#endif /* CLAWBOX_TEST_H */
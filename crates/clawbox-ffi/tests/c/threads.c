@@ -0,0 +1,87 @@
+/* Concurrent calls on one handle */
+
+#include <pthread.h>
+
+#include "test.h"
+
+#define THREADS 8
+#define ROUNDS 25
+
+static ClawBoxHandle *vault;
+
+static void *worker(void *arg) {
+    int id = (int)(long)arg;
+    char path[32];
+    char value[32];
+    char *read = NULL;
+
+    for (int i = 0; i < ROUNDS; i++) {
+        snprintf(path, sizeof(path), "thread/%d", id);
+        snprintf(value, sizeof(value), "%d-%d", id, i);
+
+        /* Another thread may have locked the vault in between */
+        int rc = clawbox_set(vault, path, value, 1);
+        CHECK(rc == CLAWBOX_OK || rc == CLAWBOX_ERR_VAULT_LOCKED);
+
+        rc = clawbox_get(vault, "shared", &read);
+        CHECK(rc == CLAWBOX_OK || rc == CLAWBOX_ERR_VAULT_LOCKED);
+        if (rc == CLAWBOX_OK) {
+            CHECK(strcmp(read, "shared value") == 0);
+            clawbox_free_string(read);
+        }
+
+        /* Copy the shared secret in and rename it back and forth */
+        char copy[32];
+        char moved[32];
+        CHECK(snprintf(copy, sizeof(copy), "copy/%d", id) < (int)sizeof(copy));
+        CHECK(snprintf(moved, sizeof(moved), "moved/%d", id) < (int)sizeof(moved));
+        rc = clawbox_copy(vault, "shared", copy, 1);
+        CHECK(rc == CLAWBOX_OK || rc == CLAWBOX_ERR_VAULT_LOCKED);
+        if (rc == CLAWBOX_OK) {
+            rc = clawbox_rename(vault, copy, moved);
+            CHECK(rc == CLAWBOX_OK || rc == CLAWBOX_ERR_EXISTS || rc == CLAWBOX_ERR_VAULT_LOCKED);
+            rc = clawbox_delete(vault, moved);
+            CHECK(rc == CLAWBOX_OK || rc == CLAWBOX_ERR_NOT_FOUND || rc == CLAWBOX_ERR_VAULT_LOCKED);
+        }
+
+        ClawBoxSecretInfo *items = NULL;
+        size_t count = 0;
+        if (clawbox_list(vault, "thread/*", &items, &count) == CLAWBOX_OK) {
+            clawbox_free_secret_list(items, count);
+        }
+
+        /* One thread keeps locking and unlocking (Argon2 is slow; do it rarely) */
+        if (id == 0 && i % 10 == 0) {
+            clawbox_lock(vault);
+            CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+        }
+        clawbox_is_unlocked(vault);
+    }
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    vault = open_vault(argc, argv);
+    CHECK(clawbox_set(vault, "shared", "shared value", 1) == CLAWBOX_OK);
+
+    pthread_t threads[THREADS];
+    for (long i = 0; i < THREADS; i++) {
+        CHECK(pthread_create(&threads[i], NULL, worker, (void *)i) == 0);
+    }
+    for (int i = 0; i < THREADS; i++) {
+        CHECK(pthread_join(threads[i], NULL) == 0);
+    }
+
+    CHECK(clawbox_unlock(vault, PASSWORD) == CLAWBOX_OK);
+    char *value = NULL;
+    for (int id = 0; id < THREADS; id++) {
+        char path[32];
+        snprintf(path, sizeof(path), "thread/%d", id);
+        CHECK(clawbox_get(vault, path, &value) == CLAWBOX_OK);
+        clawbox_free_string(value);
+    }
+    CHECK(clawbox_audit_verify(vault) == 1);
+
+    clawbox_close(vault);
+    return 0;
+}
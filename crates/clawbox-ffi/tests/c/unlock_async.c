@@ -0,0 +1,86 @@
+/* clawbox_unlock_async / clawbox_cancel_unlock */
+
+#include <pthread.h>
+
+#include "test.h"
+
+typedef struct {
+    pthread_mutex_t mutex;
+    pthread_cond_t cond;
+    int done;
+    int result;
+} Completion;
+
+static void on_unlock(int result, void *context) {
+    Completion *completion = context;
+    pthread_mutex_lock(&completion->mutex);
+    completion->done = 1;
+    completion->result = result;
+    pthread_cond_signal(&completion->cond);
+    pthread_mutex_unlock(&completion->mutex);
+}
+
+static int wait_for(Completion *completion) {
+    pthread_mutex_lock(&completion->mutex);
+    while (!completion->done) {
+        pthread_cond_wait(&completion->cond, &completion->mutex);
+    }
+    completion->done = 0;
+    pthread_mutex_unlock(&completion->mutex);
+    return completion->result;
+}
+
+static int unlock_async(ClawBoxHandle *vault, const char *password, Completion *completion) {
+    return clawbox_unlock_async(vault, (const uint8_t *)password, strlen(password), on_unlock, completion);
+}
+
+int main(int argc, char **argv) {
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    Completion completion = {PTHREAD_MUTEX_INITIALIZER, PTHREAD_COND_INITIALIZER, 0, 0};
+    char *value = NULL;
+
+    CHECK(clawbox_set(vault, "db/url", "postgres://", 1) == CLAWBOX_OK);
+    clawbox_lock(vault);
+
+    /* Key derivation takes far longer than these calls */
+    CHECK(unlock_async(vault, PASSWORD, &completion) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_set(vault, "db/other", "x", 1) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+    CHECK(unlock_async(vault, PASSWORD, &completion) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(wait_for(&completion) == CLAWBOX_OK);
+    CHECK(clawbox_is_unlocked(vault) == 1);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_OK);
+    CHECK(strcmp(value, "postgres://") == 0);
+    clawbox_free_string(value);
+
+    clawbox_lock(vault);
+    CHECK(unlock_async(vault, "wrong", &completion) == CLAWBOX_OK);
+    CHECK(wait_for(&completion) == CLAWBOX_ERR_INVALID_PASSWORD);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+
+    /* Cancelled attempts leave the handle usable and locked */
+    CHECK(unlock_async(vault, PASSWORD, &completion) == CLAWBOX_OK);
+    CHECK(clawbox_cancel_unlock(vault) == CLAWBOX_OK);
+    CHECK(clawbox_get(vault, "db/url", &value) == CLAWBOX_ERR_VAULT_LOCKED);
+    CHECK(strstr(clawbox_last_error_message(), "being unlocked") == NULL);
+    CHECK(wait_for(&completion) == CLAWBOX_ERR_CANCELLED);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+    CHECK(clawbox_cancel_unlock(vault) == CLAWBOX_OK);
+
+    CHECK(unlock_async(vault, PASSWORD, &completion) == CLAWBOX_OK);
+    clawbox_lock(vault);
+    CHECK(wait_for(&completion) == CLAWBOX_ERR_CANCELLED);
+    CHECK(clawbox_is_unlocked(vault) == 0);
+
+    /* Invalid arguments fail at once without calling back */
+    CHECK(clawbox_unlock_async(vault, NULL, 0, on_unlock, &completion) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_unlock_async(vault, (const uint8_t *)"x", 1, NULL, NULL) == CLAWBOX_ERR_INVALID_ARG);
+    CHECK(clawbox_unlock_async(NULL, (const uint8_t *)"x", 1, on_unlock, &completion) == CLAWBOX_ERR_INVALID_ARG);
+
+    /* Closing mid-unlock still calls back, after the handle is gone */
+    CHECK(unlock_async(vault, PASSWORD, &completion) == CLAWBOX_OK);
+    clawbox_close(vault);
+    CHECK(wait_for(&completion) == CLAWBOX_ERR_CANCELLED);
+    return 0;
+}
@@ -0,0 +1,26 @@
+/* clawbox_version / clawbox_abi_version / schema versions */
+
+#include "test.h"
+
+int main(int argc, char **argv) {
+    CHECK(clawbox_abi_version() == CLAWBOX_ABI_VERSION);
+
+    const char *version = clawbox_version();
+    CHECK(version != NULL);
+    CHECK(strchr(version, '.') != NULL);
+    CHECK(clawbox_version() == version);
+
+    CHECK(clawbox_core_schema_version(NULL) == -1);
+    CHECK(clawbox_last_error_code() == CLAWBOX_ERR_INVALID_ARG);
+
+    ClawBoxHandle *vault = open_vault(argc, argv);
+    CHECK(clawbox_supported_schema_version() > 0);
+    CHECK(clawbox_core_schema_version(vault) == clawbox_supported_schema_version());
+
+    /* Readable while locked, so the app can warn before asking for a password */
+    clawbox_lock(vault);
+    CHECK(clawbox_core_schema_version(vault) == clawbox_supported_schema_version());
+
+    clawbox_close(vault);
+    return 0;
+}
@@ -0,0 +1,208 @@
+//! Runs the C programs in `tests/c` against the static library
+//!
+//! Each program is compiled with AddressSanitizer where the compiler
+//! supports it, so leaks and double frees across the C boundary fail the
+//! test. Programs get a fresh vault directory as their only argument.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The static library, built next to this test as one of its dependencies
+fn static_lib() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // target/<profile>/deps/c_api-<hash>
+    exe.with_file_name("libclawbox_ffi.a")
+}
+
+/// System libraries a Rust static library needs
+fn native_libs() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &["-framework", "Security", "-framework", "CoreFoundation", "-liconv"]
+    } else {
+        &["-lpthread", "-ldl", "-lm"]
+    }
+}
+
+fn compile(source: &Path, out: &Path, asan: bool) -> bool {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut cmd = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()));
+    if asan {
+        cmd.args(["-fsanitize=address", "-fno-omit-frame-pointer"]);
+    }
+    cmd.args(["-g", "-Wall", "-Werror"])
+        .arg("-I")
+        .arg(manifest.join("include"))
+        .arg(source)
+        .arg(static_lib())
+        .args(native_libs())
+        .arg("-o")
+        .arg(out);
+    cmd.status().map(|s| s.success()).unwrap_or(false)
+}
+
+fn run_c_test(name: &str) {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let source = manifest.join("tests/c").join(format!("{}.c", name));
+    let build = tempfile::TempDir::new().unwrap();
+    let binary = build.path().join(name);
+
+    let asan = compile(&source, &binary, true);
+    if !asan {
+        assert!(compile(&source, &binary, false), "failed to compile {:?}", source);
+    }
+
+    let vault = tempfile::TempDir::new().unwrap();
+    let output = Command::new(&binary).arg(vault.path()).output().unwrap();
+    assert!(
+        output.status.success(),
+        "{} failed{}:\n{}",
+        name,
+        if asan { " (ASan)" } else { "" },
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn test_list() {
+    run_c_test("list");
+}
+
+#[test]
+fn test_last_error() {
+    run_c_test("last_error");
+}
+
+#[test]
+fn test_change_password() {
+    run_c_test("change_password");
+}
+
+#[test]
+fn test_get_info() {
+    run_c_test("get_info");
+}
+
+#[test]
+fn test_is_initialized() {
+    run_c_test("is_initialized");
+}
+
+#[test]
+fn test_bytes() {
+    run_c_test("bytes");
+}
+
+#[test]
+fn test_audit() {
+    run_c_test("audit");
+}
+
+#[test]
+fn test_threads() {
+    run_c_test("threads");
+}
+
+#[test]
+fn test_bundle() {
+    run_c_test("bundle");
+}
+
+#[test]
+fn test_delete_prefix() {
+    run_c_test("delete_prefix");
+}
+
+#[test]
+fn test_delete_hard() {
+    run_c_test("delete_hard");
+}
+
+#[test]
+fn test_keychain() {
+    run_c_test("keychain");
+}
+
+#[test]
+fn test_password_bytes() {
+    run_c_test("password_bytes");
+}
+
+#[test]
+fn test_version() {
+    run_c_test("version");
+}
+
+#[test]
+fn test_approval() {
+    run_c_test("approval");
+}
+
+#[test]
+fn test_raw_key() {
+    run_c_test("raw_key");
+}
+
+#[test]
+fn test_list_paths() {
+    run_c_test("list_paths");
+}
+
+#[test]
+fn test_sync() {
+    run_c_test("sync");
+}
+
+#[test]
+fn test_rename_copy() {
+    run_c_test("rename_copy");
+}
+
+#[test]
+fn test_health() {
+    run_c_test("health");
+}
+
+#[test]
+fn test_unlock_async() {
+    run_c_test("unlock_async");
+}
+
+#[test]
+fn test_access_level() {
+    run_c_test("access_level");
+}
+
+#[test]
+fn test_reauth() {
+    run_c_test("reauth");
+}
+
+#[test]
+fn test_read_only() {
+    run_c_test("read_only");
+}
+
+#[test]
+fn test_lockout() {
+    run_c_test("lockout");
+}
+
+#[test]
+fn test_generate() {
+    run_c_test("generate");
+}
+
+#[test]
+fn test_auto_lock() {
+    run_c_test("auto_lock");
+}
+
+#[test]
+fn test_invalid_utf8() {
+    run_c_test("invalid_utf8");
+}
+
+#[test]
+fn test_get_into() {
+    run_c_test("get_into");
+}
@@ -0,0 +1,91 @@
+//! Checks that secrets handed to C don't outlive their free: every heap block
+//! freed while reading and releasing a value is scanned for the plaintext
+
+use clawbox_ffi::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::CString;
+
+/// Counts blocks freed on the current thread, while `WATCHING` is set, that
+/// still hold `SECRET`
+struct ScanningAlloc;
+
+thread_local! {
+    static WATCHING: Cell<bool> = const { Cell::new(false) };
+    static LEAKS: Cell<usize> = const { Cell::new(0) };
+}
+
+const SECRET: &[u8] = b"wipe-me-3f9c1a7e5b2d4c6e8a0f";
+
+/// Whether the `len` bytes at `ptr` contain `SECRET`, or enough of it to
+/// matter: dropping a `CString` clears its first byte and nothing else
+unsafe fn holds_secret(ptr: *const u8, len: usize) -> bool {
+    let tail = &SECRET[4..];
+    std::slice::from_raw_parts(ptr, len).windows(tail.len()).any(|window| window == tail)
+}
+
+unsafe impl GlobalAlloc for ScanningAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if WATCHING.with(Cell::get) && holds_secret(ptr, layout.size()) {
+            LEAKS.with(|n| n.set(n.get() + 1));
+        }
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // The old block is freed unless realloc grows it in place
+        if WATCHING.with(Cell::get) && holds_secret(ptr, layout.size()) {
+            LEAKS.with(|n| n.set(n.get() + 1));
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: ScanningAlloc = ScanningAlloc;
+
+/// Blocks still holding `SECRET` that `f` freed on this thread
+fn leaks(f: impl FnOnce()) -> usize {
+    LEAKS.with(|n| n.set(0));
+    WATCHING.with(|w| w.set(true));
+    f();
+    WATCHING.with(|w| w.set(false));
+    LEAKS.with(Cell::get)
+}
+
+#[test]
+fn test_freed_values_are_wiped() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+    let key = CString::new("api/key").unwrap();
+
+    unsafe {
+        let vault = clawbox_open(path.as_ptr());
+        assert!(!vault.is_null());
+        let password = b"correct horse battery staple";
+        assert_eq!(clawbox_init_bytes(vault, password.as_ptr(), password.len()), CLAWBOX_OK);
+        let value = CString::new(SECRET).unwrap();
+        assert_eq!(clawbox_set(vault, key.as_ptr(), value.as_ptr(), CLAWBOX_ACCESS_NORMAL), CLAWBOX_OK);
+
+        let with_get = leaks(|| {
+            let mut value = std::ptr::null_mut();
+            assert_eq!(clawbox_get(vault, key.as_ptr(), &mut value), CLAWBOX_OK);
+            clawbox_free_string(value);
+        });
+        let with_get_bytes = leaks(|| {
+            let mut ptr = std::ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(clawbox_get_bytes(vault, key.as_ptr(), &mut ptr, &mut len), CLAWBOX_OK);
+            assert_eq!(std::slice::from_raw_parts(ptr, len), SECRET);
+            clawbox_free_bytes(ptr, len);
+        });
+        clawbox_close(vault);
+
+        assert_eq!(with_get, 0, "clawbox_get left the value in freed memory");
+        assert_eq!(with_get_bytes, 0, "clawbox_get_bytes left the value in freed memory");
+    }
+}